@@ -44,9 +44,13 @@ impl Harness {
             data_dir: data.path().to_owned(),
             setuid_support: false,
             password: "benchmark".to_string(),
+            keyfile: None,
+            master_key_file: None,
             file_size_padding: None,
             readonly: false,
             idmapped: false,
+            snapshot: None,
+            object_store: None,
         })
         .unwrap();
         let mut config = Config::default();
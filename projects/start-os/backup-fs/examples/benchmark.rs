@@ -40,6 +40,7 @@ fn with_mount(data: &Path, body: impl FnOnce(&Path)) {
             file_size_padding: None,
             readonly: false,
             idmapped: false,
+            snapshot: None,
         })
         .unwrap();
         let mut config = Config::default();
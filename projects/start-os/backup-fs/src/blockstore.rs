@@ -1,7 +1,7 @@
 //! Content storage as fixed-size, independently-sealed blocks.
 //!
 //! A regular file's data is split into [`CHUNK_SIZE`] logical chunks. Each
-//! chunk is stored as its own file on the backing store, sealed by
+//! chunk is stored as its own object in the [`crate::storage`] backend, sealed by
 //! [`crate::vault`] (encryption + integrity + Reed-Solomon ECC). This block
 //! layout is the core of the redesign and buys several properties at once:
 //!
@@ -23,15 +23,16 @@
 //!
 //! * **Per-block error correction.** Bit rot in one block is recovered from
 //!   that block's parity shards and never spreads to the rest of the file.
+//!
+//! In a format-v2 store the block file holds a manifest of deduplicated,
+//! content-defined chunks rather than the bytes themselves; the functions
+//! here dispatch to [`crate::chunkstore`] for that layout.
 
-use std::io::{self, Read, Write};
-
-use crate::aligned_io::BufferedDirectFile;
-use crate::atomic_file::AtomicFile;
+use crate::chunkstore;
 use crate::ctrl::Controller;
 use crate::error::BkfsResult;
 use crate::inode::ContentId;
-use crate::{open_direct, vault};
+use crate::vault;
 
 /// Logical chunk size. Each chunk maps to one sealed block file. 1 MiB
 /// balances per-file overhead (inode/dir-entry cost on the backing store)
@@ -54,36 +55,31 @@ pub fn locate(offset: u64) -> (u64, usize) {
 /// block) or, with size padding enabled, longer; callers must slice it to
 /// the logically-valid length.
 pub fn read_block(ctrl: &Controller, content: ContentId, idx: u64) -> BkfsResult<Option<Vec<u8>>> {
-    let path = ctrl.resolve_block_path(content, idx);
-    let mut file = match open_direct(&path, false) {
-        Ok(f) => f,
-        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
-        Err(e) => return Err(e.into()),
+    if ctrl.chunker().is_some() {
+        return chunkstore::read_block(ctrl, content, idx);
+    }
+    let name = ctrl.resolve_block_object(content, idx);
+    let Some(blob) = ctrl.storage().get(&name)? else {
+        return Ok(None);
     };
-    let mut blob = Vec::new();
-    file.read_to_end(&mut blob)?;
     // open (decrypt + ECC) → decompress back to the logical block bytes. A
     // block is at most one chunk, plus any size-padding applied to the final
     // block; cap the decompressed size accordingly so a malformed frame can't
     // allocate without bound.
     let stored = vault::open(&blob, ctrl.key())?;
-    Ok(Some(crate::compress::decompress(
-        &stored,
-        max_block_len(ctrl),
-    )?))
+    Ok(Some(ctrl.decompress(&stored, max_block_len(ctrl))?))
 }
 
 /// Upper bound on a decompressed block: one chunk, grown by the configured
 /// size-padding factor (the final block may be padded up to `len * (1+pad)`).
-fn max_block_len(ctrl: &Controller) -> usize {
+pub(crate) fn max_block_len(ctrl: &Controller) -> usize {
     let pad = ctrl.config().file_size_padding.unwrap_or(0.0).max(0.0);
     ((CHUNK_SIZE as f64) * (1.0 + pad)).ceil() as usize + 16
 }
 
 /// Compress (per `codec`), seal, and write one block whole. `durable`
-/// selects `sync_all` (the crash-safe path used on explicit syncs) versus
-/// the batched fast path (rename only; durability deferred to a later
-/// `syncfs`).
+/// selects a durable put (the crash-safe path used on explicit syncs) versus
+/// the batched fast path (durability deferred to a later `syncfs`).
 pub fn write_block(
     ctrl: &Controller,
     content: ContentId,
@@ -93,26 +89,44 @@ pub fn write_block(
     durable: bool,
 ) -> BkfsResult<()> {
     ctrl.check_rw()?;
+    if ctrl.chunker().is_some() {
+        return chunkstore::write_block(ctrl, content, idx, plaintext, codec, durable);
+    }
     // Compress BEFORE sealing — ciphertext is incompressible. Each block is
     // compressed independently, so a one-block edit recompresses only it.
-    let stored = crate::compress::compress(plaintext, codec);
+    let stored = ctrl.compress(plaintext, codec);
     let blob = vault::seal(&stored, ctrl.key(), ctrl.ecc());
-    let mut file = BufferedDirectFile::new(AtomicFile::create(ctrl.block_path(content, idx))?)?;
-    file.write_all(&blob)?;
-    if durable {
-        file.save()
-    } else {
-        file.save_fast()
+    let name = ctrl.block_object(content, idx);
+    ctrl.storage().put(&name, &blob, durable)?;
+    Ok(())
+}
+
+/// Share block `idx` of `from` as block `idx` of `to` — the copy-on-write
+/// step when a snapshot pins `from`. The sealed blob isn't bound to its name,
+/// so it is shared where the backend allows (see [`crate::storage::Storage::copy`])
+/// and copied verbatim otherwise. An absent block (a hole) stays absent.
+pub fn clone_block(ctrl: &Controller, from: ContentId, to: ContentId, idx: u64) -> BkfsResult<()> {
+    ctrl.check_rw()?;
+    if ctrl.chunker().is_some() && !chunkstore::share_block(ctrl, from, idx)? {
+        return Ok(());
     }
+    let src = ctrl.resolve_block_object(from, idx);
+    ctrl.storage().copy(&src, &ctrl.block_object(to, idx))?;
+    Ok(())
 }
 
-/// Remove one block file, tolerating an already-absent file.
+/// Remove one block, tolerating an already-absent one. Blocks of
+/// content pinned by a snapshot are left in place.
 pub fn remove_block(ctrl: &Controller, content: ContentId, idx: u64) -> BkfsResult<()> {
-    match std::fs::remove_file(ctrl.resolve_block_path(content, idx)) {
-        Ok(()) => Ok(()),
-        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
-        Err(e) => Err(e.into()),
+    if ctrl.is_pinned_content(content) {
+        return Ok(());
     }
+    if ctrl.chunker().is_some() {
+        return chunkstore::remove_block(ctrl, content, idx);
+    }
+    let name = ctrl.resolve_block_object(content, idx);
+    ctrl.storage().delete(&name)?;
+    Ok(())
 }
 
 /// Remove every block of a file whose logical size is `size`. Used by the
@@ -0,0 +1,383 @@
+//! Content-defined chunking and deduplicated chunk storage (format v2).
+//!
+//! In a v1 store each block file holds its block's sealed bytes under a keyed
+//! hash of `(content_id, block_index)` (see [`crate::blockstore`]), so
+//! identical data in two files — or a file shifted by one byte — is stored
+//! twice. From format v2 the block file at that same path holds a sealed
+//! [`Manifest`] instead: the block is split at content-defined cut points by
+//! a rolling hash ([`Chunker`]) and every chunk is stored once under
+//! `chunks/`, named by a keyed hash of its plaintext ([`ChunkHash`]). VM
+//! images, database dumps and media duplicated across services then share
+//! their chunks on the backup target.
+//!
+//! * **Per-block chunking.** Chunking runs inside each 1 MiB logical block,
+//!   so a random write still read-modify-writes one block and the manifest
+//!   keeps the rsync-friendly stable path. A byte inserted near the start of
+//!   a file shifts every block, but within a block the cut points
+//!   resynchronize after the first one: only the chunks straddling block
+//!   edges are new.
+//!
+//! * **Keyed chunking.** The gear table of the rolling hash is derived from
+//!   the master key, so chunk lengths (visible on the backing store) don't
+//!   fingerprint known plaintext, and the chunk name is a keyed hash that
+//!   leaks nothing about the content.
+//!
+//! * **Reference counts** live in the segment log (`Record::ChunkRefs`, latest
+//!   wins, zero drops the entry): one reference per manifest file naming the
+//!   chunk, counted with multiplicity. A write increments what the new
+//!   manifest adds and syncs the log *before* the manifest is written, so no
+//!   manifest can reach the disk ahead of its counts; what it drops is only
+//!   queued, and applied by [`reap`] after the next `syncfs` has made the
+//!   replacing manifest durable. A chunk whose count reaches zero is unlinked
+//!   only once that count is itself durable. A crash therefore leaves counts
+//!   too high (a leaked chunk), never too low.
+//!
+//! Chunk reads, writes and count updates for one hash are serialized by a
+//! striped lock (`Controller::chunk_lock`), so a chunk can't be unlinked
+//! between another writer seeing it present and recording its reference.
+
+use std::collections::HashMap;
+use std::io;
+
+use chacha20::Key;
+use log::warn;
+use sha2::{Digest, Sha256};
+
+use crate::ctrl::Controller;
+use crate::error::{BkfsError, BkfsResult};
+use crate::inode::ContentId;
+use crate::serde::{deserialize_sealed, serialize_sealed, Deserialize, Serialize};
+use crate::vault;
+
+/// Keyed SHA-256 of a chunk's plaintext: its identity and (truncated) name.
+pub type ChunkHash = [u8; 32];
+
+/// No cut point is taken before this many bytes.
+pub const MIN_CHUNK: usize = 32 * 1024;
+/// Chunks are forced to end here.
+pub const MAX_CHUNK: usize = 512 * 1024;
+/// Target average: `2^AVG_BITS` = 128 KiB.
+const AVG_BITS: u32 = 17;
+/// Normalized chunking (FastCDC): a stricter mask before the average size and
+/// a looser one after it pull chunk sizes toward the average.
+const MASK_SMALL: u64 = !0 << (64 - (AVG_BITS + 2));
+const MASK_LARGE: u64 = !0 << (64 - (AVG_BITS - 2));
+
+/// Rolling-hash chunker. FROZEN as part of format v2: the gear derivation,
+/// the size bounds and the masks determine every cut point, so changing any
+/// of them only loses deduplication against existing chunks (never
+/// correctness), but should still ride a format bump.
+pub struct Chunker {
+    gear: Box<[u64; 256]>,
+}
+
+impl Chunker {
+    pub fn new(key: &Key) -> Self {
+        let mut gear = Box::new([0u64; 256]);
+        for (i, g) in gear.iter_mut().enumerate() {
+            let mut hasher = Sha256::new();
+            hasher.update(key.as_slice());
+            hasher.update(b"gear");
+            hasher.update([i as u8]);
+            *g = u64::from_le_bytes(hasher.finalize()[..8].try_into().unwrap());
+        }
+        Self { gear }
+    }
+
+    /// Length of the first chunk of `data`.
+    fn cut(&self, data: &[u8]) -> usize {
+        if data.len() <= MIN_CHUNK {
+            return data.len();
+        }
+        let end = data.len().min(MAX_CHUNK);
+        let normal = end.min(1 << AVG_BITS);
+        let mut h = 0u64;
+        for (i, &b) in data.iter().enumerate().take(end).skip(MIN_CHUNK) {
+            h = (h << 1).wrapping_add(self.gear[b as usize]);
+            let mask = if i < normal { MASK_SMALL } else { MASK_LARGE };
+            if h & mask == 0 {
+                return i + 1;
+            }
+        }
+        end
+    }
+
+    /// Split `data` at content-defined boundaries.
+    pub fn split<'a>(&self, mut data: &'a [u8]) -> Vec<&'a [u8]> {
+        let mut chunks = Vec::new();
+        while !data.is_empty() {
+            let (chunk, rest) = data.split_at(self.cut(data));
+            chunks.push(chunk);
+            data = rest;
+        }
+        chunks
+    }
+}
+
+pub fn chunk_hash(key: &Key, plaintext: &[u8]) -> ChunkHash {
+    let mut hasher = Sha256::new();
+    hasher.update(key.as_slice());
+    hasher.update(b"chunk");
+    hasher.update(plaintext);
+    hasher.finalize().into()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkRef {
+    pub hash: ChunkHash,
+    pub len: u32,
+}
+
+/// What a v2 block file holds: the block's chunks, in order.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub chunks: Vec<ChunkRef>,
+}
+
+impl Manifest {
+    fn counts(&self) -> HashMap<ChunkHash, u64> {
+        let mut counts = HashMap::new();
+        for c in &self.chunks {
+            *counts.entry(c.hash).or_default() += 1;
+        }
+        counts
+    }
+}
+
+/// Read the manifest at block `(content, idx)`; `Ok(None)` is a hole.
+pub fn read_manifest(ctrl: &Controller, content: ContentId, idx: u64) -> BkfsResult<Option<Manifest>> {
+    let name = ctrl.resolve_block_object(content, idx);
+    match ctrl.storage().get(&name)? {
+        Some(blob) => Ok(Some(deserialize_sealed(&blob, ctrl.key())?)),
+        None => Ok(None),
+    }
+}
+
+/// The manifest a block is about to lose, for reference accounting. An
+/// unreadable one is warned about and treated as absent — its chunks leak
+/// rather than the write failing.
+fn old_manifest(ctrl: &Controller, content: ContentId, idx: u64) -> Option<Manifest> {
+    read_manifest(ctrl, content, idx).unwrap_or_else(|e| {
+        warn!("unreadable chunk manifest for {content:?}/{idx}, leaking its chunks: {e}");
+        None
+    })
+}
+
+fn read_chunk(ctrl: &Controller, chunk: &ChunkRef) -> BkfsResult<Vec<u8>> {
+    let blob = ctrl
+        .storage()
+        .get(&ctrl.chunk_object(&chunk.hash))?
+        .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+    let stored = vault::open(&blob, ctrl.key())?;
+    let plain = ctrl.decompress(&stored, MAX_CHUNK)?;
+    if plain.len() != chunk.len as usize {
+        return Err(BkfsError::wrap(io::Error::other(
+            "chunk length disagrees with its manifest",
+        )));
+    }
+    Ok(plain)
+}
+
+/// v2 counterpart of [`crate::blockstore::read_block`]: reassemble the block
+/// from its chunks.
+pub fn read_block(ctrl: &Controller, content: ContentId, idx: u64) -> BkfsResult<Option<Vec<u8>>> {
+    let Some(manifest) = read_manifest(ctrl, content, idx)? else {
+        return Ok(None);
+    };
+    let total: usize = manifest.chunks.iter().map(|c| c.len as usize).sum();
+    if total > crate::blockstore::max_block_len(ctrl) {
+        return Err(BkfsError::wrap(io::Error::other("chunk manifest exceeds a block")));
+    }
+    let mut block = Vec::with_capacity(total);
+    for chunk in &manifest.chunks {
+        block.extend_from_slice(&read_chunk(ctrl, chunk)?);
+    }
+    Ok(Some(block))
+}
+
+/// Add `n` references to `hash`, first storing `plaintext` if the chunk
+/// isn't on disk. Caller holds the chunk's lock.
+fn reference(
+    ctrl: &Controller,
+    hash: &ChunkHash,
+    n: u64,
+    plaintext: &[u8],
+    codec: crate::compress::Codec,
+    durable: bool,
+) -> BkfsResult<()> {
+    let refs = ctrl.chunk_refs(hash);
+    let name = ctrl.chunk_object(hash);
+    // A count can outlive its object across a crash (the count record made
+    // durable, the chunk write not), so a positive count alone doesn't prove
+    // the bytes are there.
+    if refs == 0 || !ctrl.storage().exists(&name).unwrap_or(false) {
+        let stored = ctrl.compress(plaintext, codec);
+        let blob = vault::seal(&stored, ctrl.key(), ctrl.ecc());
+        ctrl.storage().put(&name, &blob, durable)?;
+    }
+    ctrl.set_chunk_refs(hash, refs + n)
+}
+
+/// v2 counterpart of [`crate::blockstore::write_block`]: chunk the block,
+/// store the chunks not already present, and replace its manifest.
+pub fn write_block(
+    ctrl: &Controller,
+    content: ContentId,
+    idx: u64,
+    plaintext: &[u8],
+    codec: crate::compress::Codec,
+    durable: bool,
+) -> BkfsResult<()> {
+    ctrl.check_rw()?;
+    let Some(chunker) = ctrl.chunker() else {
+        unreachable!("chunkstore used on a v1 store");
+    };
+    let old = old_manifest(ctrl, content, idx).unwrap_or_default();
+    let mut old_counts = old.counts();
+    let mut manifest = Manifest::default();
+    let mut added = HashMap::<ChunkHash, (u64, &[u8])>::new();
+    for piece in chunker.split(plaintext) {
+        let hash = chunk_hash(ctrl.key(), piece);
+        manifest.chunks.push(ChunkRef {
+            hash,
+            len: piece.len() as u32,
+        });
+        // Chunks the old manifest already referenced keep that reference.
+        match old_counts.get_mut(&hash) {
+            Some(n) if *n > 0 => *n -= 1,
+            _ => added.entry(hash).or_insert((0, piece)).0 += 1,
+        }
+    }
+    for (hash, (n, piece)) in &added {
+        {
+            let _guard = ctrl.chunk_lock(hash);
+            reference(ctrl, hash, *n, piece, codec, durable)?;
+        }
+        // Outside the lock: a batch-triggered syncfs runs `reap`, which
+        // takes chunk locks itself.
+        ctrl.tick_save()?;
+    }
+    if !added.is_empty() {
+        ctrl.log_sync()?;
+    }
+    let blob = serialize_sealed(&manifest, ctrl.key(), ctrl.ecc())?;
+    let name = ctrl.block_object(content, idx);
+    ctrl.storage().put(&name, &blob, durable)?;
+    ctrl.defer_chunk_derefs(
+        old_counts
+            .into_iter()
+            .flat_map(|(hash, n)| std::iter::repeat_n(hash, n as usize)),
+    );
+    Ok(())
+}
+
+/// Reference every chunk of block `(content, idx)` once more, ahead of
+/// [`crate::blockstore::clone_block`] giving its manifest a second name; the
+/// counts are synced before it returns. `Ok(false)` if the block is a hole.
+pub fn share_block(ctrl: &Controller, content: ContentId, idx: u64) -> BkfsResult<bool> {
+    let Some(manifest) = read_manifest(ctrl, content, idx)? else {
+        return Ok(false);
+    };
+    for (hash, n) in manifest.counts() {
+        let _guard = ctrl.chunk_lock(&hash);
+        let refs = ctrl.chunk_refs(&hash);
+        ctrl.set_chunk_refs(&hash, refs + n)?;
+    }
+    if !manifest.chunks.is_empty() {
+        ctrl.log_sync()?;
+    }
+    Ok(true)
+}
+
+/// v2 counterpart of [`crate::blockstore::remove_block`]: drop the manifest
+/// and queue its chunks' references for release.
+pub fn remove_block(ctrl: &Controller, content: ContentId, idx: u64) -> BkfsResult<()> {
+    // An absent block has no manifest, so nothing is released for it.
+    let old = old_manifest(ctrl, content, idx);
+    let name = ctrl.resolve_block_object(content, idx);
+    ctrl.storage().delete(&name)?;
+    if let Some(old) = old {
+        ctrl.defer_chunk_derefs(old.chunks.into_iter().map(|c| c.hash));
+    }
+    Ok(())
+}
+
+/// Apply `derefs`, then unlink every chunk left unreferenced. `derefs` must be
+/// the queue as taken *before* a `syncfs` (see [`Controller::syncfs`]), so
+/// every manifest that dropped one of them is already durable; drops queued
+/// while that sync ran wait for the next one. The zeroed counts are synced
+/// before any unlink.
+pub fn reap(ctrl: &Controller, derefs: Vec<ChunkHash>) -> BkfsResult<()> {
+    if derefs.is_empty() {
+        return Ok(());
+    }
+    let mut counts = HashMap::<ChunkHash, u64>::new();
+    for hash in derefs {
+        *counts.entry(hash).or_default() += 1;
+    }
+    let mut dead = Vec::new();
+    for (hash, n) in counts {
+        let _guard = ctrl.chunk_lock(&hash);
+        let refs = ctrl.chunk_refs(&hash).saturating_sub(n);
+        ctrl.set_chunk_refs(&hash, refs)?;
+        if refs == 0 {
+            dead.push(hash);
+        }
+    }
+    ctrl.log_sync()?;
+    for hash in dead {
+        let _guard = ctrl.chunk_lock(&hash);
+        // Re-referenced (and rewritten) since its count hit zero.
+        if ctrl.chunk_refs(&hash) > 0 {
+            continue;
+        }
+        ctrl.storage().delete(&ctrl.chunk_object(&hash))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(len: usize) -> Vec<u8> {
+        // xorshift: deterministic, incompressible-looking bytes.
+        let mut x = 0x2545F4914F6CDD1Du64;
+        (0..len)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                x as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn split_respects_bounds_and_reassembles() {
+        let chunker = Chunker::new(&Key::from([7u8; 32]));
+        let input = data(3 << 20);
+        let chunks = chunker.split(&input);
+        assert!(chunks.len() > 3 << 20 >> AVG_BITS >> 1);
+        for c in &chunks[..chunks.len() - 1] {
+            assert!((MIN_CHUNK..=MAX_CHUNK).contains(&c.len()), "{}", c.len());
+        }
+        assert_eq!(chunks.concat(), input);
+    }
+
+    #[test]
+    fn cut_points_resynchronize_after_a_shift() {
+        let chunker = Chunker::new(&Key::from([7u8; 32]));
+        let input = data(1 << 20);
+        let mut shifted = vec![0u8; 100];
+        shifted.extend_from_slice(&input);
+        let a: Vec<&[u8]> = chunker.split(&input);
+        let b: Vec<&[u8]> = chunker.split(&shifted);
+        let shared = b.iter().filter(|c| a.contains(c)).count();
+        assert!(
+            shared + 2 >= a.len(),
+            "only {shared} of {} chunks survived a 100-byte shift",
+            a.len()
+        );
+    }
+}
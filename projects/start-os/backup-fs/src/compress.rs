@@ -1,34 +1,75 @@
 //! Per-file content compression, applied to content bytes *before*
 //! encryption (ciphertext doesn't compress).
 //!
-//! The codec (whether to compress, and the zstd level) is chosen from the
-//! file's extension by [`codec_for_name`]: already-compressed/media formats
-//! are stored raw (no wasted CPU), highly-compressible text/structured
-//! formats get a high level, and unknown extensions default to zstd level 2.
-//! The choice is also **adaptive**: if compression doesn't actually shrink a
-//! given chunk it's stored raw, so a misclassified or incompressible file
-//! never bloats.
+//! The codec (whether to compress, and the zstd level) is chosen per file.
+//! [`codec_for_name`] picks one from the file's extension: already-
+//! compressed/media formats are stored raw (no wasted CPU), highly-
+//! compressible text/structured formats get a high level, and unknown
+//! extensions default to zstd level 2. In the default `sniff` selection mode
+//! (`BACKUPFS_COMPRESSION`, see [`selection`]) that is only the fallback:
+//! [`sniff`] looks at the first chunk written for magic bytes, byte entropy
+//! and text, so extension-less files (git objects, database pages, container
+//! layers) are classified by what they contain. The controller caches the
+//! decision per inode. The choice is also **adaptive**: if compression
+//! doesn't actually shrink a given chunk it's stored raw, so a misclassified
+//! or incompressible file never bloats.
+//!
+//! Small structured text (JSON, logs, …) compresses poorly on its own, so on
+//! a format v3 store those chunks are compressed against a zstd dictionary
+//! trained from the store's own small files ([`Dictionaries`]). A dictionary
+//! is persisted before the first frame that uses it and is never deleted.
 //!
 //! Each stored chunk (a content block or a packed extent) is independently
 //! `compress`-ed and carries a 1-byte algorithm tag, so it can be
 //! `decompress`-ed on its own and a per-block edit recompresses only that
 //! block (preserving the rsync-incremental property). Compression is applied
 //! to packed extents and content blocks; tiny inline content (≤ the inline
-//! threshold, stored in the inode record) is left as-is.
+//! threshold, stored in the inode record) is left as-is. [`CompressionStats`]
+//! tallies the ratios achieved.
 
+use std::collections::BTreeMap;
 use std::ffi::OsStr;
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+
+use serde::Serialize;
 
 use crate::error::{BkfsError, BkfsResult};
 
 const TAG_RAW: u8 = 0;
 const TAG_ZSTD: u8 = 1;
+/// zstd against a trained dictionary: `tag | dict_id u32 LE | frame` (v3).
+const TAG_ZSTD_DICT: u8 = 2;
 
-/// Compression decision for a file: skip, or zstd at a level.
+/// Compression decision for a file: skip, zstd at a level, or zstd at a level
+/// against the store's dictionary where one applies (small chunks of
+/// structured text).
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Codec {
     None,
     Zstd(i32),
+    ZstdDict(i32),
+}
+
+/// How files get their [`Codec`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Selection {
+    /// From the file name alone ([`codec_for_name`]).
+    Extension,
+    /// From the first chunk's content ([`sniff`]), falling back to the name.
+    Sniff,
+}
+
+/// The codec selection mode, from `BACKUPFS_COMPRESSION` (`extension` or
+/// `sniff`, the default). Only affects new writes, so it isn't recorded in
+/// the superblock.
+pub fn selection() -> Selection {
+    static S: OnceLock<Selection> = OnceLock::new();
+    *S.get_or_init(|| match std::env::var("BACKUPFS_COMPRESSION").as_deref() {
+        Ok("extension") => Selection::Extension,
+        _ => Selection::Sniff,
+    })
 }
 
 /// Choose a codec from a file's name (its extension, case-insensitive).
@@ -46,31 +87,141 @@ pub fn codec_for_name(name: &OsStr) -> Codec {
             | "oga" | "opus" | "flac" | "woff" | "woff2" | "apk" | "jar" | "deb" | "rpm" | "dmg"
             | "iso" | "gpg" | "age" | "pgp",
         ) => Codec::None,
-        // Highly compressible text / structured / source — spend more.
+        // Structured text / logs — many small files sharing vocabulary, which
+        // is what a trained dictionary captures.
+        Some(
+            "log" | "txt" | "text" | "json" | "ndjson" | "jsonl" | "csv" | "tsv" | "xml" | "yaml"
+            | "yml" | "toml" | "ini" | "conf" | "cfg",
+        ) => Codec::ZstdDict(9),
+        // Other highly compressible text / source / data — spend more.
         Some(
-            "log" | "txt" | "text" | "json" | "ndjson" | "jsonl" | "csv" | "tsv" | "xml" | "html"
-            | "htm" | "yaml" | "yml" | "toml" | "ini" | "conf" | "cfg" | "md" | "rst" | "sql"
-            | "js" | "mjs" | "ts" | "tsx" | "jsx" | "css" | "scss" | "c" | "h" | "cc" | "cpp"
-            | "hpp" | "rs" | "py" | "go" | "java" | "kt" | "rb" | "php" | "sh" | "bash" | "pl"
-            | "lua" | "svg" | "tar" | "dat" | "db" | "sqlite" | "wal",
+            "html" | "htm" | "md" | "rst" | "sql" | "js" | "mjs" | "ts" | "tsx" | "jsx" | "css"
+            | "scss" | "c" | "h" | "cc" | "cpp" | "hpp" | "rs" | "py" | "go" | "java" | "kt" | "rb"
+            | "php" | "sh" | "bash" | "pl" | "lua" | "svg" | "tar" | "dat" | "db" | "sqlite"
+            | "wal",
         ) => Codec::Zstd(9),
         // Everything else (unknown extension): the requested default.
         _ => Codec::Zstd(2),
     }
 }
 
+/// Bytes of a file's first chunk that [`sniff`] looks at.
+const SNIFF_LEN: usize = 64 * 1024;
+
+/// Below this many sampled bytes the byte-entropy estimate is too noisy to
+/// act on, and [`sniff`] trusts the name.
+const SNIFF_MIN_ENTROPY_SAMPLE: usize = 512;
+
+/// Bits per byte above which a sample is taken to be compressed or
+/// encrypted already (zstd/gzip output and ciphertext sit just under 8).
+const INCOMPRESSIBLE_ENTROPY: f64 = 7.5;
+
+/// Leading bytes of common already-compressed formats, with their offset.
+const COMPRESSED_MAGIC: &[(usize, &[u8])] = &[
+    (0, b"\x28\xb5\x2f\xfd"),    // zstd
+    (0, b"\x1f\x8b"),            // gzip
+    (0, b"\xfd7zXZ\x00"),        // xz
+    (0, b"BZh"),                 // bzip2
+    (0, b"\x04\x22\x4d\x18"),    // lz4
+    (0, b"PK\x03\x04"),          // zip, jar, apk, docx, …
+    (0, b"7z\xbc\xaf\x27\x1c"),  // 7z
+    (0, b"Rar!\x1a\x07"),        // rar
+    (0, b"\x89PNG\r\n\x1a\n"),   // png
+    (0, b"\xff\xd8\xff"),        // jpeg
+    (0, b"GIF8"),                // gif
+    (8, b"WEBP"),                // webp (RIFF container)
+    (4, b"ftyp"),                // mp4, mov, heic, avif
+    (0, b"\x1a\x45\xdf\xa3"),    // matroska, webm
+    (0, b"OggS"),                // ogg, opus
+    (0, b"fLaC"),                // flac
+    (0, b"ID3"),                 // mp3
+    (0, b"wOF2"),                // woff2
+    (0, b"age-encryption.org/"), // age
+];
+
+/// Choose a codec from the start of a file's content, falling back to
+/// `by_name` (the [`codec_for_name`] choice) when the sample is
+/// inconclusive: known compressed formats and high-entropy samples are
+/// stored raw, and text gets the structured-text codec.
+pub fn sniff(sample: &[u8], by_name: Codec) -> Codec {
+    let sample = &sample[..sample.len().min(SNIFF_LEN)];
+    if COMPRESSED_MAGIC
+        .iter()
+        .any(|(at, magic)| sample.get(*at..).is_some_and(|s| s.starts_with(magic)))
+    {
+        return Codec::None;
+    }
+    if sample.len() < SNIFF_MIN_ENTROPY_SAMPLE {
+        return by_name;
+    }
+    if entropy(sample) >= INCOMPRESSIBLE_ENTROPY {
+        return Codec::None;
+    }
+    if looks_like_text(sample) {
+        return match by_name {
+            Codec::Zstd(level) | Codec::ZstdDict(level) => Codec::ZstdDict(level.max(9)),
+            Codec::None => Codec::ZstdDict(9),
+        };
+    }
+    by_name
+}
+
+/// Shannon entropy of `sample`'s byte histogram, in bits per byte.
+fn entropy(sample: &[u8]) -> f64 {
+    let mut counts = [0u64; 256];
+    for &b in sample {
+        counts[b as usize] += 1;
+    }
+    let n = sample.len() as f64;
+    counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / n;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// Mostly printable ASCII/UTF-8 with no NULs. The sample may cut a UTF-8
+/// sequence at its end, so only the bytes are judged, not their validity.
+fn looks_like_text(sample: &[u8]) -> bool {
+    if sample.contains(&0) {
+        return false;
+    }
+    let printable = sample
+        .iter()
+        .filter(|&&b| matches!(b, b'\t' | b'\n' | b'\r' | 0x20..=0x7e | 0x80..))
+        .count();
+    printable * 100 >= sample.len() * 98
+}
+
 /// Compress `plain` per `codec`, prefixing a 1-byte algorithm tag. Falls back
 /// to storing raw if compression is disabled, errors, or fails to shrink the
 /// data (so an incompressible chunk never grows beyond +1 tag byte).
-pub fn compress(plain: &[u8], codec: Codec) -> Vec<u8> {
-    if let Codec::Zstd(level) = codec {
-        if let Ok(c) = zstd::stream::encode_all(plain, level) {
-            if c.len() + 1 < plain.len() {
-                let mut out = Vec::with_capacity(c.len() + 1);
-                out.push(TAG_ZSTD);
-                out.extend_from_slice(&c);
-                return out;
-            }
+/// [`Codec::ZstdDict`] uses the newest of `dicts` for chunks small enough to
+/// benefit, and plain zstd otherwise.
+pub fn compress(plain: &[u8], codec: Codec, dicts: &Dictionaries) -> Vec<u8> {
+    let framed = match codec {
+        Codec::None => None,
+        Codec::ZstdDict(level) if plain.len() <= DICT_INPUT_MAX => match dicts.latest() {
+            Some(dict) => zstd::bulk::Compressor::with_dictionary(level, &dict.raw)
+                .and_then(|mut c| c.compress(plain))
+                .ok()
+                .map(|c| {
+                    let mut out = Vec::with_capacity(c.len() + 5);
+                    out.push(TAG_ZSTD_DICT);
+                    out.extend_from_slice(&dict.id.to_le_bytes());
+                    out.extend_from_slice(&c);
+                    out
+                }),
+            None => zstd_framed(plain, level),
+        },
+        Codec::Zstd(level) | Codec::ZstdDict(level) => zstd_framed(plain, level),
+    };
+    if let Some(out) = framed {
+        if out.len() < plain.len() {
+            return out;
         }
     }
     let mut out = Vec::with_capacity(plain.len() + 1);
@@ -79,6 +230,14 @@ pub fn compress(plain: &[u8], codec: Codec) -> Vec<u8> {
     out
 }
 
+fn zstd_framed(plain: &[u8], level: i32) -> Option<Vec<u8>> {
+    let c = zstd::stream::encode_all(plain, level).ok()?;
+    let mut out = Vec::with_capacity(c.len() + 1);
+    out.push(TAG_ZSTD);
+    out.extend_from_slice(&c);
+    Some(out)
+}
+
 /// Inverse of [`compress`]. `max_len` bounds the decompressed size: every
 /// stored chunk has a known logical ceiling (a content block is ≤ one chunk
 /// plus any size-padding; a packed extent is ≤ the pack limit), so a frame
@@ -87,10 +246,26 @@ pub fn compress(plain: &[u8], codec: Codec) -> Vec<u8> {
 /// [`crate::vault`] already rejects any frame not produced by this key — but
 /// it cheaply turns a hypothetical future logic/key bug into a clean error
 /// instead of an OOM-abort.
-pub fn decompress(stored: &[u8], max_len: usize) -> BkfsResult<Vec<u8>> {
+pub fn decompress(stored: &[u8], max_len: usize, dicts: &Dictionaries) -> BkfsResult<Vec<u8>> {
     match stored.split_first() {
         Some((&TAG_RAW, rest)) => Ok(rest.to_vec()),
         Some((&TAG_ZSTD, rest)) => decode_bounded(rest, max_len),
+        Some((&TAG_ZSTD_DICT, rest)) if rest.len() >= 4 => {
+            let id = u32::from_le_bytes(rest[..4].try_into().unwrap());
+            let dict = dicts.get(id).ok_or_else(|| {
+                BkfsError::wrap(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("compression dictionary {id} is missing"),
+                ))
+            })?;
+            // Dictionary frames are only written for chunks ≤ DICT_INPUT_MAX,
+            // and bulk decoding allocates its whole capacity up front.
+            zstd::bulk::Decompressor::with_dictionary(&dict.raw)
+                .and_then(|mut d| d.decompress(&rest[4..], max_len.min(DICT_INPUT_MAX)))
+                .map_err(|e| {
+                    BkfsError::wrap(std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+                })
+        }
         _ => Err(BkfsError::wrap(std::io::Error::new(
             std::io::ErrorKind::InvalidData,
             "missing/unknown compression tag",
@@ -119,22 +294,178 @@ fn decode_bounded(frame: &[u8], max_len: usize) -> BkfsResult<Vec<u8>> {
     Ok(out)
 }
 
+/// Largest chunk compressed against a dictionary (and sampled to train one).
+/// Past this, a chunk carries enough context of its own.
+const DICT_INPUT_MAX: usize = 64 * 1024;
+
+/// Bytes kept from each training sample.
+const SAMPLE_MAX: usize = 16 * 1024;
+
+/// Training starts once this many samples totalling this many bytes are in.
+const TRAIN_MIN_SAMPLES: usize = 64;
+const TRAIN_MIN_BYTES: usize = 512 * 1024;
+
+/// Target size of a trained dictionary.
+const DICT_SIZE: usize = 16 * 1024;
+
+/// A trained zstd dictionary, identified in the frames that use it.
+pub struct Dictionary {
+    pub id: u32,
+    pub raw: Vec<u8>,
+}
+
+/// The store's trained dictionaries, plus the samples collected toward the
+/// first one. The controller owns persistence: it loads every dictionary at
+/// mount, and when [`Dictionaries::observe`] hands back a full training set
+/// it trains, stores, and only then [`Dictionaries::insert`]s the result —
+/// so no frame can reference a dictionary that isn't durable.
+#[derive(Default)]
+pub struct Dictionaries {
+    trained: RwLock<BTreeMap<u32, Arc<Dictionary>>>,
+    /// `None` when this store doesn't train (pre-v3, read-only, or done).
+    samples: Mutex<Option<Vec<Vec<u8>>>>,
+}
+
+impl Dictionaries {
+    /// Dictionaries that also collect training samples while none exists.
+    pub fn training() -> Self {
+        Self {
+            trained: RwLock::default(),
+            samples: Mutex::new(Some(Vec::new())),
+        }
+    }
+
+    pub fn insert(&self, id: u32, raw: Vec<u8>) {
+        self.trained
+            .write()
+            .unwrap()
+            .insert(id, Arc::new(Dictionary { id, raw }));
+        *self.samples.lock().unwrap() = None;
+    }
+
+    pub fn get(&self, id: u32) -> Option<Arc<Dictionary>> {
+        self.trained.read().unwrap().get(&id).cloned()
+    }
+
+    /// The dictionary new frames use.
+    pub fn latest(&self) -> Option<Arc<Dictionary>> {
+        self.trained.read().unwrap().values().next_back().cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.trained.read().unwrap().len()
+    }
+
+    /// Offer a chunk written with `codec` as a training sample. Returns the
+    /// full training set, exactly once, when enough has been collected.
+    pub fn observe(&self, plain: &[u8], codec: Codec) -> Option<Vec<Vec<u8>>> {
+        if !matches!(codec, Codec::ZstdDict(_)) || plain.len() > DICT_INPUT_MAX {
+            return None;
+        }
+        let mut samples = self.samples.lock().unwrap();
+        let set = samples.as_mut()?;
+        set.push(plain[..plain.len().min(SAMPLE_MAX)].to_vec());
+        let bytes: usize = set.iter().map(Vec::len).sum();
+        if set.len() < TRAIN_MIN_SAMPLES || bytes < TRAIN_MIN_BYTES {
+            return None;
+        }
+        samples.take()
+    }
+}
+
+/// Train a dictionary from samples handed out by [`Dictionaries::observe`].
+pub fn train(samples: &[Vec<u8>]) -> std::io::Result<Vec<u8>> {
+    zstd::dict::from_samples(samples, DICT_SIZE)
+}
+
+/// Running totals of what [`compress`] achieved, by stored algorithm.
+#[derive(Default)]
+pub struct CompressionStats {
+    by_tag: [Tally; 3],
+}
+
+#[derive(Default)]
+struct Tally {
+    chunks: AtomicU64,
+    logical: AtomicU64,
+    stored: AtomicU64,
+}
+
+/// A snapshot of [`CompressionStats`], serialized for the stats command.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct StatsReport {
+    pub raw: TallyReport,
+    pub zstd: TallyReport,
+    pub zstd_dict: TallyReport,
+    pub logical_bytes: u64,
+    pub stored_bytes: u64,
+    /// `logical_bytes / stored_bytes`; 1.0 before anything is written.
+    pub ratio: f64,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct TallyReport {
+    pub chunks: u64,
+    pub logical_bytes: u64,
+    pub stored_bytes: u64,
+}
+
+impl CompressionStats {
+    /// Count a chunk of `logical` bytes that [`compress`] turned into `stored`.
+    pub fn record(&self, logical: usize, stored: &[u8]) {
+        let Some(tally) = stored.first().and_then(|&t| self.by_tag.get(t as usize)) else {
+            return;
+        };
+        tally.chunks.fetch_add(1, Ordering::Relaxed);
+        tally.logical.fetch_add(logical as u64, Ordering::Relaxed);
+        tally
+            .stored
+            .fetch_add(stored.len() as u64, Ordering::Relaxed);
+    }
+
+    pub fn report(&self) -> StatsReport {
+        let [raw, zstd, zstd_dict] = self.by_tag.each_ref().map(|t| TallyReport {
+            chunks: t.chunks.load(Ordering::Relaxed),
+            logical_bytes: t.logical.load(Ordering::Relaxed),
+            stored_bytes: t.stored.load(Ordering::Relaxed),
+        });
+        let logical_bytes = raw.logical_bytes + zstd.logical_bytes + zstd_dict.logical_bytes;
+        let stored_bytes = raw.stored_bytes + zstd.stored_bytes + zstd_dict.stored_bytes;
+        StatsReport {
+            ratio: if stored_bytes == 0 {
+                1.0
+            } else {
+                logical_bytes as f64 / stored_bytes as f64
+            },
+            raw,
+            zstd,
+            zstd_dict,
+            logical_bytes,
+            stored_bytes,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     const CAP: usize = 1 << 21; // generous test ceiling
 
+    fn no_dicts() -> Dictionaries {
+        Dictionaries::default()
+    }
+
     #[test]
     fn roundtrip_raw_and_zstd() {
         let text = vec![b'a'; 10_000]; // very compressible
-        let zc = compress(&text, Codec::Zstd(9));
+        let zc = compress(&text, Codec::Zstd(9), &no_dicts());
         assert!(zc.len() < text.len(), "compressible data should shrink");
-        assert_eq!(decompress(&zc, CAP).unwrap(), text);
+        assert_eq!(decompress(&zc, CAP, &no_dicts()).unwrap(), text);
 
-        let none = compress(&text, Codec::None);
+        let none = compress(&text, Codec::None, &no_dicts());
         assert_eq!(none[0], TAG_RAW);
-        assert_eq!(decompress(&none, CAP).unwrap(), text);
+        assert_eq!(decompress(&none, CAP, &no_dicts()).unwrap(), text);
     }
 
     #[test]
@@ -142,23 +473,23 @@ mod tests {
         use rand::Rng;
         let mut data = vec![0u8; 65536];
         rand::rand_core::UnwrapErr(rand::rng()).fill_bytes(&mut data); // genuinely incompressible
-        let c = compress(&data, Codec::Zstd(9));
+        let c = compress(&data, Codec::Zstd(9), &no_dicts());
         assert_eq!(c[0], TAG_RAW, "incompressible data must fall back to raw");
-        assert_eq!(decompress(&c, CAP).unwrap(), data);
+        assert_eq!(decompress(&c, CAP, &no_dicts()).unwrap(), data);
     }
 
     #[test]
     fn empty_roundtrips() {
         for codec in [Codec::None, Codec::Zstd(2)] {
-            let c = compress(&[], codec);
-            assert_eq!(decompress(&c, CAP).unwrap(), Vec::<u8>::new());
+            let c = compress(&[], codec, &no_dicts());
+            assert_eq!(decompress(&c, CAP, &no_dicts()).unwrap(), Vec::<u8>::new());
         }
     }
 
     #[test]
     fn malformed_stored_is_error_not_panic() {
-        assert!(decompress(&[], CAP).is_err()); // no tag byte
-        assert!(decompress(&[42], CAP).is_err()); // unknown tag
+        assert!(decompress(&[], CAP, &no_dicts()).is_err()); // no tag byte
+        assert!(decompress(&[42], CAP, &no_dicts()).is_err()); // unknown tag
     }
 
     #[test]
@@ -167,23 +498,103 @@ mod tests {
         // rather than allocate it. Compress 1 MiB of zeros (tiny frame, huge
         // expansion) and decode it with a deliberately small ceiling.
         let big = vec![0u8; 1 << 20];
-        let c = compress(&big, Codec::Zstd(9));
+        let c = compress(&big, Codec::Zstd(9), &no_dicts());
         assert_eq!(c[0], TAG_ZSTD);
         assert!(
-            decompress(&c, 4096).is_err(),
+            decompress(&c, 4096, &no_dicts()).is_err(),
             "expansion past cap must error"
         );
         // The same frame decodes fine under an adequate ceiling.
-        assert_eq!(decompress(&c, 1 << 20).unwrap(), big);
+        assert_eq!(decompress(&c, 1 << 20, &no_dicts()).unwrap(), big);
     }
 
     #[test]
     fn extension_policy() {
         use std::ffi::OsString;
-        assert_eq!(codec_for_name(&OsString::from("a.log")), Codec::Zstd(9));
-        assert_eq!(codec_for_name(&OsString::from("a.JSON")), Codec::Zstd(9));
+        assert_eq!(codec_for_name(&OsString::from("a.log")), Codec::ZstdDict(9));
+        assert_eq!(
+            codec_for_name(&OsString::from("a.JSON")),
+            Codec::ZstdDict(9)
+        );
+        assert_eq!(codec_for_name(&OsString::from("a.rs")), Codec::Zstd(9));
         assert_eq!(codec_for_name(&OsString::from("a.jpg")), Codec::None);
         assert_eq!(codec_for_name(&OsString::from("a.bin")), Codec::Zstd(2));
         assert_eq!(codec_for_name(&OsString::from("noext")), Codec::Zstd(2));
     }
+
+    /// `n` log-ish JSON lines starting at record `i`.
+    fn json_records(i: usize, n: usize) -> Vec<u8> {
+        (i..i + n)
+            .map(|i| {
+                format!(
+                    "{{\"id\":{i},\"service\":\"bitcoind\",\"status\":\"running\",\
+                     \"health\":{{\"rpc\":\"ok\",\"sync\":{}}},\"updated\":\"2026-10-{:02}\"}}\n",
+                    i * 7 % 100,
+                    i % 28 + 1
+                )
+            })
+            .collect::<String>()
+            .into_bytes()
+    }
+
+    #[test]
+    fn dictionary_frames_roundtrip() {
+        let dicts = Dictionaries::training();
+        let mut set = None;
+        for i in 0.. {
+            set = dicts.observe(&json_records(i * 32, 32), Codec::ZstdDict(9));
+            if set.is_some() {
+                break;
+            }
+        }
+        assert!(dicts
+            .observe(&json_records(0, 32), Codec::ZstdDict(9))
+            .is_none());
+        dicts.insert(1, train(&set.unwrap()).unwrap());
+
+        let plain = json_records(100_000, 3);
+        let with = compress(&plain, Codec::ZstdDict(9), &dicts);
+        let without = compress(&plain, Codec::Zstd(9), &dicts);
+        assert_eq!(with[0], TAG_ZSTD_DICT);
+        assert!(with.len() < without.len(), "dictionary didn't help");
+        assert_eq!(decompress(&with, CAP, &dicts).unwrap(), plain);
+        assert!(decompress(&with, CAP, &no_dicts()).is_err());
+
+        // Past the dictionary size limit a chunk is plain zstd.
+        let big = plain.repeat(DICT_INPUT_MAX / plain.len() + 1);
+        assert_eq!(compress(&big, Codec::ZstdDict(9), &dicts)[0], TAG_ZSTD);
+    }
+
+    #[test]
+    fn sniff_reads_content_over_name() {
+        use rand::Rng;
+        let mut random = vec![0u8; 8192];
+        rand::rand_core::UnwrapErr(rand::rng()).fill_bytes(&mut random);
+        assert_eq!(sniff(&random, Codec::Zstd(2)), Codec::None);
+
+        let mut gz = b"\x1f\x8b\x08\x00".to_vec();
+        gz.extend_from_slice(&[0; 100]);
+        assert_eq!(sniff(&gz, Codec::Zstd(9)), Codec::None);
+
+        assert_eq!(
+            sniff(&json_records(3, 8), Codec::Zstd(2)),
+            Codec::ZstdDict(9)
+        );
+        let binary: Vec<u8> = (0..8192u32).map(|i| (i % 7) as u8).collect();
+        assert_eq!(sniff(&binary, Codec::Zstd(2)), Codec::Zstd(2));
+        assert_eq!(sniff(b"short", Codec::Zstd(2)), Codec::Zstd(2));
+    }
+
+    #[test]
+    fn stats_tally_by_algorithm() {
+        let stats = CompressionStats::default();
+        let text = vec![b'a'; 10_000];
+        stats.record(text.len(), &compress(&text, Codec::Zstd(9), &no_dicts()));
+        stats.record(3, &compress(b"abc", Codec::None, &no_dicts()));
+        let report = stats.report();
+        assert_eq!((report.zstd.chunks, report.raw.chunks), (1, 1));
+        assert_eq!(report.logical_bytes, 10_003);
+        assert_eq!(report.raw.stored_bytes, 4);
+        assert!(report.ratio > 10.0);
+    }
 }
@@ -86,9 +86,12 @@ pub struct Contents {
     /// durable — tombstoning it earlier could, on crash, leave a durable
     /// `Packed` inode pointing at a deleted extent (reads as zeros).
     pending_content_tombstone: Option<ContentId>,
-    /// Compression codec for this file's content, chosen from its name's
-    /// extension at open time (see `compress::codec_for_name`).
-    codec: Codec,
+    /// Compression codec suggested by the file's name's extension (see
+    /// `compress::codec_for_name`).
+    by_name: Codec,
+    /// Compression codec for this file's content, settled on the first chunk
+    /// written (see [`Controller::codec_for`]).
+    codec: Option<Codec>,
     ctrl: Controller,
 }
 
@@ -151,7 +154,7 @@ impl Contents {
         // `decompress` is codec-independent and a mix of codecs across one
         // file's blocks reads back fine. A file with no name (shouldn't happen
         // for an open regular file) gets the unknown-extension default.
-        let codec = inode
+        let by_name = inode
             .attrs
             .parents
             .get_min()
@@ -162,7 +165,8 @@ impl Contents {
             changed,
             body,
             pending_content_tombstone: None,
-            codec,
+            by_name,
+            codec: None,
             ctrl,
         })
     }
@@ -171,6 +175,13 @@ impl Contents {
         self.inode.attrs.size
     }
 
+    /// The codec for this file, deciding it from `sample` on first use.
+    fn codec(&mut self, sample: &[u8]) -> Codec {
+        *self
+            .codec
+            .get_or_insert_with(|| self.ctrl.codec_for(self.inode.inode, self.by_name, sample))
+    }
+
     // ── reads ──────────────────────────────────────────────
 
     pub fn read_exact_at(&mut self, buf: &mut [u8], offset: u64) -> BkfsResult<()> {
@@ -250,6 +261,7 @@ impl Contents {
 
     pub fn write_all_at(&mut self, buf: &[u8], offset: u64) -> BkfsResult<()> {
         self.ctrl.check_rw()?;
+        self.unpin()?;
         let end = offset + buf.len() as u64;
         // Promote the body to the smallest tier that can hold `end`:
         // inline (≤ inline_threshold) → packed (≤ one chunk) → blocks.
@@ -286,6 +298,42 @@ impl Contents {
         Ok(())
     }
 
+    /// Move the body off content pinned by a snapshot before it is modified
+    /// (see [`crate::snapshot`]): the file switches to a fresh content id, a
+    /// packed extent is re-written under it on the next flush, and existing
+    /// block files are shared into it with [`blockstore::clone_block`]. The
+    /// pinned content itself is never touched.
+    fn unpin(&mut self) -> BkfsResult<()> {
+        let size = self.inode.attrs.size;
+        match &mut self.body {
+            Body::Packed {
+                content_id, dirty, ..
+            } if self.ctrl.is_pinned_content(*content_id) => {
+                *content_id = self.ctrl.next_content_id()?;
+                *dirty = true;
+                self.inode.attrs.contents = FileData::Packed(*content_id);
+            }
+            Body::Blocks {
+                content_id,
+                disk_blocks,
+                ..
+            } if self.ctrl.is_pinned_content(*content_id) => {
+                let fresh = self.ctrl.next_content_id()?;
+                // Blocks past EOF are garbage (a truncate not yet pruned);
+                // only the live range is worth sharing.
+                *disk_blocks = (*disk_blocks).min(blockstore::block_count(size));
+                for idx in 0..*disk_blocks {
+                    blockstore::clone_block(&self.ctrl, *content_id, fresh, idx)?;
+                }
+                *content_id = fresh;
+                self.inode.attrs.contents = FileData::File(fresh);
+            }
+            _ => return Ok(()),
+        }
+        self.changed = true;
+        Ok(())
+    }
+
     /// Migrate the body up to the tier that can hold a file of `end` bytes.
     fn promote_for(&mut self, end: u64) -> BkfsResult<()> {
         let inline_threshold = self.ctrl.inline_threshold();
@@ -298,7 +346,10 @@ impl Contents {
                     self.inline_to_blocks()?;
                 }
             }
-            Body::Packed { .. } if end > pack_max => self.packed_to_blocks()?,
+            Body::Packed { .. } if end > pack_max => {
+                self.unpin()?;
+                self.packed_to_blocks()?
+            }
             _ => {}
         }
         Ok(())
@@ -453,7 +504,8 @@ impl Contents {
             *dirty_bytes -= block.len();
             *disk_blocks = (*disk_blocks).max(idx + 1);
             block.truncate(valid);
-            blockstore::write_block(&self.ctrl, content_id, idx, &block, self.codec, false)?;
+            let codec = self.codec(&block);
+            blockstore::write_block(&self.ctrl, content_id, idx, &block, codec, false)?;
             self.ctrl.tick_save()?;
         }
     }
@@ -558,7 +610,8 @@ impl Contents {
             }
             Plan::Packed(cid, bytes) => {
                 if let Some(b) = bytes {
-                    self.ctrl.cpack_put(cid, &b, self.codec, false)?;
+                    let codec = self.codec(&b);
+                    self.ctrl.cpack_put(cid, &b, codec, false)?;
                     self.ctrl.tick_save()?;
                 }
                 self.inode.attrs.contents = FileData::Packed(cid);
@@ -568,6 +621,11 @@ impl Contents {
         }
 
         let required = blockstore::block_count(self.inode.attrs.size);
+        // A truncate prunes trailing blocks, which must not come out of
+        // content a snapshot still references.
+        if matches!(&self.body, Body::Blocks { disk_blocks, .. } if required < *disk_blocks) {
+            self.unpin()?;
+        }
         let (content_id, dirty, disk_blocks) = match &mut self.body {
             Body::Blocks {
                 content_id,
@@ -586,10 +644,11 @@ impl Contents {
                 continue; // past EOF; pruned below
             }
             block.truncate(self.valid_len(idx));
+            let codec = self.codec(&block);
             if idx == last {
                 self.pad_final_inline(&mut block);
             }
-            blockstore::write_block(&self.ctrl, content_id, idx, &block, self.codec, false)?;
+            blockstore::write_block(&self.ctrl, content_id, idx, &block, codec, false)?;
             self.ctrl.tick_save()?;
         }
         for idx in required..disk_blocks {
@@ -1,11 +1,9 @@
+use std::collections::HashMap;
 use std::ffi::OsString;
-use std::fs::File;
 use std::io;
-use std::io::Write;
-use std::os::fd::AsRawFd;
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
-use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, RwLock};
 
 use chacha20::Key;
 use fuser::FileType;
@@ -14,13 +12,18 @@ use rand::rand_core::UnwrapErr;
 use rand::RngExt;
 use sha2::{Digest, Sha256};
 
-use crate::atomic_file::AtomicFile;
+use crate::chunkstore::{self, ChunkHash, Chunker};
+use crate::compress::{self, Codec, CompressionStats, Dictionaries};
 use crate::directory::{DirectoryContents, DirectoryEntry};
-use crate::error::{BkfsResult, BkfsResultExt};
+use crate::error::{BkfsError, BkfsResult, BkfsResultExt};
 use crate::inode::{Attributes, ContentId, FileData, Inode, InodeAttributes};
+use crate::keyslot::{KeySlot, Secret, SlotKind, MAX_SLOTS};
+use crate::scrub::Outcome;
 use crate::seglog::{self, SegmentLog};
+use crate::snapshot::{self, Pins, Snapshot};
+use crate::storage::{self, Storage};
 use crate::superblock::{Constants, Superblock};
-use crate::vault::EccParams;
+use crate::vault::{self, EccParams};
 use crate::{serde, BackupFSOptions, FUSE_ROOT_ID};
 
 #[derive(Clone)]
@@ -36,20 +39,33 @@ const _: fn() = || {
 
 pub struct ControllerSeed {
     config: BackupFSOptions,
-    /// Path to the canonical superblock replica (`data_dir/superblock`).
-    superblock_path: PathBuf,
+    /// Where every object of the store lives (see `storage`).
+    storage: Arc<dyn Storage>,
     /// Format constants adopted from the superblock at mount — the
     /// authoritative source for ECC params, tier thresholds, etc. (the
     /// environment is consulted only at filesystem creation).
     constants: Constants,
-    /// Superblock creation time, carried through password changes.
+    /// Superblock creation time, carried through key slot changes.
     created_unix: u64,
-    /// Live superblock write generation; bumped on every password change so a
+    /// Live superblock write generation; bumped on every key slot change so a
     /// crash mid-rewrite converges to the newest replica on the next mount.
     sb_generation: AtomicU64,
+    /// The superblock's key slots. The lock also serializes their rewrites.
+    keyslots: Mutex<Vec<KeySlot>>,
+    /// Id of the slot this mount was unlocked with.
+    unlocked_slot: u8,
+    /// On-disk data format version, fixed at creation (see `superblock`).
+    format_version: u32,
     key: Key,
-    contents_dir: PathBuf,
-    dirents_dir: PathBuf,
+    /// Content-defined chunker; present iff the store deduplicates (format
+    /// v2, see `chunkstore`).
+    chunker: Option<Chunker>,
+    /// Striped per-chunk locks serializing a chunk's write, count update and
+    /// unlink.
+    chunk_locks: [Mutex<()>; CHUNK_LOCK_STRIPES],
+    /// Chunk references dropped since the last syncfs, released by
+    /// `chunkstore::reap` once the manifests that dropped them are durable.
+    chunk_derefs: Mutex<Vec<ChunkHash>>,
     /// Log-structured inode store. Inodes are records here, not per-inode
     /// files. Behind a Mutex because the Controller is shared across the
     /// worker pool; sealing happens outside the lock (see `seglog`).
@@ -63,11 +79,30 @@ pub struct ControllerSeed {
     /// this; when it reaches the batch threshold, the next caller
     /// triggers a syncfs that flushes everything accumulated.
     pending_saves: AtomicUsize,
-    /// Cached fd on the data dir for syncfs. Lazily opened and reused
-    /// so the batched syncfs path doesn't pay an open(2) on every call.
-    data_dir_fd: OnceLock<File>,
+    /// Objects referenced by snapshots, which the live tree must copy on
+    /// write instead of rewriting or deleting (see `snapshot`).
+    pins: RwLock<Pins>,
+    /// The snapshot being served instead of the live log, when mounted with
+    /// `BackupFSOptions::snapshot` (always read-only).
+    view: Option<Snapshot>,
+    /// Trained zstd dictionaries (format v3), loaded from `dicts/`.
+    dicts: Dictionaries,
+    /// Sniffed compression codec per inode (see `compress::sniff`), so a file
+    /// keeps the decision made on its first chunk across reopens.
+    codecs: Mutex<HashMap<Inode, Codec>>,
+    /// Compression ratios achieved since mount.
+    compression: CompressionStats,
 }
 
+const CHUNK_LOCK_STRIPES: usize = 64;
+
+/// Entries kept in the per-inode codec cache before it is reset. A forgotten
+/// decision just costs a re-sniff on the file's next write.
+const CODEC_CACHE_CAP: usize = 65536;
+
+/// Directory of sealed compression dictionaries, `dicts/<id:08x>`.
+const DICTS_DIR: &str = "dicts";
+
 /// Dead-byte ratio above which a sealed segment is compacted on the next
 /// reclamation pass. Overridable via `BACKUPFS_COMPACT_RATIO`; ≥1.0 disables.
 fn compact_ratio() -> f64 {
@@ -83,12 +118,16 @@ fn compact_ratio() -> f64 {
 }
 
 impl Controller {
-    pub fn new(config: BackupFSOptions) -> BkfsResult<Self> {
+    pub fn new(mut config: BackupFSOptions) -> BkfsResult<Self> {
+        if config.snapshot.is_some() {
+            config.readonly = true;
+        }
         // The superblock is the anchor: it yields the master key and the
         // authoritative format constants (validated/version-gated on open).
-        let superblock_path = config.data_dir.join("superblock");
+        let storage = storage::open(&config)?;
         let t = std::time::Instant::now();
-        let sb = Superblock::open_or_create(&superblock_path, &config.password, config.readonly)?;
+        let secret = Secret::from_options(&config)?;
+        let sb = Superblock::open_or_create(&*storage, &secret, config.readonly)?;
         log::info!("superblock opened in {:?}", t.elapsed());
         let key = sb.key;
         let constants = sb.constants;
@@ -96,27 +135,61 @@ impl Controller {
         // index and the max-inode high-water mark. The segment size is pinned
         // by the superblock, not the environment.
         let t = std::time::Instant::now();
-        let log = SegmentLog::open_sized(
-            config.data_dir.join("segments"),
-            key,
-            constants.segment_size,
-        )?;
+        let log = SegmentLog::open_sized(storage.clone(), key, constants.segment_size)?;
         log::info!("segment log opened in {:?}", t.elapsed());
         let next_inode = (log.max_inode() + 1).max(FUSE_ROOT_ID + 1);
-        Ok(Self(Arc::new(ControllerSeed {
+        let dicts = if sb.format_version >= 3 && !config.readonly {
+            Dictionaries::training()
+        } else {
+            Dictionaries::default()
+        };
+        for (name, _) in storage.list(DICTS_DIR)? {
+            let Some(id) = name
+                .strip_prefix("dicts/")
+                .and_then(|id| u32::from_str_radix(id, 16).ok())
+            else {
+                continue;
+            };
+            if let Some(blob) = storage.get(&name)? {
+                dicts.insert(id, vault::open(&blob, &key)?);
+            }
+        }
+        let snapshot = config.snapshot.clone();
+        let ctrl = Self(Arc::new(ControllerSeed {
             key,
-            contents_dir: config.data_dir.join("contents"),
-            dirents_dir: config.data_dir.join("dirents"),
+            chunker: (sb.format_version >= 2).then(|| Chunker::new(&key)),
+            chunk_locks: std::array::from_fn(|_| Mutex::new(())),
+            chunk_derefs: Mutex::new(Vec::new()),
+            format_version: sb.format_version,
             log: Mutex::new(log),
             next_inode: AtomicU64::new(next_inode),
             pending_saves: AtomicUsize::new(0),
-            data_dir_fd: OnceLock::new(),
             config,
-            superblock_path,
+            storage,
             constants,
             created_unix: sb.created_unix,
             sb_generation: AtomicU64::new(sb.generation),
-        })))
+            keyslots: Mutex::new(sb.slots),
+            unlocked_slot: sb.unlocked,
+            pins: RwLock::new(Pins::default()),
+            view: None,
+            dicts,
+            codecs: Mutex::new(HashMap::new()),
+            compression: CompressionStats::default(),
+        }));
+        let pins = snapshot::load_pins(&ctrl)?;
+        ctrl.update_pins(|p| *p = pins);
+        match snapshot {
+            Some(name) => {
+                let view = snapshot::load(&ctrl, &name)?;
+                let Ok(mut seed) = Arc::try_unwrap(ctrl.0) else {
+                    unreachable!("controller not yet shared")
+                };
+                seed.view = Some(view);
+                Ok(Self(Arc::new(seed)))
+            }
+            None => Ok(ctrl),
+        }
     }
 
     /// ECC parameters for new writes, adopted from the superblock.
@@ -124,6 +197,16 @@ impl Controller {
         self.0.constants.ecc()
     }
 
+    /// On-disk data format version of this store.
+    pub fn format_version(&self) -> u32 {
+        self.0.format_version
+    }
+
+    /// The content-defined chunker, if this store deduplicates block content.
+    pub fn chunker(&self) -> Option<&Chunker> {
+        self.0.chunker.as_ref()
+    }
+
     /// Inline-tier upper bound (bytes), from the superblock.
     pub fn inline_threshold(&self) -> u64 {
         self.0.constants.inline_threshold
@@ -171,13 +254,78 @@ impl Controller {
     }
 
     pub fn log_load(&self, inode: Inode) -> BkfsResult<Option<Attributes>> {
+        if let Some(view) = &self.0.view {
+            return Ok(view.get(inode).cloned());
+        }
         self.0.log.lock().unwrap().load(inode)
     }
 
     pub fn log_contains(&self, inode: Inode) -> bool {
+        if let Some(view) = &self.0.view {
+            return view.contains(inode);
+        }
         self.0.log.lock().unwrap().contains(inode)
     }
 
+    /// Every inode live in the log (ignores a mounted snapshot view).
+    pub fn live_inodes(&self) -> Vec<Inode> {
+        self.0.log.lock().unwrap().live_inodes()
+    }
+
+    /// Codec for `inode`'s content under the configured selection mode:
+    /// `by_name` (see `compress::codec_for_name`), or what the first chunk
+    /// written, `sample`, sniffs as — decided once per inode and cached.
+    pub fn codec_for(&self, inode: Inode, by_name: Codec, sample: &[u8]) -> Codec {
+        if compress::selection() == compress::Selection::Extension {
+            return by_name;
+        }
+        let mut codecs = self.0.codecs.lock().unwrap();
+        if let Some(codec) = codecs.get(&inode) {
+            return *codec;
+        }
+        let codec = compress::sniff(sample, by_name);
+        if codecs.len() >= CODEC_CACHE_CAP {
+            codecs.clear();
+        }
+        codecs.insert(inode, codec);
+        codec
+    }
+
+    /// Compress a chunk of content for storage (see `compress::compress`),
+    /// counting the ratio achieved and feeding the dictionary trainer.
+    pub fn compress(&self, plain: &[u8], codec: Codec) -> Vec<u8> {
+        if let Some(samples) = self.0.dicts.observe(plain, codec) {
+            self.train_dictionary(&samples);
+        }
+        let stored = compress::compress(plain, codec, &self.0.dicts);
+        self.0.compression.record(plain.len(), &stored);
+        stored
+    }
+
+    /// Inverse of [`Self::compress`], bounded by `max_len`.
+    pub fn decompress(&self, stored: &[u8], max_len: usize) -> BkfsResult<Vec<u8>> {
+        compress::decompress(stored, max_len, &self.0.dicts)
+    }
+
+    /// Train a dictionary and persist it durably before any frame can use
+    /// it. A failure just leaves small chunks on plain zstd.
+    fn train_dictionary(&self, samples: &[Vec<u8>]) {
+        let id = self.0.dicts.latest().map_or(1, |d| d.id + 1);
+        let res = compress::train(samples)
+            .map_err(BkfsError::from)
+            .and_then(|raw| {
+                let blob = vault::seal(&raw, self.key(), self.ecc());
+                self.storage()
+                    .put(&format!("{DICTS_DIR}/{id:08x}"), &blob, true)?;
+                self.0.dicts.insert(id, raw);
+                Ok(())
+            });
+        match res {
+            Ok(()) => log::info!("trained compression dictionary {id}"),
+            Err(e) => log::warn!("compression dictionary training failed: {e}"),
+        }
+    }
+
     /// Append/replace a packed-content extent (small/medium file content
     /// stored in shared segments rather than its own block file). Sealing is
     /// done outside the log lock.
@@ -185,12 +333,12 @@ impl Controller {
         &self,
         id: ContentId,
         bytes: &[u8],
-        codec: crate::compress::Codec,
+        codec: Codec,
         durable: bool,
     ) -> BkfsResult<()> {
         // Compress before sealing (ciphertext is incompressible); the
         // extent's stored payload carries the compression tag.
-        let stored = crate::compress::compress(bytes, codec);
+        let stored = self.compress(bytes, codec);
         let rec = seglog::seal_content(&self.key(), self.ecc(), id.0, &stored)?;
         let mut log = self.0.log.lock().unwrap();
         log.append(&rec)?;
@@ -204,15 +352,17 @@ impl Controller {
         match self.0.log.lock().unwrap().load_content(id.0)? {
             // A packed extent is never size-padded and is ≤ pack_max ≤ one
             // chunk, so cap the decompressed size at a chunk.
-            Some(stored) => Ok(Some(crate::compress::decompress(
-                &stored,
-                crate::blockstore::CHUNK_SIZE as usize,
-            )?)),
+            Some(stored) => Ok(Some(
+                self.decompress(&stored, crate::blockstore::CHUNK_SIZE as usize)?,
+            )),
             None => Ok(None),
         }
     }
 
     pub fn cpack_tombstone(&self, id: ContentId, durable: bool) -> BkfsResult<()> {
+        if self.is_pinned_content(id) {
+            return Ok(());
+        }
         let rec = seglog::seal_content_tombstone(&self.key(), self.ecc(), id.0)?;
         let mut log = self.0.log.lock().unwrap();
         log.append(&rec)?;
@@ -240,6 +390,31 @@ impl Controller {
         Ok(())
     }
 
+    /// Every live log frame, for [`crate::scrub`].
+    pub fn live_frames(&self) -> Vec<(seglog::Space, u64, seglog::Location)> {
+        self.0.log.lock().unwrap().live_frames()
+    }
+
+    /// Verify (and, if `repair`, heal) one live log frame in place.
+    pub fn scrub_frame(
+        &self,
+        space: seglog::Space,
+        key: u64,
+        loc: seglog::Location,
+        repair: bool,
+    ) -> BkfsResult<Outcome> {
+        self.0
+            .log
+            .lock()
+            .unwrap()
+            .scrub_frame(space, key, loc, repair)
+    }
+
+    /// The backend holding the store's objects.
+    pub fn storage(&self) -> &dyn Storage {
+        &*self.0.storage
+    }
+
     /// Reclaim dead space in the log by compacting heavily-dead sealed
     /// segments (live frames relocated verbatim, then the segment deleted).
     /// Gated on `BACKUPFS_COMPACT_RATIO` (default 0.6; ≥1.0 disables): only a
@@ -356,19 +531,86 @@ impl Controller {
         Ok(())
     }
 
+    /// Re-enroll the password slot this mount was unlocked with under a new
+    /// password.
     pub fn change_password(&self, password: &str) -> BkfsResult<()> {
         self.check_rw()?;
-        // Re-seal the superblock under the new password with a bumped
-        // generation, so a crash mid-rewrite converges to the new password
-        // (highest generation wins on load), never rolling back to the old.
+        let mut slots = self.0.keyslots.lock().unwrap();
+        let id = self.0.unlocked_slot;
+        let Some(idx) = slots
+            .iter()
+            .position(|s| s.id == id && s.kind == SlotKind::Password)
+        else {
+            return Err(BkfsError::unsupported(
+                "the store was not unlocked with a password slot; use `key-slot add` and \
+                 `key-slot remove` to manage its slots",
+            ));
+        };
+        let mut next = slots.clone();
+        next[idx] = KeySlot::new(id, &Secret::password(password), &self.0.key, self.ecc())?;
+        self.persist_superblock(&next)?;
+        *slots = next;
+        Ok(())
+    }
+
+    /// Enroll `secret` in the lowest free key slot, returning its id.
+    pub fn add_key_slot(&self, secret: &Secret) -> BkfsResult<u8> {
+        self.check_rw()?;
+        let mut slots = self.0.keyslots.lock().unwrap();
+        let Some(id) = (0..MAX_SLOTS as u8).find(|id| slots.iter().all(|s| s.id != *id)) else {
+            return Err(BkfsError::unsupported(format!(
+                "all {MAX_SLOTS} key slots are in use; remove one first"
+            )));
+        };
+        let mut next = slots.clone();
+        next.push(KeySlot::new(id, secret, &self.0.key, self.ecc())?);
+        next.sort_by_key(|s| s.id);
+        self.persist_superblock(&next)?;
+        *slots = next;
+        Ok(id)
+    }
+
+    /// Revoke key slot `id`. The last slot can't be removed: the store would
+    /// become impossible to open.
+    pub fn remove_key_slot(&self, id: u8) -> BkfsResult<()> {
+        self.check_rw()?;
+        let mut slots = self.0.keyslots.lock().unwrap();
+        let Some(idx) = slots.iter().position(|s| s.id == id) else {
+            return BkfsResult::errno_notrace(libc::ENOENT);
+        };
+        if slots.len() == 1 {
+            return Err(BkfsError::unsupported(
+                "refusing to remove the last key slot; the store could never be opened again",
+            ));
+        }
+        let mut next = slots.clone();
+        next.remove(idx);
+        self.persist_superblock(&next)?;
+        *slots = next;
+        Ok(())
+    }
+
+    /// Id and kind of every key slot.
+    pub fn key_slots(&self) -> Vec<(u8, SlotKind)> {
+        let slots = self.0.keyslots.lock().unwrap();
+        slots.iter().map(|s| (s.id, s.kind)).collect()
+    }
+
+    /// Rewrite the superblock with `slots`, under a bumped generation so a
+    /// crash mid-rewrite converges to the new slot table (highest generation
+    /// wins on load), never rolling back to the old.
+    fn persist_superblock(&self, slots: &[KeySlot]) -> BkfsResult<()> {
         let generation = self.0.sb_generation.fetch_add(1, Ordering::SeqCst) + 1;
         let sb = Superblock {
             key: self.0.key,
+            format_version: self.0.format_version,
             constants: self.0.constants,
             generation,
             created_unix: self.0.created_unix,
+            slots: slots.to_vec(),
+            unlocked: self.0.unlocked_slot,
         };
-        sb.persist(&self.0.superblock_path, password)
+        sb.persist(&*self.0.storage)
     }
 
     pub fn check_rw(&self) -> BkfsResult<()> {
@@ -379,13 +621,13 @@ impl Controller {
         }
     }
 
-    /// Deterministic, key-dependent filename for content block
+    /// Deterministic, key-dependent object name for content block
     /// `(content, idx)`. The name is a keyed SHA-256 hash, so it leaks no
     /// information about the inode or offset, yet is stable across runs —
     /// editing one region of a file rewrites exactly one block file and
     /// every other block keeps its name (the property that makes
     /// rsync/rclone incremental copies cheap).
-    pub fn block_path(&self, content: ContentId, idx: u64) -> PathBuf {
+    pub fn block_object(&self, content: ContentId, idx: u64) -> String {
         // FROZEN as superblock `path_hash_scheme == 1`: the domain tag, the
         // little-endian id encoding, and the 16-bit-dir / 112-bit-name split
         // below all determine where bytes physically live and how reads find
@@ -405,17 +647,66 @@ impl Controller {
         for b in &tag[2..16] {
             name.push_str(&format!("{b:02x}"));
         }
-        self.0.contents_dir.join(format!("{dir:04x}/{name}"))
+        format!("contents/{dir:04x}/{name}")
+    }
+
+    /// Blocks have no legacy layout, so resolution is just [`Self::block_object`].
+    pub fn resolve_block_object(&self, content: ContentId, idx: u64) -> String {
+        self.block_object(content, idx)
+    }
+
+    /// Object name of a deduplicated chunk, named by its (already keyed) hash
+    /// with the same 16-bit-dir / 112-bit-name split as
+    /// [`Self::block_object`]. FROZEN as part of format v2.
+    pub fn chunk_object(&self, hash: &ChunkHash) -> String {
+        let dir = u16::from_be_bytes([hash[0], hash[1]]);
+        let mut name = String::with_capacity(28);
+        for b in &hash[2..16] {
+            name.push_str(&format!("{b:02x}"));
+        }
+        format!("chunks/{dir:04x}/{name}")
+    }
+
+    /// Lock serializing the write, count update and unlink of `hash`.
+    pub fn chunk_lock(&self, hash: &ChunkHash) -> MutexGuard<'_, ()> {
+        self.0.chunk_locks[hash[0] as usize % CHUNK_LOCK_STRIPES]
+            .lock()
+            .unwrap()
+    }
+
+    pub fn chunk_refs(&self, hash: &ChunkHash) -> u64 {
+        self.0.log.lock().unwrap().chunk_refs(hash)
+    }
+
+    /// Record a chunk's new reference count (0 drops it from the index).
+    /// Caller holds [`Self::chunk_lock`].
+    pub fn set_chunk_refs(&self, hash: &ChunkHash, refs: u64) -> BkfsResult<()> {
+        let rec = seglog::seal_chunk_refs(&self.key(), self.ecc(), hash, refs)?;
+        self.0.log.lock().unwrap().append(&rec)
+    }
+
+    /// Number of chunks with a live reference count.
+    pub fn chunk_count(&self) -> usize {
+        self.0.log.lock().unwrap().chunk_count()
+    }
+
+    /// Queue chunk references for release after the next syncfs.
+    pub fn defer_chunk_derefs(&self, hashes: impl IntoIterator<Item = ChunkHash>) {
+        self.0.chunk_derefs.lock().unwrap().extend(hashes);
+    }
+
+    pub fn take_chunk_derefs(&self) -> Vec<ChunkHash> {
+        std::mem::take(&mut *self.0.chunk_derefs.lock().unwrap())
     }
 
-    /// Blocks have no legacy layout, so resolution is just [`Self::block_path`].
-    pub fn resolve_block_path(&self, content: ContentId, idx: u64) -> PathBuf {
-        self.block_path(content, idx)
+    /// fdatasync the active log segment.
+    pub fn log_sync(&self) -> BkfsResult<()> {
+        self.0.log.lock().unwrap().sync()
     }
 
-    /// Path of a spilled-directory bucket file, keyed by `(dir, gen, idx)`.
-    /// Part of FROZEN superblock `path_hash_scheme == 1` (see [`Self::block_path`]).
-    fn dir_bucket_path(&self, dir: Inode, gen: u64, idx: u32) -> PathBuf {
+    /// Object name of a spilled-directory bucket, keyed by `(dir, gen, idx)`.
+    /// Part of FROZEN superblock `path_hash_scheme == 1` (see [`Self::block_object`]).
+    fn dir_bucket_object(&self, dir: Inode, gen: u64, idx: u32) -> String {
         let mut hasher = Sha256::new();
         hasher.update(self.0.key.as_slice());
         hasher.update(b"dirbucket");
@@ -428,7 +719,7 @@ impl Controller {
         for b in &tag[2..16] {
             name.push_str(&format!("{b:02x}"));
         }
-        self.0.dirents_dir.join(format!("{bucket:04x}/{name}"))
+        format!("dirents/{bucket:04x}/{name}")
     }
 
     /// Load one directory bucket; a missing file is an empty bucket.
@@ -438,10 +729,9 @@ impl Controller {
         gen: u64,
         idx: u32,
     ) -> BkfsResult<crate::directory::Bucket> {
-        match std::fs::read(self.dir_bucket_path(dir, gen, idx)) {
-            Ok(blob) => serde::deserialize_sealed(&blob, self.key()),
-            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Default::default()),
-            Err(e) => Err(e.into()),
+        match self.0.storage.get(&self.dir_bucket_object(dir, gen, idx))? {
+            Some(blob) => serde::deserialize_sealed(&blob, self.key()),
+            None => Ok(Default::default()),
         }
     }
 
@@ -460,24 +750,24 @@ impl Controller {
             return self.remove_dir_bucket(dir, gen, idx);
         }
         let blob = serde::serialize_sealed(bucket, self.key(), self.ecc())?;
-        let mut file = AtomicFile::create_buffered(self.dir_bucket_path(dir, gen, idx))?;
-        file.write_all(&blob)?;
-        if durable {
-            file.save()
-        } else {
-            file.save_fast()?;
+        let name = self.dir_bucket_object(dir, gen, idx);
+        self.0.storage.put(&name, &blob, durable)?;
+        if !durable {
             self.tick_save()?;
-            Ok(())
         }
+        Ok(())
     }
 
-    /// Remove one directory bucket file, tolerating absence.
+    /// Remove one directory bucket, tolerating absence. A generation
+    /// pinned by a snapshot is left in place.
     pub fn remove_dir_bucket(&self, dir: Inode, gen: u64, idx: u32) -> BkfsResult<()> {
-        match std::fs::remove_file(self.dir_bucket_path(dir, gen, idx)) {
-            Ok(()) => Ok(()),
-            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
-            Err(e) => Err(e.into()),
+        if self.is_pinned_dir(dir, gen) {
+            return Ok(());
         }
+        self.0
+            .storage
+            .delete(&self.dir_bucket_object(dir, gen, idx))?;
+        Ok(())
     }
 
     pub fn next_inode(&self) -> BkfsResult<Inode> {
@@ -492,6 +782,29 @@ impl Controller {
         Ok(Inode(id))
     }
 
+    /// A content id for copy-on-write. Drawn from the inode allocator so it
+    /// can never collide with an inode-derived content id, and reserved in the
+    /// log with a tombstone so replay's high-water mark covers it.
+    pub fn next_content_id(&self) -> BkfsResult<ContentId> {
+        let Inode(id) = self.next_inode()?;
+        self.log_tombstone(Inode(id), false)?;
+        Ok(ContentId(id))
+    }
+
+    /// Whether a snapshot references content `id`.
+    pub fn is_pinned_content(&self, id: ContentId) -> bool {
+        self.0.pins.read().unwrap().content(id)
+    }
+
+    /// Whether a snapshot references bucket generation `gen` of `dir`.
+    pub fn is_pinned_dir(&self, dir: Inode, gen: u64) -> bool {
+        self.0.pins.read().unwrap().dir(dir, gen)
+    }
+
+    pub fn update_pins(&self, f: impl FnOnce(&mut Pins)) {
+        f(&mut self.0.pins.write().unwrap())
+    }
+
     pub fn file_pad(&self, size: u64) -> u64 {
         size + (self
             .0
@@ -532,28 +845,25 @@ impl Controller {
         T::exists(self, args)
     }
 
-    fn data_dir_fd(&self) -> io::Result<&File> {
-        if let Some(f) = self.0.data_dir_fd.get() {
-            return Ok(f);
-        }
-        let fd = File::open(&self.0.config.data_dir)?;
-        // Another thread may have raced us; either outcome is fine,
-        // the loser drops its fd.
-        let _ = self.0.data_dir_fd.set(fd);
-        Ok(self.0.data_dir_fd.get().unwrap())
-    }
-
-    /// Flush the entire backing filesystem's page cache and device
-    /// write cache. One syncfs replaces many per-file fsync calls when
-    /// batching writes with `save_fast`.
+    /// Make every write so far durable — for a local store, flush the
+    /// entire backing filesystem's page cache and device write cache. One
+    /// syncfs replaces many per-file fsync calls when batching writes with
+    /// `save_fast`.
     pub fn syncfs(&self) -> io::Result<()> {
-        let fd = self.data_dir_fd()?.as_raw_fd();
         // Zero the pending counter under the same call — any races
         // just cause an extra syncfs later, which is harmless.
         self.0.pending_saves.store(0, Ordering::Relaxed);
-        // SAFETY: fd is a valid fd we own (held in data_dir_fd).
-        if unsafe { libc::syncfs(fd) } != 0 {
-            return Err(io::Error::last_os_error());
+        // Snapshot the queued chunk drops first: a drop is queued only after
+        // the manifest replacing it is written, so everything in the snapshot
+        // is covered by the sync below. Drops queued while it runs may belong
+        // to manifests it missed, and wait for the next syncfs.
+        let derefs = self.take_chunk_derefs();
+        if let Err(e) = self.0.storage.sync() {
+            self.defer_chunk_derefs(derefs);
+            return Err(e);
+        }
+        if self.0.chunker.is_some() {
+            chunkstore::reap(self, derefs)?;
         }
         Ok(())
     }
@@ -609,6 +919,17 @@ pub struct StatFs {
     pub ffree: u64,
 }
 
+/// What the stats command reports for a mounted filesystem.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct Stats {
+    /// Inode numbers allocated so far (see [`Controller::statfs`]).
+    pub files: u64,
+    /// Trained compression dictionaries in the store.
+    pub dictionaries: usize,
+    /// Compression achieved by writes since mount.
+    pub compression: compress::StatsReport,
+}
+
 impl Controller {
     pub fn statfs(&self) -> StatFs {
         // Approximate: every number below the monotonic allocator counts as
@@ -619,4 +940,12 @@ impl Controller {
             ffree: u64::MAX - used,
         }
     }
+
+    pub fn stats(&self) -> Stats {
+        Stats {
+            files: self.statfs().files,
+            dictionaries: self.0.dicts.len(),
+            compression: self.0.compression.report(),
+        }
+    }
 }
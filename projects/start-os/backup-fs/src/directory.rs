@@ -139,6 +139,36 @@ impl DirectoryContents {
         1 + subdirs as usize
     }
 
+    /// `(gen, buckets)` of a spilled directory's current bucket set.
+    pub fn spilled_generation(&self) -> Option<(u64, u32)> {
+        match &self.0 {
+            DirEntries::Inline(_) => None,
+            DirEntries::Spilled { gen, buckets, .. } => Some((*gen, *buckets)),
+        }
+    }
+
+    /// Move a spilled directory off a bucket generation pinned by a snapshot
+    /// before it is modified: the entries are rewritten under a fresh
+    /// generation and the marker switched to it. The pinned generation is
+    /// left untouched (and is never reaped while pinned); the caller persists
+    /// the marker as part of the mutation that follows.
+    fn unpin(&mut self, ctrl: &Controller, dir: Inode) -> BkfsResult<()> {
+        let DirEntries::Spilled { gen, buckets, .. } = self.0 else {
+            return Ok(());
+        };
+        if !ctrl.is_pinned_dir(dir, gen) {
+            return Ok(());
+        }
+        let entries = self.snapshot(ctrl, dir)?;
+        // Generations only grow, so every pinned one is ≤ `gen`.
+        let new_gen = gen + 1;
+        self.write_generation(ctrl, dir, new_gen, buckets, &entries)?;
+        if let DirEntries::Spilled { gen, .. } = &mut self.0 {
+            *gen = new_gen;
+        }
+        Ok(())
+    }
+
     /// Look up one entry. Touches a single bucket when spilled.
     pub fn get(
         &self,
@@ -176,6 +206,7 @@ impl DirectoryContents {
         entry: DirectoryEntry,
         durable: bool,
     ) -> BkfsResult<Option<(u64, u32)>> {
+        self.unpin(ctrl, dir)?;
         // Spill an inline directory that is about to exceed the threshold.
         // (Spilling never deletes the inline source — it lives in the inode
         // blob until the marker is saved — so a torn spill only orphans the
@@ -237,6 +268,7 @@ impl DirectoryContents {
         name: &OsStr,
         durable: bool,
     ) -> BkfsResult<Option<DirectoryEntry>> {
+        self.unpin(ctrl, dir)?;
         match &mut self.0 {
             DirEntries::Inline(m) => Ok(m.remove(name)),
             DirEntries::Spilled {
@@ -416,9 +448,13 @@ mod tests {
             data_dir: dir.to_owned(),
             setuid_support: false,
             password: "x".to_owned(),
+            keyfile: None,
+            master_key_file: None,
             file_size_padding: None,
             readonly: false,
             idmapped: false,
+            snapshot: None,
+            object_store: None,
         })
         .unwrap()
     }
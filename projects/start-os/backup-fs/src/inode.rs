@@ -19,7 +19,7 @@ pub const BLOCK_SIZE: u64 = 4096;
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
 pub struct Inode(pub u64);
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
 pub struct ContentId(pub u64);
 
 impl From<Inode> for ContentId {
@@ -160,7 +160,7 @@ impl InodeAttributes {
     }
 }
 
-fn system_time_from_time(secs: i64, nsecs: u32) -> SystemTime {
+pub(crate) fn system_time_from_time(secs: i64, nsecs: u32) -> SystemTime {
     if secs >= 0 {
         UNIX_EPOCH + Duration::new(secs as u64, nsecs)
     } else {
@@ -0,0 +1,295 @@
+//! Key slots: independent secrets that each unlock the master key.
+//!
+//! Like LUKS, the superblock never derives the master key from a password.
+//! Each slot instead holds the master key *wrapped* (sealed with
+//! [`vault::seal`]) under a key derived from that slot's secret, so a backup
+//! can be opened by any of several passwords, a recovery keyfile, or a key
+//! the server derives from its own master key — and a slot can be added or
+//! revoked without re-encrypting anything else. The superblock body itself is
+//! sealed under the master key.
+//!
+//! The slot table sits in the superblock's plaintext envelope (see
+//! [`crate::superblock`]), so it is readable before any key is known. It is
+//! unauthenticated, like the rest of the envelope, which is why nothing in it
+//! is a tunable input: a slot records only its *kind*, and the KDF work
+//! factor for each kind is fixed by this build ([`SlotKind::rounds`]). A
+//! flipped byte can make a slot fail to unlock, but never cheaper to attack.
+
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+use chacha20::Key;
+use rand::rand_core::UnwrapErr;
+use rand::{rng, Rng};
+use zeroize::Zeroizing;
+
+use crate::error::{BkfsError, BkfsErrorKind, BkfsResult};
+use crate::vault::{self, EccParams, PBKDF2_ROUNDS, PBKDF2_SALT_LEN};
+use crate::BackupFSOptions;
+
+/// Most slots a superblock holds (slot ids are `0..MAX_SLOTS`).
+pub const MAX_SLOTS: usize = 8;
+
+/// Bounds on a keyfile's size. Keyfiles are expected to be random (see
+/// [`generate_keyfile`]), which is what lets them skip key stretching.
+const MIN_KEYFILE_LEN: usize = 16;
+const MAX_KEYFILE_LEN: usize = 8192;
+const GENERATED_KEYFILE_LEN: usize = 64;
+
+/// Upper bound on one slot's wrapped-key blob, checked before allocating.
+const MAX_WRAPPED_LEN: usize = 1024;
+
+/// What kind of secret a slot was enrolled with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlotKind {
+    Password = 1,
+    Keyfile = 2,
+    /// Key material derived by the server from its own master key.
+    MasterKey = 3,
+}
+
+impl SlotKind {
+    fn from_u8(b: u8) -> Option<Self> {
+        match b {
+            1 => Some(SlotKind::Password),
+            2 => Some(SlotKind::Keyfile),
+            3 => Some(SlotKind::MasterKey),
+            _ => None,
+        }
+    }
+
+    /// PBKDF2 work factor for this kind. Only passwords need stretching; the
+    /// other kinds are already full-entropy keys.
+    fn rounds(self) -> u32 {
+        match self {
+            SlotKind::Password => PBKDF2_ROUNDS,
+            SlotKind::Keyfile | SlotKind::MasterKey => 1,
+        }
+    }
+}
+
+impl fmt::Display for SlotKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SlotKind::Password => "password",
+            SlotKind::Keyfile => "keyfile",
+            SlotKind::MasterKey => "master-key",
+        })
+    }
+}
+
+/// A secret presented to unlock a store, or to enroll in a new slot.
+pub enum Secret {
+    Password(Zeroizing<String>),
+    Keyfile(Zeroizing<Vec<u8>>),
+    MasterKey(Zeroizing<Vec<u8>>),
+}
+
+impl Secret {
+    pub fn password(password: &str) -> Secret {
+        Secret::Password(Zeroizing::new(password.to_owned()))
+    }
+
+    /// Read a keyfile.
+    pub fn keyfile(path: &Path) -> BkfsResult<Secret> {
+        read_key_material(path).map(Secret::Keyfile)
+    }
+
+    /// Read server-derived master key material.
+    pub fn master_key(path: &Path) -> BkfsResult<Secret> {
+        read_key_material(path).map(Secret::MasterKey)
+    }
+
+    /// The secret the options unlock with: a keyfile or master key file if
+    /// given, else the password.
+    pub fn from_options(opts: &BackupFSOptions) -> BkfsResult<Secret> {
+        if let Some(path) = &opts.keyfile {
+            Secret::keyfile(path)
+        } else if let Some(path) = &opts.master_key_file {
+            Secret::master_key(path)
+        } else {
+            Ok(Secret::password(&opts.password))
+        }
+    }
+
+    pub fn kind(&self) -> SlotKind {
+        match self {
+            Secret::Password(_) => SlotKind::Password,
+            Secret::Keyfile(_) => SlotKind::Keyfile,
+            Secret::MasterKey(_) => SlotKind::MasterKey,
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Secret::Password(p) => p.as_bytes(),
+            Secret::Keyfile(b) | Secret::MasterKey(b) => b,
+        }
+    }
+
+    /// The key wrapping the master key in a slot of this secret's kind.
+    pub(crate) fn derive(&self, salt: &[u8]) -> BkfsResult<Zeroizing<[u8; 32]>> {
+        vault::derive_key(self.bytes(), salt, self.kind().rounds())
+    }
+}
+
+fn read_key_material(path: &Path) -> BkfsResult<Zeroizing<Vec<u8>>> {
+    let bytes = Zeroizing::new(std::fs::read(path)?);
+    if !(MIN_KEYFILE_LEN..=MAX_KEYFILE_LEN).contains(&bytes.len()) {
+        return Err(BkfsError::unsupported(format!(
+            "{}: key material must be {MIN_KEYFILE_LEN}..={MAX_KEYFILE_LEN} bytes",
+            path.display()
+        )));
+    }
+    Ok(bytes)
+}
+
+/// Write a fresh random keyfile (mode 0600) at `path`, which must not exist.
+pub fn generate_keyfile(path: &Path) -> BkfsResult<Secret> {
+    let mut bytes = Zeroizing::new(vec![0u8; GENERATED_KEYFILE_LEN]);
+    UnwrapErr(rng()).fill_bytes(&mut bytes);
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+    file.write_all(&bytes)?;
+    file.sync_all()?;
+    Ok(Secret::Keyfile(bytes))
+}
+
+/// One enrolled slot: the master key wrapped under its secret's derived key.
+#[derive(Clone)]
+pub struct KeySlot {
+    pub id: u8,
+    pub kind: SlotKind,
+    salt: [u8; PBKDF2_SALT_LEN],
+    wrapped: Vec<u8>,
+}
+
+impl KeySlot {
+    /// Enroll `secret` as slot `id`, wrapping `master` under a fresh salt.
+    pub(crate) fn new(
+        id: u8,
+        secret: &Secret,
+        master: &Key,
+        ecc: EccParams,
+    ) -> BkfsResult<KeySlot> {
+        let mut salt = [0u8; PBKDF2_SALT_LEN];
+        UnwrapErr(rng()).fill_bytes(&mut salt);
+        let key = secret.derive(&salt)?;
+        Ok(KeySlot::wrap(id, secret.kind(), salt, &key, master, ecc))
+    }
+
+    /// Build a slot from an already-derived key (used to carry a pre-slot
+    /// superblock's password forward as slot 0).
+    pub(crate) fn wrap(
+        id: u8,
+        kind: SlotKind,
+        salt: [u8; PBKDF2_SALT_LEN],
+        key: &[u8; 32],
+        master: &Key,
+        ecc: EccParams,
+    ) -> KeySlot {
+        KeySlot {
+            id,
+            kind,
+            salt,
+            wrapped: vault::seal(master.as_slice(), <&Key>::from(key), ecc),
+        }
+    }
+
+    pub(crate) fn salt(&self) -> &[u8; PBKDF2_SALT_LEN] {
+        &self.salt
+    }
+
+    /// Unwrap the master key with this slot's derived `key`. A wrong secret
+    /// fails the vault tag (`BadChecksum`).
+    pub(crate) fn unlock(&self, key: &[u8; 32]) -> BkfsResult<Key> {
+        let master = Zeroizing::new(vault::open(&self.wrapped, <&Key>::from(key))?);
+        let bytes: [u8; 32] = master[..].try_into().map_err(|_| BkfsError {
+            kind: BkfsErrorKind::BadChecksum,
+            backtrace: None,
+        })?;
+        Ok(Key::from(bytes))
+    }
+
+    /// This slot with its wrapped key's shards verified and rebuilt (see
+    /// [`vault::reshard`]), plus how many were bad.
+    pub(crate) fn reshard(&self) -> BkfsResult<(KeySlot, usize)> {
+        let healed = vault::reshard(&self.wrapped)?;
+        let slot = KeySlot {
+            wrapped: healed.healed,
+            ..self.clone()
+        };
+        Ok((slot, healed.bad_shards))
+    }
+
+    /// Append the envelope encoding:
+    /// `id u8 | kind u8 | salt [16] | wrapped_len u16 | wrapped`.
+    pub(crate) fn encode(&self, out: &mut Vec<u8>) {
+        out.push(self.id);
+        out.push(self.kind as u8);
+        out.extend_from_slice(&self.salt);
+        out.extend_from_slice(&(self.wrapped.len() as u16).to_le_bytes());
+        out.extend_from_slice(&self.wrapped);
+    }
+
+    /// Decode one slot from the front of `raw`, returning it and the bytes
+    /// consumed.
+    pub(crate) fn decode(raw: &[u8]) -> BkfsResult<(KeySlot, usize)> {
+        let bad = || BkfsError::unsupported("corrupt superblock key slot table");
+        const FIXED: usize = 2 + PBKDF2_SALT_LEN + 2;
+        if raw.len() < FIXED {
+            return Err(bad());
+        }
+        let id = raw[0];
+        let kind = SlotKind::from_u8(raw[1]).ok_or_else(bad)?;
+        let salt = raw[2..2 + PBKDF2_SALT_LEN].try_into().unwrap();
+        let len = u16::from_le_bytes([raw[FIXED - 2], raw[FIXED - 1]]) as usize;
+        if id as usize >= MAX_SLOTS || len > MAX_WRAPPED_LEN || raw.len() < FIXED + len {
+            return Err(bad());
+        }
+        let slot = KeySlot {
+            id,
+            kind,
+            salt,
+            wrapped: raw[FIXED..FIXED + len].to_vec(),
+        };
+        Ok((slot, FIXED + len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slot_roundtrips_and_rejects_other_secrets() {
+        let master = Key::from([3u8; 32]);
+        let secret = Secret::Keyfile(Zeroizing::new(vec![9u8; 32]));
+        let slot = KeySlot::new(5, &secret, &master, EccParams::default()).unwrap();
+
+        let mut raw = Vec::new();
+        slot.encode(&mut raw);
+        let (back, used) = KeySlot::decode(&raw).unwrap();
+        assert_eq!(used, raw.len());
+        assert_eq!((back.id, back.kind), (5, SlotKind::Keyfile));
+
+        let key = secret.derive(back.salt()).unwrap();
+        assert_eq!(back.unlock(&key).unwrap(), master);
+
+        let other = Secret::Keyfile(Zeroizing::new(vec![8u8; 32]));
+        let wrong = other.derive(back.salt()).unwrap();
+        assert!(matches!(
+            back.unlock(&wrong).unwrap_err().kind,
+            BkfsErrorKind::BadChecksum
+        ));
+
+        raw[1] = 0;
+        assert!(KeySlot::decode(&raw).is_err(), "unknown kind accepted");
+    }
+}
@@ -7,7 +7,7 @@ use std::io::{self, BufRead, BufReader};
 use std::os::raw::c_int;
 use std::os::unix::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, SystemTime};
 
 use fd_lock_rs::{FdLock, LockType};
@@ -32,6 +32,7 @@ use crate::inode::{FileData, Inode, InodeAttributes, BLOCK_SIZE};
 mod aligned_io;
 mod atomic_file;
 mod blockstore;
+mod chunkstore;
 mod compress;
 mod contents;
 mod ctrl;
@@ -40,9 +41,16 @@ mod ecc;
 pub mod error;
 mod handle;
 mod inode;
+pub mod keyslot;
+pub mod offline;
 mod pool;
+#[cfg(feature = "s3")]
+pub mod s3;
+pub mod scrub;
 mod seglog;
 mod serde;
+pub mod snapshot;
+pub mod storage;
 mod superblock;
 #[cfg(test)]
 mod tests;
@@ -52,6 +60,11 @@ mod vault;
 
 pub const FUSE_ROOT_ID: u64 = INodeNo::ROOT.0;
 
+/// Xattr on the mount root whose value is the mounted filesystem's stats as
+/// JSON: inode count, trained compression dictionaries, and the compression
+/// ratios achieved by writes since mount. Read by the `stats` command.
+pub const STATS_XATTR: &[u8] = b"trusted.backupfs.stats";
+
 pub const MAX_NAME_LENGTH: u32 = 255;
 // const MAX_FILE_SIZE: u64 = 1024 * 1024 * 1024 * 1024;
 pub const ENTRY_TTL: Duration = Duration::new(3600, 0);
@@ -83,12 +96,29 @@ pub struct BackupFSOptions {
     pub data_dir: PathBuf,
     #[cfg_attr(feature = "cli", arg(long))]
     pub setuid_support: bool,
-    #[cfg_attr(feature = "cli", arg(long))]
+    /// Unlocks a password key slot. Ignored with `--keyfile` or
+    /// `--master-key-file`.
+    #[cfg_attr(feature = "cli", arg(long, default_value = ""))]
     pub password: String,
+    /// Unlock with a keyfile key slot instead of a password.
+    #[cfg_attr(feature = "cli", arg(long, conflicts_with = "master_key_file"))]
+    pub keyfile: Option<PathBuf>,
+    /// Unlock with key material derived from the server's master key.
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub master_key_file: Option<PathBuf>,
     #[cfg_attr(feature = "cli", arg(long))]
     pub file_size_padding: Option<f64>,
     #[cfg_attr(feature = "cli", arg(short, long))]
     pub readonly: bool,
+    /// Serve the named snapshot instead of the live tree (implies readonly).
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub snapshot: Option<String>,
+    /// Keep the store in an S3-compatible bucket,
+    /// `http(s)://<endpoint>/<bucket>[/<prefix>]`, instead of under
+    /// `data_dir` (which then only holds the lock file). Credentials are read
+    /// from `AWS_ACCESS_KEY_ID` / `AWS_SECRET_ACCESS_KEY`.
+    #[cfg_attr(feature = "cli", arg(long))]
+    pub object_store: Option<String>,
     /// True for the production mount, which start-core wraps in a kernel
     /// idmapped mount and mounts with default_permissions. Gates the
     /// FUSE_ALLOW_IDMAP request — asking for it on a plain mount (no
@@ -102,6 +132,8 @@ pub struct BackupFSOptions {
 pub struct BackupFS {
     lock: FdLock<File>,
     handler: Mutex<Handler>,
+    /// Online scrub state, shared with the background scrub thread.
+    scrub: Arc<Mutex<scrub::ScrubStatus>>,
 }
 
 // The master key and all format-affecting constants live in the versioned
@@ -136,6 +168,7 @@ impl BackupFS {
         Ok(BackupFS {
             lock,
             handler: Mutex::new(Handler::new(ctrl)),
+            scrub: Arc::default(),
         })
     }
 
@@ -150,6 +183,82 @@ impl BackupFS {
             .ctrl()
             .change_password(password)
     }
+
+    /// Enroll `secret` in a free key slot, returning the slot id.
+    pub fn add_key_slot(&mut self, secret: &keyslot::Secret) -> BkfsResult<u8> {
+        self.handler.get_mut().unwrap().ctrl().add_key_slot(secret)
+    }
+
+    /// Revoke a key slot (never the last one).
+    pub fn remove_key_slot(&mut self, id: u8) -> BkfsResult<()> {
+        self.handler.get_mut().unwrap().ctrl().remove_key_slot(id)
+    }
+
+    /// Id and kind of every key slot.
+    pub fn key_slots(&mut self) -> Vec<(u8, keyslot::SlotKind)> {
+        self.handler.get_mut().unwrap().ctrl().key_slots()
+    }
+
+    /// Verify every sealed object and rewrite those ECC had to repair (only
+    /// verify when opened read-only).
+    pub fn scrub(&mut self) -> BkfsResult<scrub::ScrubReport> {
+        scrub::run(self.handler.get_mut().unwrap().ctrl())
+    }
+
+    /// Start an online scrub in the background (see [`scrub::SCRUB_XATTR`]).
+    fn start_scrub(&self) -> BkfsResult<()> {
+        let mut status = self.scrub.lock().unwrap();
+        if status.running {
+            return BkfsResult::errno_notrace(libc::EBUSY);
+        }
+        let ctrl = self.handler.lock().unwrap().ctrl().clone();
+        let shared = self.scrub.clone();
+        std::thread::Builder::new()
+            .name("backup-fs-scrub".into())
+            .spawn(move || {
+                let res = scrub::run(&ctrl);
+                let mut status = shared.lock().unwrap();
+                status.running = false;
+                match res {
+                    Ok(report) => status.last = Some(report),
+                    Err(e) => error!("online scrub failed: {e}"),
+                }
+            })?;
+        status.running = true;
+        Ok(())
+    }
+
+    /// Capture the current tree as a named, read-only snapshot.
+    pub fn create_snapshot(&mut self, name: &str) -> BkfsResult<snapshot::SnapshotInfo> {
+        snapshot::create(self.handler.get_mut().unwrap().ctrl(), name)
+    }
+
+    /// Every snapshot, oldest first.
+    pub fn list_snapshots(&mut self) -> BkfsResult<Vec<snapshot::SnapshotInfo>> {
+        snapshot::list(self.handler.get_mut().unwrap().ctrl())
+    }
+
+    /// Delete a snapshot, reclaiming whatever only it referenced.
+    pub fn delete_snapshot(&mut self, name: &str) -> BkfsResult<()> {
+        snapshot::delete(self.handler.get_mut().unwrap().ctrl(), name)
+    }
+
+    /// Delete all but the newest `keep` snapshots, returning the names removed.
+    pub fn prune_snapshots(&mut self, keep: usize) -> BkfsResult<Vec<String>> {
+        let all = self.list_snapshots()?;
+        let excess = all.len().saturating_sub(keep);
+        let mut pruned = Vec::with_capacity(excess);
+        for info in all.into_iter().take(excess) {
+            self.delete_snapshot(&info.name)?;
+            pruned.push(info.name);
+        }
+        Ok(pruned)
+    }
+
+    /// Read-only access to the backup's tree without mounting it.
+    pub fn reader(&mut self) -> offline::Reader<'_> {
+        offline::Reader::new(self.handler.get_mut().unwrap().ctrl())
+    }
 }
 
 impl Filesystem for BackupFS {
@@ -695,6 +804,15 @@ impl Filesystem for BackupFS {
         _position: u32,
         reply: ReplyEmpty,
     ) {
+        if is_scrub_xattr(ino, name) {
+            return match self.start_scrub() {
+                Ok(()) => reply.ok(),
+                Err(e) => reply.error(errno(e.to_errno_log())),
+            };
+        }
+        if is_stats_xattr(ino, name) {
+            return reply.error(errno(libc::EPERM));
+        }
         let mut h = self.handler.lock().unwrap();
         match h.setxattr(request, Inode(ino.into()), name.as_bytes(), value) {
             Ok(()) => reply.ok(),
@@ -710,8 +828,17 @@ impl Filesystem for BackupFS {
         size: u32,
         reply: ReplyXattr,
     ) {
-        let h = self.handler.lock().unwrap();
-        match h.getxattr(request, Inode(ino.into()), name.as_bytes()) {
+        let res: BkfsResult<Vec<u8>> = if is_scrub_xattr(ino, name) {
+            serde_json::to_vec(&*self.scrub.lock().unwrap())
+                .map_err(|e| io::Error::other(e).into())
+        } else if is_stats_xattr(ino, name) {
+            let stats = self.handler.lock().unwrap().ctrl().stats();
+            serde_json::to_vec(&stats).map_err(|e| io::Error::other(e).into())
+        } else {
+            let h = self.handler.lock().unwrap();
+            h.getxattr(request, Inode(ino.into()), name.as_bytes())
+        };
+        match res {
             Ok(data) => {
                 if size == 0 {
                     reply.size(data.len() as u32);
@@ -837,6 +964,14 @@ impl Filesystem for BackupFS {
     }
 }
 
+fn is_scrub_xattr(ino: INodeNo, name: &OsStr) -> bool {
+    ino.0 == FUSE_ROOT_ID && name.as_bytes() == scrub::SCRUB_XATTR
+}
+
+fn is_stats_xattr(ino: INodeNo, name: &OsStr) -> bool {
+    ino.0 == FUSE_ROOT_ID && name.as_bytes() == STATS_XATTR
+}
+
 /*
 fn as_file_kind(mut mode: u32) -> FileKind {
     mode &= libc::S_IFMT as u32;
@@ -1,11 +1,13 @@
-use std::ffi::OsString;
-use std::io::ErrorKind;
+use std::ffi::{CString, OsString};
+use std::fs::File;
+use std::io::{BufWriter, ErrorKind, Write};
 use std::path::{Path, PathBuf};
 
-use backupfs::error::BkfsErrorKind;
+use backupfs::error::{BkfsError, BkfsErrorKind};
+use backupfs::keyslot::{self, Secret};
 use backupfs::{BackupFS, BackupFSOptions};
 use clap::{CommandFactory, FromArgMatches, Parser};
-use fuser::{Config, MountOption, SessionACL};
+use fuser::{Config, FileType, MountOption, SessionACL};
 use log::{error, info};
 
 #[derive(clap::Parser)]
@@ -33,6 +35,94 @@ struct ChangePasswordOptions {
     new_password: String,
 }
 
+#[derive(clap::Parser)]
+#[command(group(clap::ArgGroup::new("secret").required(true)))]
+struct KeySlotAddOptions {
+    #[command(flatten)]
+    backup_opts: BackupFSOptions,
+    /// Enroll a password
+    #[arg(long, group = "secret")]
+    new_password: Option<String>,
+    /// Enroll a keyfile
+    #[arg(long, group = "secret")]
+    new_keyfile: Option<PathBuf>,
+    /// Create --new-keyfile with fresh random contents rather than read it
+    #[arg(long, requires = "new_keyfile")]
+    generate: bool,
+    /// Enroll key material derived from the server's master key
+    #[arg(long, group = "secret")]
+    new_master_key_file: Option<PathBuf>,
+}
+
+#[derive(clap::Parser)]
+struct KeySlotRemoveOptions {
+    #[command(flatten)]
+    backup_opts: BackupFSOptions,
+    id: u8,
+}
+
+#[derive(clap::Parser)]
+struct LsOptions {
+    #[command(flatten)]
+    backup_opts: BackupFSOptions,
+    /// Show mode, owner, size and modification time
+    #[arg(short, long)]
+    long: bool,
+    #[arg(default_value = "/")]
+    path: PathBuf,
+}
+
+#[derive(clap::Parser)]
+struct CatOptions {
+    #[command(flatten)]
+    backup_opts: BackupFSOptions,
+    path: PathBuf,
+}
+
+#[derive(clap::Parser)]
+struct ExtractOptions {
+    #[command(flatten)]
+    backup_opts: BackupFSOptions,
+    path: PathBuf,
+    dest: PathBuf,
+}
+
+#[derive(clap::Parser)]
+struct ExportOptions {
+    #[command(flatten)]
+    backup_opts: BackupFSOptions,
+    /// Write a tar archive (currently the only export format)
+    #[arg(long, required = true)]
+    tar: bool,
+    /// Output file; stdout if omitted
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    #[arg(default_value = "/")]
+    path: PathBuf,
+}
+
+#[derive(clap::Parser)]
+struct SnapshotOptions {
+    #[command(flatten)]
+    backup_opts: BackupFSOptions,
+    name: String,
+}
+
+#[derive(clap::Parser)]
+struct SnapshotPruneOptions {
+    #[command(flatten)]
+    backup_opts: BackupFSOptions,
+    /// Number of most recent snapshots to keep
+    #[arg(long)]
+    keep: usize,
+}
+
+#[derive(clap::Parser)]
+struct StatsOptions {
+    /// Where the filesystem is mounted
+    mountpoint: PathBuf,
+}
+
 enum ParsedOption {
     Mount(MountOption),
     AllowOther,
@@ -105,14 +195,53 @@ fn main() {
     let mut app = clap::command!()
         .subcommand(MountOptions::command().name("mount"))
         .subcommand(BackupFSOptions::command().name("fsck"))
-        .subcommand(ChangePasswordOptions::command().name("change-password"));
+        .subcommand(
+            BackupFSOptions::command()
+                .name("scrub")
+                .about("Verify every sealed object and rewrite any that needed ECC repair"),
+        )
+        .subcommand(
+            StatsOptions::command()
+                .name("stats")
+                .about("Show a mounted filesystem's compression statistics"),
+        )
+        .subcommand(ChangePasswordOptions::command().name("change-password"))
+        .subcommand(LsOptions::command().name("ls"))
+        .subcommand(CatOptions::command().name("cat"))
+        .subcommand(ExtractOptions::command().name("extract"))
+        .subcommand(ExportOptions::command().name("export"))
+        .subcommand(
+            clap::Command::new("snapshot")
+                .about("Manage read-only point-in-time snapshots")
+                .subcommand_required(true)
+                .subcommand(SnapshotOptions::command().name("create"))
+                .subcommand(BackupFSOptions::command().name("list"))
+                .subcommand(SnapshotOptions::command().name("delete"))
+                .subcommand(SnapshotPruneOptions::command().name("prune")),
+        )
+        .subcommand(
+            clap::Command::new("key-slot")
+                .about("Manage the passwords and keys that can unlock the backup")
+                .subcommand_required(true)
+                .subcommand(KeySlotAddOptions::command().name("add"))
+                .subcommand(BackupFSOptions::command().name("list"))
+                .subcommand(KeySlotRemoveOptions::command().name("remove")),
+        );
     let matches = app.clone().get_matches();
     match matches.subcommand() {
         Some(("mount", sub_m)) => mount(MountOptions::from_arg_matches(sub_m).unwrap()),
         Some(("fsck", sub_m)) => fsck(BackupFSOptions::from_arg_matches(sub_m).unwrap()),
+        Some(("scrub", sub_m)) => scrub(BackupFSOptions::from_arg_matches(sub_m).unwrap()),
+        Some(("stats", sub_m)) => stats(StatsOptions::from_arg_matches(sub_m).unwrap()),
         Some(("change-password", sub_m)) => {
             change_password(ChangePasswordOptions::from_arg_matches(sub_m).unwrap())
         }
+        Some(("ls", sub_m)) => ls(LsOptions::from_arg_matches(sub_m).unwrap()),
+        Some(("cat", sub_m)) => cat(CatOptions::from_arg_matches(sub_m).unwrap()),
+        Some(("extract", sub_m)) => extract(ExtractOptions::from_arg_matches(sub_m).unwrap()),
+        Some(("export", sub_m)) => export(ExportOptions::from_arg_matches(sub_m).unwrap()),
+        Some(("snapshot", sub_m)) => snapshot(sub_m),
+        Some(("key-slot", sub_m)) => key_slot(sub_m),
         _ => app.print_long_help().unwrap(),
     }
 }
@@ -132,6 +261,19 @@ fn new_fs(opts: BackupFSOptions) -> BackupFS {
     }
 }
 
+/// Open a backup for the offline (no FUSE) commands. These never write, so
+/// the store is opened read-only regardless of `--readonly`.
+fn open_readonly(mut opts: BackupFSOptions) -> BackupFS {
+    opts.readonly = true;
+    new_fs(opts)
+}
+
+/// Exit with an error message for a failed offline command.
+fn fail(err: impl std::fmt::Display) -> ! {
+    error!("{err}");
+    std::process::exit(1)
+}
+
 fn mount(
     MountOptions {
         mut backup_opts,
@@ -164,7 +306,7 @@ fn mount(
         config.mount_options.push(MountOption::AutoUnmount);
     }
 
-    if backup_opts.readonly {
+    if backup_opts.readonly || backup_opts.snapshot.is_some() {
         config.mount_options.push(MountOption::RO);
     } else if config.mount_options.contains(&MountOption::RO) {
         backup_opts.readonly = true;
@@ -206,6 +348,66 @@ fn fsck(options: BackupFSOptions) {
     new_fs(options).fsck().unwrap()
 }
 
+/// Print the scrub report as JSON. With `--readonly`, only verifies. Exits
+/// with 5 if any object was beyond repair.
+fn scrub(options: BackupFSOptions) {
+    let report = new_fs(options).scrub().unwrap_or_else(|e| fail(e));
+    let mut out = std::io::stdout().lock();
+    serde_json::to_writer_pretty(&mut out, &report)
+        .map_err(std::io::Error::from)
+        .and_then(|()| writeln!(out))
+        .unwrap_or_else(|e| fail(e));
+    if !report.unrecoverable.is_empty() {
+        std::process::exit(5);
+    }
+}
+
+/// Print the stats a mounted filesystem reports through
+/// [`backupfs::STATS_XATTR`] as JSON.
+fn stats(StatsOptions { mountpoint }: StatsOptions) {
+    let raw = getxattr(&mountpoint, backupfs::STATS_XATTR)
+        .unwrap_or_else(|e| fail(format!("{}: {e}", mountpoint.display())));
+    let stats: serde_json::Value = serde_json::from_slice(&raw).unwrap_or_else(|e| fail(e));
+    let mut out = std::io::stdout().lock();
+    serde_json::to_writer_pretty(&mut out, &stats)
+        .map_err(std::io::Error::from)
+        .and_then(|()| writeln!(out))
+        .unwrap_or_else(|e| fail(e));
+}
+
+fn getxattr(path: &Path, name: &[u8]) -> std::io::Result<Vec<u8>> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let name = CString::new(name)?;
+    loop {
+        // SAFETY: both strings are NUL-terminated, and a null buffer of
+        // length 0 only queries the size.
+        let len = unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), std::ptr::null_mut(), 0) };
+        if len < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let mut buf = vec![0u8; len as usize];
+        // SAFETY: `buf` is writable for `buf.len()` bytes.
+        let got = unsafe {
+            libc::getxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                buf.as_mut_ptr().cast(),
+                buf.len(),
+            )
+        };
+        if got >= 0 {
+            buf.truncate(got as usize);
+            return Ok(buf);
+        }
+        // The value grew between the two calls; ask again.
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ERANGE) {
+            return Err(err);
+        }
+    }
+}
+
 fn change_password(
     ChangePasswordOptions {
         backup_opts,
@@ -214,3 +416,182 @@ fn change_password(
 ) {
     new_fs(backup_opts).change_password(&new_password).unwrap()
 }
+
+fn ls(
+    LsOptions {
+        backup_opts,
+        long,
+        path,
+    }: LsOptions,
+) {
+    let mut fs = open_readonly(backup_opts);
+    let reader = fs.reader();
+    let node = reader.resolve(&path).unwrap_or_else(|e| fail(e));
+    let entries = if node.is_dir() {
+        reader.read_dir(&node).unwrap_or_else(|e| fail(e))
+    } else {
+        vec![(path.file_name().unwrap_or_default().to_owned(), node)]
+    };
+    let mut out = std::io::stdout().lock();
+    for (name, node) in entries {
+        let res = if long {
+            let attr = node.attr();
+            let mtime = attr
+                .mtime
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            let target = node
+                .symlink_target()
+                .map(|t| format!(" -> {}", t.display()))
+                .unwrap_or_default();
+            writeln!(
+                out,
+                "{}{:04o} {:>6} {:>6} {:>12} {mtime:>11} {}{target}",
+                type_char(attr.kind),
+                attr.perm & 0o7777,
+                attr.uid,
+                attr.gid,
+                attr.size,
+                Path::new(&name).display(),
+            )
+        } else {
+            writeln!(out, "{}", Path::new(&name).display())
+        };
+        res.unwrap_or_else(|e| fail(e));
+    }
+}
+
+fn type_char(kind: FileType) -> char {
+    match kind {
+        FileType::Directory => 'd',
+        FileType::RegularFile => '-',
+        FileType::Symlink => 'l',
+        FileType::NamedPipe => 'p',
+        FileType::CharDevice => 'c',
+        FileType::BlockDevice => 'b',
+        FileType::Socket => 's',
+    }
+}
+
+fn cat(CatOptions { backup_opts, path }: CatOptions) {
+    let mut fs = open_readonly(backup_opts);
+    let reader = fs.reader();
+    let node = reader.resolve(&path).unwrap_or_else(|e| fail(e));
+    let mut file = reader.open(&node).unwrap_or_else(|e| fail(e));
+    std::io::copy(&mut file, &mut std::io::stdout().lock()).unwrap_or_else(|e| fail(e));
+}
+
+fn extract(
+    ExtractOptions {
+        backup_opts,
+        path,
+        dest,
+    }: ExtractOptions,
+) {
+    let mut fs = open_readonly(backup_opts);
+    fs.reader()
+        .extract(&path, &dest)
+        .unwrap_or_else(|e| fail(e))
+}
+
+fn export(
+    ExportOptions {
+        backup_opts,
+        tar: _,
+        output,
+        path,
+    }: ExportOptions,
+) {
+    let mut fs = open_readonly(backup_opts);
+    let reader = fs.reader();
+    let res = match output {
+        Some(output) => File::create(output)
+            .map_err(BkfsError::from)
+            .and_then(|f| reader.export_tar(&path, BufWriter::new(f)))
+            .and_then(|mut w| Ok(w.flush()?)),
+        None => reader
+            .export_tar(&path, BufWriter::new(std::io::stdout().lock()))
+            .and_then(|mut w| Ok(w.flush()?)),
+    };
+    res.unwrap_or_else(|e| fail(e))
+}
+
+fn snapshot(matches: &clap::ArgMatches) {
+    match matches.subcommand() {
+        Some(("create", sub_m)) => {
+            let SnapshotOptions { backup_opts, name } =
+                SnapshotOptions::from_arg_matches(sub_m).unwrap();
+            new_fs(backup_opts)
+                .create_snapshot(&name)
+                .unwrap_or_else(|e| fail(e));
+        }
+        Some(("list", sub_m)) => {
+            let snapshots = new_fs(BackupFSOptions::from_arg_matches(sub_m).unwrap())
+                .list_snapshots()
+                .unwrap_or_else(|e| fail(e));
+            for info in snapshots {
+                println!("{}\t{}\t{}", info.created_unix, info.inodes, info.name);
+            }
+        }
+        Some(("delete", sub_m)) => {
+            let SnapshotOptions { backup_opts, name } =
+                SnapshotOptions::from_arg_matches(sub_m).unwrap();
+            new_fs(backup_opts)
+                .delete_snapshot(&name)
+                .unwrap_or_else(|e| fail(e));
+        }
+        Some(("prune", sub_m)) => {
+            let SnapshotPruneOptions { backup_opts, keep } =
+                SnapshotPruneOptions::from_arg_matches(sub_m).unwrap();
+            for name in new_fs(backup_opts)
+                .prune_snapshots(keep)
+                .unwrap_or_else(|e| fail(e))
+            {
+                info!("pruned snapshot {name}");
+            }
+        }
+        _ => unreachable!("subcommand_required"),
+    }
+}
+
+fn key_slot(matches: &clap::ArgMatches) {
+    match matches.subcommand() {
+        Some(("add", sub_m)) => {
+            let KeySlotAddOptions {
+                backup_opts,
+                new_password,
+                new_keyfile,
+                generate,
+                new_master_key_file,
+            } = KeySlotAddOptions::from_arg_matches(sub_m).unwrap();
+            let secret = match (new_password, new_keyfile, new_master_key_file) {
+                (Some(password), _, _) => Ok(Secret::password(&password)),
+                (_, Some(path), _) if generate => keyslot::generate_keyfile(&path),
+                (_, Some(path), _) => Secret::keyfile(&path),
+                (_, _, Some(path)) => Secret::master_key(&path),
+                _ => unreachable!("secret group is required"),
+            }
+            .unwrap_or_else(|e| fail(e));
+            let id = new_fs(backup_opts)
+                .add_key_slot(&secret)
+                .unwrap_or_else(|e| fail(e));
+            println!("{id}");
+        }
+        Some(("list", sub_m)) => {
+            let slots =
+                open_readonly(BackupFSOptions::from_arg_matches(sub_m).unwrap()).key_slots();
+            for (id, kind) in slots {
+                println!("{id}\t{kind}");
+            }
+        }
+        Some(("remove", sub_m)) => {
+            let KeySlotRemoveOptions { backup_opts, id } =
+                KeySlotRemoveOptions::from_arg_matches(sub_m).unwrap();
+            new_fs(backup_opts)
+                .remove_key_slot(id)
+                .unwrap_or_else(|e| fail(e));
+        }
+        _ => unreachable!("subcommand_required"),
+    }
+}
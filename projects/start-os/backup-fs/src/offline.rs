@@ -0,0 +1,302 @@
+//! Read-only access to a backup without a FUSE mount.
+//!
+//! [`Reader`] walks the inode tree straight through the [`Controller`] —
+//! directory listings come from [`DirectoryContents::snapshot`] and file bytes
+//! from [`Contents`] — so a backup can be listed, read, extracted or exported
+//! as a tarball where FUSE (or root, or a mountpoint) is unavailable, e.g.
+//! inside containers and CI. Nothing here writes to the backing store.
+
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File, Permissions};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+
+use fuser::{FileAttr, FileType};
+use log::warn;
+
+use crate::blockstore::CHUNK_SIZE;
+use crate::contents::Contents;
+use crate::ctrl::Controller;
+use crate::error::{BkfsResult, BkfsResultExt};
+use crate::inode::{FileData, Inode, InodeAttributes};
+use crate::FUSE_ROOT_ID;
+
+/// One inode of the backup, as seen by an offline reader.
+#[derive(Clone)]
+pub struct Node {
+    inode: InodeAttributes,
+}
+
+impl Node {
+    /// Attributes as they would be reported through the mount.
+    pub fn attr(&self) -> FileAttr {
+        (&self.inode).into()
+    }
+
+    pub fn kind(&self) -> FileType {
+        (&self.inode.attrs.contents).into()
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.inode.attrs.contents.is_dir()
+    }
+
+    /// Target of a symlink, `None` for any other file type.
+    pub fn symlink_target(&self) -> Option<&Path> {
+        match &self.inode.attrs.contents {
+            FileData::Symlink(target) => Some(target),
+            _ => None,
+        }
+    }
+
+    /// Extended attributes (including POSIX ACLs) stored on the inode.
+    pub fn xattrs(&self) -> impl Iterator<Item = (&[u8], &[u8])> {
+        self.inode
+            .attrs
+            .xattrs
+            .iter()
+            .map(|(k, v)| (k.as_slice(), v.as_slice()))
+    }
+}
+
+/// Sequential reader over a regular file's content.
+pub struct FileReader {
+    contents: Contents,
+    pos: u64,
+}
+
+impl Read for FileReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.contents.inode.attrs.size.saturating_sub(self.pos);
+        let n = (buf.len() as u64).min(remaining).min(CHUNK_SIZE) as usize;
+        if n == 0 {
+            return Ok(0);
+        }
+        self.contents.read_exact_at(&mut buf[..n], self.pos)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+/// Read-only view of an opened backup. Borrowed from
+/// [`crate::BackupFS::reader`], so the data-dir lock is held for its lifetime.
+pub struct Reader<'a> {
+    ctrl: &'a Controller,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(ctrl: &'a Controller) -> Self {
+        Self { ctrl }
+    }
+
+    fn load(&self, inode: Inode) -> BkfsResult<Node> {
+        Ok(Node {
+            inode: self.ctrl.load(inode)?,
+        })
+    }
+
+    pub fn root(&self) -> BkfsResult<Node> {
+        self.load(Inode(FUSE_ROOT_ID))
+    }
+
+    /// Resolve a path inside the backup. Absolute and relative paths are both
+    /// taken from the backup root; symlinks are not followed.
+    pub fn resolve(&self, path: &Path) -> BkfsResult<Node> {
+        let mut node = self.root()?;
+        for component in path.components() {
+            let name = match component {
+                Component::RootDir | Component::CurDir => continue,
+                Component::ParentDir => OsStr::new(".."),
+                Component::Normal(name) => name,
+                Component::Prefix(_) => return BkfsResult::errno_notrace(libc::EINVAL),
+            };
+            let child = node.inode.lookup(self.ctrl, name)?;
+            node = self.load(child)?;
+        }
+        Ok(node)
+    }
+
+    /// List a directory, sorted by name.
+    pub fn read_dir(&self, dir: &Node) -> BkfsResult<Vec<(OsString, Node)>> {
+        let FileData::Directory(contents) = &dir.inode.attrs.contents else {
+            return BkfsResult::errno_notrace(libc::ENOTDIR);
+        };
+        contents
+            .snapshot(self.ctrl, dir.inode.inode)?
+            .iter()
+            .map(|(name, entry)| Ok((name.clone(), self.load(entry.inode)?)))
+            .collect()
+    }
+
+    /// Open a regular file for sequential reading.
+    pub fn open(&self, file: &Node) -> BkfsResult<FileReader> {
+        if !file.inode.attrs.contents.is_file() {
+            return BkfsResult::errno_notrace(if file.is_dir() {
+                libc::EISDIR
+            } else {
+                libc::EINVAL
+            });
+        }
+        Ok(FileReader {
+            contents: Contents::open_with_attrs(self.ctrl.clone(), file.inode.clone(), false)?,
+            pos: 0,
+        })
+    }
+
+    /// Depth-first, name-ordered pre-order walk of `node` and everything
+    /// below it. `f` receives each node's path relative to `prefix`.
+    pub fn walk(
+        &self,
+        node: &Node,
+        prefix: &Path,
+        f: &mut impl FnMut(&Path, &Node) -> BkfsResult<()>,
+    ) -> BkfsResult<()> {
+        f(prefix, node)?;
+        if node.is_dir() {
+            for (name, child) in self.read_dir(node)? {
+                self.walk(&child, &prefix.join(name), f)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy `path` out of the backup to `dest` on the local filesystem. A
+    /// file lands at `dest` (or inside it, if `dest` is an existing
+    /// directory); a directory is recreated at `dest` with its whole subtree.
+    /// Ownership is restored only when running as root; sockets and device
+    /// nodes are skipped.
+    pub fn extract(&self, path: &Path, dest: &Path) -> BkfsResult<()> {
+        let node = self.resolve(path)?;
+        let dest = if !node.is_dir() && dest.is_dir() {
+            match path.file_name() {
+                Some(name) => dest.join(name),
+                None => dest.to_owned(),
+            }
+        } else {
+            dest.to_owned()
+        };
+        let restore_owner = unsafe { libc::geteuid() } == 0;
+        let mut dirs = Vec::new();
+        let mut links = HashMap::<Inode, PathBuf>::new();
+        self.walk(&node, &dest, &mut |target, node| {
+            let attr = node.attr();
+            match attr.kind {
+                FileType::Directory => {
+                    fs::create_dir_all(target)?;
+                    // Permissions are applied after the subtree is written, so
+                    // a read-only directory doesn't block its own children.
+                    dirs.push((target.to_owned(), attr));
+                    return Ok(());
+                }
+                FileType::RegularFile => {
+                    if attr.nlink > 1 {
+                        if let Some(first) = links.get(&node.inode.inode) {
+                            fs::hard_link(first, target)?;
+                            return Ok(());
+                        }
+                        links.insert(node.inode.inode, target.to_owned());
+                    }
+                    let mut file = File::create(target)?;
+                    io::copy(&mut self.open(node)?, &mut file)?;
+                    file.set_permissions(Permissions::from_mode(attr.perm as u32 & 0o7777))?;
+                    file.set_modified(attr.mtime)?;
+                }
+                FileType::Symlink => {
+                    std::os::unix::fs::symlink(node.symlink_target().unwrap(), target)?;
+                }
+                FileType::NamedPipe => {
+                    use std::os::unix::ffi::OsStrExt;
+                    let c = std::ffi::CString::new(target.as_os_str().as_bytes())
+                        .map_err(io::Error::other)?;
+                    if unsafe { libc::mkfifo(c.as_ptr(), attr.perm as libc::mode_t & 0o7777) } != 0 {
+                        return Err(io::Error::last_os_error().into());
+                    }
+                }
+                kind => {
+                    warn!("skipping {kind:?} {}", target.display());
+                    return Ok(());
+                }
+            }
+            if restore_owner {
+                std::os::unix::fs::lchown(target, Some(attr.uid), Some(attr.gid))?;
+            }
+            Ok(())
+        })?;
+        for (dir, attr) in dirs.into_iter().rev() {
+            fs::set_permissions(&dir, Permissions::from_mode(attr.perm as u32 & 0o7777))?;
+            File::open(&dir)?.set_modified(attr.mtime)?;
+            if restore_owner {
+                std::os::unix::fs::lchown(&dir, Some(attr.uid), Some(attr.gid))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write `path` and its subtree to `out` as a tar archive. Entry names are
+    /// relative to the parent of `path` (so exporting `/srv/data` yields
+    /// `data/...`, and exporting `/` yields the top-level names directly).
+    /// Hard links are preserved; sockets are skipped.
+    pub fn export_tar<W: Write>(&self, path: &Path, out: W) -> BkfsResult<W> {
+        let node = self.resolve(path)?;
+        let prefix = path
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .next_back()
+            .map(|c| PathBuf::from(c.as_os_str()))
+            .unwrap_or_default();
+        let mut builder = tar::Builder::new(out);
+        let mut links = HashMap::<Inode, PathBuf>::new();
+        self.walk(&node, &prefix, &mut |name, node| {
+            if name.as_os_str().is_empty() {
+                // The backup root itself has no name in the archive.
+                return Ok(());
+            }
+            let attr = node.attr();
+            let mut header = tar::Header::new_gnu();
+            header.set_mode(attr.perm as u32 & 0o7777);
+            header.set_uid(attr.uid as u64);
+            header.set_gid(attr.gid as u64);
+            header.set_mtime(node.inode.attrs.mtime.0.max(0) as u64);
+            header.set_size(0);
+            match attr.kind {
+                FileType::Directory => {
+                    header.set_entry_type(tar::EntryType::Directory);
+                    builder.append_data(&mut header, name, io::empty())?;
+                }
+                FileType::RegularFile => {
+                    if attr.nlink > 1 {
+                        if let Some(first) = links.get(&node.inode.inode) {
+                            header.set_entry_type(tar::EntryType::Link);
+                            builder.append_link(&mut header, name, first)?;
+                            return Ok(());
+                        }
+                        links.insert(node.inode.inode, name.to_owned());
+                    }
+                    header.set_entry_type(tar::EntryType::Regular);
+                    header.set_size(attr.size);
+                    builder.append_data(&mut header, name, self.open(node)?)?;
+                }
+                FileType::Symlink => {
+                    header.set_entry_type(tar::EntryType::Symlink);
+                    builder.append_link(&mut header, name, node.symlink_target().unwrap())?;
+                }
+                FileType::NamedPipe | FileType::CharDevice | FileType::BlockDevice => {
+                    header.set_entry_type(match attr.kind {
+                        FileType::NamedPipe => tar::EntryType::Fifo,
+                        FileType::CharDevice => tar::EntryType::Char,
+                        _ => tar::EntryType::Block,
+                    });
+                    let rdev = attr.rdev as libc::dev_t;
+                    header.set_device_major(libc::major(rdev))?;
+                    header.set_device_minor(libc::minor(rdev))?;
+                    builder.append_data(&mut header, name, io::empty())?;
+                }
+                FileType::Socket => warn!("skipping socket {}", name.display()),
+            }
+            Ok(())
+        })?;
+        Ok(builder.into_inner()?)
+    }
+}
@@ -0,0 +1,186 @@
+//! Scrubbing: proactive verification and repair of every sealed object.
+//!
+//! [`vault::open`] reconstructs up to `parity` damaged shards on every read,
+//! but only in memory — the rot stays on the medium and keeps accumulating
+//! until a blob has more bad shards than parity and is lost. A scrub reads
+//! every live sealed object straight from the medium (bypassing cached copies,
+//! which would hide rot), verifies it end to end, and writes back any blob
+//! that needed repair, restoring its full parity:
+//!
+//! * both superblock replicas — the body under the master key, and each key
+//!   slot's wrapped key by its shard CRCs alone;
+//! * every live frame of the segment log — inode records, packed extents and
+//!   chunk counts (dead frames are garbage awaiting compaction, and skipped);
+//! * every object under `contents/`, `chunks/`, `dirents/`, `snapshots/` and
+//!   `dicts/`.
+//!
+//! **Repairs are in place.** [`vault::scrub`] reproduces a blob's original
+//! bytes exactly, so a repair rewrites the same file (or log frame) with the
+//! same bytes minus the rot: a torn repair can't leave an object worse than it
+//! was, and rsync/rclone see only the healed region change. Writers only ever
+//! *replace* objects, never modify them, so writing through the handle the
+//! blob was read from is safe on a mounted filesystem — if the object was
+//! replaced meanwhile, the repair lands on the orphaned old copy, harmlessly
+//! (or, on an object store, is refused and the object skipped). A block
+//! hard-linked into a snapshot is healed for every link at once.
+//!
+//! A store opened read-only is only verified. The result is a [`ScrubReport`],
+//! which serializes to JSON for the `scrub` command and the online trigger
+//! ([`SCRUB_XATTR`]).
+
+use std::io;
+
+use log::{error, info, warn};
+use serde::Serialize;
+
+use crate::ctrl::Controller;
+use crate::error::BkfsResult;
+use crate::storage::{Access, Storage};
+use crate::{seglog, superblock, vault};
+
+/// Xattr on the mount root driving an online scrub: setting it (to any value)
+/// starts a scrub in the background, and reading it returns the
+/// [`ScrubStatus`] as JSON.
+pub const SCRUB_XATTR: &[u8] = b"trusted.backupfs.scrub";
+
+/// Directories of whole-object sealed blobs, scrubbed recursively.
+const FILE_STORES: [&str; 5] = ["contents", "chunks", "dirents", "snapshots", "dicts"];
+
+/// What scrubbing one object found.
+pub(crate) enum Outcome {
+    /// The object was superseded or removed while the scrub ran.
+    Skipped,
+    Clean,
+    /// Damage within the ECC budget; rewritten unless the scrub is read-only.
+    Repaired { bad_shards: usize },
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ScrubReport {
+    /// False when the store was opened read-only, so damage was only found,
+    /// not rewritten.
+    pub repair: bool,
+    /// Objects verified, including those repaired or found unrecoverable.
+    pub checked: u64,
+    pub repaired: Vec<RepairedObject>,
+    pub unrecoverable: Vec<UnrecoverableObject>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RepairedObject {
+    /// Object name; a log frame is `<segment>@<offset>`.
+    pub object: String,
+    /// Shards reconstructed (0 when only a log frame's header was damaged).
+    pub bad_shards: usize,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct UnrecoverableObject {
+    pub object: String,
+    pub error: String,
+}
+
+impl ScrubReport {
+    fn record(&mut self, object: String, res: BkfsResult<Outcome>) {
+        match res {
+            Ok(Outcome::Skipped) => {}
+            Ok(Outcome::Clean) => self.checked += 1,
+            Ok(Outcome::Repaired { bad_shards }) => {
+                self.checked += 1;
+                if self.repair {
+                    warn!("{object}: repaired {bad_shards} bad shard(s)");
+                } else {
+                    warn!("{object}: {bad_shards} bad shard(s), not repaired (read-only)");
+                }
+                self.repaired.push(RepairedObject { object, bad_shards });
+            }
+            Err(e) => {
+                self.checked += 1;
+                error!("{object}: unrecoverable: {e}");
+                self.unrecoverable.push(UnrecoverableObject {
+                    object,
+                    error: e.to_string(),
+                });
+            }
+        }
+    }
+}
+
+/// Online scrub state, as read through [`SCRUB_XATTR`].
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ScrubStatus {
+    pub running: bool,
+    /// The most recently completed scrub.
+    pub last: Option<ScrubReport>,
+}
+
+/// Scrub every sealed object of the store. Per-object failures — including
+/// I/O errors from a bad sector — are recorded in the report; only failing to
+/// enumerate a store aborts the scrub.
+pub fn run(ctrl: &Controller) -> BkfsResult<ScrubReport> {
+    let repair = !ctrl.config().readonly;
+    let storage = ctrl.storage();
+    let mut report = ScrubReport {
+        repair,
+        ..Default::default()
+    };
+
+    for name in superblock::replica_names() {
+        let res = scrub_object(storage, &name, repair, |raw| {
+            superblock::scrub_replica(raw, ctrl.key(), &ctrl.config().password)
+        });
+        report.record(name, res);
+    }
+
+    for (space, key, loc) in ctrl.live_frames() {
+        let res = ctrl.scrub_frame(space, key, loc, repair);
+        let segment = seglog::segment_name(loc.segment);
+        report.record(format!("{segment}@{}", loc.offset), res);
+    }
+
+    for store in FILE_STORES {
+        for (name, _) in storage.list(store)? {
+            let res = scrub_object(storage, &name, repair, |raw| vault::scrub(raw, ctrl.key()));
+            report.record(name, res);
+        }
+    }
+
+    info!(
+        "scrub checked {} objects: {} repaired, {} unrecoverable",
+        report.checked,
+        report.repaired.len(),
+        report.unrecoverable.len()
+    );
+    Ok(report)
+}
+
+/// Verify one whole-object blob with `check`, writing its healed bytes back
+/// through the same handle when it needed repair. An object removed or
+/// replaced under the scrub is skipped.
+fn scrub_object(
+    storage: &dyn Storage,
+    name: &str,
+    repair: bool,
+    check: impl FnOnce(&[u8]) -> BkfsResult<vault::Scrubbed>,
+) -> BkfsResult<Outcome> {
+    let access = if repair { Access::Write } else { Access::Read };
+    let file = match storage.open(name, access) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Outcome::Skipped),
+        Err(e) => return Err(e.into()),
+    };
+    let raw = file.read_uncached(0, file.size()? as usize)?;
+    let vault::Scrubbed { bad_shards, healed } = check(&raw)?;
+    if bad_shards == 0 {
+        return Ok(Outcome::Clean);
+    }
+    if repair {
+        file.write_at(&healed, 0)?;
+        match file.sync() {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Outcome::Skipped),
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(Outcome::Repaired { bad_shards })
+}
@@ -49,19 +49,18 @@
 //! and is skipped via forward-resync.
 
 use std::collections::HashMap;
-use std::fs::{File, OpenOptions};
-use std::io::Read;
-use std::os::fd::AsRawFd;
-use std::os::unix::fs::FileExt;
-use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use chacha20::Key;
 use serde::{Deserialize, Serialize};
 
+use crate::chunkstore::ChunkHash;
 use crate::error::{BkfsError, BkfsResult};
 use crate::inode::{Attributes, Inode};
+use crate::scrub::Outcome;
 use crate::serde::{data_config, decode, encode};
-use crate::vault::{self, EccParams};
+use crate::storage::{Access, ObjectFile, Storage};
+use crate::vault::{self, EccParams, Scrubbed};
 
 const MAGIC: [u8; 4] = *b"BKL1";
 const HEADER_LEN: usize = 24; // magic(4) + crc(4) + seq(8) + payload_len(4) + reserved(4)
@@ -112,19 +111,29 @@ pub enum Record {
     ContentTombstone {
         id: u64,
     },
+    /// Reference count of a deduplicated chunk (format v2, see
+    /// `chunkstore`). Latest wins; `refs == 0` drops the chunk from the
+    /// index like a tombstone.
+    ChunkRefs {
+        hash: ChunkHash,
+        refs: u64,
+    },
 }
 
 /// Which index a record belongs to. Inode numbers and content ids occupy
-/// independent key spaces (a file's content id equals its inode number).
+/// independent key spaces (a file's content id equals its inode number);
+/// chunk reference counts are keyed by the full chunk hash instead.
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Space {
     Inode,
     Content,
+    Chunk(ChunkHash),
 }
 
 /// A record already serialized + vault-sealed, ready to append. Produced
 /// outside the log lock so the global append section never serializes crypto.
 pub struct SealedRecord {
+    /// Inode number / content id; for [`Space::Chunk`], the new count.
     key: u64,
     bytes: Vec<u8>,
     space: Space,
@@ -184,6 +193,23 @@ pub fn seal_content(key: &Key, ecc: EccParams, id: u64, bytes: &[u8]) -> BkfsRes
     })
 }
 
+/// Seal a chunk reference-count record without touching the log.
+pub fn seal_chunk_refs(
+    key: &Key,
+    ecc: EccParams,
+    hash: &ChunkHash,
+    refs: u64,
+) -> BkfsResult<SealedRecord> {
+    let plain = encode(&Record::ChunkRefs { hash: *hash, refs }, data_config())?;
+    let bytes = vault::seal(&plain, key, ecc);
+    Ok(SealedRecord {
+        key: refs,
+        bytes,
+        space: Space::Chunk(*hash),
+        tombstone: refs == 0,
+    })
+}
+
 /// Seal a content tombstone record without touching the log.
 pub fn seal_content_tombstone(key: &Key, ecc: EccParams, id: u64) -> BkfsResult<SealedRecord> {
     let plain = encode(&Record::ContentTombstone { id }, data_config())?;
@@ -246,10 +272,10 @@ fn parse_header(buf: &[u8]) -> Option<(u64, usize)> {
 }
 
 pub struct SegmentLog {
-    dir: PathBuf,
+    storage: Arc<dyn Storage>,
     key: Key,
     /// Open append handle for the active (highest-id) segment.
-    active: File,
+    active: Box<dyn ObjectFile>,
     active_id: u64,
     active_offset: u64,
     next_seq: u64,
@@ -257,6 +283,9 @@ pub struct SegmentLog {
     index: HashMap<u64, Location>,
     /// content id → latest content-record location.
     content: HashMap<u64, Location>,
+    /// chunk hash → (reference count, latest count-record location). The
+    /// count is kept in RAM so adjusting it never reads the log.
+    chunks: HashMap<ChunkHash, (u64, Location)>,
     seg_meta: HashMap<u64, SegMeta>,
     /// Highest inode number ever observed in any frame (incl. tombstoned),
     /// so a recovered allocator never re-hands a number that was used.
@@ -265,25 +294,22 @@ pub struct SegmentLog {
     /// at open (from `segment_size()`) so it can't shift under us — and so a
     /// test can pin it without racing the process-global env cache.
     segment_size: u64,
-    /// Held write handle for the index checkpoint, opened at mount. The
-    /// checkpoint is written from `Filesystem::destroy`, which runs *after*
-    /// start-core's lazy `umount -l` has detached the backing store from the
-    /// namespace — so a fresh path open (`File::create`) would resolve to the
-    /// empty mountpoint and fail. Writing through this held fd (as the active
-    /// segment and the unmount `syncfs` do) reaches the real backing store.
-    checkpoint_fd: File,
 }
 
-fn segment_path(dir: &std::path::Path, id: u64) -> PathBuf {
-    dir.join(format!("{id:016x}.seg"))
+/// Directory of the segment files in the store.
+const SEGMENTS_DIR: &str = "segments";
+
+/// Object name of segment `id`.
+pub(crate) fn segment_name(id: u64) -> String {
+    format!("{SEGMENTS_DIR}/{id:016x}.seg")
 }
 
 /// Name of the sealed index-checkpoint sidecar in the segments dir. Not a
 /// `.seg`, so the replay scan and compaction ignore it.
-const INDEX_CACHE_NAME: &str = "index.cache";
+const INDEX_CACHE: &str = "segments/index.cache";
 
 /// Bumped if [`Checkpoint`]'s layout changes; a mismatch just forces a replay.
-const CHECKPOINT_VERSION: u32 = 1;
+const CHECKPOINT_VERSION: u32 = 2;
 
 /// A sealed snapshot of the in-RAM index so a mount can skip replaying and
 /// re-decrypting every segment. Pure cache: adopted only when `segments`
@@ -296,23 +322,23 @@ struct Checkpoint {
     max_inode: u64,
     index: HashMap<u64, Location>,
     content: HashMap<u64, Location>,
+    chunks: HashMap<ChunkHash, (u64, Location)>,
     seg_meta: HashMap<u64, SegMeta>,
     /// `(id, on-disk size)` for every segment the index reflects, sorted. Any
     /// append or compaction changes a size or the set and invalidates it.
     segments: Vec<(u64, u64)>,
 }
 
-/// `(id, size)` for every `*.seg` in `dir`, sorted by id.
-fn list_segments(dir: &Path) -> BkfsResult<Vec<(u64, u64)>> {
+/// `(id, size)` for every `*.seg` in the segments dir, sorted by id.
+fn list_segments(storage: &dyn Storage) -> BkfsResult<Vec<(u64, u64)>> {
     let mut segs = Vec::new();
-    for entry in std::fs::read_dir(dir)? {
-        let entry = entry?;
-        let name = entry.file_name();
-        let name = name.to_string_lossy();
-        if let Some(hex) = name.strip_suffix(".seg") {
-            if let Ok(id) = u64::from_str_radix(hex, 16) {
-                segs.push((id, entry.metadata()?.len()));
-            }
+    for (name, size) in storage.list(SEGMENTS_DIR)? {
+        let hex = name
+            .strip_prefix(SEGMENTS_DIR)
+            .and_then(|n| n.strip_prefix('/'))
+            .and_then(|n| n.strip_suffix(".seg"));
+        if let Some(id) = hex.and_then(|hex| u64::from_str_radix(hex, 16).ok()) {
+            segs.push((id, size));
         }
     }
     segs.sort_unstable();
@@ -320,50 +346,45 @@ fn list_segments(dir: &Path) -> BkfsResult<Vec<(u64, u64)>> {
 }
 
 impl SegmentLog {
-    /// Open (creating if absent) the log under `dir` with the default segment
-    /// size, replaying existing segments to rebuild the in-RAM index. Test
-    /// convenience; production uses [`SegmentLog::open_sized`] with the
+    /// Open (creating if absent) the log in `storage` with the default
+    /// segment size, replaying existing segments to rebuild the in-RAM index.
+    /// Test convenience; production uses [`SegmentLog::open_sized`] with the
     /// superblock-pinned size.
     #[cfg(test)]
-    pub fn open(dir: PathBuf, key: Key) -> BkfsResult<Self> {
-        Self::open_sized(dir, key, segment_size())
+    pub fn open(storage: Arc<dyn Storage>, key: Key) -> BkfsResult<Self> {
+        Self::open_sized(storage, key, segment_size())
     }
 
     /// As [`open`], with an explicit segment size (from the superblock at
     /// mount; pinned per-test elsewhere) instead of the env-cached default.
-    pub(crate) fn open_sized(dir: PathBuf, key: Key, segment_size: u64) -> BkfsResult<Self> {
-        std::fs::create_dir_all(&dir)?;
-        if let Some(log) = Self::try_open_from_checkpoint(&dir, &key, segment_size) {
+    pub(crate) fn open_sized(
+        storage: Arc<dyn Storage>,
+        key: Key,
+        segment_size: u64,
+    ) -> BkfsResult<Self> {
+        if let Some(log) = Self::try_open_from_checkpoint(&storage, &key, segment_size) {
             return Ok(log);
         }
         let replay_start = std::time::Instant::now();
-        let mut segment_ids = Vec::new();
-        for entry in std::fs::read_dir(&dir)? {
-            let entry = entry?;
-            let name = entry.file_name();
-            let name = name.to_string_lossy();
-            if let Some(hex) = name.strip_suffix(".seg") {
-                if let Ok(id) = u64::from_str_radix(hex, 16) {
-                    segment_ids.push(id);
-                }
-            }
-        }
-        segment_ids.sort_unstable();
+        let segment_ids: Vec<u64> = list_segments(&*storage)?
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
 
         let mut index: HashMap<u64, Location> = HashMap::new();
         let mut content: HashMap<u64, Location> = HashMap::new();
+        let mut chunks: HashMap<ChunkHash, (u64, Location)> = HashMap::new();
         // Winning seq per key per space, so a later-seq record wins even if
         // physically earlier (after a compaction relocation).
         let mut winning_inode: HashMap<u64, u64> = HashMap::new();
         let mut winning_content: HashMap<u64, u64> = HashMap::new();
+        let mut winning_chunk: HashMap<ChunkHash, u64> = HashMap::new();
         let mut seg_meta: HashMap<u64, SegMeta> = HashMap::new();
         let mut next_seq = 1u64;
         let mut max_inode = 0u64;
 
         for &id in &segment_ids {
-            let mut file = File::open(segment_path(&dir, id))?;
-            let mut bytes = Vec::new();
-            file.read_to_end(&mut bytes)?;
+            let bytes = storage.get(&segment_name(id))?.unwrap_or_default();
             let mut pos = 0usize;
             let mut live = 0u64;
             while pos + HEADER_LEN <= bytes.len() {
@@ -384,14 +405,13 @@ impl SegmentLog {
                 match vault::open(payload, &key).and_then(|p| decode::<Record>(&p, data_config())) {
                     Ok(record) => {
                         next_seq = next_seq.max(seq + 1);
-                        let key = record.key();
                         let loc = Location {
                             segment: id,
                             offset: pos as u64,
                             len: frame_len as u32,
                         };
                         match record {
-                            Record::Inode { .. } | Record::Tombstone { .. } => {
+                            Record::Inode { inode: key, .. } | Record::Tombstone { inode: key } => {
                                 max_inode = max_inode.max(key);
                                 if winning_inode.get(&key).map_or(true, |&w| seq >= w) {
                                     winning_inode.insert(key, seq);
@@ -402,7 +422,7 @@ impl SegmentLog {
                                     }
                                 }
                             }
-                            Record::Content { .. } | Record::ContentTombstone { .. } => {
+                            Record::Content { id: key, .. } | Record::ContentTombstone { id: key } => {
                                 if winning_content.get(&key).map_or(true, |&w| seq >= w) {
                                     winning_content.insert(key, seq);
                                     if matches!(record, Record::Content { .. }) {
@@ -412,6 +432,16 @@ impl SegmentLog {
                                     }
                                 }
                             }
+                            Record::ChunkRefs { hash, refs } => {
+                                if winning_chunk.get(&hash).map_or(true, |&w| seq >= w) {
+                                    winning_chunk.insert(hash, seq);
+                                    if refs > 0 {
+                                        chunks.insert(hash, (refs, loc));
+                                    } else {
+                                        chunks.remove(&hash);
+                                    }
+                                }
+                            }
                         }
                         live += frame_len;
                     }
@@ -439,17 +469,12 @@ impl SegmentLog {
         // Recompute live bytes precisely: a frame is live iff it is the
         // current index Location for its inode. The pass above counted every
         // parseable frame; correct it by subtracting superseded frames.
-        recompute_live(&index, &content, &mut seg_meta);
+        recompute_live(&index, &content, &chunks, &mut seg_meta);
 
         // Open (or create) the active segment = the highest id, appended to.
         let active_id = segment_ids.last().copied().unwrap_or(0);
-        let active = OpenOptions::new()
-            .create(true)
-            .read(true)
-            .append(false)
-            .write(true)
-            .open(segment_path(&dir, active_id))?;
-        let active_offset = active.metadata()?.len();
+        let active = storage.open(&segment_name(active_id), Access::Create)?;
+        let active_offset = active.size()?;
 
         log::info!(
             "replayed {} segment(s), {} bytes, in {:?}",
@@ -457,13 +482,8 @@ impl SegmentLog {
             seg_meta.values().map(|m| m.total).sum::<u64>(),
             replay_start.elapsed()
         );
-        let checkpoint_fd = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(dir.join(INDEX_CACHE_NAME))?;
         Ok(Self {
-            dir,
+            storage,
             key,
             active,
             active_id,
@@ -471,10 +491,10 @@ impl SegmentLog {
             next_seq,
             index,
             content,
+            chunks,
             seg_meta,
             max_inode,
             segment_size,
-            checkpoint_fd,
         })
     }
 
@@ -483,33 +503,28 @@ impl SegmentLog {
     /// wrong key, version/layout change, or a segment set that no longer matches
     /// the fingerprint — returns `None` so the caller full-replays. Correctness
     /// never depends on the checkpoint.
-    fn try_open_from_checkpoint(dir: &Path, key: &Key, segment_size: u64) -> Option<Self> {
-        let sealed = std::fs::read(dir.join(INDEX_CACHE_NAME)).ok()?;
+    fn try_open_from_checkpoint(
+        storage: &Arc<dyn Storage>,
+        key: &Key,
+        segment_size: u64,
+    ) -> Option<Self> {
+        let sealed = storage.get(INDEX_CACHE).ok()??;
         let cp: Checkpoint = decode(&vault::open(&sealed, key).ok()?, data_config()).ok()?;
-        if cp.version != CHECKPOINT_VERSION || list_segments(dir).ok()? != cp.segments {
+        if cp.version != CHECKPOINT_VERSION || list_segments(&**storage).ok()? != cp.segments {
             log::info!("index checkpoint present but stale — replaying");
             return None;
         }
         let active_id = cp.segments.last().map(|&(id, _)| id).unwrap_or(0);
-        let active = OpenOptions::new()
-            .create(true)
-            .read(true)
-            .write(true)
-            .open(segment_path(dir, active_id))
+        let active = storage
+            .open(&segment_name(active_id), Access::Create)
             .ok()?;
-        let active_offset = active.metadata().ok()?.len();
+        let active_offset = active.size().ok()?;
         log::info!(
             "loaded index from checkpoint ({} segments) — skipping replay",
             cp.segments.len()
         );
-        let checkpoint_fd = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(dir.join(INDEX_CACHE_NAME))
-            .ok()?;
         Some(Self {
-            dir: dir.to_path_buf(),
+            storage: storage.clone(),
             key: *key,
             active,
             active_id,
@@ -517,10 +532,10 @@ impl SegmentLog {
             next_seq: cp.next_seq,
             index: cp.index,
             content: cp.content,
+            chunks: cp.chunks,
             seg_meta: cp.seg_meta,
             max_inode: cp.max_inode,
             segment_size,
-            checkpoint_fd,
         })
     }
 
@@ -529,14 +544,12 @@ impl SegmentLog {
     ///
     /// This runs from `Filesystem::destroy`, which fires *after* start-core's
     /// lazy `umount -l` has detached the backing store from the mount namespace.
-    /// So it must not do any fresh path lookup (they'd resolve to the now-empty
-    /// mountpoint and fail — the same reason the unmount `syncfs` and segment
-    /// appends use held fds): the fingerprint is taken from the in-RAM
-    /// `seg_meta` (whose `total` tracks each segment's on-disk size) instead of
-    /// re-reading the directory, and the bytes are written through the held
-    /// `checkpoint_fd`. Not fsynced or tmp+renamed — a torn, stale, or lost
-    /// checkpoint is safely ignored on load (the mount falls back to a full
-    /// replay), so it's a pure rebuildable cache.
+    /// The storage backend still reaches the store (see
+    /// [`crate::storage::LocalStorage`]), but the fingerprint is taken from the
+    /// in-RAM `seg_meta` (whose `total` tracks each segment's on-disk size)
+    /// rather than a fresh listing anyway. Not fsynced — a torn, stale, or
+    /// lost checkpoint is safely ignored on load (the mount falls back to a
+    /// full replay), so it's a pure rebuildable cache.
     pub fn save_checkpoint(&self) -> BkfsResult<()> {
         let mut segments: Vec<(u64, u64)> =
             self.seg_meta.iter().map(|(&id, m)| (id, m.total)).collect();
@@ -548,6 +561,7 @@ impl SegmentLog {
             max_inode: self.max_inode,
             index: self.index.clone(),
             content: self.content.clone(),
+            chunks: self.chunks.clone(),
             seg_meta: self.seg_meta.clone(),
             segments,
         };
@@ -556,8 +570,7 @@ impl SegmentLog {
             &self.key,
             EccParams::default(),
         );
-        self.checkpoint_fd.set_len(0)?;
-        self.checkpoint_fd.write_all_at(&sealed, 0)?;
+        self.storage.put(INDEX_CACHE, &sealed, false)?;
         log::info!("wrote index checkpoint ({n} segments)");
         Ok(())
     }
@@ -572,15 +585,7 @@ impl SegmentLog {
 
     /// Read and decode the record at `loc`.
     pub fn read_at(&self, loc: Location) -> BkfsResult<Record> {
-        let mut frame = vec![0u8; loc.len as usize];
-        let file;
-        let f: &File = if loc.segment == self.active_id {
-            &self.active
-        } else {
-            file = File::open(segment_path(&self.dir, loc.segment))?;
-            &file
-        };
-        f.read_exact_at(&mut frame, loc.offset)?;
+        let frame = self.read_raw_frame(loc)?;
         let (_, payload_len) = parse_header(&frame)
             .ok_or_else(|| BkfsError::wrap(std::io::Error::other("corrupt log frame header")))?;
         let payload = &frame[HEADER_LEN..HEADER_LEN + payload_len];
@@ -616,6 +621,16 @@ impl SegmentLog {
         self.content.len()
     }
 
+    /// Current reference count of a deduplicated chunk (0 if unknown).
+    pub fn chunk_refs(&self, hash: &ChunkHash) -> u64 {
+        self.chunks.get(hash).map_or(0, |&(refs, _)| refs)
+    }
+
+    /// Number of referenced chunks.
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
     /// Append a pre-sealed record (see [`seal_inode`]/[`seal_tombstone`]).
     /// Sealing is done by the caller *outside* the log lock so the global
     /// append critical section never serializes ChaCha20/Reed-Solomon work.
@@ -629,7 +644,7 @@ impl SegmentLog {
             self.roll()?;
         }
         let offset = self.active_offset;
-        self.active.write_all_at(&frame, offset)?;
+        self.active.write_at(&frame, offset)?;
         self.active_offset += frame.len() as u64;
         let loc = Location {
             segment: self.active_id,
@@ -641,27 +656,42 @@ impl SegmentLog {
         seg.total += frame.len() as u64;
         seg.live += frame.len() as u64;
 
-        let dst = match rec.space {
+        // Supersede any prior live frame for this key in its space.
+        let prev = match rec.space {
             Space::Inode => {
                 self.max_inode = self.max_inode.max(rec.key);
-                &mut self.index
+                if rec.tombstone {
+                    self.index.remove(&rec.key)
+                } else {
+                    self.index.insert(rec.key, loc)
+                }
+            }
+            Space::Content => {
+                if rec.tombstone {
+                    self.content.remove(&rec.key)
+                } else {
+                    self.content.insert(rec.key, loc)
+                }
+            }
+            Space::Chunk(hash) => {
+                if rec.tombstone {
+                    self.chunks.remove(&hash)
+                } else {
+                    self.chunks.insert(hash, (rec.key, loc))
+                }
+                .map(|(_, prev)| prev)
             }
-            Space::Content => &mut self.content,
         };
-        // Supersede any prior live frame for this key in its space.
-        if let Some(prev) = dst.get(&rec.key).copied() {
+        if let Some(prev) = prev {
             if let Some(m) = self.seg_meta.get_mut(&prev.segment) {
                 m.live = m.live.saturating_sub(prev.len as u64);
             }
         }
         if rec.tombstone {
             // The tombstone frame is itself dead weight once written.
-            dst.remove(&rec.key);
             if let Some(m) = self.seg_meta.get_mut(&loc.segment) {
                 m.live = m.live.saturating_sub(loc.len as u64);
             }
-        } else {
-            dst.insert(rec.key, loc);
         }
         Ok(())
     }
@@ -684,32 +714,20 @@ impl SegmentLog {
         // Make the segment we're leaving durable before starting a new one.
         self.sync()?;
         self.active_id += 1;
-        self.active = OpenOptions::new()
-            .create(true)
-            .read(true)
-            .write(true)
-            .open(segment_path(&self.dir, self.active_id))?;
+        self.active = self
+            .storage
+            .open(&segment_name(self.active_id), Access::Create)?;
         self.active_offset = 0;
         // Make the new segment's directory entry durable.
         self.fsync_dir()?;
         Ok(())
     }
 
-    /// Flush the active segment to stable storage and drop its now-clean
-    /// pages from the cache to keep metadata cache bounded.
+    /// Flush the active segment to stable storage (a local backend also
+    /// drops its now-clean pages from the cache to keep metadata cache
+    /// bounded).
     pub fn sync(&self) -> BkfsResult<()> {
-        self.active.sync_data()?;
-        // Best-effort: drop clean pages we just synced.
-        let fd = self.active.as_raw_fd();
-        // SAFETY: fd is a valid owned fd; DONTNEED on a synced range is advisory.
-        unsafe {
-            libc::posix_fadvise(
-                fd,
-                0,
-                self.active_offset as libc::off_t,
-                libc::POSIX_FADV_DONTNEED,
-            );
-        }
+        self.active.sync()?;
         Ok(())
     }
 
@@ -717,22 +735,22 @@ impl SegmentLog {
     /// file is itself durable (a lost segment dir-entry is catastrophic,
     /// unlike a per-inode file the heal could prune).
     fn fsync_dir(&self) -> BkfsResult<()> {
-        File::open(&self.dir)?.sync_all()?;
+        self.storage.sync_dir(SEGMENTS_DIR)?;
         Ok(())
     }
 
     /// Read a frame's raw on-disk bytes (header + sealed payload + padding).
     fn read_raw_frame(&self, loc: Location) -> BkfsResult<Vec<u8>> {
-        let mut frame = vec![0u8; loc.len as usize];
-        let file;
-        let f: &File = if loc.segment == self.active_id {
-            &self.active
+        if loc.segment == self.active_id {
+            let mut frame = vec![0u8; loc.len as usize];
+            self.active.read_at(&mut frame, loc.offset)?;
+            Ok(frame)
         } else {
-            file = File::open(segment_path(&self.dir, loc.segment))?;
-            &file
-        };
-        f.read_exact_at(&mut frame, loc.offset)?;
-        Ok(frame)
+            let name = segment_name(loc.segment);
+            Ok(self
+                .storage
+                .get_range(&name, loc.offset, loc.len as usize)?)
+        }
     }
 
     /// Append an existing frame VERBATIM (preserving its seq), repointing the
@@ -744,7 +762,7 @@ impl SegmentLog {
             self.roll()?;
         }
         let offset = self.active_offset;
-        self.active.write_all_at(frame, offset)?;
+        self.active.write_at(frame, offset)?;
         self.active_offset += frame.len() as u64;
         let loc = Location {
             segment: self.active_id,
@@ -755,9 +773,16 @@ impl SegmentLog {
         seg.total += frame.len() as u64;
         seg.live += frame.len() as u64;
         match space {
-            Space::Inode => self.index.insert(key, loc),
-            Space::Content => self.content.insert(key, loc),
-        };
+            Space::Inode => {
+                self.index.insert(key, loc);
+            }
+            Space::Content => {
+                self.content.insert(key, loc);
+            }
+            Space::Chunk(hash) => {
+                self.chunks.insert(hash, (key, loc));
+            }
+        }
         Ok(())
     }
 
@@ -797,17 +822,11 @@ impl SegmentLog {
 
     fn compact_segment(&mut self, seg_id: u64) -> BkfsResult<()> {
         // Live frames in this segment = index entries pointing into it.
-        let mut live: Vec<(Space, u64, Location)> = Vec::new();
-        for (&k, &l) in &self.index {
-            if l.segment == seg_id {
-                live.push((Space::Inode, k, l));
-            }
-        }
-        for (&k, &l) in &self.content {
-            if l.segment == seg_id {
-                live.push((Space::Content, k, l));
-            }
-        }
+        let live: Vec<_> = self
+            .live_frames()
+            .into_iter()
+            .filter(|(_, _, loc)| loc.segment == seg_id)
+            .collect();
         for (space, key, loc) in live {
             let frame = self.read_raw_frame(loc)?;
             self.append_verbatim(&frame, space, key)?;
@@ -815,12 +834,87 @@ impl SegmentLog {
         // Relocated copies durable before the source is deleted.
         self.sync()?;
         self.fsync_dir()?;
-        std::fs::remove_file(segment_path(&self.dir, seg_id))?;
+        self.storage.delete(&segment_name(seg_id))?;
         self.fsync_dir()?;
         self.seg_meta.remove(&seg_id);
         Ok(())
     }
 
+    /// `(space, key, location)` of every live frame, across all three indexes
+    /// (`key` as in [`SealedRecord`]).
+    pub fn live_frames(&self) -> Vec<(Space, u64, Location)> {
+        let inodes = self.index.iter().map(|(&k, &l)| (Space::Inode, k, l));
+        let content = self.content.iter().map(|(&k, &l)| (Space::Content, k, l));
+        let chunks = self
+            .chunks
+            .iter()
+            .map(|(&hash, &(refs, l))| (Space::Chunk(hash), refs, l));
+        inodes.chain(content).chain(chunks).collect()
+    }
+
+    /// Verify the live frame at `loc` against the medium (see `crate::scrub`)
+    /// and, if `repair`, rewrite it in place with its healed bytes. A frame
+    /// superseded or compacted away since it was listed is skipped. The
+    /// payload keeps its length, so the frame never moves; a damaged header
+    /// is rebuilt around a fresh `seq`, which still wins replay because the
+    /// frame is its key's latest record.
+    pub fn scrub_frame(
+        &mut self,
+        space: Space,
+        key: u64,
+        loc: Location,
+        repair: bool,
+    ) -> BkfsResult<Outcome> {
+        let current = match space {
+            Space::Inode => self.index.get(&key).copied(),
+            Space::Content => self.content.get(&key).copied(),
+            Space::Chunk(hash) => self.chunks.get(&hash).map(|&(_, l)| l),
+        };
+        if current != Some(loc) {
+            return Ok(Outcome::Skipped);
+        }
+        let file;
+        let f: &dyn ObjectFile = if loc.segment == self.active_id {
+            &*self.active
+        } else {
+            let access = if repair { Access::Write } else { Access::Read };
+            file = self.storage.open(&segment_name(loc.segment), access)?;
+            &*file
+        };
+        let frame = f.read_uncached(loc.offset, loc.len as usize)?;
+        if frame.len() < HEADER_LEN {
+            return Err(BkfsError::wrap(std::io::Error::other("truncated log frame")));
+        }
+        let seq = parse_header(&frame)
+            .filter(|&(_, payload_len)| HEADER_LEN + payload_len <= frame.len())
+            .map(|(seq, _)| seq);
+        // The payload's own vault header bounds it, so the zero padding (or a
+        // garbled payload_len) doesn't matter here.
+        let Scrubbed { bad_shards, healed } = vault::scrub(&frame[HEADER_LEN..], &self.key)?;
+        if bad_shards == 0 && seq.is_some() {
+            return Ok(Outcome::Clean);
+        }
+        if frame_size(healed.len()) != loc.len as u64 {
+            return Err(BkfsError::wrap(std::io::Error::other(
+                "log frame length disagrees with its payload",
+            )));
+        }
+        if repair {
+            let seq = seq.unwrap_or_else(|| {
+                self.next_seq += 1;
+                self.next_seq - 1
+            });
+            f.write_at(&encode_frame(seq, &healed), loc.offset)?;
+            f.sync()?;
+        }
+        Ok(Outcome::Repaired { bad_shards })
+    }
+
+    /// Inode numbers of every live (non-tombstoned) inode.
+    pub fn live_inodes(&self) -> Vec<Inode> {
+        self.index.keys().map(|&i| Inode(i)).collect()
+    }
+
     /// Number of live inodes (index entries).
     pub fn live_count(&self) -> usize {
         self.index.len()
@@ -832,33 +926,26 @@ impl SegmentLog {
     }
 }
 
-impl Record {
-    fn key(&self) -> u64 {
-        match self {
-            Record::Inode { inode, .. } | Record::Tombstone { inode } => *inode,
-            Record::Content { id, .. } | Record::ContentTombstone { id } => *id,
-        }
-    }
-}
-
 /// Index of the first occurrence of MAGIC in `buf`, if any.
 fn find_magic(buf: &[u8]) -> Option<usize> {
     buf.windows(MAGIC.len()).position(|w| w == MAGIC)
 }
 
 /// Reset every segment's live bytes to the sum of frame sizes still pointed
-/// at by an index — BOTH the inode index and the content index. (Omitting
-/// the content index scored every live packed extent as dead after a
-/// remount, so compaction needlessly rewrote fully-live content segments.)
+/// at by an index — the inode, content AND chunk indexes. (Omitting the
+/// content index scored every live packed extent as dead after a remount, so
+/// compaction needlessly rewrote fully-live content segments.)
 fn recompute_live(
     index: &HashMap<u64, Location>,
     content: &HashMap<u64, Location>,
+    chunks: &HashMap<ChunkHash, (u64, Location)>,
     seg_meta: &mut HashMap<u64, SegMeta>,
 ) {
     for m in seg_meta.values_mut() {
         m.live = 0;
     }
-    for loc in index.values().chain(content.values()) {
+    let chunk_locs = chunks.values().map(|(_, loc)| loc);
+    for loc in index.values().chain(content.values()).chain(chunk_locs) {
         if let Some(m) = seg_meta.get_mut(&loc.segment) {
             m.live += loc.len as u64;
         }
@@ -869,11 +956,16 @@ fn recompute_live(
 mod tests {
     use super::*;
     use crate::inode::{FileData, InodeAttributes};
+    use crate::storage::LocalStorage;
 
     fn key() -> Key {
         Key::from([5u8; 32])
     }
 
+    fn store(tmp: &tempdir::TempDir) -> Arc<dyn Storage> {
+        Arc::new(LocalStorage::new(tmp.path()).unwrap())
+    }
+
     fn attrs(size: u64) -> Attributes {
         let mut a =
             InodeAttributes::new(Inode(1), None, FileData::File(crate::inode::ContentId(1)));
@@ -884,9 +976,8 @@ mod tests {
     #[test]
     fn put_load_roundtrip_and_replay() {
         let tmp = tempdir::TempDir::new("seglog").unwrap();
-        let dir = tmp.path().join("segments");
         {
-            let mut log = SegmentLog::open(dir.clone(), key()).unwrap();
+            let mut log = SegmentLog::open(store(&tmp), key()).unwrap();
             for i in 1..=50u64 {
                 log.put(Inode(i), &attrs(i * 100)).unwrap();
             }
@@ -901,7 +992,7 @@ mod tests {
             assert_eq!(log.load(Inode(40)).unwrap().unwrap().size, 4000);
         }
         // Reopen → replay rebuilds the index, latest-seq-wins, tombstone gone.
-        let log = SegmentLog::open(dir, key()).unwrap();
+        let log = SegmentLog::open(store(&tmp), key()).unwrap();
         assert_eq!(log.index_len(), 49); // 50 created, 1 tombstoned
         assert!(log.load(Inode(25)).unwrap().is_none());
         assert_eq!(log.load(Inode(1)).unwrap().unwrap().size, 999);
@@ -916,17 +1007,18 @@ mod tests {
         // with any other test that touched the log first.
         const SEG: u64 = 8192;
         let tmp = tempdir::TempDir::new("seglog").unwrap();
-        let dir = tmp.path().join("segments");
         {
-            let mut log = SegmentLog::open_sized(dir.clone(), key(), SEG).unwrap();
+            let mut log = SegmentLog::open_sized(store(&tmp), key(), SEG).unwrap();
             for i in 1..=200u64 {
                 log.put(Inode(i), &attrs(i)).unwrap();
             }
             log.sync().unwrap();
         }
-        let n_segs = std::fs::read_dir(&dir).unwrap().count();
+        let n_segs = std::fs::read_dir(tmp.path().join(SEGMENTS_DIR))
+            .unwrap()
+            .count();
         assert!(n_segs > 1, "expected multiple segments, got {n_segs}");
-        let log = SegmentLog::open_sized(dir, key(), SEG).unwrap();
+        let log = SegmentLog::open_sized(store(&tmp), key(), SEG).unwrap();
         assert_eq!(log.index_len(), 200);
         for i in 1..=200u64 {
             assert_eq!(log.load(Inode(i)).unwrap().unwrap().size, i);
@@ -937,9 +1029,8 @@ mod tests {
     fn checkpoint_roundtrips_without_replay() {
         const SEG: u64 = 8192;
         let tmp = tempdir::TempDir::new("seglog").unwrap();
-        let dir = tmp.path().join("segments");
         {
-            let mut log = SegmentLog::open_sized(dir.clone(), key(), SEG).unwrap();
+            let mut log = SegmentLog::open_sized(store(&tmp), key(), SEG).unwrap();
             for i in 1..=100u64 {
                 log.put(Inode(i), &attrs(i * 10)).unwrap();
             }
@@ -950,8 +1041,8 @@ mod tests {
         // index.cache exists and matches the on-disk segments → the fast path
         // reconstructs the index without replaying, and it must agree with what
         // a replay would have produced.
-        assert!(dir.join(INDEX_CACHE_NAME).exists());
-        let log = SegmentLog::open_sized(dir, key(), SEG).unwrap();
+        assert!(tmp.path().join(INDEX_CACHE).exists());
+        let log = SegmentLog::open_sized(store(&tmp), key(), SEG).unwrap();
         assert_eq!(log.index_len(), 99);
         assert!(log.load(Inode(7)).unwrap().is_none());
         assert_eq!(log.load(Inode(50)).unwrap().unwrap().size, 500);
@@ -962,9 +1053,8 @@ mod tests {
     fn stale_checkpoint_falls_back_to_replay() {
         const SEG: u64 = 1 << 20;
         let tmp = tempdir::TempDir::new("seglog").unwrap();
-        let dir = tmp.path().join("segments");
         {
-            let mut log = SegmentLog::open_sized(dir.clone(), key(), SEG).unwrap();
+            let mut log = SegmentLog::open_sized(store(&tmp), key(), SEG).unwrap();
             for i in 1..=10u64 {
                 log.put(Inode(i), &attrs(i)).unwrap();
             }
@@ -978,7 +1068,7 @@ mod tests {
         }
         // Fingerprint no longer matches → full replay, so records written after
         // the checkpoint must still be present.
-        let log = SegmentLog::open_sized(dir, key(), SEG).unwrap();
+        let log = SegmentLog::open_sized(store(&tmp), key(), SEG).unwrap();
         assert_eq!(log.index_len(), 20);
         assert_eq!(log.load(Inode(20)).unwrap().unwrap().size, 20);
     }
@@ -986,9 +1076,8 @@ mod tests {
     #[test]
     fn resync_past_corrupt_frame() {
         let tmp = tempdir::TempDir::new("seglog").unwrap();
-        let dir = tmp.path().join("segments");
         {
-            let mut log = SegmentLog::open(dir.clone(), key()).unwrap();
+            let mut log = SegmentLog::open(store(&tmp), key()).unwrap();
             for i in 1..=20u64 {
                 log.put(Inode(i), &attrs(i)).unwrap();
             }
@@ -996,7 +1085,7 @@ mod tests {
         }
         // Corrupt one frame's payload in the middle of the single segment so
         // its vault::open fails; replay must still recover the others.
-        let seg = segment_path(&dir, 0);
+        let seg = tmp.path().join(segment_name(0));
         let mut bytes = std::fs::read(&seg).unwrap();
         // Find the 3rd frame and trash a payload byte.
         let mut pos = 0;
@@ -1013,7 +1102,7 @@ mod tests {
         }
         std::fs::write(&seg, &bytes).unwrap();
 
-        let log = SegmentLog::open(dir, key()).unwrap();
+        let log = SegmentLog::open(store(&tmp), key()).unwrap();
         // At least all-but-one inode survive (the corrupted frame's inode is
         // beyond ECC repair and is dropped).
         assert!(
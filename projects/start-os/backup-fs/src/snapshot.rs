@@ -0,0 +1,263 @@
+//! Named, read-only point-in-time snapshots.
+//!
+//! A snapshot is a frozen copy of the inode table — every live inode's
+//! [`Attributes`] at creation time — stored under `snapshots/` as sealed
+//! parts plus a small sealed `meta` file that commits them. The inodes
+//! themselves are cheap to copy; the bulk data they point at (block files,
+//! packed extents, spilled-directory buckets) is **shared** with the live tree
+//! and protected by copy-on-write:
+//!
+//! * Every content id and `(dir, gen)` bucket generation referenced by any
+//!   snapshot is *pinned* (see [`Pins`], held by the [`Controller`]).
+//! * The live tree never rewrites or deletes a pinned object. A file whose
+//!   content is pinned moves to a fresh content id on its first mutation
+//!   (`Contents::unpin` — block files are hard-linked, or copied where the
+//!   storage backend can't share them), and a spilled directory moves to a
+//!   fresh bucket generation (`DirectoryContents::unpin`). Deletion paths
+//!   (`blockstore::remove_block`, `Controller::cpack_tombstone`,
+//!   `Controller::remove_dir_bucket`) skip pinned objects outright.
+//! * Deleting a snapshot drops its pins and reaps whatever only it referenced.
+//!
+//! So a daily backup run onto the same target costs one copy of the inode
+//! table plus the blocks that actually changed — not a copy of the tree.
+//!
+//! A snapshot is mounted read-only by opening the controller with
+//! `BackupFSOptions::snapshot`: inode loads are then served from the snapshot
+//! instead of the live log, and everything they reference is pinned.
+
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::ctrl::Controller;
+use crate::directory::DirectoryContents;
+use crate::error::{BkfsResult, BkfsResultExt};
+use crate::inode::{Attributes, ContentId, FileData, Inode};
+use crate::serde::{deserialize_sealed, serialize_sealed};
+
+/// Inodes per sealed part file, keeping each well under the decode limit.
+const PART_INODES: usize = 16 * 1024;
+
+/// The committing record of a snapshot; its parts are only trusted once this
+/// exists.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SnapshotInfo {
+    pub name: String,
+    pub created_unix: u64,
+    pub inodes: u64,
+    parts: u32,
+}
+
+/// A loaded snapshot's inode table.
+pub struct Snapshot {
+    pub info: SnapshotInfo,
+    inodes: BTreeMap<Inode, Attributes>,
+}
+
+impl Snapshot {
+    pub fn get(&self, inode: Inode) -> Option<&Attributes> {
+        self.inodes.get(&inode)
+    }
+
+    pub fn contains(&self, inode: Inode) -> bool {
+        self.inodes.contains_key(&inode)
+    }
+}
+
+/// Objects the live tree must not rewrite or delete.
+#[derive(Clone, Debug, Default)]
+pub struct Pins {
+    contents: HashSet<ContentId>,
+    dirs: HashSet<(Inode, u64)>,
+}
+
+impl Pins {
+    fn add(&mut self, inode: Inode, attrs: &Attributes) {
+        match &attrs.contents {
+            FileData::File(id) | FileData::Packed(id) => {
+                self.contents.insert(*id);
+            }
+            FileData::Directory(dir) => {
+                if let Some((gen, _)) = dir.spilled_generation() {
+                    self.dirs.insert((inode, gen));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn content(&self, id: ContentId) -> bool {
+        self.contents.contains(&id)
+    }
+
+    pub fn dir(&self, dir: Inode, gen: u64) -> bool {
+        self.dirs.contains(&(dir, gen))
+    }
+}
+
+/// Directory of the snapshot files in the store.
+const SNAPSHOTS_DIR: &str = "snapshots";
+
+/// Key-dependent file stem for a snapshot name, so names don't leak.
+fn stem(ctrl: &Controller, name: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(ctrl.key().as_slice());
+    hasher.update(b"snapshot");
+    hasher.update(name.as_bytes());
+    hasher.finalize()[..14]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn meta_object(ctrl: &Controller, name: &str) -> String {
+    format!("{SNAPSHOTS_DIR}/{}.meta", stem(ctrl, name))
+}
+
+fn part_object(ctrl: &Controller, name: &str, part: u32) -> String {
+    format!("{SNAPSHOTS_DIR}/{}.{part}", stem(ctrl, name))
+}
+
+fn write_sealed<T: Serialize>(ctrl: &Controller, object: &str, value: &T) -> BkfsResult<()> {
+    let blob = serialize_sealed(value, ctrl.key(), ctrl.ecc())?;
+    ctrl.storage().put(object, &blob, true)?;
+    Ok(())
+}
+
+fn read_sealed<T: serde::de::DeserializeOwned>(ctrl: &Controller, object: &str) -> BkfsResult<T> {
+    match ctrl.storage().get(object)? {
+        Some(blob) => deserialize_sealed(&blob, ctrl.key()),
+        None => Err(io::Error::from(io::ErrorKind::NotFound).into()),
+    }
+}
+
+fn read_info(ctrl: &Controller, name: &str) -> BkfsResult<SnapshotInfo> {
+    match ctrl.storage().get(&meta_object(ctrl, name))? {
+        Some(blob) => deserialize_sealed(&blob, ctrl.key()),
+        None => BkfsResult::errno_notrace(libc::ENOENT),
+    }
+}
+
+/// Every committed snapshot, oldest first.
+pub fn list(ctrl: &Controller) -> BkfsResult<Vec<SnapshotInfo>> {
+    let mut res = Vec::new();
+    for (object, _) in ctrl.storage().list(SNAPSHOTS_DIR)? {
+        if object.ends_with(".meta") {
+            res.push(read_sealed::<SnapshotInfo>(ctrl, &object)?);
+        }
+    }
+    res.sort_by(|a, b| (a.created_unix, &a.name).cmp(&(b.created_unix, &b.name)));
+    Ok(res)
+}
+
+/// Load a snapshot's full inode table.
+pub fn load(ctrl: &Controller, name: &str) -> BkfsResult<Snapshot> {
+    let info = read_info(ctrl, name)?;
+    let mut inodes = BTreeMap::new();
+    for part in 0..info.parts {
+        let part = read_sealed::<Vec<(Inode, Attributes)>>(ctrl, &part_object(ctrl, name, part))?;
+        inodes.extend(part);
+    }
+    Ok(Snapshot { info, inodes })
+}
+
+/// Union of the objects referenced by every committed snapshot.
+pub fn load_pins(ctrl: &Controller) -> BkfsResult<Pins> {
+    let mut pins = Pins::default();
+    for info in list(ctrl)? {
+        for (inode, attrs) in load(ctrl, &info.name)?.inodes.iter() {
+            pins.add(*inode, attrs);
+        }
+    }
+    Ok(pins)
+}
+
+/// Capture the live tree as snapshot `name`. Must run with no unflushed
+/// state (i.e. not underneath a live mount — the data-dir lock enforces this
+/// for the CLI).
+pub fn create(ctrl: &Controller, name: &str) -> BkfsResult<SnapshotInfo> {
+    ctrl.check_rw()?;
+    if name.is_empty() {
+        return BkfsResult::errno_notrace(libc::EINVAL);
+    }
+    if ctrl.storage().exists(&meta_object(ctrl, name))? {
+        return BkfsResult::errno_notrace(libc::EEXIST);
+    }
+    let mut inodes = Vec::new();
+    for inode in ctrl.live_inodes() {
+        if let Some(attrs) = ctrl.log_load(inode)? {
+            inodes.push((inode, attrs));
+        }
+    }
+    inodes.sort_by_key(|(inode, _)| *inode);
+    // Everything the snapshot will reference must be on stable storage
+    // before the meta file that commits it.
+    ctrl.syncfs()?;
+    let mut parts = 0;
+    for chunk in inodes.chunks(PART_INODES) {
+        write_sealed(ctrl, &part_object(ctrl, name, parts), &chunk)?;
+        parts += 1;
+    }
+    let info = SnapshotInfo {
+        name: name.to_owned(),
+        created_unix: SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        inodes: inodes.len() as u64,
+        parts,
+    };
+    write_sealed(ctrl, &meta_object(ctrl, name), &info)?;
+    ctrl.update_pins(|pins| {
+        for (inode, attrs) in &inodes {
+            pins.add(*inode, attrs);
+        }
+    });
+    Ok(info)
+}
+
+/// Delete snapshot `name`, reaping every object that neither the live tree
+/// nor another snapshot still references.
+pub fn delete(ctrl: &Controller, name: &str) -> BkfsResult<()> {
+    ctrl.check_rw()?;
+    let snap = load(ctrl, name)?;
+    // Uncommit first: a crash after this leaves only unreferenced parts and
+    // objects behind, never a snapshot pointing at reaped data.
+    ctrl.storage().delete(&meta_object(ctrl, name))?;
+    ctrl.syncfs()?;
+    for part in 0..snap.info.parts {
+        ctrl.storage().delete(&part_object(ctrl, name, part))?;
+    }
+
+    let pins = load_pins(ctrl)?;
+    ctrl.update_pins(|p| *p = pins);
+    let mut live = Pins::default();
+    for inode in ctrl.live_inodes() {
+        if let Some(attrs) = ctrl.log_load(inode)? {
+            live.add(inode, &attrs);
+        }
+    }
+    for (inode, attrs) in snap.inodes.iter() {
+        match &attrs.contents {
+            FileData::File(id) if !live.content(*id) => {
+                crate::blockstore::remove_all_blocks(ctrl, *id, attrs.size)?;
+            }
+            FileData::Packed(id) if !live.content(*id) => {
+                ctrl.cpack_tombstone(*id, false)?;
+            }
+            FileData::Directory(dir) => {
+                if let Some((gen, buckets)) = dir.spilled_generation() {
+                    if !live.dir(*inode, gen) {
+                        DirectoryContents::reap_generation(ctrl, *inode, gen, buckets)?;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    ctrl.syncfs()?;
+    Ok(())
+}
@@ -0,0 +1,368 @@
+//! Storage backends: where the sealed objects physically live.
+//!
+//! Everything the controller persists — superblock replicas, log segments,
+//! content blocks, chunks, directory buckets and snapshot parts — is a named
+//! object in a [`Storage`]. Names are `/`-separated and relative to the store
+//! root (`contents/00ab/…`, `segments/0000000000000003.seg`), and every
+//! object is already sealed, so a backend only ever moves opaque bytes.
+//!
+//! [`LocalStorage`] keeps the directory layout under `data_dir` (a USB drive,
+//! a CIFS share, an `rclone mount`). With the `s3` feature,
+//! [`crate::s3::ObjectStorage`] puts the same objects in an S3-compatible
+//! bucket, so a backup can go straight to object storage.
+//!
+//! Objects are accessed in two ways. Almost all are only ever replaced whole
+//! ([`Storage::put`]), which any backend does natively. The active log
+//! segment and scrub repairs write *into* an existing object through an
+//! [`ObjectFile`]; a backend without partial writes stages the object and
+//! uploads it whole on [`ObjectFile::sync`].
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::aligned_io::BufferedDirectFile;
+use crate::atomic_file::AtomicFile;
+use crate::error::BkfsResult;
+use crate::{open_direct, BackupFSOptions};
+
+/// How [`Storage::open`] opens an object.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    /// Read-write; the object must exist.
+    Write,
+    /// Read-write, creating an empty object if absent.
+    Create,
+}
+
+pub trait Storage: Send + Sync {
+    /// The whole object, or `None` if it doesn't exist.
+    fn get(&self, name: &str) -> io::Result<Option<Vec<u8>>>;
+
+    /// `len` bytes of the object at `offset`.
+    fn get_range(&self, name: &str, offset: u64, len: usize) -> io::Result<Vec<u8>>;
+
+    /// Create or replace the object whole; readers see the old bytes or the
+    /// new, never a mix. `durable` waits for stable storage; otherwise
+    /// durability rides the next [`Storage::sync`].
+    fn put(&self, name: &str, data: &[u8], durable: bool) -> io::Result<()>;
+
+    fn exists(&self, name: &str) -> io::Result<bool>;
+
+    /// Remove the object, tolerating its absence.
+    fn delete(&self, name: &str) -> io::Result<()>;
+
+    /// Make `to` a copy of `from` (sharing storage where the backend can).
+    /// `Ok(false)` if `from` doesn't exist.
+    fn copy(&self, from: &str, to: &str) -> io::Result<bool>;
+
+    /// `(name, size)` of every object under the directory `prefix`,
+    /// recursively and in no particular order. Writes still in flight are
+    /// not objects yet and aren't listed.
+    fn list(&self, prefix: &str) -> io::Result<Vec<(String, u64)>>;
+
+    /// Open the object for access in place. A missing object is `NotFound`
+    /// unless `access` is [`Access::Create`].
+    fn open(&self, name: &str, access: Access) -> io::Result<Box<dyn ObjectFile>>;
+
+    /// Make the creation and removal of objects directly under the directory
+    /// `dir` (`""` for the root) durable.
+    fn sync_dir(&self, dir: &str) -> io::Result<()>;
+
+    /// Make every write so far durable.
+    fn sync(&self) -> io::Result<()>;
+}
+
+/// An object opened with [`Storage::open`].
+pub trait ObjectFile: Send + Sync {
+    fn size(&self) -> io::Result<u64>;
+
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()>;
+
+    /// Read `len` bytes at `offset` from the medium itself, bypassing any
+    /// cached copy that could hide rot underneath (see `crate::scrub`).
+    fn read_uncached(&self, offset: u64, len: usize) -> io::Result<Vec<u8>>;
+
+    fn write_at(&self, data: &[u8], offset: u64) -> io::Result<()>;
+
+    /// Make the writes so far durable. Fails with `NotFound` if the object
+    /// was replaced since it was opened and the backend can only write to
+    /// the current copy.
+    fn sync(&self) -> io::Result<()>;
+}
+
+/// The backend `config` selects: an object store if one is configured,
+/// else the data dir.
+pub fn open(config: &BackupFSOptions) -> BkfsResult<Arc<dyn Storage>> {
+    match &config.object_store {
+        #[cfg(feature = "s3")]
+        Some(spec) => Ok(Arc::new(crate::s3::ObjectStorage::new(spec)?)),
+        #[cfg(not(feature = "s3"))]
+        Some(_) => Err(crate::error::BkfsError::unsupported(
+            "this build has no object store support (feature `s3`)",
+        )),
+        None => Ok(Arc::new(LocalStorage::new(&config.data_dir)?)),
+    }
+}
+
+/// Objects at least this large are read and written with `O_DIRECT`, keeping
+/// content blocks and chunks out of the page cache (the CIFS writeback
+/// deadlock, see `aligned_io`); smaller metadata goes through the cache and
+/// the batched `syncfs`.
+const DIRECT_MIN: u64 = 4096;
+
+/// Objects as files under a directory.
+///
+/// The directory is held open and every path is resolved through
+/// `/proc/self/fd/<fd>`, never through the mount namespace. start-core
+/// detaches a backup target with `umount -l` before the final unmount work
+/// (`Filesystem::destroy`: the index checkpoint, the last `syncfs`) runs; a
+/// fresh lookup of `data_dir` would then land in the empty mountpoint, while
+/// the held handle still reaches the real store.
+pub struct LocalStorage {
+    dir: File,
+    base: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(dir: &Path) -> io::Result<LocalStorage> {
+        let dir = File::open(dir)?;
+        let base = PathBuf::from(format!("/proc/self/fd/{}", dir.as_raw_fd()));
+        Ok(LocalStorage { dir, base })
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.base.join(name)
+    }
+
+    fn put_file(&self, path: PathBuf, data: &[u8], durable: bool) -> BkfsResult<()> {
+        if data.len() as u64 >= DIRECT_MIN {
+            let mut file = BufferedDirectFile::new(AtomicFile::create(path)?)?;
+            file.write_all(data)?;
+            if durable {
+                file.save()
+            } else {
+                file.save_fast()
+            }
+        } else {
+            let mut file = AtomicFile::create_buffered(path)?;
+            file.write_all(data)?;
+            if durable {
+                file.save()
+            } else {
+                file.save_fast()
+            }
+        }
+    }
+}
+
+fn found<T>(res: io::Result<T>) -> io::Result<Option<T>> {
+    match res {
+        Ok(v) => Ok(Some(v)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+impl Storage for LocalStorage {
+    fn get(&self, name: &str) -> io::Result<Option<Vec<u8>>> {
+        let path = self.path(name);
+        let Some(meta) = found(fs::metadata(&path))? else {
+            return Ok(None);
+        };
+        if meta.len() < DIRECT_MIN {
+            return found(fs::read(&path));
+        }
+        let Some(mut file) = found(open_direct(&path, false))? else {
+            return Ok(None);
+        };
+        let mut blob = Vec::with_capacity(meta.len() as usize);
+        file.read_to_end(&mut blob)?;
+        Ok(Some(blob))
+    }
+
+    fn get_range(&self, name: &str, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0u8; len];
+        File::open(self.path(name))?.read_exact_at(&mut buf, offset)?;
+        Ok(buf)
+    }
+
+    fn put(&self, name: &str, data: &[u8], durable: bool) -> io::Result<()> {
+        Ok(self.put_file(self.path(name), data, durable)?)
+    }
+
+    fn exists(&self, name: &str) -> io::Result<bool> {
+        self.path(name).try_exists()
+    }
+
+    fn delete(&self, name: &str) -> io::Result<()> {
+        found(fs::remove_file(self.path(name))).map(drop)
+    }
+
+    /// Hard-links where the filesystem allows — writers replace objects by
+    /// rename, so a link is never modified through the other name — and
+    /// copies otherwise.
+    fn copy(&self, from: &str, to: &str) -> io::Result<bool> {
+        let (src, dst) = (self.path(from), self.path(to));
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        match fs::hard_link(&src, &dst) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(_) => found(fs::copy(&src, &dst)).map(|copied| copied.is_some()),
+        }
+    }
+
+    fn list(&self, prefix: &str) -> io::Result<Vec<(String, u64)>> {
+        let mut objects = Vec::new();
+        let mut dirs = vec![prefix.to_owned()];
+        while let Some(dir) = dirs.pop() {
+            let Some(entries) = found(fs::read_dir(self.path(&dir)))? else {
+                continue;
+            };
+            for entry in entries {
+                let entry = entry?;
+                let name = format!("{dir}/{}", entry.file_name().to_string_lossy());
+                let Some(meta) = found(entry.metadata())? else {
+                    continue;
+                };
+                if meta.is_dir() {
+                    dirs.push(name);
+                } else if !name.ends_with(".tmp") {
+                    // (`.tmp` files are `AtomicFile` writes in flight, or
+                    // abandoned by a crash.)
+                    objects.push((name, meta.len()));
+                }
+            }
+        }
+        Ok(objects)
+    }
+
+    fn open(&self, name: &str, access: Access) -> io::Result<Box<dyn ObjectFile>> {
+        let path = self.path(name);
+        if access == Access::Create {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(access != Access::Read)
+            .create(access == Access::Create)
+            .truncate(false)
+            .open(path)?;
+        Ok(Box::new(LocalFile(file)))
+    }
+
+    fn sync_dir(&self, dir: &str) -> io::Result<()> {
+        File::open(self.path(dir))?.sync_all()
+    }
+
+    /// `syncfs` of the filesystem holding the store: one call replaces the
+    /// per-file fsyncs that `put(.., false)` skipped.
+    fn sync(&self) -> io::Result<()> {
+        // SAFETY: the fd is valid for as long as `self.dir` lives.
+        if unsafe { libc::syncfs(self.dir.as_raw_fd()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+struct LocalFile(File);
+
+impl LocalFile {
+    /// Drop clean cached pages of a range (`len` 0: to the end of the file).
+    /// Dirty pages stay; they are newer than the medium anyway.
+    fn drop_cache(&self, offset: u64, len: u64) {
+        // SAFETY: fd is valid for the borrow; DONTNEED is advisory.
+        unsafe {
+            libc::posix_fadvise(
+                self.0.as_raw_fd(),
+                offset as libc::off_t,
+                len as libc::off_t,
+                libc::POSIX_FADV_DONTNEED,
+            );
+        }
+    }
+}
+
+impl ObjectFile for LocalFile {
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.0.metadata()?.len())
+    }
+
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        self.0.read_exact_at(buf, offset)
+    }
+
+    fn read_uncached(&self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        self.drop_cache(offset, len as u64);
+        let mut buf = vec![0u8; len];
+        self.0.read_exact_at(&mut buf, offset)?;
+        Ok(buf)
+    }
+
+    fn write_at(&self, data: &[u8], offset: u64) -> io::Result<()> {
+        self.0.write_all_at(data, offset)
+    }
+
+    /// fdatasync, then drop the now-clean pages to keep the cache bounded.
+    fn sync(&self) -> io::Result<()> {
+        self.0.sync_data()?;
+        self.drop_cache(0, 0);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_objects_roundtrip() {
+        let tmp = tempdir::TempDir::new("storage").unwrap();
+        let store = LocalStorage::new(tmp.path()).unwrap();
+        let big = vec![7u8; 3 * DIRECT_MIN as usize + 5];
+
+        assert_eq!(store.get("contents/00ab/cd").unwrap(), None);
+        store.put("contents/00ab/cd", b"small", false).unwrap();
+        store.put("contents/00ac/ef", &big, true).unwrap();
+        assert_eq!(store.get("contents/00ab/cd").unwrap().unwrap(), b"small");
+        assert_eq!(store.get("contents/00ac/ef").unwrap().unwrap(), big);
+        assert_eq!(store.get_range("contents/00ab/cd", 1, 3).unwrap(), b"mal");
+
+        assert!(store.copy("contents/00ab/cd", "contents/00ad/01").unwrap());
+        assert!(!store.copy("contents/none", "contents/00ad/02").unwrap());
+        fs::write(tmp.path().join("contents/00ad/03.tmp"), b"in flight").unwrap();
+        let mut listed = store.list("contents").unwrap();
+        listed.sort();
+        assert_eq!(
+            listed,
+            [
+                ("contents/00ab/cd".to_owned(), 5),
+                ("contents/00ac/ef".to_owned(), big.len() as u64),
+                ("contents/00ad/01".to_owned(), 5),
+            ]
+        );
+
+        let file = store.open("segments/0.seg", Access::Create).unwrap();
+        file.write_at(b"frame", 0).unwrap();
+        file.sync().unwrap();
+        assert_eq!(file.size().unwrap(), 5);
+        assert_eq!(file.read_uncached(1, 4).unwrap(), b"rame");
+        store.sync_dir("segments").unwrap();
+        store.sync().unwrap();
+
+        store.delete("contents/00ab/cd").unwrap();
+        store.delete("contents/00ab/cd").unwrap();
+        assert!(!store.exists("contents/00ab/cd").unwrap());
+        assert!(store.exists("contents/00ad/01").unwrap());
+        assert!(store.open("contents/00ab/cd", Access::Read).is_err());
+    }
+}
@@ -7,23 +7,31 @@
 //! or read it with the original parameters — and so this build refuses, with
 //! an actionable error, a store it cannot safely read.
 //!
-//! ## On-disk layout (`data_dir/superblock`, replicated to `superblock.bak1`)
+//! ## On-disk layout (object `superblock`, replicated to `superblock.bak1`)
 //!
 //! ```text
 //!   ── plaintext envelope (raw little-endian, parsed forever) ──
 //!   magic          "BKSB"   (4)
-//!   envelope_ver   u8                 layout version of THIS header
+//!   envelope_ver   u8        (=2)     layout version of THIS header
 //!   format_version u32                on-disk DATA format version (key-free version gate)
-//!   kdf_algo       u8        (=1)     PBKDF2-HMAC-SHA256
-//!   kdf_rounds     u32       (=600k)  validated == build & hard-bounded
-//!   salt           [u8; 16]
-//!   ── sealed body = vault::seal(superblock_config.encode(Body), derive_key(pw,salt,rounds)) ──
+//!   slot_count     u8                 1..=MAX_SLOTS
+//!   slots          slot_count × { id u8, kind u8, salt [u8; 16], wrapped_len u16, wrapped }
+//!   ── sealed body = vault::seal(superblock_config.encode(Body), master_key) ──
 //! ```
 //!
+//! Each slot wraps the master key under a key derived from one secret — a
+//! password, keyfile or server-derived key (see [`crate::keyslot`]). Opening
+//! tries every slot of the presented secret's kind.
+//!
+//! Envelope v1 (a single password, `kdf_algo u8 | kdf_rounds u32 | salt
+//! [u8; 16]` in place of the slot table, body sealed under the derived key) is
+//! still read, as a store with one password slot; it is rewritten as v2 on the
+//! next read-write open.
+//!
 //! ### Why this layering is bootstrap-safe (no chicken-and-egg)
 //!
 //! Everything needed to *open* the superblock is either raw plaintext in the
-//! envelope (magic, versions, KDF params, salt) or self-described in the vault
+//! envelope (magic, versions, slot table) or self-described in the vault
 //! blob header (ECC params, nonce, lengths — see [`crate::vault`]). The body is
 //! always encoded with a single *hardcoded* config ([`superblock_config`]),
 //! never the configurable `bincode_encoding` it records. So the superblock is
@@ -33,15 +41,16 @@
 //! ### Security of the plaintext envelope
 //!
 //! The envelope is unauthenticated (the vault tag covers only the sealed body).
-//! To keep that from being a downgrade/DoS surface, `kdf_algo`/`kdf_rounds` are
-//! **not** taken as tunable inputs: they are validated equal to this build's
-//! constants and hard-bounded *before* any PBKDF2 work runs, so a flipped byte
-//! can only produce a clean error, never an expensive or weakened derivation.
-//! `format_version` is additionally echoed inside the sealed body and
+//! To keep that from being a downgrade/DoS surface, the KDF work factor is
+//! **not** a tunable input: a slot records only its kind, whose rounds are
+//! fixed by this build (and a v1 envelope's `kdf_algo`/`kdf_rounds` are
+//! validated equal to this build's constants and hard-bounded *before* any
+//! PBKDF2 work runs), so a flipped byte can only produce a clean error, never
+//! an expensive or weakened derivation. A slot that unwraps only yields a key
+//! that must then open the body's own tag, and match the master key echoed
+//! inside it. `format_version` is likewise echoed inside the sealed body and
 //! cross-checked, turning envelope tampering into an authenticated mismatch.
 
-use std::fs::File;
-use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use chacha20::Key;
@@ -50,10 +59,11 @@ use rand::rand_core::UnwrapErr;
 use rand::{rng, Rng};
 use zeroize::{Zeroize, Zeroizing};
 
-use crate::atomic_file::AtomicFile;
 use crate::blockstore::CHUNK_SIZE;
 use crate::error::{BkfsError, BkfsErrorKind, BkfsResult, BkfsResultExt};
+use crate::keyslot::{KeySlot, Secret, SlotKind, MAX_SLOTS};
 use crate::serde::{decode, encode, superblock_config, Deserialize, Serialize};
+use crate::storage::Storage;
 use crate::vault::{self, EccParams, PBKDF2_ROUNDS, PBKDF2_SALT_LEN};
 
 /// Number of redundant superblock copies (`superblock`, `superblock.bak1`).
@@ -65,7 +75,10 @@ const SB_MAGIC: [u8; 4] = *b"BKSB";
 /// Layout version of the plaintext envelope. Bump if the envelope's byte shape
 /// (or the body's hardcoded codec) ever changes, so old builds reject via the
 /// raw parse rather than a confusing decode error.
-const ENVELOPE_VER: u8 = 1;
+///
+/// v2 replaced v1's single password salt with the key slot table.
+const ENVELOPE_VER: u8 = 2;
+const ENVELOPE_VER_V1: u8 = 1;
 const KDF_PBKDF2_HMAC_SHA256: u8 = 1;
 
 /// The on-disk DATA format version this build *writes*.
@@ -75,16 +88,25 @@ const KDF_PBKDF2_HMAC_SHA256: u8 = 1;
 /// compression, and **bincode v2 (`standard`) encoding**. (The pre-versioning
 /// `cryptinfo`-based dev format is not readable; there is no migration path
 /// from it — it predates any deployment.)
-pub const FORMAT_VERSION: u32 = 1;
+///
+/// v2 stores block content as deduplicated, content-defined chunks: the block
+/// file holds a chunk manifest, chunks live under `chunks/`, and their
+/// reference counts in the segment log (see [`crate::chunkstore`]). Nothing
+/// else changes, so the version is fixed at creation and a v1 store keeps
+/// reading and writing its own layout.
+///
+/// v3 adds compressed frames against trained zstd dictionaries stored under
+/// `dicts/` (see [`crate::compress`]); v1/v2 stores never write them.
+pub const FORMAT_VERSION: u32 = 3;
 /// Highest DATA format version this build can read. A store recording a higher
 /// version is refused with an actionable "upgrade the software" error.
-pub const SUPPORTED_FORMAT_VERSION: u32 = 1;
+pub const SUPPORTED_FORMAT_VERSION: u32 = 3;
 
 // Scheme identifiers for the VALIDATE-EQUALITY constants. Each governs reads,
 // so a build that doesn't recognize the recorded value must refuse to mount.
 const BINCODE_STANDARD_V2: u8 = 1;
 /// `SHA256(master_key ‖ "block"|"dirbucket" ‖ id.to_le_bytes() ‖ …)`, with the
-/// 16-bit-dir / 120-bit-name split (see `ctrl::block_path`). Frozen for v1.
+/// 16-bit-dir / 120-bit-name split (see `ctrl::block_object`). Frozen for v1.
 pub const PATH_HASH_SCHEME_V1: u8 = 1;
 /// `SHA256(master_key ‖ "dirent" ‖ name)[..8] % buckets` (see
 /// `directory::bucket_of`). Frozen for v1.
@@ -104,10 +126,15 @@ const MAX_DIR_MAX_BUCKET: u32 = 100_000;
 // Envelope byte offsets.
 const OFF_ENVELOPE_VER: usize = 4;
 const OFF_FORMAT_VERSION: usize = 5;
+const HEADER_LEN: usize = 9;
+// v2
+const OFF_SLOT_COUNT: usize = 9;
+const OFF_SLOTS: usize = 10;
+// v1
 const OFF_KDF_ALGO: usize = 9;
 const OFF_KDF_ROUNDS: usize = 10;
 const OFF_SALT: usize = 14;
-const ENVELOPE_LEN: usize = OFF_SALT + PBKDF2_SALT_LEN;
+const ENVELOPE_V1_LEN: usize = OFF_SALT + PBKDF2_SALT_LEN;
 
 // ── creation-time defaults / env snapshot ─────────────────────────────
 // Defaults for the policy constants, read from the environment ONCE at
@@ -125,6 +152,15 @@ fn env_u32(key: &str) -> Option<u32> {
     std::env::var(key).ok().and_then(|s| s.parse().ok())
 }
 
+/// Format version for a *new* filesystem: [`FORMAT_VERSION`], or an older
+/// supported one via `BACKUPFS_FORMAT_VERSION` (e.g. `1` to opt out of
+/// deduplication).
+fn create_format_version() -> u32 {
+    env_u32("BACKUPFS_FORMAT_VERSION")
+        .filter(|v| (1..=FORMAT_VERSION).contains(v))
+        .unwrap_or(FORMAT_VERSION)
+}
+
 /// The format constants for a filesystem. Recorded in the superblock so a
 /// future build can detect and migrate a store it predates, and so this build
 /// refuses anything it cannot honor (rather than silently mis-reading).
@@ -288,9 +324,15 @@ struct Body {
 /// filesystem must use.
 pub struct Superblock {
     pub key: Key,
+    /// On-disk data format version, fixed at creation.
+    pub format_version: u32,
     pub constants: Constants,
     pub generation: u64,
     pub created_unix: u64,
+    /// Every enrolled key slot, each wrapping `key`.
+    pub slots: Vec<KeySlot>,
+    /// Id of the slot that unlocked this superblock.
+    pub unlocked: u8,
 }
 
 fn now_unix() -> u64 {
@@ -300,47 +342,51 @@ fn now_unix() -> u64 {
         .unwrap_or(0)
 }
 
-/// The replica paths for a superblock at `primary`: `superblock`,
-/// `superblock.bak1`, …
-pub fn replica_paths(primary: &Path) -> Vec<PathBuf> {
-    let mut paths = vec![primary.to_owned()];
+/// The superblock's replica object names: `superblock`, `superblock.bak1`, …
+pub fn replica_names() -> Vec<String> {
+    let mut names = vec!["superblock".to_owned()];
     for i in 1..SUPERBLOCK_REPLICAS {
-        paths.push(primary.with_extension(format!("bak{i}")));
+        names.push(format!("superblock.bak{i}"));
     }
-    paths
+    names
 }
 
-pub fn any_exists(primary: &Path) -> bool {
-    replica_paths(primary).iter().any(|p| p.exists())
+pub fn any_exists(storage: &dyn Storage) -> BkfsResult<bool> {
+    for name in replica_names() {
+        if storage.exists(&name)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
 }
 
-/// Whether the data store siblings of the superblock (`segments`, `contents`,
-/// `dirents`) hold any data. Used to refuse creating a fresh superblock over a
+/// Whether the data store beside the superblock (`segments`, `contents`,
+/// `dirents`, `chunks`) holds any data. Used to refuse creating a fresh superblock over a
 /// store whose superblock replicas were lost but whose data survived. A truly
 /// fresh store has none of these yet (they are created lazily after the
 /// superblock), so this never false-positives at first creation.
-fn data_store_nonempty(primary: &Path) -> bool {
-    let Some(dir) = primary.parent() else {
-        return false;
-    };
-    ["segments", "contents", "dirents"].iter().any(|sub| {
-        std::fs::read_dir(dir.join(sub))
-            .map(|mut entries| entries.next().is_some())
-            .unwrap_or(false)
-    })
+fn data_store_nonempty(storage: &dyn Storage) -> bool {
+    ["segments", "contents", "dirents", "chunks"]
+        .iter()
+        .any(|sub| {
+            storage
+                .list(sub)
+                .map(|objects| !objects.is_empty())
+                .unwrap_or(false)
+        })
 }
 
 impl Superblock {
-    /// Open the existing superblock at `primary`, or create a fresh one if no
+    /// Open the existing superblock in `storage`, or create a fresh one if no
     /// replica exists (unless `readonly`).
     pub fn open_or_create(
-        primary: &Path,
-        password: &str,
+        storage: &dyn Storage,
+        secret: &Secret,
         readonly: bool,
     ) -> BkfsResult<Superblock> {
-        if any_exists(primary) {
-            Self::load(primary, password, readonly)
-        } else if primary.with_file_name("cryptinfo").exists() {
+        if any_exists(storage)? {
+            Self::load(storage, secret, readonly)
+        } else if storage.exists("cryptinfo")? {
             // A pre-versioning store (master key in `cryptinfo`, bincode-v1
             // data). There is no migration path; refuse with a clear error
             // rather than silently creating a fresh superblock over it (which
@@ -350,7 +396,7 @@ impl Superblock {
                 "found a legacy unversioned `cryptinfo` store with no superblock; this build cannot \
                  read the pre-versioning on-disk format",
             ))
-        } else if data_store_nonempty(primary) {
+        } else if data_store_nonempty(storage) {
             // Both superblock replicas are gone but a populated data store
             // remains (e.g. an unreliable backing store dropped the two small
             // superblock files while segments/content survived). Minting a
@@ -366,49 +412,57 @@ impl Superblock {
         } else if readonly {
             BkfsResult::errno_notrace(libc::EROFS)
         } else {
-            Self::create(primary, password)
+            Self::create(storage, secret)
         }
     }
 
-    fn create(primary: &Path, password: &str) -> BkfsResult<Superblock> {
+    /// Create a store whose only key slot (id 0) is `secret`.
+    fn create(storage: &dyn Storage, secret: &Secret) -> BkfsResult<Superblock> {
         // Choose ECC once so the body's own seal and its recorded params agree.
         let ecc = EccParams::from_env_or_default();
         let constants = Constants::create(ecc);
         constants.validate()?;
         let mut master = Zeroizing::new([0u8; 32]);
         UnwrapErr(rng()).fill_bytes(&mut *master);
+        let key = Key::from(*master);
         let sb = Superblock {
-            key: Key::from(*master),
+            key,
+            format_version: create_format_version(),
             constants,
             generation: 1,
             created_unix: now_unix(),
+            slots: vec![KeySlot::new(0, secret, &key, ecc)?],
+            unlocked: 0,
         };
-        sb.persist(primary, password)?;
+        sb.persist(storage)?;
         Ok(sb)
     }
 
     /// Load, picking the replica with the highest generation as canonical and
-    /// healing the rest toward it. Distinguishes a wrong password (every
-    /// readable replica fails the integrity tag) from genuine loss.
-    fn load(primary: &Path, password: &str, readonly: bool) -> BkfsResult<Superblock> {
-        let paths = replica_paths(primary);
+    /// healing the rest toward it. Distinguishes a wrong secret (no slot of
+    /// any readable replica unlocks) from genuine loss.
+    fn load(storage: &dyn Storage, secret: &Secret, readonly: bool) -> BkfsResult<Superblock> {
+        let names = replica_names();
         let mut best: Option<Superblock> = None;
         let mut bad_checksum = 0usize;
         let mut healthy = 0usize;
+        let mut legacy = false;
         let mut last_err: Option<BkfsError> = None;
+        let mut keys = DerivedKeys::default();
 
-        for path in &paths {
-            let raw = match std::fs::read(path) {
-                Ok(b) => b,
-                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+        for name in &names {
+            let raw = match storage.get(name) {
+                Ok(Some(b)) => b,
+                Ok(None) => continue,
                 Err(e) => {
                     last_err = Some(e.into());
                     continue;
                 }
             };
-            match parse_one(&raw, password) {
-                Ok(sb) => {
+            match parse_one(&raw, secret, &mut keys) {
+                Ok((sb, envelope_ver)) => {
                     healthy += 1;
+                    legacy |= envelope_ver < ENVELOPE_VER;
                     best = Some(match best {
                         Some(prev) if prev.generation >= sb.generation => prev,
                         _ => sb,
@@ -425,21 +479,22 @@ impl Superblock {
 
         match best {
             Some(sb) => {
-                if healthy < paths.len() && !readonly {
-                    // Heal damaged/missing replicas from the canonical copy.
-                    // Skipped on a read-only mount (which must never write); a
+                if (healthy < names.len() || legacy) && !readonly {
+                    // Heal damaged/missing replicas from the canonical copy,
+                    // and rewrite a v1 envelope with its slot table. Skipped
+                    // on a read-only mount (which must never write); a
                     // near-full store likewise keeps mounting off the good
                     // replica with reduced redundancy.
-                    if let Err(e) = sb.persist(primary, password) {
+                    if let Err(e) = sb.persist(storage) {
                         warn!("superblock self-heal failed (reduced redundancy): {e}");
                     }
-                } else if healthy < paths.len() {
+                } else if healthy < names.len() {
                     warn!("superblock has reduced redundancy; self-heal skipped (read-only mount)");
                 }
                 Ok(sb)
             }
-            // No replica decoded, but at least one failed the integrity tag:
-            // almost certainly the wrong password rather than independent loss.
+            // No replica decoded, but at least one failed to unlock: almost
+            // certainly the wrong secret rather than independent loss.
             // (`> 0`, not `== readable`, so a second replica that is also
             // independently corrupt at the envelope level can't mask the
             // wrong-password signal — `best` is None here, so `healthy == 0`.)
@@ -453,17 +508,13 @@ impl Superblock {
     }
 
     /// Re-seal and rewrite all replicas (used at creation, for self-heal, and
-    /// for password change). Each write generates a fresh salt + nonce.
-    pub fn persist(&self, primary: &Path, password: &str) -> BkfsResult<()> {
-        use std::io::Write;
-        let mut salt = [0u8; PBKDF2_SALT_LEN];
-        UnwrapErr(rng()).fill_bytes(&mut salt);
-        let key = vault::derive_key(password, &salt, PBKDF2_ROUNDS)?;
-
+    /// for key slot changes). Each write seals the body under a fresh nonce;
+    /// the slots are written as they are.
+    pub fn persist(&self, storage: &dyn Storage) -> BkfsResult<()> {
         let mut master = Zeroizing::new([0u8; 32]);
         master.copy_from_slice(self.key.as_slice());
         let body = Body {
-            format_version: FORMAT_VERSION,
+            format_version: self.format_version,
             generation: self.generation,
             created_unix: self.created_unix,
             master_key: master,
@@ -471,58 +522,209 @@ impl Superblock {
             features: 0,
         };
         let mut body_bytes = encode(&body, superblock_config())?;
-        let sealed = vault::seal(&body_bytes, <&Key>::from(&*key), self.constants.ecc());
+        let sealed = vault::seal(&body_bytes, &self.key, self.constants.ecc());
         body_bytes.zeroize();
 
-        let mut file = Vec::with_capacity(ENVELOPE_LEN + sealed.len());
+        debug_assert!((1..=MAX_SLOTS).contains(&self.slots.len()));
+        let mut file = Vec::with_capacity(OFF_SLOTS + sealed.len());
         file.extend_from_slice(&SB_MAGIC);
         file.push(ENVELOPE_VER);
-        file.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
-        file.push(KDF_PBKDF2_HMAC_SHA256);
-        file.extend_from_slice(&PBKDF2_ROUNDS.to_le_bytes());
-        file.extend_from_slice(&salt);
+        file.extend_from_slice(&self.format_version.to_le_bytes());
+        file.push(self.slots.len() as u8);
+        for slot in &self.slots {
+            slot.encode(&mut file);
+        }
         file.extend_from_slice(&sealed);
 
         // Write backups first and the primary last, so a crash mid-save never
         // leaves the primary as the only (torn) copy; combined with
         // highest-generation-wins on load, a half-applied rewrite converges to
         // the newest copy.
-        for path in replica_paths(primary).into_iter().rev() {
-            let mut f = AtomicFile::create_buffered(path)?;
-            f.write_all(&file)?;
-            f.save()?;
+        for name in replica_names().into_iter().rev() {
+            storage.put(&name, &file, true)?;
         }
         // fsync the containing directory so the rename(s) that expose the new
-        // superblock are themselves durable. AtomicFile::save fsyncs the file
-        // data and renames, but not the parent dir entry — and the CLI
-        // `change-password`/`fsck` paths return without the unmount syncfs, so
-        // without this a reported password rotation could be lost on a crash.
+        // superblock are themselves durable. A durable put syncs the object
+        // but, on a local store, not the parent dir entry — and the CLI
+        // `change-password`/`key-slot` paths return without the unmount
+        // syncfs, so without this a reported password rotation could be lost
+        // on a crash.
         // (Mirrors seglog's fsync_dir for segment renames.)
-        if let Some(dir) = primary.parent() {
-            File::open(dir)?.sync_all()?;
-        }
+        storage.sync_dir("")?;
         Ok(())
     }
 }
 
-/// Parse and authenticate one superblock replica. The version gate runs on the
-/// plaintext envelope *before* any decode, so a store written by a newer build
-/// produces the actionable "upgrade" message rather than a confusing decode
-/// error.
-fn parse_one(raw: &[u8], password: &str) -> BkfsResult<Superblock> {
-    if raw.len() < ENVELOPE_LEN || raw[..4] != SB_MAGIC {
+/// Slot keys derived while loading, so replicas sharing a slot (normally all
+/// of them) pay for the password KDF once. Keyed by salt: every slot tried in
+/// one load matches the one presented secret's kind.
+#[derive(Default)]
+struct DerivedKeys(Vec<([u8; PBKDF2_SALT_LEN], Zeroizing<[u8; 32]>)>);
+
+impl DerivedKeys {
+    fn get(&mut self, secret: &Secret, salt: &[u8; PBKDF2_SALT_LEN]) -> BkfsResult<&[u8; 32]> {
+        let i = match self.0.iter().position(|(s, _)| s == salt) {
+            Some(i) => i,
+            None => {
+                self.0.push((*salt, secret.derive(salt)?));
+                self.0.len() - 1
+            }
+        };
+        Ok(&*self.0[i].1)
+    }
+}
+
+/// Parse and authenticate one superblock replica, returning it with its
+/// envelope version. The version gate runs on the plaintext envelope *before*
+/// any decode, so a store written by a newer build produces the actionable
+/// "upgrade" message rather than a confusing decode error.
+fn parse_one(raw: &[u8], secret: &Secret, keys: &mut DerivedKeys) -> BkfsResult<(Superblock, u8)> {
+    let (envelope_ver, format_version) = open_header(raw)?;
+    if envelope_ver == ENVELOPE_VER_V1 {
+        return parse_v1(raw, format_version, secret, keys).map(|sb| (sb, envelope_ver));
+    }
+    let (slots, body_off) = decode_slots(raw)?;
+    // unlock-then-open: a wrong secret fails every slot's vault tag
+    // (BadChecksum) before any decode runs, so it never surfaces as a decode
+    // error.
+    let mut unlocked = None;
+    for slot in slots.iter().filter(|s| s.kind == secret.kind()) {
+        if let Ok(master) = slot.unlock(keys.get(secret, slot.salt())?) {
+            unlocked = Some((slot.id, master));
+            break;
+        }
+    }
+    let Some((unlocked, key)) = unlocked else {
+        return Err(BkfsError {
+            kind: BkfsErrorKind::BadChecksum,
+            backtrace: None,
+        });
+    };
+    let body = open_body(&raw[body_off..], &key, format_version)?;
+    if body.master_key[..] != key[..] {
+        return Err(BkfsError::unsupported(
+            "superblock key slot unwraps a different master key than the body records",
+        ));
+    }
+    let sb = Superblock {
+        key,
+        format_version,
+        constants: body.constants,
+        generation: body.generation,
+        created_unix: body.created_unix,
+        slots,
+        unlocked,
+    };
+    Ok((sb, envelope_ver))
+}
+
+/// A v1 replica, whose body is sealed directly under the password-derived
+/// key. It loads as a store with that password in slot 0 (the derived key
+/// wrapping the master key), so the next persist writes it out as v2.
+fn parse_v1(
+    raw: &[u8],
+    format_version: u32,
+    secret: &Secret,
+    keys: &mut DerivedKeys,
+) -> BkfsResult<Superblock> {
+    let salt = open_envelope_v1(raw)?;
+    if secret.kind() != SlotKind::Password {
+        // v1 has no slot but the password.
+        return Err(BkfsError {
+            kind: BkfsErrorKind::BadChecksum,
+            backtrace: None,
+        });
+    }
+    let slot_key = keys.get(secret, &salt)?;
+    let body = open_body(
+        &raw[ENVELOPE_V1_LEN..],
+        <&Key>::from(slot_key),
+        format_version,
+    )?;
+    let key = Key::from(*body.master_key);
+    let slot = KeySlot::wrap(
+        0,
+        SlotKind::Password,
+        salt,
+        slot_key,
+        &key,
+        body.constants.ecc(),
+    );
+    Ok(Superblock {
+        key,
+        format_version,
+        constants: body.constants,
+        generation: body.generation,
+        created_unix: body.created_unix,
+        slots: vec![slot],
+        unlocked: 0,
+    })
+}
+
+/// Open, decode and validate a sealed body.
+fn open_body(sealed: &[u8], key: &Key, format_version: u32) -> BkfsResult<Body> {
+    let mut plain = vault::open(sealed, key)?;
+    let body: Body = decode(&plain, superblock_config())?;
+    plain.zeroize();
+
+    if body.format_version != format_version {
+        return Err(BkfsError::unsupported(
+            "superblock envelope/body version mismatch (corrupt or tampered header)",
+        ));
+    }
+    body.constants.validate()?;
+    Ok(body)
+}
+
+/// Verify one replica (see [`vault::scrub`]): every slot's wrapped key is
+/// checked shard by shard (its tag needs that slot's secret), and the body
+/// fully, under the master `key`. A v1 replica's body is sealed under the
+/// `password`-derived key instead. The healed bytes include the envelope, so
+/// they can replace the replica file's contents as-is.
+pub fn scrub_replica(raw: &[u8], key: &Key, password: &str) -> BkfsResult<vault::Scrubbed> {
+    let (envelope_ver, _) = open_header(raw)?;
+    let (mut healed, body) = if envelope_ver == ENVELOPE_VER_V1 {
+        let salt = open_envelope_v1(raw)?;
+        let slot_key = Secret::password(password).derive(&salt)?;
+        let body = vault::scrub(&raw[ENVELOPE_V1_LEN..], <&Key>::from(&*slot_key))?;
+        (raw[..ENVELOPE_V1_LEN].to_vec(), body)
+    } else {
+        let (slots, body_off) = decode_slots(raw)?;
+        let mut healed = raw[..OFF_SLOTS].to_vec();
+        let mut bad_shards = 0;
+        for slot in &slots {
+            let (slot, bad) = slot.reshard()?;
+            slot.encode(&mut healed);
+            bad_shards += bad;
+        }
+        let mut body = vault::scrub(&raw[body_off..], key)?;
+        body.bad_shards += bad_shards;
+        (healed, body)
+    };
+    healed.extend_from_slice(&body.healed);
+    Ok(vault::Scrubbed {
+        bad_shards: body.bad_shards,
+        healed,
+    })
+}
+
+/// Validate the version-independent start of the envelope, returning its
+/// envelope and format versions.
+fn open_header(raw: &[u8]) -> BkfsResult<(u8, u32)> {
+    if raw.len() < HEADER_LEN || raw[..4] != SB_MAGIC {
         return Err(BkfsError::unsupported(
             "not a backup-fs superblock (an older unversioned store, or a corrupt/truncated header)",
         ));
     }
     let envelope_ver = raw[OFF_ENVELOPE_VER];
-    if envelope_ver != ENVELOPE_VER {
+    if !(ENVELOPE_VER_V1..=ENVELOPE_VER).contains(&envelope_ver) {
         return Err(BkfsError::unsupported(format!(
-            "superblock envelope version {envelope_ver} unsupported (this build understands {ENVELOPE_VER})"
+            "superblock envelope version {envelope_ver} unsupported (this build understands \
+             {ENVELOPE_VER_V1}..={ENVELOPE_VER})"
         )));
     }
     let format_version =
-        u32::from_le_bytes(raw[OFF_FORMAT_VERSION..OFF_KDF_ALGO].try_into().unwrap());
+        u32::from_le_bytes(raw[OFF_FORMAT_VERSION..HEADER_LEN].try_into().unwrap());
     if format_version == 0 || format_version > SUPPORTED_FORMAT_VERSION {
         return Err(BkfsError::unsupported(format!(
             "unsupported filesystem format version {format_version}; this build supports \
@@ -530,6 +732,41 @@ fn parse_one(raw: &[u8], password: &str) -> BkfsResult<Superblock> {
              upgrade the software)"
         )));
     }
+    Ok((envelope_ver, format_version))
+}
+
+/// Decode a v2 envelope's slot table, returning it and the offset of the
+/// sealed body.
+fn decode_slots(raw: &[u8]) -> BkfsResult<(Vec<KeySlot>, usize)> {
+    let count = raw.get(OFF_SLOT_COUNT).copied().unwrap_or(0) as usize;
+    if !(1..=MAX_SLOTS).contains(&count) {
+        return Err(BkfsError::unsupported(format!(
+            "superblock records {count} key slots (expected 1..={MAX_SLOTS})"
+        )));
+    }
+    let mut slots: Vec<KeySlot> = Vec::with_capacity(count);
+    let mut off = OFF_SLOTS;
+    for _ in 0..count {
+        let (slot, len) = KeySlot::decode(&raw[off..])?;
+        if slots.iter().any(|s| s.id == slot.id) {
+            return Err(BkfsError::unsupported(format!(
+                "superblock records key slot {} twice",
+                slot.id
+            )));
+        }
+        slots.push(slot);
+        off += len;
+    }
+    Ok((slots, off))
+}
+
+/// Validate a v1 envelope's KDF parameters, returning its password salt.
+fn open_envelope_v1(raw: &[u8]) -> BkfsResult<[u8; PBKDF2_SALT_LEN]> {
+    if raw.len() < ENVELOPE_V1_LEN {
+        return Err(BkfsError::unsupported(
+            "not a backup-fs superblock (an older unversioned store, or a corrupt/truncated header)",
+        ));
+    }
     let kdf_algo = raw[OFF_KDF_ALGO];
     if kdf_algo != KDF_PBKDF2_HMAC_SHA256 {
         return Err(BkfsError::unsupported(format!(
@@ -549,34 +786,13 @@ fn parse_one(raw: &[u8], password: &str) -> BkfsResult<Superblock> {
             "superblock KDF rounds {kdf_rounds} differ from this build's {PBKDF2_ROUNDS}"
         )));
     }
-    let salt = &raw[OFF_SALT..OFF_SALT + PBKDF2_SALT_LEN];
-    let sealed = &raw[ENVELOPE_LEN..];
-
-    let key = vault::derive_key(password, salt, kdf_rounds)?;
-    // open()-then-decode: a wrong password fails the vault tag (BadChecksum)
-    // before any decode runs, so it never surfaces as a decode error.
-    let mut plain = vault::open(sealed, <&Key>::from(&*key))?;
-    let body: Body = decode(&plain, superblock_config())?;
-    plain.zeroize();
-
-    if body.format_version != format_version {
-        return Err(BkfsError::unsupported(
-            "superblock envelope/body version mismatch (corrupt or tampered header)",
-        ));
-    }
-    body.constants.validate()?;
-
-    Ok(Superblock {
-        key: Key::from(*body.master_key),
-        constants: body.constants,
-        generation: body.generation,
-        created_unix: body.created_unix,
-    })
+    Ok(raw[OFF_SALT..ENVELOPE_V1_LEN].try_into().unwrap())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::storage::LocalStorage;
 
     fn default_constants() -> Constants {
         Constants::create(EccParams::default())
@@ -629,4 +845,81 @@ mod tests {
         c.pack_max = c.chunk_size + 1;
         assert!(c.validate().is_err());
     }
+
+    fn keyfile(byte: u8) -> Secret {
+        Secret::Keyfile(Zeroizing::new(vec![byte; 32]))
+    }
+
+    fn assert_bad_checksum(res: BkfsResult<Superblock>) {
+        match res {
+            Err(e) => assert!(matches!(e.kind, BkfsErrorKind::BadChecksum)),
+            Ok(_) => panic!("unenrolled secret unlocked the superblock"),
+        }
+    }
+
+    #[test]
+    fn every_slot_unlocks_the_same_master_key() {
+        let tmp = tempdir::TempDir::new("superblock").unwrap();
+        let primary = tmp.path().join("superblock");
+        let store = LocalStorage::new(tmp.path()).unwrap();
+        let mut sb = Superblock::open_or_create(&store, &keyfile(1), false).unwrap();
+        let ecc = sb.constants.ecc();
+        sb.slots
+            .push(KeySlot::new(3, &keyfile(2), &sb.key, ecc).unwrap());
+        sb.persist(&store).unwrap();
+
+        let other = Superblock::open_or_create(&store, &keyfile(2), true).unwrap();
+        assert_eq!(other.key, sb.key);
+        assert_eq!(other.unlocked, 3);
+        assert_eq!(other.slots.len(), 2);
+        assert_bad_checksum(Superblock::open_or_create(&store, &keyfile(9), true));
+
+        // Scrubbing reproduces a replica byte for byte, slot table included.
+        let raw = std::fs::read(&primary).unwrap();
+        let scrubbed = scrub_replica(&raw, &sb.key, "").unwrap();
+        assert_eq!(scrubbed.bad_shards, 0);
+        assert_eq!(scrubbed.healed, raw);
+    }
+
+    #[test]
+    fn v1_envelope_opens_as_password_slot_0() {
+        let tmp = tempdir::TempDir::new("superblock").unwrap();
+        let primary = tmp.path().join("superblock");
+        let store = LocalStorage::new(tmp.path()).unwrap();
+        let key = Key::from([0x5au8; 32]);
+        let body = Body {
+            format_version: 1,
+            generation: 4,
+            created_unix: 123,
+            master_key: Zeroizing::new([0x5au8; 32]),
+            constants: default_constants(),
+            features: 0,
+        };
+        let salt = [0x11u8; PBKDF2_SALT_LEN];
+        let slot_key = Secret::password("hunter2").derive(&salt).unwrap();
+        let mut raw = SB_MAGIC.to_vec();
+        raw.push(ENVELOPE_VER_V1);
+        raw.extend_from_slice(&1u32.to_le_bytes());
+        raw.push(KDF_PBKDF2_HMAC_SHA256);
+        raw.extend_from_slice(&PBKDF2_ROUNDS.to_le_bytes());
+        raw.extend_from_slice(&salt);
+        raw.extend_from_slice(&vault::seal(
+            &encode(&body, superblock_config()).unwrap(),
+            <&Key>::from(&*slot_key),
+            EccParams::default(),
+        ));
+        std::fs::write(&primary, &raw).unwrap();
+        assert_eq!(scrub_replica(&raw, &key, "hunter2").unwrap().healed, raw);
+
+        assert_bad_checksum(Superblock::open_or_create(&store, &keyfile(1), true));
+        let sb = Superblock::open_or_create(&store, &Secret::password("hunter2"), false).unwrap();
+        assert_eq!((sb.key, sb.generation, sb.unlocked), (key, 4, 0));
+        assert_eq!(sb.slots[0].kind, SlotKind::Password);
+
+        // The read-write open rewrote both replicas as v2.
+        for name in replica_names() {
+            let raw = store.get(&name).unwrap().unwrap();
+            assert_eq!(raw[OFF_ENVELOPE_VER], ENVELOPE_VER);
+        }
+    }
 }
@@ -73,9 +73,13 @@ fn with_backupfs(
         data_dir: data.to_owned(),
         setuid_support: false,
         password,
+        keyfile: None,
+        master_key_file: None,
         file_size_padding,
         readonly: false,
         idmapped: false,
+        snapshot: None,
+        object_store: None,
     })
     .unwrap();
     // 0.17's spawn() moves the mount into the BackgroundSession, so an
@@ -243,9 +247,13 @@ fn checksum() {
         data_dir: data.path().to_owned(),
         setuid_support: false,
         password: "rtns".to_owned(),
+        keyfile: None,
+        master_key_file: None,
         file_size_padding: None,
         readonly: false,
         idmapped: false,
+        snapshot: None,
+        object_store: None,
     });
     match res {
         Ok(_) => panic!(),
@@ -270,9 +278,13 @@ fn change_password() {
             data_dir: data.path().to_owned(),
             setuid_support: false,
             password: "ohea".to_owned(),
+            keyfile: None,
+            master_key_file: None,
             file_size_padding: None,
             readonly: false,
             idmapped: false,
+            snapshot: None,
+            object_store: None,
         })
         .unwrap();
         fs.change_password("rtns").unwrap();
@@ -1388,9 +1400,13 @@ fn opts(data: &Path, password: &str) -> BackupFSOptions {
         data_dir: data.to_owned(),
         setuid_support: false,
         password: password.to_owned(),
+        keyfile: None,
+        master_key_file: None,
         file_size_padding: None,
         readonly: false,
         idmapped: false,
+        snapshot: None,
+        object_store: None,
     }
 }
 
@@ -1495,7 +1511,8 @@ fn ecc_recovers_corrupted_block() {
     // the very start of the shard region — damages a single shard, well
     // within the parity budget.
     let ctrl = Controller::new(opts(data.path(), "ohea")).unwrap();
-    let block = ctrl.resolve_block_path(ContentId(ino), 0);
+    let block = ctrl.resolve_block_object(ContentId(ino), 0);
+    let block = data.path().join(block);
     drop(ctrl);
     assert!(block.exists(), "expected block file at {block:?}");
     let mut bytes = fs::read(&block).unwrap();
@@ -1535,7 +1552,8 @@ fn incremental_overwrite_touches_one_block() {
 
     let ctrl = Controller::new(opts(data.path(), "ohea")).unwrap();
     let paths: Vec<_> = (0..3)
-        .map(|i| ctrl.resolve_block_path(ContentId(ino), i))
+        .map(|i| ctrl.resolve_block_object(ContentId(ino), i))
+        .map(|name| data.path().join(name))
         .collect();
     drop(ctrl);
     for p in &paths {
@@ -1605,18 +1623,13 @@ fn sparse_file_omits_hole_blocks() {
     });
 
     let ctrl = Controller::new(opts(data.path(), "ohea")).unwrap();
-    assert!(
-        !ctrl.resolve_block_path(ContentId(ino), 0).exists(),
-        "hole block 0 should not exist on disk"
-    );
-    assert!(
-        !ctrl.resolve_block_path(ContentId(ino), 1).exists(),
-        "hole block 1 should not exist on disk"
-    );
-    assert!(
-        ctrl.resolve_block_path(ContentId(ino), 2).exists(),
-        "written block 2 should exist on disk"
-    );
+    let exists = |i| {
+        let name = ctrl.resolve_block_object(ContentId(ino), i);
+        ctrl.storage().exists(&name).unwrap()
+    };
+    assert!(!exists(0), "hole block 0 should not exist on disk");
+    assert!(!exists(1), "hole block 1 should not exist on disk");
+    assert!(exists(2), "written block 2 should exist on disk");
     drop(ctrl);
 
     with_backupfs(
@@ -2210,11 +2223,11 @@ fn idle_remount_does_not_recompact_live_packed() {
     );
 }
 
-/// Total bytes across content block files.
+/// Total bytes across content block files (and, in a deduplicating store,
+/// the chunks their manifests reference).
 fn content_bytes(data: &Path) -> u64 {
-    let dir = data.join("contents");
     let mut total = 0u64;
-    let mut stack = vec![dir];
+    let mut stack = vec![data.join("contents"), data.join("chunks")];
     while let Some(d) = stack.pop() {
         let Ok(rd) = fs::read_dir(&d) else { continue };
         for e in rd.flatten() {
@@ -2281,6 +2294,47 @@ fn compression_shrinks_compressible_content() {
     );
 }
 
+/// Many small extension-less JSON files sniff as structured text, train a
+/// compression dictionary part-way through, and read back after a remount —
+/// including those compressed against the dictionary.
+#[test_log::test]
+fn dictionary_trained_from_small_files_survives_remount() {
+    let record = |i: usize| -> Vec<u8> {
+        (0..96)
+            .map(|j| {
+                format!(
+                    "{{\"file\":{i},\"line\":{j},\"level\":\"info\",\"msg\":\"block {} validated\"}}\n",
+                    i * 96 + j
+                )
+            })
+            .collect::<String>()
+            .into_bytes()
+    };
+    let data = TempDir::new("backupfs_dict").unwrap();
+    with_backupfs(
+        data.path(),
+        "ohea".to_owned(),
+        |mnt| {
+            for i in 0..200 {
+                fs::write(mnt.join(format!("rec{i}")), record(i)).unwrap();
+            }
+        },
+        None,
+    );
+    let dicts = fs::read_dir(data.path().join("dicts")).unwrap().count();
+    assert_eq!(dicts, 1, "expected one trained dictionary");
+    with_backupfs(
+        data.path(),
+        "ohea".to_owned(),
+        |mnt| {
+            for i in 0..200 {
+                assert_eq!(fs::read(mnt.join(format!("rec{i}"))).unwrap(), record(i));
+            }
+        },
+        None,
+    );
+}
+
 /// Regression for the close()-path packed→blocks crash-consistency blocker.
 ///
 /// When the last fd closes a file that just grew packed→blocks, `close()`
@@ -2530,6 +2584,47 @@ fn superblock_change_password_persists() {
     );
 }
 
+/// A keyfile enrolled in a second slot opens the store on its own, and keeps
+/// opening it after the password slot is revoked; the last slot can't be
+/// removed.
+#[test_log::test]
+fn key_slots_unlock_independently() {
+    use crate::error::BkfsErrorKind;
+    use crate::keyslot::{generate_keyfile, SlotKind};
+    let data = TempDir::new("backupfs_data").unwrap();
+    let keys = TempDir::new("backupfs_keys").unwrap();
+    let keyfile = keys.path().join("recovery.key");
+    let with_keyfile = || BackupFSOptions {
+        keyfile: Some(keyfile.clone()),
+        ..opts(data.path(), "")
+    };
+    {
+        let ctrl = crate::ctrl::Controller::new(opts(data.path(), "ohea")).unwrap();
+        let id = ctrl
+            .add_key_slot(&generate_keyfile(&keyfile).unwrap())
+            .unwrap();
+        assert_eq!(id, 1);
+        assert_eq!(
+            ctrl.key_slots(),
+            [(0, SlotKind::Password), (1, SlotKind::Keyfile)]
+        );
+    }
+    {
+        let ctrl = crate::ctrl::Controller::new(with_keyfile()).unwrap();
+        // Not unlocked with a password, so there is no password to change.
+        assert!(ctrl.change_password("other").is_err());
+        ctrl.remove_key_slot(0).unwrap();
+        assert!(ctrl.remove_key_slot(1).is_err(), "removed the last slot");
+    }
+    let err = open_ctrl_err(data.path(), "ohea");
+    assert!(
+        matches!(err.kind, BkfsErrorKind::BadChecksum),
+        "revoked password must no longer open, got {err:?}"
+    );
+    let ctrl = crate::ctrl::Controller::new(with_keyfile()).unwrap();
+    assert_eq!(ctrl.key_slots(), [(1, SlotKind::Keyfile)]);
+}
+
 /// If both superblock replicas are lost but the data store survives (e.g. an
 /// unreliable backing store dropped the two small superblock files), mounting
 /// must REFUSE rather than mint a fresh superblock with a new key — which would
@@ -2567,3 +2662,343 @@ fn missing_superblock_over_existing_data_is_refused() {
     // And no fresh superblock was created over the data.
     assert!(!data.path().join("superblock").exists());
 }
+
+/// The offline reader sees exactly what was written through the mount —
+/// listing, reading, extracting and tar-exporting — without a FUSE session,
+/// and keeps hard links and symlinks intact on the way out.
+#[test_log::test]
+fn offline_reader_extracts_and_exports() {
+    use std::io::Read;
+    let data = TempDir::new("backupfs_data").unwrap();
+    let mut big = vec![0_u8; (3 << 20) + 17];
+    pattern_fill(0, &mut big);
+    let big_in = big.clone();
+    with_backupfs(
+        data.path(),
+        "ohea".to_owned(),
+        move |mnt| {
+            fs::create_dir(mnt.join("dir")).unwrap();
+            fs::write(mnt.join("dir/small.txt"), b"hello").unwrap();
+            fs::write(mnt.join("dir/big.bin"), &big_in).unwrap();
+            std::os::unix::fs::symlink("small.txt", mnt.join("dir/link")).unwrap();
+            fs::hard_link(mnt.join("dir/small.txt"), mnt.join("hard.txt")).unwrap();
+        },
+        None,
+    );
+
+    let mut bkfs = BackupFS::new(BackupFSOptions {
+        readonly: true,
+        ..opts(data.path(), "ohea")
+    })
+    .unwrap();
+    let reader = bkfs.reader();
+
+    let dir = reader.resolve(Path::new("/dir")).unwrap();
+    let names: Vec<_> = reader
+        .read_dir(&dir)
+        .unwrap()
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    assert_eq!(names, ["big.bin", "link", "small.txt"]);
+
+    let mut read_back = Vec::new();
+    reader
+        .open(&reader.resolve(Path::new("dir/big.bin")).unwrap())
+        .unwrap()
+        .read_to_end(&mut read_back)
+        .unwrap();
+    pattern_check(0, &read_back);
+    assert_eq!(read_back.len(), big.len());
+
+    let out = TempDir::new("backupfs_extract").unwrap();
+    reader.extract(Path::new("/"), out.path()).unwrap();
+    assert_eq!(fs::read(out.path().join("dir/big.bin")).unwrap(), big);
+    assert_eq!(fs::read(out.path().join("dir/small.txt")).unwrap(), b"hello");
+    assert_eq!(
+        fs::read_link(out.path().join("dir/link")).unwrap(),
+        Path::new("small.txt")
+    );
+    assert_eq!(
+        fs::metadata(out.path().join("hard.txt")).unwrap().nlink(),
+        2,
+        "hard link must be recreated, not copied"
+    );
+
+    let archive = reader.export_tar(Path::new("/dir"), Vec::new()).unwrap();
+    let mut archive = tar::Archive::new(archive.as_slice());
+    let mut seen = Vec::new();
+    for entry in archive.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let name = entry.path().unwrap().into_owned();
+        if name == Path::new("dir/big.bin") {
+            let mut bytes = Vec::new();
+            entry.read_to_end(&mut bytes).unwrap();
+            assert_eq!(bytes, big);
+        }
+        seen.push((name, entry.header().entry_type()));
+    }
+    assert_eq!(
+        seen,
+        [
+            (PathBuf::from("dir"), tar::EntryType::Directory),
+            (PathBuf::from("dir/big.bin"), tar::EntryType::Regular),
+            (PathBuf::from("dir/link"), tar::EntryType::Symlink),
+            (PathBuf::from("dir/small.txt"), tar::EntryType::Regular),
+        ]
+    );
+}
+
+/// A snapshot keeps serving the tree as it was while the live tree moves on:
+/// block, packed and spilled-directory content are copied on write rather
+/// than rewritten, and deleting the snapshot reaps exactly what only it held.
+#[test_log::test]
+fn snapshot_is_immutable_and_prunes_cleanly() {
+    use std::io::Read;
+    let data = TempDir::new("backupfs_data").unwrap();
+    let n = 1100usize; // > the 1024 spill threshold → spills
+    let mut big = vec![0_u8; (3 << 20) + 5];
+    pattern_fill(0, &mut big);
+    let big_in = big.clone();
+    with_backupfs(
+        data.path(),
+        "ohea".to_owned(),
+        move |mnt| {
+            fs::write(mnt.join("big.bin"), &big_in).unwrap();
+            fs::write(mnt.join("medium.txt"), vec![b'm'; 64 * 1024]).unwrap();
+            fs::write(mnt.join("gone.txt"), b"deleted later").unwrap();
+            let d = mnt.join("many");
+            fs::create_dir(&d).unwrap();
+            for i in 0..n {
+                fs::write(d.join(format!("f{i:05}")), format!("data {i}")).unwrap();
+            }
+        },
+        None,
+    );
+
+    BackupFS::new(opts(data.path(), "ohea"))
+        .unwrap()
+        .create_snapshot("daily-1")
+        .unwrap();
+
+    with_backupfs(
+        data.path(),
+        "ohea".to_owned(),
+        |mnt| {
+            let mut f = fs::OpenOptions::new()
+                .write(true)
+                .open(mnt.join("big.bin"))
+                .unwrap();
+            f.write_all(&[0xAA; 4096]).unwrap();
+            drop(f);
+            fs::write(mnt.join("medium.txt"), vec![b'n'; 64 * 1024]).unwrap();
+            fs::remove_file(mnt.join("gone.txt")).unwrap();
+            fs::remove_file(mnt.join("many/f00000")).unwrap();
+        },
+        None,
+    );
+
+    fn read(reader: &crate::offline::Reader<'_>, path: &str) -> Vec<u8> {
+        let mut buf = Vec::new();
+        reader
+            .open(&reader.resolve(Path::new(path)).unwrap())
+            .unwrap()
+            .read_to_end(&mut buf)
+            .unwrap();
+        buf
+    }
+    {
+        let mut snap = BackupFS::new(BackupFSOptions {
+            snapshot: Some("daily-1".to_owned()),
+            ..opts(data.path(), "ohea")
+        })
+        .unwrap();
+        let reader = snap.reader();
+        assert_eq!(read(&reader, "big.bin"), big, "snapshot saw a live write");
+        assert_eq!(read(&reader, "medium.txt"), vec![b'm'; 64 * 1024]);
+        assert_eq!(read(&reader, "gone.txt"), b"deleted later");
+        let many = reader.resolve(Path::new("many")).unwrap();
+        assert_eq!(reader.read_dir(&many).unwrap().len(), n);
+        assert_eq!(read(&reader, "many/f00000"), b"data 0");
+    }
+    {
+        let mut live = BackupFS::new(opts(data.path(), "ohea")).unwrap();
+        let reader = live.reader();
+        let live_big = read(&reader, "big.bin");
+        assert_eq!(&live_big[..4096], &[0xAA; 4096][..]);
+        pattern_check(4096, &live_big[4096..]);
+        assert_eq!(read(&reader, "medium.txt"), vec![b'n'; 64 * 1024]);
+        assert!(reader.resolve(Path::new("gone.txt")).is_err());
+        let many = reader.resolve(Path::new("many")).unwrap();
+        assert_eq!(reader.read_dir(&many).unwrap().len(), n - 1);
+    }
+
+    let mut bkfs = BackupFS::new(opts(data.path(), "ohea")).unwrap();
+    assert_eq!(
+        bkfs.list_snapshots()
+            .unwrap()
+            .into_iter()
+            .map(|s| s.name)
+            .collect::<Vec<_>>(),
+        ["daily-1"]
+    );
+    assert!(content_files(data.path()) > 4);
+    assert_eq!(bkfs.prune_snapshots(0).unwrap(), ["daily-1"]);
+    assert!(bkfs.list_snapshots().unwrap().is_empty());
+    drop(bkfs);
+    assert_eq!(
+        content_files(data.path()),
+        4,
+        "only the live big.bin's blocks should remain after pruning"
+    );
+    with_backupfs(
+        data.path(),
+        "ohea".to_owned(),
+        |mnt| {
+            assert_eq!(fs::read_dir(mnt.join("many")).unwrap().count(), n - 1);
+            assert_eq!(fs::read(mnt.join("many/f00001")).unwrap(), b"data 1");
+        },
+        None,
+    );
+}
+
+fn chunk_files(data: &Path) -> usize {
+    tree(data.join("chunks"), false)
+        .map(|v| v.len())
+        .unwrap_or(0)
+}
+
+/// Identical content in two files is stored once, a copy shifted by one byte
+/// shares all but the chunks at block edges, and deleting every file that
+/// references a chunk reclaims it (and its reference count) by unmount.
+#[test_log::test]
+fn dedup_shares_identical_and_shifted_content() {
+    let data = TempDir::new("backupfs_data").unwrap();
+    let mut original = vec![0u8; 4 * 1024 * 1024 + 12345];
+    {
+        use rand::Rng;
+        rand::rand_core::UnwrapErr(rand::rng()).fill_bytes(&mut original);
+    }
+    let mut shifted = vec![0x5A];
+    shifted.extend_from_slice(&original);
+
+    let first = original.clone();
+    with_backupfs(
+        data.path(),
+        "ohea".to_owned(),
+        move |mnt| fs::write(mnt.join("a.img"), &first).unwrap(),
+        None,
+    );
+    let alone = chunk_files(data.path());
+    assert!(alone > 5, "expected several chunks per block, got {alone}");
+
+    let (copy, shift) = (original.clone(), shifted.clone());
+    with_backupfs(
+        data.path(),
+        "ohea".to_owned(),
+        move |mnt| {
+            fs::write(mnt.join("b.img"), &copy).unwrap();
+            fs::write(mnt.join("c.img"), &shift).unwrap();
+        },
+        None,
+    );
+    let shared = chunk_files(data.path());
+    // Only the chunks straddling each of c.img's five block edges are new.
+    assert!(
+        shared < alone + alone / 2,
+        "copies were not deduplicated: {alone} chunks alone, {shared} with copies"
+    );
+
+    with_backupfs(
+        data.path(),
+        "ohea".to_owned(),
+        |mnt| {
+            assert_eq!(fs::read(mnt.join("a.img")).unwrap(), original);
+            assert_eq!(fs::read(mnt.join("b.img")).unwrap(), original);
+            assert_eq!(fs::read(mnt.join("c.img")).unwrap(), shifted);
+            fs::remove_file(mnt.join("a.img")).unwrap();
+            fs::remove_file(mnt.join("c.img")).unwrap();
+        },
+        None,
+    );
+    // b.img still holds every chunk of the original.
+    assert!(chunk_files(data.path()) >= alone);
+
+    with_backupfs(
+        data.path(),
+        "ohea".to_owned(),
+        |mnt| fs::remove_file(mnt.join("b.img")).unwrap(),
+        None,
+    );
+    assert_eq!(content_files(data.path()), 0);
+    assert_eq!(chunk_files(data.path()), 0, "unreferenced chunks not reaped");
+    let ctrl = Controller::new(opts(data.path(), "ohea")).unwrap();
+    assert_eq!(ctrl.chunk_count(), 0, "stale chunk reference counts");
+}
+
+/// Scrub must find rot that reads silently repair, write the healed bytes
+/// back (only when opened read-write), and report damage beyond parity.
+#[test_log::test]
+fn scrub_heals_repairable_rot_and_reports_the_rest() {
+    let data = TempDir::new("backupfs_data").unwrap();
+    let size = 2 * 1024 * 1024usize;
+    let mut payload = vec![0u8; size];
+    pattern_fill(0, &mut payload);
+    with_backupfs(
+        data.path(),
+        "ohea".to_owned(),
+        move |mnt| fs::write(mnt.join("data"), &payload).unwrap(),
+        None,
+    );
+
+    let chunks = tree(data.path().join("chunks"), false).unwrap();
+    assert!(chunks.len() >= 2, "expected several chunks, got {chunks:?}");
+    let healable = data.path().join("chunks").join(&chunks[0]);
+    let lost = data.path().join("chunks").join(&chunks[1]);
+    let replica = data.path().join("superblock.bak1");
+    let originals: Vec<Vec<u8>> = [&healable, &replica]
+        .iter()
+        .map(|p| fs::read(p).unwrap())
+        .collect();
+    // Flip the final byte: damages only the last parity shard.
+    for (path, original) in [&healable, &replica].iter().zip(&originals) {
+        let mut bytes = original.clone();
+        *bytes.last_mut().unwrap() ^= 0x01;
+        fs::write(path, &bytes).unwrap();
+    }
+    // Zero most of another chunk: far more bad shards than parity.
+    let mut bytes = fs::read(&lost).unwrap();
+    let len = bytes.len();
+    bytes[64..len - 64].fill(0);
+    fs::write(&lost, &bytes).unwrap();
+
+    let mut ro = opts(data.path(), "ohea");
+    ro.readonly = true;
+    let report = BackupFS::new(ro).unwrap().scrub().unwrap();
+    assert!(!report.repair);
+    assert_eq!(report.repaired.len(), 2, "{report:?}");
+    assert_eq!(report.unrecoverable.len(), 1, "{report:?}");
+    assert_ne!(fs::read(&healable).unwrap(), originals[0]);
+
+    let report = BackupFS::new(opts(data.path(), "ohea"))
+        .unwrap()
+        .scrub()
+        .unwrap();
+    assert!(report.repair);
+    let mut repaired: Vec<_> = report.repaired.iter().map(|r| r.object.as_str()).collect();
+    repaired.sort_unstable();
+    let chunk = format!("chunks/{}", chunks[0]);
+    assert_eq!(repaired, [chunk.as_str(), "superblock.bak1"]);
+    assert!(report.repaired.iter().all(|r| r.bad_shards == 1));
+    assert_eq!(report.unrecoverable[0].object, format!("chunks/{}", chunks[1]));
+    assert_eq!(fs::read(&healable).unwrap(), originals[0]);
+    assert_eq!(fs::read(&replica).unwrap(), originals[1]);
+
+    let report = BackupFS::new(opts(data.path(), "ohea"))
+        .unwrap()
+        .scrub()
+        .unwrap();
+    assert!(report.repaired.is_empty(), "{report:?}");
+    assert_eq!(report.unrecoverable.len(), 1);
+    assert!(report.checked > 3);
+}
@@ -33,6 +33,12 @@ export abstract class ApiService {
   abstract verifyCifs(
     cifs: T.VerifyCifsParams,
   ): Promise<Record<string, StartOSDiskInfo>> // setup.cifs.verify
+  abstract scanSftpHostKeys(
+    params: T.ScanSftpParams,
+  ): Promise<T.SftpHostKey[]> // setup.sftp.scan-host-keys
+  abstract verifySftp(
+    sftp: T.VerifySftpParams,
+  ): Promise<Record<string, StartOSDiskInfo>> // setup.sftp.verify
 
   // Completion
   abstract complete(): Promise<T.SetupResult> // setup.complete
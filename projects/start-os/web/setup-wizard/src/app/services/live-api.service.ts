@@ -89,6 +89,20 @@ export class LiveApiService extends ApiService {
     })
   }
 
+  async scanSftpHostKeys(params: T.ScanSftpParams) {
+    return this.rpcRequest<T.SftpHostKey[]>({
+      method: 'setup.sftp.scan-host-keys',
+      params,
+    })
+  }
+
+  async verifySftp(source: T.VerifySftpParams) {
+    return this.rpcRequest<Record<string, StartOSDiskInfo>>({
+      method: 'setup.sftp.verify',
+      params: source,
+    })
+  }
+
   async attach(params: T.AttachParams) {
     return this.rpcRequest<T.SetupProgress>({
       method: 'setup.attach',
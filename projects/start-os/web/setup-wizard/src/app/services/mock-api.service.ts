@@ -137,6 +137,27 @@ export class MockApiService extends ApiService {
     }
   }
 
+  async scanSftpHostKeys(params: T.ScanSftpParams): Promise<T.SftpHostKey[]> {
+    await pauseFor(1000)
+    return [
+      {
+        key: 'ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIOMqqnkVzrm0SdG6UOoqKLsabgH5C9okWi0dh2l9GKJl',
+        fingerprint: 'SHA256:+DiY3wvvV6TuJJhbpZisF/zLDA0zPMSvHdkr4UvCOqU',
+      },
+    ]
+  }
+
+  async verifySftp(
+    params: T.VerifySftpParams,
+  ): Promise<Record<string, StartOSDiskInfo>> {
+    return this.verifyCifs({
+      hostname: params.hostname,
+      path: params.path,
+      username: params.username,
+      password: params.password,
+    })
+  }
+
   async attach(params: T.AttachParams): Promise<T.SetupProgress> {
     await pauseFor(1000)
     this.statusIndex = 1 // Jump to running state
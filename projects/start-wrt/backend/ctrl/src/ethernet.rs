@@ -458,9 +458,24 @@ fn set_config(
         .cloned()
         .collect();
 
+    // Ports serving additional WAN uplinks stay out of the bridge, like the WAN port
+    let uplink_ports = crate::wan::uplink_devices(cfgs);
+    if let Some(wan_port) = ethernet
+        .wan_port
+        .as_ref()
+        .filter(|p| uplink_ports.contains(*p))
+    {
+        return Err(Error::new(
+            eyre!("{wan_port} is already a WAN uplink"),
+            ErrorKind::InvalidValue,
+        ));
+    }
+    let is_wan_port =
+        |name: &String| Some(name) == ethernet.wan_port.as_ref() || uplink_ports.contains(name);
+
     bridge.ports.clear();
     for (port_name, port) in &ethernet.ports {
-        if Some(port_name) == ethernet.wan_port.as_ref() {
+        if is_wan_port(port_name) {
             if port.profile.is_some() {
                 return Err(Error::new(
                     eyre!("WAN port cannot have profile: {port_name}"),
@@ -572,7 +587,7 @@ fn set_config(
     for profile in lookup.list() {
         let mut ports = Vec::new();
         for (port_name, port) in &ethernet.ports {
-            if Some(port_name) == ethernet.wan_port.as_ref() {
+            if is_wan_port(port_name) {
                 continue;
             }
             let assigned = port.profile.as_ref() == Some(profile);
@@ -161,7 +161,7 @@ pub async fn ipv4_set<C: CtrlContext>(
         let mut cfgs = parse_all(
             ctx.uci_root(),
             &arena,
-            &["network", "startwrt", "dhcp", "firewall"],
+            &["network", "startwrt", "dhcp", "firewall", "mwan3"],
         )
         .await?;
 
@@ -254,6 +254,9 @@ pub async fn ipv4_set<C: CtrlContext>(
             Default::default()
         };
 
+        // Uplink pins match on profile subnets, which may have just moved
+        crate::wan::rewrite_mwan3(&mut cfgs)?;
+
         let dump_result = dump_all(ctx.uci_root(), cfgs).await;
         drop(arena);
         match dump_result {
@@ -310,6 +313,7 @@ pub async fn ipv4_set<C: CtrlContext>(
                             .cloned(),
                     );
                     restart_network_services(address, ifaces).await;
+                    crate::wan::restart_mwan3().await;
                     removed_vpns.apply_post_reload().await;
                 }
                 return Ok(());
@@ -23,6 +23,14 @@ use crate::utils::{DeserializeStdin, HandlerExtSerde};
 use crate::CtrlContext;
 
 pub const DEFAULT_WAN_ZONE: &str = "wan";
+
+/// True for outbounds that leave through the WAN zone: `"wan"` itself (every
+/// uplink, per the mwan3 policy) or a `wan:<uplink>` pin. Anything else is an
+/// outbound VPN client's interface.
+pub(crate) fn is_wan_outbound(outbound: &str) -> bool {
+    outbound == DEFAULT_WAN_ZONE || crate::wan::pinned_uplink(outbound).is_some()
+}
+
 /// Max length of a generated profile interface id. The binding constraint is the
 /// WiFi VLAN netdev OpenWrt derives for a profile's `wifi-vlan`:
 /// `<ap_ifname>-<iface>` (e.g. `phy0-ap0-<iface>`; see `iface_vlan` in
@@ -239,7 +247,9 @@ fn get_vpn_dns(cfgs: &Configs, vpn_interface: &str) -> Vec<String> {
 fn compute_dns_source(cfgs: &Configs, profile: &Profile) -> String {
     if !profile.dns_override.is_empty() {
         "custom".into()
-    } else if profile.outbound != "wan" && !get_vpn_dns(cfgs, &profile.outbound).is_empty() {
+    } else if !is_wan_outbound(&profile.outbound)
+        && !get_vpn_dns(cfgs, &profile.outbound).is_empty()
+    {
         "vpn".into()
     } else {
         "system".into()
@@ -249,7 +259,7 @@ fn compute_dns_source(cfgs: &Configs, profile: &Profile) -> String {
 /// Returns true if the profile has non-system DNS (custom override or VPN DNS).
 fn has_effective_dns(cfgs: &Configs, profile: &Profile) -> bool {
     !profile.dns_override.is_empty()
-        || (profile.outbound != "wan" && !get_vpn_dns(cfgs, &profile.outbound).is_empty())
+        || (!is_wan_outbound(&profile.outbound) && !get_vpn_dns(cfgs, &profile.outbound).is_empty())
 }
 
 /// Rewrite DNS forwarding (dnsmasq sections) for ALL profiles.
@@ -375,7 +385,7 @@ pub(crate) fn rewrite_dns_forwarding(cfgs: &mut Configs, profile: &Profile) -> R
         // Custom DNS: route through SmartDNS profile group
         let port = dns::smartdns_port_for_vlan(profile.id.vlan_tag);
         vec![format!("127.0.0.1#{}", port)]
-    } else if profile.outbound != "wan" {
+    } else if !is_wan_outbound(&profile.outbound) {
         // VPN DNS: use dnsmasq server IP@interface directly
         let vpn_dns = get_vpn_dns(cfgs, &profile.outbound);
         if vpn_dns.is_empty() {
@@ -582,7 +592,9 @@ pub async fn delete<C: CtrlContext>(ctx: C, id: ProfileIdOpt) -> Result<(), Erro
         let mut cfgs = parse_all(
             ctx.uci_root(),
             &arena,
-            &["startwrt", "network", "firewall", "dhcp", "wireless"],
+            &[
+                "startwrt", "network", "firewall", "dhcp", "wireless", "mwan3",
+            ],
         )
         .await?;
         if let Err(e) = delete_config(ctx.clone(), &mut cfgs, &id) {
@@ -595,6 +607,8 @@ pub async fn delete<C: CtrlContext>(ctx: C, id: ProfileIdOpt) -> Result<(), Erro
             );
             return Err(e);
         }
+        // Drop the deleted profile's uplink pin, if it had one
+        crate::wan::rewrite_mwan3(&mut cfgs)?;
         let dump_result = dump_all(ctx.uci_root(), cfgs).await;
         drop(arena);
         match dump_result {
@@ -896,6 +910,9 @@ async fn reload_system_inner(restart_network: bool) -> Result<(), Error> {
     let _ =
         crate::run_quiet_async(tokio::process::Command::new("/etc/init.d/dnsmasq").arg("restart"))
             .await;
+    // After network, so mwan3 re-tracks the restarted uplinks and picks up a
+    // regenerated /etc/config/mwan3 (profile pins, uplink changes)
+    crate::wan::restart_mwan3().await;
     // Re-apply WAN schedules — firewall restart rebuilds the nftables ruleset
     reapply_schedules_after_reload().await;
     Ok(())
@@ -942,6 +959,9 @@ async fn reload_system_and_wifi_inner(restart_network: bool) -> Result<(), Error
     let _ =
         crate::run_quiet_async(tokio::process::Command::new("/etc/init.d/dnsmasq").arg("restart"))
             .await;
+    // After network, so mwan3 re-tracks the restarted uplinks and picks up a
+    // regenerated /etc/config/mwan3 (profile pins, uplink changes)
+    crate::wan::restart_mwan3().await;
     // Re-apply WAN schedules — firewall restart rebuilds the nftables ruleset
     reapply_schedules_after_reload().await;
     Ok(())
@@ -1037,7 +1057,7 @@ pub async fn set<C: CtrlContext>(
         let mut cfgs = parse_all(
             ctx.uci_root(),
             &arena,
-            &["startwrt", "network", "firewall", "dhcp"],
+            &["startwrt", "network", "firewall", "dhcp", "mwan3"],
         )
         .await?;
 
@@ -1061,6 +1081,9 @@ pub async fn set<C: CtrlContext>(
                 return Err(e);
             }
         };
+        // The outbound may have moved to or from an uplink pin, and pins
+        // match on the profile's subnet
+        crate::wan::rewrite_mwan3(&mut cfgs)?;
 
         // Detect admin IP change and propagate block changes to sibling profiles
         let admin_ip_changed =
@@ -1153,6 +1176,7 @@ pub async fn set<C: CtrlContext>(
                     if admin_ip_changed {
                         crate::lan::restart_network_services(profile.gateway_ip, restart_ifaces)
                             .await;
+                        crate::wan::restart_mwan3().await;
                     } else {
                         // Full network restart (not reload) so an outbound change
                         // that flips IPv6 eligibility re-runs netifd prefix
@@ -1477,7 +1501,9 @@ fn set_config<C: CtrlContext>(
 /// vpn_<wg>`) is rebuilt as `<zone> → wan`. Mirrors the rewrite sequence at the
 /// tail of `set_config`; the VPN delete/disable paths previously ran only
 /// `rewrite_routing` + `rewrite_dns_forwarding`, leaving the profile with no
-/// `→ wan` forwarding (fw4 then dropped all of its WAN traffic).
+/// `→ wan` forwarding (fw4 then dropped all of its WAN traffic). A non-admin
+/// profile's `ip6assign` is re-gated on the new outbound too, as in
+/// `set_config`, so a restart hands it a /64 again (see `reload_system_full`).
 pub(crate) fn reapply_profile_config<C: CtrlContext>(
     ctx: &C,
     cfgs: &mut Configs,
@@ -1496,6 +1522,21 @@ pub(crate) fn reapply_profile_config<C: CtrlContext>(
             }
         })
         .collect();
+    if !profile.owns_lan {
+        let ipv6 = is_ipv6_enabled(cfgs) && outbound_supports_ipv6(cfgs, &profile.outbound);
+        let ip6assign = ipv6.then(|| "64".to_string());
+        for section in &mut cfgs["network"].sections {
+            if section.name().as_deref() == Some(profile.id.interface.as_str()) {
+                if let Some(mut iface) = section.get_typed::<NetworkInterface>()? {
+                    if iface.ip6assign != ip6assign {
+                        iface.ip6assign = ip6assign;
+                        section.set(&iface)?;
+                    }
+                }
+                break;
+            }
+        }
+    }
     rewrite_firewall(ctx, cfgs, &profile, &all_interfaces, &[], false)?;
     rewrite_dhcp(ctx, cfgs, &profile)?;
     rewrite_dns_forwarding(cfgs, &profile)?;
@@ -1531,7 +1572,9 @@ pub async fn create<C: CtrlContext>(
         let mut cfgs = parse_all(
             ctx.uci_root(),
             &arena,
-            &["startwrt", "network", "firewall", "dhcp", "wireless"],
+            &[
+                "startwrt", "network", "firewall", "dhcp", "wireless", "mwan3",
+            ],
         )
         .await?;
         let out = match create_config(
@@ -1552,6 +1595,7 @@ pub async fn create<C: CtrlContext>(
                 return Err(e);
             }
         };
+        crate::wan::rewrite_mwan3(&mut cfgs)?;
         let dump_result = dump_all(ctx.uci_root(), cfgs).await;
         drop(arena);
         match dump_result {
@@ -2127,13 +2171,19 @@ pub(crate) fn is_ipv6_enabled(cfgs: &Configs) -> bool {
 }
 
 /// Check whether an outbound VPN interface has IPv6 addresses configured.
-/// If the outbound is "wan" or the VPN has at least one IPv6 address, returns true.
+/// If the outbound is `"wan"` or the VPN has at least one IPv6 address,
+/// returns true.
 /// If the VPN has no IPv6 addresses, returns false (IPv6 would leak outside the tunnel).
+/// A `wan:<uplink>` pin returns false too: mwan3 only pins IPv4, and the
+/// profile's IPv6 would leave through wan6 whatever the pin.
 pub(crate) fn outbound_supports_ipv6(cfgs: &Configs, outbound: &str) -> bool {
     use crate::vpn_server::WgInterface;
-    if outbound == "wan" {
+    if outbound == DEFAULT_WAN_ZONE {
         return true;
     }
+    if crate::wan::pinned_uplink(outbound).is_some() {
+        return false;
+    }
     cfgs["network"]
         .sections
         .iter()
@@ -2158,7 +2208,7 @@ pub fn rewrite_dhcp(
     // so LAN clients have no IPv6 default route and traffic never reaches the
     // policy-routing layer. `wan` keeps odhcpd's default behavior (mode 2 —
     // advertise only if wan6 has a default), so we don't override there.
-    let ra_default_value = if ipv6 && profile.outbound != "wan" {
+    let ra_default_value = if ipv6 && !is_wan_outbound(&profile.outbound) {
         Some("1".to_string())
     } else {
         None
@@ -2251,8 +2301,14 @@ pub(crate) fn rewrite_routing(
         .sections
         .retain(|s| s.name().as_deref() != Some(mark_rule_name.as_str()));
 
-    // 2. If outbound is "wan", main table suffices — no policy routing needed
-    if profile.outbound == "wan" {
+    // 2. If outbound is WAN, main table suffices — no policy routing needed
+    //    (uplink choice, including a `wan:<uplink>` pin, is mwan3's job). A pin
+    //    only holds for IPv4, so a pinned profile's v6 fails closed instead.
+    if is_wan_outbound(&profile.outbound) {
+        if crate::wan::pinned_uplink(&profile.outbound).is_some() {
+            append_v6_policy_routing(cfgs, profile, None)?;
+            ensure_dnat_return6_rule(cfgs)?;
+        }
         // Clean up any stale VPN peer routes from when this profile used a VPN outbound
         crate::vpn_server::sync_peer_policy_routes(cfgs, &profile.id.interface)?;
         return Ok(());
@@ -2340,27 +2396,75 @@ pub(crate) fn rewrite_routing(
     // 5b. IPv6 policy routing. Installed for EVERY VPN-routed profile (mirrors
     //     the always-on v4 fallback above), independent of whether IPv6 is
     //     globally served — so v6 fails closed in all states (global v6 off,
-    //     v4-only VPN, tunnel interface down). We can't mirror IPv4's
-    //     `src=<prefix>` matcher because LAN /64s are dynamic under DHCPv6-PD,
-    //     so two rule6 sections instead:
-    //       * `prl6_<iface>`: lookup main, suppress_prefixlength=0 — match any
-    //         specific route (e.g. on-link /64 to a sibling LAN) but fall
-    //         through on default-route-only matches. Lets cross-VLAN and
-    //         link-local traffic stay local instead of being captured by the
-    //         per-VLAN table's ::/0 → VPN entry.
-    //         NOTE (v4/v6 asymmetry, accepted): this escape can also reach the
-    //         WAN interface's own on-link /64 (e.g. an upstream ULA/GUA segment)
-    //         directly, bypassing the tunnel. It only ever permits on-link
-    //         destinations — public-internet traffic has no specific main-table
-    //         route, so it is always captured by `prr6_` below (tunnel or
-    //         unreachable) and never leaks. The v4 path has no equivalent
-    //         because its per-VLAN table is seeded with explicit local/sibling
-    //         routes (plr_/pxr_). This matches upstream `pbr`'s escape rule.
-    //       * `prr6_<iface>`: lookup <vlan_tag>, the per-VLAN table holding
-    //         the VPN default route (or the unreachable kill switch).
-    //     netifd matches `in: <logical_iface>` against logical interface names
-    //     (iprule.c:194) and substitutes the kernel netdev at install time
-    //     (iprule.c:134), so we pass the profile interface name directly.
+    //     v4-only VPN, tunnel interface down).
+    append_v6_policy_routing(
+        cfgs,
+        profile,
+        vpn_has_v6.then_some(profile.outbound.as_str()),
+    )?;
+
+    // 6. DNAT-return marking: replies to inbound port-forwarded connections must
+    //    route via the main table, not the VPN tunnel. The marks are set by
+    //    static nftables chains (fw4 has no UCI option for `ct status dnat` /
+    //    connmark matching); here we only ensure the matching ip rules.
+    //      * IPv4: 10-startwrt-dnat-mark.nft marks `ct status dnat` packets;
+    //        dnat_return routes fwmark 0x80 -> main.
+    //      * IPv6: 11-startwrt-inbound6-mark.nft connection-marks WAN-initiated
+    //        flows (v6 port-forwards aren't DNAT'd, so there's no dnat status to
+    //        key on); dnat_return6 routes fwmark 0x80 -> main, ahead of prl6_/
+    //        prr6_, so a published-port reply leaves via wan6 instead of the VPN.
+    ensure_dnat_return_rule(cfgs)?;
+    ensure_dnat_return6_rule(cfgs)?;
+
+    // 7. Ensure a dedicated firewall zone exists for the VPN outbound, carrying
+    //    masq=1/masq6=1. The per-profile forwardings created by rewrite_firewall
+    //    target this zone (not wan), so NAT (incl. NAT66) applies on the VPN
+    //    egress while wan6's GUA path and inbound port-forwards stay intact.
+    ensure_vpn_outbound_zone(cfgs, &profile.outbound)?;
+
+    // 8. Add /32 peer routes so locally-generated responses (DNS, HTTP) reach
+    //    VPN clients via wg_X instead of being caught by the /24 subnet route
+    crate::vpn_server::sync_peer_policy_routes(cfgs, &profile.id.interface)?;
+
+    Ok(())
+}
+
+/// IPv6 policy routing for a profile whose v6 must never fall through to wan6:
+/// every VPN-routed profile, and every profile pinned to one WAN uplink (mwan3
+/// steers IPv4 only, so a pin can't be honoured for v6 and fails closed
+/// instead). `via` is the tunnel the per-VLAN default points at, for a VPN
+/// that carries v6; without it the unreachable fallback is the only default.
+///
+/// We can't mirror IPv4's `src=<prefix>` matcher because LAN /64s are dynamic
+/// under DHCPv6-PD, so two rule6 sections instead:
+///   * `prl6_<iface>`: lookup main, suppress_prefixlength=0 — match any
+///     specific route (e.g. on-link /64 to a sibling LAN) but fall through on
+///     default-route-only matches. Lets cross-VLAN and link-local traffic stay
+///     local instead of being captured by the per-VLAN table's ::/0 entry.
+///     NOTE (v4/v6 asymmetry, accepted): this escape can also reach the WAN
+///     interface's own on-link /64 (e.g. an upstream ULA/GUA segment)
+///     directly, bypassing the tunnel. It only ever permits on-link
+///     destinations — public-internet traffic has no specific main-table
+///     route, so it is always captured by `prr6_` below (tunnel or
+///     unreachable) and never leaks. The v4 path has no equivalent because its
+///     per-VLAN table is seeded with explicit local/sibling routes
+///     (plr_/pxr_). This matches upstream `pbr`'s escape rule.
+///   * `prr6_<iface>`: lookup <vlan_tag>, the per-VLAN table holding the VPN
+///     default route (or the unreachable kill switch).
+///
+/// netifd matches `in: <logical_iface>` against logical interface names
+/// (iprule.c:194) and substitutes the kernel netdev at install time
+/// (iprule.c:134), so we pass the profile interface name directly.
+fn append_v6_policy_routing(
+    cfgs: &mut Configs,
+    profile: &Profile,
+    via: Option<&str>,
+) -> Result<(), Error> {
+    let route6_name = format!("prt6_{}", profile.id.interface);
+    let route6_block_name = format!("prt6b_{}", profile.id.interface);
+    let local6_rule_name = format!("prl6_{}", profile.id.interface);
+    let rule6_name = format!("prr6_{}", profile.id.interface);
+
     cfgs["network"].append(
         &NetworkRule6 {
             in_iface: Some(profile.id.interface.clone()),
@@ -2382,10 +2486,10 @@ pub(crate) fn rewrite_routing(
     )?;
 
     // When the VPN carries v6, the per-VLAN default points at the tunnel.
-    if vpn_has_v6 {
+    if let Some(via) = via {
         cfgs["network"].append(
             &NetworkRoute6 {
-                interface: profile.outbound.clone(),
+                interface: via.to_string(),
                 target: "::/0".to_string(),
                 metric: Some(VPN_DEFAULT_ROUTE_METRIC),
                 table: Some(profile.id.vlan_tag as u32),
@@ -2396,10 +2500,10 @@ pub(crate) fn rewrite_routing(
     }
 
     // IPv6 kill switch / fail-closed fallback: an `unreachable` default in the
-    // per-VLAN table, attached to loopback at a higher metric. For a v4-only VPN
-    // it is the only default (v6 is always blocked); for a v6-capable VPN it
-    // backstops the `dev <wg>` route when the tunnel interface goes down — so v6
-    // never falls through to wan6.
+    // per-VLAN table, attached to loopback at a higher metric. Without `via`
+    // it is the only default (v6 is always blocked); with one it backstops
+    // the `dev <wg>` route when the tunnel interface goes down — so v6 never
+    // falls through to wan6.
     cfgs["network"].append(
         &NetworkRoute6 {
             interface: "loopback".to_string(),
@@ -2411,30 +2515,6 @@ pub(crate) fn rewrite_routing(
         },
         Some(&route6_block_name),
     )?;
-
-    // 6. DNAT-return marking: replies to inbound port-forwarded connections must
-    //    route via the main table, not the VPN tunnel. The marks are set by
-    //    static nftables chains (fw4 has no UCI option for `ct status dnat` /
-    //    connmark matching); here we only ensure the matching ip rules.
-    //      * IPv4: 10-startwrt-dnat-mark.nft marks `ct status dnat` packets;
-    //        dnat_return routes fwmark 0x80 -> main.
-    //      * IPv6: 11-startwrt-inbound6-mark.nft connection-marks WAN-initiated
-    //        flows (v6 port-forwards aren't DNAT'd, so there's no dnat status to
-    //        key on); dnat_return6 routes fwmark 0x80 -> main, ahead of prl6_/
-    //        prr6_, so a published-port reply leaves via wan6 instead of the VPN.
-    ensure_dnat_return_rule(cfgs)?;
-    ensure_dnat_return6_rule(cfgs)?;
-
-    // 7. Ensure a dedicated firewall zone exists for the VPN outbound, carrying
-    //    masq=1/masq6=1. The per-profile forwardings created by rewrite_firewall
-    //    target this zone (not wan), so NAT (incl. NAT66) applies on the VPN
-    //    egress while wan6's GUA path and inbound port-forwards stay intact.
-    ensure_vpn_outbound_zone(cfgs, &profile.outbound)?;
-
-    // 8. Add /32 peer routes so locally-generated responses (DNS, HTTP) reach
-    //    VPN clients via wg_X instead of being caught by the /24 subnet route
-    crate::vpn_server::sync_peer_policy_routes(cfgs, &profile.id.interface)?;
-
     Ok(())
 }
 
@@ -2525,14 +2605,14 @@ pub(crate) fn sync_cross_subnet_routes(cfgs: &mut Configs) -> Result<(), Error>
         .sections
         .retain(|s| !s.name().as_deref().map_or(false, |n| n.starts_with("pxr_")));
 
-    // 2. Collect profiles with VPN routing (outbound != "wan")
+    // 2. Collect profiles with VPN routing (non-WAN outbound)
     let vpn_profiles: Vec<(String, u16)> = cfgs["startwrt"]
         .sections
         .iter()
         .filter_map(|s| {
             let p = s.get::<UciProfile>().ok()?;
             let outbound = p.outbound.as_deref().unwrap_or("wan");
-            if outbound != "wan" {
+            if !is_wan_outbound(outbound) {
                 Some((p.interface.clone(), p.vlan_tag))
             } else {
                 None
@@ -2586,14 +2666,14 @@ pub(crate) fn sync_cross_subnet_routes(cfgs: &mut Configs) -> Result<(), Error>
 }
 
 /// The firewall zone name for a profile's outbound:
-///   - `"wan"` when outbound is `"wan"`.
+///   - `"wan"` when outbound is `"wan"` or a `wan:<uplink>` pin.
 ///   - `"vpn_<wg_name>"` (a dedicated NAT66-enabled zone) otherwise.
 ///
 /// Used in `rewrite_firewall`/`evaluate_and_apply_schedules` to compute
 /// forwarding/rule `dest` fields and in `ensure_vpn_outbound_zone` to manage
 /// the zone lifecycle.
 pub(crate) fn resolve_outbound_zone(outbound: &str) -> String {
-    if outbound == DEFAULT_WAN_ZONE {
+    if is_wan_outbound(outbound) {
         DEFAULT_WAN_ZONE.to_string()
     } else {
         format!("vpn_{outbound}")
@@ -2693,7 +2773,7 @@ pub(crate) fn cleanup_orphaned_vpn_zones(cfgs: &mut Configs) {
         }
         if let Ok(profile) = section.get::<UciProfile>() {
             let outbound = profile.outbound.unwrap_or_else(|| "wan".to_string());
-            if outbound != "wan" {
+            if !is_wan_outbound(&outbound) {
                 referenced_vpns.insert(outbound);
             }
         }
@@ -6936,6 +7016,90 @@ config dhcp 'guest'
         assert_eq!(ks.interface, "loopback");
     }
 
+    #[tokio::test]
+    async fn test_rewrite_routing_fails_v6_closed_for_uplink_pin() {
+        // mwan3 pins only IPv4, so a profile pinned to one uplink gets no v6
+        // and the kill switch, instead of leaving through wan6.
+        let dir = tempfile::tempdir().unwrap();
+        let ctx = TestContext(dir.path().to_path_buf());
+        setup_configs_with_ipv6_vpn(dir.path());
+        let mut network = std::fs::read_to_string(dir.path().join("network")).unwrap();
+        network.push_str(
+            "\n\
+config interface 'wan'
+\toption device 'eth1'
+\toption proto 'dhcp'
+",
+        );
+        std::fs::write(dir.path().join("network"), network).unwrap();
+
+        let arena = Arena::new();
+        let mut cfgs = parse_all(
+            ctx.uci_root(),
+            &arena,
+            &["startwrt", "network", "firewall", "dhcp"],
+        )
+        .await
+        .unwrap();
+
+        let profile_pinned = Profile {
+            id: ProfileIdOpt {
+                fullname: Some("Guest".into()),
+                interface: Some("guest".into()),
+                vlan_tag: Some(101),
+            },
+            gateway_ip: Ipv4Addr::new(192, 168, 101, 1),
+            outbound: "wan:wan".into(),
+            lan_access: LanAccess::SameProfile,
+            wan_access: WanAccess::All,
+            dns_override: Vec::new(),
+            dns_source: String::new(),
+            access_to_new_profiles: false,
+            owns_lan: false,
+        };
+        set_config(ctx.clone(), &mut cfgs, &profile_pinned).unwrap();
+
+        let has = |cfgs: &Configs, name: &str| {
+            cfgs["network"]
+                .sections
+                .iter()
+                .any(|s| s.name().as_deref() == Some(name))
+        };
+        for name in ["prl6_guest", "prr6_guest", "prt6b_guest"] {
+            assert!(has(&cfgs, name), "{name} should exist for a pinned profile");
+        }
+        // IPv4 stays with the main table (and mwan3); nothing points v6 anywhere
+        for name in ["prt_guest", "prr_guest", "prt6_guest"] {
+            assert!(
+                !has(&cfgs, name),
+                "{name} should not exist for a pinned profile"
+            );
+        }
+        let dhcp_guest = |cfgs: &Configs| {
+            cfgs["dhcp"]
+                .sections
+                .iter()
+                .find(|s| s.name().as_deref() == Some("guest"))
+                .and_then(|s| s.get::<Dhcp>().ok())
+                .expect("dhcp 'guest' should exist")
+        };
+        assert_eq!(dhcp_guest(&cfgs).ra.as_deref(), Some("disabled"));
+
+        // Back on the shared policy, v6 is served and routed normally again
+        let profile_wan = Profile {
+            outbound: "wan".into(),
+            ..profile_pinned
+        };
+        set_config(ctx, &mut cfgs, &profile_wan).unwrap();
+        for name in ["prl6_guest", "prr6_guest", "prt6b_guest"] {
+            assert!(
+                !has(&cfgs, name),
+                "{name} should be removed on switching to wan"
+            );
+        }
+        assert_eq!(dhcp_guest(&cfgs).ra.as_deref(), Some("server"));
+    }
+
     #[tokio::test]
     async fn rewrite_dhcp_preserves_admin_lan_ra_on_v4_only_outbound() {
         // Regression: editing the LAN-owning (admin) profile onto a v4-only
@@ -191,7 +191,8 @@ fn wg_interface(cfgs: &Configs, name: &str) -> Option<WgInterface> {
 
 /// Reject a profile outbound that isn't a usable outbound VPN client.
 ///
-/// `"wan"` (direct) always passes. Anything else must be an interface `list`
+/// `"wan"` (direct) always passes, and a `wan:<uplink>` pin passes if that
+/// WAN uplink exists. Anything else must be an interface `list`
 /// reports as `enabled`, so the RPC/CLI can't select an outbound the UI never
 /// offers. Both failures blackhole the profile silently otherwise: an unknown
 /// interface still gets a `vpn_<X>` zone and a policy route pointing at
@@ -208,6 +209,9 @@ pub(crate) fn guard_outbound_available(cfgs: &Configs, outbound: &str) -> Result
     if outbound == crate::profiles::DEFAULT_WAN_ZONE {
         return Ok(());
     }
+    if let Some(uplink) = crate::wan::pinned_uplink(outbound) {
+        return crate::wan::guard_uplink_exists(cfgs, uplink);
+    }
 
     let meta = cfgs["startwrt"]
         .sections
@@ -596,7 +600,7 @@ fn validate_mtu(mtu: u32) -> Result<(), Error> {
 }
 
 /// Validate a label is non-empty and safe for UCI
-fn validate_label(label: &str) -> Result<(), Error> {
+pub(crate) fn validate_label(label: &str) -> Result<(), Error> {
     let trimmed = label.trim();
     if trimmed.is_empty() || trimmed.chars().any(|c| c < '\x20') {
         return Err(Error::new(
@@ -481,7 +481,7 @@ pub(crate) fn sync_peer_policy_routes(
     };
 
     let outbound = profile.outbound.unwrap_or_else(|| "wan".to_string());
-    if outbound == "wan" {
+    if crate::profiles::is_wan_outbound(&outbound) {
         return Ok(());
     }
 
@@ -583,7 +583,7 @@ pub(crate) fn sync_vpn_peer_cross_routes(cfgs: &mut Configs) -> Result<(), Error
         .iter()
         .filter_map(|s| {
             let p = s.get::<UciProfile>().ok()?;
-            if p.outbound.as_deref().unwrap_or("wan") != "wan" {
+            if !crate::profiles::is_wan_outbound(p.outbound.as_deref().unwrap_or("wan")) {
                 Some((p.interface, p.vlan_tag))
             } else {
                 None
@@ -1,13 +1,20 @@
+use std::collections::HashSet;
+use std::net::Ipv4Addr;
+
+use imbl_value::Value;
 use rpc_toolkit::{from_fn_async_local, ParentHandler};
 use serde::{Deserialize, Serialize};
 use uciedit::openwrt::{
-    DdnsService, InterfaceProto, NetworkDevice, NetworkInterface, UciSystemDns,
+    DdnsService, DeviceType, FirewallZone, InterfaceProto, Mwan3Globals, Mwan3Interface,
+    Mwan3Member, Mwan3Policy, Mwan3Rule, NetworkBridgeVlan, NetworkDevice, NetworkInterface,
+    UciSystemDns,
 };
-use uciedit::{dump_all, parse_all, Arena};
+use uciedit::{dump_all, parse_all, Arena, Configs, TypedSection};
 
 use crate::dns::{self, DnsServer};
 use crate::invoke::Invoke;
 use crate::prelude::*;
+use crate::profiles::{UciProfile, DEFAULT_WAN_ZONE};
 use crate::system::{get_wan_ipv6s, has_global_ipv6};
 use crate::utils::{DeserializeStdin, HandlerExtSerde};
 use crate::{profiles, CtrlContext};
@@ -164,6 +171,8 @@ pub struct WanDdnsResponse {
     pub password: Option<String>,
     pub token: Option<String>,
     pub zone: Option<String>,
+    /// The uplink whose address is published; None follows the active uplink
+    pub uplink: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -175,6 +184,172 @@ pub struct WanDdnsSetRequest {
     pub password: Option<String>,
     pub token: Option<String>,
     pub zone: Option<String>,
+    /// Pin DDNS to one uplink; None follows whichever uplink mwan3 prefers
+    #[serde(default)]
+    pub uplink: Option<String>,
+}
+
+// ── Uplink types ────────────────────────────────────────────
+
+/// Prefix of a profile outbound that pins the profile to one uplink
+/// (`wan:<uplink id>`). A plain `"wan"` outbound follows the shared policy.
+pub const WAN_PIN_PREFIX: &str = "wan:";
+/// Uplink interfaces are `wan_<sanitized label>`. Linux enforces IFNAMSIZ = 16
+/// (15 chars + NUL) and mwan3 builds its chain names from the interface name.
+const UPLINK_PREFIX: &str = "wan_";
+const MAX_UPLINK_NAME_LEN: usize = 15;
+const DEFAULT_UPLINK_LABEL: &str = "Primary WAN";
+const DEFAULT_PRIMARY_PRIORITY: u32 = 1;
+const DEFAULT_UPLINK_PRIORITY: u32 = 2;
+const DEFAULT_UPLINK_WEIGHT: u32 = 1;
+const MAX_UPLINK_PRIORITY: u32 = 256;
+const MAX_UPLINK_WEIGHT: u32 = 1000;
+const DEFAULT_PROBE_TARGETS: [&str; 2] = ["1.1.1.1", "8.8.8.8"];
+const DEFAULT_PROBE_INTERVAL: u32 = 5;
+const DEFAULT_PROBE_DOWN: u32 = 3;
+const DEFAULT_PROBE_UP: u32 = 3;
+/// Uplink default routes share the main table, so each needs its own metric:
+/// the primary gets `UPLINK_METRIC_STEP`, the next uplink twice that, and so on.
+const UPLINK_METRIC_STEP: u32 = 10;
+
+/// Uplink metadata stored in /etc/config/startwrt, named by the uplink's
+/// netifd interface. The primary (`wan`) has no section until its settings
+/// are first edited; until then it reads back with the defaults.
+#[derive(Debug, TypedSection)]
+#[uci(ty = "wan_uplink")]
+struct UciWanUplink {
+    pub interface: String,
+    pub label: String,
+    #[uci(default_value = DEFAULT_UPLINK_PRIORITY)]
+    pub priority: u32,
+    #[uci(default_value = DEFAULT_UPLINK_WEIGHT)]
+    pub weight: u32,
+    #[uci(default)]
+    pub probe_target: Vec<String>,
+    #[uci(default_value = DEFAULT_PROBE_INTERVAL)]
+    pub probe_interval: u32,
+    #[uci(default_value = DEFAULT_PROBE_DOWN)]
+    pub probe_down: u32,
+    #[uci(default_value = DEFAULT_PROBE_UP)]
+    pub probe_up: u32,
+    /// The device was taken out of the LAN bridge to become this uplink, and
+    /// goes back into it when the uplink is deleted
+    #[uci(default_value = false)]
+    pub lan_port: bool,
+}
+
+impl UciWanUplink {
+    fn primary() -> Self {
+        UciWanUplink {
+            interface: WAN_INTERFACE.to_string(),
+            label: DEFAULT_UPLINK_LABEL.to_string(),
+            priority: DEFAULT_PRIMARY_PRIORITY,
+            weight: DEFAULT_UPLINK_WEIGHT,
+            probe_target: vec![],
+            probe_interval: DEFAULT_PROBE_INTERVAL,
+            probe_down: DEFAULT_PROBE_DOWN,
+            probe_up: DEFAULT_PROBE_UP,
+            lan_port: false,
+        }
+    }
+
+    fn probe(&self) -> WanProbe {
+        WanProbe {
+            targets: if self.probe_target.is_empty() {
+                DEFAULT_PROBE_TARGETS.map(String::from).to_vec()
+            } else {
+                self.probe_target.clone()
+            },
+            interval: self.probe_interval,
+            down: self.probe_down,
+            up: self.probe_up,
+        }
+    }
+
+    fn set_probe(&mut self, probe: WanProbe) {
+        self.probe_target = probe.targets;
+        self.probe_interval = probe.interval;
+        self.probe_down = probe.down;
+        self.probe_up = probe.up;
+    }
+}
+
+/// Health probing for an uplink: it is marked offline after `down` failed
+/// rounds of pings to `targets`, one round every `interval` seconds, and
+/// online again after `up` successful rounds.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WanProbe {
+    pub targets: Vec<String>,
+    pub interval: u32,
+    pub down: u32,
+    pub up: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WanUplinkStatus {
+    Online,
+    Offline,
+    Connecting,
+    Disabled,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WanUplink {
+    /// The uplink's netifd interface: `wan` for the primary, `wan_<label>` otherwise
+    pub id: String,
+    pub label: String,
+    pub primary: bool,
+    pub ipv4: WanIpv4Response,
+    /// mwan3 member metric: traffic uses the online uplinks with the lowest
+    /// priority, and fails over to the next priority when they all go offline
+    pub priority: u32,
+    /// Share of traffic among online uplinks of equal priority
+    pub weight: u32,
+    pub probe: WanProbe,
+    /// None when not running on the router
+    pub status: Option<WanUplinkStatus>,
+    /// Profiles pinned to this uplink (outbound `wan:<id>`)
+    pub used_by: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WanUplinkCreateRequest {
+    pub label: String,
+    /// Kernel device, e.g. "eth2" or "usb0". A LAN port is taken out of the
+    /// LAN bridge for as long as the uplink exists.
+    pub device: String,
+    /// `device` is ignored here; the top-level `device` is used in every mode
+    pub ipv4: WanIpv4SetRequest,
+    #[serde(default)]
+    pub priority: Option<u32>,
+    #[serde(default)]
+    pub weight: Option<u32>,
+    #[serde(default)]
+    pub probe: Option<WanProbe>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WanUplinkCreateResponse {
+    pub id: String,
+}
+
+/// Replaces an uplink's settings. `ipv4` is optional so the primary's
+/// priority/weight/probe can be edited without touching `wan.ipv4-set`'s state.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WanUplinkSetRequest {
+    pub id: String,
+    pub label: String,
+    #[serde(default)]
+    pub ipv4: Option<WanIpv4SetRequest>,
+    pub priority: u32,
+    pub weight: u32,
+    pub probe: WanProbe,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WanUplinkDeleteRequest {
+    pub id: String,
 }
 
 // ── Provider mapping ────────────────────────────────────────
@@ -249,22 +424,53 @@ pub fn wan<C: CtrlContext + Clone>() -> ParentHandler<C> {
             "ddns-set",
             from_fn_async_local(ddns_set::<C>).with_display_serializable(),
         )
+        .subcommand(
+            "ddns-refresh",
+            from_fn_async_local(ddns_refresh::<C>)
+                .with_metadata("no_auth", Value::Bool(true))
+                .no_display(),
+        )
+        .subcommand(
+            "uplink-list",
+            from_fn_async_local(uplink_list::<C>).with_display_serializable(),
+        )
+        .subcommand(
+            "uplink-create",
+            from_fn_async_local(uplink_create::<C>).with_display_serializable(),
+        )
+        .subcommand(
+            "uplink-set",
+            from_fn_async_local(uplink_set::<C>).with_display_serializable(),
+        )
+        .subcommand(
+            "uplink-delete",
+            from_fn_async_local(uplink_delete::<C>).with_display_serializable(),
+        )
 }
 
 // ── Helpers ─────────────────────────────────────────────────
 
-async fn get_assigned_wan_ip() -> Option<String> {
+/// netifd's runtime status for an interface, or None if it doesn't know it.
+async fn get_interface_status(interface: &str) -> Option<serde_json::Value> {
     let output = tokio::process::Command::new("ubus")
-        .args(["call", "network.interface.wan", "status"])
+        .args(["call", &format!("network.interface.{interface}"), "status"])
         .invoke(ErrorKind::Network.into())
         .await
         .ok()?;
-    let json: serde_json::Value = serde_json::from_slice(&output).ok()?;
-    json.pointer("/ipv4-address/0/address")
+    serde_json::from_slice(&output).ok()
+}
+
+fn assigned_ip(status: &serde_json::Value) -> Option<String> {
+    status
+        .pointer("/ipv4-address/0/address")
         .and_then(|v| v.as_str())
         .map(|s| s.to_string())
 }
 
+async fn get_assigned_wan_ip() -> Option<String> {
+    assigned_ip(&get_interface_status(WAN_INTERFACE).await?)
+}
+
 async fn get_default_mac(dev_name: &str) -> String {
     // Read the hardware MAC from the WAN device via ip link
     let output = tokio::process::Command::new("ip")
@@ -293,6 +499,64 @@ async fn restart_network() {
 
 // ── IPv4 handlers ───────────────────────────────────────────
 
+fn ipv4_response(iface: NetworkInterface, assigned_ip: Option<String>) -> WanIpv4Response {
+    let mode = match iface.proto {
+        InterfaceProto::STATIC => WanIpv4Mode::Static,
+        InterfaceProto::PPPOE => WanIpv4Mode::Pppoe,
+        _ => WanIpv4Mode::Dhcp,
+    };
+    WanIpv4Response {
+        mode,
+        assigned_ip,
+        address: iface.ipaddr.map(|ip| ip.to_string()),
+        netmask: iface.netmask.map(|m| m.to_string()),
+        gateway: iface.gateway,
+        username: iface.username,
+        password: iface.password,
+        device: Some(iface.device),
+    }
+}
+
+fn apply_ipv4(iface: &mut NetworkInterface, req: &WanIpv4SetRequest) {
+    match req.mode {
+        WanIpv4Mode::Dhcp => {
+            iface.proto = InterfaceProto::DHCP;
+            iface.ipaddr = None;
+            iface.netmask = None;
+            iface.gateway = None;
+            iface.username = None;
+            iface.password = None;
+        }
+        WanIpv4Mode::Static => {
+            iface.proto = InterfaceProto::STATIC;
+            iface.ipaddr = req.address.as_ref().and_then(|a| a.parse().ok());
+            iface.netmask = req.netmask.as_ref().and_then(|n| n.parse().ok());
+            iface.gateway = req.gateway.clone();
+            iface.username = None;
+            iface.password = None;
+            // If no custom DNS is configured, use the gateway as DNS
+            // so dnsmasq has an upstream to forward to
+            if iface.peerdns.as_deref() != Some("0") {
+                if let Some(gw) = &iface.gateway {
+                    iface.dns = vec![gw.clone()];
+                    iface.peerdns = Some("0".to_string());
+                }
+            }
+        }
+        WanIpv4Mode::Pppoe => {
+            iface.proto = InterfaceProto::PPPOE;
+            iface.username = req.username.clone();
+            iface.password = req.password.clone();
+            if let Some(dev) = &req.device {
+                iface.device = dev.clone();
+            }
+            iface.ipaddr = None;
+            iface.netmask = None;
+            iface.gateway = None;
+        }
+    }
+}
+
 #[instrument(skip_all)]
 pub async fn ipv4_get<C: CtrlContext>(ctx: C) -> Result<WanIpv4Response, Error> {
     let effectful = ctx.effectful();
@@ -304,12 +568,7 @@ pub async fn ipv4_get<C: CtrlContext>(ctx: C) -> Result<WanIpv4Response, Error>
         for section in &cfgs["network"].sections {
             if section.name().as_deref() == Some(WAN_INTERFACE) {
                 if let Some(iface) = section.get_typed::<NetworkInterface>()? {
-                    let mode = match iface.proto {
-                        InterfaceProto::STATIC => WanIpv4Mode::Static,
-                        InterfaceProto::PPPOE => WanIpv4Mode::Pppoe,
-                        _ => WanIpv4Mode::Dhcp,
-                    };
-                    found = Some((mode, iface));
+                    found = Some(iface);
                     break;
                 }
             }
@@ -318,22 +577,13 @@ pub async fn ipv4_get<C: CtrlContext>(ctx: C) -> Result<WanIpv4Response, Error>
     };
 
     match result {
-        Some((mode, iface)) => {
+        Some(iface) => {
             let assigned_ip = if effectful {
                 get_assigned_wan_ip().await
             } else {
                 None
             };
-            Ok(WanIpv4Response {
-                mode,
-                assigned_ip,
-                address: iface.ipaddr.map(|ip| ip.to_string()),
-                netmask: iface.netmask.map(|m| m.to_string()),
-                gateway: iface.gateway,
-                username: iface.username,
-                password: iface.password,
-                device: Some(iface.device),
-            })
+            Ok(ipv4_response(iface, assigned_ip))
         }
         None => Err(Error::new(
             eyre!("WAN interface not found"),
@@ -356,43 +606,7 @@ pub async fn ipv4_set<C: CtrlContext>(
         for section in &mut cfgs["network"].sections {
             if section.name().as_deref() == Some(WAN_INTERFACE) {
                 if let Some(mut iface) = section.get_typed::<NetworkInterface>()? {
-                    match req.mode {
-                        WanIpv4Mode::Dhcp => {
-                            iface.proto = InterfaceProto::DHCP;
-                            iface.ipaddr = None;
-                            iface.netmask = None;
-                            iface.gateway = None;
-                            iface.username = None;
-                            iface.password = None;
-                        }
-                        WanIpv4Mode::Static => {
-                            iface.proto = InterfaceProto::STATIC;
-                            iface.ipaddr = req.address.as_ref().and_then(|a| a.parse().ok());
-                            iface.netmask = req.netmask.as_ref().and_then(|n| n.parse().ok());
-                            iface.gateway = req.gateway.clone();
-                            iface.username = None;
-                            iface.password = None;
-                            // If no custom DNS is configured, use the gateway as DNS
-                            // so dnsmasq has an upstream to forward to
-                            if iface.peerdns.as_deref() != Some("0") {
-                                if let Some(gw) = &iface.gateway {
-                                    iface.dns = vec![gw.clone()];
-                                    iface.peerdns = Some("0".to_string());
-                                }
-                            }
-                        }
-                        WanIpv4Mode::Pppoe => {
-                            iface.proto = InterfaceProto::PPPOE;
-                            iface.username = req.username.clone();
-                            iface.password = req.password.clone();
-                            if let Some(dev) = &req.device {
-                                iface.device = dev.clone();
-                            }
-                            iface.ipaddr = None;
-                            iface.netmask = None;
-                            iface.gateway = None;
-                        }
-                    }
+                    apply_ipv4(&mut iface, &req);
                     section.set(&iface)?;
                     found = true;
                     break;
@@ -1004,6 +1218,7 @@ pub async fn ddns_get<C: CtrlContext>(ctx: C) -> Result<WanDdnsResponse, Error>
                     password,
                     token,
                     zone,
+                    uplink: svc._ddns_uplink,
                 });
             }
         }
@@ -1017,6 +1232,7 @@ pub async fn ddns_get<C: CtrlContext>(ctx: C) -> Result<WanDdnsResponse, Error>
         password: None,
         token: None,
         zone: None,
+        uplink: None,
     })
 }
 
@@ -1059,11 +1275,20 @@ pub async fn ddns_set<C: CtrlContext>(
         }
         _ => (req.username.clone(), req.hostname.clone()),
     };
+    let mwan3 = if ctx.effectful() {
+        get_mwan3_status().await
+    } else {
+        None
+    };
 
     let mut retries = 4;
     loop {
         let arena = Arena::new();
-        let mut cfgs = parse_all(ctx.uci_root(), &arena, &["ddns"]).await?;
+        let mut cfgs = parse_all(ctx.uci_root(), &arena, &["ddns", "network", "startwrt"]).await?;
+        if let Some(uplink) = &req.uplink {
+            guard_uplink_exists(&cfgs, uplink)?;
+        }
+        let uplink = ddns_uplink(&cfgs, req.uplink.as_deref(), mwan3.as_ref());
 
         let ddns_idx = cfgs["ddns"]
             .sections
@@ -1074,10 +1299,10 @@ pub async fn ddns_set<C: CtrlContext>(
             enabled: Some(if req.enabled { "1" } else { "0" }.to_string()),
             service_name: Some(provider_to_service(&req.provider).to_string()),
             ip_source: Some("network".to_string()),
-            ip_network: Some("wan".to_string()),
-            // Hotplug binding: re-run the update as soon as the WAN bounces
+            ip_network: Some(uplink.clone()),
+            // Hotplug binding: re-run the update as soon as the uplink bounces
             // instead of waiting for the daemon's next check interval
-            interface: Some("wan".to_string()),
+            interface: Some(uplink),
             use_api_check: if req.provider == DdnsProvider::Cloudflare {
                 Some("1".to_string())
             } else {
@@ -1091,13 +1316,944 @@ pub async fn ddns_set<C: CtrlContext>(
             } else {
                 None
             },
+            _ddns_uplink: req.uplink.clone(),
+        };
+
+        if let Some(idx) = ddns_idx {
+            cfgs["ddns"].sections[idx].set(&new_svc)?;
+        } else {
+            cfgs["ddns"].append(&new_svc, Some(DDNS_SECTION))?;
+        }
+
+        let dump_result = dump_all(ctx.uci_root(), cfgs).await;
+        drop(arena);
+        match dump_result {
+            Err(uciedit::Error::Conflict { .. }) if retries > 0 => {
+                retries -= 1;
+                continue;
+            }
+            Err(err) => {
+                crate::activity::log(
+                    "wan",
+                    "ddns-updated",
+                    false,
+                    "Failed to update DDNS",
+                    Some(&err.to_string()),
+                );
+                return Err(err.into());
+            }
+            Ok(()) => {
+                crate::activity::log(
+                    "wan",
+                    "ddns-updated",
+                    true,
+                    &format!("Updated DDNS (provider: {})", serde_name(&req.provider)),
+                    None,
+                );
+                if ctx.effectful() {
+                    let _ = crate::run_quiet_async(
+                        tokio::process::Command::new("/etc/init.d/ddns").arg(if req.enabled {
+                            "restart"
+                        } else {
+                            "stop"
+                        }),
+                    )
+                    .await;
+                }
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Rebind DDNS to the uplink it should publish and re-run its update. Fired
+/// by the mwan3 hotplug hook when an uplink connects or disconnects, so a
+/// failover reaches DNS without waiting for the next check interval.
+#[instrument(skip_all)]
+pub async fn ddns_refresh<C: CtrlContext>(ctx: C) -> Result<(), Error> {
+    let mwan3 = if ctx.effectful() {
+        get_mwan3_status().await
+    } else {
+        None
+    };
+
+    let mut retries = 4;
+    loop {
+        let arena = Arena::new();
+        let mut cfgs = parse_all(ctx.uci_root(), &arena, &["ddns", "network", "startwrt"]).await?;
+
+        let enabled = cfgs["ddns"]
+            .sections
+            .iter()
+            .find(|s| s.name().as_deref() == Some(DDNS_SECTION))
+            .and_then(|s| s.get_typed::<DdnsService>().ok().flatten())
+            .is_some_and(|svc| svc.enabled.as_deref() == Some("1"));
+        if !enabled {
+            return Ok(());
+        }
+        let moved = rebind_ddns(&mut cfgs, mwan3.as_ref())?;
+
+        let dump_result = match moved {
+            Some(_) => dump_all(ctx.uci_root(), cfgs).await,
+            None => Ok(()),
+        };
+        drop(arena);
+        match dump_result {
+            Err(uciedit::Error::Conflict { .. }) if retries > 0 => {
+                retries -= 1;
+                continue;
+            }
+            Err(err) => return Err(err.into()),
+            Ok(()) => {
+                if let Some(uplink) = moved {
+                    crate::activity::log(
+                        "wan",
+                        "ddns-updated",
+                        true,
+                        &format!("DDNS now follows uplink {uplink}"),
+                        None,
+                    );
+                }
+                if ctx.effectful() {
+                    let _ = crate::run_quiet_async(
+                        tokio::process::Command::new("/etc/init.d/ddns").arg("restart"),
+                    )
+                    .await;
+                }
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// The uplink DDNS publishes the address of: `pinned` while that uplink
+/// exists, otherwise the one mwan3 sends traffic over — the online uplink of
+/// lowest priority, earliest first — or the primary when none is online (or
+/// there is only one uplink, which mwan3 doesn't track).
+fn ddns_uplink(cfgs: &Configs, pinned: Option<&str>, mwan3: Option<&serde_json::Value>) -> String {
+    let uplinks = read_uplinks(cfgs);
+    if let Some(pinned) = pinned {
+        if uplinks.iter().any(|(meta, _)| meta.interface == pinned) {
+            return pinned.to_string();
+        }
+    }
+    uplinks
+        .iter()
+        .filter(|(meta, _)| uplink_status(mwan3, &meta.interface, None) == WanUplinkStatus::Online)
+        .min_by_key(|(meta, _)| meta.priority)
+        .map(|(meta, _)| meta.interface.clone())
+        .unwrap_or_else(|| WAN_INTERFACE.to_string())
+}
+
+/// Point the DDNS section, if any, at [`ddns_uplink`], dropping a pin to an
+/// uplink that no longer exists. Returns the uplink when the section changed.
+fn rebind_ddns(
+    cfgs: &mut Configs,
+    mwan3: Option<&serde_json::Value>,
+) -> Result<Option<String>, Error> {
+    let Some(idx) = cfgs["ddns"]
+        .sections
+        .iter()
+        .position(|s| s.name().as_deref() == Some(DDNS_SECTION))
+    else {
+        return Ok(None);
+    };
+    let Some(mut svc) = cfgs["ddns"].sections[idx].get_typed::<DdnsService>()? else {
+        return Ok(None);
+    };
+    let uplink = ddns_uplink(cfgs, svc._ddns_uplink.as_deref(), mwan3);
+    let pin = svc._ddns_uplink.clone().filter(|pin| *pin == uplink);
+    if svc.interface.as_deref() == Some(uplink.as_str())
+        && svc.ip_network.as_deref() == Some(uplink.as_str())
+        && svc._ddns_uplink == pin
+    {
+        return Ok(None);
+    }
+    svc.interface = Some(uplink.clone());
+    svc.ip_network = Some(uplink.clone());
+    svc._ddns_uplink = pin;
+    cfgs["ddns"].sections[idx].set(&svc)?;
+    Ok(Some(uplink))
+}
+
+// ── Uplink helpers ──────────────────────────────────────────
+
+/// mwan3 fwmark bits; clear of the 0x80 DNAT-return mark (see profiles.rs)
+const MWAN3_MMX_MASK: &str = "0x3F00";
+/// The policy `"wan"` outbounds follow: every uplink, by priority then weight
+const MWAN3_POLICY: &str = "uplinks";
+
+/// The uplink a profile outbound pins the profile to, if it's a pin.
+pub(crate) fn pinned_uplink(outbound: &str) -> Option<&str> {
+    outbound.strip_prefix(WAN_PIN_PREFIX)
+}
+
+fn pin_outbound(id: &str) -> String {
+    format!("{WAN_PIN_PREFIX}{id}")
+}
+
+/// All uplinks, primary first, then in creation order. An uplink whose
+/// network interface is gone (e.g. the primary on a WAN-less setup) is skipped.
+fn read_uplinks(cfgs: &Configs) -> Vec<(UciWanUplink, NetworkInterface)> {
+    let mut metas: Vec<UciWanUplink> = cfgs["startwrt"]
+        .sections
+        .iter()
+        .filter_map(|s| s.get::<UciWanUplink>().ok())
+        .collect();
+    if !metas.iter().any(|m| m.interface == WAN_INTERFACE) {
+        metas.push(UciWanUplink::primary());
+    }
+    metas.sort_by_key(|m| m.interface != WAN_INTERFACE);
+    metas
+        .into_iter()
+        .filter_map(|meta| {
+            let iface = cfgs["network"]
+                .sections
+                .iter()
+                .find(|s| s.name().as_deref() == Some(meta.interface.as_str()))
+                .and_then(|s| s.get_typed::<NetworkInterface>().ok().flatten())?;
+            Some((meta, iface))
+        })
+        .collect()
+}
+
+/// Devices of the additional uplinks, which `ethernet.set` must keep out of
+/// the LAN bridge just like the primary WAN port.
+pub(crate) fn uplink_devices(cfgs: &Configs) -> HashSet<String> {
+    read_uplinks(cfgs)
+        .into_iter()
+        .filter(|(meta, _)| meta.interface != WAN_INTERFACE)
+        .map(|(_, iface)| iface.device)
+        .collect()
+}
+
+/// Reject a `wan:<id>` profile outbound whose uplink doesn't exist.
+pub(crate) fn guard_uplink_exists(cfgs: &Configs, id: &str) -> Result<(), Error> {
+    if read_uplinks(cfgs)
+        .iter()
+        .any(|(meta, _)| meta.interface == id)
+    {
+        Ok(())
+    } else {
+        Err(Error::new(
+            eyre!("no WAN uplink named {id}"),
+            ErrorKind::NotFound,
+        ))
+    }
+}
+
+/// Sanitize a label into the uplink's interface name, `wan_<label>`.
+fn sanitize_uplink_name(label: &str) -> Result<String, Error> {
+    let sanitized: String = label
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if UPLINK_PREFIX.len() + sanitized.len() > MAX_UPLINK_NAME_LEN {
+        return Err(Error::new(
+            eyre!(
+                "invalid label: '{}' produces a {}-char interface name, max is {}",
+                label,
+                UPLINK_PREFIX.len() + sanitized.len(),
+                MAX_UPLINK_NAME_LEN
+            ),
+            ErrorKind::InvalidValue,
+        ));
+    }
+    Ok(format!("{UPLINK_PREFIX}{sanitized}"))
+}
+
+fn guard_uplink_label(cfgs: &Configs, label: &str, self_id: Option<&str>) -> Result<(), Error> {
+    crate::vpn_client::validate_label(label)?;
+    let duplicate = read_uplinks(cfgs)
+        .iter()
+        .any(|(meta, _)| meta.label == label && Some(meta.interface.as_str()) != self_id);
+    if duplicate {
+        return Err(Error::new(
+            eyre!("invalid label: {label} (duplicate)"),
+            ErrorKind::InvalidValue,
+        ));
+    }
+    Ok(())
+}
+
+/// Reject a device an uplink can't run on: a malformed name, a bridge, or a
+/// device a network interface already uses — another uplink's port, a profile
+/// VLAN (`br-lan.99`), or a WireGuard interface's netdev (named after the
+/// interface). Interfaces in `own` belong to the uplink being edited.
+fn guard_uplink_device(cfgs: &Configs, device: &str, own: &[&str]) -> Result<(), Error> {
+    let valid = !device.is_empty()
+        && device.len() <= MAX_UPLINK_NAME_LEN
+        && device
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+    if !valid {
+        return Err(Error::new(
+            eyre!("invalid device: {device}"),
+            ErrorKind::InvalidValue,
+        ));
+    }
+    if let Some((meta, _)) = read_uplinks(cfgs)
+        .iter()
+        .find(|(_, iface)| iface.device == device)
+    {
+        return Err(Error::new(
+            eyre!("device {device} is already used by uplink '{}'", meta.label),
+            ErrorKind::InvalidValue,
+        ));
+    }
+    let user = cfgs["network"].sections.iter().find_map(|s| {
+        let name = s.name()?;
+        if own.iter().any(|own| *own == name) {
+            return None;
+        }
+        let iface = s.get_typed::<NetworkInterface>().ok().flatten()?;
+        let uses =
+            iface.device == device || (iface.proto == InterfaceProto::WIREGUARD && name == device);
+        uses.then(|| name.into_owned())
+    });
+    if let Some(name) = user {
+        return Err(Error::new(
+            eyre!("device {device} is already used by interface {name}"),
+            ErrorKind::InvalidValue,
+        ));
+    }
+    let is_bridge = cfgs["network"]
+        .sections
+        .iter()
+        .filter_map(|s| s.get_typed::<NetworkDevice>().ok().flatten())
+        .any(|dev| dev.name == device && dev.ty == Some(DeviceType::BRIDGE));
+    if is_bridge {
+        return Err(Error::new(
+            eyre!("{device} is a bridge, not a port"),
+            ErrorKind::InvalidValue,
+        ));
+    }
+    Ok(())
+}
+
+fn validate_balancing(priority: u32, weight: u32) -> Result<(), Error> {
+    if !(1..=MAX_UPLINK_PRIORITY).contains(&priority) {
+        return Err(Error::new(
+            eyre!("priority must be between 1 and {MAX_UPLINK_PRIORITY}"),
+            ErrorKind::InvalidValue,
+        ));
+    }
+    if !(1..=MAX_UPLINK_WEIGHT).contains(&weight) {
+        return Err(Error::new(
+            eyre!("weight must be between 1 and {MAX_UPLINK_WEIGHT}"),
+            ErrorKind::InvalidValue,
+        ));
+    }
+    Ok(())
+}
+
+fn validate_probe(probe: &WanProbe) -> Result<(), Error> {
+    if probe.targets.is_empty() {
+        return Err(Error::new(
+            eyre!("at least one probe target is required"),
+            ErrorKind::InvalidValue,
+        ));
+    }
+    // Probes run over IPv4 only, like the uplinks' mwan3 policy
+    if let Some(target) = probe
+        .targets
+        .iter()
+        .find(|t| t.parse::<Ipv4Addr>().is_err())
+    {
+        return Err(Error::new(
+            eyre!("invalid probe target: {target} (expected an IPv4 address)"),
+            ErrorKind::InvalidValue,
+        ));
+    }
+    if !(1..=3600).contains(&probe.interval)
+        || !(1..=100).contains(&probe.down)
+        || !(1..=100).contains(&probe.up)
+    {
+        return Err(Error::new(
+            eyre!("probe interval must be 1-3600 seconds and down/up counts 1-100"),
+            ErrorKind::InvalidValue,
+        ));
+    }
+    Ok(())
+}
+
+/// Take `device` out of the LAN bridge and its VLAN port lists. Returns
+/// whether it was a bridge port.
+fn detach_from_lan_bridge(cfgs: &mut Configs, device: &str) -> Result<bool, Error> {
+    let mut detached = false;
+    for section in &mut cfgs["network"].sections {
+        if let Some(mut dev) = section.get_typed::<NetworkDevice>().ok().flatten() {
+            if dev.ty == Some(DeviceType::BRIDGE) && dev.ports.iter().any(|p| p == device) {
+                dev.ports.retain(|p| p != device);
+                section.set(&dev)?;
+                detached = true;
+            }
+        } else if let Some(mut vlan) = section.get_typed::<NetworkBridgeVlan>()? {
+            let before = vlan.ports.len();
+            vlan.ports.retain(|p| p.port != device);
+            if vlan.ports.len() != before {
+                section.set(&vlan)?;
+            }
+        }
+    }
+    Ok(detached)
+}
+
+/// Put a port taken by `detach_from_lan_bridge` back into the LAN bridge,
+/// unassigned to any profile (as `ethernet.get` will report it).
+fn return_to_lan_bridge(cfgs: &mut Configs, device: &str) -> Result<(), Error> {
+    let Some(bridge) = crate::ethernet::find_lan_bridge(cfgs)? else {
+        return Ok(());
+    };
+    for section in &mut cfgs["network"].sections {
+        if let Some(mut dev) = section.get_typed::<NetworkDevice>().ok().flatten() {
+            if dev.name == bridge.name {
+                if !dev.ports.iter().any(|p| p == device) {
+                    dev.ports.push(device.to_string());
+                    section.set(&dev)?;
+                }
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Add or remove an uplink interface in the `wan` firewall zone, so it gets
+/// the zone's masquerading and inbound policy.
+fn set_in_wan_zone(cfgs: &mut Configs, interface: &str, member: bool) -> Result<(), Error> {
+    for section in &mut cfgs["firewall"].sections {
+        if let Ok(mut zone) = section.get::<FirewallZone>() {
+            if zone.name == DEFAULT_WAN_ZONE {
+                let present = zone.network.iter().any(|n| n == interface);
+                if member && !present {
+                    zone.network.push(interface.to_string());
+                    section.set(&zone)?;
+                } else if !member && present {
+                    zone.network.retain(|n| n != interface);
+                    section.set(&zone)?;
+                }
+                return Ok(());
+            }
+        }
+    }
+    Err(Error::new(
+        eyre!("WAN firewall zone not found"),
+        ErrorKind::MissingFirewallZone,
+    ))
+}
+
+fn pinned_profiles(cfgs: &Configs, id: &str) -> Vec<String> {
+    let pin = pin_outbound(id);
+    cfgs["startwrt"]
+        .sections
+        .iter()
+        .filter_map(|s| s.get::<UciProfile>().ok())
+        .filter(|p| p.outbound.as_deref() == Some(pin.as_str()))
+        .map(|p| p.fullname)
+        .collect()
+}
+
+/// Point profiles pinned to uplink `id` back at the shared policy. Firewall
+/// and IPv4 routing already treat a pin as WAN; the IPv6 a pin withholds is
+/// the caller's to restore (see `profiles::reapply_profile_config`).
+fn reset_pinned_profiles(cfgs: &mut Configs, id: &str) -> Result<Vec<String>, Error> {
+    let pin = pin_outbound(id);
+    let mut reset = Vec::new();
+    for section in &mut cfgs["startwrt"].sections {
+        let Ok(mut profile) = section.get::<UciProfile>() else {
+            continue;
+        };
+        if profile.outbound.as_deref() == Some(pin.as_str()) {
+            profile.outbound = Some(DEFAULT_WAN_ZONE.to_string());
+            section.set(&profile)?;
+            reset.push(profile.fullname);
+        }
+    }
+    Ok(reset)
+}
+
+/// Regenerate /etc/config/mwan3, and each uplink's route metric, from the
+/// uplinks and the profiles pinned to them. The file is owned entirely by
+/// startwrt. With a single uplink it gets no sections, leaving mwan3 idle and
+/// routing exactly as it was before multi-WAN.
+///
+/// Profiles routed through a VPN are unaffected: their policy rules (priority
+/// 200) are evaluated before mwan3's, and only the tunnel's own packets, sent
+/// by the router, go through the uplink policy.
+pub(crate) fn rewrite_mwan3(cfgs: &mut Configs) -> Result<(), Error> {
+    let uplinks = read_uplinks(cfgs);
+    let multi = uplinks.len() > 1;
+
+    // mwan3 needs every uplink's default route present in the main table at
+    // once, which netifd only allows when their metrics differ
+    for (idx, (meta, _)) in uplinks.iter().enumerate() {
+        let metric = multi.then(|| UPLINK_METRIC_STEP * (idx as u32 + 1));
+        for section in &mut cfgs["network"].sections {
+            if section.name().as_deref() == Some(meta.interface.as_str()) {
+                if let Some(mut iface) = section.get_typed::<NetworkInterface>()? {
+                    if iface.metric != metric {
+                        iface.metric = metric;
+                        section.set(&iface)?;
+                    }
+                }
+                break;
+            }
+        }
+    }
+
+    cfgs["mwan3"].sections.clear();
+    if !multi {
+        return Ok(());
+    }
+
+    cfgs["mwan3"].append(
+        &Mwan3Globals {
+            mmx_mask: Some(MWAN3_MMX_MASK.to_string()),
+        },
+        Some("globals"),
+    )?;
+
+    // mwan3 caps policy and rule names at 15 chars, so those are numbered
+    // rather than named after the (up to 15-char) uplink interfaces
+    let member_name = |idx: usize| format!("uplink_{idx}");
+    let pin_policy_name = |idx: usize| format!("pin_{idx}");
+    for (idx, (meta, _)) in uplinks.iter().enumerate() {
+        let probe = meta.probe();
+        cfgs["mwan3"].append(
+            &Mwan3Interface {
+                enabled: true,
+                family: Some("ipv4".to_string()),
+                track_ip: probe.targets,
+                reliability: Some(1),
+                interval: Some(probe.interval),
+                down: Some(probe.down),
+                up: Some(probe.up),
+            },
+            Some(&meta.interface),
+        )?;
+        cfgs["mwan3"].append(
+            &Mwan3Member {
+                interface: meta.interface.clone(),
+                metric: meta.priority,
+                weight: meta.weight,
+            },
+            Some(&member_name(idx)),
+        )?;
+        // A pinned profile's IPv4 fails closed rather than spilling onto another
+        // uplink. Only IPv4 is pinned: the interfaces above track IPv4 and
+        // profile /64s are delegated dynamically, so there is no source prefix
+        // to match. A pinned profile gets no IPv6 instead — see
+        // `profiles::outbound_supports_ipv6` and `profiles::rewrite_routing`.
+        cfgs["mwan3"].append(
+            &Mwan3Policy {
+                members: vec![member_name(idx)],
+                last_resort: Some("unreachable".to_string()),
+            },
+            Some(&pin_policy_name(idx)),
+        )?;
+    }
+    cfgs["mwan3"].append(
+        &Mwan3Policy {
+            members: (0..uplinks.len()).map(member_name).collect(),
+            last_resort: Some("default".to_string()),
+        },
+        Some(MWAN3_POLICY),
+    )?;
+
+    // mwan3 applies the first matching rule, so pins go before the catch-all
+    let mut pins = Vec::new();
+    for profile in cfgs["startwrt"]
+        .sections
+        .iter()
+        .filter_map(|s| s.get::<UciProfile>().ok())
+    {
+        let Some(idx) = profile
+            .outbound
+            .as_deref()
+            .and_then(pinned_uplink)
+            .and_then(|pin| uplinks.iter().position(|(meta, _)| meta.interface == pin))
+        else {
+            continue;
+        };
+        let gateway = cfgs["network"]
+            .sections
+            .iter()
+            .find(|s| s.name().as_deref() == Some(profile.interface.as_str()))
+            .and_then(|s| s.get_typed::<NetworkInterface>().ok().flatten())
+            .and_then(|iface| iface.ipaddr);
+        if let Some(gateway) = gateway {
+            pins.push((profile.vlan_tag, gateway, idx));
+        }
+    }
+    for (vlan_tag, gateway, idx) in pins {
+        let o = gateway.octets();
+        cfgs["mwan3"].append(
+            &Mwan3Rule {
+                src_ip: Some(format!("{}.{}.{}.0/24", o[0], o[1], o[2])),
+                family: Some("ipv4".to_string()),
+                use_policy: pin_policy_name(idx),
+                ..Default::default()
+            },
+            Some(&format!("profile_{vlan_tag}")),
+        )?;
+    }
+    cfgs["mwan3"].append(
+        &Mwan3Rule {
+            dest_ip: Some("0.0.0.0/0".to_string()),
+            family: Some("ipv4".to_string()),
+            sticky: Some("1".to_string()),
+            use_policy: MWAN3_POLICY.to_string(),
+            ..Default::default()
+        },
+        Some("default_v4"),
+    )?;
+    Ok(())
+}
+
+pub(crate) async fn restart_mwan3() {
+    let _ =
+        crate::run_quiet_async(tokio::process::Command::new("/etc/init.d/mwan3").arg("restart"))
+            .await;
+}
+
+/// mwan3's per-interface tracking state. Empty while there's only one uplink.
+async fn get_mwan3_status() -> Option<serde_json::Value> {
+    let output = tokio::process::Command::new("ubus")
+        .args(["call", "mwan3", "status"])
+        .invoke(ErrorKind::Network.into())
+        .await
+        .ok()?;
+    serde_json::from_slice(&output).ok()
+}
+
+/// mwan3's view of an uplink when it tracks it, otherwise netifd's.
+fn uplink_status(
+    mwan3: Option<&serde_json::Value>,
+    id: &str,
+    netifd: Option<&serde_json::Value>,
+) -> WanUplinkStatus {
+    let tracked = mwan3
+        .and_then(|m| m.pointer(&format!("/interfaces/{id}/status")))
+        .and_then(|v| v.as_str());
+    match tracked {
+        Some("online") | Some("notracking") => WanUplinkStatus::Online,
+        Some("offline") => WanUplinkStatus::Offline,
+        Some("connecting") | Some("disconnecting") => WanUplinkStatus::Connecting,
+        Some("disabled") => WanUplinkStatus::Disabled,
+        _ => {
+            let flag = |key: &str| {
+                netifd
+                    .and_then(|s| s.get(key))
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false)
+            };
+            if flag("up") {
+                WanUplinkStatus::Online
+            } else if flag("pending") {
+                WanUplinkStatus::Connecting
+            } else {
+                WanUplinkStatus::Offline
+            }
+        }
+    }
+}
+
+// ── Uplink handlers ─────────────────────────────────────────
+
+#[instrument(skip_all)]
+pub async fn uplink_list<C: CtrlContext>(ctx: C) -> Result<Vec<WanUplink>, Error> {
+    let mut uplinks: Vec<WanUplink> = {
+        let arena = Arena::new();
+        let cfgs = parse_all(ctx.uci_root(), &arena, &["network", "startwrt"]).await?;
+        read_uplinks(&cfgs)
+            .into_iter()
+            .map(|(meta, iface)| WanUplink {
+                used_by: pinned_profiles(&cfgs, &meta.interface),
+                primary: meta.interface == WAN_INTERFACE,
+                probe: meta.probe(),
+                ipv4: ipv4_response(iface, None),
+                id: meta.interface,
+                label: meta.label,
+                priority: meta.priority,
+                weight: meta.weight,
+                status: None,
+            })
+            .collect()
+    };
+
+    if ctx.effectful() {
+        let mwan3 = get_mwan3_status().await;
+        for uplink in &mut uplinks {
+            let status = get_interface_status(&uplink.id).await;
+            uplink.ipv4.assigned_ip = status.as_ref().and_then(assigned_ip);
+            uplink.status = Some(uplink_status(mwan3.as_ref(), &uplink.id, status.as_ref()));
+        }
+    }
+    Ok(uplinks)
+}
+
+#[instrument(skip_all)]
+pub async fn uplink_create<C: CtrlContext>(
+    ctx: C,
+    DeserializeStdin(req): DeserializeStdin<WanUplinkCreateRequest>,
+) -> Result<WanUplinkCreateResponse, Error> {
+    let id = sanitize_uplink_name(&req.label)?;
+    let priority = req.priority.unwrap_or(DEFAULT_UPLINK_PRIORITY);
+    let weight = req.weight.unwrap_or(DEFAULT_UPLINK_WEIGHT);
+    validate_balancing(priority, weight)?;
+    if let Some(probe) = &req.probe {
+        validate_probe(probe)?;
+    }
+
+    let mut retries = 4;
+    loop {
+        let arena = Arena::new();
+        let mut cfgs = parse_all(
+            ctx.uci_root(),
+            &arena,
+            &["network", "startwrt", "firewall", "mwan3"],
+        )
+        .await?;
+
+        guard_uplink_label(&cfgs, &req.label, None)?;
+        let name_conflict = cfgs["network"]
+            .sections
+            .iter()
+            .any(|s| s.name().as_deref() == Some(id.as_str()));
+        if name_conflict {
+            return Err(Error::new(
+                eyre!("interface name conflict: {id}"),
+                ErrorKind::InterfaceNameConflict,
+            ));
+        }
+        guard_uplink_device(&cfgs, &req.device, &[])?;
+
+        let mut iface = NetworkInterface::default();
+        apply_ipv4(&mut iface, &req.ipv4);
+        iface.device = req.device.clone();
+        cfgs["network"].append(&iface, Some(&id))?;
+        let lan_port = detach_from_lan_bridge(&mut cfgs, &req.device)?;
+
+        let mut meta = UciWanUplink {
+            interface: id.clone(),
+            label: req.label.clone(),
+            priority,
+            weight,
+            probe_target: vec![],
+            probe_interval: DEFAULT_PROBE_INTERVAL,
+            probe_down: DEFAULT_PROBE_DOWN,
+            probe_up: DEFAULT_PROBE_UP,
+            lan_port,
+        };
+        if let Some(probe) = req.probe.clone() {
+            meta.set_probe(probe);
+        }
+        cfgs["startwrt"].append(&meta, Some(&id))?;
+
+        set_in_wan_zone(&mut cfgs, &id, true)?;
+        rewrite_mwan3(&mut cfgs)?;
+
+        let dump_result = dump_all(ctx.uci_root(), cfgs).await;
+        drop(arena);
+        match dump_result {
+            Err(uciedit::Error::Conflict { .. }) if retries > 0 => {
+                retries -= 1;
+                continue;
+            }
+            Err(err) => {
+                crate::activity::log(
+                    "wan",
+                    "uplink-created",
+                    false,
+                    &format!("Failed to add WAN uplink '{}'", req.label),
+                    Some(&err.to_string()),
+                );
+                return Err(err.into());
+            }
+            Ok(()) => {
+                crate::activity::log(
+                    "wan",
+                    "uplink-created",
+                    true,
+                    &format!("Added WAN uplink '{}' on {}", req.label, req.device),
+                    None,
+                );
+                if ctx.effectful() {
+                    // Network for the new interface and metrics, firewall for
+                    // the wan zone, mwan3 for the policy
+                    profiles::reload_system().await?;
+                }
+                return Ok(WanUplinkCreateResponse { id });
+            }
+        }
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn uplink_set<C: CtrlContext>(
+    ctx: C,
+    DeserializeStdin(req): DeserializeStdin<WanUplinkSetRequest>,
+) -> Result<(), Error> {
+    validate_balancing(req.priority, req.weight)?;
+    validate_probe(&req.probe)?;
+
+    let mut retries = 4;
+    loop {
+        let arena = Arena::new();
+        let mut cfgs = parse_all(ctx.uci_root(), &arena, &["network", "startwrt", "mwan3"]).await?;
+
+        let Some((mut meta, current)) = read_uplinks(&cfgs)
+            .into_iter()
+            .find(|(meta, _)| meta.interface == req.id)
+        else {
+            return Err(Error::new(
+                eyre!("WAN uplink {} not found", req.id),
+                ErrorKind::NotFound,
+            ));
+        };
+        guard_uplink_label(&cfgs, &req.label, Some(&req.id))?;
+        if let Some(WanIpv4SetRequest {
+            mode: WanIpv4Mode::Pppoe,
+            device: Some(device),
+            ..
+        }) = &req.ipv4
+        {
+            // A PPPoE move may not land on a device another interface uses
+            if req.id == WAN_INTERFACE && *device != current.device {
+                guard_uplink_device(&cfgs, device, &[WAN_INTERFACE, WAN6_INTERFACE])?;
+            }
+        }
+        meta.label = req.label.clone();
+        meta.priority = req.priority;
+        meta.weight = req.weight;
+        meta.set_probe(req.probe.clone());
+
+        if let Some(ipv4) = &req.ipv4 {
+            for section in &mut cfgs["network"].sections {
+                if section.name().as_deref() == Some(req.id.as_str()) {
+                    if let Some(mut iface) = section.get_typed::<NetworkInterface>()? {
+                        let device = iface.device.clone();
+                        apply_ipv4(&mut iface, ipv4);
+                        // Only the primary may move devices (PPPoE, as in
+                        // ipv4-set); an uplink's device is its identity
+                        if req.id != WAN_INTERFACE {
+                            iface.device = device;
+                        }
+                        section.set(&iface)?;
+                    }
+                    break;
+                }
+            }
+        }
+
+        let existing = cfgs["startwrt"]
+            .sections
+            .iter()
+            .position(|s| s.get::<UciWanUplink>().is_ok_and(|m| m.interface == req.id));
+        match existing {
+            Some(idx) => cfgs["startwrt"].sections[idx].set(&meta)?,
+            None => cfgs["startwrt"].append(&meta, Some(&req.id))?,
+        }
+
+        rewrite_mwan3(&mut cfgs)?;
+
+        let dump_result = dump_all(ctx.uci_root(), cfgs).await;
+        drop(arena);
+        match dump_result {
+            Err(uciedit::Error::Conflict { .. }) if retries > 0 => {
+                retries -= 1;
+                continue;
+            }
+            Err(err) => {
+                crate::activity::log(
+                    "wan",
+                    "uplink-updated",
+                    false,
+                    &format!("Failed to update WAN uplink '{}'", req.label),
+                    Some(&err.to_string()),
+                );
+                return Err(err.into());
+            }
+            Ok(()) => {
+                crate::activity::log(
+                    "wan",
+                    "uplink-updated",
+                    true,
+                    &format!("Updated WAN uplink '{}'", req.label),
+                    None,
+                );
+                if ctx.effectful() {
+                    if req.ipv4.is_some() {
+                        restart_network().await;
+                    }
+                    restart_mwan3().await;
+                }
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[instrument(skip_all)]
+pub async fn uplink_delete<C: CtrlContext>(
+    ctx: C,
+    DeserializeStdin(req): DeserializeStdin<WanUplinkDeleteRequest>,
+) -> Result<(), Error> {
+    if req.id == WAN_INTERFACE {
+        return Err(Error::new(
+            eyre!("the primary WAN uplink can't be deleted"),
+            ErrorKind::InvalidRequest,
+        ));
+    }
+
+    let mut retries = 4;
+    loop {
+        let arena = Arena::new();
+        let mut cfgs = parse_all(
+            ctx.uci_root(),
+            &arena,
+            &["network", "startwrt", "firewall", "dhcp", "ddns", "mwan3"],
+        )
+        .await?;
+
+        let Some((meta, iface)) = read_uplinks(&cfgs)
+            .into_iter()
+            .find(|(meta, _)| meta.interface == req.id)
+        else {
+            return Err(Error::new(
+                eyre!("WAN uplink {} not found", req.id),
+                ErrorKind::NotFound,
+            ));
         };
 
-        if let Some(idx) = ddns_idx {
-            cfgs["ddns"].sections[idx].set(&new_svc)?;
-        } else {
-            cfgs["ddns"].append(&new_svc, Some(DDNS_SECTION))?;
+        let reset = reset_pinned_profiles(&mut cfgs, &req.id)?;
+        for fullname in &reset {
+            profiles::reapply_profile_config(
+                &ctx,
+                &mut cfgs,
+                profiles::ProfileIdOpt {
+                    fullname: Some(fullname.clone()),
+                    interface: None,
+                    vlan_tag: None,
+                },
+            )?;
+        }
+        cfgs["network"]
+            .sections
+            .retain(|s| !(s.name().as_deref() == Some(req.id.as_str()) && s.ty() == "interface"));
+        cfgs["startwrt"]
+            .sections
+            .retain(|s| !s.get::<UciWanUplink>().is_ok_and(|m| m.interface == req.id));
+        set_in_wan_zone(&mut cfgs, &req.id, false)?;
+        if meta.lan_port {
+            return_to_lan_bridge(&mut cfgs, &iface.device)?;
         }
+        // Off the deleted uplink; mwan3's restart below fires the hotplug hook
+        // that settles it on the active one
+        rebind_ddns(&mut cfgs, None)?;
+        rewrite_mwan3(&mut cfgs)?;
 
         let dump_result = dump_all(ctx.uci_root(), cfgs).await;
         drop(arena);
@@ -1109,30 +2265,27 @@ pub async fn ddns_set<C: CtrlContext>(
             Err(err) => {
                 crate::activity::log(
                     "wan",
-                    "ddns-updated",
+                    "uplink-deleted",
                     false,
-                    "Failed to update DDNS",
+                    &format!("Failed to remove WAN uplink '{}'", meta.label),
                     Some(&err.to_string()),
                 );
                 return Err(err.into());
             }
             Ok(()) => {
-                crate::activity::log(
-                    "wan",
-                    "ddns-updated",
-                    true,
-                    &format!("Updated DDNS (provider: {})", serde_name(&req.provider)),
-                    None,
-                );
+                let mut summary = format!("Removed WAN uplink '{}'", meta.label);
+                if !reset.is_empty() {
+                    summary +=
+                        &format!("; profiles moved back to all uplinks: {}", reset.join(", "));
+                }
+                crate::activity::log("wan", "uplink-deleted", true, &summary, None);
                 if ctx.effectful() {
-                    let _ = crate::run_quiet_async(
-                        tokio::process::Command::new("/etc/init.d/ddns").arg(if req.enabled {
-                            "restart"
-                        } else {
-                            "stop"
-                        }),
-                    )
-                    .await;
+                    // A restart hands the reset profiles their /64 back
+                    if reset.is_empty() {
+                        profiles::reload_system().await?;
+                    } else {
+                        profiles::reload_system_full().await?;
+                    }
                 }
                 return Ok(());
             }
@@ -1555,6 +2708,7 @@ config service 'wan'
                 password: None,
                 token: Some("cf-api-token-123".to_string()),
                 zone: Some("example.com".to_string()),
+                uplink: None,
             }),
         )
         .await
@@ -1594,6 +2748,7 @@ config service 'wan'
                 password: None,
                 token: Some("cf-api-token-123".to_string()),
                 zone: Some("example.com".to_string()),
+                uplink: None,
             }),
         )
         .await
@@ -1623,6 +2778,7 @@ config service 'wan'
                 password: None,
                 token: Some("cf-api-token-123".to_string()),
                 zone: None,
+                uplink: None,
             }),
         )
         .await
@@ -1647,6 +2803,7 @@ config service 'wan'
                     password: None,
                     token: Some("cf-api-token-123".to_string()),
                     zone: Some("example.com".to_string()),
+                    uplink: None,
                 }),
             )
             .await
@@ -1697,6 +2854,7 @@ config service 'wan'
                 password: None,
                 token: Some("freedns-update-key".to_string()),
                 zone: None,
+                uplink: None,
             }),
         )
         .await
@@ -2376,6 +3534,7 @@ config service 'wan'
                 password: None,
                 token: Some("tok123".to_string()),
                 zone: None,
+                uplink: None,
             }),
         )
         .await
@@ -2391,6 +3550,7 @@ config service 'wan'
                 password: None,
                 token: None,
                 zone: None,
+                uplink: None,
             }),
         )
         .await
@@ -2417,6 +3577,7 @@ config service 'wan'
                 password: None,
                 token: Some("duck-token".to_string()),
                 zone: None,
+                uplink: None,
             }),
         )
         .await
@@ -2448,6 +3609,7 @@ config service 'wan'
                 password: None,
                 token: Some("cf-api-token-123".to_string()),
                 zone: Some("example.com".to_string()),
+                uplink: None,
             }),
         )
         .await
@@ -2463,6 +3625,7 @@ config service 'wan'
                 password: None,
                 token: Some("duck-token".to_string()),
                 zone: None,
+                uplink: None,
             }),
         )
         .await
@@ -2473,4 +3636,479 @@ config service 'wan'
         assert!(!raw.contains("Bearer"));
         assert!(raw.contains("option interface 'wan'"));
     }
+
+    // ── Uplinks ─────────────────────────────────────────────────
+
+    fn write_uplink_fixture(dir: &std::path::Path, outbound: &str) {
+        std::fs::write(
+            dir.join("network"),
+            "\
+config device
+\toption name 'br-lan'
+\toption type 'bridge'
+\tlist ports 'lan1'
+\tlist ports 'lan2'
+
+config interface 'lan'
+\toption device 'br-lan.99'
+\toption proto 'static'
+\toption ipaddr '10.0.99.1'
+\toption netmask '255.255.255.0'
+
+config interface 'wan'
+\toption device 'eth1'
+\toption proto 'dhcp'
+",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("firewall"),
+            "\
+config zone 'wan'
+\toption name 'wan'
+\toption input 'REJECT'
+\toption output 'ACCEPT'
+\toption forward 'REJECT'
+\tlist network 'wan'
+\tlist network 'wan6'
+",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("startwrt"),
+            format!(
+                "\
+config profile 'lan'
+\toption fullname 'Admin'
+\toption interface 'lan'
+\toption vlan_tag '99'
+\toption outbound '{outbound}'
+"
+            ),
+        )
+        .unwrap();
+    }
+
+    fn lte_request(device: &str) -> WanUplinkCreateRequest {
+        WanUplinkCreateRequest {
+            label: "LTE".to_string(),
+            device: device.to_string(),
+            ipv4: WanIpv4SetRequest {
+                mode: WanIpv4Mode::Dhcp,
+                address: None,
+                netmask: None,
+                gateway: None,
+                username: None,
+                password: None,
+                device: None,
+            },
+            priority: None,
+            weight: None,
+            probe: None,
+        }
+    }
+
+    async fn interface_metric(ctx: &TestContext, name: &str) -> Option<u32> {
+        let arena = Arena::new();
+        let cfgs = parse_all(ctx.uci_root(), &arena, &["network"])
+            .await
+            .unwrap();
+        cfgs["network"]
+            .sections
+            .iter()
+            .find(|s| s.name().as_deref() == Some(name))
+            .and_then(|s| s.get_typed::<NetworkInterface>().unwrap())
+            .unwrap()
+            .metric
+    }
+
+    #[tokio::test]
+    async fn uplink_create_adds_failover_uplink() {
+        let dir = tempfile::tempdir().unwrap();
+        write_uplink_fixture(dir.path(), "wan");
+        let ctx = TestContext(dir.path().to_path_buf());
+
+        let res = uplink_create(ctx.clone(), DeserializeStdin(lte_request("usb0")))
+            .await
+            .unwrap();
+        assert_eq!(res.id, "wan_lte");
+
+        let uplinks = uplink_list(ctx.clone()).await.unwrap();
+        assert_eq!(uplinks.len(), 2);
+        assert!(uplinks[0].primary);
+        assert_eq!(uplinks[0].id, "wan");
+        assert_eq!(uplinks[0].priority, 1);
+        assert!(!uplinks[1].primary);
+        assert_eq!(uplinks[1].label, "LTE");
+        assert_eq!(uplinks[1].priority, 2);
+        assert_eq!(uplinks[1].probe.targets, vec!["1.1.1.1", "8.8.8.8"]);
+        assert!(uplinks[1].status.is_none());
+
+        assert_eq!(interface_metric(&ctx, "wan").await, Some(10));
+        assert_eq!(interface_metric(&ctx, "wan_lte").await, Some(20));
+
+        let firewall = std::fs::read_to_string(dir.path().join("firewall")).unwrap();
+        assert!(firewall.contains("list network 'wan_lte'"));
+
+        let mwan3 = std::fs::read_to_string(dir.path().join("mwan3")).unwrap();
+        assert!(mwan3.contains("option mmx_mask '0x3F00'"));
+        assert!(mwan3.contains("config interface wan_lte"));
+        assert!(mwan3.contains("config policy uplinks"));
+        assert!(mwan3.contains("config rule default_v4"));
+        assert!(
+            !mwan3.contains("config rule profile_"),
+            "no profile is pinned"
+        );
+    }
+
+    #[tokio::test]
+    async fn uplink_delete_returns_lan_port_and_idles_mwan3() {
+        let dir = tempfile::tempdir().unwrap();
+        write_uplink_fixture(dir.path(), "wan");
+        let ctx = TestContext(dir.path().to_path_buf());
+
+        uplink_create(ctx.clone(), DeserializeStdin(lte_request("lan2")))
+            .await
+            .unwrap();
+        {
+            let arena = Arena::new();
+            let cfgs = parse_all(ctx.uci_root(), &arena, &["network"])
+                .await
+                .unwrap();
+            let bridge = crate::ethernet::find_lan_bridge(&cfgs).unwrap().unwrap();
+            assert_eq!(bridge.ports, vec!["lan1"]);
+        }
+
+        uplink_delete(
+            ctx.clone(),
+            DeserializeStdin(WanUplinkDeleteRequest {
+                id: "wan_lte".to_string(),
+            }),
+        )
+        .await
+        .unwrap();
+        {
+            let arena = Arena::new();
+            let cfgs = parse_all(ctx.uci_root(), &arena, &["network"])
+                .await
+                .unwrap();
+            let bridge = crate::ethernet::find_lan_bridge(&cfgs).unwrap().unwrap();
+            assert_eq!(bridge.ports, vec!["lan1", "lan2"]);
+        }
+
+        assert_eq!(uplink_list(ctx.clone()).await.unwrap().len(), 1);
+        assert_eq!(interface_metric(&ctx, "wan").await, None);
+        let firewall = std::fs::read_to_string(dir.path().join("firewall")).unwrap();
+        assert!(!firewall.contains("wan_lte"));
+        let mwan3 = std::fs::read_to_string(dir.path().join("mwan3")).unwrap();
+        assert!(!mwan3.contains("config "));
+    }
+
+    #[tokio::test]
+    async fn uplink_pinned_profile_gets_rule_and_is_reset_on_delete() {
+        let dir = tempfile::tempdir().unwrap();
+        write_uplink_fixture(dir.path(), "wan:wan_lte");
+        let ctx = TestContext(dir.path().to_path_buf());
+
+        uplink_create(ctx.clone(), DeserializeStdin(lte_request("usb0")))
+            .await
+            .unwrap();
+
+        let mwan3 = std::fs::read_to_string(dir.path().join("mwan3")).unwrap();
+        let pin = mwan3.find("config rule profile_99").unwrap();
+        assert!(pin < mwan3.find("config rule default_v4").unwrap());
+        assert!(mwan3.contains("option src_ip '10.0.99.0/24'"));
+        assert!(mwan3.contains("option use_policy 'pin_1'"));
+
+        let uplinks = uplink_list(ctx.clone()).await.unwrap();
+        assert_eq!(uplinks[1].used_by, vec!["Admin"]);
+
+        uplink_delete(
+            ctx.clone(),
+            DeserializeStdin(WanUplinkDeleteRequest {
+                id: "wan_lte".to_string(),
+            }),
+        )
+        .await
+        .unwrap();
+        let startwrt = std::fs::read_to_string(dir.path().join("startwrt")).unwrap();
+        assert!(startwrt.contains("option outbound 'wan'"));
+        assert!(!startwrt.contains("wan:wan_lte"));
+    }
+
+    #[tokio::test]
+    async fn uplink_delete_primary_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        write_uplink_fixture(dir.path(), "wan");
+        let ctx = TestContext(dir.path().to_path_buf());
+
+        let err = uplink_delete(
+            ctx,
+            DeserializeStdin(WanUplinkDeleteRequest {
+                id: "wan".to_string(),
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InvalidRequest);
+    }
+
+    #[tokio::test]
+    async fn uplink_create_rejects_duplicate_label_and_device() {
+        let dir = tempfile::tempdir().unwrap();
+        write_uplink_fixture(dir.path(), "wan");
+        let ctx = TestContext(dir.path().to_path_buf());
+
+        let err = uplink_create(ctx.clone(), DeserializeStdin(lte_request("eth1")))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InvalidValue);
+
+        let mut req = lte_request("usb0");
+        req.label = "Primary WAN".to_string();
+        let err = uplink_create(ctx.clone(), DeserializeStdin(req))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InvalidValue);
+
+        let mut req = lte_request("br-lan");
+        req.label = "Bridge".to_string();
+        let err = uplink_create(ctx.clone(), DeserializeStdin(req))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InvalidValue);
+
+        // The admin profile's VLAN device
+        let mut req = lte_request("br-lan.99");
+        req.label = "Vlan".to_string();
+        let err = uplink_create(ctx.clone(), DeserializeStdin(req))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InvalidValue);
+
+        assert_eq!(uplink_list(ctx).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn uplink_set_updates_balancing_and_probe() {
+        let dir = tempfile::tempdir().unwrap();
+        write_uplink_fixture(dir.path(), "wan");
+        let ctx = TestContext(dir.path().to_path_buf());
+
+        uplink_create(ctx.clone(), DeserializeStdin(lte_request("usb0")))
+            .await
+            .unwrap();
+        uplink_set(
+            ctx.clone(),
+            DeserializeStdin(WanUplinkSetRequest {
+                id: "wan".to_string(),
+                label: "Fiber".to_string(),
+                ipv4: None,
+                priority: 1,
+                weight: 3,
+                probe: WanProbe {
+                    targets: vec!["9.9.9.9".to_string()],
+                    interval: 10,
+                    down: 2,
+                    up: 4,
+                },
+            }),
+        )
+        .await
+        .unwrap();
+
+        let uplinks = uplink_list(ctx.clone()).await.unwrap();
+        assert_eq!(uplinks[0].label, "Fiber");
+        assert_eq!(uplinks[0].weight, 3);
+        assert_eq!(uplinks[0].probe.targets, vec!["9.9.9.9"]);
+        assert_eq!(uplinks[0].probe.interval, 10);
+
+        let mwan3 = std::fs::read_to_string(dir.path().join("mwan3")).unwrap();
+        assert!(mwan3.contains("list track_ip '9.9.9.9'"));
+        assert!(mwan3.contains("option weight '3'"));
+
+        let err = uplink_set(
+            ctx,
+            DeserializeStdin(WanUplinkSetRequest {
+                id: "wan_lte".to_string(),
+                label: "LTE".to_string(),
+                ipv4: None,
+                priority: 2,
+                weight: 1,
+                probe: WanProbe {
+                    targets: vec!["example.com".to_string()],
+                    interval: 5,
+                    down: 3,
+                    up: 3,
+                },
+            }),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InvalidValue);
+    }
+
+    #[tokio::test]
+    async fn uplink_set_guards_pppoe_device() {
+        let dir = tempfile::tempdir().unwrap();
+        write_uplink_fixture(dir.path(), "wan");
+        let ctx = TestContext(dir.path().to_path_buf());
+
+        uplink_create(ctx.clone(), DeserializeStdin(lte_request("usb0")))
+            .await
+            .unwrap();
+        let pppoe = |device: &str| WanUplinkSetRequest {
+            id: "wan".to_string(),
+            label: "Primary WAN".to_string(),
+            ipv4: Some(WanIpv4SetRequest {
+                mode: WanIpv4Mode::Pppoe,
+                address: None,
+                netmask: None,
+                gateway: None,
+                username: Some("user".to_string()),
+                password: Some("pass".to_string()),
+                device: Some(device.to_string()),
+            }),
+            priority: 1,
+            weight: 1,
+            probe: WanProbe {
+                targets: vec!["1.1.1.1".to_string()],
+                interval: 5,
+                down: 3,
+                up: 3,
+            },
+        };
+
+        for device in ["usb0", "br-lan", "br-lan.99", "eth1;reboot"] {
+            let err = uplink_set(ctx.clone(), DeserializeStdin(pppoe(device)))
+                .await
+                .unwrap_err();
+            assert_eq!(err.kind, ErrorKind::InvalidValue, "{device}");
+        }
+        let network = std::fs::read_to_string(dir.path().join("network")).unwrap();
+        assert!(network.contains("option device 'eth1'"));
+
+        // Keeping its own device is not a conflict
+        uplink_set(ctx.clone(), DeserializeStdin(pppoe("eth1")))
+            .await
+            .unwrap();
+        uplink_set(ctx.clone(), DeserializeStdin(pppoe("eth2")))
+            .await
+            .unwrap();
+        let uplinks = uplink_list(ctx).await.unwrap();
+        assert_eq!(uplinks[0].ipv4.device.as_deref(), Some("eth2"));
+        assert_eq!(uplinks[1].ipv4.device.as_deref(), Some("usb0"));
+    }
+
+    fn ddns_request(uplink: Option<&str>) -> WanDdnsSetRequest {
+        WanDdnsSetRequest {
+            enabled: true,
+            provider: DdnsProvider::Duckdns,
+            hostname: Some("myhost.duckdns.org".to_string()),
+            username: None,
+            password: None,
+            token: Some("duck-token".to_string()),
+            zone: None,
+            uplink: uplink.map(String::from),
+        }
+    }
+
+    #[tokio::test]
+    async fn ddns_pins_to_an_uplink_until_it_is_deleted() {
+        let dir = tempfile::tempdir().unwrap();
+        write_uplink_fixture(dir.path(), "wan");
+        std::fs::write(dir.path().join("ddns"), "").unwrap();
+        let ctx = TestContext(dir.path().to_path_buf());
+
+        uplink_create(ctx.clone(), DeserializeStdin(lte_request("usb0")))
+            .await
+            .unwrap();
+        let err = ddns_set(
+            ctx.clone(),
+            DeserializeStdin(ddns_request(Some("wan_nope"))),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::NotFound);
+
+        ddns_set(ctx.clone(), DeserializeStdin(ddns_request(Some("wan_lte"))))
+            .await
+            .unwrap();
+        let raw = std::fs::read_to_string(dir.path().join("ddns")).unwrap();
+        assert!(raw.contains("option interface 'wan_lte'"));
+        assert!(raw.contains("option ip_network 'wan_lte'"));
+        assert_eq!(
+            ddns_get(ctx.clone()).await.unwrap().uplink.as_deref(),
+            Some("wan_lte")
+        );
+
+        uplink_delete(
+            ctx.clone(),
+            DeserializeStdin(WanUplinkDeleteRequest {
+                id: "wan_lte".to_string(),
+            }),
+        )
+        .await
+        .unwrap();
+        let raw = std::fs::read_to_string(dir.path().join("ddns")).unwrap();
+        assert!(raw.contains("option interface 'wan'"));
+        assert!(raw.contains("option ip_network 'wan'"));
+        assert_eq!(ddns_get(ctx).await.unwrap().uplink, None);
+    }
+
+    #[tokio::test]
+    async fn ddns_follows_the_uplink_mwan3_prefers() {
+        let dir = tempfile::tempdir().unwrap();
+        write_uplink_fixture(dir.path(), "wan");
+        let ctx = TestContext(dir.path().to_path_buf());
+        uplink_create(ctx.clone(), DeserializeStdin(lte_request("usb0")))
+            .await
+            .unwrap();
+
+        let arena = Arena::new();
+        let cfgs = parse_all(ctx.uci_root(), &arena, &["network", "startwrt"])
+            .await
+            .unwrap();
+        let status = |wan: &str, lte: &str| {
+            serde_json::json!({
+                "interfaces": { "wan": { "status": wan }, "wan_lte": { "status": lte } }
+            })
+        };
+
+        let failed_over = status("offline", "online");
+        assert_eq!(ddns_uplink(&cfgs, None, Some(&failed_over)), "wan_lte");
+        // Both up: the lower priority number carries the traffic
+        let both = status("online", "online");
+        assert_eq!(ddns_uplink(&cfgs, None, Some(&both)), "wan");
+        // Nothing online, or mwan3 idle: the primary
+        let none = status("offline", "offline");
+        assert_eq!(ddns_uplink(&cfgs, None, Some(&none)), "wan");
+        assert_eq!(ddns_uplink(&cfgs, None, None), "wan");
+        // A pin holds whatever the status
+        assert_eq!(ddns_uplink(&cfgs, Some("wan_lte"), Some(&both)), "wan_lte");
+        assert_eq!(
+            ddns_uplink(&cfgs, Some("wan_gone"), Some(&failed_over)),
+            "wan_lte"
+        );
+    }
+
+    #[tokio::test]
+    async fn uplink_pin_outbound_guard() {
+        let dir = tempfile::tempdir().unwrap();
+        write_uplink_fixture(dir.path(), "wan");
+        let ctx = TestContext(dir.path().to_path_buf());
+
+        uplink_create(ctx.clone(), DeserializeStdin(lte_request("usb0")))
+            .await
+            .unwrap();
+
+        let arena = Arena::new();
+        let cfgs = parse_all(ctx.uci_root(), &arena, &["network", "startwrt"])
+            .await
+            .unwrap();
+        crate::vpn_client::guard_outbound_available(&cfgs, "wan:wan_lte").unwrap();
+        crate::vpn_client::guard_outbound_available(&cfgs, "wan:wan").unwrap();
+        let err = crate::vpn_client::guard_outbound_available(&cfgs, "wan:nope").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::NotFound);
+    }
 }
@@ -159,6 +159,10 @@ pub struct NetworkInterface {
     pub netmask: Option<Ipv4Addr>,
     #[uci(default)]
     pub gateway: Option<String>,
+    /// Default-route metric. Each WAN uplink gets a distinct one so their
+    /// default routes can coexist in the main table for mwan3 to pick from.
+    #[uci(default)]
+    pub metric: Option<u32>,
     // PPPoE fields
     #[uci(default)]
     pub username: Option<String>,
@@ -538,4 +542,79 @@ pub struct DdnsService {
     pub domain: Option<String>,
     #[uci(default)]
     pub lookup_host: Option<String>,
+    /// StartWRT metadata: the WAN uplink the user pinned DDNS to. Without it
+    /// `interface`/`ip_network` follow the active uplink.
+    #[uci(default)]
+    pub _ddns_uplink: Option<String>,
+}
+
+/// The `config globals 'globals'` section of /etc/config/mwan3.
+#[derive(Debug, TypedSection, Default)]
+#[uci(ty = "globals")]
+pub struct Mwan3Globals {
+    /// Firewall mark bits mwan3 may use; must not overlap other fwmark users.
+    #[uci(default)]
+    pub mmx_mask: Option<String>,
+}
+
+/// An mwan3 `interface`: health tracking for one netifd interface, named after it.
+#[derive(Debug, TypedSection, Default)]
+#[uci(ty = "interface")]
+pub struct Mwan3Interface {
+    #[uci(default_value = false)]
+    pub enabled: bool,
+    #[uci(default)]
+    pub family: Option<String>,
+    #[uci(default)]
+    pub track_ip: Vec<String>,
+    #[uci(default)]
+    pub reliability: Option<u32>,
+    /// Seconds between probe rounds
+    #[uci(default)]
+    pub interval: Option<u32>,
+    /// Failed rounds before the interface is marked offline
+    #[uci(default)]
+    pub down: Option<u32>,
+    /// Successful rounds before an offline interface is marked online again
+    #[uci(default)]
+    pub up: Option<u32>,
+}
+
+/// An mwan3 `member`: an interface with a metric (lower is preferred) and a
+/// weight (load share among members of equal metric).
+#[derive(Debug, TypedSection, Default)]
+#[uci(ty = "member")]
+pub struct Mwan3Member {
+    pub interface: String,
+    pub metric: u32,
+    pub weight: u32,
+}
+
+#[derive(Debug, TypedSection, Default)]
+#[uci(ty = "policy")]
+pub struct Mwan3Policy {
+    #[uci(rename = "use_member")]
+    pub members: Vec<String>,
+    /// What to do when every member is offline: "unreachable", "blackhole" or
+    /// "default" (fall back to the main routing table)
+    #[uci(default)]
+    pub last_resort: Option<String>,
+}
+
+#[derive(Debug, TypedSection, Default)]
+#[uci(ty = "rule")]
+pub struct Mwan3Rule {
+    #[uci(default)]
+    pub src_ip: Option<String>,
+    #[uci(default)]
+    pub dest_ip: Option<String>,
+    #[uci(default)]
+    pub family: Option<String>,
+    #[uci(default)]
+    pub proto: Option<String>,
+    /// "1" keeps a connection's later flows on the uplink it first used, so
+    /// sites that bind sessions to a source address keep working under balancing
+    #[uci(default)]
+    pub sticky: Option<String>,
+    pub use_policy: String,
 }
@@ -140,6 +140,9 @@ cp "$PROJECT_DIR"/backend/hotplug/99-startwrt-proxy-arp "${FILES_DIR}/etc/hotplu
 chmod +x "${FILES_DIR}/etc/hotplug.d/iface/99-startwrt-proxy-arp"
 cp "$PROJECT_DIR"/backend/hotplug/99-startwrt-published-ports "${FILES_DIR}/etc/hotplug.d/iface/99-startwrt-published-ports"
 chmod +x "${FILES_DIR}/etc/hotplug.d/iface/99-startwrt-published-ports"
+mkdir -p "${FILES_DIR}/etc/hotplug.d/mwan3"
+cp "$PROJECT_DIR"/backend/hotplug/99-startwrt-ddns "${FILES_DIR}/etc/hotplug.d/mwan3/99-startwrt-ddns"
+chmod +x "${FILES_DIR}/etc/hotplug.d/mwan3/99-startwrt-ddns"
 
 # Custom nftables rules auto-included by fw4 (/etc/nftables.d/*.nft).
 # 10-startwrt-dnat-mark.nft marks DNAT-state reply traffic so port-forward
@@ -446,6 +446,16 @@ impl ExtendedVersion {
             Flavor(flavor) => &self.flavor == flavor,
         }
     }
+
+    /// Whether the upstream or downstream version is a prerelease
+    pub fn is_prerelease(&self) -> bool {
+        !self.upstream.prerelease.is_empty() || !self.downstream.prerelease.is_empty()
+    }
+}
+impl AsRef<ExtendedVersion> for ExtendedVersion {
+    fn as_ref(&self) -> &ExtendedVersion {
+        self
+    }
 }
 
 // Left is inversion, Right is identity
@@ -578,13 +588,23 @@ impl VersionRange {
 
     fn is_expr(&self) -> bool {
         match self {
-            Self::Anchor(_, _) | Self::Any | Self::None => false,
+            Self::Anchor(_, _) | Self::Any | Self::None | Self::Flavor(_) => false,
             _ => true,
         }
     }
 
-    fn write_with_parens(self: &Box<Self>, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.is_expr() {
+    /// Writes an operand of `parent`. An `And` within an `And`, or an `Or` within an `Or`, needs
+    /// no parentheses, since both are associative.
+    fn write_with_parens(
+        self: &Box<Self>,
+        parent: &Self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        let same_op = matches!(
+            (parent, self.deref()),
+            (Self::And(_, _), Self::And(_, _)) | (Self::Or(_, _), Self::Or(_, _))
+        );
+        if self.is_expr() && !same_op {
             write!(f, "({})", self.deref())
         } else {
             write!(f, "{}", self.deref())
@@ -605,6 +625,65 @@ impl VersionRange {
     pub fn intersects(&self, other: &Self) -> bool {
         sat::Tables::and(sat::tables_of(self), sat::tables_of(other)).satisfiable()
     }
+
+    /// Returns `true` if every [`ExtendedVersion`] that satisfies this range also satisfies
+    /// `other`, i.e. this range implies `other`.
+    pub fn is_subset_of(&self, other: &Self) -> bool {
+        !sat::Tables::and(sat::tables_of(self), sat::tables_of(other).not()).satisfiable()
+    }
+
+    /// The range satisfied by exactly the versions that do not satisfy this one, in
+    /// [`normalize`](Self::normalize)d form.
+    pub fn complement(&self) -> Self {
+        sat::tables_of(self).not().into_range()
+    }
+
+    /// An equivalent range in canonical form: for each flavor in turn, a disjunction of disjoint
+    /// intervals, e.g. `>=1.2:0 <2:0 || =3:0`, with single excluded versions written as `!=`.
+    ///
+    /// A range that also accepts flavors it never mentions is written as the negation of its
+    /// normalized complement instead, e.g. `!(>=1:0 <2:0)`. Equivalent ranges normalize to the
+    /// same result.
+    pub fn normalize(&self) -> Self {
+        sat::tables_of(self).into_range()
+    }
+
+    /// The candidate that satisfies this range and ranks highest by `preference`, if any.
+    pub fn best<V: AsRef<ExtendedVersion>>(
+        &self,
+        candidates: impl IntoIterator<Item = V>,
+        preference: &Preference,
+    ) -> Option<V> {
+        candidates
+            .into_iter()
+            .filter(|v| v.as_ref().satisfies(self))
+            .max_by(|a, b| preference.cmp(a.as_ref(), b.as_ref()))
+    }
+
+    /// The best candidate of each flavor that satisfies this range, by `preference`, in the
+    /// order [`Preference::cmp`] ranks them, best first.
+    pub fn best_per_flavor<V: AsRef<ExtendedVersion>>(
+        &self,
+        candidates: impl IntoIterator<Item = V>,
+        preference: &Preference,
+    ) -> Vec<V> {
+        let mut best: Vec<V> = Vec::new();
+        for v in candidates
+            .into_iter()
+            .filter(|v| v.as_ref().satisfies(self))
+        {
+            match best
+                .iter_mut()
+                .find(|b| b.as_ref().flavor == v.as_ref().flavor)
+            {
+                Some(b) if preference.cmp(v.as_ref(), b.as_ref()).is_gt() => *b = v,
+                Some(_) => (),
+                None => best.push(v),
+            }
+        }
+        best.sort_by(|a, b| preference.cmp(b.as_ref(), a.as_ref()));
+        best
+    }
 }
 
 mod sat {
@@ -760,7 +839,7 @@ mod sat {
             Tables::Map(m)
         }
 
-        fn not(self) -> Self {
+        pub(super) fn not(self) -> Self {
             match self {
                 Tables::True => Tables::False,
                 Tables::False => Tables::True,
@@ -807,23 +886,40 @@ mod sat {
                 (Tables::True, _) | (_, Tables::True) => Tables::True,
                 (Tables::False, x) | (x, Tables::False) => x,
                 (Tables::Map(am), Tables::Map(bm)) => {
-                    let mut out = am;
-                    for (flavor, table) in bm {
-                        match out.remove(&flavor) {
-                            Some(prev) => {
-                                let merged = Table::zip(&prev, &table, |x, y| x || y);
-                                out.insert(flavor, merged);
-                            }
-                            None => {
-                                out.insert(flavor, table);
-                            }
-                        }
-                    }
-                    Tables::Map(out)
+                    Tables::Map(Self::partition(am.into_iter().chain(bm).collect()))
                 }
             }
         }
 
+        /// Rekeys `tables` by a partition of the flavor space: each flavor mentioned by a key,
+        /// plus every other flavor. The keys of a union can overlap (e.g. `#a` and "not `#b`"),
+        /// and `not` is only sound when every flavor falls under exactly one key.
+        fn partition(tables: Vec<(FlavorAtom, Table)>) -> BTreeMap<FlavorAtom, Table> {
+            let flavors: BTreeSet<Option<InternedString>> = tables
+                .iter()
+                .flat_map(|(atom, _)| match atom {
+                    FlavorAtom::Flavor(f) => vec![f.clone()],
+                    FlavorAtom::FlavorNot(set) => set.iter().cloned().collect(),
+                })
+                .collect();
+            flavors
+                .iter()
+                .cloned()
+                .map(FlavorAtom::Flavor)
+                .chain([FlavorAtom::FlavorNot(flavors.clone())])
+                .map(|part| {
+                    // every part is either within a key or disjoint from it
+                    let table = tables
+                        .iter()
+                        .filter(|(atom, _)| FlavorAtom::and(&part, atom).as_ref() == Some(&part))
+                        .fold(Table::from_value(false), |acc, (_, table)| {
+                            Table::zip(&acc, table, |x, y| x || y)
+                        });
+                    (part, table)
+                })
+                .collect()
+        }
+
         pub(super) fn satisfiable(&self) -> bool {
             match self {
                 Tables::True => true,
@@ -831,6 +927,88 @@ mod sat {
                 Tables::Map(m) => m.values().any(|t| t.values.iter().any(|&v| v)),
             }
         }
+
+        /// Reads the tables back as a range in the form described by
+        /// [`VersionRange::normalize`].
+        pub(super) fn into_range(self) -> VersionRange {
+            let m = match self {
+                Tables::True => return VersionRange::Any,
+                Tables::False => return VersionRange::None,
+                Tables::Map(m) => m,
+            };
+            // the table for unmentioned flavors is constant, so its complement is `false`
+            if m.iter().any(|(atom, table)| {
+                matches!(atom, FlavorAtom::FlavorNot(_)) && table.values.iter().any(|&v| v)
+            }) {
+                return match Tables::Map(m).not().into_range() {
+                    VersionRange::Any => VersionRange::None,
+                    VersionRange::None => VersionRange::Any,
+                    VersionRange::Anchor(EQ, v) => VersionRange::Anchor(NEQ, v),
+                    r => VersionRange::Not(Box::new(r)),
+                };
+            }
+            m.iter()
+                .filter_map(|(atom, table)| match atom {
+                    FlavorAtom::Flavor(f) => Some(table.to_range(f)),
+                    FlavorAtom::FlavorNot(_) => None,
+                })
+                .fold(VersionRange::None, VersionRange::or)
+        }
+    }
+
+    impl Table {
+        /// Reads the table for `flavor` back as a disjunction of intervals, where a run of
+        /// `true` segments broken only by single versions is one interval excluding them.
+        fn to_range(&self, flavor: &Option<InternedString>) -> VersionRange {
+            let version = |p: &Point| ExtendedVersion {
+                flavor: flavor.clone(),
+                upstream: p.upstream.clone(),
+                downstream: p.downstream.clone(),
+            };
+            let same_version =
+                |a: &Point, b: &Point| a.upstream == b.upstream && a.downstream == b.downstream;
+            let interval = |lower: Option<&Point>, upper: Option<&Point>, excluded: Vec<&Point>| {
+                let bounds = match (lower, upper) {
+                    (None, None) => VersionRange::Flavor(flavor.clone()),
+                    (Some(l), Some(u)) if same_version(l, u) => {
+                        VersionRange::Anchor(EQ, version(l))
+                    }
+                    (l, u) => VersionRange::and(
+                        l.map_or(VersionRange::Any, |l| {
+                            VersionRange::Anchor(if l.side < 0 { GTE } else { GT }, version(l))
+                        }),
+                        u.map_or(VersionRange::Any, |u| {
+                            VersionRange::Anchor(if u.side < 0 { LT } else { LTE }, version(u))
+                        }),
+                    ),
+                };
+                excluded.into_iter().fold(bounds, |r, p| {
+                    VersionRange::and(r, VersionRange::Anchor(NEQ, version(p)))
+                })
+            };
+
+            let mut res = VersionRange::None;
+            // the lower bound and excluded versions of the interval being read
+            let mut run: Option<(Option<&Point>, Vec<&Point>)> = None;
+            for (i, &value) in self.values.iter().enumerate() {
+                let below = i.checked_sub(1).map(|i| &self.points[i]);
+                if value {
+                    run.get_or_insert((below, Vec::new()));
+                } else if let Some((lower, mut excluded)) = run.take() {
+                    match (below, self.points.get(i)) {
+                        (Some(a), Some(b)) if same_version(a, b) => {
+                            excluded.push(a);
+                            run = Some((lower, excluded));
+                        }
+                        _ => res = VersionRange::or(res, interval(lower, below, excluded)),
+                    }
+                }
+            }
+            if let Some((lower, excluded)) = run {
+                res = VersionRange::or(res, interval(lower, None, excluded));
+            }
+            res
+        }
     }
 
     pub(super) fn tables_of(range: &VersionRange) -> Tables {
@@ -885,18 +1063,18 @@ impl fmt::Display for VersionRange {
             Anchor(LTE, v) => write!(f, "<={}", v),
             Anchor(GT, v) => write!(f, ">{}", v),
             And(a, b) => {
-                a.write_with_parens(f)?;
+                a.write_with_parens(self, f)?;
                 write!(f, " ")?;
-                b.write_with_parens(f)
+                b.write_with_parens(self, f)
             }
             Or(a, b) => {
-                a.write_with_parens(f)?;
+                a.write_with_parens(self, f)?;
                 write!(f, " || ")?;
-                b.write_with_parens(f)
+                b.write_with_parens(self, f)
             }
             Not(a) => {
                 write!(f, "!")?;
-                a.write_with_parens(f)
+                a.write_with_parens(self, f)
             }
             Any => write!(f, "*"),
             None => write!(f, "!"),
@@ -956,6 +1134,68 @@ impl Empty for AllRange {
 }
 impl Monoid for AllRange {}
 
+/// How [`VersionRange::best`] ranks versions: those of the preferred flavor first, then, if
+/// stable versions are preferred, releases over prereleases, then the highest version (or the
+/// lowest, if [`min`](Self::min)).
+///
+/// Versions of different flavors are incomparable, so between them the one without a flavor, and
+/// then the alphabetically first flavor, ranks higher.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Preference {
+    flavor: Option<Option<InternedString>>,
+    stable: bool,
+    min: bool,
+}
+impl Preference {
+    /// Prefers the highest version
+    pub fn max() -> Self {
+        Self::default()
+    }
+
+    /// Prefers the lowest version
+    pub fn min() -> Self {
+        Self {
+            min: true,
+            ..Self::default()
+        }
+    }
+
+    /// Prefers versions of `flavor`, or versions without a flavor if it is empty
+    pub fn prefer_flavor(mut self, flavor: impl Into<InternedString>) -> Self {
+        self.flavor = Some(Some(flavor.into()).filter(|f| !f.is_empty()));
+        self
+    }
+
+    /// Prefers any release over any prerelease
+    pub fn prefer_stable(mut self) -> Self {
+        self.stable = true;
+        self
+    }
+
+    /// A total order on versions, where [`Ordering::Greater`] means `a` is preferred over `b`.
+    pub fn cmp(&self, a: &ExtendedVersion, b: &ExtendedVersion) -> Ordering {
+        let preferred = |v: &ExtendedVersion| self.flavor.as_ref() == Some(&v.flavor);
+        preferred(a)
+            .cmp(&preferred(b))
+            .then_with(|| {
+                if self.stable {
+                    b.is_prerelease().cmp(&a.is_prerelease())
+                } else {
+                    Ordering::Equal
+                }
+            })
+            .then_with(|| match a.partial_cmp(b) {
+                Some(ord) if self.min => ord.reverse(),
+                Some(ord) => ord,
+                None => match (&a.flavor, &b.flavor) {
+                    (None, _) => Ordering::Greater,
+                    (_, None) => Ordering::Less,
+                    (a, b) => b.cmp(a),
+                },
+            })
+    }
+}
+
 #[derive(Parser)]
 #[grammar = "grammar.pest"]
 struct Grammar;
@@ -0,0 +1,562 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use yasi::InternedString;
+
+use crate::exver::{
+    ExtendedVersion, ParseError, PreReleaseSegment, Version, VersionRange, EQ, GT, GTE, LT, LTE,
+};
+
+/// A [SemVer 2.0.0](https://semver.org/spec/v2.0.0.html) version, as published by an upstream
+/// project.
+///
+/// Parsing and displaying round-trip exactly, build metadata included. exver has no place for
+/// build metadata, and it takes no part in SemVer precedence, so converting into a [`Version`] or
+/// [`ExtendedVersion`] drops only it, and keeps the order between versions.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SemVer {
+    version: Version,
+    build: Vec<InternedString>,
+}
+impl SemVer {
+    pub fn version(&self) -> &Version {
+        &self.version
+    }
+
+    pub fn build(&self) -> &[InternedString] {
+        &self.build
+    }
+
+    pub fn with_build(mut self, build: impl IntoIterator<Item = InternedString>) -> Self {
+        self.build = build.into_iter().collect();
+        self
+    }
+}
+impl fmt::Display for SemVer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.version)?;
+        if !self.build.is_empty() {
+            let build = self.build.iter().map(|id| &**id).collect::<Vec<_>>();
+            write!(f, "+{}", build.join("."))?;
+        }
+        Ok(())
+    }
+}
+impl std::str::FromStr for SemVer {
+    type Err = ParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err_fn = |reason| ParseError::InvalidVersion(s.to_owned(), reason);
+        let (version, build) = s.split_once('+').map_or((s, None), |(v, b)| (v, Some(b)));
+        let core = version.split_once('-').map_or(version, |(core, _)| core);
+        let numbers = core.split('.').collect::<Vec<_>>();
+        if numbers.len() != 3 {
+            return Err(err_fn("expected MAJOR.MINOR.PATCH"));
+        }
+        if numbers
+            .iter()
+            .any(|n| n.is_empty() || !n.chars().all(|c| c.is_ascii_digit()))
+        {
+            return Err(err_fn("invalid numeric identifier"));
+        }
+        if numbers.iter().any(|n| n.len() > 1 && n.starts_with('0')) {
+            return Err(err_fn("numeric identifier may not have leading zero"));
+        }
+        let build = build
+            .map(|build| {
+                build
+                    .split('.')
+                    .map(|id| {
+                        if id.is_empty() {
+                            Err(err_fn("build identifier may not be empty"))
+                        } else if !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+                            Err(err_fn("invalid character in build identifier"))
+                        } else {
+                            Ok(InternedString::from(id))
+                        }
+                    })
+                    .collect::<Result<_, _>>()
+            })
+            .transpose()?
+            .unwrap_or_default();
+        Ok(Self {
+            version: version.parse()?,
+            build,
+        })
+    }
+}
+impl TryFrom<Version> for SemVer {
+    type Error = ParseError;
+    /// Pads `version` to three numbers, or fails if it has more that are not zero.
+    fn try_from(version: Version) -> Result<Self, Self::Error> {
+        let mut number = version.number().to_vec();
+        if number.len() > 3 {
+            if number[3..].iter().any(|n| *n != 0) {
+                return Err(ParseError::InvalidVersion(
+                    version.to_string(),
+                    "SemVer has only MAJOR.MINOR.PATCH",
+                ));
+            }
+            number.truncate(3);
+        }
+        number.resize(3, 0);
+        Ok(Self {
+            version: Version::new(number, version.prerelease().iter().cloned()),
+            build: Vec::new(),
+        })
+    }
+}
+impl From<SemVer> for Version {
+    fn from(value: SemVer) -> Self {
+        value.version
+    }
+}
+impl From<SemVer> for ExtendedVersion {
+    fn from(value: SemVer) -> Self {
+        ExtendedVersion::from(value.version)
+    }
+}
+#[cfg(feature = "serde")]
+impl serde::Serialize for SemVer {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{}", self))
+    }
+}
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for SemVer {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// A Debian package version, `[epoch:]upstream_version[-debian_revision]`, as described in the
+/// [Debian Policy Manual](https://www.debian.org/doc/debian-policy/ch-controlfields.html#version).
+///
+/// Parsing and displaying round-trip exactly, and versions compare as `dpkg` compares them,
+/// including `~` sorting before everything, even the end of the version.
+///
+/// Converting into an [`ExtendedVersion`] maps the upstream version to the upstream component and
+/// the Debian revision to the downstream one. It keeps the order between versions, and so only
+/// succeeds for versions exver can order the same way: no epoch, and numbers separated by dots,
+/// optionally followed by a `~` prerelease, e.g. `1.2.3~rc1-2` to `1.2.3-rc.1:2`. Letters and
+/// digits in a prerelease are split into separate segments, since `dpkg` compares their runs
+/// separately. `dpkg` orders `1.0` before `1.0.0`, which exver considers equal.
+#[derive(Clone, Debug)]
+pub struct DebVersion {
+    epoch: Option<usize>,
+    upstream: InternedString,
+    revision: Option<InternedString>,
+}
+impl DebVersion {
+    pub fn epoch(&self) -> usize {
+        self.epoch.unwrap_or(0)
+    }
+
+    pub fn upstream(&self) -> &str {
+        &self.upstream
+    }
+
+    pub fn revision(&self) -> Option<&str> {
+        self.revision.as_deref()
+    }
+}
+impl fmt::Display for DebVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(epoch) = self.epoch {
+            write!(f, "{epoch}:")?;
+        }
+        write!(f, "{}", self.upstream)?;
+        if let Some(revision) = &self.revision {
+            write!(f, "-{revision}")?;
+        }
+        Ok(())
+    }
+}
+impl std::str::FromStr for DebVersion {
+    type Err = ParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err_fn = |reason| ParseError::InvalidVersion(s.to_owned(), reason);
+        let (epoch, rest) = match s.split_once(':') {
+            Some((epoch, rest)) => (
+                Some(
+                    epoch
+                        .parse::<usize>()
+                        .map_err(|_| err_fn("invalid epoch"))?,
+                ),
+                rest,
+            ),
+            None => (None, s),
+        };
+        let (upstream, revision) = rest
+            .rsplit_once('-')
+            .map_or((rest, None), |(u, r)| (u, Some(r)));
+        if !upstream.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(err_fn("upstream version must start with a digit"));
+        }
+        if !upstream
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '+' | '~' | '-' | ':'))
+        {
+            return Err(err_fn("invalid character in upstream version"));
+        }
+        if let Some(revision) = revision {
+            if revision.is_empty()
+                || !revision
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '+' | '~'))
+            {
+                return Err(err_fn("invalid debian revision"));
+            }
+        }
+        Ok(Self {
+            epoch,
+            upstream: upstream.into(),
+            revision: revision.map(From::from),
+        })
+    }
+}
+impl PartialEq for DebVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for DebVersion {}
+impl Ord for DebVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.epoch()
+            .cmp(&other.epoch())
+            .then_with(|| deb_cmp(&self.upstream, &other.upstream))
+            .then_with(|| {
+                deb_cmp(
+                    self.revision().unwrap_or_default(),
+                    other.revision().unwrap_or_default(),
+                )
+            })
+    }
+}
+impl PartialOrd for DebVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl TryFrom<&DebVersion> for ExtendedVersion {
+    type Error = ParseError;
+    fn try_from(value: &DebVersion) -> Result<Self, Self::Error> {
+        let err_fn = |reason| ParseError::InvalidVersion(value.to_string(), reason);
+        if value.epoch() != 0 {
+            return Err(err_fn("an epoch has no equivalent in exver"));
+        }
+        let upstream = deb_to_version(&value.upstream).ok_or_else(|| {
+            err_fn("only dotted numbers with an optional ~ prerelease can be converted")
+        })?;
+        let downstream = value
+            .revision()
+            .map(|r| {
+                deb_to_version(r).ok_or_else(|| {
+                    err_fn("only dotted numbers with an optional ~ prerelease can be converted")
+                })
+            })
+            .transpose()?
+            .unwrap_or_default();
+        Ok(ExtendedVersion::new(upstream, downstream))
+    }
+}
+impl TryFrom<DebVersion> for ExtendedVersion {
+    type Error = ParseError;
+    fn try_from(value: DebVersion) -> Result<Self, Self::Error> {
+        ExtendedVersion::try_from(&value)
+    }
+}
+
+/// `dpkg`'s comparison of an upstream version or revision: alternating runs of non-digits,
+/// compared by character with letters first and `~` before anything, and of digits, compared
+/// numerically.
+fn deb_cmp(a: &str, b: &str) -> Ordering {
+    fn order(c: Option<u8>) -> i32 {
+        match c {
+            None => 0,
+            Some(c) if c.is_ascii_digit() => 0,
+            Some(c) if c.is_ascii_alphabetic() => c as i32,
+            Some(b'~') => -1,
+            Some(c) => c as i32 + 256,
+        }
+    }
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let (mut i, mut j) = (0, 0);
+    let is_digit = |s: &[u8], i: usize| s.get(i).is_some_and(|c| c.is_ascii_digit());
+    while i < a.len() || j < b.len() {
+        while (i < a.len() && !is_digit(a, i)) || (j < b.len() && !is_digit(b, j)) {
+            let (ac, bc) = (order(a.get(i).copied()), order(b.get(j).copied()));
+            if ac != bc {
+                return ac.cmp(&bc);
+            }
+            i += 1;
+            j += 1;
+        }
+        while a.get(i) == Some(&b'0') {
+            i += 1;
+        }
+        while b.get(j) == Some(&b'0') {
+            j += 1;
+        }
+        let mut first_diff = Ordering::Equal;
+        while is_digit(a, i) && is_digit(b, j) {
+            if first_diff == Ordering::Equal {
+                first_diff = a[i].cmp(&b[j]);
+            }
+            i += 1;
+            j += 1;
+        }
+        if is_digit(a, i) {
+            return Ordering::Greater;
+        }
+        if is_digit(b, j) {
+            return Ordering::Less;
+        }
+        if first_diff != Ordering::Equal {
+            return first_diff;
+        }
+    }
+    Ordering::Equal
+}
+
+/// Converts `N(.N)*[~prerelease]`, or returns `None` for anything exver would order differently.
+/// The prerelease is split into runs of letters and digits, so it may not contain a `.`: exver
+/// would read `rc.1` and `rc1` alike, where dpkg sorts `rc.1` after `rc10`.
+fn deb_to_version(s: &str) -> Option<Version> {
+    let (number, prerelease) = s.split_once('~').map_or((s, None), |(n, p)| (n, Some(p)));
+    let number = number
+        .split('.')
+        .map(|n| {
+            if !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()) {
+                n.parse::<usize>().ok()
+            } else {
+                None
+            }
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let mut segments = Vec::new();
+    if let Some(seg) = prerelease {
+        if seg.is_empty() || !seg.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return None;
+        }
+        let mut rest = seg;
+        while let Some(c) = rest.chars().next() {
+            let digits = c.is_ascii_digit();
+            let len = rest
+                .find(|c: char| c.is_ascii_digit() != digits)
+                .unwrap_or(rest.len());
+            let (run, tail) = rest.split_at(len);
+            segments.push(if digits {
+                if run.len() > 1 && run.starts_with('0') {
+                    return None;
+                }
+                PreReleaseSegment::Number(run.parse().ok()?)
+            } else {
+                PreReleaseSegment::from(run)
+            });
+            rest = tail;
+        }
+    }
+    Some(Version::new(number, segments))
+}
+
+/// A version with components left out or wildcarded, as npm and Cargo ranges allow, e.g. `1.2`
+/// or `1.x`.
+struct Partial {
+    number: Vec<usize>,
+    /// Whether a component of `number` was written as a wildcard, rather than left out
+    wildcard: bool,
+    prerelease: Vec<PreReleaseSegment>,
+}
+impl Partial {
+    fn parse(s: &str) -> Result<Self, ParseError> {
+        let err_fn = |reason| ParseError::InvalidVersion(s.to_owned(), reason);
+        let version = s.strip_prefix(['v', 'V']).unwrap_or(s);
+        // build metadata plays no part in ranges
+        let version = version.split_once('+').map_or(version, |(v, _)| v);
+        let (core, prerelease) = version
+            .split_once('-')
+            .map_or((version, None), |(c, p)| (c, Some(p)));
+        let mut number = Vec::new();
+        let mut wildcard = false;
+        for (i, seg) in core.split('.').enumerate() {
+            if i >= 3 {
+                return Err(err_fn("expected at most MAJOR.MINOR.PATCH"));
+            }
+            if matches!(seg, "x" | "X" | "*") {
+                wildcard = true;
+                continue;
+            }
+            if seg.is_empty() || !seg.chars().all(|c| c.is_ascii_digit()) {
+                return Err(err_fn("invalid numeric identifier"));
+            }
+            if number.len() < i {
+                return Err(err_fn("wildcard may only be followed by wildcards"));
+            }
+            number.push(
+                seg.parse()
+                    .map_err(|_| err_fn("invalid numeric identifier"))?,
+            );
+        }
+        let prerelease = match prerelease {
+            Some(_) if number.len() < 3 => {
+                return Err(err_fn("prerelease requires MAJOR.MINOR.PATCH"));
+            }
+            Some(p) => format!("0-{p}").parse::<Version>()?.prerelease().to_vec(),
+            None => Vec::new(),
+        };
+        Ok(Self {
+            number,
+            wildcard,
+            prerelease,
+        })
+    }
+
+    /// The lowest version matching the partial version
+    fn floor(&self) -> ExtendedVersion {
+        let mut number = self.number.clone();
+        number.resize(3, 0);
+        Version::new(number, self.prerelease.iter().cloned()).into()
+    }
+
+    /// The lowest version above `number[..=idx]`
+    fn bump(&self, idx: usize) -> Version {
+        let mut number = self.number[..=idx].to_vec();
+        number[idx] += 1;
+        number.resize(3, 0);
+        Version::new(number, [])
+    }
+
+    fn comparator(&self, op: &str) -> VersionRange {
+        // an upper bound excludes the prereleases of the version it names
+        let below = |v: Version| {
+            VersionRange::anchor(LT, v.with_prerelease([PreReleaseSegment::Number(0)]).into())
+        };
+        let between = |idx: usize| {
+            VersionRange::and(
+                VersionRange::anchor(GTE, self.floor()),
+                below(self.bump(idx)),
+            )
+        };
+        let Some(last) = self.number.len().checked_sub(1) else {
+            return match op {
+                "<" | ">" => VersionRange::None,
+                _ => VersionRange::Any,
+            };
+        };
+        let exact = self.number.len() == 3;
+        match op {
+            "" | "=" if exact => VersionRange::anchor(EQ, self.floor()),
+            "" | "=" => between(last),
+            ">" if exact => VersionRange::anchor(GT, self.floor()),
+            ">" => VersionRange::anchor(GTE, self.bump(last).into()),
+            ">=" => VersionRange::anchor(GTE, self.floor()),
+            "<" if exact => VersionRange::anchor(LT, self.floor()),
+            "<" => below(self.floor().upstream().clone()),
+            "<=" if exact => VersionRange::anchor(LTE, self.floor()),
+            "<=" => below(self.bump(last)),
+            "~" => between(last.min(1)),
+            _ => {
+                // "^": the first number that is not zero, or the last one given, may not change
+                between(self.number.iter().position(|n| *n != 0).unwrap_or(last))
+            }
+        }
+    }
+}
+
+/// Splits a comparator into its operator and version.
+fn split_op(s: &str) -> (&str, &str) {
+    let len = s
+        .find(|c: char| !matches!(c, '<' | '>' | '=' | '~' | '^'))
+        .unwrap_or(s.len());
+    let (op, version) = s.split_at(len);
+    (op, version.trim_start())
+}
+
+fn check_op<'a>(input: &str, op: &'a str, ops: &[&str]) -> Result<&'a str, ParseError> {
+    if ops.contains(&op) {
+        Ok(op)
+    } else {
+        Err(ParseError::InvalidVersionRange(input.to_owned(), None))
+    }
+}
+
+impl VersionRange {
+    /// Translates an [npm](https://github.com/npm/node-semver#ranges) range, e.g.
+    /// `^1.2.3 || >=2.0.0 <3 || 4.x || 1.2.3 - 1.4`, into a range of upstream versions, which
+    /// [`Version::satisfies`] checks exactly.
+    ///
+    /// Upper bounds that leave out a version's prereleases are written with a `-0` prerelease,
+    /// e.g. `~1.2` is `>=1.2.0:0 <1.3.0-0:0`. Unlike npm, the range then matches prereleases of
+    /// other versions within it; prefer stable versions with
+    /// [`Preference::prefer_stable`](crate::Preference::prefer_stable) instead.
+    pub fn from_npm(s: &str) -> Result<Self, ParseError> {
+        s.split("||")
+            .map(|range| {
+                let range = range.trim();
+                if let Some((lower, upper)) = range.split_once(" - ") {
+                    let (lower, upper) =
+                        (Partial::parse(lower.trim())?, Partial::parse(upper.trim())?);
+                    return Ok(VersionRange::and(
+                        lower.comparator(">="),
+                        upper.comparator("<="),
+                    ));
+                }
+                // an operator may be separated from its version by spaces
+                let mut comparators = Vec::<String>::new();
+                let mut pending = String::new();
+                for word in range.split_whitespace() {
+                    pending.push_str(word);
+                    if !split_op(&pending).1.is_empty() {
+                        comparators.push(std::mem::take(&mut pending));
+                    }
+                }
+                if !pending.is_empty() {
+                    return Err(ParseError::InvalidVersionRange(s.to_owned(), None));
+                }
+                comparators
+                    .iter()
+                    .try_fold(VersionRange::Any, |acc, comparator| {
+                        let (op, version) = split_op(comparator);
+                        let op = match op {
+                            "~>" => "~",
+                            op => check_op(s, op, &["", "=", "<", "<=", ">", ">=", "~", "^"])?,
+                        };
+                        Ok(VersionRange::and(
+                            acc,
+                            Partial::parse(version)?.comparator(op),
+                        ))
+                    })
+            })
+            .try_fold(VersionRange::None, |acc, range| {
+                Ok(VersionRange::or(acc, range?))
+            })
+    }
+
+    /// Translates a [Cargo](https://doc.rust-lang.org/cargo/reference/specifying-dependencies.html)
+    /// version requirement, e.g. `>=1.2, <1.5` or `1.2.*`, into a range of upstream versions, as
+    /// [`from_npm`](Self::from_npm) does. A bare version is a caret requirement, unless it has
+    /// a wildcard.
+    pub fn from_cargo(s: &str) -> Result<Self, ParseError> {
+        s.split(',')
+            .map(|comparator| {
+                let (op, version) = split_op(comparator.trim());
+                if version.is_empty() {
+                    return Err(ParseError::InvalidVersionRange(s.to_owned(), None));
+                }
+                let op = check_op(s, op, &["", "=", "<", "<=", ">", ">=", "~", "^"])?;
+                let partial = Partial::parse(version)?;
+                // a wildcard in a bare version matches it, rather than being a caret requirement
+                let op = if op.is_empty() && !partial.wildcard {
+                    "^"
+                } else {
+                    op
+                };
+                Ok(partial.comparator(op))
+            })
+            .try_fold(VersionRange::Any, |acc, comparator| {
+                Ok(VersionRange::and(acc, comparator?))
+            })
+    }
+}
@@ -7,9 +7,13 @@
 /// or let the issue persist until the next update. Neither of these promote good user experiences, for different reasons.
 /// This module extends the semver standard linked above with a 4th digit, which is given PATCH semantics.
 pub mod exver;
+pub mod interop;
+pub mod resolver;
 pub use emver;
 
 pub use crate::exver::*;
+pub use crate::interop::*;
+pub use crate::resolver::*;
 
 #[cfg(test)]
 mod test;
@@ -0,0 +1,205 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::exver::{ExtendedVersion, Preference, VersionRange};
+
+/// A range that a version of some package must satisfy, and what imposed it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Requirement<K> {
+    pub range: VersionRange,
+    /// The chosen package version that depends on it, or `None` for a requirement passed to
+    /// [`Resolver::require`].
+    pub required_by: Option<(K, ExtendedVersion)>,
+}
+
+/// Why [`Resolver::resolve`] failed: no version of `package` could be chosen that satisfies all of
+/// `requirements`.
+///
+/// If no available version satisfies them, `requirements` is a minimal such set, and empty if the
+/// package has no versions at all. Otherwise they clash with a version already chosen for
+/// `package`, and all of them are listed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Conflict<K> {
+    pub package: K,
+    pub requirements: Vec<Requirement<K>>,
+}
+impl<K: fmt::Display> fmt::Display for Conflict<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.requirements.is_empty() {
+            return write!(f, "no version of {} is available", self.package);
+        }
+        write!(f, "no version of {} satisfies ", self.package)?;
+        for (i, req) in self.requirements.iter().enumerate() {
+            if i > 0 {
+                write!(f, " and ")?;
+            }
+            write!(f, "{}", req.range.normalize())?;
+            if let Some((package, version)) = &req.required_by {
+                write!(f, " (required by {package}@{version})")?;
+            }
+        }
+        Ok(())
+    }
+}
+impl<K: fmt::Debug + fmt::Display> std::error::Error for Conflict<K> {}
+
+/// Chooses one version of every required package, such that every requirement is satisfied,
+/// including the dependencies of the versions chosen.
+///
+/// Packages are decided in key order, trying their candidates best first by the [`Preference`]
+/// and backtracking on a conflict, so each package gets the best version that still allows a
+/// solution for the ones after it. The search is exhaustive, and meant for the handful of
+/// packages that depend on each other on one server, not for a whole registry.
+#[derive(Clone, Debug)]
+pub struct Resolver<K> {
+    preference: Preference,
+    available: BTreeMap<K, Vec<Candidate<K>>>,
+    requirements: Vec<(K, VersionRange)>,
+}
+
+/// A version, and the ranges of its dependencies
+type Candidate<K> = (ExtendedVersion, Vec<(K, VersionRange)>);
+
+#[derive(Clone)]
+struct State<K> {
+    chosen: BTreeMap<K, ExtendedVersion>,
+    requirements: BTreeMap<K, Vec<Requirement<K>>>,
+}
+
+impl<K: Ord + Clone> Resolver<K> {
+    pub fn new(preference: Preference) -> Self {
+        Self {
+            preference,
+            available: BTreeMap::new(),
+            requirements: Vec::new(),
+        }
+    }
+
+    /// Makes `version` of `package` a candidate, which, if chosen, requires a version of each
+    /// of `dependencies` in the given range.
+    pub fn add_version(
+        &mut self,
+        package: K,
+        version: ExtendedVersion,
+        dependencies: impl IntoIterator<Item = (K, VersionRange)>,
+    ) -> &mut Self {
+        self.available
+            .entry(package)
+            .or_default()
+            .push((version, dependencies.into_iter().collect()));
+        self
+    }
+
+    /// Requires a version of `package` in `range`.
+    pub fn require(&mut self, package: K, range: VersionRange) -> &mut Self {
+        self.requirements.push((package, range));
+        self
+    }
+
+    /// The chosen version of every package that is required, directly or as a dependency of
+    /// another chosen version.
+    ///
+    /// If there is no solution, the conflict is the first one met while trying the preferred
+    /// candidates.
+    pub fn resolve(&self) -> Result<BTreeMap<K, ExtendedVersion>, Conflict<K>> {
+        let mut state = State {
+            chosen: BTreeMap::new(),
+            requirements: BTreeMap::new(),
+        };
+        for (package, range) in &self.requirements {
+            state
+                .requirements
+                .entry(package.clone())
+                .or_insert_with(Vec::new)
+                .push(Requirement {
+                    range: range.clone(),
+                    required_by: None,
+                });
+        }
+        let mut conflict = None;
+        if self.search(&mut state, &mut conflict) {
+            Ok(state.chosen)
+        } else {
+            Err(conflict.expect("a failed search records a conflict"))
+        }
+    }
+
+    fn versions(&self, package: &K) -> &[Candidate<K>] {
+        self.available.get(package).map_or(&[], |v| &v[..])
+    }
+
+    fn search(&self, state: &mut State<K>, conflict: &mut Option<Conflict<K>>) -> bool {
+        let Some((package, requirements)) = state
+            .requirements
+            .iter()
+            .find(|(package, _)| !state.chosen.contains_key(*package))
+        else {
+            return true;
+        };
+        let mut candidates = self
+            .versions(package)
+            .iter()
+            .filter(|(version, _)| requirements.iter().all(|r| version.satisfies(&r.range)))
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            conflict.get_or_insert_with(|| self.explain(package, requirements.clone()));
+            return false;
+        }
+        candidates.sort_by(|(a, _), (b, _)| self.preference.cmp(b, a));
+        let package = package.clone();
+
+        for (version, dependencies) in candidates {
+            let mut next = state.clone();
+            next.chosen.insert(package.clone(), version.clone());
+            let mut consistent = true;
+            for (dependency, range) in dependencies {
+                let requirement = Requirement {
+                    range: range.clone(),
+                    required_by: Some((package.clone(), version.clone())),
+                };
+                let requirements = next.requirements.entry(dependency.clone()).or_default();
+                requirements.push(requirement);
+                // a dependency that is already decided has to agree
+                if next
+                    .chosen
+                    .get(dependency)
+                    .is_some_and(|chosen| !chosen.satisfies(range))
+                {
+                    let requirements = requirements.clone();
+                    conflict.get_or_insert_with(|| self.explain(dependency, requirements));
+                    consistent = false;
+                    break;
+                }
+            }
+            if consistent && self.search(&mut next, conflict) {
+                *state = next;
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Reduces `requirements` to a minimal set that no version of `package` satisfies, if they
+    /// are such a set.
+    fn explain(&self, package: &K, mut requirements: Vec<Requirement<K>>) -> Conflict<K> {
+        let satisfiable = |requirements: &[Requirement<K>]| {
+            self.versions(package)
+                .iter()
+                .any(|(version, _)| requirements.iter().all(|r| version.satisfies(&r.range)))
+        };
+        if !satisfiable(&requirements) {
+            let mut i = 0;
+            while i < requirements.len() {
+                let requirement = requirements.remove(i);
+                if satisfiable(&requirements) {
+                    requirements.insert(i, requirement);
+                    i += 1;
+                }
+            }
+        }
+        Conflict {
+            package: package.clone(),
+            requirements,
+        }
+    }
+}
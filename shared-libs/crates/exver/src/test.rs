@@ -2,6 +2,8 @@ use proptest::prelude::*;
 use yasi::InternedString;
 
 use crate::exver::*;
+use crate::interop::*;
+use crate::resolver::*;
 
 prop_compose! {
     fn flavor_gen()(
@@ -200,6 +202,37 @@ proptest! {
     fn intersects_symmetric(a in range_gen(), b in range_gen()) {
         assert!(a.intersects(&b) == b.intersects(&a));
     }
+
+    #[test]
+    fn normalize_equivalent(a in range_gen(), obs in ex_version_gen()) {
+        assert!(obs.satisfies(&a) == obs.satisfies(&a.normalize()))
+    }
+
+    #[test]
+    fn normalize_idempotent(a in range_gen()) {
+        let n = a.normalize();
+        assert_eq!(n, n.normalize());
+        assert_eq!(n, n.to_string().parse::<VersionRange>().unwrap().normalize());
+    }
+
+    #[test]
+    fn complement_excludes(a in range_gen(), obs in ex_version_gen()) {
+        assert!(obs.satisfies(&a) != obs.satisfies(&a.complement()))
+    }
+
+    #[test]
+    fn subset_of_union(a in range_gen(), b in range_gen()) {
+        assert!(a.is_subset_of(&VersionRange::or(a.clone(), b.clone())));
+        assert!(VersionRange::and(a.clone(), b).is_subset_of(&a));
+        assert!(a.is_subset_of(&a.normalize()) && a.normalize().is_subset_of(&a));
+    }
+
+    #[test]
+    fn subset_implies_satisfies(a in range_gen(), b in range_gen(), obs in ex_version_gen()) {
+        if a.is_subset_of(&b) && obs.satisfies(&a) {
+            assert!(obs.satisfies(&b));
+        }
+    }
 }
 
 #[test]
@@ -246,6 +279,47 @@ fn satisfiable_flavor_disjoint() {
     assert!(r1.intersects(&r2));
 }
 
+#[test]
+fn satisfiable_flavor_union() {
+    // `not` of a union over different flavors
+    let r: VersionRange = "!(#a || #b) && #a".parse().unwrap();
+    assert!(!r.satisfiable());
+    let r: VersionRange = "!(#a || #b) && #c".parse().unwrap();
+    assert!(r.satisfiable());
+}
+
+#[test]
+fn normalize_display() {
+    let normalize = |s: &str| s.parse::<VersionRange>().unwrap().normalize().to_string();
+    assert_eq!(normalize("(>=1.2:0 <3:0) (<2:0 || >=5:0)"), ">=1.2:0 <2:0");
+    assert_eq!(normalize("^1.2 || ^1.5 || =3:0"), "(>=1.2:0 <2:0) || =3:0");
+    assert_eq!(normalize(">=1:0 <2:0 !=1.5:0"), ">=1:0 <2:0 !=1.5:0");
+    assert_eq!(normalize("!=1:0"), "!=1:0");
+    // anchors without a flavor only match versions without one
+    assert_eq!(normalize("<1:0 || >=1:0"), "#");
+    assert_eq!(normalize("<1:0 || >=1:0 || !#"), "*");
+    assert_eq!(normalize(">=2:0 <1:0"), "!");
+    assert_eq!(normalize("#knots || ^#knots:29:0"), "#knots");
+    assert_eq!(
+        normalize("^#knots:29:0 || <=29.3:10"),
+        "<=29.3:10 || (>=#knots:29:0 <#knots:30:0)"
+    );
+    assert_eq!(normalize("!(>=1:0 <2:0)"), "!(>=1:0 <2:0)");
+}
+
+#[test]
+fn subset_and_complement() {
+    let range = |s: &str| s.parse::<VersionRange>().unwrap();
+    assert!(range("^1.2").is_subset_of(&range(">=1:0")));
+    assert!(!range(">=1:0").is_subset_of(&range("^1.2")));
+    assert!(range("!").is_subset_of(&range("=1:0")));
+    assert!(range("#a").is_subset_of(&range("!#b")));
+    assert!(!range("^#knots:29:0").is_subset_of(&range("<=29.3:10")));
+    assert_eq!(range(">=1:0").complement().to_string(), "!>=1:0");
+    assert_eq!(range(">=1:0").complement().complement(), range(">=1:0"));
+    assert_eq!(range("=1:0").complement().to_string(), "!=1:0");
+}
+
 #[test]
 fn caret() {
     let thing = "^1.2.3.4"
@@ -268,3 +342,240 @@ fn caret() {
 fn deser() {
     let _v: ExtendedVersion = serde_yaml::from_str("---\n0.2.5:0\n").unwrap();
 }
+
+#[test]
+fn best_version() {
+    let versions: Vec<ExtendedVersion> = ["1.0:0", "1.2:0", "2.0-beta.1:0", "#knots:1.5:0"]
+        .into_iter()
+        .map(|v| v.parse().unwrap())
+        .collect();
+    let best = |range: &str, preference: &Preference| {
+        range
+            .parse::<VersionRange>()
+            .unwrap()
+            .best(&versions, preference)
+            .map(|v| v.to_string())
+    };
+    assert_eq!(
+        best("*", &Preference::max()).as_deref(),
+        Some("2.0-beta.1:0")
+    );
+    assert_eq!(
+        best("*", &Preference::max().prefer_stable()).as_deref(),
+        Some("1.2:0")
+    );
+    assert_eq!(best("^1", &Preference::min()).as_deref(), Some("1.0:0"));
+    assert_eq!(
+        best("*", &Preference::max().prefer_flavor("knots")).as_deref(),
+        Some("#knots:1.5:0")
+    );
+    assert_eq!(best(">=3:0", &Preference::max()), None);
+
+    let best = VersionRange::Any.best_per_flavor(&versions, &Preference::max().prefer_stable());
+    assert_eq!(
+        best.iter().map(|v| v.to_string()).collect::<Vec<_>>(),
+        ["1.2:0", "#knots:1.5:0"]
+    );
+}
+
+#[test]
+fn resolve() {
+    let v = |s: &str| s.parse::<ExtendedVersion>().unwrap();
+    let r = |s: &str| s.parse::<VersionRange>().unwrap();
+    let mut resolver = Resolver::new(Preference::max());
+    resolver
+        .add_version("bitcoind", v("27.0:0"), [])
+        .add_version("bitcoind", v("28.0:0"), [])
+        .add_version("electrs", v("0.10:0"), [("bitcoind", r("<28:0"))])
+        .add_version("electrs", v("0.9:0"), [("bitcoind", r(">=27:0"))])
+        .add_version("mempool", v("3.0:0"), [("electrs", r(">=0.10:0"))])
+        .add_version("mempool", v("2.5:0"), [("electrs", r("*"))]);
+
+    // bitcoind is decided first, so the newer mempool and electrs are ruled out
+    resolver
+        .require("mempool", r("*"))
+        .require("bitcoind", r("*"));
+    let chosen = resolver.resolve().unwrap();
+    assert_eq!(chosen["bitcoind"], v("28.0:0"));
+    assert_eq!(chosen["electrs"], v("0.9:0"));
+    assert_eq!(chosen["mempool"], v("2.5:0"));
+
+    resolver.require("mempool", r(">=3:0"));
+    let chosen = resolver.resolve().unwrap();
+    assert_eq!(chosen["bitcoind"], v("27.0:0"));
+    assert_eq!(chosen["electrs"], v("0.10:0"));
+
+    resolver.require("bitcoind", r(">=28:0"));
+    let conflict = resolver.resolve().unwrap_err();
+    assert_eq!(conflict.package, "bitcoind");
+    assert_eq!(
+        conflict.to_string(),
+        "no version of bitcoind satisfies >=28:0 and <28:0 (required by electrs@0.10:0)"
+    );
+
+    let mut resolver = Resolver::new(Preference::max());
+    resolver.require("lnd", r("*"));
+    assert_eq!(
+        resolver.resolve().unwrap_err().to_string(),
+        "no version of lnd is available"
+    );
+}
+
+proptest! {
+    #[test]
+    fn semver_round_trip(
+        number in proptest::array::uniform3(0..1000usize),
+        prerelease in proptest::collection::vec("[1-9][0-9]{0,3}|[a-zA-Z-][0-9a-zA-Z-]{0,5}", 0..3),
+        build in proptest::collection::vec("[0-9a-zA-Z-]{1,6}", 0..3),
+    ) {
+        let mut s = format!("{}.{}.{}", number[0], number[1], number[2]);
+        if !prerelease.is_empty() {
+            s = format!("{s}-{}", prerelease.join("."));
+        }
+        if !build.is_empty() {
+            s = format!("{s}+{}", build.join("."));
+        }
+        let semver = s.parse::<SemVer>().unwrap();
+        assert_eq!(semver.to_string(), s);
+        assert_eq!(semver.build().len(), build.len());
+        assert_eq!(SemVer::try_from(semver.version().clone()).unwrap(), semver.clone().with_build([]));
+    }
+}
+
+#[test]
+fn semver() {
+    let semver = "1.2.3-rc.1+build.5".parse::<SemVer>().unwrap();
+    assert_eq!(
+        ExtendedVersion::from(semver.clone()),
+        "1.2.3-rc.1:0".parse().unwrap()
+    );
+    assert_eq!(
+        semver.build(),
+        &["build".into(), "5".into()] as &[InternedString]
+    );
+    for invalid in [
+        "1.2",
+        "1.2.3.4",
+        "01.2.3",
+        "1.2.3+",
+        "1.2.3+a..b",
+        "1.2.3-01",
+    ] {
+        assert!(invalid.parse::<SemVer>().is_err(), "{invalid}");
+    }
+    assert_eq!(
+        SemVer::try_from("1.2".parse::<Version>().unwrap())
+            .unwrap()
+            .to_string(),
+        "1.2.0"
+    );
+    assert!(SemVer::try_from("1.2.3.4".parse::<Version>().unwrap()).is_err());
+}
+
+#[test]
+fn debian() {
+    let d = |s: &str| s.parse::<DebVersion>().unwrap();
+    for s in [
+        "1:2.30-1",
+        "0:1.0",
+        "2.0+dfsg-1ubuntu2",
+        "1.0~rc1",
+        "7.6p1-4",
+    ] {
+        assert_eq!(d(s).to_string(), s);
+    }
+    // in ascending order, as dpkg sorts them
+    let ordered = [
+        "1.0~~", "1.0~~a", "1.0~", "1.0~rc1", "1.0~rc9", "1.0~rc10", "1.0~rc.1", "1.0", "1.0-1",
+        "1.0a", "1.0+dfsg", "1.0.1", "1.10", "1:0.1",
+    ];
+    for pair in ordered.windows(2) {
+        assert!(d(pair[0]) < d(pair[1]), "{} < {}", pair[0], pair[1]);
+    }
+    assert_eq!(d("0:1.0-0"), d("1.0"));
+
+    let e = |s: &str| ExtendedVersion::try_from(d(s));
+    assert_eq!(e("1.2.3~rc10-2").unwrap(), "1.2.3-rc.10:2".parse().unwrap());
+    assert_eq!(e("1.2").unwrap(), "1.2:0".parse().unwrap());
+    let converted =
+        ["1.0~rc1", "1.0~rc9", "1.0~rc10", "1.0", "1.0-1", "1.10"].map(|s| e(s).unwrap());
+    for pair in converted.windows(2) {
+        assert!(pair[0] < pair[1], "{} < {}", pair[0], pair[1]);
+    }
+    // `rc.1` would convert to the same version as `rc1`
+    for unconvertible in ["1:1.0", "1.0+dfsg", "1.0-0ubuntu1", "1.0~rc01", "1.0~rc.1"] {
+        assert!(e(unconvertible).is_err(), "{unconvertible}");
+    }
+}
+
+#[test]
+fn npm_ranges() {
+    let npm = |s: &str| VersionRange::from_npm(s).unwrap();
+    let v = |s: &str| s.parse::<Version>().unwrap();
+    assert_eq!(npm("^1.2.3").to_string(), ">=1.2.3:0 <2.0.0-0:0");
+    assert_eq!(npm("~1.2").to_string(), ">=1.2.0:0 <1.3.0-0:0");
+    assert_eq!(npm("1.2.3").to_string(), "=1.2.3:0");
+    assert_eq!(npm("*").to_string(), "*");
+    assert_eq!(npm("").to_string(), "*");
+
+    let cases = [
+        ("^0.2.3", "0.2.9", "0.3.0"),
+        ("^0.0.3", "0.0.3", "0.0.4"),
+        ("^0.x", "0.9.9", "1.0.0"),
+        ("~1", "1.9.0", "2.0.0"),
+        ("1.x", "1.0.0", "2.0.0-alpha"),
+        (">1.2", "1.3.0", "1.2.9"),
+        ("<=1.2", "1.2.9", "1.3.0-0"),
+        ("<1.2", "1.1.9", "1.2.0-rc.1"),
+        (">= 1.2.3 < 2", "1.9.9", "2.0.0"),
+        ("1.2.3 - 2.3", "2.3.9", "2.4.0"),
+        ("1.2 - 2.3.4", "1.2.0", "2.3.5"),
+        ("v1.2.3+build", "1.2.3", "1.2.4"),
+        ("<1 || >=2.1 <3", "2.5.0", "1.5.0"),
+        ("~>1.2.3", "1.2.9", "1.3.0"),
+    ];
+    for (range, inside, outside) in cases {
+        assert!(v(inside).satisfies(&npm(range)), "{inside} in {range}");
+        assert!(
+            !v(outside).satisfies(&npm(range)),
+            "{outside} not in {range}"
+        );
+    }
+    for invalid in ["^1.x.2", "1.2.3.4", ">=", "!1.2.3", "1.2-rc.1"] {
+        assert!(VersionRange::from_npm(invalid).is_err(), "{invalid}");
+    }
+}
+
+#[test]
+fn cargo_ranges() {
+    let cargo = |s: &str| VersionRange::from_cargo(s).unwrap();
+    let v = |s: &str| s.parse::<Version>().unwrap();
+    assert_eq!(cargo("1.2.3"), VersionRange::from_npm("^1.2.3").unwrap());
+    assert_eq!(cargo("=1.2.3").to_string(), "=1.2.3:0");
+    assert_eq!(cargo("*"), VersionRange::Any);
+    // an `x` in the prerelease or build metadata is not a wildcard
+    assert_eq!(
+        cargo("1.2.3-x.1"),
+        VersionRange::from_npm("^1.2.3-x.1").unwrap()
+    );
+    assert_eq!(cargo("1.2.3+x"), VersionRange::from_npm("^1.2.3").unwrap());
+
+    let cases = [
+        ("0.2", "0.2.9", "0.3.0"),
+        ("0", "0.9.0", "1.0.0"),
+        (">=1.2, <1.5", "1.4.9", "1.5.0"),
+        ("1.2.*", "1.2.9", "1.3.0"),
+        ("=1.2", "1.2.5", "1.3.0"),
+        ("~1.2.3", "1.2.9", "1.3.0"),
+    ];
+    for (range, inside, outside) in cases {
+        assert!(v(inside).satisfies(&cargo(range)), "{inside} in {range}");
+        assert!(
+            !v(outside).satisfies(&cargo(range)),
+            "{outside} not in {range}"
+        );
+    }
+    for invalid in ["", ">=1.2,", "1.2 || 1.3", "~>1.2"] {
+        assert!(VersionRange::from_cargo(invalid).is_err(), "{invalid}");
+    }
+}
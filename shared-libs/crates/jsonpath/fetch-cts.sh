@@ -0,0 +1,19 @@
+#!/bin/bash
+
+set -e
+
+#
+# Downloads the JSONPath Compliance Test Suite for the ignored
+# `rfc9535_compliance_test_suite` test, then runs it.
+# CTS_REF must name the suite commit to test against, so runs are reproducible.
+#
+
+if [ -z "$CTS_REF" ]; then
+  echo "usage: CTS_REF=<commit> $0" >&2
+  exit 1
+fi
+
+curl -fsSL -o ./tests/cts.json \
+  "https://raw.githubusercontent.com/jsonpath-standard/jsonpath-compliance-test-suite/${CTS_REF}/cts.json"
+sha256sum ./tests/cts.json
+cargo test -p jsonpath_lib --test rfc9535 -- --ignored
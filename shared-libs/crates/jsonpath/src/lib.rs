@@ -100,6 +100,7 @@ extern crate core;
 #[macro_use]
 extern crate log;
 extern crate imbl_value;
+extern crate regex;
 extern crate serde;
 
 use std::rc::Rc;
@@ -129,6 +130,7 @@ mod parser;
 mod select;
 
 mod paths;
+pub mod rfc9535;
 mod selector;
 
 impl From<&paths::TokenError> for JsonPathError {
@@ -0,0 +1,129 @@
+use imbl_value::Value;
+use regex::Regex;
+
+#[derive(Clone, Debug)]
+pub enum Segment {
+    Child(Vec<Selector>),
+    Descendant(Vec<Selector>),
+}
+
+#[derive(Clone, Debug)]
+pub enum Selector {
+    Name(String),
+    Wildcard,
+    Index(i64),
+    Slice {
+        start: Option<i64>,
+        end: Option<i64>,
+        step: Option<i64>,
+    },
+    Filter(LogicalExpr),
+}
+
+#[derive(Clone, Debug)]
+pub enum LogicalExpr {
+    Or(Vec<LogicalExpr>),
+    And(Vec<LogicalExpr>),
+    Not(Box<LogicalExpr>),
+    Comparison(Comparable, CmpOp, Comparable),
+    Query(FilterQuery),
+    Function(FunctionExpr),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug)]
+pub enum Comparable {
+    Literal(Value),
+    /// Always a singular query
+    Query(FilterQuery),
+    /// Always of [`Type::Value`]
+    Function(FunctionExpr),
+}
+
+/// A query within a filter, relative to the current node (`@`) or to the root (`$`).
+#[derive(Clone, Debug)]
+pub struct FilterQuery {
+    pub relative: bool,
+    pub segments: Vec<Segment>,
+}
+
+/// The types of RFC 9535 §2.4.1: a `Value` may be nothing, a `Nodes` argument is a nodelist. No
+/// function takes a `Logical` argument or returns `Nodes`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Type {
+    Value,
+    Logical,
+    Nodes,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Function {
+    Length,
+    Count,
+    Match,
+    Search,
+    Value,
+}
+
+impl Function {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "length" => Function::Length,
+            "count" => Function::Count,
+            "match" => Function::Match,
+            "search" => Function::Search,
+            "value" => Function::Value,
+            _ => return None,
+        })
+    }
+
+    pub fn params(self) -> &'static [Type] {
+        match self {
+            Function::Length => &[Type::Value],
+            Function::Count | Function::Value => &[Type::Nodes],
+            Function::Match | Function::Search => &[Type::Value, Type::Value],
+        }
+    }
+
+    pub fn result(self) -> Type {
+        match self {
+            Function::Match | Function::Search => Type::Logical,
+            _ => Type::Value,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FunctionExpr {
+    pub function: Function,
+    pub args: Vec<Argument>,
+    /// The pattern of `match` or `search`, compiled once if it is a literal. `None` within means
+    /// the literal is not a valid I-Regexp, so nothing matches.
+    pub regex: Option<Option<Regex>>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Argument {
+    Literal(Value),
+    Query(FilterQuery),
+    Function(FunctionExpr),
+}
+
+/// Whether `segments` select at most one node: only names and indices, one per segment.
+pub fn is_singular(segments: &[Segment]) -> bool {
+    segments.iter().all(|segment| match segment {
+        Segment::Child(selectors) => {
+            selectors.len() == 1 && matches!(selectors[0], Selector::Name(_) | Selector::Index(_))
+        }
+        Segment::Descendant(_) => false,
+    })
+}
@@ -0,0 +1,276 @@
+use std::cmp::Ordering;
+
+use imbl_value::{Number, Value};
+
+use super::ast::*;
+use super::iregexp;
+use super::{NormalizedPath, PathElement};
+
+/// Where a node is, tracked only when the caller asks for it.
+pub trait Location: Clone {
+    fn child(&self, element: impl FnOnce() -> PathElement) -> Self;
+}
+
+impl Location for () {
+    fn child(&self, _: impl FnOnce() -> PathElement) -> Self {}
+}
+
+impl Location for NormalizedPath {
+    fn child(&self, element: impl FnOnce() -> PathElement) -> Self {
+        let mut path = self.clone();
+        path.0.push(element());
+        path
+    }
+}
+
+type Node<'a, L> = (L, &'a Value);
+
+/// Applies `segments` to `node`, with `root` the document that `$` within filters refers to.
+pub fn select<'a, L: Location>(
+    segments: &[Segment],
+    root: &'a Value,
+    node: Node<'a, L>,
+) -> Vec<Node<'a, L>> {
+    let mut nodes = vec![node];
+    for segment in segments {
+        let mut next = Vec::new();
+        for node in &nodes {
+            match segment {
+                Segment::Child(selectors) => {
+                    for selector in selectors {
+                        select_children(selector, root, node, &mut next);
+                    }
+                }
+                Segment::Descendant(selectors) => descend(node, &mut |node| {
+                    for selector in selectors {
+                        select_children(selector, root, node, &mut next);
+                    }
+                }),
+            }
+        }
+        nodes = next;
+    }
+    nodes
+}
+
+/// Visits `node` and then its descendants, each before its own children.
+fn descend<'a, L: Location>(node: &Node<'a, L>, f: &mut impl FnMut(&Node<'a, L>)) {
+    f(node);
+    for child in children(node) {
+        descend(&child, f);
+    }
+}
+
+fn children<'a, L: Location>((loc, value): &Node<'a, L>) -> Vec<Node<'a, L>> {
+    match value {
+        Value::Array(vec) => vec
+            .iter()
+            .enumerate()
+            .map(|(idx, v)| (loc.child(|| PathElement::Index(idx)), v))
+            .collect(),
+        Value::Object(map) => map
+            .iter()
+            .map(|(key, v)| (loc.child(|| PathElement::Name(key.clone())), v))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn select_children<'a, L: Location>(
+    selector: &Selector,
+    root: &'a Value,
+    node: &Node<'a, L>,
+    out: &mut Vec<Node<'a, L>>,
+) {
+    let (loc, value) = node;
+    match (selector, value) {
+        (Selector::Name(name), Value::Object(map)) => {
+            if let Some((key, v)) = map.get_key_value(name.as_str()) {
+                out.push((loc.child(|| PathElement::Name(key.clone())), v));
+            }
+        }
+        (Selector::Wildcard, _) => out.extend(children(node)),
+        (Selector::Index(idx), Value::Array(vec)) => {
+            let len = vec.len() as i64;
+            let idx = if *idx < 0 { len + idx } else { *idx };
+            if 0 <= idx && idx < len {
+                let idx = idx as usize;
+                out.push((loc.child(|| PathElement::Index(idx)), &vec[idx]));
+            }
+        }
+        (Selector::Slice { start, end, step }, Value::Array(vec)) => {
+            for idx in slice(*start, *end, *step, vec.len() as i64) {
+                out.push((loc.child(|| PathElement::Index(idx)), &vec[idx]));
+            }
+        }
+        (Selector::Filter(expr), _) => out.extend(
+            children(node)
+                .into_iter()
+                .filter(|(_, child)| test(expr, root, child)),
+        ),
+        _ => (),
+    }
+}
+
+/// The indices a slice selects from an array of `len` elements, per RFC 9535 §2.3.4.2.2.
+fn slice(start: Option<i64>, end: Option<i64>, step: Option<i64>, len: i64) -> Vec<usize> {
+    let step = step.unwrap_or(1);
+    let normalize = |i: i64| if i >= 0 { i } else { len + i };
+    let mut res = Vec::new();
+    if step > 0 {
+        let lower = normalize(start.unwrap_or(0)).clamp(0, len);
+        let upper = normalize(end.unwrap_or(len)).clamp(0, len);
+        let mut i = lower;
+        while i < upper {
+            res.push(i as usize);
+            i += step;
+        }
+    } else if step < 0 {
+        let upper = normalize(start.unwrap_or(len - 1)).clamp(-1, len - 1);
+        let lower = normalize(end.unwrap_or(-len - 1)).clamp(-1, len - 1);
+        let mut i = upper;
+        while lower < i {
+            res.push(i as usize);
+            i += step;
+        }
+    }
+    res
+}
+
+fn test(expr: &LogicalExpr, root: &Value, current: &Value) -> bool {
+    match expr {
+        LogicalExpr::Or(exprs) => exprs.iter().any(|e| test(e, root, current)),
+        LogicalExpr::And(exprs) => exprs.iter().all(|e| test(e, root, current)),
+        LogicalExpr::Not(expr) => !test(expr, root, current),
+        LogicalExpr::Comparison(lhs, op, rhs) => {
+            let lhs = comparable(lhs, root, current);
+            let rhs = comparable(rhs, root, current);
+            compare(lhs.as_ref(), *op, rhs.as_ref())
+        }
+        LogicalExpr::Query(query) => !filter_query(query, root, current).is_empty(),
+        LogicalExpr::Function(f) => match call(f, root, current) {
+            Output::Logical(b) => b,
+            Output::Value(_) => unreachable!("value functions are compared"),
+        },
+    }
+}
+
+fn filter_query<'a>(query: &FilterQuery, root: &'a Value, current: &'a Value) -> Vec<&'a Value> {
+    let start = if query.relative { current } else { root };
+    select(&query.segments, root, ((), start))
+        .into_iter()
+        .map(|(_, v)| v)
+        .collect()
+}
+
+/// The value of a comparable, or `None` for Nothing.
+fn comparable(comparable: &Comparable, root: &Value, current: &Value) -> Option<Value> {
+    match comparable {
+        Comparable::Literal(v) => Some(v.clone()),
+        Comparable::Query(query) => filter_query(query, root, current).first().cloned().cloned(),
+        Comparable::Function(f) => match call(f, root, current) {
+            Output::Value(v) => v,
+            _ => unreachable!("only value functions are compared"),
+        },
+    }
+}
+
+fn compare(lhs: Option<&Value>, op: CmpOp, rhs: Option<&Value>) -> bool {
+    let eq = || match (lhs, rhs) {
+        (None, None) => true,
+        (Some(a), Some(b)) => json_eq(a, b),
+        _ => false,
+    };
+    let lt = |a: Option<&Value>, b: Option<&Value>| match (a, b) {
+        (Some(Value::Number(a)), Some(Value::Number(b))) => {
+            number_cmp(a, b) == Some(Ordering::Less)
+        }
+        (Some(Value::String(a)), Some(Value::String(b))) => a < b,
+        _ => false,
+    };
+    match op {
+        CmpOp::Eq => eq(),
+        CmpOp::Ne => !eq(),
+        CmpOp::Lt => lt(lhs, rhs),
+        CmpOp::Le => lt(lhs, rhs) || eq(),
+        CmpOp::Gt => lt(rhs, lhs),
+        CmpOp::Ge => lt(rhs, lhs) || eq(),
+    }
+}
+
+fn number_cmp(a: &Number, b: &Number) -> Option<Ordering> {
+    match (a.as_i64(), b.as_i64(), a.as_u64(), b.as_u64()) {
+        (Some(a), Some(b), _, _) => Some(a.cmp(&b)),
+        (_, _, Some(a), Some(b)) => Some(a.cmp(&b)),
+        _ => a.as_f64()?.partial_cmp(&b.as_f64()?),
+    }
+}
+
+/// Equality of JSON values, under which numbers are equal by value and members are unordered.
+fn json_eq(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => number_cmp(a, b) == Some(Ordering::Equal),
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| json_eq(a, b))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, a)| b.get(key).is_some_and(|b| json_eq(a, b)))
+        }
+        (a, b) => a == b,
+    }
+}
+
+enum Output {
+    Value(Option<Value>),
+    Logical(bool),
+}
+
+fn call(f: &FunctionExpr, root: &Value, current: &Value) -> Output {
+    let value_arg = |idx: usize| match &f.args[idx] {
+        Argument::Literal(v) => Some(v.clone()),
+        Argument::Query(query) => filter_query(query, root, current).first().cloned().cloned(),
+        Argument::Function(f) => match call(f, root, current) {
+            Output::Value(v) => v,
+            _ => unreachable!("arguments are type checked"),
+        },
+    };
+    let nodes_arg = |idx: usize| match &f.args[idx] {
+        Argument::Query(query) => filter_query(query, root, current),
+        _ => unreachable!("arguments are type checked"),
+    };
+    match f.function {
+        Function::Length => Output::Value(match value_arg(0) {
+            Some(Value::String(s)) => Some(s.chars().count().into()),
+            Some(Value::Array(vec)) => Some(vec.len().into()),
+            Some(Value::Object(map)) => Some(map.len().into()),
+            _ => None,
+        }),
+        Function::Count => Output::Value(Some(nodes_arg(0).len().into())),
+        Function::Value => {
+            let nodes = nodes_arg(0);
+            Output::Value(if nodes.len() == 1 {
+                Some(nodes[0].clone())
+            } else {
+                None
+            })
+        }
+        Function::Match | Function::Search => {
+            let matched = match (value_arg(0), &f.regex) {
+                (Some(Value::String(s)), Some(regex)) => {
+                    regex.as_ref().is_some_and(|regex| regex.is_match(&s))
+                }
+                (Some(Value::String(s)), None) => match value_arg(1) {
+                    Some(Value::String(pattern)) => {
+                        iregexp::compile(&pattern, f.function == Function::Match)
+                            .is_some_and(|regex| regex.is_match(&s))
+                    }
+                    _ => false,
+                },
+                _ => false,
+            };
+            Output::Logical(matched)
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! [I-Regexp](https://www.rfc-editor.org/rfc/rfc9485), the interoperable regular expressions that
+//! `match` and `search` take, translated into the syntax of the `regex` crate.
+
+use regex::Regex;
+
+/// Compiles `pattern` to match whole strings, or any substring if not `whole`, or `None` if it is
+/// not a valid I-Regexp.
+pub fn compile(pattern: &str, whole: bool) -> Option<Regex> {
+    let mut translator = Translator {
+        chars: pattern.chars().collect(),
+        pos: 0,
+        out: String::new(),
+    };
+    translator.regexp()?;
+    if translator.pos < translator.chars.len() {
+        return None;
+    }
+    let translated = if whole {
+        format!("^(?:{})$", translator.out)
+    } else {
+        translator.out
+    };
+    Regex::new(&translated).ok()
+}
+
+const CATEGORIES: &[&str] = &[
+    "L", "Ll", "Lm", "Lo", "Lt", "Lu", "M", "Mc", "Me", "Mn", "N", "Nd", "Nl", "No", "P", "Pc",
+    "Pd", "Pe", "Pf", "Pi", "Po", "Ps", "Z", "Zl", "Zp", "Zs", "S", "Sc", "Sk", "Sm", "So", "C",
+    "Cc", "Cf", "Cn", "Co",
+];
+
+struct Translator {
+    chars: Vec<char>,
+    pos: usize,
+    out: String,
+}
+
+impl Translator {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn push_literal(&mut self, c: char) {
+        self.out
+            .push_str(&regex::escape(c.encode_utf8(&mut [0; 4])));
+    }
+
+    fn regexp(&mut self) -> Option<()> {
+        self.branch()?;
+        while self.eat('|') {
+            self.out.push('|');
+            self.branch()?;
+        }
+        Some(())
+    }
+
+    fn branch(&mut self) -> Option<()> {
+        while !matches!(self.peek(), None | Some('|' | ')')) {
+            self.atom()?;
+            self.quantifier()?;
+        }
+        Some(())
+    }
+
+    fn atom(&mut self) -> Option<()> {
+        let c = self.peek()?;
+        self.pos += 1;
+        match c {
+            '(' => {
+                self.out.push_str("(?:");
+                self.regexp()?;
+                if !self.eat(')') {
+                    return None;
+                }
+                self.out.push(')');
+            }
+            // unlike in the regex crate, '.' never matches a line break
+            '.' => self.out.push_str("[^\\n\\r]"),
+            '[' => self.class()?,
+            '\\' => self.escape()?,
+            '*' | '+' | '?' | '{' | '}' | ']' | ')' | '|' => return None,
+            c => self.push_literal(c),
+        }
+        Some(())
+    }
+
+    fn quantifier(&mut self) -> Option<()> {
+        match self.peek() {
+            Some(c @ ('*' | '+' | '?')) => {
+                self.pos += 1;
+                self.out.push(c);
+            }
+            Some('{') => {
+                self.pos += 1;
+                let min = self.digits()?;
+                self.out.push('{');
+                self.out.push_str(&min);
+                if self.eat(',') {
+                    self.out.push(',');
+                    if self.peek() != Some('}') {
+                        let max = self.digits()?;
+                        self.out.push_str(&max);
+                    }
+                }
+                if !self.eat('}') {
+                    return None;
+                }
+                self.out.push('}');
+            }
+            _ => (),
+        }
+        Some(())
+    }
+
+    fn digits(&mut self) -> Option<String> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return None;
+        }
+        Some(self.chars[start..self.pos].iter().collect())
+    }
+
+    /// An escape outside of a class, the backslash already consumed.
+    fn escape(&mut self) -> Option<()> {
+        match self.peek()? {
+            'p' | 'P' => self.category(),
+            _ => {
+                let c = self.single_char_escape()?;
+                self.push_literal(c);
+                Some(())
+            }
+        }
+    }
+
+    /// The character a `SingleCharEsc` stands for, the backslash already consumed.
+    fn single_char_escape(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        match c {
+            'n' => Some('\n'),
+            'r' => Some('\r'),
+            't' => Some('\t'),
+            '(' | ')' | '*' | '+' | '-' | '.' | '?' | '[' | '\\' | ']' | '^' | '{' | '|' | '}' => {
+                Some(c)
+            }
+            _ => None,
+        }
+    }
+
+    /// `\p{..}` or `\P{..}`, the backslash already consumed.
+    fn category(&mut self) -> Option<()> {
+        let p = self.peek()?;
+        self.pos += 1;
+        if !self.eat('{') {
+            return None;
+        }
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+            self.pos += 1;
+        }
+        let name: String = self.chars[start..self.pos].iter().collect();
+        if !self.eat('}') || !CATEGORIES.contains(&name.as_str()) {
+            return None;
+        }
+        self.out.push('\\');
+        self.out.push(p);
+        self.out.push('{');
+        self.out.push_str(&name);
+        self.out.push('}');
+        Some(())
+    }
+
+    /// A character class, the `[` already consumed.
+    fn class(&mut self) -> Option<()> {
+        self.out.push('[');
+        if self.eat('^') {
+            self.out.push('^');
+        }
+        let mut first = true;
+        loop {
+            match self.peek()? {
+                ']' if !first => break,
+                // a '-' is literal only first or last
+                '-' if first || self.chars.get(self.pos + 1) == Some(&']') => {
+                    self.pos += 1;
+                    self.out.push_str("\\-");
+                }
+                '\\' if matches!(self.chars.get(self.pos + 1), Some('p' | 'P')) => {
+                    self.pos += 1;
+                    self.category()?;
+                }
+                _ => {
+                    let from = self.class_char()?;
+                    self.push_literal(from);
+                    if self.peek() == Some('-') && self.chars.get(self.pos + 1) != Some(&']') {
+                        self.pos += 1;
+                        let to = self.class_char()?;
+                        self.out.push('-');
+                        self.push_literal(to);
+                    }
+                }
+            }
+            first = false;
+        }
+        self.pos += 1;
+        self.out.push(']');
+        Some(())
+    }
+
+    fn class_char(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        match c {
+            '\\' => self.single_char_escape(),
+            '-' | '[' | ']' => None,
+            c => Some(c),
+        }
+    }
+}
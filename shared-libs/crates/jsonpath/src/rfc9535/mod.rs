@@ -0,0 +1,160 @@
+//! [RFC 9535](https://www.rfc-editor.org/rfc/rfc9535) JSONPath.
+//!
+//! The rest of the crate implements the dialect it inherited from the original project. This
+//! module is a separate engine that follows the standard exactly, for callers whose paths are also
+//! evaluated elsewhere, e.g. by the TypeScript SDK, and so must mean the same thing:
+//!
+//! - paths are validated up front, including the types of function arguments, and a path that is
+//!   not well-formed and well-typed fails to compile
+//! - filters are written `?<expr>`, parentheses are optional, and `@`/`$` queries compare with
+//!   JSON equality (`1 == 1.0`), with a missing value only equal to another missing value
+//! - the function extensions `length`, `count`, `match`, `search` and `value` are available, with
+//!   [I-Regexp](https://www.rfc-editor.org/rfc/rfc9485) patterns
+//! - every selected node can be reported with its [`NormalizedPath`]
+//!
+//! ```
+//! extern crate jsonpath_lib as jsonpath;
+//! #[macro_use] extern crate imbl_value;
+//!
+//! use jsonpath::rfc9535::JsonPath;
+//!
+//! let json_obj = json!({"a": [3, 5, 1, {"b": "kilo"}]});
+//!
+//! let path = JsonPath::compile("$.a[?@ > 2 || match(@.b, 'k.*')]").unwrap();
+//! let located: Vec<_> = path
+//!     .select_located(&json_obj)
+//!     .into_iter()
+//!     .map(|(path, _)| path.to_string())
+//!     .collect();
+//! assert_eq!(located, ["$['a'][0]", "$['a'][1]", "$['a'][3]"]);
+//! ```
+use std::fmt;
+use std::str::FromStr;
+
+use imbl_value::imbl::Vector;
+use imbl_value::{InternedString, Value};
+use JsonPathError;
+
+use self::ast::Segment;
+
+mod ast;
+mod eval;
+mod iregexp;
+mod parser;
+
+pub use self::parser::MAX_DEPTH;
+
+/// A compiled RFC 9535 JSONPath query.
+#[derive(Clone, Debug)]
+pub struct JsonPath {
+    path: String,
+    segments: Vec<Segment>,
+}
+
+impl JsonPath {
+    /// Compile a query, failing if it is not well-formed and well-typed, or if its filters,
+    /// parentheses and function calls nest deeper than [`MAX_DEPTH`].
+    pub fn compile(path: &str) -> Result<Self, JsonPathError> {
+        Ok(JsonPath {
+            path: path.to_owned(),
+            segments: parser::parse(path)?,
+        })
+    }
+
+    /// The nodes the query selects from `value`, in the order the RFC gives them.
+    pub fn select<'a>(&self, value: &'a Value) -> Vector<&'a Value> {
+        eval::select(&self.segments, value, ((), value))
+            .into_iter()
+            .map(|(_, v)| v)
+            .collect()
+    }
+
+    /// The nodes the query selects from `value`, each with its location, in the same order as
+    /// [`select`](Self::select).
+    pub fn select_located<'a>(&self, value: &'a Value) -> Vector<(NormalizedPath, &'a Value)> {
+        eval::select(&self.segments, value, (NormalizedPath::default(), value))
+            .into_iter()
+            .collect()
+    }
+
+    /// Whether the query selects at most one node from any value.
+    pub fn is_singular(&self) -> bool {
+        ast::is_singular(&self.segments)
+    }
+}
+
+impl FromStr for JsonPath {
+    type Err = JsonPathError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        JsonPath::compile(s)
+    }
+}
+
+impl fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.path)
+    }
+}
+
+/// One step of a [`NormalizedPath`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PathElement {
+    Name(InternedString),
+    Index(usize),
+}
+
+/// The location of a node within a value, displayed in the normal form of RFC 9535, e.g.
+/// `$['store']['book'][0]`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct NormalizedPath(Vec<PathElement>);
+
+impl NormalizedPath {
+    pub fn elements(&self) -> &[PathElement] {
+        &self.0
+    }
+
+    /// The same location as an RFC 6901 JSON Pointer, e.g. `/store/book/0`.
+    pub fn to_json_pointer(&self) -> String {
+        let mut res = String::new();
+        for element in &self.0 {
+            res.push('/');
+            match element {
+                PathElement::Name(name) => {
+                    res.push_str(&name.replace('~', "~0").replace('/', "~1"))
+                }
+                PathElement::Index(idx) => res.push_str(&idx.to_string()),
+            }
+        }
+        res
+    }
+}
+
+impl fmt::Display for NormalizedPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("$")?;
+        for element in &self.0 {
+            match element {
+                PathElement::Name(name) => {
+                    f.write_str("['")?;
+                    for c in name.chars() {
+                        match c {
+                            '\u{8}' => f.write_str("\\b")?,
+                            '\u{c}' => f.write_str("\\f")?,
+                            '\n' => f.write_str("\\n")?,
+                            '\r' => f.write_str("\\r")?,
+                            '\t' => f.write_str("\\t")?,
+                            '\'' => f.write_str("\\'")?,
+                            '\\' => f.write_str("\\\\")?,
+                            c if c < ' ' => write!(f, "\\u{:04x}", c as u32)?,
+                            c => write!(f, "{}", c)?,
+                        }
+                    }
+                    f.write_str("']")?;
+                }
+                PathElement::Index(idx) => write!(f, "[{}]", idx)?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,550 @@
+use std::sync::Arc;
+
+use imbl_value::{Number, Value};
+use JsonPathError;
+
+use super::ast::*;
+use super::iregexp;
+
+/// The largest index or slice bound the RFC allows, the I-JSON integer range.
+const MAX_INT: i64 = (1 << 53) - 1;
+/// How deeply filters, parentheses and function calls may nest. The parser and evaluator recurse
+/// once per level, so an unbounded path could overflow the stack.
+pub const MAX_DEPTH: usize = 64;
+
+pub fn parse(input: &str) -> Result<Vec<Segment>, JsonPathError> {
+    let mut parser = Parser {
+        input,
+        pos: 0,
+        depth: 0,
+    };
+    parser.expect('$')?;
+    let segments = parser.segments()?;
+    if parser.pos < input.len() {
+        return Err(parser.error("unexpected character"));
+    }
+    Ok(segments)
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, msg: &str) -> JsonPathError {
+        JsonPathError::Path(format!("{} at position {}", msg, self.pos))
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), JsonPathError> {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", c)))
+        }
+    }
+
+    fn skip_blank(&mut self) {
+        while let Some(' ' | '\t' | '\n' | '\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    /// Runs `f` one level deeper, failing past [`MAX_DEPTH`].
+    fn nested<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, JsonPathError>,
+    ) -> Result<T, JsonPathError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("expression nested too deeply"));
+        }
+        self.depth += 1;
+        let res = f(self);
+        self.depth -= 1;
+        res
+    }
+
+    /// Runs `f` after any blank space, or restores the position if it returns `None`.
+    fn after_blank<T>(&mut self, f: impl FnOnce(&mut Self) -> Option<T>) -> Option<T> {
+        let pos = self.pos;
+        self.skip_blank();
+        let res = f(self);
+        if res.is_none() {
+            self.pos = pos;
+        }
+        res
+    }
+
+    fn segments(&mut self) -> Result<Vec<Segment>, JsonPathError> {
+        let mut segments = Vec::new();
+        while self
+            .after_blank(|p| matches!(p.peek(), Some('.' | '[')).then_some(()))
+            .is_some()
+        {
+            segments.push(self.segment()?);
+        }
+        Ok(segments)
+    }
+
+    fn segment(&mut self) -> Result<Segment, JsonPathError> {
+        if self.eat("..") {
+            return Ok(Segment::Descendant(match self.peek() {
+                Some('[') => self.bracketed()?,
+                Some('*') => {
+                    self.pos += 1;
+                    vec![Selector::Wildcard]
+                }
+                _ => vec![Selector::Name(self.member_name()?)],
+            }));
+        }
+        if self.eat(".") {
+            return Ok(Segment::Child(if self.eat("*") {
+                vec![Selector::Wildcard]
+            } else {
+                vec![Selector::Name(self.member_name()?)]
+            }));
+        }
+        Ok(Segment::Child(self.bracketed()?))
+    }
+
+    fn bracketed(&mut self) -> Result<Vec<Selector>, JsonPathError> {
+        self.expect('[')?;
+        self.skip_blank();
+        let mut selectors = vec![self.selector()?];
+        while self.after_blank(|p| p.eat(",").then_some(())).is_some() {
+            self.skip_blank();
+            selectors.push(self.selector()?);
+        }
+        self.skip_blank();
+        self.expect(']')?;
+        Ok(selectors)
+    }
+
+    fn member_name(&mut self) -> Result<String, JsonPathError> {
+        let start = self.pos;
+        match self.peek() {
+            Some(c) if c.is_ascii_alphabetic() || c == '_' || c >= '\u{80}' => (),
+            _ => return Err(self.error("expected a member name")),
+        }
+        while let Some(c) = self.peek() {
+            if !(c.is_ascii_alphanumeric() || c == '_' || c >= '\u{80}') {
+                break;
+            }
+            self.next();
+        }
+        Ok(self.input[start..self.pos].to_owned())
+    }
+
+    fn selector(&mut self) -> Result<Selector, JsonPathError> {
+        match self.peek() {
+            Some(q @ ('\'' | '"')) => {
+                self.pos += 1;
+                Ok(Selector::Name(self.string(q)?))
+            }
+            Some('*') => {
+                self.pos += 1;
+                Ok(Selector::Wildcard)
+            }
+            Some('?') => {
+                self.pos += 1;
+                self.skip_blank();
+                Ok(Selector::Filter(self.nested(Self::logical_or)?))
+            }
+            _ => {
+                let start = self.int()?;
+                if self.after_blank(|p| p.eat(":").then_some(())).is_none() {
+                    return start
+                        .map(Selector::Index)
+                        .ok_or_else(|| self.error("expected a selector"));
+                }
+                self.skip_blank();
+                let end = self.int()?;
+                let step = match self.after_blank(|p| p.eat(":").then_some(())) {
+                    Some(()) => {
+                        self.skip_blank();
+                        self.int()?
+                    }
+                    None => None,
+                };
+                Ok(Selector::Slice { start, end, step })
+            }
+        }
+    }
+
+    /// An integer, if one starts here.
+    fn int(&mut self) -> Result<Option<i64>, JsonPathError> {
+        let start = self.pos;
+        self.eat("-");
+        let digits = self.pos;
+        while let Some('0'..='9') = self.peek() {
+            self.pos += 1;
+        }
+        let text = &self.input[start..self.pos];
+        if self.pos == digits {
+            self.pos = start;
+            return Ok(None);
+        }
+        if text == "-0" || (self.input[digits..self.pos].starts_with('0') && self.pos - digits > 1)
+        {
+            return Err(self.error("integers may not have a leading zero"));
+        }
+        match text.parse::<i64>() {
+            Ok(n) if (-MAX_INT..=MAX_INT).contains(&n) => Ok(Some(n)),
+            _ => Err(self.error("integer out of range")),
+        }
+    }
+
+    /// The rest of a string literal quoted with `quote`, unescaped.
+    fn string(&mut self, quote: char) -> Result<String, JsonPathError> {
+        let mut res = String::new();
+        loop {
+            match self.next() {
+                None => return Err(self.error("unterminated string")),
+                Some(c) if c == quote => return Ok(res),
+                Some('\\') => {
+                    let c = match self.next() {
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some(c @ ('/' | '\\')) => c,
+                        Some(c) if c == quote => c,
+                        Some('u') => self.unicode_escape()?,
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    res.push(c);
+                }
+                Some(c) if c < ' ' => return Err(self.error("unescaped control character")),
+                Some(c) => res.push(c),
+            }
+        }
+    }
+
+    /// The code point of a `\u` escape, and of the low surrogate after it for a high surrogate.
+    fn unicode_escape(&mut self) -> Result<char, JsonPathError> {
+        let high = self.hex4()?;
+        let code = match high {
+            0xD800..=0xDBFF => {
+                if !self.eat("\\u") {
+                    return Err(self.error("unpaired surrogate"));
+                }
+                match self.hex4()? {
+                    low @ 0xDC00..=0xDFFF => 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00),
+                    _ => return Err(self.error("unpaired surrogate")),
+                }
+            }
+            0xDC00..=0xDFFF => return Err(self.error("unpaired surrogate")),
+            code => code,
+        };
+        char::from_u32(code).ok_or_else(|| self.error("invalid escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, JsonPathError> {
+        let hex = self.rest().get(..4).unwrap_or_default();
+        if hex.len() < 4 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(self.error("expected 4 hex digits"));
+        }
+        self.pos += 4;
+        Ok(u32::from_str_radix(hex, 16).unwrap())
+    }
+
+    fn logical_or(&mut self) -> Result<LogicalExpr, JsonPathError> {
+        let mut exprs = vec![self.logical_and()?];
+        while self.after_blank(|p| p.eat("||").then_some(())).is_some() {
+            self.skip_blank();
+            exprs.push(self.logical_and()?);
+        }
+        Ok(if exprs.len() == 1 {
+            exprs.pop().unwrap()
+        } else {
+            LogicalExpr::Or(exprs)
+        })
+    }
+
+    fn logical_and(&mut self) -> Result<LogicalExpr, JsonPathError> {
+        let mut exprs = vec![self.basic()?];
+        while self.after_blank(|p| p.eat("&&").then_some(())).is_some() {
+            self.skip_blank();
+            exprs.push(self.basic()?);
+        }
+        Ok(if exprs.len() == 1 {
+            exprs.pop().unwrap()
+        } else {
+            LogicalExpr::And(exprs)
+        })
+    }
+
+    fn basic(&mut self) -> Result<LogicalExpr, JsonPathError> {
+        if self.peek() == Some('!') && !self.rest().starts_with("!=") {
+            self.pos += 1;
+            self.skip_blank();
+            let expr = match self.peek() {
+                Some('(') => self.paren()?,
+                _ => match self.operand()? {
+                    Operand::Query(q) => LogicalExpr::Query(q),
+                    Operand::Function(f) => self.test_function(f)?,
+                    Operand::Literal(_) => return Err(self.error("expected a query")),
+                },
+            };
+            return Ok(LogicalExpr::Not(Box::new(expr)));
+        }
+        if self.peek() == Some('(') {
+            return self.paren();
+        }
+        let operand = self.operand()?;
+        match self.after_blank(|p| p.cmp_op()) {
+            Some(op) => {
+                let lhs = self.comparable(operand)?;
+                self.skip_blank();
+                let rhs = self.operand()?;
+                let rhs = self.comparable(rhs)?;
+                Ok(LogicalExpr::Comparison(lhs, op, rhs))
+            }
+            None => match operand {
+                Operand::Query(q) => Ok(LogicalExpr::Query(q)),
+                Operand::Function(f) => self.test_function(f),
+                Operand::Literal(_) => Err(self.error("a literal must be compared")),
+            },
+        }
+    }
+
+    fn paren(&mut self) -> Result<LogicalExpr, JsonPathError> {
+        self.nested(|p| {
+            p.expect('(')?;
+            p.skip_blank();
+            let expr = p.logical_or()?;
+            p.skip_blank();
+            p.expect(')')?;
+            Ok(expr)
+        })
+    }
+
+    fn cmp_op(&mut self) -> Option<CmpOp> {
+        for (token, op) in [
+            ("==", CmpOp::Eq),
+            ("!=", CmpOp::Ne),
+            ("<=", CmpOp::Le),
+            (">=", CmpOp::Ge),
+            ("<", CmpOp::Lt),
+            (">", CmpOp::Gt),
+        ] {
+            if self.eat(token) {
+                return Some(op);
+            }
+        }
+        None
+    }
+
+    fn test_function(&self, f: FunctionExpr) -> Result<LogicalExpr, JsonPathError> {
+        if f.function.result() == Type::Value {
+            return Err(self.error("a function returning a value must be compared"));
+        }
+        Ok(LogicalExpr::Function(f))
+    }
+
+    fn comparable(&self, operand: Operand) -> Result<Comparable, JsonPathError> {
+        match operand {
+            Operand::Literal(v) => Ok(Comparable::Literal(v)),
+            Operand::Query(q) if is_singular(&q.segments) => Ok(Comparable::Query(q)),
+            Operand::Query(_) => Err(self.error("only a singular query can be compared")),
+            Operand::Function(f) if f.function.result() == Type::Value => {
+                Ok(Comparable::Function(f))
+            }
+            Operand::Function(_) => Err(self.error("a logical function can not be compared")),
+        }
+    }
+
+    /// A literal, query or function call.
+    fn operand(&mut self) -> Result<Operand, JsonPathError> {
+        match self.peek() {
+            Some(c @ ('@' | '$')) => {
+                self.pos += 1;
+                Ok(Operand::Query(FilterQuery {
+                    relative: c == '@',
+                    segments: self.segments()?,
+                }))
+            }
+            Some(q @ ('\'' | '"')) => {
+                self.pos += 1;
+                Ok(Operand::Literal(Value::String(Arc::new(self.string(q)?))))
+            }
+            Some('-' | '0'..='9') => Ok(Operand::Literal(self.number()?)),
+            Some('a'..='z') => {
+                let start = self.pos;
+                while let Some('a'..='z' | '0'..='9' | '_') = self.peek() {
+                    self.pos += 1;
+                }
+                let name = &self.input[start..self.pos];
+                if self.peek() == Some('(') {
+                    return Ok(Operand::Function(self.function(name)?));
+                }
+                match name {
+                    "true" => Ok(Operand::Literal(Value::Bool(true))),
+                    "false" => Ok(Operand::Literal(Value::Bool(false))),
+                    "null" => Ok(Operand::Literal(Value::Null)),
+                    _ => {
+                        self.pos = start;
+                        Err(self.error("expected a literal, query or function"))
+                    }
+                }
+            }
+            _ => Err(self.error("expected a literal, query or function")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, JsonPathError> {
+        let start = self.pos;
+        self.eat("-");
+        let int = self.pos;
+        if !self.digits() {
+            return Err(self.error("expected a number"));
+        }
+        if self.input[int..self.pos].starts_with('0') && self.pos - int > 1 {
+            return Err(self.error("integers may not have a leading zero"));
+        }
+        let mut float = self.input[start..self.pos] == *"-0";
+        if self.eat(".") {
+            float = true;
+            if !self.digits() {
+                return Err(self.error("expected a digit"));
+            }
+        }
+        if self.eat("e") || self.eat("E") {
+            float = true;
+            if !self.eat("-") {
+                self.eat("+");
+            }
+            if !self.digits() {
+                return Err(self.error("expected a digit"));
+            }
+        }
+        let text = &self.input[start..self.pos];
+        let number = match text.parse::<i64>() {
+            Ok(n) if !float => Some(Number::from(n)),
+            _ => text.parse::<f64>().ok().and_then(Number::from_f64),
+        };
+        number
+            .map(Value::Number)
+            .ok_or_else(|| self.error("number out of range"))
+    }
+
+    fn digits(&mut self) -> bool {
+        let start = self.pos;
+        while let Some('0'..='9') = self.peek() {
+            self.pos += 1;
+        }
+        self.pos > start
+    }
+
+    fn function(&mut self, name: &str) -> Result<FunctionExpr, JsonPathError> {
+        let function = Function::from_name(name)
+            .ok_or_else(|| self.error(&format!("unknown function '{}'", name)))?;
+        let args = self.nested(|p| {
+            p.expect('(')?;
+            p.skip_blank();
+            let mut args = Vec::new();
+            if p.peek() != Some(')') {
+                args.push(p.argument()?);
+                while p.after_blank(|p| p.eat(",").then_some(())).is_some() {
+                    p.skip_blank();
+                    args.push(p.argument()?);
+                }
+            }
+            p.skip_blank();
+            p.expect(')')?;
+            Ok(args)
+        })?;
+        let params = function.params();
+        if args.len() != params.len() {
+            return Err(self.error(&format!(
+                "{} takes {} argument(s), not {}",
+                name,
+                params.len(),
+                args.len()
+            )));
+        }
+        for (arg, param) in args.iter().zip(params) {
+            if !well_typed(arg, *param) {
+                return Err(self.error(&format!("argument of {} has the wrong type", name)));
+            }
+        }
+        let regex = match (function, args.get(1)) {
+            (Function::Match, Some(Argument::Literal(Value::String(pattern)))) => {
+                Some(iregexp::compile(pattern, true))
+            }
+            (Function::Search, Some(Argument::Literal(Value::String(pattern)))) => {
+                Some(iregexp::compile(pattern, false))
+            }
+            _ => None,
+        };
+        Ok(FunctionExpr {
+            function,
+            args,
+            regex,
+        })
+    }
+
+    fn argument(&mut self) -> Result<Argument, JsonPathError> {
+        let start = self.pos;
+        if !matches!(self.peek(), Some('!' | '(')) {
+            let operand = self.operand()?;
+            // anything else is the start of a logical expression
+            if self
+                .after_blank(|p| matches!(p.peek(), Some(',' | ')')).then_some(()))
+                .is_some()
+            {
+                return Ok(match operand {
+                    Operand::Literal(v) => Argument::Literal(v),
+                    Operand::Query(q) => Argument::Query(q),
+                    Operand::Function(f) => Argument::Function(f),
+                });
+            }
+            self.pos = start;
+        }
+        self.logical_or()?;
+        Err(self.error("no function takes a logical expression"))
+    }
+}
+
+enum Operand {
+    Literal(Value),
+    Query(FilterQuery),
+    Function(FunctionExpr),
+}
+
+/// Whether `arg` may be passed as a parameter of type `param`, per RFC 9535 §2.4.3.
+fn well_typed(arg: &Argument, param: Type) -> bool {
+    match (param, arg) {
+        (Type::Value, Argument::Literal(_)) => true,
+        (Type::Value, Argument::Query(q)) => is_singular(&q.segments),
+        (Type::Nodes, Argument::Query(_)) => true,
+        (param, Argument::Function(f)) => f.function.result() == param,
+        _ => false,
+    }
+}
@@ -0,0 +1,383 @@
+#[macro_use]
+extern crate imbl_value;
+extern crate jsonpath_lib as jsonpath;
+extern crate serde_json;
+
+use imbl_value::Value;
+use jsonpath::rfc9535::JsonPath;
+
+use common::{read_contents, read_json, setup};
+
+mod common;
+
+fn select(path: &str, json: &Value) -> Value {
+    let path = JsonPath::compile(path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+    imbl_value::to_value(&path.select(json)).unwrap()
+}
+
+fn located(path: &str, json: &Value) -> Vec<String> {
+    JsonPath::compile(path)
+        .unwrap()
+        .select_located(json)
+        .into_iter()
+        .map(|(path, _)| path.to_string())
+        .collect()
+}
+
+#[test]
+fn rfc9535_store_examples() {
+    setup();
+
+    let json = read_json("./benchmark/example.json");
+    let book = |idx: usize| json["store"]["book"][idx].clone();
+    assert_eq!(
+        select("$.store.book[*].author", &json),
+        json!([
+            "Nigel Rees",
+            "Evelyn Waugh",
+            "Herman Melville",
+            "J. R. R. Tolkien"
+        ])
+    );
+    assert_eq!(
+        select("$..author", &json),
+        select("$.store.book[*].author", &json)
+    );
+    assert_eq!(
+        select("$.store.*", &json),
+        json!([json["store"]["book"], json["store"]["bicycle"]])
+    );
+    assert_eq!(
+        select("$.store..price", &json),
+        json!([8.95, 12.99, 8.99, 22.99, 19.95])
+    );
+    assert_eq!(select("$..book[2]", &json), json!([book(2)]));
+    assert_eq!(
+        select("$..book[2].author", &json),
+        json!(["Herman Melville"])
+    );
+    assert_eq!(select("$..book[2].publisher", &json), json!([]));
+    assert_eq!(select("$..book[-1]", &json), json!([book(3)]));
+    assert_eq!(select("$..book[0,1]", &json), json!([book(0), book(1)]));
+    assert_eq!(select("$..book[:2]", &json), json!([book(0), book(1)]));
+    assert_eq!(select("$..book[?@.isbn]", &json), json!([book(2), book(3)]));
+    assert_eq!(
+        select("$..book[?@.price<10]", &json),
+        json!([book(0), book(2)])
+    );
+    assert_eq!(
+        select("$..book[?@.price < $.expensive]", &json),
+        json!([book(0), book(2)])
+    );
+}
+
+#[test]
+fn rfc9535_selectors() {
+    setup();
+
+    let json = json!(["a", "b", "c", "d", "e", "f", "g"]);
+    assert_eq!(select("$[1:3]", &json), json!(["b", "c"]));
+    assert_eq!(select("$[5:]", &json), json!(["f", "g"]));
+    assert_eq!(select("$[1:5:2]", &json), json!(["b", "d"]));
+    assert_eq!(select("$[5:1:-2]", &json), json!(["f", "d"]));
+    assert_eq!(
+        select("$[::-1]", &json),
+        json!(["g", "f", "e", "d", "c", "b", "a"])
+    );
+    assert_eq!(select("$[::0]", &json), json!([]));
+    assert_eq!(select("$[-2, 0, 7]", &json), json!(["f", "a"]));
+
+    let json = json!({"o": {"j": 1, "k": 2}, "a": [5, 3, [{"j": 4}, {"k": 6}]]});
+    assert_eq!(select("$..j", &json), json!([1, 4]));
+    assert_eq!(select("$..[0]", &json), json!([5, {"j": 4}]));
+    assert_eq!(select("$.o['j', 'k']", &json), json!([1, 2]));
+    assert_eq!(select("$.o[\"\\u006a\"]", &json), json!([1]));
+    assert_eq!(select("$['o'].j", &json), json!([1]));
+    assert_eq!(select("$.a[0]['x']", &json), json!([]));
+}
+
+#[test]
+fn rfc9535_filters() {
+    setup();
+
+    let json = json!({
+        "a": [3, 5, 1, 2, 4, 6, {"b": "j"}, {"b": "k"}, {"b": {}}, {"b": "kilo"}],
+        "o": {"p": 1, "q": 2, "r": 3, "s": 5, "t": {"u": 6}},
+        "e": "f"
+    });
+    assert_eq!(select("$.a[?@.b == 'kilo']", &json), json!([{"b": "kilo"}]));
+    assert_eq!(
+        select("$.a[?(@.b == 'kilo')]", &json),
+        json!([{"b": "kilo"}])
+    );
+    assert_eq!(select("$.a[?@>3.5]", &json), json!([5, 4, 6]));
+    assert_eq!(
+        select("$.a[?@.b]", &json),
+        json!([{"b": "j"}, {"b": "k"}, {"b": {}}, {"b": "kilo"}])
+    );
+    assert_eq!(select("$[?@.*]", &json), json!([json["a"], json["o"]]));
+    assert_eq!(select("$[?@[?@.b]]", &json), json!([json["a"]]));
+    assert_eq!(select("$.o[?@<3, ?@<3]", &json), json!([1, 2, 1, 2]));
+    assert_eq!(
+        select("$.a[?@<2 || @.b == \"k\"]", &json),
+        json!([1, {"b": "k"}])
+    );
+    assert_eq!(
+        select("$.a[?match(@.b, \"[jk]\")]", &json),
+        json!([{"b": "j"}, {"b": "k"}])
+    );
+    assert_eq!(
+        select("$.a[?search(@.b, \"[jk]\")]", &json),
+        json!([{"b": "j"}, {"b": "k"}, {"b": "kilo"}])
+    );
+    assert_eq!(select("$.o[?@>1 && @<4]", &json), json!([2, 3]));
+    assert_eq!(select("$.o[?@.u || @.x]", &json), json!([{"u": 6}]));
+    assert_eq!(select("$.a[?@.b == $.x]", &json), json!([3, 5, 1, 2, 4, 6]));
+    assert_eq!(select("$.a[?@ == @]", &json), json["a"]);
+    assert_eq!(select("$.a[?!@.b]", &json), json!([3, 5, 1, 2, 4, 6]));
+    assert_eq!(select("$.a[?@ == 1.0]", &json), json!([1]));
+    assert_eq!(select("$.a[?@.b == $.a[8].b]", &json), json!([{"b": {}}]));
+}
+
+#[test]
+fn rfc9535_functions() {
+    setup();
+
+    let json = json!([
+        {"name": "a", "tags": ["x", "y"], "tz": "Europe/Berlin"},
+        {"name": "bb", "tags": [], "tz": "America/New_York"},
+        {"name": "ccc", "tags": ["x"], "tz": "Europe/\nParis"}
+    ]);
+    assert_eq!(
+        select("$[?length(@.name) > 1].name", &json),
+        json!(["bb", "ccc"])
+    );
+    assert_eq!(
+        select("$[?count(@.tags[*]) == 1].name", &json),
+        json!(["ccc"])
+    );
+    assert_eq!(
+        select("$[?value(@.tags[0]) == 'x'].name", &json),
+        json!(["a", "ccc"])
+    );
+    assert_eq!(
+        select("$[?match(@.tz, 'Europe/.*')].name", &json),
+        json!(["a"])
+    );
+    assert_eq!(select("$[?search(@.tz, 'York$')].name", &json), json!([]));
+    assert_eq!(
+        select("$[?search(@.tz, '/[\\\\p{Lu}]')].name", &json),
+        json!(["a", "bb"])
+    );
+    assert_eq!(
+        select("$[?match(@.name, @.name)].name", &json),
+        json!(["a", "bb", "ccc"])
+    );
+    assert_eq!(
+        select("$[?length(@) == 3].name", &json),
+        json!(["a", "bb", "ccc"])
+    );
+    assert_eq!(select("$[?length(1) == 1]", &json), json!([]));
+}
+
+#[test]
+fn rfc9535_invalid() {
+    setup();
+
+    for path in [
+        "",
+        " $",
+        "$ ",
+        "$.",
+        "$..",
+        "$. a",
+        "$[]",
+        "$[01]",
+        "$[-0]",
+        "$[9007199254740992]",
+        "$['a'",
+        "$['\\'']x",
+        "$[\"\\'\"]",
+        "$['\u{1}']",
+        "$['\\uD800']",
+        "$[?true]",
+        "$[?1]",
+        "$[?@.a == 1 == 2]",
+        "$[?@.* == 1]",
+        "$[?@..a == 1]",
+        "$[?length(@.*) < 3]",
+        "$[?count(1) == 1]",
+        "$[?count(foo(@.*)) == 1]",
+        "$[?match(@.timezone, 'Europe/.*') == true]",
+        "$[?value(@..color)]",
+        "$[?length(@)]",
+        "$[?length(@.a, @.b) == 1]",
+        "$[?count(@.a == 1) == 1]",
+        "$[?!@.a == 1]",
+        "$[?@.a === 1]",
+        "$[?(@.a]",
+        "$[?@.a == 01]",
+        "$[?@.a == 1.]",
+        "$[?@.a == .1]",
+        "$[?@.b == {}]",
+    ] {
+        assert!(
+            JsonPath::compile(path).is_err(),
+            "{:?} should be invalid",
+            path
+        );
+    }
+    for path in [
+        "$",
+        "$ .a",
+        "$[ 0 , 1 ]",
+        "$[?count(@.*) == 1]",
+        "$[?match(@.timezone, 'Europe/.*')]",
+        "$[?value(@..color) == \"red\"]",
+        "$[?length(@) < 3]",
+        "$[? !(@.a) && (@.b || @.c)]",
+        "$[?@.a == -0 || @.a == 1e3 || @.a == -1.5E-2]",
+        "$[?1 == 1]",
+        "$.\u{e9}t\u{e9}",
+        "$[1:2:]",
+    ] {
+        assert!(
+            JsonPath::compile(path).is_ok(),
+            "{:?} should be valid",
+            path
+        );
+    }
+}
+
+#[test]
+fn rfc9535_invalid_iregexp() {
+    setup();
+
+    let json = json!(["a", "a*", "(a)", "^a$"]);
+    // not I-Regexps, so they match nothing rather than fail the query
+    for pattern in ["a*?", "(?i)a", "\\d", "a{1", "[]", "\\w+", "(a"] {
+        let path = format!("$[?match(@, '{}')]", pattern.replace('\\', "\\\\"));
+        assert_eq!(select(&path, &json), json!([]), "{}", pattern);
+    }
+    assert_eq!(select("$[?match(@, '\\\\^a\\\\$')]", &json), json!([]));
+    assert_eq!(select("$[?match(@, '^a$')]", &json), json!(["^a$"]));
+    assert_eq!(select("$[?match(@, 'a\\\\*')]", &json), json!(["a*"]));
+    assert_eq!(select("$[?match(@, '[(]a[)]')]", &json), json!(["(a)"]));
+    assert_eq!(select("$[?match(@, '.{1,2}')]", &json), json!(["a", "a*"]));
+}
+
+#[test]
+fn rfc9535_normalized_paths() {
+    setup();
+
+    let json = json!({"a": [1, {"b'\\\n\u{b}": 2}], "c": 3});
+    assert_eq!(located("$", &json), ["$"]);
+    assert_eq!(
+        located("$.a[-1].*", &json),
+        ["$['a'][1]['b\\'\\\\\\n\\u000b']"]
+    );
+    assert_eq!(
+        located("$..*", &json),
+        [
+            "$['a']",
+            "$['c']",
+            "$['a'][0]",
+            "$['a'][1]",
+            "$['a'][1]['b\\'\\\\\\n\\u000b']"
+        ]
+    );
+    let (path, _) = JsonPath::compile("$.a[1][?@ == 2]")
+        .unwrap()
+        .select_located(&json)
+        .remove(0);
+    assert_eq!(path.to_json_pointer(), "/a/1/b'\\\n\u{b}");
+    assert!(JsonPath::compile("$.a[1]['b']").unwrap().is_singular());
+    assert!(!JsonPath::compile("$.a[1:]").unwrap().is_singular());
+}
+
+/// Checks every case of a file in the
+/// [JSONPath Compliance Test Suite](https://github.com/jsonpath-standard/jsonpath-compliance-test-suite)
+/// format, returning one message per failing case.
+fn run_cts(file: &str) -> Vec<String> {
+    let cts: serde_json::Value = serde_json::from_str(&read_contents(file)).unwrap();
+    let mut failures = Vec::new();
+    for case in cts["tests"].as_array().unwrap() {
+        let name = case["name"].as_str().unwrap();
+        let selector = case["selector"].as_str().unwrap();
+        let path = JsonPath::compile(selector);
+        if case["invalid_selector"].as_bool() == Some(true) {
+            if path.is_ok() {
+                failures.push(format!("{}: {:?} should be invalid", name, selector));
+            }
+            continue;
+        }
+        let path = match path {
+            Ok(path) => path,
+            Err(e) => {
+                failures.push(format!("{}: {:?} failed to compile: {}", name, selector, e));
+                continue;
+            }
+        };
+        let document: Value = serde_json::from_value(case["document"].clone()).unwrap();
+        let located = path.select_located(&document);
+        let values = json!(located.iter().map(|(_, v)| *v).collect::<Vec<_>>());
+        let paths = json!(located
+            .iter()
+            .map(|(p, _)| p.to_string())
+            .collect::<Vec<_>>());
+        let expected = |key: &str| -> Option<Vec<Value>> {
+            let expected: Value = serde_json::from_value(case[key].clone()).ok()?;
+            match expected {
+                Value::Null => None,
+                Value::Array(_) if key.starts_with("results") => {
+                    expected.as_array().map(|all| all.iter().cloned().collect())
+                }
+                expected => Some(vec![expected]),
+            }
+        };
+        let matches = |actual: &Value, key: &str| {
+            expected(key).is_none_or(|expected| expected.contains(actual))
+        };
+        if !(matches(&values, "result") && matches(&values, "results")) {
+            failures.push(format!("{}: {:?} selected {}", name, selector, values));
+        } else if !(matches(&paths, "result_paths") && matches(&paths, "results_paths")) {
+            failures.push(format!("{}: {:?} located {}", name, selector, paths));
+        }
+    }
+    failures
+}
+
+/// Runs `tests/cts-subset.json`, a checked-in selection of compliance cases
+/// covering each selector, the filter operators and the function extensions.
+#[test]
+fn rfc9535_compliance_subset() {
+    setup();
+
+    let failures = run_cts("./tests/cts-subset.json");
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+/// Runs the full compliance suite, once `./fetch-cts.sh` has downloaded it.
+#[test]
+#[ignore]
+fn rfc9535_compliance_test_suite() {
+    setup();
+
+    let failures = run_cts("./tests/cts.json");
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn rfc9535_nesting_limit() {
+    setup();
+
+    let nested =
+        |depth: usize| format!("$[?{}@.a{}]", "(".repeat(depth - 1), ")".repeat(depth - 1));
+    assert!(JsonPath::compile(&nested(jsonpath::rfc9535::MAX_DEPTH)).is_ok());
+    assert!(JsonPath::compile(&nested(jsonpath::rfc9535::MAX_DEPTH + 1)).is_err());
+    let filters =
+        |depth: usize| format!("$[?{}@{}]", "@[?".repeat(depth - 1), "]".repeat(depth - 1));
+    assert!(JsonPath::compile(&filters(jsonpath::rfc9535::MAX_DEPTH)).is_ok());
+    assert!(JsonPath::compile(&filters(100_000)).is_err());
+    assert!(JsonPath::compile(&format!("$[?{}@.a", "(".repeat(100_000))).is_err());
+}
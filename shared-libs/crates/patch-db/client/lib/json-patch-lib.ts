@@ -36,7 +36,25 @@ export interface ReplaceOperation<T> extends BaseOperation {
 }
 
 /**
- * A single RFC 6902 patch operation (add, remove, or replace).
+ * An RFC 6902 "move" operation. Removes the value at {@link from} and adds it at {@link path}.
+ */
+export interface MoveOperation extends BaseOperation {
+  op: PatchOp.MOVE
+  /** RFC 6901 JSON Pointer to the value being moved. */
+  from: string
+}
+
+/**
+ * An RFC 6902 "copy" operation. Adds a copy of the value at {@link from} at {@link path}.
+ */
+export interface CopyOperation extends BaseOperation {
+  op: PatchOp.COPY
+  /** RFC 6901 JSON Pointer to the value being copied. */
+  from: string
+}
+
+/**
+ * A single RFC 6902 patch operation (add, remove, replace, move, or copy).
  *
  * @typeParam T - The type of values carried by add/replace operations.
  */
@@ -44,6 +62,8 @@ export type Operation<T> =
   | AddOperation<T>
   | RemoveOperation
   | ReplaceOperation<T>
+  | MoveOperation
+  | CopyOperation
 
 /**
  * Sentinel value used internally to distinguish a "remove" result from a
@@ -98,13 +118,33 @@ export function getValueByPointer<T extends Record<string, T>>(
  * reference identity changes propagate correctly for UI framework change detection.
  *
  * @param doc - The document to modify. The `value` field is replaced with the updated state.
- * @param op - The operation to apply. Must include `path` and `op`; `value` is required for add/replace.
+ * @param operation - The operation to apply. `value` is required for add/replace, `from` for move/copy.
  */
 export function applyOperation<T>(
   doc: Dump<Record<string, any>>,
-  { path, op, value }: Operation<T> & { value?: T },
+  operation: Operation<T>,
 ) {
-  doc.value = recursiveApply(doc.value, arrayFromPath(path), op, value)
+  const path = arrayFromPath(operation.path)
+  switch (operation.op) {
+    case PatchOp.MOVE:
+    case PatchOp.COPY: {
+      const value = getValueByPointer(doc.value, operation.from)
+      if (operation.op === PatchOp.MOVE) {
+        doc.value = recursiveApply(
+          doc.value,
+          arrayFromPath(operation.from),
+          PatchOp.REMOVE,
+        )
+      }
+      doc.value = recursiveApply(doc.value, path, PatchOp.ADD, value)
+      break
+    }
+    case PatchOp.REMOVE:
+      doc.value = recursiveApply(doc.value, path, operation.op)
+      break
+    default:
+      doc.value = recursiveApply(doc.value, path, operation.op, operation.value)
+  }
 }
 
 /**
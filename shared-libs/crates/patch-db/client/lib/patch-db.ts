@@ -1,4 +1,4 @@
-import { Dump, Revision, Update } from './types'
+import { Dump, PatchOp, Revision, Update } from './types'
 import {
   BehaviorSubject,
   filter,
@@ -228,9 +228,15 @@ export class PatchDB<T extends { [key: string]: any }> {
     // @claude fix #20: Previously, arrayFromPath(op.path) was called for every
     // (watchedNode, patchOp) pair — O(watchedNodes × patchOps) redundant parsing.
     // Pre-converting once outside the loop makes it O(patchOps + watchedNodes).
-    const patchArrs = revision.patch.map(op => ({
-      path: op.path,
-      arr: arrayFromPath(op.path),
+    const patchPaths: string[] = []
+    revision.patch.forEach(op => {
+      patchPaths.push(op.path)
+      // a move also changes what is at its source
+      if (op.op === PatchOp.MOVE) patchPaths.push(op.from)
+    })
+    const patchArrs = patchPaths.map(path => ({
+      path,
+      arr: arrayFromPath(path),
     }))
     // update watched nodes
     Object.entries(this.watchedNodes).forEach(([watchedPath, { pathArr }]) => {
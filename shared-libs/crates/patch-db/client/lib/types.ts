@@ -26,12 +26,14 @@ export type Dump<T> = { id: number; value: T }
 export type Update<T> = Revision | Dump<T>
 
 /**
- * The three JSON Patch operation types produced by patch-db.
+ * The JSON Patch operation types produced by patch-db.
  *
- * Only `add`, `remove`, and `replace` are used — `test`, `move`, and `copy` are not produced by the server.
+ * `test` is never sent to clients.
  */
 export enum PatchOp {
   ADD = 'add',
   REMOVE = 'remove',
   REPLACE = 'replace',
+  MOVE = 'move',
+  COPY = 'copy',
 }
@@ -0,0 +1,535 @@
+use std::cell::RefCell;
+use std::ffi::OsString;
+use std::future::Future;
+use std::io::{BufReader, SeekFrom};
+use std::ops::RangeBounds;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use imbl_value::Value;
+use json_patch::{AddOperation, Patch, PatchOperation, RemoveOperation, ReplaceOperation};
+use json_ptr::{JsonPointer, SegList};
+use serde::{Deserialize, Serialize};
+use tokio::fs::File;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::patch::{DiffPatch, Dump, Revision};
+use crate::Error;
+
+tokio::task_local! {
+    static METADATA: RefCell<Value>;
+}
+
+/// Run `f` with `metadata` attached to every revision it produces, e.g. the
+/// RPC method and session responsible. [`update_metadata`] changes it from
+/// within `f`.
+pub async fn with_metadata<F: Future>(metadata: Value, f: F) -> F::Output {
+    METADATA.scope(RefCell::new(metadata), f).await
+}
+
+/// Change the metadata of the enclosing [`with_metadata`]. Outside of one,
+/// this does nothing.
+pub fn update_metadata(f: impl FnOnce(&mut Value)) {
+    let _ = METADATA.try_with(|m| f(&mut m.borrow_mut()));
+}
+
+fn current_metadata() -> Value {
+    METADATA
+        .try_with(|m| m.borrow().clone())
+        .unwrap_or_default()
+}
+
+/// How [`PatchDb::open_with_history`](crate::PatchDb::open_with_history)
+/// records revisions.
+#[derive(Debug, Clone)]
+pub struct HistoryOptions {
+    /// Object members left out of the log, e.g. secrets. Revisions and
+    /// checkpoints are redacted before they are written, so neither
+    /// [`PatchDb::history`](crate::PatchDb::history) nor
+    /// [`PatchDb::value_at`](crate::PatchDb::value_at) ever sees them.
+    pub exclude: Vec<JsonPointer>,
+    /// Once the current segment of the log grows past this many bytes, it is
+    /// rotated out and a new one is started with a checkpoint.
+    pub segment_size: u64,
+    /// How many segments to keep, including the current one. Revisions in
+    /// older ones are forgotten.
+    pub segments: usize,
+}
+impl Default for HistoryOptions {
+    fn default() -> Self {
+        Self {
+            exclude: Vec::new(),
+            segment_size: 16 * 1024 * 1024,
+            segments: 4,
+        }
+    }
+}
+
+/// A [`Revision`] as recorded in the history log.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct HistoryEntry {
+    pub id: u64,
+    pub timestamp: SystemTime,
+    /// Whatever [`with_metadata`] attached, or `null`
+    pub metadata: Value,
+    pub patch: DiffPatch,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum Record {
+    /// The full state as of a revision. Written at the start of every segment
+    /// and whenever the log would otherwise skip revisions, so that every
+    /// later state can be rebuilt.
+    Checkpoint(Dump),
+    Revision(HistoryEntry),
+}
+impl Record {
+    fn id(&self) -> u64 {
+        match self {
+            Record::Checkpoint(dump) => dump.id,
+            Record::Revision(entry) => entry.id,
+        }
+    }
+}
+
+/// `path` for the current segment, `path.<n>` for the one rotated out `n`
+/// rotations ago.
+fn segment_path(path: &Path, n: usize) -> PathBuf {
+    if n == 0 {
+        return path.to_owned();
+    }
+    let mut res = OsString::from(path);
+    res.push(format!(".{}", n));
+    res.into()
+}
+
+/// The segments of the log at `path`, oldest first.
+fn segments(path: &Path) -> Vec<PathBuf> {
+    let mut res = vec![path.to_owned()];
+    res.extend(
+        (1..)
+            .map(|n| segment_path(path, n))
+            .take_while(|rotated| rotated.exists()),
+    );
+    res.reverse();
+    res
+}
+
+/// Feed the records of the segment at `path` to `f` one at a time, in order.
+/// Returns where the last whole record ends: a torn write at the end is
+/// dropped, just like in the main file.
+fn read_segment(path: &Path, mut f: impl FnMut(Record)) -> Result<u64, Error> {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        // rotated out from under us
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    let mut stream =
+        serde_cbor::Deserializer::from_reader(BufReader::new(file)).into_iter::<Record>();
+    let mut end = 0;
+    while let Some(Ok(record)) = stream.next() {
+        end = stream.byte_offset() as u64;
+        f(record);
+    }
+    Ok(end)
+}
+
+pub(crate) fn overlaps<S: AsRef<str>, V: SegList>(
+    path: &JsonPointer<S, V>,
+    exclude: &[JsonPointer],
+) -> bool {
+    exclude
+        .iter()
+        .any(|ex| path.starts_with(ex) || ex.starts_with(path))
+}
+
+fn excluded<S: AsRef<str>, V: SegList>(path: &JsonPointer<S, V>, exclude: &[JsonPointer]) -> bool {
+    exclude.iter().any(|ex| path.starts_with(ex))
+}
+
+/// `value`, found at `at`, without the excluded members under it.
+fn redact_value<S: AsRef<str>, V: SegList>(
+    at: &JsonPointer<S, V>,
+    value: &Value,
+    exclude: &[JsonPointer],
+) -> Value {
+    let mut res = value.clone();
+    for ex in exclude {
+        if let Some(tail) = ex.strip_prefix(at) {
+            if !tail.is_empty() {
+                tail.remove(&mut res, false);
+            }
+        }
+    }
+    res
+}
+
+/// Whether any operation of `patch` reads or writes an excluded member or one of
+/// its ancestors.
+pub(crate) fn patch_overlaps(patch: &DiffPatch, exclude: &[JsonPointer]) -> bool {
+    (patch.0).0.iter().any(|op| match op {
+        PatchOperation::Add(op) => overlaps(&op.path, exclude),
+        PatchOperation::Remove(op) => overlaps(&op.path, exclude),
+        PatchOperation::Replace(op) => overlaps(&op.path, exclude),
+        PatchOperation::Test(op) => overlaps(&op.path, exclude),
+        PatchOperation::Move(op) => overlaps(&op.from, exclude) || overlaps(&op.path, exclude),
+        PatchOperation::Copy(op) => overlaps(&op.from, exclude) || overlaps(&op.path, exclude),
+    })
+}
+
+/// `patch` as it applies to a state without the excluded members.
+pub(crate) fn redact_patch(patch: &DiffPatch, exclude: &[JsonPointer]) -> DiffPatch {
+    if exclude.is_empty() {
+        return patch.clone();
+    }
+    let mut res = Vec::new();
+    // the value a move or copy takes, from the test before it
+    let mut source: Option<&Value> = None;
+    for op in &(patch.0).0 {
+        match op {
+            PatchOperation::Add(op) if !excluded(&op.path, exclude) => {
+                res.push(PatchOperation::Add(AddOperation {
+                    path: op.path.clone(),
+                    value: redact_value(&op.path, &op.value, exclude),
+                }))
+            }
+            PatchOperation::Replace(op) if !excluded(&op.path, exclude) => {
+                res.push(PatchOperation::Replace(ReplaceOperation {
+                    path: op.path.clone(),
+                    value: redact_value(&op.path, &op.value, exclude),
+                }))
+            }
+            PatchOperation::Remove(op) if !excluded(&op.path, exclude) => {
+                res.push(PatchOperation::Remove(op.clone()))
+            }
+            PatchOperation::Test(op) => {
+                source = Some(&op.value);
+                if !excluded(&op.path, exclude) {
+                    let mut op = op.clone();
+                    op.value = redact_value(&op.path, &op.value, exclude);
+                    res.push(PatchOperation::Test(op));
+                }
+            }
+            PatchOperation::Move(op)
+                if overlaps(&op.from, exclude) || overlaps(&op.path, exclude) =>
+            {
+                let source = source.take().cloned().unwrap_or_default();
+                if !excluded(&op.from, exclude) {
+                    res.push(PatchOperation::Remove(RemoveOperation {
+                        path: op.from.clone(),
+                    }));
+                }
+                if !excluded(&op.path, exclude) {
+                    res.push(PatchOperation::Add(AddOperation {
+                        value: redact_value(&op.path, &source, exclude),
+                        path: op.path.clone(),
+                    }));
+                }
+            }
+            PatchOperation::Copy(op)
+                if overlaps(&op.from, exclude) || overlaps(&op.path, exclude) =>
+            {
+                let source = source.take().cloned().unwrap_or_default();
+                if !excluded(&op.path, exclude) {
+                    res.push(PatchOperation::Add(AddOperation {
+                        value: redact_value(&op.path, &source, exclude),
+                        path: op.path.clone(),
+                    }));
+                }
+            }
+            PatchOperation::Move(_) | PatchOperation::Copy(_) => {
+                source = None;
+                res.push(op.clone());
+            }
+            // adds, replaces and removes of excluded members
+            _ => (),
+        }
+    }
+    DiffPatch(Patch(res))
+}
+
+enum Command {
+    Record {
+        revision: Arc<Revision>,
+        timestamp: SystemTime,
+        metadata: Value,
+        state: Value,
+    },
+    Flush(oneshot::Sender<()>),
+}
+
+/// The optional append-only log of every revision a [`Store`](crate::Store)
+/// applies. Unlike the store's own file, it is never compressed, but it is
+/// rotated per [`HistoryOptions`].
+///
+/// The store only queues revisions here: a background task redacts, writes and
+/// syncs them, so the store's lock is never held for the log's disk writes.
+pub(crate) struct HistoryLog {
+    path: PathBuf,
+    send: mpsc::UnboundedSender<Command>,
+}
+impl HistoryLog {
+    pub(crate) async fn open(
+        path: PathBuf,
+        options: HistoryOptions,
+        current: &Dump,
+    ) -> Result<Self, Error> {
+        let mut writer = Writer::open(path.clone(), options).await?;
+        if writer.last != Some(current.id) {
+            writer.checkpoint(current.id, &current.value).await?;
+            writer.file.sync_data().await?;
+        }
+        let (send, recv) = mpsc::unbounded_channel();
+        tokio::spawn(writer.run(recv));
+        Ok(HistoryLog { path, send })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Queue `revision`, which brought the store to `state`. The metadata of
+    /// the enclosing [`with_metadata`] is taken now, while still inside it.
+    pub(crate) fn record(&self, revision: Arc<Revision>, state: &Value) {
+        let _ = self.send.send(Command::Record {
+            revision,
+            timestamp: SystemTime::now(),
+            metadata: current_metadata(),
+            state: state.clone(),
+        });
+    }
+
+    /// Resolves once everything queued so far is on disk. The flush is queued
+    /// right away, so the future need not be awaited under the store's lock.
+    pub(crate) fn flush(&self) -> impl Future<Output = ()> + Send + 'static {
+        let (done, flushed) = oneshot::channel();
+        let _ = self.send.send(Command::Flush(done));
+        async move {
+            let _ = flushed.await;
+        }
+    }
+}
+
+struct Writer {
+    path: PathBuf,
+    options: HistoryOptions,
+    file: File,
+    file_cursor: u64,
+    /// The revision the log can rebuild the state of, if it is the store's
+    /// current one
+    last: Option<u64>,
+    /// Written since the last sync
+    dirty: bool,
+}
+impl Writer {
+    async fn open(path: PathBuf, options: HistoryOptions) -> Result<Self, Error> {
+        let (file_cursor, last) = tokio::task::spawn_blocking({
+            let path = path.clone();
+            move || {
+                let mut last = None;
+                let cursor = read_segment(&path, |record| last = Some(record.id()))?;
+                Ok::<_, Error>((cursor, last))
+            }
+        })
+        .await??;
+        let file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(&path)
+            .await?;
+        Ok(Writer {
+            path,
+            options,
+            file,
+            file_cursor,
+            last,
+            dirty: false,
+        })
+    }
+
+    async fn run(mut self, mut recv: mpsc::UnboundedReceiver<Command>) {
+        while let Some(command) = recv.recv().await {
+            let res = match command {
+                Command::Record {
+                    revision,
+                    timestamp,
+                    metadata,
+                    state,
+                } => self.append(&revision, timestamp, metadata, &state).await,
+                Command::Flush(done) => {
+                    let res = self.sync().await;
+                    let _ = done.send(());
+                    res
+                }
+            };
+            // sync once the queue drains, not after every revision
+            let res = match res {
+                Ok(()) if recv.is_empty() => self.sync().await,
+                res => res,
+            };
+            if let Err(_e) = res {
+                // the next revision starts over from a checkpoint
+                self.last = None;
+                #[cfg(feature = "tracing")]
+                tracing::error!("Error recording revision in history: {}", _e);
+            }
+        }
+        let _ = self.sync().await;
+    }
+
+    async fn write(&mut self, record: &Record) -> Result<(), Error> {
+        let bin = serde_cbor::to_vec(record)?;
+        self.dirty = true;
+        // drop whatever a failed write left behind
+        self.file.set_len(self.file_cursor).await?;
+        self.file.seek(SeekFrom::Start(self.file_cursor)).await?;
+        self.file.write_all(&bin).await?;
+        self.file.flush().await?;
+        self.file_cursor += bin.len() as u64;
+        Ok(())
+    }
+
+    async fn sync(&mut self) -> Result<(), Error> {
+        if self.dirty {
+            self.file.sync_data().await?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+
+    async fn checkpoint(&mut self, id: u64, state: &Value) -> Result<(), Error> {
+        self.last = None;
+        let value = redact_value(
+            &JsonPointer::<&str>::default(),
+            state,
+            &self.options.exclude,
+        );
+        self.write(&Record::Checkpoint(Dump { id, value })).await?;
+        self.last = Some(id);
+        Ok(())
+    }
+
+    /// Record `revision`, which brought the store to `state`.
+    async fn append(
+        &mut self,
+        revision: &Revision,
+        timestamp: SystemTime,
+        metadata: Value,
+        state: &Value,
+    ) -> Result<(), Error> {
+        let consecutive = self.last.is_some_and(|last| last + 1 == revision.id);
+        self.last = None;
+        self.write(&Record::Revision(HistoryEntry {
+            id: revision.id,
+            timestamp,
+            metadata,
+            patch: redact_patch(&revision.patch, &self.options.exclude),
+        }))
+        .await?;
+        if self.file_cursor >= self.options.segment_size {
+            self.rotate().await?;
+            self.checkpoint(revision.id, state).await
+        } else if !consecutive {
+            self.checkpoint(revision.id, state).await
+        } else {
+            self.last = Some(revision.id);
+            Ok(())
+        }
+    }
+
+    /// Move the current segment to `path.1`, shifting older ones up and
+    /// deleting those past [`HistoryOptions::segments`], and start an empty
+    /// one.
+    async fn rotate(&mut self) -> Result<(), Error> {
+        self.sync().await?;
+        let keep = self.options.segments.max(1);
+        // the oldest segment, and any left over from a larger `segments`
+        let mut n = (keep - 1).max(1);
+        while tokio::fs::metadata(segment_path(&self.path, n))
+            .await
+            .is_ok()
+        {
+            tokio::fs::remove_file(segment_path(&self.path, n)).await?;
+            n += 1;
+        }
+        if keep > 1 {
+            for n in (1..keep - 1).rev() {
+                let rotated = segment_path(&self.path, n);
+                if tokio::fs::metadata(&rotated).await.is_ok() {
+                    tokio::fs::rename(&rotated, segment_path(&self.path, n + 1)).await?;
+                }
+            }
+            tokio::fs::rename(&self.path, segment_path(&self.path, 1)).await?;
+            self.file = File::create(&self.path).await?;
+        } else {
+            self.file.set_len(0).await?;
+        }
+        self.file_cursor = 0;
+        self.last = None;
+        Ok(())
+    }
+}
+
+/// The entries of the log at `path` with ids in `range`, oldest first and
+/// scoped to `ptr`. Entries that did not touch `ptr` are left out.
+pub(crate) fn entries<S: AsRef<str>, V: SegList>(
+    path: &Path,
+    ptr: &JsonPointer<S, V>,
+    range: impl RangeBounds<u64>,
+) -> Result<Vec<HistoryEntry>, Error> {
+    let mut res = Vec::new();
+    for segment in segments(path) {
+        read_segment(&segment, |record| match record {
+            Record::Revision(entry) if range.contains(&entry.id) => {
+                let entry = HistoryEntry {
+                    patch: entry.patch.for_path(ptr),
+                    ..entry
+                };
+                if !entry.patch.is_empty() {
+                    res.push(entry);
+                }
+            }
+            _ => (),
+        })?;
+    }
+    Ok(res)
+}
+
+/// The value at `ptr` as of revision `id`, rebuilt from the log at `path`.
+/// `None` if the log does not reach back that far.
+pub(crate) fn value_at<S: AsRef<str>, V: SegList>(
+    path: &Path,
+    ptr: &JsonPointer<S, V>,
+    id: u64,
+) -> Result<Option<Value>, Error> {
+    let mut state: Option<Dump> = None;
+    let mut found = None;
+    for segment in segments(path) {
+        read_segment(&segment, |record| {
+            match record {
+                Record::Checkpoint(dump) => state = Some(dump),
+                Record::Revision(entry) => {
+                    state = state
+                        .take()
+                        .filter(|dump| dump.id + 1 == entry.id)
+                        .and_then(|mut dump| {
+                            json_patch::patch(&mut dump.value, &entry.patch).ok()?;
+                            dump.id = entry.id;
+                            Some(dump)
+                        });
+                }
+            }
+            if let Some(dump) = state.as_ref().filter(|dump| dump.id == id) {
+                // keep looking: a store that was replaced reuses revision ids
+                found = Some(ptr.get(&dump.value).cloned().unwrap_or_default());
+            }
+        })?;
+    }
+    Ok(found)
+}
@@ -4,23 +4,29 @@ use std::sync::Arc;
 use json_ptr::JsonPointer;
 use thiserror::Error;
 
+mod history;
 mod model;
 mod patch;
+mod query;
+mod storage;
 mod store;
 mod subscriber;
 
 #[cfg(test)]
 mod test;
 
+pub use history::{update_metadata, with_metadata, HistoryEntry, HistoryOptions};
 pub use imbl_value as value;
 pub use imbl_value::Value;
 pub use json_patch;
 pub use json_ptr;
 pub use model::{DestructureMut, HasModel, Model, ModelExt, Pointer};
-pub use patch::{DiffPatch, Dump, Revision};
+pub use patch::{Catchup, DiffPatch, Dump, Revision};
 pub use patch_db_macro::HasModel;
+pub use query::{Query, Selection};
+pub use storage::{Cipher, EncryptedStorage, FileStorage, MemoryStorage, Storage};
 pub use store::{MutateResult, PatchDb, Store, TypedPatchDb};
-pub use subscriber::{DbWatch, Subscriber, TypedDbWatch};
+pub use subscriber::{DbWatch, QueryWatch, Subscriber, TypedDbWatch};
 use tokio::sync::TryLockError;
 
 #[derive(Error, Debug)]
@@ -47,6 +53,14 @@ pub enum Error {
     NodeDoesNotExist(JsonPointer),
     #[error("Provided Function Panicked! {0}")]
     Panic(String),
+    #[error("History Not Recorded")]
+    NoHistory,
+    #[error("Revision Not In History: {0}")]
+    NotInHistory(u64),
+    #[error("Revision Conflict: expected {expected}, found {actual}")]
+    Conflict { expected: u64, actual: u64 },
+    #[error("JSONPath Error: {0}")]
+    JsonPath(String),
     #[error("Would Block")]
     WouldBlock(#[from] TryLockError),
 }
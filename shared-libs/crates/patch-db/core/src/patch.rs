@@ -2,7 +2,10 @@ use std::collections::BTreeSet;
 use std::ops::Deref;
 
 use imbl_value::Value;
-use json_patch::{AddOperation, Patch, PatchOperation, RemoveOperation, ReplaceOperation};
+use json_patch::{
+    AddOperation, CopyOperation, MoveOperation, Patch, PatchOperation, RemoveOperation,
+    ReplaceOperation, TestOperation,
+};
 use json_ptr::{JsonPointer, SegList};
 use serde::{Deserialize, Serialize};
 
@@ -28,6 +31,20 @@ pub struct Dump {
     pub value: Value,
 }
 
+/// What a subscriber resuming from a revision it has already seen needs to
+/// catch up. See [`PatchDb::subscribe_from`](crate::PatchDb::subscribe_from).
+#[derive(Debug, Clone)]
+pub enum Catchup {
+    /// The revisions it missed, scoped to its pointer. Revisions that did not
+    /// touch the pointer are left out, so this may be empty.
+    Revisions(Vec<Revision>),
+    /// The missed revisions are no longer retained: start over from a dump.
+    Dump(Dump),
+}
+
+/// A patch produced by [`diff`]. Every `move` and `copy` in it is preceded by a
+/// `test` of its source, so that it can be scoped to a pointer that only
+/// covers its destination.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct DiffPatch(pub(crate) Patch);
 impl DiffPatch {
@@ -49,57 +66,115 @@ impl DiffPatch {
     // safe to assume dictionary style symantics for arrays since patches will always be rebased before being applied
     pub fn for_path<S: AsRef<str>, V: SegList>(&self, ptr: &JsonPointer<S, V>) -> DiffPatch {
         let DiffPatch(Patch(ops)) = self;
-        DiffPatch(Patch(
-            ops.iter()
-                .filter_map(|op| match op {
-                    PatchOperation::Add(op) => {
-                        if let Some(tail) = op.path.strip_prefix(ptr) {
-                            Some(PatchOperation::Add(AddOperation {
-                                path: tail.to_owned(),
-                                value: op.value.clone(),
-                            }))
-                        } else if let Some(tail) = ptr.strip_prefix(&op.path) {
-                            Some(PatchOperation::Add(AddOperation {
-                                path: Default::default(),
-                                value: tail.get(&op.value).cloned().unwrap_or_default(),
-                            }))
-                        } else {
-                            None
-                        }
+        let mut res = Vec::new();
+        // the value a move or copy takes, from the test before it
+        let mut source: Option<&Value> = None;
+        for op in ops {
+            let scoped = match op {
+                PatchOperation::Add(op) => {
+                    if let Some(tail) = op.path.strip_prefix(ptr) {
+                        Some(PatchOperation::Add(AddOperation {
+                            path: tail.to_owned(),
+                            value: op.value.clone(),
+                        }))
+                    } else if let Some(tail) = ptr.strip_prefix(&op.path) {
+                        Some(PatchOperation::Add(AddOperation {
+                            path: Default::default(),
+                            value: tail.get(&op.value).cloned().unwrap_or_default(),
+                        }))
+                    } else {
+                        None
                     }
-                    PatchOperation::Replace(op) => {
-                        if let Some(tail) = op.path.strip_prefix(ptr) {
-                            Some(PatchOperation::Replace(ReplaceOperation {
-                                path: tail.to_owned(),
-                                value: op.value.clone(),
-                            }))
-                        } else if let Some(tail) = ptr.strip_prefix(&op.path) {
-                            Some(PatchOperation::Replace(ReplaceOperation {
-                                path: Default::default(),
-                                value: tail.get(&op.value).cloned().unwrap_or_default(),
-                            }))
-                        } else {
-                            None
-                        }
+                }
+                PatchOperation::Replace(op) => {
+                    if let Some(tail) = op.path.strip_prefix(ptr) {
+                        Some(PatchOperation::Replace(ReplaceOperation {
+                            path: tail.to_owned(),
+                            value: op.value.clone(),
+                        }))
+                    } else if let Some(tail) = ptr.strip_prefix(&op.path) {
+                        Some(PatchOperation::Replace(ReplaceOperation {
+                            path: Default::default(),
+                            value: tail.get(&op.value).cloned().unwrap_or_default(),
+                        }))
+                    } else {
+                        None
+                    }
+                }
+                PatchOperation::Remove(op) => {
+                    if ptr.starts_with(&op.path) {
+                        Some(PatchOperation::Replace(ReplaceOperation {
+                            path: Default::default(),
+                            value: Default::default(),
+                        }))
+                    } else if let Some(tail) = op.path.strip_prefix(ptr) {
+                        Some(PatchOperation::Remove(RemoveOperation {
+                            path: tail.to_owned(),
+                        }))
+                    } else {
+                        None
                     }
-                    PatchOperation::Remove(op) => {
-                        if ptr.starts_with(&op.path) {
-                            Some(PatchOperation::Replace(ReplaceOperation {
-                                path: Default::default(),
-                                value: Default::default(),
-                            }))
-                        } else if let Some(tail) = op.path.strip_prefix(ptr) {
-                            Some(PatchOperation::Remove(RemoveOperation {
-                                path: tail.to_owned(),
-                            }))
-                        } else {
-                            None
+                }
+                PatchOperation::Move(op) => {
+                    let source: Value = source.take().cloned().unwrap_or_default();
+                    if let Some(tail) = ptr.strip_prefix(&op.path) {
+                        Some(PatchOperation::Add(AddOperation {
+                            path: Default::default(),
+                            value: tail.get(&source).cloned().unwrap_or_default(),
+                        }))
+                    } else if ptr.starts_with(&op.from) {
+                        Some(PatchOperation::Replace(ReplaceOperation {
+                            path: Default::default(),
+                            value: Default::default(),
+                        }))
+                    } else {
+                        match (op.from.strip_prefix(ptr), op.path.strip_prefix(ptr)) {
+                            (Some(from), Some(path)) => Some(PatchOperation::Move(MoveOperation {
+                                from: from.to_owned(),
+                                path: path.to_owned(),
+                            })),
+                            (Some(from), None) => Some(PatchOperation::Remove(RemoveOperation {
+                                path: from.to_owned(),
+                            })),
+                            (None, Some(path)) => Some(PatchOperation::Add(AddOperation {
+                                path: path.to_owned(),
+                                value: source,
+                            })),
+                            (None, None) => None,
                         }
                     }
-                    _ => unreachable!(),
-                })
-                .collect(),
-        ))
+                }
+                PatchOperation::Copy(op) => {
+                    let source: Value = source.take().cloned().unwrap_or_default();
+                    if let Some(tail) = ptr.strip_prefix(&op.path) {
+                        Some(PatchOperation::Add(AddOperation {
+                            path: Default::default(),
+                            value: tail.get(&source).cloned().unwrap_or_default(),
+                        }))
+                    } else if let Some(path) = op.path.strip_prefix(ptr) {
+                        Some(match op.from.strip_prefix(ptr) {
+                            Some(from) => PatchOperation::Copy(CopyOperation {
+                                from: from.to_owned(),
+                                path: path.to_owned(),
+                            }),
+                            None => PatchOperation::Add(AddOperation {
+                                path: path.to_owned(),
+                                value: source,
+                            }),
+                        })
+                    } else {
+                        None
+                    }
+                }
+                // the revision is already applied, so its tests held
+                PatchOperation::Test(op) => {
+                    source = Some(&op.value);
+                    None
+                }
+            };
+            res.extend(scoped);
+        }
+        DiffPatch(Patch(res))
     }
 
     pub fn rebase(&mut self, onto: &DiffPatch) {
@@ -115,24 +190,20 @@ impl DiffPatch {
                 {
                     let prefix = onto_op.path.slice(..arr_path_idx).unwrap_or_default();
                     for op in ops.iter_mut() {
-                        let path = match op {
-                            PatchOperation::Add(op) => &mut op.path,
-                            PatchOperation::Replace(op) => &mut op.path,
-                            PatchOperation::Remove(op) => &mut op.path,
-                            _ => unreachable!(),
-                        };
-                        if path.starts_with(&prefix) {
-                            if let Some(idx) = path
-                                .get_segment(arr_path_idx)
-                                .and_then(|seg| seg.parse::<usize>().ok())
-                            {
-                                if idx >= onto_idx {
-                                    let mut new_path = prefix.clone().to_owned();
-                                    new_path.push_end_idx(idx + 1);
-                                    if let Some(tail) = path.slice(arr_path_idx + 1..) {
-                                        new_path.append(&tail);
+                        for path in paths_mut(op) {
+                            if path.starts_with(&prefix) {
+                                if let Some(idx) = path
+                                    .get_segment(arr_path_idx)
+                                    .and_then(|seg| seg.parse::<usize>().ok())
+                                {
+                                    if idx >= onto_idx {
+                                        let mut new_path = prefix.clone().to_owned();
+                                        new_path.push_end_idx(idx + 1);
+                                        if let Some(tail) = path.slice(arr_path_idx + 1..) {
+                                            new_path.append(&tail);
+                                        }
+                                        *path = new_path;
                                     }
-                                    *path = new_path;
                                 }
                             }
                         }
@@ -147,40 +218,34 @@ impl DiffPatch {
                 {
                     let prefix = onto_op.path.slice(..arr_path_idx).unwrap_or_default();
                     for op in ops.iter_mut() {
-                        let path = match op {
-                            PatchOperation::Add(op) => &mut op.path,
-                            PatchOperation::Replace(op) => &mut op.path,
-                            PatchOperation::Remove(op) => &mut op.path,
-                            _ => unreachable!(),
-                        };
-                        if path.starts_with(&prefix) {
-                            if let Some(idx) = path
-                                .get_segment(arr_path_idx)
-                                .and_then(|seg| seg.parse::<usize>().ok())
-                            {
-                                if idx > onto_idx {
-                                    let mut new_path = prefix.clone().to_owned();
-                                    new_path.push_end_idx(idx - 1);
-                                    if let Some(tail) = path.slice(arr_path_idx + 1..) {
-                                        new_path.append(&tail);
-                                    }
-                                    *path = new_path;
-                                } else if idx == onto_idx {
-                                    let new_op = match &*op {
-                                        PatchOperation::Replace(r) => {
-                                            Some(PatchOperation::Add(AddOperation {
-                                                path: r.path.clone(),
-                                                value: r.value.clone(),
-                                            }))
+                        let mut removed = false;
+                        for path in paths_mut(op) {
+                            if path.starts_with(&prefix) {
+                                if let Some(idx) = path
+                                    .get_segment(arr_path_idx)
+                                    .and_then(|seg| seg.parse::<usize>().ok())
+                                {
+                                    if idx > onto_idx {
+                                        let mut new_path = prefix.clone().to_owned();
+                                        new_path.push_end_idx(idx - 1);
+                                        if let Some(tail) = path.slice(arr_path_idx + 1..) {
+                                            new_path.append(&tail);
                                         }
-                                        _ => None,
-                                    };
-                                    if let Some(new_op) = new_op {
-                                        *op = new_op;
+                                        *path = new_path;
+                                    } else if idx == onto_idx {
+                                        removed = true;
                                     }
                                 }
                             }
                         }
+                        if removed {
+                            if let PatchOperation::Replace(r) = &*op {
+                                *op = PatchOperation::Add(AddOperation {
+                                    path: r.path.clone(),
+                                    value: r.value.clone(),
+                                });
+                            }
+                        }
                     }
                 }
             }
@@ -206,7 +271,9 @@ impl DiffPatch {
                         res = Some(false)
                     }
                 }
-                _ => unreachable!(),
+                // diff never moves or copies to the root, and a scoped patch
+                // turns those that replace it into adds
+                PatchOperation::Move(_) | PatchOperation::Copy(_) | PatchOperation::Test(_) => (),
             }
         }
         res
@@ -226,7 +293,20 @@ impl DiffPatch {
                         keys.remove(a.path.get_segment(0).unwrap());
                     }
                 }
-                _ => unreachable!(),
+                PatchOperation::Move(a) => {
+                    if a.from.len() == 1 {
+                        keys.remove(a.from.get_segment(0).unwrap());
+                    }
+                    if a.path.len() == 1 {
+                        keys.insert(a.path.get_segment(0).unwrap().to_owned());
+                    }
+                }
+                PatchOperation::Copy(a) => {
+                    if a.path.len() == 1 {
+                        keys.insert(a.path.get_segment(0).unwrap().to_owned());
+                    }
+                }
+                PatchOperation::Test(_) => (),
             }
         }
         keys
@@ -248,6 +328,40 @@ impl Deref for DiffPatch {
     }
 }
 
+fn paths_mut(op: &mut PatchOperation) -> Vec<&mut JsonPointer<String>> {
+    match op {
+        PatchOperation::Add(op) => vec![&mut op.path],
+        PatchOperation::Replace(op) => vec![&mut op.path],
+        PatchOperation::Remove(op) => vec![&mut op.path],
+        PatchOperation::Move(op) => vec![&mut op.from, &mut op.path],
+        PatchOperation::Copy(op) => vec![&mut op.from, &mut op.path],
+        PatchOperation::Test(op) => vec![&mut op.path],
+    }
+}
+
 pub fn diff(left: &Value, right: &Value) -> DiffPatch {
-    DiffPatch(json_patch::diff(left, right))
+    let Patch(ops) = json_patch::diff(left, right);
+    if !ops
+        .iter()
+        .any(|op| matches!(op, PatchOperation::Move(_) | PatchOperation::Copy(_)))
+    {
+        return DiffPatch(Patch(ops));
+    }
+    // replay the patch to find what each move or copy takes along
+    let mut state = left.clone();
+    let mut res = Vec::with_capacity(ops.len());
+    for op in ops {
+        if let PatchOperation::Move(MoveOperation { from, .. })
+        | PatchOperation::Copy(CopyOperation { from, .. }) = &op
+        {
+            res.push(PatchOperation::Test(TestOperation {
+                path: from.clone(),
+                value: from.get(&state).cloned().unwrap_or_default(),
+            }));
+        }
+        let op = Patch(vec![op]);
+        json_patch::patch_unsafe(&mut state, &op).expect("diff produces a valid patch");
+        res.extend(op.0);
+    }
+    DiffPatch(Patch(res))
 }
@@ -0,0 +1,164 @@
+use imbl_value::Value;
+use json_ptr::JsonPointer;
+use jsonpath_lib::rfc9535::{JsonPath, PathElement};
+
+use crate::Error;
+
+/// The nodes a [`Query`] matched, with their locations, in document order.
+pub type Selection = Vec<(JsonPointer, Value)>;
+
+/// A compiled [RFC 9535](https://www.rfc-editor.org/rfc/rfc9535) JSONPath
+/// expression, as used by [`PatchDb::watch_query`](crate::PatchDb::watch_query).
+#[derive(Clone, Debug)]
+pub struct Query {
+    path: JsonPath,
+    /// The longest run of plain keys the expression starts with. Nothing
+    /// outside of it can change what the expression matches.
+    prefix: JsonPointer,
+}
+impl Query {
+    pub fn compile(expr: &str) -> Result<Self, Error> {
+        Ok(Query {
+            path: JsonPath::compile(expr).map_err(|e| Error::JsonPath(e.to_string()))?,
+            prefix: prefix(expr),
+        })
+    }
+    pub fn prefix(&self) -> &JsonPointer {
+        &self.prefix
+    }
+    pub fn select(&self, value: &Value) -> Selection {
+        self.path
+            .select_located(value)
+            .into_iter()
+            .map(|(path, value)| {
+                let mut ptr = JsonPointer::default();
+                for element in path.elements() {
+                    match element {
+                        PathElement::Name(name) => ptr.push_end(name),
+                        PathElement::Index(idx) => ptr.push_end_idx(*idx),
+                    }
+                }
+                (ptr, value.clone())
+            })
+            .collect()
+    }
+}
+
+/// The plain keys `expr` starts with, e.g. `/a/b` for `$.a['b'][*].c`. Stops
+/// at the first wildcard, recursive descent, filter, slice or union, and is
+/// empty if a filter refers back to the root.
+fn prefix(expr: &str) -> JsonPointer {
+    let mut res = JsonPointer::default();
+    let mut rest = match expr.trim().strip_prefix('$') {
+        Some(rest) if !rest.contains('$') => rest,
+        _ => return res,
+    };
+    fn is_key(c: char) -> bool {
+        c.is_alphanumeric() || c == '_' || c == '-'
+    }
+    loop {
+        if let Some(tail) = rest.strip_prefix('.') {
+            let end = tail.find(|c| !is_key(c)).unwrap_or(tail.len());
+            let (key, tail) = tail.split_at(end);
+            if key.is_empty() || !(tail.is_empty() || tail.starts_with(['.', '['])) {
+                break;
+            }
+            res.push_end(key);
+            rest = tail;
+        } else if let Some(tail) = rest.strip_prefix('[') {
+            let quote = match tail.chars().next() {
+                Some(q @ ('\'' | '"')) => q,
+                _ => break,
+            };
+            let tail = &tail[1..];
+            let end = match tail.find([quote, '\\']) {
+                Some(end) => end,
+                None => break,
+            };
+            let (key, tail) = tail.split_at(end);
+            let tail = match tail.strip_prefix(quote).and_then(|t| t.strip_prefix(']')) {
+                Some(tail) => tail,
+                None => break,
+            };
+            res.push_end(key);
+            rest = tail;
+        } else {
+            break;
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use imbl_value::json;
+
+    use super::*;
+
+    #[test]
+    fn prefixes() {
+        for (expr, expected) in [
+            ("$", ""),
+            ("$.a.b", "/a/b"),
+            ("$.a['b c'].d", "/a/b c/d"),
+            ("$.packageData.*.status", "/packageData"),
+            ("$.a..b", "/a"),
+            ("$.a[0]", "/a"),
+            ("$.a[?(@.b == $.c)]", ""),
+            ("$.a['b', 'c']", "/a"),
+            ("$.a.b*", "/a"),
+        ] {
+            assert_eq!(prefix(expr).to_string(), expected, "{}", expr);
+        }
+    }
+
+    #[test]
+    fn selects_locations() {
+        let value = json!({
+            "packageData": {
+                "a": { "status": "running", "icon": "..." },
+                "b": { "status": "stopped", "icon": "..." },
+                "c": { "icon": "..." },
+            },
+            "list": [{ "a": 1 }, { "a": 2 }, { "a": 3 }],
+        });
+        let selection = Query::compile("$.packageData.*.status")
+            .unwrap()
+            .select(&value);
+        assert_eq!(
+            selection
+                .iter()
+                .map(|(ptr, v)| (ptr.to_string(), v.clone()))
+                .collect::<Vec<_>>(),
+            [
+                ("/packageData/a/status".to_owned(), json!("running")),
+                ("/packageData/b/status".to_owned(), json!("stopped")),
+            ]
+        );
+        let selection = Query::compile("$.list[?(@.a > 1)]").unwrap().select(&value);
+        assert_eq!(
+            selection
+                .iter()
+                .map(|(ptr, _)| ptr.to_string())
+                .collect::<Vec<_>>(),
+            ["/list/1", "/list/2"]
+        );
+    }
+
+    #[test]
+    fn selects_shared_nodes() {
+        // a cloned vector shares its chunks, so both elements hold the very
+        // same nodes
+        let list = Value::Array((0..100).map(Value::from).collect());
+        let value = Value::Array(vec![list.clone(), list].into_iter().collect());
+        assert!(std::ptr::eq(&value[0][5], &value[1][5]));
+        let selection = Query::compile("$[*][5]").unwrap().select(&value);
+        assert_eq!(
+            selection
+                .iter()
+                .map(|(ptr, _)| ptr.to_string())
+                .collect::<Vec<_>>(),
+            ["/0/5", "/1/5"]
+        );
+    }
+}
@@ -0,0 +1,361 @@
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::{SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use fd_lock_rs::FdLock;
+use imbl_value::Value;
+use json_patch::{AddOperation, PatchOperation, RemoveOperation};
+use json_ptr::JsonPointer;
+use lazy_static::lazy_static;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+use crate::history::{patch_overlaps, redact_patch};
+use crate::patch::DiffPatch;
+use crate::Error;
+
+lazy_static! {
+    static ref OPEN_STORES: std::sync::Mutex<HashMap<PathBuf, Arc<Mutex<()>>>> =
+        std::sync::Mutex::new(HashMap::new());
+}
+
+/// Where a [`Store`](crate::Store) persists its state.
+///
+/// A backend holds a snapshot of the root value as of some revision, followed
+/// by every patch applied since. The store keeps the current value in memory
+/// and only calls into the backend on open and on write, so a backend is free
+/// to transform what it is handed, e.g. to encrypt it at rest.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// The persisted revision and root value. Called once, when the store is
+    /// opened.
+    async fn load(&mut self) -> Result<(u64, Value), Error>;
+    /// Persist `patch`, which brings the state to `revision`. On error,
+    /// nothing may have been persisted: the store rolls the patch back.
+    async fn append(&mut self, revision: u64, patch: &DiffPatch) -> Result<(), Error>;
+    /// Replace everything persisted with a snapshot of `value` at `revision`.
+    ///
+    /// Sets `*committed = true` once the snapshot is guaranteed to be what
+    /// [`Storage::load`] recovers. An error before that point leaves the old
+    /// state intact and the store rolls back; after it, the store keeps the
+    /// new state and propagates the error.
+    async fn compact(
+        &mut self,
+        revision: u64,
+        value: &Value,
+        committed: &mut bool,
+    ) -> Result<(), Error>;
+    /// Flush anything buffered. Called by [`PatchDb::close`](crate::PatchDb::close).
+    async fn close(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// The default backend: a single CBOR file holding the revision, the root
+/// value, and every patch since, locked against other processes and against
+/// being opened twice in this one.
+pub struct FileStorage {
+    path: PathBuf,
+    file: FdLock<File>,
+    file_cursor: u64,
+    _lock: OwnedMutexGuard<()>,
+}
+impl FileStorage {
+    pub async fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let (_lock, path) = {
+            if !path.as_ref().exists() {
+                tokio::fs::File::create(path.as_ref()).await?;
+            }
+            let path = tokio::fs::canonicalize(path).await?;
+            let mut lock = OPEN_STORES.lock().unwrap();
+            (
+                if let Some(open) = lock.get(&path) {
+                    open.clone().try_lock_owned()?
+                } else {
+                    let tex = Arc::new(Mutex::new(()));
+                    lock.insert(path.clone(), tex.clone());
+                    tex.try_lock_owned()?
+                },
+                path,
+            )
+        };
+        tokio::task::spawn_blocking(move || {
+            let bak = path.with_extension("bak");
+            if bak.exists() {
+                std::fs::rename(&bak, &path)?;
+            }
+            let file = FdLock::lock(
+                OpenOptions::new()
+                    .create(true)
+                    .read(true)
+                    .write(true)
+                    .truncate(false)
+                    .open(&path)?,
+                fd_lock_rs::LockType::Exclusive,
+                false,
+            )?;
+            Ok::<_, Error>(FileStorage {
+                path,
+                file: file.map(File::from_std),
+                file_cursor: 0,
+                _lock,
+            })
+        })
+        .await?
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+#[async_trait]
+impl Storage for FileStorage {
+    async fn load(&mut self) -> Result<(u64, Value), Error> {
+        let mut buf = Vec::new();
+        self.file.seek(SeekFrom::Start(0)).await?;
+        self.file.read_to_end(&mut buf).await?;
+        let failed = self.path.with_extension("failed");
+        let (revision, persistent, cursor) = tokio::task::spawn_blocking(move || {
+            let mut stream = serde_cbor::Deserializer::from_slice(&buf).into_iter::<u64>();
+            let mut revision: u64 = stream.next().transpose()?.unwrap_or(0);
+            let mut stream = stream.change_output_type();
+            let mut persistent: Value = stream.next().transpose()?.unwrap_or(Value::Null);
+            let mut stream = stream.change_output_type();
+            // a torn write at the end is dropped: it was never acknowledged
+            while let Some(Ok(patch)) = stream.next() {
+                if let Err(_) = json_patch::patch(&mut persistent, &patch) {
+                    #[cfg(feature = "tracing")]
+                    tracing::error!("Error applying patch, skipping...");
+                    writeln!(
+                        OpenOptions::new().create(true).append(true).open(&failed)?,
+                        "{}",
+                        imbl_value::to_value(&patch).map_err(Error::JSON)?,
+                    )?;
+                }
+                revision += 1;
+            }
+            Ok::<_, Error>((revision, persistent, stream.byte_offset() as u64))
+        })
+        .await??;
+        self.file_cursor = cursor;
+        Ok((revision, persistent))
+    }
+    async fn append(&mut self, _revision: u64, patch: &DiffPatch) -> Result<(), Error> {
+        let patch_bin = serde_cbor::to_vec(&**patch)?;
+        if self.file.stream_position().await? != self.file_cursor {
+            self.file.set_len(self.file_cursor).await?;
+            self.file.seek(SeekFrom::Start(self.file_cursor)).await?;
+        }
+        self.file.write_all(&patch_bin).await?;
+        self.file.flush().await?;
+        self.file.sync_all().await?;
+        self.file_cursor += patch_bin.len() as u64;
+        Ok(())
+    }
+    /// Writes the snapshot to a `.bak` file first. `*committed` is set once
+    /// that file is renamed into place, since [`FileStorage::open`] renames it
+    /// over the main file if it finds one.
+    async fn compact(
+        &mut self,
+        revision: u64,
+        value: &Value,
+        committed: &mut bool,
+    ) -> Result<(), Error> {
+        let bak = self.path.with_extension("bak");
+        let bak_tmp = bak.with_extension("bak.tmp");
+        let revision_cbor = serde_cbor::to_vec(&revision)?;
+        let data_cbor = serde_cbor::to_vec(value)?;
+
+        // Phase 1: Create atomic backup. If this fails, the main file is
+        // untouched and the caller can safely undo the in-memory patch.
+        let mut backup_file = File::create(&bak_tmp).await?;
+        backup_file.write_all(&revision_cbor).await?;
+        backup_file.write_all(&data_cbor).await?;
+        backup_file.flush().await?;
+        backup_file.sync_all().await?;
+        tokio::fs::rename(&bak_tmp, &bak).await?;
+        *committed = true;
+
+        // Point of no return: the backup exists with the new state. On restart,
+        // FileStorage::open will rename it over the main file. From here, errors
+        // must NOT cause an in-memory undo.
+
+        // Phase 2: Rewrite main file. If this fails, the backup ensures crash
+        // recovery. We propagate the error but signal that undo is unsafe.
+        self.file.set_len(0).await?;
+        self.file.seek(SeekFrom::Start(0)).await?;
+        self.file.write_all(&revision_cbor).await?;
+        self.file.write_all(&data_cbor).await?;
+        self.file.flush().await?;
+        self.file.sync_all().await?;
+        self.file_cursor = self.file.stream_position().await?;
+
+        // Phase 3: Remove backup. Non-fatal — on restart, the backup (which
+        // matches the main file) will be harmlessly applied.
+        let _ = tokio::fs::remove_file(&bak).await;
+
+        Ok(())
+    }
+    async fn close(&mut self) -> Result<(), Error> {
+        self.file.flush().await?;
+        self.file.shutdown().await?;
+        Ok(())
+    }
+}
+impl Drop for FileStorage {
+    fn drop(&mut self) {
+        if let Ok(mut lock) = OPEN_STORES.lock() {
+            lock.remove(&self.path);
+        }
+    }
+}
+
+/// A backend that persists nothing, for tests and scratch databases. It only
+/// hands back the state it was created with.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    initial: Option<(u64, Value)>,
+}
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Start from `value` at `revision` instead of `null` at 0.
+    pub fn with_value(revision: u64, value: Value) -> Self {
+        Self {
+            initial: Some((revision, value)),
+        }
+    }
+}
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn load(&mut self) -> Result<(u64, Value), Error> {
+        Ok(self.initial.take().unwrap_or((0, Value::Null)))
+    }
+    async fn append(&mut self, _revision: u64, _patch: &DiffPatch) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn compact(
+        &mut self,
+        _revision: u64,
+        _value: &Value,
+        committed: &mut bool,
+    ) -> Result<(), Error> {
+        *committed = true;
+        Ok(())
+    }
+}
+
+/// Seals the subtree an [`EncryptedStorage`] protects.
+pub trait Cipher: Send + Sync {
+    /// Encrypt `plaintext`, e.g. to base64 of a nonce and AEAD ciphertext.
+    fn encrypt(&self, plaintext: &[u8]) -> Result<String, Error>;
+    /// Decrypt what [`Cipher::encrypt`] returned.
+    fn decrypt(&self, ciphertext: &str) -> Result<Vec<u8>, Error>;
+}
+
+/// Wraps another backend so that the subtree at one pointer, e.g. `/private`,
+/// only ever reaches it encrypted: the inner backend stores a string holding
+/// the [`Cipher`]'s encryption of the subtree's CBOR. Patches that touch the
+/// subtree are rewritten to replace that string as a whole, so none of its
+/// keys or values are written in the clear.
+///
+/// A database written before the subtree was encrypted still loads: a subtree
+/// that is not a string is taken as plaintext, and encrypted by the compaction
+/// that follows opening. The subtree itself therefore can't be a string.
+pub struct EncryptedStorage<S, C> {
+    inner: S,
+    cipher: C,
+    ptr: JsonPointer,
+    /// The plaintext state, to encrypt the subtree from after each patch.
+    state: Value,
+}
+impl<S: Storage, C: Cipher> EncryptedStorage<S, C> {
+    pub fn new(inner: S, cipher: C, ptr: JsonPointer) -> Self {
+        Self {
+            inner,
+            cipher,
+            ptr,
+            state: Value::Null,
+        }
+    }
+    fn encrypt(&self, subtree: &Value) -> Result<Value, Error> {
+        Ok(Value::String(
+            self.cipher.encrypt(&serde_cbor::to_vec(subtree)?)?.into(),
+        ))
+    }
+    /// `value` with the subtree encrypted.
+    fn seal(&self, value: &Value) -> Result<Value, Error> {
+        let mut res = value.clone();
+        if let Some(subtree) = self.ptr.get(value) {
+            let sealed = self.encrypt(subtree)?;
+            self.ptr.set(&mut res, sealed, false)?;
+        }
+        Ok(res)
+    }
+    /// `patch` as it applies to the stored state, given that it takes the
+    /// plaintext state from `self.state` to `state`.
+    fn seal_patch(&self, patch: &DiffPatch, state: &Value) -> Result<DiffPatch, Error> {
+        let exclude = std::slice::from_ref(&self.ptr);
+        if !patch_overlaps(patch, exclude) {
+            return Ok(patch.clone());
+        }
+        let mut res = redact_patch(patch, exclude);
+        // whether the subtree survives the rest of the patch as stored
+        let mut stored = self.state.clone();
+        json_patch::patch(&mut stored, &res)?;
+        let op = match self.ptr.get(state) {
+            Some(subtree) => Some(PatchOperation::Add(AddOperation {
+                path: self.ptr.clone(),
+                value: self.encrypt(subtree)?,
+            })),
+            None if self.ptr.get(&stored).is_some() => {
+                Some(PatchOperation::Remove(RemoveOperation {
+                    path: self.ptr.clone(),
+                }))
+            }
+            None => None,
+        };
+        (res.0).0.extend(op);
+        Ok(res)
+    }
+}
+#[async_trait]
+impl<S: Storage, C: Cipher> Storage for EncryptedStorage<S, C> {
+    async fn load(&mut self) -> Result<(u64, Value), Error> {
+        let (revision, mut value) = self.inner.load().await?;
+        if let Some(Value::String(sealed)) = self.ptr.get(&value) {
+            let subtree: Value = serde_cbor::from_slice(&self.cipher.decrypt(sealed)?)?;
+            self.ptr.set(&mut value, subtree, false)?;
+        }
+        self.state = value.clone();
+        Ok((revision, value))
+    }
+    async fn append(&mut self, revision: u64, patch: &DiffPatch) -> Result<(), Error> {
+        let mut state = self.state.clone();
+        json_patch::patch(&mut state, patch)?;
+        let sealed = self.seal_patch(patch, &state)?;
+        self.inner.append(revision, &sealed).await?;
+        self.state = state;
+        Ok(())
+    }
+    async fn compact(
+        &mut self,
+        revision: u64,
+        value: &Value,
+        committed: &mut bool,
+    ) -> Result<(), Error> {
+        let sealed = self.seal(value)?;
+        let res = self.inner.compact(revision, &sealed, committed).await;
+        if *committed {
+            self.state = value.clone();
+        }
+        res
+    }
+    async fn close(&mut self) -> Result<(), Error> {
+        self.inner.close().await
+    }
+}
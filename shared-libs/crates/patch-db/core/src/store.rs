@@ -1,122 +1,63 @@
-use std::collections::{BTreeSet, HashMap};
-use std::fs::OpenOptions;
-use std::io::{SeekFrom, Write};
+use std::collections::{BTreeSet, VecDeque};
 use std::marker::PhantomData;
+use std::ops::RangeBounds;
 use std::panic::UnwindSafe;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use fd_lock_rs::FdLock;
 use futures::{Future, FutureExt};
 use imbl::Vector;
 use imbl_value::{InOMap, InternedString, Value};
 use json_patch::PatchError;
 use json_ptr::{JsonPointer, SegList, ROOT};
-use lazy_static::lazy_static;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use tokio::fs::File;
-use tokio::io::AsyncSeekExt;
-use tokio::sync::{Mutex, OwnedMutexGuard, RwLock};
+use tokio::sync::RwLock;
 
-use crate::patch::{diff, DiffPatch, Dump, Revision};
+use crate::history::{HistoryLog, HistoryOptions};
+use crate::patch::{diff, Catchup, DiffPatch, Dump, Revision};
+use crate::query::Query;
+use crate::storage::{FileStorage, MemoryStorage, Storage};
 use crate::subscriber::Broadcast;
-use crate::{DbWatch, Error, HasModel, Subscriber};
+use crate::{history, DbWatch, Error, HasModel, HistoryEntry, QueryWatch, Subscriber};
 
-lazy_static! {
-    static ref OPEN_STORES: std::sync::Mutex<HashMap<PathBuf, Arc<Mutex<()>>>> =
-        std::sync::Mutex::new(HashMap::new());
-}
+/// How many recent revisions a [`Store`] keeps in memory for
+/// [`PatchDb::subscribe_from`].
+pub(crate) const HISTORY_LEN: usize = 1024;
 
 pub struct Store {
-    path: PathBuf,
-    file: FdLock<File>,
-    file_cursor: u64,
-    _lock: OwnedMutexGuard<()>,
+    storage: Box<dyn Storage>,
     persistent: Value,
     revision: u64,
+    /// The last [`HISTORY_LEN`] revisions, oldest first. Starts empty: the
+    /// backend is compacted on open.
+    history: VecDeque<Arc<Revision>>,
+    history_log: Option<HistoryLog>,
     broadcast: Broadcast,
 }
 impl Store {
-    pub(crate) async fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
-        let (_lock, path) = {
-            if !path.as_ref().exists() {
-                tokio::fs::File::create(path.as_ref()).await?;
-            }
-            let path = tokio::fs::canonicalize(path).await?;
-            let mut lock = OPEN_STORES.lock().unwrap();
-            (
-                if let Some(open) = lock.get(&path) {
-                    open.clone().try_lock_owned()?
-                } else {
-                    let tex = Arc::new(Mutex::new(()));
-                    lock.insert(path.clone(), tex.clone());
-                    tex.try_lock_owned()?
-                },
-                path,
-            )
-        };
-        let mut res = tokio::task::spawn_blocking(move || {
-            use std::io::Seek;
-            let bak = path.with_extension("bak");
-            if bak.exists() {
-                std::fs::rename(&bak, &path)?;
-            }
-            let mut f = FdLock::lock(
-                OpenOptions::new()
-                    .create(true)
-                    .read(true)
-                    .write(true)
-                    .truncate(false)
-                    .open(&path)?,
-                fd_lock_rs::LockType::Exclusive,
-                false,
-            )?;
-            let mut stream =
-                serde_cbor::StreamDeserializer::new(serde_cbor::de::IoRead::new(&mut *f));
-            let mut revision: u64 = stream.next().transpose()?.unwrap_or(0);
-            let mut stream = stream.change_output_type();
-            let mut persistent: Value = stream.next().transpose()?.unwrap_or(Value::Null);
-            let mut stream = stream.change_output_type();
-            while let Some(Ok(patch)) = stream.next() {
-                if let Err(_) = json_patch::patch(&mut persistent, &patch) {
-                    #[cfg(feature = "tracing")]
-                    tracing::error!("Error applying patch, skipping...");
-                    writeln!(
-                        OpenOptions::new()
-                            .create(true)
-                            .append(true)
-                            .open(path.with_extension("failed"))?,
-                        "{}",
-                        imbl_value::to_value(&patch).map_err(Error::JSON)?,
-                    )?;
-                }
-                revision += 1;
-            }
-            let file_cursor = f.stream_position()?;
-
-            Ok::<_, Error>(Store {
-                path,
-                file: f.map(File::from_std),
-                file_cursor,
-                _lock,
-                persistent,
-                revision,
-                broadcast: Broadcast::new(),
-            })
-        })
-        .await??;
+    pub(crate) fn new(storage: Box<dyn Storage>, revision: u64, persistent: Value) -> Self {
+        Store {
+            storage,
+            persistent,
+            revision,
+            history: VecDeque::new(),
+            history_log: None,
+            broadcast: Broadcast::new(),
+        }
+    }
+    pub(crate) async fn open(mut storage: Box<dyn Storage>) -> Result<Self, Error> {
+        let (revision, persistent) = storage.load().await?;
+        let mut res = Store::new(storage, revision, persistent);
         let mut _committed = false;
         res.compress(&mut _committed).await?;
         Ok(res)
     }
     pub async fn close(mut self) -> Result<(), Error> {
-        use tokio::io::AsyncWriteExt;
-
-        self.file.flush().await?;
-        self.file.shutdown().await?;
-
-        Ok(())
+        if let Some(log) = self.history_log.take() {
+            log.flush().await;
+        }
+        self.storage.close().await
     }
     pub(crate) fn exists<S: AsRef<str>, V: SegList>(&self, ptr: &JsonPointer<S, V>) -> bool {
         ptr.get(&self.persistent).unwrap_or(&Value::Null) != &Value::Null
@@ -148,9 +89,43 @@ impl Store {
     pub(crate) fn sequence(&self) -> u64 {
         self.revision
     }
+    /// Fails with [`Error::Conflict`] if `expected` is given and is not the
+    /// current revision.
+    pub(crate) fn check_revision(&self, expected: Option<u64>) -> Result<(), Error> {
+        match expected {
+            Some(expected) if expected != self.revision => Err(Error::Conflict {
+                expected,
+                actual: self.revision,
+            }),
+            _ => Ok(()),
+        }
+    }
     pub(crate) fn subscribe(&mut self, ptr: JsonPointer) -> Subscriber {
         self.broadcast.subscribe(ptr)
     }
+    pub(crate) fn catchup<S: AsRef<str>, V: SegList>(
+        &self,
+        ptr: &JsonPointer<S, V>,
+        since: u64,
+    ) -> Catchup {
+        // a revision from the future means the store was replaced since
+        let retained = since <= self.revision
+            && self
+                .history
+                .front()
+                .map_or(since == self.revision, |oldest| oldest.id <= since + 1);
+        if !retained {
+            return Catchup::Dump(self.dump(ptr));
+        }
+        Catchup::Revisions(
+            self.history
+                .iter()
+                .filter(|rev| rev.id > since)
+                .map(|rev| rev.for_path(ptr))
+                .filter(|rev| !rev.patch.is_empty())
+                .collect(),
+        )
+    }
     pub(crate) async fn put_value<S: AsRef<str>, V: SegList>(
         &mut self,
         ptr: &JsonPointer<S, V>,
@@ -170,51 +145,14 @@ impl Store {
     ) -> Result<Option<Arc<Revision>>, Error> {
         self.put_value(ptr, &imbl_value::to_value(&value)?).await
     }
-    /// Compresses the database file by writing a fresh snapshot.
-    ///
-    /// Sets `*committed = true` once the atomic backup rename succeeds (point
-    /// of no return — the new state will be recovered on restart regardless of
-    /// main file state).  Before that point, the caller can safely undo.
+    /// Compresses the backend by writing a fresh snapshot. See
+    /// [`Storage::compact`] for the meaning of `committed`.
     pub(crate) async fn compress(&mut self, committed: &mut bool) -> Result<(), Error> {
-        use tokio::io::AsyncWriteExt;
-        let bak = self.path.with_extension("bak");
-        let bak_tmp = bak.with_extension("bak.tmp");
-        let revision_cbor = serde_cbor::to_vec(&self.revision)?;
-        let data_cbor = serde_cbor::to_vec(&self.persistent)?;
-
-        // Phase 1: Create atomic backup. If this fails, the main file is
-        // untouched and the caller can safely undo the in-memory patch.
-        let mut backup_file = File::create(&bak_tmp).await?;
-        backup_file.write_all(&revision_cbor).await?;
-        backup_file.write_all(&data_cbor).await?;
-        backup_file.flush().await?;
-        backup_file.sync_all().await?;
-        tokio::fs::rename(&bak_tmp, &bak).await?;
-        *committed = true;
-
-        // Point of no return: the backup exists with the new state. On restart,
-        // Store::open will rename it over the main file. From here, errors
-        // must NOT cause an in-memory undo.
-
-        // Phase 2: Rewrite main file. If this fails, the backup ensures crash
-        // recovery. We propagate the error but signal that undo is unsafe.
-        self.file.set_len(0).await?;
-        self.file.seek(SeekFrom::Start(0)).await?;
-        self.file.write_all(&revision_cbor).await?;
-        self.file.write_all(&data_cbor).await?;
-        self.file.flush().await?;
-        self.file.sync_all().await?;
-        self.file_cursor = self.file.stream_position().await?;
-
-        // Phase 3: Remove backup. Non-fatal — on restart, the backup (which
-        // matches the main file) will be harmlessly applied.
-        let _ = tokio::fs::remove_file(&bak).await;
-
-        Ok(())
+        self.storage
+            .compact(self.revision, &self.persistent, committed)
+            .await
     }
     pub(crate) async fn apply(&mut self, patch: DiffPatch) -> Result<Option<Arc<Revision>>, Error> {
-        use tokio::io::AsyncWriteExt;
-
         // eject if noop
         if (patch.0).0.is_empty() {
             return Ok(None);
@@ -247,7 +185,6 @@ impl Store {
         tracing::trace!("Attempting to apply patch: {:?}", patch);
 
         // apply patch in memory
-        let patch_bin = serde_cbor::to_vec(&*patch)?;
         let mut updated = TentativeUpdated::new(self, &patch)?;
 
         if updated.store.revision % 4096 == 0 {
@@ -258,29 +195,22 @@ impl Store {
             }
             res?;
         } else {
-            if updated.store.file.stream_position().await? != updated.store.file_cursor {
-                updated
-                    .store
-                    .file
-                    .set_len(updated.store.file_cursor)
-                    .await?;
-                updated
-                    .store
-                    .file
-                    .seek(SeekFrom::Start(updated.store.file_cursor))
-                    .await?;
-            }
-            updated.store.file.write_all(&patch_bin).await?;
-            updated.store.file.flush().await?;
-            updated.store.file.sync_all().await?;
-            updated.store.file_cursor += patch_bin.len() as u64;
+            let revision = updated.store.revision;
+            updated.store.storage.append(revision, &patch).await?;
         }
         drop(updated.undo.take());
         drop(updated);
 
         let id = self.revision;
         let res = Arc::new(Revision { id, patch });
-        self.broadcast.send(&res);
+        self.broadcast.send(&res, &self.persistent);
+        if self.history.len() == HISTORY_LEN {
+            self.history.pop_front();
+        }
+        self.history.push_back(res.clone());
+        if let Some(log) = &self.history_log {
+            log.record(res.clone(), &self.persistent);
+        }
 
         Ok(Some(res))
     }
@@ -290,10 +220,6 @@ impl Store {
 // Without this, the compiler-generated recursive Drop can exhaust the call stack.
 impl Drop for Store {
     fn drop(&mut self) {
-        if let Ok(mut lock) = OPEN_STORES.lock() {
-            lock.remove(&self.path);
-        }
-
         // Only Array and Object can cause deep recursion
         match &self.persistent {
             Value::Array(vec) if !vec.is_empty() => {}
@@ -380,8 +306,39 @@ pub struct PatchDb {
 }
 impl PatchDb {
     pub async fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::open_with_storage(FileStorage::open(path).await?).await
+    }
+    /// Open a database persisted by `storage` instead of the default
+    /// [`FileStorage`].
+    pub async fn open_with_storage<S: Storage + 'static>(storage: S) -> Result<Self, Error> {
+        Ok(PatchDb {
+            store: Arc::new(RwLock::new(Store::open(Box::new(storage)).await?)),
+        })
+    }
+    /// A database that starts out `null` and persists nothing. See
+    /// [`MemoryStorage`].
+    pub fn in_memory() -> Self {
+        PatchDb {
+            store: Arc::new(RwLock::new(Store::new(
+                Box::new(MemoryStorage::new()),
+                0,
+                Value::Null,
+            ))),
+        }
+    }
+    /// Like [`PatchDb::open_with_storage`], but also record every revision,
+    /// along with its [metadata](crate::with_metadata), in the append-only log
+    /// at `history`, as configured by `options`.
+    pub async fn open_with_history<S: Storage + 'static, H: AsRef<Path>>(
+        storage: S,
+        history: H,
+        options: HistoryOptions,
+    ) -> Result<Self, Error> {
+        let mut store = Store::open(Box::new(storage)).await?;
+        store.history_log =
+            Some(HistoryLog::open(history.as_ref().to_owned(), options, &store.dump(&ROOT)).await?);
         Ok(PatchDb {
-            store: Arc::new(RwLock::new(Store::open(path).await?)),
+            store: Arc::new(RwLock::new(store)),
         })
     }
     pub async fn close(self) {
@@ -399,6 +356,43 @@ impl PatchDb {
         let mut store = self.store.write().await;
         (store.dump(&ptr), store.broadcast.subscribe(ptr))
     }
+    /// Subscribe on behalf of a client that has already seen revision `since`,
+    /// e.g. one reconnecting after a dropped connection. The [`Catchup`] holds
+    /// only what it missed while the store still retains that; the subscriber
+    /// yields everything after.
+    pub async fn subscribe_from(&self, ptr: JsonPointer, since: u64) -> (Catchup, Subscriber) {
+        let mut store = self.store.write().await;
+        (store.catchup(&ptr, since), store.broadcast.subscribe(ptr))
+    }
+    /// The path of the history log, once everything already applied has been
+    /// written to it.
+    async fn history_path(&self) -> Result<PathBuf, Error> {
+        let (path, flushed) = {
+            let store = self.store.read().await;
+            let log = store.history_log.as_ref().ok_or(Error::NoHistory)?;
+            (log.path().to_owned(), log.flush())
+        };
+        flushed.await;
+        Ok(path)
+    }
+    /// The recorded revisions with ids in `range`, oldest first, scoped to
+    /// `ptr`. Revisions that did not touch `ptr` are left out. The log is
+    /// read one record at a time.
+    pub async fn history(
+        &self,
+        ptr: JsonPointer,
+        range: impl RangeBounds<u64> + Send + 'static,
+    ) -> Result<Vec<HistoryEntry>, Error> {
+        let path = self.history_path().await?;
+        tokio::task::spawn_blocking(move || history::entries(&path, &ptr, range)).await?
+    }
+    /// The value at `ptr` as of revision `id`, rebuilt from the history log.
+    pub async fn value_at(&self, ptr: JsonPointer, id: u64) -> Result<Value, Error> {
+        let path = self.history_path().await?;
+        tokio::task::spawn_blocking(move || history::value_at(&path, &ptr, id))
+            .await??
+            .ok_or(Error::NotInHistory(id))
+    }
     pub async fn watch(&self, ptr: JsonPointer) -> DbWatch {
         let (dump, sub) = self.dump_and_sub(ptr).await;
         DbWatch::new(dump, sub)
@@ -406,6 +400,15 @@ impl PatchDb {
     pub async fn subscribe(&self, ptr: JsonPointer) -> Subscriber {
         self.store.write().await.subscribe(ptr)
     }
+    /// Watch the nodes the JSONPath expression `query` selects, e.g.
+    /// `$.packageData.*.status`. Unlike [`PatchDb::watch`], this only wakes
+    /// when a revision changes the selection.
+    pub async fn watch_query(&self, query: &str) -> Result<QueryWatch, Error> {
+        let query = Query::compile(query)?;
+        let mut store = self.store.write().await;
+        let state = store.persistent.clone();
+        Ok(store.broadcast.subscribe_query(query, state))
+    }
     pub async fn exists<S: AsRef<str>, V: SegList>(&self, ptr: &JsonPointer<S, V>) -> bool {
         self.store.read().await.exists(ptr)
     }
@@ -438,13 +441,69 @@ impl PatchDb {
         let rev = store.apply(patch).await?;
         Ok(rev)
     }
+    /// Apply an arbitrary RFC 6902 patch atomically: if any operation fails,
+    /// including a `test`, nothing is applied.
+    pub async fn apply_json_patch(
+        &self,
+        patch: &json_patch::Patch,
+    ) -> Result<Option<Arc<Revision>>, Error> {
+        self.apply_json_patch_if(None, patch).await
+    }
+    /// Like [`PatchDb::apply_json_patch`], but fails with [`Error::Conflict`]
+    /// unless the database is still at `revision`.
+    pub async fn apply_json_patch_at(
+        &self,
+        revision: u64,
+        patch: &json_patch::Patch,
+    ) -> Result<Option<Arc<Revision>>, Error> {
+        self.apply_json_patch_if(Some(revision), patch).await
+    }
+    async fn apply_json_patch_if(
+        &self,
+        revision: Option<u64>,
+        patch: &json_patch::Patch,
+    ) -> Result<Option<Arc<Revision>>, Error> {
+        let mut store = self.store.write().await;
+        store.check_revision(revision)?;
+        // client patches may move, copy or test, which a DiffPatch can't
+        // scope to a subscriber, so apply to a copy and diff it back in
+        let mut new = store.persistent.clone();
+        json_patch::patch(&mut new, patch)?;
+        let diff = diff(&store.persistent, &new);
+        store.apply(diff).await
+    }
     pub async fn apply_function<F, T, E>(&self, f: F) -> MutateResult<(Value, T), E>
+    where
+        F: FnOnce(Value) -> Result<(Value, T), E> + UnwindSafe,
+        E: From<Error>,
+    {
+        self.apply_function_if(None, f).await
+    }
+    /// Like [`PatchDb::apply_function`], but fails with [`Error::Conflict`]
+    /// without calling `f` unless the database is still at `revision`.
+    pub async fn apply_function_at<F, T, E>(
+        &self,
+        revision: u64,
+        f: F,
+    ) -> MutateResult<(Value, T), E>
+    where
+        F: FnOnce(Value) -> Result<(Value, T), E> + UnwindSafe,
+        E: From<Error>,
+    {
+        self.apply_function_if(Some(revision), f).await
+    }
+    async fn apply_function_if<F, T, E>(
+        &self,
+        revision: Option<u64>,
+        f: F,
+    ) -> MutateResult<(Value, T), E>
     where
         F: FnOnce(Value) -> Result<(Value, T), E> + UnwindSafe,
         E: From<Error>,
     {
         async {
             let mut store = self.store.write().await;
+            store.check_revision(revision)?;
             let old = store.persistent.clone();
             let (new, res) = std::panic::catch_unwind(move || f(old)).map_err(|e| {
                 Error::Panic(
@@ -535,6 +594,22 @@ impl<T: HasModel, E: From<Error>> TypedPatchDb<T, E> {
         .await
         .map_ok(|(_, v)| v)
     }
+    /// Like [`TypedPatchDb::mutate`], but fails with [`Error::Conflict`]
+    /// without calling `f` unless the database is still at `revision`.
+    pub async fn mutate_at<U: UnwindSafe + Send>(
+        &self,
+        revision: u64,
+        f: impl FnOnce(&mut T::Model) -> Result<U, E> + UnwindSafe + Send,
+    ) -> MutateResult<U, E> {
+        use crate::ModelExt;
+        self.apply_function_at(revision, |mut v| {
+            let model = T::Model::value_as_mut(&mut v);
+            let res = f(model)?;
+            Ok::<_, E>((v, res))
+        })
+        .await
+        .map_ok(|(_, v)| v)
+    }
     pub async fn map_mutate(
         &self,
         f: impl FnOnce(T::Model) -> Result<T::Model, E> + UnwindSafe + Send,
@@ -8,6 +8,7 @@ use json_ptr::JsonPointer;
 use serde::de::DeserializeOwned;
 use tokio::sync::mpsc;
 
+use crate::query::{Query, Selection};
 use crate::{Dump, Error, HasModel, ModelExt, Revision};
 
 pub type Subscriber = mpsc::UnboundedReceiver<Revision>;
@@ -24,14 +25,37 @@ impl ScopedSender {
     }
 }
 
+/// Hands a [`QueryWatch`] each state a revision under its query's prefix
+/// brings the store to. The watch evaluates the query itself, so that the
+/// store's lock is not held for it.
+#[derive(Debug)]
+struct QuerySender {
+    prefix: JsonPointer,
+    send: mpsc::UnboundedSender<Value>,
+}
+impl QuerySender {
+    fn send(
+        &self,
+        revision: &Revision,
+        state: &Value,
+    ) -> Result<(), mpsc::error::SendError<Value>> {
+        if revision.patch.for_path(&self.prefix).is_empty() {
+            return Ok(());
+        }
+        self.send.send(state.clone())
+    }
+}
+
 #[derive(Debug)]
 pub struct Broadcast {
     listeners: Vec<ScopedSender>,
+    queries: Vec<QuerySender>,
 }
 impl Default for Broadcast {
     fn default() -> Self {
         Self {
             listeners: Vec::new(),
+            queries: Vec::new(),
         }
     }
 }
@@ -40,7 +64,9 @@ impl Broadcast {
         Default::default()
     }
 
-    pub fn send(&mut self, value: &Revision) {
+    /// Send `value`, which brought the store to `state`, to every listener
+    /// it concerns.
+    pub fn send(&mut self, value: &Revision, state: &Value) {
         let mut i = 0;
         while i < self.listeners.len() {
             if self.listeners[i].send(value).is_err() {
@@ -49,6 +75,14 @@ impl Broadcast {
                 i += 1;
             }
         }
+        let mut i = 0;
+        while i < self.queries.len() {
+            if self.queries[i].send(value, state).is_err() {
+                self.queries.swap_remove(i);
+            } else {
+                i += 1;
+            }
+        }
     }
 
     pub fn subscribe(&mut self, ptr: JsonPointer) -> Subscriber {
@@ -56,6 +90,23 @@ impl Broadcast {
         self.listeners.push(ScopedSender(ptr, send));
         recv
     }
+
+    /// Watch what `query` selects from `state`, the current state of the
+    /// store.
+    pub fn subscribe_query(&mut self, query: Query, state: Value) -> QueryWatch {
+        let (send, recv) = mpsc::unbounded_channel();
+        self.queries.push(QuerySender {
+            prefix: query.prefix().clone(),
+            send,
+        });
+        QueryWatch {
+            query,
+            state: Some(state),
+            selection: Selection::new(),
+            subscriber: recv,
+            seen: false,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -126,6 +177,73 @@ impl Stream for DbWatch {
     }
 }
 
+/// Like a [`DbWatch`], but of what a [`Query`] selects. Only wakes when the
+/// selection changes.
+#[derive(Debug)]
+pub struct QueryWatch {
+    query: Query,
+    /// The latest state received, not yet selected from.
+    state: Option<Value>,
+    selection: Selection,
+    subscriber: mpsc::UnboundedReceiver<Value>,
+    seen: bool,
+}
+impl QueryWatch {
+    /// Select from the latest state, if there is a new one.
+    fn update(&mut self) {
+        if let Some(state) = self.state.take() {
+            let selection = self.query.select(&state);
+            if selection != self.selection {
+                self.selection = selection;
+                self.seen = false;
+            }
+        }
+    }
+    pub fn sync(&mut self) {
+        while let Ok(state) = self.subscriber.try_recv() {
+            self.state = Some(state);
+        }
+        self.update();
+    }
+    pub fn peek(&mut self) -> Selection {
+        self.sync();
+        self.selection.clone()
+    }
+    pub fn peek_and_mark_seen(&mut self) -> Selection {
+        self.sync();
+        self.seen = true;
+        self.selection.clone()
+    }
+    pub fn poll_changed(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), Error>> {
+        self.sync();
+        while self.seen {
+            let state = ready!(self.subscriber.poll_recv(cx))
+                .ok_or(mpsc::error::TryRecvError::Disconnected)?;
+            self.state = Some(state);
+            self.sync();
+        }
+        self.seen = true;
+        Poll::Ready(Ok(()))
+    }
+    pub async fn changed(&mut self) -> Result<(), Error> {
+        futures::future::poll_fn(|cx| self.poll_changed(cx)).await
+    }
+}
+impl Unpin for QueryWatch {}
+impl Stream for QueryWatch {
+    type Item = Result<Selection, Error>;
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if let Err(e) = ready!(this.poll_changed(cx)) {
+            return Poll::Ready(Some(Err(e)));
+        }
+        Poll::Ready(Some(Ok(this.selection.clone())))
+    }
+}
+
 pub struct TypedDbWatch<T> {
     watch: DbWatch,
     _phantom: PhantomData<T>,
@@ -2,9 +2,13 @@ use std::future::Future;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
+use futures::FutureExt;
 use imbl_value::{json, Value};
 use json_ptr::JsonPointer;
-use patch_db::{PatchDb, Revision};
+use patch_db::{
+    Catchup, Cipher, DiffPatch, EncryptedStorage, Error, FileStorage, HistoryOptions, PatchDb,
+    Revision, Storage,
+};
 use proptest::prelude::*;
 use tokio::fs;
 use tokio::runtime::Builder;
@@ -43,6 +47,12 @@ async fn cleanup_db(db_name: &str) {
     fs::remove_file(format!("{}.bak", db_name)).await.ok();
     fs::remove_file(format!("{}.bak.tmp", db_name)).await.ok();
     fs::remove_file(format!("{}.failed", db_name)).await.ok();
+    fs::remove_file(format!("{}.history", db_name)).await.ok();
+    for n in 1..4 {
+        fs::remove_file(format!("{}.history.{}", db_name, n))
+            .await
+            .ok();
+    }
 }
 
 async fn put_string_into_root(db: &PatchDb, s: String) -> Arc<Revision> {
@@ -66,6 +76,580 @@ async fn basic() {
     cleanup_db(&path).await;
 }
 
+#[tokio::test]
+async fn subscribe_from_replays_missed_revisions() {
+    let path = unique_db_path("subscribe-from");
+    let db = init_db(path.clone()).await;
+    let ptr: JsonPointer = "/b".parse().unwrap();
+    let seen = db.sequence().await;
+    db.put(&"/b/b".parse::<JsonPointer>().unwrap(), &2)
+        .await
+        .unwrap();
+    db.put(&"/a".parse::<JsonPointer>().unwrap(), "outside")
+        .await
+        .unwrap();
+    db.put(&"/b/c".parse::<JsonPointer>().unwrap(), "set")
+        .await
+        .unwrap();
+
+    let (catchup, mut sub) = db.subscribe_from(ptr.clone(), seen).await;
+    let Catchup::Revisions(revs) = catchup else {
+        panic!("expected revisions, got {:?}", catchup);
+    };
+    // the write to /a is outside the subscription
+    assert_eq!(
+        revs.iter().map(|rev| rev.id).collect::<Vec<_>>(),
+        [seen + 1, seen + 3]
+    );
+    let mut value = json!({ "a": "test2", "b": 1, "c": null });
+    for rev in &revs {
+        json_patch::patch(&mut value, &rev.patch.0).unwrap();
+    }
+    assert_eq!(value, db.get_value(&ptr).await);
+
+    db.put(&"/b/a".parse::<JsonPointer>().unwrap(), "live")
+        .await
+        .unwrap();
+    assert_eq!(sub.recv().await.unwrap().id, seen + 4);
+
+    let (catchup, _) = db.subscribe_from(ptr.clone(), seen + 4).await;
+    assert!(matches!(catchup, Catchup::Revisions(revs) if revs.is_empty()));
+    db.close().await;
+    cleanup_db(&path).await;
+}
+
+#[tokio::test]
+async fn subscribe_from_dumps_outside_window() {
+    let path = unique_db_path("subscribe-from-window");
+    let db = init_db(path.clone()).await;
+    let ptr: JsonPointer = "/b/b".parse().unwrap();
+    let seen = db.sequence().await;
+    for i in 0..=crate::store::HISTORY_LEN as u64 {
+        db.put(&ptr, &i).await.unwrap();
+    }
+    let current = db.sequence().await;
+
+    let (catchup, _) = db.subscribe_from(ptr.clone(), seen).await;
+    assert!(
+        matches!(&catchup, Catchup::Dump(dump) if dump.id == current),
+        "expected a dump, got {:?}",
+        catchup
+    );
+    // the oldest retained revision still connects
+    let (catchup, _) = db.subscribe_from(ptr.clone(), seen + 1).await;
+    assert!(matches!(catchup, Catchup::Revisions(revs) if revs.len() == crate::store::HISTORY_LEN));
+    // a revision the store never reached
+    let (catchup, _) = db.subscribe_from(ptr, current + 1).await;
+    assert!(matches!(catchup, Catchup::Dump(_)));
+    db.close().await;
+    cleanup_db(&path).await;
+}
+
+#[tokio::test]
+async fn history_records_metadata_and_rebuilds_values() {
+    let path = unique_db_path("history");
+    cleanup_db(&path).await;
+    let history = format!("{}.history", path);
+    let ptr: JsonPointer = "/b".parse().unwrap();
+    let db = PatchDb::open_with_history(
+        FileStorage::open(&path).await.unwrap(),
+        &history,
+        HistoryOptions::default(),
+    )
+    .await
+    .unwrap();
+    db.put(
+        &JsonPointer::<&'static str>::default(),
+        &json!({ "a": 1, "b": 1 }),
+    )
+    .await
+    .unwrap();
+    let first = db.sequence().await;
+    patch_db::with_metadata(json!({ "method": "set-b" }), async {
+        db.put(&ptr, &2).await.unwrap();
+    })
+    .await;
+    db.put(&"/a".parse::<JsonPointer>().unwrap(), &2)
+        .await
+        .unwrap();
+    db.close().await;
+
+    // a gap: these revisions are not recorded
+    let db = PatchDb::open(&path).await.unwrap();
+    db.put(&ptr, &3).await.unwrap();
+    let unrecorded = db.sequence().await;
+    db.close().await;
+
+    let db = PatchDb::open_with_history(
+        FileStorage::open(&path).await.unwrap(),
+        &history,
+        HistoryOptions::default(),
+    )
+    .await
+    .unwrap();
+    db.put(&ptr, &4).await.unwrap();
+
+    let entries = db.history(ptr.clone(), ..).await.unwrap();
+    assert_eq!(
+        entries.iter().map(|e| e.id).collect::<Vec<_>>(),
+        [first, first + 1, unrecorded + 1]
+    );
+    assert_eq!(entries[1].metadata, json!({ "method": "set-b" }));
+    assert_eq!(entries[2].metadata, Value::Null);
+    assert_eq!(db.history(ptr.clone(), first + 1..).await.unwrap().len(), 2);
+
+    assert_eq!(db.value_at(ptr.clone(), first).await.unwrap(), json!(1));
+    assert_eq!(db.value_at(ptr.clone(), first + 2).await.unwrap(), json!(2));
+    // reopening checkpoints the state it missed
+    assert_eq!(
+        db.value_at(ptr.clone(), unrecorded).await.unwrap(),
+        json!(3)
+    );
+    assert_eq!(
+        db.value_at(ptr.clone(), unrecorded + 1).await.unwrap(),
+        json!(4)
+    );
+    assert!(matches!(
+        db.value_at(ptr, unrecorded + 2).await,
+        Err(Error::NotInHistory(_))
+    ));
+    db.close().await;
+    cleanup_db(&path).await;
+}
+
+#[tokio::test]
+async fn history_leaves_out_excluded_members() {
+    let path = unique_db_path("history-exclude");
+    cleanup_db(&path).await;
+    let history = format!("{}.history", path);
+    let secret: JsonPointer = "/private/key".parse().unwrap();
+    let db = PatchDb::open_with_history(
+        FileStorage::open(&path).await.unwrap(),
+        &history,
+        HistoryOptions {
+            exclude: vec!["/private".parse().unwrap()],
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    db.put(
+        &JsonPointer::<&'static str>::default(),
+        &json!({ "public": { "a": 1 }, "private": { "key": "hunter2" } }),
+    )
+    .await
+    .unwrap();
+    let first = db.sequence().await;
+    db.put(&secret, "hunter3").await.unwrap();
+    db.put(&"/public/a".parse::<JsonPointer>().unwrap(), &2)
+        .await
+        .unwrap();
+    // a move out of the excluded member is recorded as an add of its value
+    db.apply_json_patch(
+        &imbl_value::from_value(json!([
+            { "op": "move", "from": "/private/key", "path": "/public/key" }
+        ]))
+        .unwrap(),
+    )
+    .await
+    .unwrap();
+    db.close().await;
+
+    let raw = fs::read(&history).await.unwrap();
+    assert!(!raw.windows(7).any(|w| w == b"hunter2"));
+    let db = PatchDb::open(&path).await.unwrap();
+    db.close().await;
+    let db = PatchDb::open_with_history(
+        FileStorage::open(&path).await.unwrap(),
+        &history,
+        HistoryOptions {
+            exclude: vec!["/private".parse().unwrap()],
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let root = JsonPointer::default();
+    let entries = db.history(root.clone(), ..).await.unwrap();
+    assert_eq!(
+        entries.iter().map(|e| e.id).collect::<Vec<_>>(),
+        [first, first + 2, first + 3]
+    );
+    assert_eq!(
+        db.value_at(root.clone(), first).await.unwrap(),
+        json!({ "public": { "a": 1 } })
+    );
+    assert_eq!(
+        db.value_at(root.clone(), first + 3).await.unwrap(),
+        json!({ "public": { "a": 2, "key": "hunter3" } })
+    );
+    assert_eq!(db.value_at(secret, first + 1).await.unwrap(), Value::Null);
+    db.close().await;
+    cleanup_db(&path).await;
+}
+
+#[tokio::test]
+async fn history_rotates_segments() {
+    let path = unique_db_path("history-rotate");
+    cleanup_db(&path).await;
+    let history = format!("{}.history", path);
+    let ptr: JsonPointer = "/a".parse().unwrap();
+    let db = PatchDb::open_with_history(
+        FileStorage::open(&path).await.unwrap(),
+        &history,
+        HistoryOptions {
+            segment_size: 256,
+            segments: 2,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    db.put(&JsonPointer::<&'static str>::default(), &json!({}))
+        .await
+        .unwrap();
+    for i in 0..64 {
+        db.put(&ptr, &i).await.unwrap();
+    }
+    let current = db.sequence().await;
+    let entries = db.history(ptr.clone(), ..).await.unwrap();
+    // only the last two segments are kept, and they connect
+    assert!(!entries.is_empty() && entries.len() < 64);
+    let oldest = entries[0].id;
+    assert_eq!(
+        entries.iter().map(|e| e.id).collect::<Vec<_>>(),
+        (oldest..=current).collect::<Vec<_>>()
+    );
+    assert!(fs::metadata(format!("{}.1", history)).await.is_ok());
+    assert!(fs::metadata(format!("{}.2", history)).await.is_err());
+    assert_eq!(db.value_at(ptr.clone(), current).await.unwrap(), json!(63));
+    assert!(matches!(
+        db.value_at(ptr, 1).await,
+        Err(Error::NotInHistory(_))
+    ));
+    db.close().await;
+    cleanup_db(&path).await;
+}
+
+#[tokio::test]
+async fn conditional_mutations_detect_conflicts() {
+    let path = unique_db_path("conditional");
+    let db = init_db(path.clone()).await;
+    let ptr: JsonPointer = "/a".parse().unwrap();
+    db.put(&ptr, &1).await.unwrap();
+    let read = db.sequence().await;
+
+    // someone else writes between our read and our write
+    db.put(&ptr, &2).await.unwrap();
+    let res = db
+        .apply_function_at(read, |mut v| {
+            v["a"] = json!(3);
+            Ok::<_, Error>((v, ()))
+        })
+        .await;
+    assert!(matches!(
+        res.result,
+        Err(Error::Conflict { expected, actual }) if expected == read && actual == read + 1
+    ));
+    assert_eq!(db.get_value(&ptr).await, json!(2));
+
+    let patch = |ops: Value| imbl_value::from_value::<json_patch::Patch>(ops).unwrap();
+    assert!(matches!(
+        db.apply_json_patch_at(
+            read,
+            &patch(json!([{ "op": "replace", "path": "/a", "value": 3 }]))
+        )
+        .await,
+        Err(Error::Conflict { .. })
+    ));
+    // a failing test leaves the earlier operations unapplied
+    assert!(matches!(
+        db.apply_json_patch(&patch(json!([
+            { "op": "replace", "path": "/a", "value": 3 },
+            { "op": "test", "path": "/a", "value": 1 },
+        ])))
+        .await,
+        Err(Error::Patch(json_patch::PatchError::TestFailed))
+    ));
+    assert_eq!(db.get_value(&ptr).await, json!(2));
+
+    let rev = db
+        .apply_json_patch_at(
+            read + 1,
+            &patch(json!([
+                { "op": "test", "path": "/a", "value": 2 },
+                { "op": "copy", "from": "/a", "path": "/b" },
+            ])),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(rev.id, read + 2);
+    assert_eq!(
+        db.get_value(&"/b".parse::<JsonPointer>().unwrap()).await,
+        json!(2)
+    );
+    db.close().await;
+    cleanup_db(&path).await;
+}
+
+#[tokio::test]
+async fn moves_reach_every_subscriber() {
+    let path = unique_db_path("moves");
+    let db = init_db(path.clone()).await;
+    let root = JsonPointer::<&'static str>::default();
+    db.put(
+        &root,
+        &json!({
+            "list": [{ "a": 1 }, { "b": 2 }, { "c": 3 }],
+            "map": { "old": { "x": [1, 2, 3] } },
+        }),
+    )
+    .await
+    .unwrap();
+    let ptrs = ["", "/list", "/map", "/map/old", "/map/new", "/map/new/x"];
+    let mut watches = Vec::new();
+    for ptr in ptrs {
+        let ptr: JsonPointer = ptr.parse().unwrap();
+        watches.push((ptr.clone(), db.watch(ptr).await));
+    }
+
+    let after = json!({
+        "list": [{ "b": 2 }, { "c": 3 }, { "a": 1 }],
+        "map": { "new": { "x": [1, 2, 3] } },
+    });
+    let rev = db.put(&root, &after).await.unwrap().unwrap();
+    assert!((rev.patch.0)
+        .0
+        .iter()
+        .any(|op| matches!(op, json_patch::PatchOperation::Move(_))));
+    for (ptr, watch) in &mut watches {
+        assert_eq!(
+            watch.peek().unwrap(),
+            ptr.get(&after).cloned().unwrap_or_default(),
+            "{}",
+            ptr
+        );
+    }
+    db.close().await;
+    cleanup_db(&path).await;
+}
+
+/// Keeps everything "persisted" in memory shared with the test, and can be
+/// told to fail.
+#[derive(Clone, Default)]
+struct SharedStorage {
+    state: Arc<std::sync::Mutex<(u64, Value, Vec<json_patch::Patch>)>>,
+    fail: Arc<std::sync::atomic::AtomicBool>,
+}
+#[async_trait::async_trait]
+impl Storage for SharedStorage {
+    async fn load(&mut self) -> Result<(u64, Value), Error> {
+        let (revision, value, patches) = &*self.state.lock().unwrap();
+        let mut value = value.clone();
+        for patch in patches {
+            json_patch::patch(&mut value, patch)?;
+        }
+        Ok((revision + patches.len() as u64, value))
+    }
+    async fn append(&mut self, _revision: u64, patch: &DiffPatch) -> Result<(), Error> {
+        if self.fail.load(Ordering::SeqCst) {
+            return Err(Error::IO(std::io::ErrorKind::Other.into()));
+        }
+        self.state.lock().unwrap().2.push((**patch).clone());
+        Ok(())
+    }
+    async fn compact(
+        &mut self,
+        revision: u64,
+        value: &Value,
+        committed: &mut bool,
+    ) -> Result<(), Error> {
+        *self.state.lock().unwrap() = (revision, value.clone(), Vec::new());
+        *committed = true;
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn storage_backends() {
+    let root = JsonPointer::<&'static str>::default();
+    let ptr: JsonPointer = "/a".parse().unwrap();
+
+    let db = PatchDb::in_memory();
+    assert_eq!(db.get_value(&root).await, Value::Null);
+    db.put(&root, &json!({ "a": 1 })).await.unwrap();
+    assert_eq!(db.get::<u32, _, _>(&ptr).await.unwrap(), 1);
+    db.close().await;
+
+    let storage = SharedStorage::default();
+    let db = PatchDb::open_with_storage(storage.clone()).await.unwrap();
+    db.put(&root, &json!({ "a": 1 })).await.unwrap();
+    db.put(&ptr, &2).await.unwrap();
+    let sequence = db.sequence().await;
+    storage.fail.store(true, Ordering::SeqCst);
+    assert!(db.put(&ptr, &3).await.is_err());
+    // a write the backend rejected is rolled back
+    assert_eq!(db.get::<u32, _, _>(&ptr).await.unwrap(), 2);
+    assert_eq!(db.sequence().await, sequence);
+    storage.fail.store(false, Ordering::SeqCst);
+    db.close().await;
+
+    let db = PatchDb::open_with_storage(storage.clone()).await.unwrap();
+    assert_eq!(db.get::<u32, _, _>(&ptr).await.unwrap(), 2);
+    assert_eq!(db.sequence().await, sequence);
+    // opening compacts
+    assert!(storage.state.lock().unwrap().2.is_empty());
+    db.close().await;
+}
+
+/// Hex of the bytes XORed with a constant: no cipher, but enough that the
+/// plaintext can't be found in what the inner backend stores.
+struct XorCipher;
+impl Cipher for XorCipher {
+    fn encrypt(&self, plaintext: &[u8]) -> Result<String, Error> {
+        Ok(plaintext
+            .iter()
+            .map(|b| format!("{:02x}", b ^ 0x5a))
+            .collect())
+    }
+    fn decrypt(&self, ciphertext: &str) -> Result<Vec<u8>, Error> {
+        (0..ciphertext.len())
+            .step_by(2)
+            .map(|i| {
+                u8::from_str_radix(&ciphertext[i..i + 2], 16)
+                    .map(|b| b ^ 0x5a)
+                    .map_err(|e| Error::IO(std::io::Error::new(std::io::ErrorKind::InvalidData, e)))
+            })
+            .collect()
+    }
+}
+
+#[tokio::test]
+async fn encrypted_storage_seals_subtree() {
+    let root = JsonPointer::<&'static str>::default();
+    let private: JsonPointer = "/private".parse().unwrap();
+    let password: JsonPointer = "/private/password".parse().unwrap();
+    let public: JsonPointer = "/public".parse().unwrap();
+    let storage = SharedStorage::default();
+    let open = || {
+        PatchDb::open_with_storage(EncryptedStorage::new(
+            storage.clone(),
+            XorCipher,
+            private.clone(),
+        ))
+    };
+    let stored = || format!("{:?}", *storage.state.lock().unwrap());
+
+    // written before the subtree was encrypted
+    storage.state.lock().unwrap().1 = json!({ "public": 1, "private": { "password": "hunter2" } });
+    let db = open().await.unwrap();
+    assert_eq!(db.get::<String, _, _>(&password).await.unwrap(), "hunter2");
+    // opening compacts, which encrypts it
+    assert!(!stored().contains("hunter2"));
+
+    db.put(&password, "swordfish").await.unwrap();
+    db.put(&public, &2).await.unwrap();
+    db.put(
+        &root,
+        &json!({ "public": 3, "private": { "password": "correct horse" } }),
+    )
+    .await
+    .unwrap();
+    let written = stored();
+    for secret in &["password", "swordfish", "correct horse"] {
+        assert!(
+            !written.contains(secret),
+            "{} was stored in the clear",
+            secret
+        );
+    }
+    db.close().await;
+
+    // reopening replays the sealed patches
+    let db = open().await.unwrap();
+    assert_eq!(
+        db.get_value(&root).await,
+        json!({ "public": 3, "private": { "password": "correct horse" } })
+    );
+    db.put(&root, &json!({ "public": 4 })).await.unwrap();
+    db.close().await;
+
+    let db = open().await.unwrap();
+    assert_eq!(db.get_value(&root).await, json!({ "public": 4 }));
+    db.close().await;
+}
+
+#[tokio::test]
+async fn query_watches_wake_on_selected_changes() {
+    let root = JsonPointer::<&'static str>::default();
+    let db = PatchDb::in_memory();
+    db.put(
+        &root,
+        &json!({
+            "packageData": {
+                "a": { "status": "running", "icon": "a.png" },
+                "b": { "status": "stopped", "icon": "b.png" },
+            },
+            "other": 1,
+        }),
+    )
+    .await
+    .unwrap();
+    let mut watch = db.watch_query("$.packageData.*.status").await.unwrap();
+    watch.changed().await.unwrap();
+    let statuses = |watch: &mut patch_db::QueryWatch| {
+        watch
+            .peek_and_mark_seen()
+            .into_iter()
+            .map(|(ptr, v)| (ptr.to_string(), v))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        statuses(&mut watch),
+        [
+            ("/packageData/a/status".to_owned(), json!("running")),
+            ("/packageData/b/status".to_owned(), json!("stopped")),
+        ]
+    );
+
+    // neither outside the prefix nor an unselected sibling
+    db.put(&"/other".parse::<JsonPointer>().unwrap(), &2)
+        .await
+        .unwrap();
+    db.put(
+        &"/packageData/a/icon".parse::<JsonPointer>().unwrap(),
+        &"new.png",
+    )
+    .await
+    .unwrap();
+    assert!(watch.changed().now_or_never().is_none());
+
+    db.put(
+        &"/packageData/b/status".parse::<JsonPointer>().unwrap(),
+        &"running",
+    )
+    .await
+    .unwrap();
+    watch.changed().await.unwrap();
+    assert_eq!(statuses(&mut watch)[1].1, json!("running"));
+
+    db.put(
+        &"/packageData/c".parse::<JsonPointer>().unwrap(),
+        &json!({ "status": "installing" }),
+    )
+    .await
+    .unwrap();
+    watch.changed().await.unwrap();
+    assert_eq!(statuses(&mut watch).len(), 3);
+
+    assert!(matches!(
+        db.watch_query("$.[").await,
+        Err(Error::JsonPath(_))
+    ));
+    db.close().await;
+}
+
 fn run_future<S: Into<String>, Fut: Future<Output = ()>>(name: S, fut: Fut) {
     Builder::new_multi_thread()
         .thread_name(name)
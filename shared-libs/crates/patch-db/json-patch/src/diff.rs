@@ -1,9 +1,16 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 
-use imbl_value::Value;
+use imbl_value::imbl::Vector;
+use imbl_value::{InOMap, InternedString, Value};
 use json_ptr::JsonPointer;
 
-use crate::{AddOperation, PatchOperation, RemoveOperation, ReplaceOperation};
+use crate::{
+    AddOperation, CopyOperation, MoveOperation, PatchOperation, RemoveOperation, ReplaceOperation,
+};
+
+/// Arrays whose differing middles would take more comparisons than this to
+/// align are diffed index by index instead.
+const MAX_ALIGN_CELLS: usize = 1 << 20;
 
 struct PatchDiffer {
     path: JsonPointer,
@@ -21,6 +28,12 @@ impl PatchDiffer {
 
 /// Diff two JSON documents and generate a JSON Patch (RFC 6902).
 ///
+/// Arrays are aligned by their longest common subsequence, so inserting,
+/// removing or reordering elements only touches those elements: an element
+/// that changed position is `move`d rather than removed and re-added. Likewise
+/// a renamed object key is a `move`, and a key added with the same value as an
+/// unchanged sibling is a `copy` when that is shorter than the value itself.
+///
 /// # Example
 /// Diff two JSONs:
 ///
@@ -72,116 +85,39 @@ pub fn diff(from: &Value, to: &Value) -> super::Patch {
     differ.patch
 }
 
-fn diff_mut(differ: &mut PatchDiffer, from: &Value, to: &Value) {
-    match (from, to) {
-        (Value::Object(f), Value::Object(t)) if !f.ptr_eq(t) => {
-            for key in f
-                .keys()
-                .chain(t.keys())
-                .map(|k| &**k)
-                .collect::<BTreeSet<_>>()
-            {
-                differ.path.push_end(key);
-                match (f.get(key), to.get(key)) {
-                    (Some(f), Some(t)) if f != t => {
-                        diff_mut(differ, f, t);
-                    }
-                    (Some(_), None) => {
-                        differ.patch.0.push(PatchOperation::Remove(RemoveOperation {
-                            path: differ.path.clone(),
-                        }));
-                    }
-                    (None, Some(t)) => {
-                        differ.patch.0.push(PatchOperation::Add(AddOperation {
-                            path: differ.path.clone(),
-                            value: t.clone(),
-                        }));
-                    }
-                    _ => (),
-                }
-                differ.path.pop_end();
-            }
+fn same(a: &Value, b: &Value) -> bool {
+    a.ptr_eq(b) || a == b
+}
+
+/// Whether `value` would serialize to more than about `limit` bytes. Stops
+/// looking once it does.
+fn larger_than(value: &Value, limit: usize) -> bool {
+    fn exhausts(value: &Value, budget: &mut usize) -> bool {
+        let own = match value {
+            Value::Null => 4,
+            Value::Bool(b) => 4 + !b as usize,
+            Value::Number(n) => n.to_string().len(),
+            Value::String(s) => s.len() + 2,
+            Value::Array(a) => a.len() + 2,
+            Value::Object(o) => o.keys().map(|k| k.len() + 4).sum::<usize>() + 2,
+        };
+        if own > *budget {
+            return true;
         }
-        (Value::Array(f), Value::Array(t)) if !f.ptr_eq(t) => {
-            if f.len() < t.len() {
-                let mut f_idx = 0;
-                let mut t_idx = 0;
-                while t_idx < t.len() {
-                    if f_idx == f.len() {
-                        differ.patch.0.push(PatchOperation::Add(AddOperation {
-                            path: differ.path.clone().join_end_idx(t_idx),
-                            value: t[t_idx].clone(),
-                        }));
-                        t_idx += 1;
-                    } else {
-                        if !f[f_idx].ptr_eq(&t[t_idx]) {
-                            if t.iter().skip(t_idx + 1).any(|t| f[f_idx].ptr_eq(t)) {
-                                differ.patch.0.push(PatchOperation::Add(AddOperation {
-                                    path: differ.path.clone().join_end_idx(t_idx),
-                                    value: t[t_idx].clone(),
-                                }));
-                                t_idx += 1;
-                                continue;
-                            } else {
-                                differ.path.push_end_idx(t_idx);
-                                diff_mut(differ, &f[f_idx], &t[t_idx]);
-                                differ.path.pop_end();
-                            }
-                        }
-                        f_idx += 1;
-                        t_idx += 1;
-                    }
-                }
-                while f_idx < f.len() {
-                    differ.patch.0.push(PatchOperation::Remove(RemoveOperation {
-                        path: differ.path.clone().join_end_idx(t_idx),
-                    }));
-                    f_idx += 1;
-                }
-            } else if f.len() > t.len() {
-                let mut f_idx = 0;
-                let mut t_idx = 0;
-                while f_idx < f.len() {
-                    if t_idx == t.len() {
-                        differ.patch.0.push(PatchOperation::Remove(RemoveOperation {
-                            path: differ.path.clone().join_end_idx(t_idx),
-                        }));
-                        f_idx += 1;
-                    } else {
-                        if !f[f_idx].ptr_eq(&t[t_idx]) {
-                            if f.iter().skip(f_idx + 1).any(|f| t[t_idx].ptr_eq(f)) {
-                                differ.patch.0.push(PatchOperation::Remove(RemoveOperation {
-                                    path: differ.path.clone().join_end_idx(t_idx),
-                                }));
-                                f_idx += 1;
-                                continue;
-                            } else {
-                                differ.path.push_end_idx(t_idx);
-                                diff_mut(differ, &f[f_idx], &t[t_idx]);
-                                differ.path.pop_end();
-                            }
-                        }
-                        f_idx += 1;
-                        t_idx += 1;
-                    }
-                }
-                while t_idx < t.len() {
-                    differ.patch.0.push(PatchOperation::Add(AddOperation {
-                        path: differ.path.clone().join_end_idx(t_idx),
-                        value: t[t_idx].clone(),
-                    }));
-                    t_idx += 1;
-                }
-            } else {
-                for i in 0..f.len() {
-                    if !f[i].ptr_eq(&t[i]) {
-                        differ.path.push_end_idx(i);
-                        diff_mut(differ, &f[i], &t[i]);
-                        differ.path.pop_end();
-                    }
-                }
-            }
+        *budget -= own;
+        match value {
+            Value::Array(a) => a.iter().any(|v| exhausts(v, budget)),
+            Value::Object(o) => o.iter().any(|(_, v)| exhausts(v, budget)),
+            _ => false,
         }
+    }
+    exhausts(value, &mut { limit })
+}
+
+fn diff_mut(differ: &mut PatchDiffer, from: &Value, to: &Value) {
+    match (from, to) {
+        (Value::Object(f), Value::Object(t)) if !f.ptr_eq(t) => diff_object(differ, f, t),
+        (Value::Array(f), Value::Array(t)) if !f.ptr_eq(t) => diff_array(differ, f, t),
         (f, t) if f != t => differ
             .patch
             .0
@@ -193,9 +129,242 @@ fn diff_mut(differ: &mut PatchDiffer, from: &Value, to: &Value) {
     }
 }
 
+fn diff_object(
+    differ: &mut PatchDiffer,
+    f: &InOMap<InternedString, Value>,
+    t: &InOMap<InternedString, Value>,
+) {
+    // a key removed while another is added with the same value was renamed
+    let mut renamed_from = BTreeMap::new();
+    let mut renamed = BTreeSet::new();
+    for (key, value) in t.iter().filter(|(k, _)| !f.contains_key(k)) {
+        if let Some((old, _)) = f
+            .iter()
+            .find(|(k, v)| !t.contains_key(k) && !renamed.contains(&**k) && same(v, value))
+        {
+            renamed.insert(&**old);
+            renamed_from.insert(&**key, &**old);
+        }
+    }
+    let parent = differ.path.clone();
+    for key in f
+        .keys()
+        .chain(t.keys())
+        .map(|k| &**k)
+        .collect::<BTreeSet<_>>()
+    {
+        differ.path.push_end(key);
+        match (f.get(key), t.get(key)) {
+            (Some(f), Some(t)) if !same(f, t) => {
+                diff_mut(differ, f, t);
+            }
+            (Some(_), None) if !renamed.contains(key) => {
+                differ.patch.0.push(PatchOperation::Remove(RemoveOperation {
+                    path: differ.path.clone(),
+                }));
+            }
+            (None, Some(value)) => {
+                let op = if let Some(old) = renamed_from.get(key) {
+                    PatchOperation::Move(MoveOperation {
+                        from: parent.clone().join_end(old),
+                        path: differ.path.clone(),
+                    })
+                } else if let Some(from) = f
+                    .iter()
+                    .find(|(k, v)| t.get(k).is_some_and(|t| same(v, t)) && same(v, value))
+                    .map(|(k, _)| parent.clone().join_end(k))
+                    .filter(|from| {
+                        // a copy names its source where an add has the value
+                        larger_than(value, from.as_ref().len() + 2)
+                    })
+                {
+                    PatchOperation::Copy(CopyOperation {
+                        from,
+                        path: differ.path.clone(),
+                    })
+                } else {
+                    PatchOperation::Add(AddOperation {
+                        path: differ.path.clone(),
+                        value: value.clone(),
+                    })
+                };
+                differ.patch.0.push(op);
+            }
+            _ => (),
+        }
+        differ.path.pop_end();
+    }
+}
+
+/// Where the element at an index of the new array comes from
+#[derive(Clone, Copy)]
+enum Source {
+    /// Part of the common subsequence: stays where it is
+    Keep(usize),
+    /// Equal to an element elsewhere in the old array
+    Move(usize),
+    /// Diffed against the element it replaces
+    Modify(usize),
+    Insert,
+}
+
+/// A slot of the array as it is rewritten: either an element of the old array
+/// not yet accounted for, or the element of the new array it has become.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Slot {
+    Old(usize),
+    New(usize),
+}
+
+/// The index pairs of a longest common subsequence of `f` and `t`, in order.
+fn align(f: &[&Value], t: &[&Value]) -> Vec<(usize, usize)> {
+    let (n, m) = (f.len(), t.len());
+    // lengths[i][j] is the length of the LCS of f[i..] and t[j..]
+    let mut lengths = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lengths[i][j] = if same(f[i], t[j]) {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let mut pairs = Vec::with_capacity(lengths[0][0] as usize);
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if same(f[i], t[j]) {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+fn diff_array(differ: &mut PatchDiffer, f: &Vector<Value>, t: &Vector<Value>) {
+    // common ends are by far the usual case, and need no alignment
+    let prefix = f
+        .iter()
+        .zip(t.iter())
+        .take_while(|(f, t)| same(f, t))
+        .count();
+    let suffix = f
+        .iter()
+        .skip(prefix)
+        .rev()
+        .zip(t.iter().skip(prefix).rev())
+        .take_while(|(f, t)| same(f, t))
+        .count();
+    let f: Vec<_> = f
+        .iter()
+        .skip(prefix)
+        .take(f.len() - prefix - suffix)
+        .collect();
+    let t: Vec<_> = t
+        .iter()
+        .skip(prefix)
+        .take(t.len() - prefix - suffix)
+        .collect();
+    let aligned = f.len() * t.len() <= MAX_ALIGN_CELLS;
+
+    let mut sources = vec![Source::Insert; t.len()];
+    let mut used = vec![false; f.len()];
+    let anchors = if aligned { align(&f, &t) } else { Vec::new() };
+    for &(i, j) in &anchors {
+        sources[j] = Source::Keep(i);
+        used[i] = true;
+    }
+    if aligned {
+        for j in 0..t.len() {
+            if let Source::Insert = sources[j] {
+                if let Some(i) = (0..f.len()).find(|&i| !used[i] && same(f[i], t[j])) {
+                    sources[j] = Source::Move(i);
+                    used[i] = true;
+                }
+            }
+        }
+    }
+    // what is left between two anchors was changed in place, as far as it
+    // pairs up
+    let mut gap = (0, 0);
+    for &(end_i, end_j) in anchors.iter().chain([(f.len(), t.len())].iter()) {
+        let old = (gap.0..end_i).filter(|&i| !used[i]).collect::<Vec<_>>();
+        let new = (gap.1..end_j)
+            .filter(|&j| matches!(sources[j], Source::Insert))
+            .collect::<Vec<_>>();
+        for (i, j) in old.into_iter().zip(new) {
+            sources[j] = Source::Modify(i);
+            used[i] = true;
+        }
+        gap = (end_i + 1, end_j + 1);
+    }
+
+    let mut slots: Vec<_> = (0..f.len()).map(Slot::Old).collect();
+    let position = |slots: &[Slot], slot: Slot| slots.iter().position(|s| *s == slot).unwrap();
+    for i in (0..f.len()).filter(|&i| !used[i]) {
+        let idx = position(&slots, Slot::Old(i));
+        slots.remove(idx);
+        differ.patch.0.push(PatchOperation::Remove(RemoveOperation {
+            path: differ.path.clone().join_end_idx(prefix + idx),
+        }));
+    }
+    // build the new array front to back: each element goes right after the
+    // one before it, and whatever is still in the way is moved out later
+    for (j, source) in sources.into_iter().enumerate() {
+        let after_previous = |slots: &[Slot]| {
+            if j == 0 {
+                0
+            } else {
+                position(slots, Slot::New(j - 1)) + 1
+            }
+        };
+        match source {
+            Source::Keep(i) => {
+                let idx = position(&slots, Slot::Old(i));
+                slots[idx] = Slot::New(j);
+            }
+            Source::Modify(i) => {
+                let idx = position(&slots, Slot::Old(i));
+                slots[idx] = Slot::New(j);
+                differ.path.push_end_idx(prefix + idx);
+                diff_mut(differ, f[i], t[j]);
+                differ.path.pop_end();
+            }
+            Source::Move(i) => {
+                let from = position(&slots, Slot::Old(i));
+                slots.remove(from);
+                let to = after_previous(&slots);
+                slots.insert(to, Slot::New(j));
+                if from != to {
+                    differ.patch.0.push(PatchOperation::Move(MoveOperation {
+                        from: differ.path.clone().join_end_idx(prefix + from),
+                        path: differ.path.clone().join_end_idx(prefix + to),
+                    }));
+                }
+            }
+            Source::Insert => {
+                let to = after_previous(&slots);
+                slots.insert(to, Slot::New(j));
+                differ.patch.0.push(PatchOperation::Add(AddOperation {
+                    path: differ.path.clone().join_end_idx(prefix + to),
+                    value: t[j].clone(),
+                }));
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use imbl_value::Value;
+    use proptest::prelude::*;
+
+    use crate::PatchOperation;
 
     #[test]
     pub fn replace_all() {
@@ -282,6 +451,84 @@ mod tests {
         assert_eq!(left, right);
     }
 
+    #[test]
+    fn reorder_array() {
+        let left = json!([{ "port": 80 }, { "port": 443 }, { "port": 8080 }]);
+        let right = json!([{ "port": 443 }, { "port": 8080 }, { "port": 80 }]);
+        let p = super::diff(&left, &right);
+        assert_eq!(
+            p,
+            imbl_value::from_value(json!([
+                { "op": "move", "from": "/0", "path": "/2" },
+            ]))
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn insert_into_array() {
+        let left = json!(["a", "b", "c", "d"]);
+        let right = json!(["a", "x", "b", "c", "y", "d"]);
+        let p = super::diff(&left, &right);
+        assert_eq!(
+            p,
+            imbl_value::from_value(json!([
+                { "op": "add", "path": "/1", "value": "x" },
+                { "op": "add", "path": "/4", "value": "y" },
+            ]))
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn modify_in_array() {
+        let left = json!([{ "a": 1 }, { "b": 1 }, { "c": 1 }]);
+        let right = json!([{ "a": 1 }, { "b": 2 }, { "c": 1 }]);
+        let p = super::diff(&left, &right);
+        assert_eq!(
+            p,
+            imbl_value::from_value(json!([
+                { "op": "replace", "path": "/1/b", "value": 2 },
+            ]))
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn rename_key() {
+        let left = json!({ "old": { "enabled": true }, "other": 1 });
+        let right = json!({ "new": { "enabled": true }, "other": 1 });
+        let p = super::diff(&left, &right);
+        assert_eq!(
+            p,
+            imbl_value::from_value(json!([
+                { "op": "move", "from": "/old", "path": "/new" },
+            ]))
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn copy_key() {
+        let rule = json!({ "action": "accept", "source": "10.0.0.0/24", "ports": [22, 80, 443] });
+        let left = json!({ "a": rule.clone(), "n": 1 });
+        let right = json!({ "a": rule.clone(), "b": rule.clone(), "m": 1, "n": 1 });
+        let p = super::diff(&left, &right);
+        // "/m" is too short to be worth a copy
+        assert_eq!(
+            p,
+            imbl_value::from_value(json!([
+                { "op": "copy", "from": "/a", "path": "/b" },
+                { "op": "add", "path": "/m", "value": 1 },
+            ]))
+            .unwrap()
+        );
+    }
+
+    fn small_array() -> impl Strategy<Value = Value> {
+        proptest::collection::vec(0u8..6, 0..12).prop_map(|v| json!(v))
+    }
+
     proptest::proptest! {
         #[test]
         fn test_diff(mut from: Value, to: Value) {
@@ -289,5 +536,49 @@ mod tests {
             crate::patch(&mut from, &patch).unwrap();
             assert_eq!(from, to);
         }
+
+        #[test]
+        fn test_diff_arrays(mut from in small_array(), to in small_array()) {
+            let patch = super::diff(&from, &to);
+            crate::patch(&mut from, &patch).unwrap();
+            assert_eq!(from, to);
+        }
+
+        #[test]
+        fn test_diff_nested_arrays(
+            mut from in proptest::collection::vec(small_array(), 0..6).prop_map(|v| json!(v)),
+            to in proptest::collection::vec(small_array(), 0..6).prop_map(|v| json!(v)),
+        ) {
+            let patch = super::diff(&from, &to);
+            crate::patch(&mut from, &patch).unwrap();
+            assert_eq!(from, to);
+        }
+
+        #[test]
+        fn test_diff_permutation(
+            (from, to) in proptest::collection::vec(any::<Value>(), 0..12)
+                .prop_flat_map(|v| (Just(v.clone()), Just(v).prop_shuffle()))
+        ) {
+            let (mut from, to) = (json!(from), json!(to));
+            let patch = super::diff(&from, &to);
+            prop_assert!(patch.0.iter().all(|op| matches!(op, PatchOperation::Move(_))));
+            crate::patch(&mut from, &patch).unwrap();
+            assert_eq!(from, to);
+        }
+
+        #[test]
+        fn test_diff_renamed(
+            from in proptest::collection::btree_map("[a-z]{1,4}", any::<Value>(), 0..8),
+        ) {
+            let to = from
+                .iter()
+                .map(|(k, v)| (format!("{}_", k), v.clone()))
+                .collect::<std::collections::BTreeMap<_, _>>();
+            let (mut from, to) = (json!(from), json!(to));
+            let patch = super::diff(&from, &to);
+            prop_assert!(patch.0.iter().all(|op| matches!(op, PatchOperation::Move(_))));
+            crate::patch(&mut from, &patch).unwrap();
+            assert_eq!(from, to);
+        }
     }
 }
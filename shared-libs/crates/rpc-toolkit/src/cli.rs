@@ -1,7 +1,9 @@
 use std::collections::VecDeque;
 use std::ffi::OsString;
+use std::path::PathBuf;
 
-use clap::{CommandFactory, FromArgMatches};
+use clap::{ArgMatches, CommandFactory, FromArgMatches};
+use clap_complete::Shell;
 use futures::Future;
 use imbl_value::imbl::OrdMap;
 use imbl_value::Value;
@@ -23,11 +25,21 @@ type GenericRpcMethod<'a> = yajrc::GenericRpcMethod<&'a str, Value, Value>;
 type RpcRequest<'a> = yajrc::RpcRequest<GenericRpcMethod<'a>>;
 type RpcResponse<'a> = yajrc::RpcResponse<GenericRpcMethod<'static>>;
 
+pub(crate) const REPL_COMMAND: &str = "repl";
+pub(crate) const COMPLETIONS_COMMAND: &str = "completions";
+
+/// A command line app for a handler tree.
+///
+/// Besides the handlers' subcommands, it provides `repl`, an interactive
+/// shell (see [`CliApp::repl_history`]), and `completions <SHELL>`, which
+/// prints a completion script, unless the tree defines either name itself.
 pub struct CliApp<Context: crate::Context + Clone, Config: CommandFactory + FromArgMatches> {
     _phantom: PhantomData<(Context, Config)>,
     make_ctx: Box<dyn FnOnce(Config) -> Result<Context, RpcError> + Send + Sync>,
     root_handler: ParentHandler<Context>,
     mut_cmd: Option<Box<dyn FnOnce(clap::Command) -> clap::Command + Send + Sync>>,
+    history: Option<PathBuf>,
+    builtins: Vec<&'static str>,
 }
 impl<Context: crate::Context + Clone, Config: CommandFactory + FromArgMatches>
     CliApp<Context, Config>
@@ -41,8 +53,18 @@ impl<Context: crate::Context + Clone, Config: CommandFactory + FromArgMatches>
             make_ctx: Box::new(make_ctx),
             root_handler,
             mut_cmd: None,
+            history: None,
+            builtins: Vec::new(),
         }
     }
+    /// Where `repl` keeps its history between sessions. Without one, history
+    /// only lasts for the session. Each line is appended as it's entered,
+    /// except lines that set a password, passphrase, secret or token
+    /// argument, which are never recorded.
+    pub fn repl_history(mut self, path: impl Into<PathBuf>) -> Self {
+        self.history = Some(path.into());
+        self
+    }
     pub fn mutate_command(
         mut self,
         f: impl FnOnce(clap::Command) -> clap::Command + Send + Sync + 'static,
@@ -61,6 +83,27 @@ impl<Context: crate::Context + Clone, Config: CommandFactory + FromArgMatches>
                 cmd = cmd.subcommand(cli.cli_command().name(name));
             }
         }
+        for (name, builtin) in [
+            (
+                REPL_COMMAND,
+                clap::Command::new(REPL_COMMAND).about("Start an interactive shell"),
+            ),
+            (
+                COMPLETIONS_COMMAND,
+                clap::Command::new(COMPLETIONS_COMMAND)
+                    .about("Print a completion script for a shell")
+                    .arg(
+                        clap::Arg::new("shell")
+                            .required(true)
+                            .value_parser(clap::value_parser!(Shell)),
+                    ),
+            ),
+        ] {
+            if cmd.find_subcommand(name).is_none() {
+                cmd = cmd.subcommand(builtin);
+                self.builtins.push(name);
+            }
+        }
         if let Some(f) = self.mut_cmd.take() {
             cmd = f(cmd);
         }
@@ -70,31 +113,58 @@ impl<Context: crate::Context + Clone, Config: CommandFactory + FromArgMatches>
         self.command()
     }
     pub fn run(mut self, args: impl IntoIterator<Item = OsString>) -> Result<(), RpcError> {
-        let cmd = self.command();
-        let matches = cmd.get_matches_from(args);
+        let mut cmd = self.command();
+        let matches = cmd.clone().get_matches_from(args);
+        let builtin = matches
+            .subcommand_name()
+            .and_then(|name| self.builtins.iter().find(|b| **b == name).copied());
+        if let (Some(COMPLETIONS_COMMAND), Some((_, sub))) = (builtin, matches.subcommand()) {
+            if let Some(shell) = sub.get_one::<Shell>("shell").copied() {
+                let name = cmd.get_name().to_owned();
+                clap_complete::generate(shell, &mut cmd, name, &mut std::io::stdout());
+            }
+            return Ok(());
+        }
         let config = Config::from_arg_matches(&matches)?;
         let ctx = (self.make_ctx)(config)?;
         let root_handler = AnyHandler::new(self.root_handler);
-        let (method, params) = root_handler.cli_parse(&matches)?;
-        let res = root_handler.handle_sync(HandleAnyArgs {
-            context: ctx.clone(),
+        if builtin == Some(REPL_COMMAND) {
+            return crate::repl::repl(
+                &root_handler,
+                cmd,
+                &self.builtins,
+                ctx,
+                self.history.as_deref(),
+            );
+        }
+        call_cli(&root_handler, ctx, &matches)
+    }
+}
+
+pub(crate) fn call_cli<Context: crate::Context + Clone>(
+    root_handler: &AnyHandler<Context, Empty, ParentHandler<Context>>,
+    ctx: Context,
+    matches: &ArgMatches,
+) -> Result<(), RpcError> {
+    let (method, params) = root_handler.cli_parse(matches)?;
+    let res = root_handler.handle_sync(HandleAnyArgs {
+        context: ctx.clone(),
+        parent_method: VecDeque::new(),
+        method: method.clone(),
+        params: params.clone(),
+        inherited: crate::Empty {},
+    })?;
+    root_handler.cli_display(
+        HandleAnyArgs {
+            context: ctx,
             parent_method: VecDeque::new(),
-            method: method.clone(),
-            params: params.clone(),
+            method,
+            params,
             inherited: crate::Empty {},
-        })?;
-        root_handler.cli_display(
-            HandleAnyArgs {
-                context: ctx,
-                parent_method: VecDeque::new(),
-                method,
-                params,
-                inherited: crate::Empty {},
-            },
-            res,
-        )?;
-        Ok(())
-    }
+        },
+        res,
+    )?;
+    Ok(())
 }
 
 pub trait CallRemote<RemoteContext, Extra = Empty>: crate::Context {
@@ -288,6 +358,9 @@ where
     fn method_from_dots(&self, method: &str) -> Option<VecDeque<&'static str>> {
         self.handler.method_from_dots(method)
     }
+    fn methods(&self) -> Vec<VecDeque<&'static str>> {
+        self.handler.methods()
+    }
 }
 impl<Context, RemoteContext, RemoteHandler, Extra> PrintCliResult<Context>
     for CallRemoteHandler<Context, RemoteContext, RemoteHandler, Extra>
@@ -0,0 +1,382 @@
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+
+use futures::{SinkExt, Stream, StreamExt};
+use imbl_value::imbl::OrdMap;
+use imbl_value::{InternedString, Value};
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use yajrc::{Id, RpcError};
+
+use crate::server::{GenericRpcMethod, RpcRequest, RpcResponse};
+use crate::util::internal_error;
+use crate::{CallRemote, Empty, SUBSCRIPTION_METHOD, UNSUBSCRIBE_METHOD};
+
+/// How many outgoing messages the client queues before calls wait for the
+/// socket.
+const OUTBOUND_BUFFER: usize = 64;
+/// How many items of a subscription the client holds until they are read.
+/// A subscription that falls further behind is cancelled, see
+/// [`WsSubscription::lagged`].
+pub const SUBSCRIPTION_BUFFER: usize = 64;
+
+/// A notification pushed by the server, other than a subscription item.
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub method: InternedString,
+    pub params: Value,
+}
+
+struct Subscriber {
+    send: mpsc::Sender<Value>,
+    lagged: Arc<AtomicBool>,
+}
+
+struct Call {
+    res: oneshot::Sender<Result<Value, RpcError>>,
+    subscription: Option<Subscriber>,
+}
+
+#[derive(Default)]
+struct State {
+    closed: bool,
+    calls: BTreeMap<u64, Call>,
+    subscriptions: BTreeMap<u64, Subscriber>,
+}
+
+struct Shared {
+    send: mpsc::Sender<Message>,
+    next_id: AtomicU64,
+    state: Mutex<State>,
+    notifications: broadcast::Sender<Notification>,
+}
+impl Shared {
+    async fn send<T: Serialize>(&self, msg: &T) -> Result<(), RpcError> {
+        let text = serde_json::to_string(msg).map_err(internal_error)?;
+        self.send
+            .send(Message::Text(text.into()))
+            .await
+            .map_err(|_| connection_closed())
+    }
+    /// Tells the server to end subscription `id`, unless the outgoing queue
+    /// is full. Never waits, so that it can be called from the reader and
+    /// from `Drop`.
+    fn unsubscribe(&self, id: u64) {
+        let req = RpcRequest {
+            id: None,
+            method: GenericRpcMethod::new(InternedString::intern(UNSUBSCRIBE_METHOD)),
+            params: imbl_value::json!({ "subscription": id }),
+        };
+        if let Ok(text) = serde_json::to_string(&req) {
+            self.send.try_send(Message::Text(text.into())).ok();
+        }
+    }
+    fn receive(&self, msg: Value) {
+        if let Value::Array(batch) = msg {
+            for msg in batch {
+                self.receive(msg);
+            }
+            return;
+        }
+        if let Some(method) = msg["method"].as_str() {
+            if method == SUBSCRIPTION_METHOD {
+                let params = &msg["params"];
+                let mut state = self.state.lock().unwrap();
+                if let Some(id) = params["subscription"].as_u64() {
+                    let ended = match state.subscriptions.get(&id) {
+                        Some(_) if params["end"].as_bool() == Some(true) => true,
+                        Some(sub) => match sub.send.try_send(params["result"].clone()) {
+                            Ok(()) => false,
+                            Err(TrySendError::Full(_)) => {
+                                sub.lagged.store(true, Ordering::SeqCst);
+                                self.unsubscribe(id);
+                                true
+                            }
+                            Err(TrySendError::Closed(_)) => true,
+                        },
+                        None => false,
+                    };
+                    if ended {
+                        state.subscriptions.remove(&id);
+                    }
+                }
+            } else {
+                self.notifications
+                    .send(Notification {
+                        method: InternedString::intern(method),
+                        params: msg["params"].clone(),
+                    })
+                    .ok();
+            }
+            return;
+        }
+        let id = msg["id"].as_u64();
+        let res = match imbl_value::from_value::<RpcResponse>(msg) {
+            Ok(res) => res.result,
+            Err(e) => Err(internal_error(e)),
+        };
+        let mut state = self.state.lock().unwrap();
+        if let Some(call) = id.and_then(|id| state.calls.remove(&id)) {
+            // registered before the call completes, so that no item sent
+            // right after the response is missed
+            if let (Some(sub), Ok(sub_id)) = (call.subscription, &res) {
+                if let Some(sub_id) = sub_id.as_u64() {
+                    state.subscriptions.insert(sub_id, sub);
+                }
+            }
+            call.res.send(res).ok();
+        }
+    }
+    fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        for (_, call) in std::mem::take(&mut state.calls) {
+            call.res.send(Err(connection_closed())).ok();
+        }
+        state.subscriptions.clear();
+    }
+}
+
+fn connection_closed() -> RpcError {
+    internal_error("websocket connection closed")
+}
+
+/// A JSON-RPC client for [`HttpServer::handle_ws`](crate::HttpServer::handle_ws).
+///
+/// Any number of calls and subscriptions share the one connection. Clones
+/// share it too. It is a [`CallRemote`] context itself, so handlers made with
+/// `with_call_remote::<WsClient>()` forward their calls over the connection.
+///
+/// Outgoing messages are queued up to a fixed bound, beyond which calls wait
+/// for the socket.
+#[derive(Clone)]
+pub struct WsClient(Arc<Shared>);
+impl WsClient {
+    pub async fn connect(request: impl IntoClientRequest + Unpin) -> Result<Self, RpcError> {
+        let (socket, _) = tokio_tungstenite::connect_async(request)
+            .await
+            .map_err(internal_error)?;
+        Ok(Self::new(socket))
+    }
+    /// Runs the client over an already established websocket, e.g. one that
+    /// needs a custom TLS setup.
+    pub fn new<S>(socket: WebSocketStream<S>) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (mut sink, mut stream) = socket.split();
+        let (send, mut recv) = mpsc::channel(OUTBOUND_BUFFER);
+        let shared = Arc::new(Shared {
+            send,
+            next_id: AtomicU64::new(0),
+            state: Mutex::new(State::default()),
+            notifications: broadcast::channel(32).0,
+        });
+        tokio::spawn(async move {
+            while let Some(msg) = recv.recv().await {
+                if sink.send(msg).await.is_err() {
+                    break;
+                }
+            }
+            sink.close().await.ok();
+        });
+        let reader = Arc::downgrade(&shared);
+        tokio::spawn(async move {
+            while let Some(Ok(msg)) = stream.next().await {
+                let msg = match &msg {
+                    Message::Text(text) => serde_json::from_str::<Value>(text.as_str()),
+                    Message::Binary(bin) => serde_json::from_slice::<Value>(bin),
+                    Message::Close(_) => break,
+                    _ => continue,
+                };
+                match (reader.upgrade(), msg) {
+                    (Some(shared), Ok(msg)) => shared.receive(msg),
+                    (Some(_), Err(_)) => (),
+                    (None, _) => return,
+                }
+            }
+            if let Some(shared) = reader.upgrade() {
+                shared.close();
+            }
+        });
+        Self(shared)
+    }
+    async fn request(
+        &self,
+        method: &str,
+        params: Value,
+        subscription: Option<Subscriber>,
+    ) -> Result<Value, RpcError> {
+        let id = self.0.next_id.fetch_add(1, Ordering::SeqCst);
+        let (res, recv) = oneshot::channel();
+        {
+            let mut state = self.0.state.lock().unwrap();
+            if state.closed {
+                return Err(connection_closed());
+            }
+            state.calls.insert(id, Call { res, subscription });
+        }
+        let req = RpcRequest {
+            id: Some(Id::Number(id.into())),
+            method: GenericRpcMethod::new(InternedString::intern(method)),
+            params,
+        };
+        if let Err(e) = self.0.send(&req).await {
+            self.0.state.lock().unwrap().calls.remove(&id);
+            return Err(e);
+        }
+        recv.await.unwrap_or_else(|_| Err(connection_closed()))
+    }
+    pub async fn call(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        self.request(method, params, None).await
+    }
+    /// Sends `calls` as one JSON-RPC batch and returns their results in the
+    /// same order. The server runs the calls of a batch concurrently.
+    pub async fn batch<'a>(
+        &self,
+        calls: impl IntoIterator<Item = (&'a str, Value)>,
+    ) -> Result<Vec<Result<Value, RpcError>>, RpcError> {
+        let calls: Vec<_> = calls.into_iter().collect();
+        if calls.is_empty() {
+            // an empty array is not a valid batch
+            return Ok(Vec::new());
+        }
+        let mut ids = Vec::with_capacity(calls.len());
+        let mut results = Vec::with_capacity(calls.len());
+        let mut reqs = Vec::with_capacity(calls.len());
+        {
+            let mut state = self.0.state.lock().unwrap();
+            if state.closed {
+                return Err(connection_closed());
+            }
+            for (method, params) in calls {
+                let id = self.0.next_id.fetch_add(1, Ordering::SeqCst);
+                let (res, recv) = oneshot::channel();
+                state.calls.insert(
+                    id,
+                    Call {
+                        res,
+                        subscription: None,
+                    },
+                );
+                ids.push(id);
+                results.push(recv);
+                reqs.push(RpcRequest {
+                    id: Some(Id::Number(id.into())),
+                    method: GenericRpcMethod::new(InternedString::intern(method)),
+                    params,
+                });
+            }
+        }
+        if let Err(e) = self.0.send(&reqs).await {
+            let mut state = self.0.state.lock().unwrap();
+            for id in ids {
+                state.calls.remove(&id);
+            }
+            return Err(e);
+        }
+        Ok(futures::future::join_all(results)
+            .await
+            .into_iter()
+            .map(|res| res.unwrap_or_else(|_| Err(connection_closed())))
+            .collect())
+    }
+    /// Calls a method that returns a subscription id, and streams the items
+    /// the server sends for it. Dropping the stream unsubscribes.
+    pub async fn subscribe(&self, method: &str, params: Value) -> Result<WsSubscription, RpcError> {
+        let (send, recv) = mpsc::channel(SUBSCRIPTION_BUFFER);
+        let lagged = Arc::new(AtomicBool::new(false));
+        let subscriber = Subscriber {
+            send,
+            lagged: lagged.clone(),
+        };
+        let id = self.request(method, params, Some(subscriber)).await?;
+        let id = id
+            .as_u64()
+            .ok_or_else(|| internal_error(format!("{} did not return a subscription", method)))?;
+        Ok(WsSubscription {
+            client: self.clone(),
+            id,
+            recv,
+            lagged,
+        })
+    }
+    /// Sends a notification: a call without a response.
+    pub async fn notify(&self, method: &str, params: Value) -> Result<(), RpcError> {
+        let req = RpcRequest {
+            id: None,
+            method: GenericRpcMethod::new(InternedString::intern(method)),
+            params,
+        };
+        self.0.send(&req).await
+    }
+    /// Notifications sent outside of a subscription, from now on.
+    pub fn notifications(&self) -> broadcast::Receiver<Notification> {
+        self.0.notifications.subscribe()
+    }
+}
+
+impl crate::Context for WsClient {}
+impl<RemoteContext> CallRemote<RemoteContext> for WsClient {
+    async fn call_remote(
+        &self,
+        method: &str,
+        _: OrdMap<&'static str, Value>,
+        params: Value,
+        _: Empty,
+    ) -> Result<Value, RpcError> {
+        self.call(method, params).await
+    }
+}
+
+/// The items of a subscription made with [`WsClient::subscribe`]. Ends when
+/// the server ends it, the connection closes, or it lags.
+pub struct WsSubscription {
+    client: WsClient,
+    id: u64,
+    recv: mpsc::Receiver<Value>,
+    lagged: Arc<AtomicBool>,
+}
+impl WsSubscription {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+    /// Whether the client cancelled the subscription because more than
+    /// [`SUBSCRIPTION_BUFFER`] of its items were left unread, so that later
+    /// ones were lost.
+    pub fn lagged(&self) -> bool {
+        self.lagged.load(Ordering::SeqCst)
+    }
+}
+impl Stream for WsSubscription {
+    type Item = Value;
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        self.recv.poll_recv(cx)
+    }
+}
+impl Drop for WsSubscription {
+    fn drop(&mut self) {
+        let shared = &self.client.0;
+        if shared
+            .state
+            .lock()
+            .unwrap()
+            .subscriptions
+            .remove(&self.id)
+            .is_some()
+        {
+            shared.unsubscribe(self.id);
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use std::any::TypeId;
 use std::collections::VecDeque;
 use std::fmt::Debug;
+use std::sync::Arc;
 
 use clap::builder::{IntoResettable, StyledStr};
 use clap::{CommandFactory, FromArgMatches};
@@ -201,6 +202,9 @@ where
     fn method_from_dots(&self, method: &str) -> Option<VecDeque<&'static str>> {
         self.0.method_from_dots(method)
     }
+    fn methods(&self) -> Vec<VecDeque<&'static str>> {
+        self.0.methods()
+    }
 }
 impl<Context, H> CliBindings<Context> for NoCli<H>
 where
@@ -296,6 +300,9 @@ where
     fn method_from_dots(&self, method: &str) -> Option<VecDeque<&'static str>> {
         self.0.method_from_dots(method)
     }
+    fn methods(&self) -> Vec<VecDeque<&'static str>> {
+        self.0.methods()
+    }
 }
 impl<Context, H> PrintCliResult<Context> for NoDisplay<H>
 where
@@ -420,6 +427,9 @@ where
     fn method_from_dots(&self, method: &str) -> Option<VecDeque<&'static str>> {
         self.handler.method_from_dots(method)
     }
+    fn methods(&self) -> Vec<VecDeque<&'static str>> {
+        self.handler.methods()
+    }
 }
 impl<Context, P, H> PrintCliResult<Context> for CustomDisplay<P, H>
 where
@@ -593,6 +603,9 @@ where
     fn method_from_dots(&self, method: &str) -> Option<VecDeque<&'static str>> {
         self.handler.method_from_dots(method)
     }
+    fn methods(&self) -> Vec<VecDeque<&'static str>> {
+        self.handler.methods()
+    }
 }
 impl<F, H, Context> PrintCliResult<Context> for CustomDisplayFn<F, H, Context>
 where
@@ -821,6 +834,9 @@ where
     fn method_from_dots(&self, method: &str) -> Option<VecDeque<&'static str>> {
         self.handler.method_from_dots(method)
     }
+    fn methods(&self) -> Vec<VecDeque<&'static str>> {
+        self.handler.methods()
+    }
 }
 
 impl<Context, Params, InheritedParams, H, F> CliBindings<Context>
@@ -898,7 +914,7 @@ impl<Context, M, H> HandlerFor<Context> for WithAbout<M, H>
 where
     Context: crate::Context,
     H: HandlerFor<Context>,
-    M: Clone + Send + Sync + 'static,
+    M: IntoResettable<StyledStr> + Clone + Send + Sync + 'static,
 {
     fn handle_sync(
         &self,
@@ -942,12 +958,24 @@ where
             })
             .await
     }
+    /// Also exposes the message as `about`, for
+    /// [`OpenRpcDocument`](crate::OpenRpcDocument).
     fn metadata(&self, method: VecDeque<&'static str>) -> OrdMap<&'static str, Value> {
-        self.handler.metadata(method)
+        let is_self = method.is_empty();
+        let mut metadata = self.handler.metadata(method);
+        if is_self {
+            if let Some(about) = self.message.clone().into_resettable().into_option() {
+                metadata.insert("about", Value::String(Arc::new(about.to_string())));
+            }
+        }
+        metadata
     }
     fn method_from_dots(&self, method: &str) -> Option<VecDeque<&'static str>> {
         self.handler.method_from_dots(method)
     }
+    fn methods(&self) -> Vec<VecDeque<&'static str>> {
+        self.handler.methods()
+    }
 }
 impl<Context, M, H> CliBindings<Context> for WithAbout<M, H>
 where
@@ -1048,6 +1076,9 @@ where
     fn method_from_dots(&self, method: &str) -> Option<VecDeque<&'static str>> {
         self.0.method_from_dots(method)
     }
+    fn methods(&self) -> Vec<VecDeque<&'static str>> {
+        self.0.methods()
+    }
 }
 
 impl<Context, H> CliBindings<Context> for NoTS<H>
@@ -1148,6 +1179,9 @@ where
     fn method_from_dots(&self, method: &str) -> Option<VecDeque<&'static str>> {
         self.0.method_from_dots(method)
     }
+    fn methods(&self) -> Vec<VecDeque<&'static str>> {
+        self.0.methods()
+    }
 }
 
 impl<Context, H> CliBindings<Context> for UnknownTS<H>
@@ -1255,6 +1289,9 @@ where
     fn method_from_dots(&self, method: &str) -> Option<VecDeque<&'static str>> {
         self.handler.method_from_dots(method)
     }
+    fn methods(&self) -> Vec<VecDeque<&'static str>> {
+        self.handler.methods()
+    }
 }
 
 impl<Context, H> CliBindings<Context> for CustomTS<H>
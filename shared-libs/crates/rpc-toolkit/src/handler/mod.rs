@@ -84,6 +84,7 @@ pub(crate) trait HandleAny<Context>: HandleAnyRequires {
     ) -> Result<Value, RpcError>;
     fn metadata(&self, method: VecDeque<&'static str>) -> OrdMap<&'static str, Value>;
     fn method_from_dots(&self, method: &str) -> Option<VecDeque<&'static str>>;
+    fn methods(&self) -> Vec<VecDeque<&'static str>>;
     fn cli(&self) -> Option<&dyn CliBindingsAny<Context, Inherited = Self::Inherited>>;
 }
 #[async_trait::async_trait]
@@ -107,6 +108,9 @@ impl<Context: crate::Context, T: HandleAny<Context>> HandleAny<Context> for Arc<
     fn method_from_dots(&self, method: &str) -> Option<VecDeque<&'static str>> {
         self.deref().method_from_dots(method)
     }
+    fn methods(&self) -> Vec<VecDeque<&'static str>> {
+        self.deref().methods()
+    }
     fn cli(&self) -> Option<&dyn CliBindingsAny<Context, Inherited = Self::Inherited>> {
         self.deref().cli()
     }
@@ -197,6 +201,9 @@ impl<Context: crate::Context, Inherited: Send> HandleAny<Context>
     fn method_from_dots(&self, method: &str) -> Option<VecDeque<&'static str>> {
         self.0.method_from_dots(method)
     }
+    fn methods(&self) -> Vec<VecDeque<&'static str>> {
+        self.0.methods()
+    }
     fn cli(&self) -> Option<&dyn CliBindingsAny<Context, Inherited = Self::Inherited>> {
         self.0.cli()
     }
@@ -292,6 +299,11 @@ pub trait HandlerFor<Context: crate::Context>: HandlerRequires {
             None
         }
     }
+    /// Every method this handler serves, relative to it. A leaf serves only
+    /// itself.
+    fn methods(&self) -> Vec<VecDeque<&'static str>> {
+        vec![VecDeque::new()]
+    }
 }
 
 pub trait Handler<Inherited> {
@@ -406,6 +418,9 @@ where
     fn method_from_dots(&self, method: &str) -> Option<VecDeque<&'static str>> {
         self.handler.method_from_dots(method)
     }
+    fn methods(&self) -> Vec<VecDeque<&'static str>> {
+        self.handler.methods()
+    }
     fn cli(&self) -> Option<&dyn CliBindingsAny<Context, Inherited = Self::Inherited>> {
         if H::NO_CLI {
             None
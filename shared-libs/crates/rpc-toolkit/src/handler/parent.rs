@@ -340,6 +340,20 @@ where
             Some(res)
         }
     }
+    fn methods(&self) -> Vec<VecDeque<&'static str>> {
+        let mut res = self
+            .subcommands
+            .get_root()
+            .map(|h| h.methods())
+            .unwrap_or_default();
+        for (Name(name), handler) in &self.subcommands.1 {
+            for mut method in handler.methods() {
+                method.push_front(*name);
+                res.push(method);
+            }
+        }
+        res
+    }
 }
 
 impl<Context, Params, InheritedParams> CliBindings<Context>
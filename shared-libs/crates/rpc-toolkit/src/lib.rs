@@ -1,9 +1,11 @@
 pub use clap;
 pub use cli::*;
+pub use client::*;
 // pub use command::*;
 pub use context::*;
 pub use futures;
 pub use handler::*;
+pub use openrpc::*;
 pub use reqwest;
 pub use serde;
 pub use serde_json;
@@ -13,9 +15,12 @@ pub use url;
 pub use yajrc;
 
 mod cli;
+mod client;
 pub mod command_helpers;
 mod context;
 mod handler;
+mod openrpc;
+mod repl;
 mod server;
 pub mod util;
 
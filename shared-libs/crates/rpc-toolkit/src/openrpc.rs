@@ -0,0 +1,420 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+
+use imbl_value::{json, InOMap, InternedString, Value};
+use serde::{Deserialize, Serialize};
+
+use crate::handler::HandleAnyTS;
+use crate::HandleAny;
+
+/// The method [`Server`](crate::Server) answers with its [`OpenRpcDocument`].
+pub const DISCOVER_METHOD: &str = "rpc.discover";
+
+const OPENRPC_VERSION: &str = "1.3.2";
+
+/// An [OpenRPC](https://spec.open-rpc.org) description of a handler tree.
+///
+/// Every method is listed by its dotted name. Its `with_about` message is the
+/// summary, and the rest of its metadata is kept as `x-` extensions, e.g.
+/// `x-authenticated`. Params and result schemas are derived from the
+/// handlers' type info, so they are only precise under the `ts-rs` feature,
+/// and accept anything otherwise.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenRpcDocument {
+    pub openrpc: String,
+    pub info: OpenRpcInfo,
+    pub methods: Vec<OpenRpcMethod>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenRpcInfo {
+    pub title: String,
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+impl OpenRpcInfo {
+    pub fn new(title: impl Into<String>, version: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            version: version.into(),
+            description: None,
+        }
+    }
+}
+impl Default for OpenRpcInfo {
+    fn default() -> Self {
+        Self::new("JSON-RPC", "0.0.0")
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenRpcMethod {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    pub param_structure: String,
+    pub params: Vec<ContentDescriptor>,
+    pub result: ContentDescriptor,
+    #[serde(flatten)]
+    pub extensions: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContentDescriptor {
+    pub name: String,
+    #[serde(default)]
+    pub required: bool,
+    pub schema: Value,
+}
+
+impl OpenRpcDocument {
+    pub(crate) fn new<Context, H>(root: &H, info: OpenRpcInfo) -> Self
+    where
+        Context: crate::Context,
+        H: HandleAny<Context>,
+    {
+        let types = root
+            .type_info()
+            .and_then(|ts| TsParser::new(&ts).parse_all());
+        let methods = root
+            .methods()
+            .into_iter()
+            .filter(|method| !method.is_empty())
+            .map(|method| {
+                let name = method.iter().copied().collect::<Vec<_>>().join(".");
+                let (params, result) = match &types {
+                    Some(types) => method_types(types, &method),
+                    None => (Vec::new(), Value::Null),
+                };
+                let mut metadata = root.metadata(method);
+                let summary = metadata
+                    .remove("about")
+                    .and_then(|about| about.as_str().map(|s| s.to_owned()));
+                OpenRpcMethod {
+                    name,
+                    summary,
+                    param_structure: "by-name".into(),
+                    params: content_descriptors(params),
+                    result: ContentDescriptor {
+                        name: "result".into(),
+                        required: false,
+                        schema: match result {
+                            Value::Null => json!({}),
+                            result => result,
+                        },
+                    },
+                    extensions: metadata
+                        .into_iter()
+                        .map(|(key, value)| (format!("x-{}", key.replace('_', "-")), value))
+                        .collect(),
+                }
+            })
+            .collect();
+        OpenRpcDocument {
+            openrpc: OPENRPC_VERSION.into(),
+            info,
+            methods,
+        }
+    }
+}
+
+/// The `_PARAMS` of every handler along `method`, and the `_RETURN` of the
+/// last, from the type info of the root.
+fn method_types(root: &Value, method: &VecDeque<&'static str>) -> (Vec<Value>, Value) {
+    let mut params = Vec::new();
+    let mut node = root;
+    if let Some(p) = property(node, "_PARAMS") {
+        params.push(p.clone());
+    }
+    for name in method {
+        match property(node, "_CHILDREN").and_then(|c| property(c, name)) {
+            Some(child) => node = child,
+            // no type info, e.g. `no_ts`
+            None => return (Vec::new(), Value::Null),
+        }
+        if let Some(p) = property(node, "_PARAMS") {
+            if !params.contains(p) {
+                params.push(p.clone());
+            }
+        }
+    }
+    (
+        params,
+        property(node, "_RETURN").cloned().unwrap_or_default(),
+    )
+}
+
+fn property<'a>(schema: &'a Value, key: &str) -> Option<&'a Value> {
+    if let Some(p) = schema["properties"].as_object().and_then(|p| p.get(key)) {
+        return Some(p);
+    }
+    schema["allOf"]
+        .as_array()?
+        .iter()
+        .find_map(|s| property(s, key))
+}
+
+/// One descriptor per property of the params objects. Params that are not
+/// object literals, e.g. named types, become a single `params` descriptor.
+fn content_descriptors(params: Vec<Value>) -> Vec<ContentDescriptor> {
+    if params.is_empty() {
+        return Vec::new();
+    }
+    let is_object = |p: &Value| p["type"].as_str() == Some("object") && p["properties"].is_object();
+    if !params.iter().all(is_object) {
+        return vec![ContentDescriptor {
+            name: "params".into(),
+            required: true,
+            schema: if params.len() == 1 {
+                params.into_iter().next().unwrap_or_default()
+            } else {
+                json!({ "allOf": params })
+            },
+        }];
+    }
+    let mut res = Vec::new();
+    for p in &params {
+        let required = p["required"].as_array().cloned().unwrap_or_default();
+        if let Some(props) = p["properties"].as_object() {
+            for (name, schema) in props {
+                res.push(ContentDescriptor {
+                    name: name.to_string(),
+                    required: required.iter().any(|r| r.as_str() == Some(&**name)),
+                    schema: schema.clone(),
+                });
+            }
+        }
+    }
+    res
+}
+
+fn string(s: &str) -> Value {
+    Value::String(Arc::new(s.to_owned()))
+}
+
+/// Converts the TypeScript type expressions that ts-rs generates into JSON
+/// Schema. Named types are not resolved: they only keep their name as the
+/// `title` of a schema that accepts anything.
+struct TsParser<'a> {
+    src: &'a str,
+}
+impl<'a> TsParser<'a> {
+    fn new(src: &'a str) -> Self {
+        Self { src }
+    }
+    fn parse_all(mut self) -> Option<Value> {
+        let res = self.parse_type()?;
+        self.skip_ws();
+        if self.src.is_empty() {
+            Some(res)
+        } else {
+            None
+        }
+    }
+    fn skip_ws(&mut self) {
+        loop {
+            self.src = self.src.trim_start();
+            if let Some(rest) = self.src.strip_prefix("/*") {
+                self.src = rest.find("*/").map_or("", |end| &rest[end + 2..]);
+            } else if let Some(rest) = self.src.strip_prefix("//") {
+                self.src = rest.find('\n').map_or("", |end| &rest[end..]);
+            } else {
+                return;
+            }
+        }
+    }
+    fn peek(&mut self) -> Option<char> {
+        self.skip_ws();
+        self.src.chars().next()
+    }
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.src = &self.src[c.len_utf8()..];
+            true
+        } else {
+            false
+        }
+    }
+    fn ident(&mut self) -> Option<&'a str> {
+        self.skip_ws();
+        let end = self
+            .src
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$'))
+            .unwrap_or(self.src.len());
+        if end == 0 {
+            return None;
+        }
+        let (ident, rest) = self.src.split_at(end);
+        self.src = rest;
+        Some(ident)
+    }
+    fn string_literal(&mut self) -> Option<String> {
+        let quote = self.peek().filter(|c| *c == '"' || *c == '\'')?;
+        let mut res = String::new();
+        let mut chars = self.src[1..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => res.push(chars.next()?.1),
+                c if c == quote => {
+                    self.src = &self.src[i + 2..];
+                    return Some(res);
+                }
+                c => res.push(c),
+            }
+        }
+        None
+    }
+    fn number_literal(&mut self) -> Option<Value> {
+        self.skip_ws();
+        let end = self
+            .src
+            .find(|c: char| !(c.is_ascii_digit() || "-+.eE".contains(c)))
+            .unwrap_or(self.src.len());
+        let n = serde_json::from_str::<serde_json::Number>(&self.src[..end]).ok()?;
+        self.src = &self.src[end..];
+        imbl_value::to_value(&n).ok()
+    }
+    fn parse_type(&mut self) -> Option<Value> {
+        self.eat('|');
+        let mut variants = vec![self.parse_intersection()?];
+        while self.eat('|') {
+            variants.push(self.parse_intersection()?);
+        }
+        Some(compound("anyOf", variants))
+    }
+    fn parse_intersection(&mut self) -> Option<Value> {
+        let mut parts = vec![self.parse_postfix()?];
+        while self.eat('&') {
+            parts.push(self.parse_postfix()?);
+        }
+        Some(compound("allOf", parts))
+    }
+    fn parse_postfix(&mut self) -> Option<Value> {
+        let mut res = self.parse_primary()?;
+        while self.src.trim_start().starts_with("[]") {
+            self.eat('[');
+            self.eat(']');
+            res = json!({ "type": "array", "items": res });
+        }
+        Some(res)
+    }
+    fn parse_primary(&mut self) -> Option<Value> {
+        match self.peek()? {
+            '(' => {
+                self.eat('(');
+                let res = self.parse_type()?;
+                self.eat(')').then_some(res)
+            }
+            '{' => self.parse_object(),
+            '[' => {
+                self.eat('[');
+                let mut items = Vec::new();
+                while !self.eat(']') {
+                    items.push(self.parse_type()?);
+                    self.eat(',');
+                }
+                let len = items.len();
+                Some(json!({
+                    "type": "array",
+                    "prefixItems": items,
+                    "minItems": len,
+                    "maxItems": len,
+                }))
+            }
+            '"' | '\'' => {
+                let s = self.string_literal()?;
+                Some(json!({ "const": s }))
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                let n = self.number_literal()?;
+                Some(json!({ "const": n }))
+            }
+            _ => {
+                let ident = self.ident()?;
+                let mut args = Vec::new();
+                if self.eat('<') {
+                    while !self.eat('>') {
+                        args.push(self.parse_type()?);
+                        self.eat(',');
+                    }
+                }
+                Some(match (ident, args.as_slice()) {
+                    ("string", _) => json!({ "type": "string" }),
+                    ("number", _) => json!({ "type": "number" }),
+                    ("bigint", _) => json!({ "type": "integer" }),
+                    ("boolean", _) => json!({ "type": "boolean" }),
+                    ("true", _) => json!({ "const": true }),
+                    ("false", _) => json!({ "const": false }),
+                    ("null" | "undefined" | "void", _) => json!({ "type": "null" }),
+                    ("unknown" | "any" | "object", _) => json!({}),
+                    ("never", _) => json!({ "not": {} }),
+                    ("Array", [items]) => json!({ "type": "array", "items": items }),
+                    ("Record", [_, value]) => {
+                        json!({ "type": "object", "additionalProperties": value })
+                    }
+                    (name, _) => json!({ "title": name }),
+                })
+            }
+        }
+    }
+    fn parse_object(&mut self) -> Option<Value> {
+        self.eat('{');
+        let mut properties = InOMap::new();
+        let mut required = Vec::new();
+        let mut additional = None;
+        while !self.eat('}') {
+            if self.eat('[') {
+                // index signature: `[key: K]` or `[key in K]`
+                self.ident()?;
+                if !self.eat(':') {
+                    (self.ident()? == "in").then_some(())?;
+                }
+                self.parse_type()?;
+                self.eat(']').then_some(())?;
+                self.eat('?');
+                self.eat(':').then_some(())?;
+                additional = Some(self.parse_type()?);
+            } else {
+                let key = match self.peek()? {
+                    '"' | '\'' => self.string_literal()?,
+                    _ => self.ident()?.to_owned(),
+                };
+                let optional = self.eat('?');
+                self.eat(':').then_some(())?;
+                let schema = self.parse_type()?;
+                if !optional {
+                    required.push(string(&key));
+                }
+                properties.insert(InternedString::intern(key), schema);
+            }
+            if !self.eat(',') {
+                self.eat(';');
+            }
+        }
+        let mut res = json!({ "type": "object", "properties": Value::Object(properties) });
+        if !required.is_empty() {
+            res["required"] = Value::Array(required.into());
+        }
+        if let Some(additional) = additional {
+            res["additionalProperties"] = additional;
+        }
+        Some(res)
+    }
+}
+
+fn compound(keyword: &str, mut schemas: Vec<Value>) -> Value {
+    if schemas.len() == 1 {
+        return schemas.pop().unwrap_or_default();
+    }
+    let mut res = InOMap::new();
+    res.insert(
+        InternedString::intern(keyword),
+        Value::Array(schemas.into()),
+    );
+    Value::Object(res)
+}
@@ -0,0 +1,222 @@
+use std::path::Path;
+
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Command};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Editor, Helper};
+use yajrc::RpcError;
+
+use crate::util::{internal_error, parse_error};
+use crate::{call_cli, AnyHandler, Empty, ParentHandler};
+
+/// Runs commands read from the terminal until EOF or `exit`, all against the
+/// one context, so a session or connection it holds is reused throughout.
+///
+/// A command is written as on the command line, minus the program name, and
+/// its subcommand path may be dotted like a method name, e.g.
+/// `server.metrics` for `server metrics`.
+pub(crate) fn repl<Context: crate::Context + Clone>(
+    root_handler: &AnyHandler<Context, Empty, ParentHandler<Context>>,
+    cmd: Command,
+    builtins: &[&'static str],
+    ctx: Context,
+    history: Option<&Path>,
+) -> Result<(), RpcError> {
+    let mut editor = Editor::<ReplHelper, DefaultHistory>::new().map_err(internal_error)?;
+    editor.set_helper(Some(ReplHelper::new(cmd.clone(), builtins)));
+    if let Some(history) = history {
+        if let Some(parent) = history.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // there is none yet on first use
+        editor.load_history(history).ok();
+    }
+    let prompt = format!("{}> ", cmd.get_name());
+    loop {
+        let line = match editor.readline(&prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(internal_error(e)),
+        };
+        let line = line.trim();
+        match line {
+            "" => continue,
+            "exit" | "quit" => break,
+            _ => (),
+        }
+        if !has_secret(&cmd, line) {
+            editor.add_history_entry(line).ok();
+            // appended as we go, so a command killed with Ctrl-C or a
+            // failing read doesn't lose the session
+            if let Some(history) = history {
+                if let Err(e) = editor.append_history(history) {
+                    eprintln!("failed to save history: {}", e);
+                }
+            }
+        }
+        if let Err(e) = run_line(root_handler, &cmd, builtins, ctx.clone(), line) {
+            match e.data {
+                Some(serde_json::Value::String(s)) => eprintln!("{}: {}", e.message, s),
+                Some(data) => eprintln!("{}: {}", e.message, data),
+                None => eprintln!("{}", e.message),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Argument names whose values are kept out of the history.
+const SECRET_ARGS: &[&str] = &["password", "passphrase", "secret", "token"];
+
+fn names_secret(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    SECRET_ARGS.iter().any(|secret| name.contains(secret))
+}
+
+/// Whether `line` passes a password or other secret: it sets an argument
+/// named like one, or doesn't parse and mentions one.
+fn has_secret(cmd: &Command, line: &str) -> bool {
+    fn sets_secret(matches: &ArgMatches) -> bool {
+        matches.ids().any(|id| {
+            names_secret(id.as_str())
+                && matches.value_source(id.as_str()) == Some(ValueSource::CommandLine)
+        }) || matches
+            .subcommand()
+            .map_or(false, |(_, sub)| sets_secret(sub))
+    }
+    let words = match shell_words::split(line) {
+        Ok(words) => words,
+        Err(_) => return names_secret(line),
+    };
+    let args = std::iter::once(cmd.get_name().to_owned()).chain(expand(&words));
+    match cmd.clone().try_get_matches_from(args) {
+        Ok(matches) => sets_secret(&matches),
+        Err(_) => names_secret(line),
+    }
+}
+
+fn run_line<Context: crate::Context + Clone>(
+    root_handler: &AnyHandler<Context, Empty, ParentHandler<Context>>,
+    cmd: &Command,
+    builtins: &[&'static str],
+    ctx: Context,
+    line: &str,
+) -> Result<(), RpcError> {
+    let words = shell_words::split(line).map_err(parse_error)?;
+    let args = std::iter::once(cmd.get_name().to_owned()).chain(expand(&words));
+    let matches = match cmd.clone().try_get_matches_from(args) {
+        Ok(a) => a,
+        Err(e) => {
+            // includes `--help`
+            e.print().map_err(internal_error)?;
+            return Ok(());
+        }
+    };
+    if let Some(builtin) = matches
+        .subcommand_name()
+        .filter(|name| builtins.contains(name))
+    {
+        return Err(RpcError {
+            data: Some(format!("{} is not available in the repl", builtin).into()),
+            ..yajrc::INVALID_REQUEST_ERROR
+        });
+    }
+    call_cli(root_handler, ctx, &matches)
+}
+
+/// Splits a dotted method name in the first word into a subcommand path.
+fn expand<S: AsRef<str>>(words: &[S]) -> Vec<String> {
+    let mut words = words.iter().map(|w| w.as_ref());
+    let mut res = Vec::new();
+    if let Some(first) = words.next() {
+        if first.starts_with('-') {
+            res.push(first.to_owned());
+        } else {
+            res.extend(first.split('.').map(|w| w.to_owned()));
+        }
+    }
+    res.extend(words.map(|w| w.to_owned()));
+    res
+}
+
+/// Tab completion of dotted method names, subcommands, and long flags.
+struct ReplHelper {
+    cmd: Command,
+    methods: Vec<String>,
+}
+impl ReplHelper {
+    fn new(cmd: Command, builtins: &[&'static str]) -> Self {
+        fn walk(cmd: &Command, prefix: &str, res: &mut Vec<String>) {
+            for sub in cmd.get_subcommands().filter(|c| !c.is_hide_set()) {
+                let name = format!("{}{}", prefix, sub.get_name());
+                walk(sub, &format!("{}.", name), res);
+                res.push(name);
+            }
+        }
+        let mut methods = Vec::new();
+        walk(&cmd, "", &mut methods);
+        methods.retain(|m| !builtins.contains(&m.as_str()));
+        methods.sort();
+        Self { cmd, methods }
+    }
+}
+impl Completer for ReplHelper {
+    type Candidate = String;
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let start = line.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let word = &line[start..];
+        let before: Vec<_> = line[..start].split_whitespace().collect();
+        if before.is_empty() {
+            let candidates = self
+                .methods
+                .iter()
+                .filter(|m| m.starts_with(word))
+                .cloned()
+                .collect();
+            return Ok((start, candidates));
+        }
+        let mut cmd = &self.cmd;
+        for word in expand(&before) {
+            if let Some(sub) = cmd.find_subcommand(&word) {
+                cmd = sub;
+            }
+        }
+        let mut candidates: Vec<_> = if word.starts_with('-') {
+            cmd.get_arguments()
+                .chain(self.cmd.get_arguments())
+                .filter(|a| !a.is_hide_set())
+                .filter_map(|a| a.get_long())
+                .map(|l| format!("--{}", l))
+                .filter(|l| l.starts_with(word))
+                .collect()
+        } else {
+            cmd.get_subcommands()
+                .filter(|c| !c.is_hide_set())
+                .map(|c| c.get_name())
+                .filter(|n| n.starts_with(word))
+                .map(|n| n.to_owned())
+                .collect()
+        };
+        candidates.sort();
+        candidates.dedup();
+        Ok((start, candidates))
+    }
+}
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+impl Highlighter for ReplHelper {}
+impl Validator for ReplHelper {}
+impl Helper for ReplHelper {}
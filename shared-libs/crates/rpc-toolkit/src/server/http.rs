@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use axum::body::Body;
 use axum::extract::Request;
 use axum::handler::Handler;
@@ -186,15 +188,21 @@ impl<Context> Clone for DynMiddleware<Context> {
     }
 }
 
+/// Wraps the handling of a single call, see [`HttpServer::with_call_scope`].
+pub type CallScope =
+    Arc<dyn for<'a> Fn(BoxFuture<'a, RpcResponse>) -> BoxFuture<'a, RpcResponse> + Send + Sync>;
+
 pub struct HttpServer<Context: crate::Context> {
-    inner: Server<Context>,
-    middleware: Vector<DynMiddleware<Context>>,
+    pub(super) inner: Server<Context>,
+    pub(super) middleware: Vector<DynMiddleware<Context>>,
+    pub(super) call_scope: Option<CallScope>,
 }
 impl<Context: crate::Context> Clone for HttpServer<Context> {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
             middleware: self.middleware.clone(),
+            call_scope: self.call_scope.clone(),
         }
     }
 }
@@ -203,6 +211,7 @@ impl<Context: crate::Context> Server<Context> {
         HttpServer {
             inner: self,
             middleware: Vector::new(),
+            call_scope: None,
         }
     }
     pub fn middleware<T: Middleware<Context>>(self, middleware: T) -> HttpServer<Context> {
@@ -214,6 +223,20 @@ impl<Context: crate::Context> HttpServer<Context> {
         self.middleware.push_back(DynMiddleware::new(middleware));
         self
     }
+    /// Runs every call, including its middleware, inside `scope`, e.g. to give
+    /// each call its own task-local state. Calls of a batch, and calls sharing
+    /// a websocket, run concurrently in one task, so a scope around the whole
+    /// HTTP request or connection would be shared between them.
+    pub fn with_call_scope<F>(mut self, scope: F) -> Self
+    where
+        F: for<'a> Fn(BoxFuture<'a, RpcResponse>) -> BoxFuture<'a, RpcResponse>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.call_scope = Some(Arc::new(scope));
+        self
+    }
     async fn process_http_request(&self, mut req: Request) -> Response {
         let mut mid = self.middleware.clone();
         match async {
@@ -275,35 +298,27 @@ impl<Context: crate::Context> HttpServer<Context> {
             }),
         }
     }
-    async fn process_rpc_request(
+    pub(super) async fn process_rpc_request(
         &self,
         ctx: &Context,
         mid: &mut Vector<DynMiddleware<Context>>,
         mut req: RpcRequest,
     ) -> RpcResponse {
         let metadata = Value::Object(
-            self.inner
-                .root_handler
-                .metadata(
-                    match self
-                        .inner
-                        .root_handler
-                        .method_from_dots(req.method.as_str())
-                    {
-                        Some(a) => a,
-                        None => {
-                            return RpcResponse {
-                                id: req.id,
-                                result: Err(yajrc::METHOD_NOT_FOUND_ERROR),
-                            }
-                        }
-                    },
-                )
-                .into_iter()
-                .map(|(key, value)| (key.into(), value))
-                .collect(),
+            match self.inner.method_metadata(req.method.as_str()) {
+                Some(a) => a,
+                None => {
+                    return RpcResponse {
+                        id: req.id,
+                        result: Err(yajrc::METHOD_NOT_FOUND_ERROR),
+                    }
+                }
+            }
+            .into_iter()
+            .map(|(key, value)| (key.into(), value))
+            .collect(),
         );
-        let mut res = async {
+        let call = async {
             for middleware in mid.iter_mut().rev() {
                 if let Err(res) = middleware
                     .process_rpc_request(ctx, metadata.clone(), &mut req)
@@ -313,8 +328,11 @@ impl<Context: crate::Context> HttpServer<Context> {
                 }
             }
             self.inner.handle_single_request(req).await
-        }
-        .await;
+        };
+        let mut res = match &self.call_scope {
+            Some(scope) => scope(call.boxed()).await,
+            None => call.await,
+        };
         for middleware in mid.iter_mut() {
             middleware.process_rpc_response(ctx, &mut res).await;
         }
@@ -0,0 +1,156 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use imbl_value::imbl::OrdMap;
+use imbl_value::Value;
+use yajrc::RpcError;
+
+/// A call ran longer than the `timeout` of its method.
+pub const TIMEOUT_ERROR: RpcError = RpcError {
+    code: -32010,
+    message: Cow::Borrowed("Request timed out"),
+    data: None,
+};
+/// The `max_concurrent` calls of a method were already running.
+pub const CONCURRENCY_LIMIT_ERROR: RpcError = RpcError {
+    code: -32011,
+    message: Cow::Borrowed("Too many concurrent requests"),
+    data: None,
+};
+/// The `rate_limit` of a method was used up.
+pub const RATE_LIMIT_ERROR: RpcError = RpcError {
+    code: -32012,
+    message: Cow::Borrowed("Rate limit exceeded"),
+    data: None,
+};
+
+/// The limits a [`Server`](crate::Server) enforces on calls of a method,
+/// declared in its metadata:
+///
+/// - `timeout`: seconds a call may run before it fails with [`TIMEOUT_ERROR`]
+/// - `max_concurrent`: calls that may run at once; any more fail with
+///   [`CONCURRENCY_LIMIT_ERROR`]
+/// - `rate_limit`: `{ "count": <calls>, "per": <seconds> }`, the calls that may
+///   start within any window of that length; any more fail with
+///   [`RATE_LIMIT_ERROR`]
+///
+/// e.g. `from_fn_async(dump).with_metadata("max_concurrent", Value::from(2))`.
+/// Limits apply to the method across all clients and transports.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Limits {
+    pub timeout: Option<Duration>,
+    pub max_concurrent: Option<usize>,
+    pub rate_limit: Option<(usize, Duration)>,
+}
+impl Limits {
+    pub fn from_metadata(metadata: &OrdMap<&'static str, Value>) -> Self {
+        let seconds = |v: &Value| v.as_f64().and_then(|s| Duration::try_from_secs_f64(s).ok());
+        Limits {
+            timeout: metadata.get("timeout").and_then(seconds),
+            max_concurrent: metadata
+                .get("max_concurrent")
+                .and_then(|v| v.as_u64())
+                .map(|n| n as usize),
+            rate_limit: metadata
+                .get("rate_limit")
+                .and_then(|v| Some((v["count"].as_u64()? as usize, seconds(&v["per"])?))),
+        }
+    }
+}
+
+#[derive(Default)]
+struct MethodState {
+    running: usize,
+    started: VecDeque<Instant>,
+}
+
+/// Tracks the running and recent calls of every method with limits.
+#[derive(Default)]
+pub(crate) struct Limiter {
+    methods: Mutex<BTreeMap<String, MethodState>>,
+}
+impl Limiter {
+    /// Admits a call, or fails with the limit it would exceed. The call
+    /// counts as running until the returned permit is dropped.
+    pub(crate) fn acquire(
+        self: &Arc<Self>,
+        method: &str,
+        limits: &Limits,
+    ) -> Result<Option<Permit>, RpcError> {
+        if limits.max_concurrent.is_none() && limits.rate_limit.is_none() {
+            return Ok(None);
+        }
+        let mut methods = self.methods.lock().unwrap();
+        let state = methods.entry(method.to_owned()).or_default();
+        let now = Instant::now();
+        if let Some((count, per)) = limits.rate_limit {
+            while state
+                .started
+                .front()
+                .is_some_and(|t| now.duration_since(*t) >= per)
+            {
+                state.started.pop_front();
+            }
+            if state.started.len() >= count {
+                let retry_after = state
+                    .started
+                    .front()
+                    .map_or(per, |t| per - now.duration_since(*t));
+                return Err(RpcError {
+                    data: Some(serde_json::json!({
+                        "method": method,
+                        "retryAfter": retry_after.as_secs_f64(),
+                    })),
+                    ..RATE_LIMIT_ERROR
+                });
+            }
+        }
+        if let Some(max) = limits.max_concurrent {
+            if state.running >= max {
+                return Err(RpcError {
+                    data: Some(serde_json::json!({
+                        "method": method,
+                        "maxConcurrent": max,
+                    })),
+                    ..CONCURRENCY_LIMIT_ERROR
+                });
+            }
+        }
+        if limits.rate_limit.is_some() {
+            state.started.push_back(now);
+        }
+        state.running += 1;
+        Ok(Some(Permit {
+            limiter: self.clone(),
+            method: method.to_owned(),
+        }))
+    }
+}
+
+pub(crate) struct Permit {
+    limiter: Arc<Limiter>,
+    method: String,
+}
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut methods = self.limiter.methods.lock().unwrap();
+        if let Some(state) = methods.get_mut(&self.method) {
+            state.running -= 1;
+            if state.running == 0 && state.started.is_empty() {
+                methods.remove(&self.method);
+            }
+        }
+    }
+}
+
+pub(crate) fn timeout_error(method: &str, timeout: Duration) -> RpcError {
+    RpcError {
+        data: Some(serde_json::json!({
+            "method": method,
+            "timeout": timeout.as_secs_f64(),
+        })),
+        ..TIMEOUT_ERROR
+    }
+}
@@ -3,11 +3,15 @@ use std::sync::Arc;
 
 use futures::future::{join_all, BoxFuture};
 use futures::{Future, FutureExt, Stream, StreamExt};
+use imbl_value::imbl::OrdMap;
 use imbl_value::{InternedString, Value};
 use yajrc::{RpcError, RpcMethod};
 
-use crate::util::{invalid_request, JobRunner};
-use crate::{AnyHandler, Empty, HandleAny, HandleAnyArgs, ParentHandler};
+use crate::util::{internal_error, invalid_request, JobRunner};
+use crate::{
+    AnyHandler, Empty, HandleAny, HandleAnyArgs, OpenRpcDocument, OpenRpcInfo, ParentHandler,
+    DISCOVER_METHOD,
+};
 
 pub type GenericRpcMethod = yajrc::GenericRpcMethod<InternedString, Value, Value>;
 pub type RpcRequest = yajrc::RpcRequest<GenericRpcMethod>;
@@ -15,20 +19,28 @@ pub type RpcResponse = yajrc::RpcResponse<GenericRpcMethod>;
 pub type SingleOrBatchRpcRequest = yajrc::SingleOrBatchRpcRequest<GenericRpcMethod>;
 
 pub mod http;
+pub mod limits;
 pub mod socket;
+pub mod ws;
 
 pub use http::*;
+pub use limits::*;
 pub use socket::*;
+pub use ws::*;
 
 pub struct Server<Context: crate::Context> {
     make_ctx: Arc<dyn Fn() -> BoxFuture<'static, Result<Context, RpcError>> + Send + Sync>,
     root_handler: Arc<AnyHandler<Context, Empty, ParentHandler<Context>>>,
+    openrpc: Arc<dyn Fn(&mut OpenRpcDocument) + Send + Sync>,
+    limiter: Arc<Limiter>,
 }
 impl<Context: crate::Context> Clone for Server<Context> {
     fn clone(&self) -> Self {
         Self {
             make_ctx: self.make_ctx.clone(),
             root_handler: self.root_handler.clone(),
+            openrpc: self.openrpc.clone(),
+            limiter: self.limiter.clone(),
         }
     }
 }
@@ -43,30 +55,91 @@ impl<Context: crate::Context> Server<Context> {
         Server {
             make_ctx: Arc::new(move || make_ctx().boxed()),
             root_handler: Arc::new(AnyHandler::new(root_handler)),
+            openrpc: Arc::new(|_| ()),
+            limiter: Arc::new(Limiter::default()),
         }
     }
 
+    /// Amends the document served by `rpc.discover`, e.g. to set its `info`
+    /// or to translate summaries.
+    pub fn with_openrpc(
+        mut self,
+        f: impl Fn(&mut OpenRpcDocument) + Send + Sync + 'static,
+    ) -> Self {
+        self.openrpc = Arc::new(f);
+        self
+    }
+
+    /// The [`OpenRpcDocument`] for every method this server handles.
+    ///
+    /// Also served as the result of `rpc.discover`, unless the root handler
+    /// defines a method by that name itself.
+    pub fn openrpc(&self) -> OpenRpcDocument {
+        let mut doc = OpenRpcDocument::new(&*self.root_handler, OpenRpcInfo::default());
+        (self.openrpc)(&mut doc);
+        doc
+    }
+
+    /// The metadata of `method`, or `None` if this server does not handle it.
+    /// `rpc.discover` and `rpc.unsubscribe` get the metadata of the root.
+    pub(crate) fn method_metadata(&self, method: &str) -> Option<OrdMap<&'static str, Value>> {
+        match self.root_handler.method_from_dots(method) {
+            Some(method) => Some(self.root_handler.metadata(method)),
+            None if method == DISCOVER_METHOD || method == UNSUBSCRIBE_METHOD => {
+                Some(self.root_handler.metadata(VecDeque::new()))
+            }
+            None => None,
+        }
+    }
+
+    /// Calls `method_name`, subject to the [`Limits`] in its metadata.
     pub fn handle_command(
         &self,
-        method: &str,
+        method_name: &str,
         params: Value,
     ) -> impl Future<Output = Result<Value, RpcError>> + Send + 'static {
         let (make_ctx, root_handler, method) = (
             self.make_ctx.clone(),
             self.root_handler.clone(),
-            self.root_handler.method_from_dots(method),
+            self.root_handler.method_from_dots(method_name),
         );
+        let builtin = match (&method, method_name) {
+            (None, DISCOVER_METHOD) => {
+                Some(imbl_value::to_value(&self.openrpc()).map_err(internal_error))
+            }
+            (None, UNSUBSCRIBE_METHOD) => Some(ws::unsubscribe(&params)),
+            _ => None,
+        };
+        let limits = method
+            .as_ref()
+            .map(|method| Limits::from_metadata(&self.root_handler.metadata(method.clone())))
+            .unwrap_or_default();
+        let limiter = self.limiter.clone();
 
         async move {
-            root_handler
-                .handle_async(HandleAnyArgs {
-                    context: make_ctx().await?,
-                    parent_method: VecDeque::new(),
-                    method: method.ok_or_else(|| yajrc::METHOD_NOT_FOUND_ERROR)?,
-                    params,
-                    inherited: crate::Empty {},
-                })
-                .await
+            if let Some(res) = builtin {
+                return res;
+            }
+            let method = method.ok_or_else(|| yajrc::METHOD_NOT_FOUND_ERROR)?;
+            let name = method.iter().copied().collect::<Vec<_>>().join(".");
+            let _permit = limiter.acquire(&name, &limits)?;
+            let res = async {
+                root_handler
+                    .handle_async(HandleAnyArgs {
+                        context: make_ctx().await?,
+                        parent_method: VecDeque::new(),
+                        method,
+                        params,
+                        inherited: crate::Empty {},
+                    })
+                    .await
+            };
+            match limits.timeout {
+                Some(timeout) => tokio::time::timeout(timeout, res)
+                    .await
+                    .unwrap_or_else(|_| Err(timeout_error(&name, timeout))),
+                None => res.await,
+            }
         }
     }
 
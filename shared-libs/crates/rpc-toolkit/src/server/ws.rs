@@ -0,0 +1,383 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{FromRequestParts, Request};
+use axum::response::{IntoResponse, Response};
+use futures::future::{join_all, BoxFuture};
+use futures::stream::{BoxStream, SplitSink};
+use futures::{FutureExt, SinkExt, Stream, StreamExt};
+use imbl_value::imbl::Vector;
+use imbl_value::{json, InternedString, Value};
+use tokio::sync::{mpsc, Notify};
+use tokio::task::JoinHandle;
+use yajrc::RpcError;
+
+use crate::server::{GenericRpcMethod, RpcRequest, RpcResponse, SingleOrBatchRpcRequest};
+use crate::util::{parse_error, JobRunner};
+use crate::{DynMiddleware, HttpServer};
+
+/// The notification a subscription's items are delivered in. Its params are
+/// `{ "subscription": <id>, "result": <item> }`, and finally
+/// `{ "subscription": <id>, "end": true }` if the server ends it.
+pub const SUBSCRIPTION_METHOD: &str = "rpc.subscription";
+/// Cancels a subscription. Takes `{ "subscription": <id> }` and returns
+/// whether it was still active.
+pub const UNSUBSCRIBE_METHOD: &str = "rpc.unsubscribe";
+/// How many outgoing messages a connection queues before senders wait for
+/// the socket. Subscriptions pause until there is room, so a client that
+/// stops reading holds up its own connection rather than growing the queue.
+const OUTBOUND_BUFFER: usize = 64;
+
+tokio::task_local! {
+    static NOTIFIER: Notifier;
+}
+
+struct Connection {
+    send: mpsc::Sender<Value>,
+    next_id: AtomicU64,
+    subscriptions: Mutex<BTreeMap<u64, JoinHandle<()>>>,
+    /// Waits for the signal registered with [`Notifier::close_when`].
+    guard: Mutex<Option<JoinHandle<()>>>,
+    close: Notify,
+}
+impl Connection {
+    fn abort_all(&self) {
+        for (_, task) in std::mem::take(&mut *self.subscriptions.lock().unwrap()) {
+            task.abort();
+        }
+        if let Some(guard) = self.guard.lock().unwrap().take() {
+            guard.abort();
+        }
+    }
+}
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.abort_all()
+    }
+}
+
+/// The websocket connection a request came in on, for pushing notifications
+/// back to the client.
+///
+/// Only available to handlers called through [`HttpServer::handle_ws`], via
+/// [`Notifier::current`].
+#[derive(Clone)]
+pub struct Notifier {
+    conn: Arc<Connection>,
+    /// Subscriptions made while handling the request. They start once its
+    /// response is sent, so that a client knows the id before any items
+    /// arrive.
+    pending: Arc<Mutex<Option<Vec<(u64, BoxStream<'static, Value>)>>>>,
+}
+impl Notifier {
+    fn new(conn: Arc<Connection>) -> Self {
+        Self {
+            conn,
+            pending: Arc::new(Mutex::new(Some(Vec::new()))),
+        }
+    }
+    /// The connection of the request being handled, or `None` if it did not
+    /// come in over a websocket.
+    pub fn current() -> Option<Self> {
+        NOTIFIER.try_with(|n| n.clone()).ok()
+    }
+    /// Sends a notification, waiting for room in the connection's queue.
+    /// Returns `false` if the connection is closed.
+    pub async fn notify(&self, method: &str, params: Value) -> bool {
+        send_notification(&self.conn.send, method, params).await
+    }
+    /// Forwards every item of `stream` as an [`SUBSCRIPTION_METHOD`]
+    /// notification, until the stream ends, the client unsubscribes, or the
+    /// connection closes. Returns the subscription id, which the handler
+    /// should return to the client.
+    pub fn subscribe(&self, stream: impl Stream<Item = Value> + Send + 'static) -> u64 {
+        let id = self.conn.next_id.fetch_add(1, Ordering::SeqCst);
+        let stream = stream.boxed();
+        let mut pending = self.pending.lock().unwrap();
+        match &mut *pending {
+            Some(pending) => pending.push((id, stream)),
+            None => start_subscription(&self.conn, id, stream),
+        }
+        id
+    }
+    /// Cancels a subscription. Returns whether it was still active.
+    pub fn unsubscribe(&self, id: u64) -> bool {
+        if let Some(pending) = &mut *self.pending.lock().unwrap() {
+            let len = pending.len();
+            pending.retain(|(i, _)| *i != id);
+            if pending.len() != len {
+                return true;
+            }
+        }
+        match self.conn.subscriptions.lock().unwrap().remove(&id) {
+            Some(task) => {
+                task.abort();
+                true
+            }
+            None => false,
+        }
+    }
+    /// Closes the connection, ending its subscriptions and cancelling the
+    /// calls still running on it, once `signal` resolves, e.g. when the
+    /// credentials it was opened with are revoked.
+    ///
+    /// Only the first signal registered on a connection is kept; later ones
+    /// are dropped and `false` returned, so a middleware can make this call on
+    /// every request without piling up waiters.
+    pub fn close_when(&self, signal: impl Future<Output = ()> + Send + 'static) -> bool {
+        let mut guard = self.conn.guard.lock().unwrap();
+        if guard.is_some() {
+            return false;
+        }
+        let weak = Arc::downgrade(&self.conn);
+        *guard = Some(tokio::spawn(async move {
+            signal.await;
+            if let Some(conn) = weak.upgrade() {
+                conn.close.notify_one();
+            }
+        }));
+        true
+    }
+    fn start_pending(&self) {
+        for (id, stream) in self.pending.lock().unwrap().take().unwrap_or_default() {
+            start_subscription(&self.conn, id, stream);
+        }
+    }
+}
+
+async fn send_notification(send: &mpsc::Sender<Value>, method: &str, params: Value) -> bool {
+    match imbl_value::to_value(&RpcRequest {
+        id: None,
+        method: GenericRpcMethod::new(InternedString::intern(method)),
+        params,
+    }) {
+        Ok(notification) => send.send(notification).await.is_ok(),
+        Err(_) => false,
+    }
+}
+
+fn start_subscription(conn: &Arc<Connection>, id: u64, mut stream: BoxStream<'static, Value>) {
+    let send = conn.send.clone();
+    let weak: Weak<Connection> = Arc::downgrade(conn);
+    let mut subscriptions = conn.subscriptions.lock().unwrap();
+    subscriptions.insert(
+        id,
+        tokio::spawn(async move {
+            while let Some(item) = stream.next().await {
+                let params = json!({ "subscription": id, "result": item });
+                if !send_notification(&send, SUBSCRIPTION_METHOD, params).await {
+                    return;
+                }
+            }
+            let params = json!({ "subscription": id, "end": true });
+            send_notification(&send, SUBSCRIPTION_METHOD, params).await;
+            if let Some(conn) = weak.upgrade() {
+                conn.subscriptions.lock().unwrap().remove(&id);
+            }
+        }),
+    );
+}
+
+async fn send_value(sink: &mut SplitSink<WebSocket, Message>, value: &Value) -> bool {
+    match serde_json::to_string(value) {
+        Ok(text) => sink.send(Message::Text(text.into())).await.is_ok(),
+        Err(_) => true,
+    }
+}
+
+/// Handles [`UNSUBSCRIBE_METHOD`] for the current websocket connection.
+pub(crate) fn unsubscribe(params: &Value) -> Result<Value, RpcError> {
+    let notifier = Notifier::current().ok_or(yajrc::METHOD_NOT_FOUND_ERROR)?;
+    let id = params["subscription"].as_u64().ok_or_else(|| RpcError {
+        data: Some("expected { \"subscription\": <id> }".into()),
+        ..yajrc::INVALID_PARAMS_ERROR
+    })?;
+    Ok(Value::Bool(notifier.unsubscribe(id)))
+}
+
+impl<Context: crate::Context> HttpServer<Context> {
+    /// Upgrades `req` to a websocket that carries JSON-RPC in both
+    /// directions: any number of requests and batches, answered as they
+    /// complete, plus notifications pushed by handlers through [`Notifier`].
+    ///
+    /// `process_http_request` and `process_http_response` run once, on the
+    /// upgrade, and every call goes through `process_rpc_request` and
+    /// `process_rpc_response` as over HTTP. Requests without an id get no
+    /// response.
+    pub fn handle_ws(&self, req: Request) -> BoxFuture<'static, Response> {
+        let server = self.clone();
+        async move { server.process_ws_request(req).await }.boxed()
+    }
+    async fn process_ws_request(&self, mut req: Request) -> Response {
+        let mut mid = self.middleware.clone();
+        match async {
+            let ctx = (self.inner.make_ctx)().await?;
+            for middleware in mid.iter_mut().rev() {
+                if let Err(e) = middleware.process_http_request(&ctx, &mut req).await {
+                    return Ok::<_, RpcError>(e);
+                }
+            }
+            let (mut parts, _) = req.into_parts();
+            let upgrade = match WebSocketUpgrade::from_request_parts(&mut parts, &()).await {
+                Ok(a) => a,
+                Err(e) => return Ok(e.into_response()),
+            };
+            let server = self.clone();
+            let conn_mid = mid.clone();
+            let mut res = upgrade.on_upgrade(move |mut socket| async move {
+                match (server.inner.make_ctx)().await {
+                    Ok(ctx) => server.serve_ws(&ctx, conn_mid, socket).await,
+                    Err(e) => {
+                        if let Ok(res) = serde_json::to_string(&RpcResponse {
+                            id: None,
+                            result: Err(e),
+                        }) {
+                            socket.send(Message::Text(res.into())).await.ok();
+                        }
+                    }
+                }
+            });
+            for middleware in mid.iter_mut() {
+                middleware.process_http_response(&ctx, &mut res).await;
+            }
+            Ok(res)
+        }
+        .await
+        {
+            Ok(a) => a,
+            Err(e) => super::json_http_response(&RpcResponse {
+                id: None,
+                result: Err(e),
+            }),
+        }
+    }
+    async fn serve_ws(
+        &self,
+        ctx: &Context,
+        mid: Vector<DynMiddleware<Context>>,
+        socket: WebSocket,
+    ) {
+        let (mut sink, stream) = socket.split();
+        let (send, mut recv) = mpsc::channel::<Value>(OUTBOUND_BUFFER);
+        let conn = Arc::new(Connection {
+            send,
+            next_id: AtomicU64::new(0),
+            subscriptions: Mutex::new(BTreeMap::new()),
+            guard: Mutex::new(None),
+            close: Notify::new(),
+        });
+
+        let writer = async {
+            while let Some(msg) = recv.recv().await {
+                if !send_value(&mut sink, &msg).await {
+                    break;
+                }
+            }
+        };
+
+        let reader = async {
+            let mut runner = JobRunner::new();
+            let requests = stream
+                .take_while(|msg| {
+                    futures::future::ready(matches!(
+                        msg,
+                        Ok(Message::Text(_)
+                            | Message::Binary(_)
+                            | Message::Ping(_)
+                            | Message::Pong(_))
+                    ))
+                })
+                .filter_map(|msg| {
+                    futures::future::ready(match msg {
+                        Ok(Message::Text(text)) => Some(serde_json::from_str::<
+                            SingleOrBatchRpcRequest,
+                        >(text.as_str())),
+                        Ok(Message::Binary(bin)) => {
+                            Some(serde_json::from_slice::<SingleOrBatchRpcRequest>(&bin))
+                        }
+                        _ => None,
+                    })
+                })
+                .map(|req| {
+                    let notifier = Notifier::new(conn.clone());
+                    let mid = mid.clone();
+                    NOTIFIER.scope(notifier.clone(), async move {
+                        let res = match req.map_err(parse_error) {
+                            Ok(req) => self.process_ws_message(ctx, mid, req).await,
+                            Err(e) => imbl_value::to_value(&RpcResponse {
+                                id: None,
+                                result: Err(e),
+                            })
+                            .ok(),
+                        };
+                        if let Some(res) = res {
+                            notifier.conn.send.send(res).await.ok();
+                        }
+                        notifier.start_pending();
+                    })
+                });
+            tokio::pin!(requests);
+            while runner.next_result(&mut requests).await.is_some() {}
+        };
+
+        // whether to flush the queued responses, and whether to close the
+        // socket from this end
+        let (flush, close) = tokio::select! {
+            _ = writer => (false, false),
+            _ = reader => (true, true),
+            _ = conn.close.notified() => (false, true),
+        };
+        conn.abort_all();
+        if flush {
+            // flush the responses to the last requests
+            while let Ok(msg) = recv.try_recv() {
+                if !send_value(&mut sink, &msg).await {
+                    return;
+                }
+            }
+        }
+        if close {
+            sink.close().await.ok();
+        }
+    }
+    async fn process_ws_message(
+        &self,
+        ctx: &Context,
+        mid: Vector<DynMiddleware<Context>>,
+        req: SingleOrBatchRpcRequest,
+    ) -> Option<Value> {
+        match req {
+            SingleOrBatchRpcRequest::Single(req) => {
+                let notification = req.id.is_none();
+                let res = self.process_rpc_request(ctx, &mut mid.clone(), req).await;
+                if notification {
+                    None
+                } else {
+                    imbl_value::to_value(&res).ok()
+                }
+            }
+            SingleOrBatchRpcRequest::Batch(reqs) => {
+                let res: Vec<_> = join_all(reqs.into_iter().map(|req| {
+                    let mut mid = mid.clone();
+                    async move {
+                        let notification = req.id.is_none();
+                        let res = self.process_rpc_request(ctx, &mut mid, req).await;
+                        (!notification).then(|| res)
+                    }
+                }))
+                .await
+                .into_iter()
+                .flatten()
+                .collect();
+                if res.is_empty() {
+                    None
+                } else {
+                    imbl_value::to_value(&res).ok()
+                }
+            }
+        }
+    }
+}
@@ -1,6 +1,8 @@
 use clap::Parser;
+use futures::StreamExt;
 use rpc_toolkit::{
-    from_fn, from_fn_async, Context, Empty, HandlerExt, HandlerTS, ParentHandler, Server,
+    from_fn, from_fn_async, Context, Empty, HandlerExt, HandlerTS, Notifier, OpenRpcDocument,
+    OpenRpcInfo, ParentHandler, Server, WsClient,
 };
 use serde::{Deserialize, Serialize};
 use yajrc::RpcError;
@@ -87,3 +89,251 @@ async fn test_basic_server() {
     let response: String = imbl_value::from_value(result).unwrap();
     assert_eq!(response, "Thing1 is nested");
 }
+
+#[tokio::test]
+async fn test_discover() {
+    let root_handler = ParentHandler::new()
+        .subcommand(
+            "thing1",
+            from_fn_async(thing1_handler)
+                .with_metadata("sync_db", imbl_value::Value::Bool(true))
+                .with_about("Describe thing1"),
+        )
+        .subcommand(
+            "group",
+            ParentHandler::<TestContext, Empty, Empty>::new()
+                .subcommand("no-ts", from_fn(no_ts_handler).no_ts()),
+        );
+
+    let server = Server::new(|| async { Ok(TestContext) }, root_handler)
+        .with_openrpc(|doc| doc.info = OpenRpcInfo::new("test", "1.0.0"));
+
+    let result = server
+        .handle_command("rpc.discover", imbl_value::Value::Null)
+        .await
+        .unwrap();
+    let doc: OpenRpcDocument = imbl_value::from_value(result).unwrap();
+    assert_eq!(doc.info.title, "test");
+
+    let mut names: Vec<_> = doc.methods.iter().map(|m| m.name.as_str()).collect();
+    names.sort();
+    assert_eq!(names, ["group.no-ts", "thing1"]);
+
+    let thing1 = doc.methods.iter().find(|m| m.name == "thing1").unwrap();
+    assert_eq!(thing1.summary.as_deref(), Some("Describe thing1"));
+    assert_eq!(
+        thing1.extensions.get("x-sync-db"),
+        Some(&imbl_value::Value::Bool(true))
+    );
+    #[cfg(feature = "ts-rs")]
+    assert_eq!(
+        thing1
+            .params
+            .iter()
+            .map(|p| p.name.as_str())
+            .collect::<Vec<_>>(),
+        ["thing"]
+    );
+}
+
+#[derive(Debug, Deserialize, Serialize, Parser)]
+struct CountParams {
+    to: u64,
+}
+
+#[tokio::test]
+async fn test_websocket() {
+    let root_handler = ParentHandler::new()
+        .subcommand("thing1", from_fn_async(thing1_handler))
+        .subcommand(
+            "count",
+            from_fn_async(|_ctx: TestContext, params: CountParams| async move {
+                let notifier = Notifier::current().ok_or(yajrc::INTERNAL_ERROR)?;
+                let items = futures::stream::iter(0..params.to).map(|i| imbl_value::json!(i));
+                Ok::<_, RpcError>(notifier.subscribe(items))
+            }),
+        );
+    let server = Server::new(|| async { Ok(TestContext) }, root_handler).for_http();
+    let app = axum::Router::new().route(
+        "/ws",
+        axum::routing::any(move |req: axum::extract::Request| server.handle_ws(req)),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let client = WsClient::connect(format!("ws://{}/ws", addr))
+        .await
+        .unwrap();
+
+    // calls share the connection
+    let (a, b) = futures::join!(
+        client.call("thing1", imbl_value::json!({ "thing": "a" })),
+        client.call("thing1", imbl_value::json!({ "thing": "b" })),
+    );
+    assert_eq!(a.unwrap(), imbl_value::json!("Thing1 is a"));
+    assert_eq!(b.unwrap(), imbl_value::json!("Thing1 is b"));
+
+    // a batch answers each of its calls, in order
+    let res = client
+        .batch(vec![
+            ("thing1", imbl_value::json!({ "thing": "c" })),
+            ("missing", imbl_value::json!({})),
+            ("thing1", imbl_value::json!({ "thing": "d" })),
+        ])
+        .await
+        .unwrap();
+    assert_eq!(res.len(), 3);
+    assert_eq!(res[0].as_ref().unwrap(), &imbl_value::json!("Thing1 is c"));
+    assert_eq!(
+        res[1].as_ref().unwrap_err().code,
+        yajrc::METHOD_NOT_FOUND_ERROR.code
+    );
+    assert_eq!(res[2].as_ref().unwrap(), &imbl_value::json!("Thing1 is d"));
+    assert!(client.batch(Vec::new()).await.unwrap().is_empty());
+
+    let items: Vec<_> = client
+        .subscribe("count", imbl_value::json!({ "to": 3 }))
+        .await
+        .unwrap()
+        .collect()
+        .await;
+    assert_eq!(
+        items,
+        [
+            imbl_value::json!(0),
+            imbl_value::json!(1),
+            imbl_value::json!(2)
+        ]
+    );
+
+    // a subscription that is not read is cut off once its buffer fills
+    let mut sub = client
+        .subscribe("count", imbl_value::json!({ "to": 1000 }))
+        .await
+        .unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    let mut read = 0;
+    while sub.next().await.is_some() {
+        read += 1;
+    }
+    assert_eq!(read, rpc_toolkit::SUBSCRIPTION_BUFFER);
+    assert!(sub.lagged());
+
+    // the connection stays usable, also as the context of a handler tree
+    // that forwards its calls
+    let proxy = Server::new(
+        move || {
+            let client = client.clone();
+            async move { Ok(client) }
+        },
+        ParentHandler::new().subcommand(
+            "thing1",
+            from_fn_async(thing1_handler).with_call_remote::<WsClient>(),
+        ),
+    );
+    let res = proxy
+        .handle_command("thing1", imbl_value::json!({ "thing": "c" }))
+        .await
+        .unwrap();
+    assert_eq!(res, imbl_value::json!("Thing1 is c"));
+}
+
+#[tokio::test]
+async fn test_websocket_close_when() {
+    let (revoke, revoked) = tokio::sync::watch::channel(false);
+    let root_handler = ParentHandler::new().subcommand(
+        "guarded",
+        from_fn_async(move |_ctx: TestContext, _: Empty| {
+            let mut revoked = revoked.clone();
+            async move {
+                let notifier = Notifier::current().ok_or(yajrc::INTERNAL_ERROR)?;
+                notifier.close_when(async move {
+                    revoked.wait_for(|r| *r).await.ok();
+                });
+                let items = futures::stream::pending::<imbl_value::Value>();
+                Ok::<_, RpcError>(notifier.subscribe(items))
+            }
+        }),
+    );
+    let server = Server::new(|| async { Ok(TestContext) }, root_handler).for_http();
+    let app = axum::Router::new().route(
+        "/ws",
+        axum::routing::any(move |req: axum::extract::Request| server.handle_ws(req)),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let client = WsClient::connect(format!("ws://{}/ws", addr))
+        .await
+        .unwrap();
+    let mut sub = client
+        .subscribe("guarded", imbl_value::json!({}))
+        .await
+        .unwrap();
+    client.call("guarded", imbl_value::json!({})).await.unwrap();
+
+    // the signal ends the subscription and the connection with it
+    revoke.send(true).unwrap();
+    let ended = tokio::time::timeout(std::time::Duration::from_secs(5), sub.next()).await;
+    assert_eq!(ended.unwrap(), None);
+    assert!(client.call("guarded", imbl_value::json!({})).await.is_err());
+}
+
+#[tokio::test]
+async fn test_limits() {
+    let root_handler = ParentHandler::new()
+        .subcommand(
+            "slow",
+            from_fn_async(|_ctx: TestContext, _: Empty| async move {
+                tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+                Ok::<_, RpcError>(())
+            })
+            .with_metadata("timeout", imbl_value::Value::from(0.05)),
+        )
+        .subcommand(
+            "wait",
+            from_fn_async(|_ctx: TestContext, _: Empty| async move {
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                Ok::<_, RpcError>(())
+            })
+            .with_metadata("max_concurrent", imbl_value::Value::from(1)),
+        )
+        .subcommand(
+            "thing1",
+            from_fn_async(thing1_handler)
+                .with_metadata("rate_limit", imbl_value::json!({ "count": 2, "per": 3600 })),
+        );
+    let server = Server::new(|| async { Ok(TestContext) }, root_handler);
+
+    let err = server
+        .handle_command("slow", imbl_value::json!({}))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code, rpc_toolkit::TIMEOUT_ERROR.code);
+
+    let first = tokio::spawn(server.handle_command("wait", imbl_value::json!({})));
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    let err = server
+        .handle_command("wait", imbl_value::json!({}))
+        .await
+        .unwrap_err();
+    assert_eq!(err.code, rpc_toolkit::CONCURRENCY_LIMIT_ERROR.code);
+    first.await.unwrap().unwrap();
+    server
+        .handle_command("wait", imbl_value::json!({}))
+        .await
+        .unwrap();
+
+    let params = imbl_value::json!({ "thing": "limited" });
+    for _ in 0..2 {
+        server
+            .handle_command("thing1", params.clone())
+            .await
+            .unwrap();
+    }
+    let err = server.handle_command("thing1", params).await.unwrap_err();
+    assert_eq!(err.code, rpc_toolkit::RATE_LIMIT_ERROR.code);
+    assert!(err.data.unwrap()["retryAfter"].as_f64().unwrap() > 0.0);
+}
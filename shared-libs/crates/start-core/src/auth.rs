@@ -2,7 +2,7 @@ use std::collections::BTreeMap;
 use std::sync::LazyLock;
 use std::time::{Duration, Instant};
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeDelta, Utc};
 use clap::Parser;
 use color_eyre::eyre::eyre;
 use imbl_value::{InternedString, json};
@@ -410,6 +410,24 @@ pub struct Session {
     pub user_agent: Option<String>,
 }
 
+/// How stale a persisted [`Session::last_active`] may get. Refreshing it is a
+/// db write, which would move the revision under any client holding one for a
+/// compare-and-swap (`db apply --revision`), so signed requests only refresh it
+/// once it is older than this.
+pub const SESSION_ACTIVITY_INTERVAL: TimeDelta = TimeDelta::hours(1);
+
+impl Session {
+    /// Set `last_active` to `now`, unless it is already within
+    /// [`SESSION_ACTIVITY_INTERVAL`] of it. Returns whether it changed.
+    pub fn touch(&mut self, now: DateTime<Utc>) -> bool {
+        if now - self.last_active < SESSION_ACTIVITY_INTERVAL {
+            return false;
+        }
+        self.last_active = now;
+        true
+    }
+}
+
 #[derive(Deserialize, Serialize, TS)]
 #[serde(rename_all = "camelCase")]
 #[ts(export)]
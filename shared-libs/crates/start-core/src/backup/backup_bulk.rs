@@ -10,6 +10,7 @@ use imbl::OrdSet;
 use imbl_value::InternedString;
 use serde::{Deserialize, Serialize};
 use tokio::io::AsyncWriteExt;
+use tokio::task::JoinHandle;
 use tracing::instrument;
 use ts_rs::TS;
 
@@ -18,6 +19,7 @@ use super::target::{BackupTargetId, PackageBackupInfo};
 use crate::PackageId;
 use crate::auth::LoginContext;
 use crate::backup::os::OsBackup;
+use crate::backup::schedule::{Retention, snapshot_name};
 use crate::backup::{BackupReport, ServerBackupReport};
 use crate::context::RpcContext;
 use crate::db::model::{Database, DatabaseModel};
@@ -188,12 +190,26 @@ pub async fn backup_all(
         password,
     }: BackupParams,
 ) -> Result<(), Error> {
-    let old_password_decrypted = old_password
-        .as_ref()
-        .unwrap_or(&password)
-        .clone()
-        .decrypt(&ctx)?;
+    let old_password = old_password.map(|p| p.decrypt(&ctx)).transpose()?;
     let password = password.decrypt(&ctx)?;
+    start_backup(ctx, target_id, old_password, package_ids, password, None).await?;
+    Ok(())
+}
+
+/// Validate and start a backup, returning once it is running. The handle
+/// resolves when the backup is done; its outcome is reported by notification.
+/// With `retention`, the backup is also kept as a snapshot and older
+/// snapshots pruned.
+#[instrument(skip(ctx, old_password, password))]
+pub async fn start_backup(
+    ctx: RpcContext,
+    target_id: BackupTargetId,
+    old_password: Option<String>,
+    package_ids: Option<Vec<PackageId>>,
+    password: String,
+    retention: Option<Retention>,
+) -> Result<JoinHandle<()>, Error> {
+    let old_password_decrypted = old_password.as_ref().unwrap_or(&password).clone();
 
     // Progress is shown from the moment the request is accepted. The
     // "Initializing" phase covers mounting the target and opening its encrypted
@@ -278,7 +294,7 @@ pub async fn backup_all(
         Some(Duration::from_millis(300)),
     )));
 
-    tokio::task::spawn(async move {
+    Ok(tokio::task::spawn(async move {
         let _progress_db_sync = progress_db_sync;
         let mut backup_guard =
             match BackupMountGuard::mount(disk_guard.clone(), &server_id, &old_password_decrypted)
@@ -304,13 +320,13 @@ pub async fn backup_all(
                     backup_guard,
                     disk_guard,
                     &package_ids,
+                    retention.as_ref(),
                 )
                 .await,
             )
             .await
             .unwrap();
-    });
-    Ok(())
+    }))
 }
 
 #[instrument(skip(db, initial))]
@@ -338,6 +354,7 @@ async fn perform_backup(
     backup_guard: BackupMountGuard<TmpMountGuard>,
     disk_guard: TmpMountGuard,
     package_ids: &OrdSet<PackageId>,
+    retention: Option<&Retention>,
 ) -> Result<BTreeMap<PackageId, PackageBackupReport>, Error> {
     let db = ctx.db.peek().await;
     let mut backup_report = BTreeMap::new();
@@ -477,7 +494,34 @@ async fn perform_backup(
     backup_guard.metadata.timestamp = Some(timestamp);
     backup_guard.metadata.package_backups = package_backups;
 
-    backup_guard.save_and_unmount().await?;
+    if let Some(retention) = retention {
+        backup_guard.save().await?;
+        // the backup itself is saved by now: a failed snapshot only costs
+        // history, so it is reported without failing the backup
+        if let Err(e) = backup_guard
+            .snapshot_and_unmount(&snapshot_name(timestamp), retention)
+            .await
+        {
+            tracing::warn!("{}", t!("backup.bulk.snapshot-failed-message", error = e));
+            tracing::debug!("{e:?}");
+            ctx.db
+                .mutate(|db| {
+                    notify(
+                        db,
+                        None,
+                        NotificationLevel::Warning,
+                        t!("backup.bulk.snapshot-failed-title").to_string(),
+                        t!("backup.bulk.snapshot-failed-message", error = e).to_string(),
+                        (),
+                    )
+                })
+                .await
+                .result
+                .log_err();
+        }
+    } else {
+        backup_guard.save_and_unmount().await?;
+    }
 
     ctx.db
         .mutate(|v| {
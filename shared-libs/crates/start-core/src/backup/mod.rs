@@ -12,6 +12,7 @@ use crate::prelude::*;
 pub mod backup_bulk;
 pub mod os;
 pub mod restore;
+pub mod schedule;
 pub mod target;
 pub mod trash;
 
@@ -51,6 +52,10 @@ pub fn backup<C: Context>() -> ParentHandler<C> {
             "target",
             target::target::<C>().with_about("about.commands-backup-target"),
         )
+        .subcommand(
+            "schedule",
+            schedule::schedule::<C>().with_about("about.commands-backup-schedule"),
+        )
 }
 
 pub fn package_backup<C: Context>() -> ParentHandler<C> {
@@ -0,0 +1,679 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+use chrono::{DateTime, Datelike, Utc};
+use clap::Parser;
+use color_eyre::eyre::eyre;
+use const_format::formatcp;
+use imbl_value::InternedString;
+use rpc_toolkit::{Context, HandlerExt, ParentHandler, from_fn_async};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use ts_rs::TS;
+
+use super::backup_bulk::start_backup;
+use super::target::BackupTargetId;
+use super::{BackupReport, ServerBackupReport};
+use crate::auth::LoginContext;
+use crate::context::{CliContext, RpcContext};
+use crate::db::model::DatabaseModel;
+use crate::disk::mount::filesystem::backupfs::BackupFsSnapshot;
+use crate::notifications::{NotificationLevel, notify};
+use crate::prelude::*;
+use crate::util::cron::CronSchedule;
+use crate::util::crypto::{decrypt_slice, encrypt_slice};
+use crate::util::io::{create_file_mod, rename};
+use crate::util::serde::HandlerExtSerde;
+use crate::{MAIN_DATA, PackageId};
+
+/// How often the scheduler checks for due schedules: cron's own resolution.
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The key stored schedule passwords are wrapped with. It lives in its own
+/// root-only file, outside the database, so the private DB, its revision
+/// history and OS backups never carry the key next to what it protects.
+const SCHEDULE_KEY_PATH: &str = formatcp!("{MAIN_DATA}/backup-schedule.key");
+/// Keeps two schedule edits from both creating [`SCHEDULE_KEY_PATH`].
+static SCHEDULE_KEY_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Prefix of the backup-fs snapshots scheduled backups take. Retention only
+/// ever deletes snapshots carrying it.
+pub const SNAPSHOT_PREFIX: &str = "scheduled-";
+
+pub fn snapshot_name(timestamp: DateTime<Utc>) -> String {
+    format!("{SNAPSHOT_PREFIX}{}", timestamp.format("%Y%m%dT%H%M%SZ"))
+}
+
+/// Which of the snapshots taken by scheduled backups to keep, in the style of
+/// grandfather-father-son rotation. Periods are UTC days, ISO weeks and
+/// months.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub struct Retention {
+    pub daily: u32,
+    pub weekly: u32,
+    pub monthly: u32,
+}
+impl Retention {
+    /// The scheduled snapshots this policy no longer keeps. Kept are the
+    /// newest, plus the newest of each of the last `daily` days, `weekly`
+    /// weeks and `monthly` months that have one.
+    pub fn expired<'a>(&self, snapshots: &'a [BackupFsSnapshot]) -> Vec<&'a BackupFsSnapshot> {
+        let mut scheduled: Vec<_> = snapshots
+            .iter()
+            .filter(|s| s.name.starts_with(SNAPSHOT_PREFIX))
+            .collect();
+        scheduled.sort_by_key(|s| std::cmp::Reverse(s.created));
+        let mut keep = vec![false; scheduled.len()];
+        if let Some(newest) = keep.first_mut() {
+            *newest = true;
+        }
+        keep_per_period(&scheduled, &mut keep, self.daily, |t| {
+            (t.year(), t.ordinal())
+        });
+        keep_per_period(&scheduled, &mut keep, self.weekly, |t| t.iso_week());
+        keep_per_period(&scheduled, &mut keep, self.monthly, |t| {
+            (t.year(), t.month())
+        });
+        scheduled
+            .into_iter()
+            .zip(keep)
+            .filter(|(_, keep)| !keep)
+            .map(|(s, _)| s)
+            .collect()
+    }
+}
+
+/// Mark the newest snapshot of each of the `count` most recent periods.
+/// `newest_first` must be sorted newest first.
+fn keep_per_period<P: PartialEq>(
+    newest_first: &[&BackupFsSnapshot],
+    keep: &mut [bool],
+    count: u32,
+    period: impl Fn(DateTime<Utc>) -> P,
+) {
+    let mut last = None;
+    let mut kept = 0;
+    for (snapshot, keep) in newest_first.iter().zip(keep) {
+        if kept == count {
+            break;
+        }
+        let p = period(snapshot.created);
+        if last.as_ref() != Some(&p) {
+            *keep = true;
+            kept += 1;
+            last = Some(p);
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct BackupSchedules(pub BTreeMap<u32, BackupSchedule>);
+impl BackupSchedules {
+    pub fn new() -> Self {
+        Self(BTreeMap::new())
+    }
+}
+impl Map for BackupSchedules {
+    type Key = u32;
+    type Value = BackupSchedule;
+    fn key_str(key: &Self::Key) -> Result<impl AsRef<str>, Error> {
+        Self::key_string(key)
+    }
+    fn key_string(key: &Self::Key) -> Result<InternedString, Error> {
+        Ok(InternedString::from_display(key))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupSchedule {
+    pub cron: CronSchedule,
+    pub target_id: BackupTargetId,
+    /// `None` backs up every installed package
+    pub package_ids: Option<BTreeSet<PackageId>>,
+    /// `None` takes no snapshots
+    pub retention: Option<Retention>,
+    /// The backup password. Scheduled backups run unattended, so it has to be
+    /// stored. It is encrypted under the key in [`SCHEDULE_KEY_PATH`], which is
+    /// never written to the DB: reading the private DB alone doesn't recover
+    /// it, though root on the server still can.
+    wrapped_password: String,
+    pub created: DateTime<Utc>,
+    pub last_run: Option<DateTime<Utc>>,
+}
+impl BackupSchedule {
+    /// When the schedule next fires. A time in the past means a run was
+    /// missed (e.g. the server was off) and is due now.
+    pub fn next_run(&self) -> Option<DateTime<Utc>> {
+        self.cron
+            .next_after(self.last_run.unwrap_or(self.created).max(self.created))
+    }
+
+    /// Whether the schedule should run at `now`.
+    pub fn is_due(&self, now: DateTime<Utc>) -> bool {
+        self.next_run().is_some_and(|next| next <= now)
+    }
+
+    fn password(&self, key: &[u8; 32]) -> Result<String, Error> {
+        let wrapped = base32::decode(
+            base32::Alphabet::Rfc4648 { padding: true },
+            &self.wrapped_password,
+        )
+        .ok_or_else(|| {
+            Error::new(
+                eyre!("{}", t!("backup.schedule.bad-wrapped-password")),
+                ErrorKind::Deserialization,
+            )
+        })?;
+        Ok(String::from_utf8(decrypt_slice(wrapped, key))?)
+    }
+}
+
+/// Read the key in [`SCHEDULE_KEY_PATH`], creating it first if `create` is
+/// set and there is none yet.
+async fn schedule_key(create: bool) -> Result<[u8; 32], Error> {
+    let _lock = SCHEDULE_KEY_LOCK.lock().await;
+    match tokio::fs::read(SCHEDULE_KEY_PATH).await {
+        Ok(key) => key.try_into().map_err(|_| {
+            Error::new(
+                eyre!(
+                    "{}",
+                    t!("backup.schedule.bad-key", path = SCHEDULE_KEY_PATH)
+                ),
+                ErrorKind::Filesystem,
+            )
+        }),
+        Err(e) if create && e.kind() == std::io::ErrorKind::NotFound => {
+            let key: [u8; 32] = rand::random();
+            let tmp = formatcp!("{SCHEDULE_KEY_PATH}.tmp");
+            let mut file = create_file_mod(tmp, 0o600).await?;
+            file.write_all(&key)
+                .await
+                .with_ctx(|_| (ErrorKind::Filesystem, tmp))?;
+            file.sync_all()
+                .await
+                .with_ctx(|_| (ErrorKind::Filesystem, tmp))?;
+            rename(tmp, SCHEDULE_KEY_PATH).await?;
+            Ok(key)
+        }
+        Err(e) => Err(e).with_ctx(|_| (ErrorKind::Filesystem, SCHEDULE_KEY_PATH)),
+    }
+}
+
+fn wrap_password(key: &[u8; 32], password: &str) -> String {
+    base32::encode(
+        base32::Alphabet::Rfc4648 { padding: true },
+        &encrypt_slice(password, key),
+    )
+}
+
+#[derive(Debug, Deserialize, Serialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupScheduleInfo {
+    pub cron: CronSchedule,
+    pub target_id: BackupTargetId,
+    pub package_ids: Option<BTreeSet<PackageId>>,
+    pub retention: Option<Retention>,
+    #[ts(type = "string | null")]
+    pub last_run: Option<DateTime<Utc>>,
+    #[ts(type = "string | null")]
+    pub next_run: Option<DateTime<Utc>>,
+}
+impl From<BackupSchedule> for BackupScheduleInfo {
+    fn from(schedule: BackupSchedule) -> Self {
+        Self {
+            next_run: schedule.next_run(),
+            cron: schedule.cron,
+            target_id: schedule.target_id,
+            package_ids: schedule.package_ids,
+            retention: schedule.retention,
+            last_run: schedule.last_run,
+        }
+    }
+}
+
+pub fn schedule<C: Context>() -> ParentHandler<C> {
+    ParentHandler::new()
+        .subcommand(
+            "list",
+            from_fn_async(list)
+                .with_display_serializable()
+                .with_about("about.list-backup-schedules")
+                .with_call_remote::<CliContext>(),
+        )
+        .subcommand(
+            "add",
+            from_fn_async(add)
+                .with_display_serializable()
+                .with_about("about.add-backup-schedule")
+                .with_call_remote::<CliContext>(),
+        )
+        .subcommand(
+            "update",
+            from_fn_async(update)
+                .no_display()
+                .with_about("about.update-backup-schedule")
+                .with_call_remote::<CliContext>(),
+        )
+        .subcommand(
+            "remove",
+            from_fn_async(remove)
+                .no_display()
+                .with_about("about.remove-backup-schedule")
+                .with_call_remote::<CliContext>(),
+        )
+}
+
+pub async fn list(ctx: RpcContext) -> Result<BTreeMap<u32, BackupScheduleInfo>, Error> {
+    Ok(ctx
+        .db
+        .peek()
+        .await
+        .into_private()
+        .into_backup_schedules()
+        .de()?
+        .0
+        .into_iter()
+        .map(|(id, schedule)| (id, schedule.into()))
+        .collect())
+}
+
+fn retention(
+    keep_daily: Option<u32>,
+    keep_weekly: Option<u32>,
+    keep_monthly: Option<u32>,
+) -> Option<Retention> {
+    if keep_daily.is_none() && keep_weekly.is_none() && keep_monthly.is_none() {
+        return None;
+    }
+    Some(Retention {
+        daily: keep_daily.unwrap_or_default(),
+        weekly: keep_weekly.unwrap_or_default(),
+        monthly: keep_monthly.unwrap_or_default(),
+    })
+}
+
+/// Check what a schedule will need at run time while someone is around to fix
+/// it: the password and the target.
+fn validate(db: &DatabaseModel, target_id: &BackupTargetId, password: &str) -> Result<(), Error> {
+    <RpcContext as LoginContext>::check_password(db, password)?;
+    target_id.clone().load(db)?;
+    Ok(())
+}
+
+#[derive(Deserialize, Serialize, Parser, TS)]
+#[group(skip)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+#[command(rename_all = "kebab-case")]
+pub struct AddScheduleParams {
+    #[arg(help = "help.arg.backup-schedule-cron")]
+    pub cron: CronSchedule,
+    #[arg(help = "help.arg.backup-target-id")]
+    pub target_id: BackupTargetId,
+    #[arg(long = "package-ids", help = "help.arg.package-ids-to-backup")]
+    pub package_ids: Option<Vec<PackageId>>,
+    #[arg(long, help = "help.arg.backup-keep-daily")]
+    pub keep_daily: Option<u32>,
+    #[arg(long, help = "help.arg.backup-keep-weekly")]
+    pub keep_weekly: Option<u32>,
+    #[arg(long, help = "help.arg.backup-keep-monthly")]
+    pub keep_monthly: Option<u32>,
+    #[arg(help = "help.arg.backup-password")]
+    pub password: crate::auth::PasswordType,
+}
+
+pub async fn add(
+    ctx: RpcContext,
+    AddScheduleParams {
+        cron,
+        target_id,
+        package_ids,
+        keep_daily,
+        keep_weekly,
+        keep_monthly,
+        password,
+    }: AddScheduleParams,
+) -> Result<u32, Error> {
+    let password = password.decrypt(&ctx)?;
+    let schedule = BackupSchedule {
+        cron,
+        target_id,
+        package_ids: package_ids.map(|ids| ids.into_iter().collect()),
+        retention: retention(keep_daily, keep_weekly, keep_monthly),
+        wrapped_password: wrap_password(&schedule_key(true).await?, &password),
+        created: Utc::now(),
+        last_run: None,
+    };
+    ctx.db
+        .mutate(|db| {
+            validate(db, &schedule.target_id, &password)?;
+            let id = db
+                .as_private()
+                .as_backup_schedules()
+                .keys()?
+                .into_iter()
+                .max()
+                .map_or(0, |a| a + 1);
+            db.as_private_mut()
+                .as_backup_schedules_mut()
+                .insert(&id, &schedule)?;
+            Ok(id)
+        })
+        .await
+        .result
+}
+
+#[derive(Deserialize, Serialize, Parser, TS)]
+#[group(skip)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+#[command(rename_all = "kebab-case")]
+pub struct UpdateScheduleParams {
+    #[arg(help = "help.arg.backup-schedule-id")]
+    pub id: u32,
+    #[arg(help = "help.arg.backup-schedule-cron")]
+    pub cron: CronSchedule,
+    #[arg(help = "help.arg.backup-target-id")]
+    pub target_id: BackupTargetId,
+    #[arg(long = "package-ids", help = "help.arg.package-ids-to-backup")]
+    pub package_ids: Option<Vec<PackageId>>,
+    #[arg(long, help = "help.arg.backup-keep-daily")]
+    pub keep_daily: Option<u32>,
+    #[arg(long, help = "help.arg.backup-keep-weekly")]
+    pub keep_weekly: Option<u32>,
+    #[arg(long, help = "help.arg.backup-keep-monthly")]
+    pub keep_monthly: Option<u32>,
+    #[arg(help = "help.arg.backup-password")]
+    pub password: crate::auth::PasswordType,
+}
+
+pub async fn update(
+    ctx: RpcContext,
+    UpdateScheduleParams {
+        id,
+        cron,
+        target_id,
+        package_ids,
+        keep_daily,
+        keep_weekly,
+        keep_monthly,
+        password,
+    }: UpdateScheduleParams,
+) -> Result<(), Error> {
+    let password = password.decrypt(&ctx)?;
+    let wrapped_password = wrap_password(&schedule_key(true).await?, &password);
+    ctx.db
+        .mutate(|db| {
+            validate(db, &target_id, &password)?;
+            let model = db
+                .as_private_mut()
+                .as_backup_schedules_mut()
+                .as_idx_mut(&id)
+                .ok_or_else(|| {
+                    Error::new(
+                        eyre!("{}", t!("backup.schedule.not-found", id = id)),
+                        ErrorKind::NotFound,
+                    )
+                })?;
+            let prev = model.de()?;
+            model.ser(&BackupSchedule {
+                cron,
+                target_id,
+                package_ids: package_ids.map(|ids| ids.into_iter().collect()),
+                retention: retention(keep_daily, keep_weekly, keep_monthly),
+                wrapped_password,
+                // a new expression counts from now, not from the last run
+                created: Utc::now(),
+                last_run: prev.last_run,
+            })
+        })
+        .await
+        .result
+}
+
+#[derive(Deserialize, Serialize, Parser, TS)]
+#[group(skip)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+#[command(rename_all = "kebab-case")]
+pub struct RemoveScheduleParams {
+    #[arg(help = "help.arg.backup-schedule-id")]
+    pub id: u32,
+}
+
+pub async fn remove(
+    ctx: RpcContext,
+    RemoveScheduleParams { id }: RemoveScheduleParams,
+) -> Result<(), Error> {
+    ctx.db
+        .mutate(|db| db.as_private_mut().as_backup_schedules_mut().remove(&id))
+        .await
+        .result?;
+    Ok(())
+}
+
+/// Run due schedules, one backup at a time, until the context goes away.
+/// `ctx` yields the context only while it is still alive, so this task never
+/// keeps it alive between runs.
+pub async fn run_schedules(ctx: impl Fn() -> Option<RpcContext>) {
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        let Some(ctx) = ctx() else { break };
+        if let Err(e) = run_due(&ctx).await {
+            tracing::error!("{}", t!("backup.schedule.error-in-scheduler", error = e));
+            tracing::debug!("{e:?}");
+        }
+    }
+}
+
+async fn run_due(ctx: &RpcContext) -> Result<(), Error> {
+    let peek = ctx.db.peek().await;
+    // a wrong boot-time clock would fire, or skip, every schedule
+    if !peek.as_public().as_server_info().as_ntp_synced().de()? {
+        return Ok(());
+    }
+    let now = Utc::now();
+    let due = peek
+        .into_private()
+        .into_backup_schedules()
+        .de()?
+        .0
+        .into_iter()
+        .filter(|(_, schedule)| schedule.is_due(now));
+    for (id, schedule) in due {
+        // recorded up front, so a failing schedule waits for its next slot
+        // instead of retrying every minute
+        ctx.db
+            .mutate(|db| {
+                if let Some(model) = db
+                    .as_private_mut()
+                    .as_backup_schedules_mut()
+                    .as_idx_mut(&id)
+                {
+                    let mut schedule = model.de()?;
+                    schedule.last_run = Some(now);
+                    model.ser(&schedule)?;
+                }
+                Ok(())
+            })
+            .await
+            .result?;
+        if let Err(e) = run(ctx, &schedule).await {
+            tracing::error!("{}", t!("backup.schedule.failed-error", id = id, error = e));
+            tracing::debug!("{e:?}");
+            let err_string = e.to_string();
+            ctx.db
+                .mutate(|db| {
+                    notify(
+                        db,
+                        None,
+                        NotificationLevel::Error,
+                        t!("backup.schedule.failed-title").to_string(),
+                        t!(
+                            "backup.schedule.failed-message",
+                            target = schedule.target_id
+                        )
+                        .to_string(),
+                        BackupReport {
+                            server: ServerBackupReport {
+                                attempted: false,
+                                error: Some(err_string),
+                            },
+                            packages: BTreeMap::new(),
+                        },
+                    )
+                })
+                .await
+                .result
+                .log_err();
+        }
+    }
+    Ok(())
+}
+
+/// Start a scheduled backup and wait for it. Errors are only those that kept
+/// it from starting; once running, the backup reports its own outcome.
+async fn run(ctx: &RpcContext, schedule: &BackupSchedule) -> Result<(), Error> {
+    // a lost key file or a since-changed server password shows up here,
+    // rather than as a failure to open the target
+    let password = schedule.password(&schedule_key(false).await?)?;
+    <RpcContext as LoginContext>::check_password(&ctx.db.peek().await, &password)?;
+    let backup = start_backup(
+        ctx.clone(),
+        schedule.target_id.clone(),
+        None,
+        schedule
+            .package_ids
+            .clone()
+            .map(|ids| ids.into_iter().collect()),
+        password,
+        schedule.retention.clone(),
+    )
+    .await?;
+    backup.await.with_kind(ErrorKind::Unknown)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn snapshot(name: &str, y: i32, m: u32, d: u32, h: u32) -> BackupFsSnapshot {
+        BackupFsSnapshot {
+            name: name.to_owned(),
+            created: Utc.with_ymd_and_hms(y, m, d, h, 0, 0).unwrap(),
+        }
+    }
+
+    fn at(y: i32, mo: u32, d: u32, h: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, mo, d, h, 0, 0).unwrap()
+    }
+
+    fn nightly(created: DateTime<Utc>, last_run: Option<DateTime<Utc>>) -> BackupSchedule {
+        BackupSchedule {
+            cron: "0 3 * * *".parse().unwrap(),
+            target_id: BackupTargetId::Cifs { id: 0 },
+            package_ids: None,
+            retention: None,
+            wrapped_password: String::new(),
+            created,
+            last_run,
+        }
+    }
+
+    #[test]
+    fn next_run_counts_from_last_run_or_creation() {
+        // never run: the first slot after creation
+        let fresh = nightly(at(2026, 1, 1, 12), None);
+        assert_eq!(fresh.next_run(), Some(at(2026, 1, 2, 3)));
+        assert!(!fresh.is_due(at(2026, 1, 2, 2)));
+        assert!(fresh.is_due(at(2026, 1, 2, 3)));
+
+        let ran = nightly(at(2026, 1, 1, 12), Some(at(2026, 1, 5, 3)));
+        assert_eq!(ran.next_run(), Some(at(2026, 1, 6, 3)));
+
+        // updated since its last run: counts from the update
+        let updated = nightly(at(2026, 1, 10, 12), Some(at(2026, 1, 9, 3)));
+        assert_eq!(updated.next_run(), Some(at(2026, 1, 11, 3)));
+    }
+
+    #[test]
+    fn missed_run_is_due_once() {
+        // the server was off through several slots: one run, not one per slot
+        let missed = nightly(at(2026, 1, 1, 12), Some(at(2026, 1, 2, 3)));
+        let now = at(2026, 1, 6, 12);
+        assert!(missed.is_due(now));
+        let caught_up = nightly(at(2026, 1, 1, 12), Some(now));
+        assert_eq!(caught_up.next_run(), Some(at(2026, 1, 7, 3)));
+        assert!(!caught_up.is_due(now));
+    }
+
+    #[test]
+    fn retention_keeps_newest_per_period() {
+        // twice a day from 2026-01-01 through 2026-03-01
+        let mut snapshots = Vec::new();
+        let mut day = Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap();
+        while day <= Utc.with_ymd_and_hms(2026, 3, 1, 0, 0, 0).unwrap() {
+            for hour in [3, 15] {
+                let created = day + chrono::TimeDelta::hours(hour);
+                snapshots.push(BackupFsSnapshot {
+                    name: snapshot_name(created),
+                    created,
+                });
+            }
+            day += chrono::TimeDelta::days(1);
+        }
+        snapshots.push(snapshot("manual", 2025, 6, 1, 0));
+
+        let policy = Retention {
+            daily: 3,
+            weekly: 2,
+            monthly: 3,
+        };
+        let expired: BTreeSet<&str> = policy
+            .expired(&snapshots)
+            .into_iter()
+            .map(|s| s.name.as_str())
+            .collect();
+        let mut kept: Vec<&str> = snapshots
+            .iter()
+            .map(|s| s.name.as_str())
+            .filter(|name| !expired.contains(name))
+            .collect();
+        kept.sort();
+        assert_eq!(
+            kept,
+            [
+                "manual",
+                // monthly: end of January, end of February (the 1st of March
+                // is covered below)
+                "scheduled-20260131T150000Z",
+                // weekly: 2026-W08 ends Sunday 2026-02-22
+                "scheduled-20260222T150000Z",
+                // daily (and the newest: March, and the week of 2026-03-01)
+                "scheduled-20260227T150000Z",
+                "scheduled-20260228T150000Z",
+                "scheduled-20260301T150000Z",
+            ]
+        );
+    }
+
+    #[test]
+    fn retention_always_keeps_newest() {
+        let snapshots = [
+            snapshot("scheduled-a", 2026, 1, 1, 0),
+            snapshot("scheduled-b", 2026, 1, 2, 0),
+        ];
+        let expired = Retention::default().expired(&snapshots);
+        assert_eq!(
+            expired.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(),
+            ["scheduled-a"]
+        );
+    }
+}
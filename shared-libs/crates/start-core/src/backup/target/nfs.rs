@@ -0,0 +1,290 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use color_eyre::eyre::eyre;
+use imbl_value::InternedString;
+use rpc_toolkit::{Context, HandlerExt, ParentHandler, from_fn_async};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use super::{BackupTarget, BackupTargetId};
+use crate::context::{CliContext, RpcContext};
+use crate::db::model::DatabaseModel;
+use crate::disk::mount::filesystem::ReadOnly;
+use crate::disk::mount::filesystem::nfs::Nfs;
+use crate::disk::mount::guard::{GenericMountGuard, TmpMountGuard};
+use crate::disk::util::{StartOsRecoveryInfo, get_available, has_legacy_backup, recovery_info};
+use crate::prelude::*;
+use crate::util::serde::KeyVal;
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct NfsTargets(pub BTreeMap<u32, Nfs>);
+impl NfsTargets {
+    pub fn new() -> Self {
+        Self(BTreeMap::new())
+    }
+}
+impl Map for NfsTargets {
+    type Key = u32;
+    type Value = Nfs;
+    fn key_str(key: &Self::Key) -> Result<impl AsRef<str>, Error> {
+        Self::key_string(key)
+    }
+    fn key_string(key: &Self::Key) -> Result<InternedString, Error> {
+        Ok(InternedString::from_display(key))
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub struct NfsBackupTarget {
+    hostname: String,
+    path: PathBuf,
+    mountable: bool,
+    #[ts(type = "number | null")]
+    available: Option<u64>,
+    start_os: BTreeMap<String, StartOsRecoveryInfo>,
+    legacy_backup: bool,
+}
+
+pub fn nfs<C: Context>() -> ParentHandler<C> {
+    ParentHandler::new()
+        .subcommand(
+            "add",
+            from_fn_async(add)
+                .no_display()
+                .with_about("about.add-new-backup-target")
+                .with_call_remote::<CliContext>(),
+        )
+        .subcommand(
+            "update",
+            from_fn_async(update)
+                .no_display()
+                .with_about("about.update-existing-backup-target")
+                .with_call_remote::<CliContext>(),
+        )
+        .subcommand(
+            "remove",
+            from_fn_async(remove)
+                .no_display()
+                .with_about("about.remove-existing-backup-target")
+                .with_call_remote::<CliContext>(),
+        )
+}
+
+async fn probe(
+    nfs: &Nfs,
+    server_id: &str,
+) -> Result<(BTreeMap<String, StartOsRecoveryInfo>, Option<u64>, bool), Error> {
+    // checked first, so a dead server fails now rather than after the timeouts
+    nfs.reachable().await?;
+    let guard = TmpMountGuard::mount(nfs, ReadOnly).await?;
+    let start_os = recovery_info(guard.path()).await?;
+    let available = get_available(guard.path()).await.ok();
+    let legacy_backup = has_legacy_backup(guard.path(), server_id).await;
+    guard.unmount().await?;
+    Ok((start_os, available, legacy_backup))
+}
+
+#[derive(Deserialize, Serialize, Parser, TS)]
+#[group(skip)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+#[command(rename_all = "kebab-case")]
+pub struct NfsAddParams {
+    #[arg(help = "help.arg.nfs-hostname")]
+    pub hostname: String,
+    #[arg(help = "help.arg.nfs-path")]
+    pub path: PathBuf,
+}
+
+pub async fn add(
+    ctx: RpcContext,
+    NfsAddParams { hostname, path }: NfsAddParams,
+) -> Result<KeyVal<BackupTargetId, BackupTarget>, Error> {
+    let nfs = Nfs {
+        hostname,
+        path: Path::new("/").join(path),
+    };
+    let server_id = ctx
+        .db
+        .peek()
+        .await
+        .as_public()
+        .as_server_info()
+        .as_id()
+        .de()?;
+    let (start_os, available, legacy_backup) = probe(&nfs, &server_id).await?;
+    let id = ctx
+        .db
+        .mutate(|db| {
+            let id = db
+                .as_private()
+                .as_nfs()
+                .keys()?
+                .into_iter()
+                .max()
+                .map_or(0, |a| a + 1);
+            db.as_private_mut().as_nfs_mut().insert(&id, &nfs)?;
+            Ok(id)
+        })
+        .await
+        .result?;
+    Ok(KeyVal {
+        key: BackupTargetId::Nfs { id },
+        value: BackupTarget::Nfs(NfsBackupTarget {
+            hostname: nfs.hostname,
+            path: nfs.path,
+            mountable: true,
+            available,
+            start_os,
+            legacy_backup,
+        }),
+    })
+}
+
+#[derive(Deserialize, Serialize, Parser, TS)]
+#[group(skip)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+#[command(rename_all = "kebab-case")]
+pub struct NfsUpdateParams {
+    #[arg(help = "help.arg.backup-target-id")]
+    pub id: BackupTargetId,
+    #[arg(help = "help.arg.nfs-hostname")]
+    pub hostname: String,
+    #[arg(help = "help.arg.nfs-path")]
+    pub path: PathBuf,
+}
+
+pub async fn update(
+    ctx: RpcContext,
+    NfsUpdateParams { id, hostname, path }: NfsUpdateParams,
+) -> Result<KeyVal<BackupTargetId, BackupTarget>, Error> {
+    let id = if let BackupTargetId::Nfs { id } = id {
+        id
+    } else {
+        return Err(Error::new(
+            eyre!("{}", t!("backup.target.nfs.target-not-found", id = id)),
+            ErrorKind::NotFound,
+        ));
+    };
+    let nfs = Nfs {
+        hostname,
+        path: Path::new("/").join(path),
+    };
+    let server_id = ctx
+        .db
+        .peek()
+        .await
+        .as_public()
+        .as_server_info()
+        .as_id()
+        .de()?;
+    let (start_os, available, legacy_backup) = probe(&nfs, &server_id).await?;
+    ctx.db
+        .mutate(|db| {
+            db.as_private_mut()
+                .as_nfs_mut()
+                .as_idx_mut(&id)
+                .ok_or_else(|| {
+                    Error::new(
+                        eyre!(
+                            "{}",
+                            t!(
+                                "backup.target.nfs.target-not-found",
+                                id = BackupTargetId::Nfs { id }
+                            )
+                        ),
+                        ErrorKind::NotFound,
+                    )
+                })?
+                .ser(&nfs)
+        })
+        .await
+        .result?;
+    Ok(KeyVal {
+        key: BackupTargetId::Nfs { id },
+        value: BackupTarget::Nfs(NfsBackupTarget {
+            hostname: nfs.hostname,
+            path: nfs.path,
+            mountable: true,
+            available,
+            start_os,
+            legacy_backup,
+        }),
+    })
+}
+
+#[derive(Deserialize, Serialize, Parser, TS)]
+#[group(skip)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+#[command(rename_all = "kebab-case")]
+pub struct NfsRemoveParams {
+    #[arg(help = "help.arg.backup-target-id")]
+    pub id: BackupTargetId,
+}
+
+pub async fn remove(ctx: RpcContext, NfsRemoveParams { id }: NfsRemoveParams) -> Result<(), Error> {
+    let id = if let BackupTargetId::Nfs { id } = id {
+        id
+    } else {
+        return Err(Error::new(
+            eyre!("{}", t!("backup.target.nfs.target-not-found", id = id)),
+            ErrorKind::NotFound,
+        ));
+    };
+    ctx.db
+        .mutate(|db| db.as_private_mut().as_nfs_mut().remove(&id))
+        .await
+        .result?;
+    Ok(())
+}
+
+pub fn load(db: &DatabaseModel, id: u32) -> Result<Nfs, Error> {
+    db.as_private()
+        .as_nfs()
+        .as_idx(&id)
+        .ok_or_else(|| {
+            Error::new(
+                eyre!(
+                    "{}",
+                    t!(
+                        "backup.target.nfs.target-not-found",
+                        id = BackupTargetId::Nfs { id }
+                    )
+                ),
+                ErrorKind::NotFound,
+            )
+        })?
+        .de()
+}
+
+pub async fn list(
+    db: &DatabaseModel,
+    server_id: &str,
+) -> Result<Vec<(u32, NfsBackupTarget)>, Error> {
+    let mut nfs = Vec::new();
+    for (id, model) in db.as_private().as_nfs().as_entries()? {
+        let mount_info = model.de()?;
+        let info = probe(&mount_info, server_id).await;
+        let mountable = info.is_ok();
+        let (start_os, available, legacy_backup) = info.ok().unwrap_or_default();
+        nfs.push((
+            id,
+            NfsBackupTarget {
+                hostname: mount_info.hostname,
+                path: mount_info.path,
+                mountable,
+                available,
+                start_os,
+                legacy_backup,
+            },
+        ));
+    }
+
+    Ok(nfs)
+}
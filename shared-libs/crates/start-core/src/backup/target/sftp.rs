@@ -0,0 +1,418 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use color_eyre::eyre::eyre;
+use imbl_value::InternedString;
+use rpc_toolkit::{Context, HandlerExt, ParentHandler, from_fn_async};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use super::{BackupTarget, BackupTargetId};
+use crate::context::{CliContext, RpcContext};
+use crate::db::model::DatabaseModel;
+use crate::disk::mount::filesystem::ReadOnly;
+use crate::disk::mount::filesystem::sftp::{
+    SFTP_PORT, Sftp, SftpHostKey, confirm_host_keys, scan_host_keys, validate_username,
+};
+use crate::disk::mount::guard::{GenericMountGuard, TmpMountGuard};
+use crate::disk::util::{StartOsRecoveryInfo, get_available, has_legacy_backup, recovery_info};
+use crate::prelude::*;
+use crate::util::serde::KeyVal;
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct SftpTargets(pub BTreeMap<u32, Sftp>);
+impl SftpTargets {
+    pub fn new() -> Self {
+        Self(BTreeMap::new())
+    }
+}
+impl Map for SftpTargets {
+    type Key = u32;
+    type Value = Sftp;
+    fn key_str(key: &Self::Key) -> Result<impl AsRef<str>, Error> {
+        Self::key_string(key)
+    }
+    fn key_string(key: &Self::Key) -> Result<InternedString, Error> {
+        Ok(InternedString::from_display(key))
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub struct SftpBackupTarget {
+    hostname: String,
+    port: u16,
+    path: PathBuf,
+    username: String,
+    mountable: bool,
+    #[ts(type = "number | null")]
+    available: Option<u64>,
+    start_os: BTreeMap<String, StartOsRecoveryInfo>,
+    legacy_backup: bool,
+}
+
+pub fn sftp<C: Context>() -> ParentHandler<C> {
+    ParentHandler::new()
+        .subcommand(
+            "scan-host-keys",
+            from_fn_async(scan)
+                .with_display_serializable()
+                .with_about("about.scan-sftp-host-keys")
+                .with_call_remote::<CliContext>(),
+        )
+        .subcommand(
+            "add",
+            from_fn_async(add)
+                .no_display()
+                .with_about("about.add-new-backup-target")
+                .with_call_remote::<CliContext>(),
+        )
+        .subcommand(
+            "update",
+            from_fn_async(update)
+                .no_display()
+                .with_about("about.update-existing-backup-target")
+                .with_call_remote::<CliContext>(),
+        )
+        .subcommand(
+            "remove",
+            from_fn_async(remove)
+                .no_display()
+                .with_about("about.remove-existing-backup-target")
+                .with_call_remote::<CliContext>(),
+        )
+}
+
+#[derive(Deserialize, Serialize, Parser, TS)]
+#[group(skip)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+#[command(rename_all = "kebab-case")]
+pub struct SftpScanParams {
+    #[arg(help = "help.arg.sftp-hostname")]
+    pub hostname: String,
+    #[arg(long, help = "help.arg.sftp-port")]
+    pub port: Option<u16>,
+}
+
+/// The keys `hostname` presents now, for the user to confirm before passing
+/// them back as `hostKeys` to `add` or `update`.
+pub async fn scan(
+    _: RpcContext,
+    SftpScanParams { hostname, port }: SftpScanParams,
+) -> Result<Vec<SftpHostKey>, Error> {
+    scan_host_keys(&hostname, port.unwrap_or(SFTP_PORT)).await
+}
+
+/// Parse user-confirmed host keys. Without any, nothing is trusted: the keys
+/// the server presents now are returned in the error, for the user to confirm
+/// and pass back.
+async fn confirmed_host_keys(
+    hostname: &str,
+    port: u16,
+    host_keys: Option<Vec<String>>,
+) -> Result<Vec<String>, Error> {
+    if let Some(keys) = host_keys {
+        return confirm_host_keys(hostname, keys);
+    }
+    let scanned = scan_host_keys(hostname, port).await?;
+    Err(Error::new(
+        eyre!(
+            "{}",
+            t!(
+                "backup.target.sftp.confirm-host-keys",
+                host = hostname,
+                fingerprints = scanned
+                    .iter()
+                    .map(|key| key.fingerprint.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        ),
+        ErrorKind::InvalidRequest,
+    )
+    .with_info(to_value(&scanned)?))
+}
+
+async fn probe(
+    sftp: &Sftp,
+    server_id: &str,
+) -> Result<(BTreeMap<String, StartOsRecoveryInfo>, Option<u64>, bool), Error> {
+    sftp.reachable().await?;
+    let guard = TmpMountGuard::mount(sftp, ReadOnly).await?;
+    let start_os = recovery_info(guard.path()).await?;
+    let available = get_available(guard.path()).await.ok();
+    let legacy_backup = has_legacy_backup(guard.path(), server_id).await;
+    guard.unmount().await?;
+    Ok((start_os, available, legacy_backup))
+}
+
+#[derive(Deserialize, Serialize, Parser, TS)]
+#[group(skip)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+#[command(rename_all = "kebab-case")]
+pub struct SftpAddParams {
+    #[arg(help = "help.arg.sftp-hostname")]
+    pub hostname: String,
+    #[arg(long, help = "help.arg.sftp-port")]
+    pub port: Option<u16>,
+    #[arg(help = "help.arg.sftp-path")]
+    pub path: PathBuf,
+    #[arg(help = "help.arg.sftp-username")]
+    pub username: String,
+    #[arg(help = "help.arg.sftp-password")]
+    pub password: Option<String>,
+    #[arg(long, help = "help.arg.sftp-private-key")]
+    pub private_key: Option<String>,
+    /// Keys confirmed from `scan-host-keys`. Required: without them the
+    /// request fails with the keys the server presents.
+    #[arg(long = "host-key", help = "help.arg.sftp-host-keys")]
+    pub host_keys: Option<Vec<String>>,
+}
+
+pub async fn add(
+    ctx: RpcContext,
+    SftpAddParams {
+        hostname,
+        port,
+        path,
+        username,
+        password,
+        private_key,
+        host_keys,
+    }: SftpAddParams,
+) -> Result<KeyVal<BackupTargetId, BackupTarget>, Error> {
+    validate_username(&username)?;
+    let port = port.unwrap_or(SFTP_PORT);
+    let host_keys = confirmed_host_keys(&hostname, port, host_keys).await?;
+    let sftp = Sftp {
+        hostname,
+        port,
+        path: Path::new("/").join(path),
+        username,
+        password,
+        private_key,
+        host_keys,
+    };
+    let server_id = ctx
+        .db
+        .peek()
+        .await
+        .as_public()
+        .as_server_info()
+        .as_id()
+        .de()?;
+    let (start_os, available, legacy_backup) = probe(&sftp, &server_id).await?;
+    let id = ctx
+        .db
+        .mutate(|db| {
+            let id = db
+                .as_private()
+                .as_sftp()
+                .keys()?
+                .into_iter()
+                .max()
+                .map_or(0, |a| a + 1);
+            db.as_private_mut().as_sftp_mut().insert(&id, &sftp)?;
+            Ok(id)
+        })
+        .await
+        .result?;
+    Ok(KeyVal {
+        key: BackupTargetId::Sftp { id },
+        value: BackupTarget::Sftp(SftpBackupTarget {
+            hostname: sftp.hostname,
+            port: sftp.port,
+            path: sftp.path,
+            username: sftp.username,
+            mountable: true,
+            available,
+            start_os,
+            legacy_backup,
+        }),
+    })
+}
+
+#[derive(Deserialize, Serialize, Parser, TS)]
+#[group(skip)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+#[command(rename_all = "kebab-case")]
+pub struct SftpUpdateParams {
+    #[arg(help = "help.arg.backup-target-id")]
+    pub id: BackupTargetId,
+    #[arg(help = "help.arg.sftp-hostname")]
+    pub hostname: String,
+    #[arg(long, help = "help.arg.sftp-port")]
+    pub port: Option<u16>,
+    #[arg(help = "help.arg.sftp-path")]
+    pub path: PathBuf,
+    #[arg(help = "help.arg.sftp-username")]
+    pub username: String,
+    #[arg(help = "help.arg.sftp-password")]
+    pub password: Option<String>,
+    #[arg(long, help = "help.arg.sftp-private-key")]
+    pub private_key: Option<String>,
+    /// Keys confirmed from `scan-host-keys`. Without them the pinned keys are
+    /// kept, unless the hostname or port changed, in which case the request
+    /// fails with the keys the server presents.
+    #[arg(long = "host-key", help = "help.arg.sftp-host-keys")]
+    pub host_keys: Option<Vec<String>>,
+}
+
+pub async fn update(
+    ctx: RpcContext,
+    SftpUpdateParams {
+        id,
+        hostname,
+        port,
+        path,
+        username,
+        password,
+        private_key,
+        host_keys,
+    }: SftpUpdateParams,
+) -> Result<KeyVal<BackupTargetId, BackupTarget>, Error> {
+    let id = if let BackupTargetId::Sftp { id } = id {
+        id
+    } else {
+        return Err(Error::new(
+            eyre!("{}", t!("backup.target.sftp.target-not-found", id = id)),
+            ErrorKind::NotFound,
+        ));
+    };
+    validate_username(&username)?;
+    let port = port.unwrap_or(SFTP_PORT);
+    let peek = ctx.db.peek().await;
+    let existing = load(&peek, id)?;
+    let host_keys = if host_keys.is_none() && existing.hostname == hostname && existing.port == port
+    {
+        existing.host_keys
+    } else {
+        confirmed_host_keys(&hostname, port, host_keys).await?
+    };
+    let sftp = Sftp {
+        hostname,
+        port,
+        path: Path::new("/").join(path),
+        username,
+        password,
+        private_key,
+        host_keys,
+    };
+    let server_id = peek.as_public().as_server_info().as_id().de()?;
+    let (start_os, available, legacy_backup) = probe(&sftp, &server_id).await?;
+    ctx.db
+        .mutate(|db| {
+            db.as_private_mut()
+                .as_sftp_mut()
+                .as_idx_mut(&id)
+                .ok_or_else(|| {
+                    Error::new(
+                        eyre!(
+                            "{}",
+                            t!(
+                                "backup.target.sftp.target-not-found",
+                                id = BackupTargetId::Sftp { id }
+                            )
+                        ),
+                        ErrorKind::NotFound,
+                    )
+                })?
+                .ser(&sftp)
+        })
+        .await
+        .result?;
+    Ok(KeyVal {
+        key: BackupTargetId::Sftp { id },
+        value: BackupTarget::Sftp(SftpBackupTarget {
+            hostname: sftp.hostname,
+            port: sftp.port,
+            path: sftp.path,
+            username: sftp.username,
+            mountable: true,
+            available,
+            start_os,
+            legacy_backup,
+        }),
+    })
+}
+
+#[derive(Deserialize, Serialize, Parser, TS)]
+#[group(skip)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+#[command(rename_all = "kebab-case")]
+pub struct SftpRemoveParams {
+    #[arg(help = "help.arg.backup-target-id")]
+    pub id: BackupTargetId,
+}
+
+pub async fn remove(
+    ctx: RpcContext,
+    SftpRemoveParams { id }: SftpRemoveParams,
+) -> Result<(), Error> {
+    let id = if let BackupTargetId::Sftp { id } = id {
+        id
+    } else {
+        return Err(Error::new(
+            eyre!("{}", t!("backup.target.sftp.target-not-found", id = id)),
+            ErrorKind::NotFound,
+        ));
+    };
+    ctx.db
+        .mutate(|db| db.as_private_mut().as_sftp_mut().remove(&id))
+        .await
+        .result?;
+    Ok(())
+}
+
+pub fn load(db: &DatabaseModel, id: u32) -> Result<Sftp, Error> {
+    db.as_private()
+        .as_sftp()
+        .as_idx(&id)
+        .ok_or_else(|| {
+            Error::new(
+                eyre!(
+                    "{}",
+                    t!(
+                        "backup.target.sftp.target-not-found",
+                        id = BackupTargetId::Sftp { id }
+                    )
+                ),
+                ErrorKind::NotFound,
+            )
+        })?
+        .de()
+}
+
+pub async fn list(
+    db: &DatabaseModel,
+    server_id: &str,
+) -> Result<Vec<(u32, SftpBackupTarget)>, Error> {
+    let mut sftp = Vec::new();
+    for (id, model) in db.as_private().as_sftp().as_entries()? {
+        let mount_info = model.de()?;
+        let info = probe(&mount_info, server_id).await;
+        let mountable = info.is_ok();
+        let (start_os, available, legacy_backup) = info.ok().unwrap_or_default();
+        sftp.push((
+            id,
+            SftpBackupTarget {
+                hostname: mount_info.hostname,
+                port: mount_info.port,
+                path: mount_info.path,
+                username: mount_info.username,
+                mountable,
+                available,
+                start_os,
+                legacy_backup,
+            },
+        ));
+    }
+
+    Ok(sftp)
+}
@@ -3,6 +3,7 @@ use std::ffi::OsString;
 use std::path::Path;
 use std::sync::OnceLock;
 
+use rpc_toolkit::CliApp;
 use rust_i18n::t;
 
 pub mod container_cli;
@@ -41,6 +42,23 @@ pub fn startos_version() -> &'static str {
     env!("STARTOS_VERSION")
 }
 
+/// Keeps a CLI's `repl` history next to the local config, in
+/// `~/.startos/<bin>_history`.
+fn with_repl_history<C, Cfg>(app: CliApp<C, Cfg>, bin: &str) -> CliApp<C, Cfg>
+where
+    C: rpc_toolkit::Context + Clone,
+    Cfg: clap::CommandFactory + clap::FromArgMatches,
+{
+    match std::env::var("HOME") {
+        Ok(home) => app.repl_history(
+            Path::new(&home)
+                .join(".startos")
+                .join(format!("{bin}_history")),
+        ),
+        Err(_) => app,
+    }
+}
+
 pub fn set_locale_from_env() {
     let lang = std::env::var("LANG").ok();
     let lang = lang
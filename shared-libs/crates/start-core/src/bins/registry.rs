@@ -94,12 +94,15 @@ pub fn main(args: impl IntoIterator<Item = OsString>) {
 }
 
 fn app() -> CliApp<CliContext, ClientConfig> {
-    CliApp::new(
-        |cfg: ClientConfig| Ok(CliContext::init(cfg.load()?)?),
-        crate::registry::registry_api(),
+    super::with_repl_history(
+        CliApp::new(
+            |cfg: ClientConfig| Ok(CliContext::init(cfg.load()?)?),
+            crate::registry::registry_api(),
+        )
+        .mutate_command(super::translate_cli)
+        .mutate_command(|cmd| cmd.name("start-registry").version(super::product_version())),
+        "start-registry",
     )
-    .mutate_command(super::translate_cli)
-    .mutate_command(|cmd| cmd.name("start-registry").version(super::product_version()))
 }
 
 pub fn cli(args: impl IntoIterator<Item = OsString>) {
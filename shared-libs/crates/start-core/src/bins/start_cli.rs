@@ -8,12 +8,15 @@ use crate::context::config::ClientConfig;
 use crate::util::logger::LOGGER;
 
 fn app() -> CliApp<CliContext, ClientConfig> {
-    CliApp::new(
-        |cfg: ClientConfig| Ok(CliContext::init(cfg.load()?)?),
-        crate::main_api(),
+    super::with_repl_history(
+        CliApp::new(
+            |cfg: ClientConfig| Ok(CliContext::init(cfg.load()?)?),
+            crate::main_api(),
+        )
+        .mutate_command(super::translate_cli)
+        .mutate_command(|cmd| cmd.name("start-cli").version(super::cli_version())),
+        "start-cli",
     )
-    .mutate_command(super::translate_cli)
-    .mutate_command(|cmd| cmd.name("start-cli").version(super::cli_version()))
 }
 
 pub fn main(args: impl IntoIterator<Item = OsString>) {
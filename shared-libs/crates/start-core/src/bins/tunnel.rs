@@ -211,12 +211,15 @@ pub fn main(args: impl IntoIterator<Item = OsString>) {
 }
 
 fn app() -> CliApp<CliContext, ClientConfig> {
-    CliApp::new(
-        |cfg: ClientConfig| Ok(CliContext::init(cfg.load()?)?),
-        crate::tunnel::api::tunnel_api(),
+    super::with_repl_history(
+        CliApp::new(
+            |cfg: ClientConfig| Ok(CliContext::init(cfg.load()?)?),
+            crate::tunnel::api::tunnel_api(),
+        )
+        .mutate_command(super::translate_cli)
+        .mutate_command(|cmd| cmd.name("start-tunnel").version(super::product_version())),
+        "start-tunnel",
     )
-    .mutate_command(super::translate_cli)
-    .mutate_command(|cmd| cmd.name("start-tunnel").version(super::product_version()))
 }
 
 pub fn cli(args: impl IntoIterator<Item = OsString>) {
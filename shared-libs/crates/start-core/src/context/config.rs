@@ -6,11 +6,13 @@ use std::path::{Path, PathBuf};
 use clap::Parser;
 use clap::builder::{StringValueParser, TypedValueParser, ValueParser, ValueParserFactory};
 use imbl_value::InternedString;
+use patch_db::{FileStorage, HistoryOptions};
 use reqwest::Url;
 use serde::de::{DeserializeOwned, MapAccess, Visitor};
 use serde::{Deserialize, Deserializer, Serialize};
 
 use crate::MAIN_DATA;
+use crate::db::PRIVATE;
 use crate::prelude::*;
 use crate::util::serde::IoFormat;
 use crate::version::VersionT;
@@ -325,6 +327,8 @@ pub struct ServerConfig {
     pub id_key_path: Option<PathBuf>,
     #[arg(long, help = "help.arg.max-proxy-conns-per-target")]
     pub max_proxy_conns_per_target: Option<usize>,
+    #[arg(long, help = "help.arg.db-history")]
+    pub db_history: Option<bool>,
 }
 impl ContextConfig for ServerConfig {
     fn next(&mut self) -> Option<PathBuf> {
@@ -343,6 +347,7 @@ impl ContextConfig for ServerConfig {
             .max_proxy_conns_per_target
             .take()
             .or(other.max_proxy_conns_per_target);
+        self.db_history = self.db_history.take().or(other.db_history);
     }
 }
 
@@ -356,9 +361,23 @@ impl ServerConfig {
     }
     pub async fn db(&self) -> Result<PatchDb, Error> {
         let db_path = Path::new(MAIN_DATA).join("embassy.db");
-        let db = PatchDb::open(&db_path)
-            .await
-            .with_ctx(|_| (crate::ErrorKind::Filesystem, db_path.display().to_string()))?;
+        let db = async {
+            if self.db_history.unwrap_or(false) {
+                PatchDb::open_with_history(
+                    FileStorage::open(&db_path).await?,
+                    db_path.with_extension("history"),
+                    HistoryOptions {
+                        exclude: vec![PRIVATE.clone()],
+                        ..Default::default()
+                    },
+                )
+                .await
+            } else {
+                PatchDb::open(&db_path).await
+            }
+        }
+        .await
+        .with_ctx(|_| (crate::ErrorKind::Filesystem, db_path.display().to_string()))?;
 
         Ok(db)
     }
@@ -515,6 +515,18 @@ impl RpcContext {
         self.services.init(&self).await?;
         init_services.complete();
 
+        // Started once services are up: a scheduled backup may be due right
+        // away if one was missed while the server was off.
+        let weak = Arc::downgrade(&self.0);
+        self.add_cron(crate::backup::schedule::run_schedules(move || {
+            weak.upgrade().map(RpcContext)
+        }));
+        // Queued deliveries are persisted, so any made during startup go out now.
+        let weak = Arc::downgrade(&self.0);
+        self.add_cron(crate::notifications::delivery::run_delivery(move || {
+            weak.upgrade().map(RpcContext)
+        }));
+
         prune_s9pks.start();
         let peek = self.db.peek().await;
         let keep = peek
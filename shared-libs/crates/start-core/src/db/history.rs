@@ -0,0 +1,157 @@
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use imbl_value::InternedString;
+use patch_db::HistoryEntry;
+use patch_db::json_patch::Patch;
+use patch_db::json_ptr::JsonPointer;
+use rpc_toolkit::{Context, HandlerExt, ParentHandler, from_fn_async};
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::context::{CliContext, RpcContext};
+use crate::db::{PRIVATE, PUBLIC};
+use crate::prelude::*;
+use crate::sign::AnyVerifyingKey;
+use crate::util::serde::HandlerExtSerde;
+
+pub fn history<C: Context>() -> ParentHandler<C> {
+    ParentHandler::new()
+        .subcommand(
+            "list",
+            from_fn_async(list)
+                .with_display_serializable()
+                .with_about("about.list-db-history")
+                .with_call_remote::<CliContext>(),
+        )
+        .subcommand(
+            "diff",
+            from_fn_async(diff)
+                .with_display_serializable()
+                .with_about("about.diff-db-revisions")
+                .with_call_remote::<CliContext>(),
+        )
+}
+
+/// Who made a change, as recorded by the RPC middleware. Changes made by the
+/// server itself have neither.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ChangeSource {
+    method: Option<InternedString>,
+    signer: Option<AnyVerifyingKey>,
+}
+
+#[derive(Debug, Deserialize, Serialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntryInfo {
+    #[ts(type = "number")]
+    pub id: u64,
+    #[ts(type = "string")]
+    pub timestamp: DateTime<Utc>,
+    /// The RPC method that made the change
+    pub method: Option<InternedString>,
+    /// The key that signed the request, if it was not made locally
+    pub signer: Option<AnyVerifyingKey>,
+    /// The change, relative to the pointer listed
+    #[ts(type = "unknown[]")]
+    pub patch: Patch,
+}
+impl From<HistoryEntry> for HistoryEntryInfo {
+    fn from(entry: HistoryEntry) -> Self {
+        let ChangeSource { method, signer } = from_value(entry.metadata).unwrap_or_default();
+        Self {
+            id: entry.id,
+            timestamp: entry.timestamp.into(),
+            method,
+            signer,
+            patch: (*entry.patch).clone(),
+        }
+    }
+}
+
+/// `pointer`, or `/public` by default. `/private` is left out of the history, so
+/// pointers into it are rejected rather than answered with nothing.
+fn history_pointer(pointer: Option<JsonPointer>) -> Result<JsonPointer, Error> {
+    let pointer = pointer.unwrap_or_else(|| PUBLIC.clone());
+    if pointer.starts_with(&*PRIVATE) {
+        return Err(Error::new(
+            eyre!("{}", t!("db.history.private-pointer", pointer = pointer)),
+            ErrorKind::InvalidRequest,
+        ));
+    }
+    Ok(pointer)
+}
+
+#[derive(Deserialize, Serialize, Parser, TS)]
+#[group(skip)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+#[command(rename_all = "kebab-case")]
+pub struct ListHistoryParams {
+    #[arg(long, help = "help.arg.db-history-pointer")]
+    #[ts(type = "string | null")]
+    pointer: Option<JsonPointer>,
+    #[arg(long, help = "help.arg.db-history-before")]
+    #[ts(type = "number | null")]
+    before: Option<u64>,
+    #[arg(long, help = "help.arg.db-history-limit")]
+    #[ts(type = "number | null")]
+    limit: Option<usize>,
+}
+
+/// The recorded changes under `pointer`, newest first.
+pub async fn list(
+    ctx: RpcContext,
+    ListHistoryParams {
+        pointer,
+        before,
+        limit,
+    }: ListHistoryParams,
+) -> Result<Vec<HistoryEntryInfo>, Error> {
+    let pointer = history_pointer(pointer)?;
+    let entries = match before {
+        Some(before) => ctx.db.history(pointer, ..before).await?,
+        None => ctx.db.history(pointer, ..).await?,
+    };
+    Ok(entries
+        .into_iter()
+        .rev()
+        .take(limit.unwrap_or(40))
+        .map(HistoryEntryInfo::from)
+        .collect())
+}
+
+#[derive(Deserialize, Serialize, Parser, TS)]
+#[group(skip)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+#[command(rename_all = "kebab-case")]
+pub struct DiffRevisionsParams {
+    #[arg(help = "help.arg.db-history-from")]
+    #[ts(type = "number")]
+    from: u64,
+    #[arg(help = "help.arg.db-history-to")]
+    #[ts(type = "number | null")]
+    to: Option<u64>,
+    #[arg(long, help = "help.arg.db-history-pointer")]
+    #[ts(type = "string | null")]
+    pointer: Option<JsonPointer>,
+}
+
+/// The patch that takes `pointer` from how it was at revision `from` to how it
+/// was at `to`, or is now. Both sides come from the history, so neither
+/// includes `/private`.
+pub async fn diff(
+    ctx: RpcContext,
+    DiffRevisionsParams { from, to, pointer }: DiffRevisionsParams,
+) -> Result<Patch, Error> {
+    let pointer = history_pointer(pointer)?;
+    let to = match to {
+        Some(to) => to,
+        None => ctx.db.sequence().await,
+    };
+    let before = ctx.db.value_at(pointer.clone(), from).await?;
+    let after = ctx.db.value_at(pointer, to).await?;
+    Ok(patch_db::json_patch::diff(&before, &after))
+}
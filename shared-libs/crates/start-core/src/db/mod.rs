@@ -1,3 +1,4 @@
+pub mod history;
 pub mod model;
 pub mod prelude;
 
@@ -9,8 +10,9 @@ use axum::extract::ws;
 use clap::Parser;
 use imbl_value::InternedString;
 use itertools::Itertools;
+use patch_db::json_patch::{AddOperation, Patch, PatchOperation};
 use patch_db::json_ptr::{JsonPointer, ROOT};
-use patch_db::{DiffPatch, Dump, Revision};
+use patch_db::{Catchup, DiffPatch, Dump, Revision};
 use rpc_toolkit::yajrc::RpcError;
 use rpc_toolkit::{Context, HandlerArgs, HandlerExt, ParentHandler, from_fn_async};
 use serde::{Deserialize, Serialize};
@@ -22,10 +24,11 @@ use ts_rs::TS;
 use crate::context::{CliContext, RpcContext};
 use crate::prelude::*;
 use crate::rpc_continuations::{Guid, RpcContinuation};
-use crate::util::serde::{HandlerExtSerde, apply_expr};
+use crate::util::serde::{CliFromJsonString, HandlerExtSerde, apply_expr};
 
 lazy_static::lazy_static! {
     static ref PUBLIC: JsonPointer = "/public".parse().unwrap();
+    pub(crate) static ref PRIVATE: JsonPointer = "/private".parse().unwrap();
 }
 
 pub trait DbAccess<T>: Sized {
@@ -56,7 +59,12 @@ pub fn db<C: Context>() -> ParentHandler<C> {
                 .with_display_serializable()
                 .with_about("about.filter-query-db"),
         )
-        .subcommand("dump", from_fn_async(dump).no_cli())
+        .subcommand(
+            "dump",
+            from_fn_async(dump)
+                .with_metadata("timeout", Value::from(60))
+                .no_cli(),
+        )
         .subcommand(
             "subscribe",
             from_fn_async(subscribe)
@@ -74,6 +82,17 @@ pub fn db<C: Context>() -> ParentHandler<C> {
                 .with_about("about.update-db-record"),
         )
         .subcommand("apply", from_fn_async(apply).no_cli())
+        .subcommand(
+            "patch",
+            from_fn_async(patch)
+                .with_display_serializable()
+                .with_about("about.apply-db-json-patch")
+                .with_call_remote::<CliContext>(),
+        )
+        .subcommand(
+            "history",
+            history::history::<C>().with_about("about.db-history"),
+        )
 }
 
 #[derive(Deserialize, Serialize)]
@@ -151,6 +170,11 @@ pub async fn dump(ctx: RpcContext, DumpParams { pointer }: DumpParams) -> Result
 pub struct SubscribeParams {
     #[ts(type = "string | null")]
     pointer: Option<JsonPointer>,
+    /// The last revision the client applied, if it is resuming a previous
+    /// subscription to the same pointer
+    #[ts(type = "number | null")]
+    #[serde(default)]
+    revision: Option<u64>,
     #[ts(skip)]
     #[serde(rename = "__Auth_signer")]
     signer: Option<InternedString>,
@@ -159,8 +183,10 @@ pub struct SubscribeParams {
 #[derive(Deserialize, Serialize, TS)]
 #[serde(rename_all = "camelCase")]
 pub struct SubscribeRes {
-    #[ts(type = "{ id: number; value: unknown }")]
-    pub dump: Dump,
+    /// `null` when resuming from a revision the server still retains: the
+    /// revisions missed since then are sent first on the websocket instead
+    #[ts(type = "{ id: number; value: unknown } | null")]
+    pub dump: Option<Dump>,
     pub guid: Guid,
 }
 
@@ -203,14 +229,29 @@ impl DbSubscriber {
 
 pub async fn subscribe(
     ctx: RpcContext,
-    SubscribeParams { pointer, signer }: SubscribeParams,
+    SubscribeParams {
+        pointer,
+        revision,
+        signer,
+    }: SubscribeParams,
 ) -> Result<SubscribeRes, Error> {
-    let (dump, sub) = ctx
-        .db
-        .dump_and_sub(pointer.unwrap_or_else(|| PUBLIC.clone()))
-        .await;
+    let pointer = pointer.unwrap_or_else(|| PUBLIC.clone());
+    let (dump, missed, sub) = if let Some(revision) = revision {
+        match ctx.db.subscribe_from(pointer, revision).await {
+            (Catchup::Revisions(missed), sub) => (None, missed, sub),
+            (Catchup::Dump(dump), sub) => (Some(dump), Vec::new(), sub),
+        }
+    } else {
+        let (dump, sub) = ctx.db.dump_and_sub(pointer).await;
+        (Some(dump), Vec::new(), sub)
+    };
     let mut sub = DbSubscriber {
-        rev: dump.id,
+        rev: missed
+            .last()
+            .map(|rev| rev.id)
+            .or(dump.as_ref().map(|dump| dump.id))
+            .or(revision)
+            .unwrap_or_default(),
         sub,
         sync_db: ctx.sync_db.subscribe(),
     };
@@ -223,6 +264,15 @@ pub async fn subscribe(
                 signer,
                 |mut ws| async move {
                     if let Err(e) = async {
+                        for rev in missed {
+                            ws.send(ws::Message::Text(
+                                serde_json::to_string(&rev)
+                                    .with_kind(ErrorKind::Serialization)?
+                                    .into(),
+                            ))
+                            .await
+                            .with_kind(ErrorKind::Network)?;
+                        }
                         loop {
                             tokio::select! {
                                 rev = sub.recv() => {
@@ -273,6 +323,8 @@ pub struct CliApplyParams {
     expr: String,
     #[arg(help = "help.arg.db-path")]
     path: Option<PathBuf>,
+    #[arg(long, help = "help.arg.db-revision")]
+    revision: Option<u64>,
 }
 
 #[instrument(skip_all)]
@@ -286,48 +338,54 @@ async fn cli_apply(
                 allow_model_mismatch,
                 expr,
                 path,
+                revision,
             },
         ..
     }: HandlerArgs<CliContext, CliApplyParams>,
 ) -> Result<(), RpcError> {
     if let Some(path) = path {
-        PatchDb::open(path)
-            .await?
-            .apply_function(|db| {
-                let res = apply_expr(
-                    serde_json::to_value(patch_db::Value::from(db))
-                        .with_kind(ErrorKind::Deserialization)?
-                        .into(),
-                    &expr,
-                )?;
+        let db = PatchDb::open(path).await?;
+        let f = |db: Value| {
+            let res = apply_expr(
+                serde_json::to_value(patch_db::Value::from(db))
+                    .with_kind(ErrorKind::Deserialization)?
+                    .into(),
+                &expr,
+            )?;
 
-                let value = if allow_model_mismatch {
-                    serde_json::from_value::<Value>(res.clone().into()).with_ctx(|_| {
-                        (
-                            crate::ErrorKind::Deserialization,
-                            "result does not match database model",
-                        )
-                    })?
-                } else {
-                    to_value(
-                        &serde_json::from_value::<model::Database>(res.clone().into()).with_ctx(
-                            |_| {
-                                (
-                                    crate::ErrorKind::Deserialization,
-                                    "result does not match database model",
-                                )
-                            },
-                        )?,
-                    )?
-                };
-                Ok::<_, Error>((value, ()))
-            })
-            .await
-            .result?;
+            let value = if allow_model_mismatch {
+                serde_json::from_value::<Value>(res.clone().into()).with_ctx(|_| {
+                    (
+                        crate::ErrorKind::Deserialization,
+                        "result does not match database model",
+                    )
+                })?
+            } else {
+                to_value(
+                    &serde_json::from_value::<model::Database>(res.clone().into()).with_ctx(
+                        |_| {
+                            (
+                                crate::ErrorKind::Deserialization,
+                                "result does not match database model",
+                            )
+                        },
+                    )?,
+                )?
+            };
+            Ok::<_, Error>((value, ()))
+        };
+        match revision {
+            Some(revision) => db.apply_function_at(revision, f).await,
+            None => db.apply_function(f).await,
+        }
+        .result?;
     } else {
         let method = parent_method.into_iter().chain(method).join(".");
         context
-            .call_remote::<RpcContext>(&method, imbl_value::json!({ "expr": expr }))
+            .call_remote::<RpcContext>(
+                &method,
+                imbl_value::json!({ "expr": expr, "revision": revision }),
+            )
             .await?;
     }
 
@@ -341,29 +399,83 @@ async fn cli_apply(
 pub struct ApplyParams {
     #[arg(help = "help.arg.db-apply-expr")]
     expr: String,
+    /// Fail with a conflict instead of applying if the database is no longer
+    /// at this revision
+    #[arg(long, help = "help.arg.db-revision")]
+    #[ts(type = "number | null")]
+    #[serde(default)]
+    revision: Option<u64>,
 }
 
-pub async fn apply(ctx: RpcContext, ApplyParams { expr }: ApplyParams) -> Result<(), Error> {
-    ctx.db
-        .mutate(|db| {
-            let res = apply_expr(
-                serde_json::to_value(patch_db::Value::from(db.clone()))
-                    .with_kind(ErrorKind::Deserialization)?
-                    .into(),
-                &expr,
-            )?;
+pub async fn apply(
+    ctx: RpcContext,
+    ApplyParams { expr, revision }: ApplyParams,
+) -> Result<(), Error> {
+    let f = |db: &mut Model<model::Database>| {
+        let res = apply_expr(
+            serde_json::to_value(patch_db::Value::from(db.clone()))
+                .with_kind(ErrorKind::Deserialization)?
+                .into(),
+            &expr,
+        )?;
 
-            db.ser(
-                &serde_json::from_value::<model::Database>(res.clone().into()).with_ctx(|_| {
-                    (
-                        crate::ErrorKind::Deserialization,
-                        "result does not match database model",
-                    )
-                })?,
-            )
-        })
-        .await
-        .result
+        db.ser(
+            &serde_json::from_value::<model::Database>(res.clone().into()).with_ctx(|_| {
+                (
+                    crate::ErrorKind::Deserialization,
+                    "result does not match database model",
+                )
+            })?,
+        )
+    };
+    match revision {
+        Some(revision) => ctx.db.mutate_at(revision, f).await,
+        None => ctx.db.mutate(f).await,
+    }
+    .result
+}
+
+#[derive(Deserialize, Serialize, Parser, TS)]
+#[group(skip)]
+#[serde(rename_all = "camelCase")]
+#[command(rename_all = "kebab-case")]
+pub struct PatchParams {
+    /// An RFC 6902 patch. `test` operations are honored: if one fails,
+    /// nothing is applied.
+    #[arg(help = "help.arg.db-json-patch")]
+    #[ts(type = "unknown[]")]
+    patch: CliFromJsonString<Patch>,
+    /// Fail with a conflict instead of applying if the database is no longer
+    /// at this revision
+    #[arg(long, help = "help.arg.db-revision")]
+    #[ts(type = "number | null")]
+    #[serde(default)]
+    revision: Option<u64>,
+}
+
+/// Returns the revision the patch produced, or `null` if it changed nothing.
+pub async fn patch(
+    ctx: RpcContext,
+    PatchParams { patch, revision }: PatchParams,
+) -> Result<Option<u64>, Error> {
+    let f = |db: &mut Model<model::Database>| {
+        let mut value = Value::from(db.clone());
+        patch_db::json_patch::patch(&mut value, &patch).map_err(patch_db::Error::from)?;
+        db.ser(
+            &patch_db::value::from_value::<model::Database>(value).with_ctx(|_| {
+                (
+                    crate::ErrorKind::Deserialization,
+                    "result does not match database model",
+                )
+            })?,
+        )
+    };
+    let res = match revision {
+        Some(revision) => ctx.db.mutate_at(revision, f).await,
+        None => ctx.db.mutate(f).await,
+    };
+    res.result?;
+    Ok(res.revision.map(|rev| rev.id))
 }
 
 pub fn put<C: Context>() -> ParentHandler<C> {
@@ -386,15 +498,40 @@ pub struct UiParams {
     #[arg(help = "help.arg.json-value")]
     #[ts(type = "any")]
     value: Value,
+    /// Fail with a conflict instead of writing if the database is no longer
+    /// at this revision
+    #[arg(long, help = "help.arg.db-revision")]
+    #[ts(type = "number | null")]
+    #[serde(default)]
+    revision: Option<u64>,
 }
 
 // #[command(display(display_serializable))]
 #[instrument(skip_all)]
-pub async fn ui(ctx: RpcContext, UiParams { pointer, value, .. }: UiParams) -> Result<(), Error> {
+pub async fn ui(
+    ctx: RpcContext,
+    UiParams {
+        pointer,
+        value,
+        revision,
+    }: UiParams,
+) -> Result<(), Error> {
     let ptr = "/public/ui"
         .parse::<JsonPointer>()
         .with_kind(ErrorKind::Database)?
         + &pointer;
-    ctx.db.put(&ptr, &value).await?;
+    match revision {
+        Some(revision) => {
+            ctx.db
+                .apply_json_patch_at(
+                    revision,
+                    &Patch(vec![PatchOperation::Add(AddOperation { path: ptr, value })]),
+                )
+                .await?;
+        }
+        None => {
+            ctx.db.put(&ptr, &value).await?;
+        }
+    }
     Ok(())
 }
@@ -5,12 +5,16 @@ use serde::{Deserialize, Serialize};
 
 use crate::account::AccountInfo;
 use crate::auth::AuthKeys;
+use crate::backup::schedule::BackupSchedules;
 use crate::backup::target::cifs::CifsTargets;
+use crate::backup::target::nfs::NfsTargets;
+use crate::backup::target::sftp::SftpTargets;
 use crate::db::model::private::Private;
 use crate::db::model::public::Public;
 use crate::net::forward::AvailablePorts;
 use crate::net::keys::KeyStore;
 use crate::notifications::Notifications;
+use crate::notifications::delivery::NotificationDelivery;
 use crate::prelude::*;
 use crate::ssh::SshKeys;
 use crate::system::KeyboardOptions;
@@ -45,7 +49,11 @@ impl Database {
                 // Empty: `os_bindings` claims the admin UI's 80/443 as a privileged bind.
                 available_ports: AvailablePorts::new(),
                 notifications: Notifications::new(),
+                notification_delivery: NotificationDelivery::default(),
                 cifs: CifsTargets::new(),
+                sftp: SftpTargets::new(),
+                nfs: NfsTargets::new(),
+                backup_schedules: BackupSchedules::new(),
                 package_stores: BTreeMap::new(),
                 developer_key: Pem(account.developer_key.clone()),
             }, // TODO
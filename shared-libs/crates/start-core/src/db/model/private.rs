@@ -5,10 +5,14 @@ use serde::{Deserialize, Serialize};
 
 use crate::PackageId;
 use crate::auth::AuthKeys;
+use crate::backup::schedule::BackupSchedules;
 use crate::backup::target::cifs::CifsTargets;
+use crate::backup::target::nfs::NfsTargets;
+use crate::backup::target::sftp::SftpTargets;
 use crate::net::forward::AvailablePorts;
 use crate::net::keys::KeyStore;
 use crate::notifications::Notifications;
+use crate::notifications::delivery::NotificationDelivery;
 use crate::prelude::*;
 use crate::ssh::SshKeys;
 use crate::util::serde::Pem;
@@ -27,8 +31,16 @@ pub struct Private {
     pub ssh_pubkeys: SshKeys,
     pub available_ports: AvailablePorts,
     pub notifications: Notifications,
+    #[serde(default)]
+    pub notification_delivery: NotificationDelivery,
     pub cifs: CifsTargets,
     #[serde(default)]
+    pub sftp: SftpTargets,
+    #[serde(default)]
+    pub nfs: NfsTargets,
+    #[serde(default)]
+    pub backup_schedules: BackupSchedules,
+    #[serde(default)]
     pub package_stores: BTreeMap<PackageId, Value>,
 }
 
@@ -9,10 +9,11 @@ use super::guard::{GenericMountGuard, TmpMountGuard};
 use super::util::sync_directory;
 use crate::PackageId;
 use crate::auth::check_password;
+use crate::backup::schedule::Retention;
 use crate::backup::target::BackupInfo;
 use crate::disk::BACKUP_DIR_NAME;
 use crate::disk::mount::filesystem::ReadWrite;
-use crate::disk::mount::filesystem::backupfs::BackupFS;
+use crate::disk::mount::filesystem::backupfs::{BackupFS, BackupFsSnapshot};
 use crate::disk::mount::guard::SubPath;
 use crate::disk::util::BackupUnencryptedMetadata;
 use crate::prelude::*;
@@ -242,7 +243,66 @@ impl<G: GenericMountGuard> BackupMountGuard<G> {
         self.unmount().await?;
         Ok(())
     }
+
+    /// Snapshot the saved store as `name` and delete the snapshots `retention`
+    /// no longer keeps. Snapshots need the store closed, so this runs between
+    /// unmounting it and unmounting the target, and the guard is unmounted
+    /// even if it fails.
+    #[instrument(skip_all)]
+    pub async fn snapshot_and_unmount(
+        self,
+        name: &str,
+        retention: &Retention,
+    ) -> Result<(), Error> {
+        let store = BackupFS::new(
+            self.unencrypted_metadata_path.with_file_name("crypt"),
+            self.enc_key.clone(),
+        );
+        self.snapshot_with_and_unmount(&store, name, retention)
+            .await
+    }
+    async fn snapshot_with_and_unmount(
+        mut self,
+        store: &impl SnapshotStore,
+        name: &str,
+        retention: &Retention,
+    ) -> Result<(), Error> {
+        if let Some(guard) = self.encrypted_guard.take() {
+            sync_directory(guard.path()).await?;
+            guard.unmount().await?;
+        }
+        let res = async {
+            store.create_snapshot(name).await?;
+            for snapshot in retention.expired(&store.list_snapshots().await?) {
+                store.delete_snapshot(&snapshot.name).await?;
+            }
+            Ok::<_, Error>(())
+        }
+        .await;
+        self.unmount().await?;
+        res
+    }
+}
+
+/// The snapshot operations [`BackupMountGuard::snapshot_and_unmount`] runs on
+/// the closed store.
+trait SnapshotStore: Sync {
+    fn create_snapshot(&self, name: &str) -> impl Future<Output = Result<(), Error>> + Send;
+    fn list_snapshots(&self) -> impl Future<Output = Result<Vec<BackupFsSnapshot>, Error>> + Send;
+    fn delete_snapshot(&self, name: &str) -> impl Future<Output = Result<(), Error>> + Send;
+}
+impl SnapshotStore for BackupFS<PathBuf, String> {
+    async fn create_snapshot(&self, name: &str) -> Result<(), Error> {
+        BackupFS::create_snapshot(self, name).await
+    }
+    async fn list_snapshots(&self) -> Result<Vec<BackupFsSnapshot>, Error> {
+        BackupFS::list_snapshots(self).await
+    }
+    async fn delete_snapshot(&self, name: &str) -> Result<(), Error> {
+        BackupFS::delete_snapshot(self, name).await
+    }
 }
+
 impl<G: GenericMountGuard> GenericMountGuard for BackupMountGuard<G> {
     fn path(&self) -> &Path {
         if let Some(guard) = &self.encrypted_guard {
@@ -277,3 +337,112 @@ impl<G: GenericMountGuard> Drop for BackupMountGuard<G> {
         });
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+
+    /// What the target and the store were asked to do, in order.
+    #[derive(Debug, Clone, Default)]
+    struct Log(Arc<Mutex<Vec<String>>>);
+    impl Log {
+        fn push(&self, event: String) {
+            self.0.lock().unwrap().push(event);
+        }
+        fn events(&self) -> Vec<String> {
+            self.0.lock().unwrap().clone()
+        }
+    }
+
+    #[derive(Debug)]
+    struct Target(Log);
+    impl GenericMountGuard for Target {
+        fn path(&self) -> &Path {
+            Path::new("/backup-target")
+        }
+        async fn unmount(self) -> Result<(), Error> {
+            self.0.push("unmount target".into());
+            Ok(())
+        }
+    }
+
+    struct Store {
+        log: Log,
+        snapshots: Vec<BackupFsSnapshot>,
+        fail_create: bool,
+    }
+    impl SnapshotStore for Store {
+        async fn create_snapshot(&self, name: &str) -> Result<(), Error> {
+            if self.fail_create {
+                return Err(Error::new(eyre!("store is corrupt"), ErrorKind::Backup));
+            }
+            self.log.push(format!("create {name}"));
+            Ok(())
+        }
+        async fn list_snapshots(&self) -> Result<Vec<BackupFsSnapshot>, Error> {
+            Ok(self.snapshots.clone())
+        }
+        async fn delete_snapshot(&self, name: &str) -> Result<(), Error> {
+            self.log.push(format!("delete {name}"));
+            Ok(())
+        }
+    }
+
+    fn guard(log: &Log) -> BackupMountGuard<Target> {
+        BackupMountGuard {
+            backup_disk_mount_guard: Some(Target(log.clone())),
+            encrypted_guard: None,
+            enc_key: String::new(),
+            unencrypted_metadata_path: PathBuf::from("/backup-target/unencrypted-metadata.json"),
+            unencrypted_metadata: Default::default(),
+            metadata: Default::default(),
+        }
+    }
+
+    fn store(log: &Log, fail_create: bool) -> Store {
+        let snapshot = |name: &str, day| BackupFsSnapshot {
+            name: name.to_owned(),
+            created: Utc.with_ymd_and_hms(2026, 1, day, 3, 0, 0).unwrap(),
+        };
+        Store {
+            log: log.clone(),
+            snapshots: vec![
+                snapshot("scheduled-old", 1),
+                snapshot("manual", 2),
+                snapshot("scheduled-new", 3),
+            ],
+            fail_create,
+        }
+    }
+
+    #[tokio::test]
+    async fn snapshots_then_prunes_then_unmounts() {
+        let log = Log::default();
+        guard(&log)
+            .snapshot_with_and_unmount(&store(&log, false), "scheduled-new", &Retention::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            log.events(),
+            [
+                "create scheduled-new",
+                "delete scheduled-old",
+                "unmount target"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn unmounts_when_the_snapshot_fails() {
+        let log = Log::default();
+        guard(&log)
+            .snapshot_with_and_unmount(&store(&log, true), "scheduled-new", &Retention::default())
+            .await
+            .unwrap_err();
+        assert_eq!(log.events(), ["unmount target"]);
+    }
+}
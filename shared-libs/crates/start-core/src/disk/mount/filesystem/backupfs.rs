@@ -3,11 +3,23 @@ use std::fmt::{self, Display};
 use std::os::unix::ffi::OsStrExt;
 use std::path::Path;
 
+use chrono::{DateTime, Utc};
 use digest::Digest;
 use sha2::Sha256;
+use tokio::process::Command;
 
 use super::FileSystem;
 use crate::prelude::*;
+use crate::util::Invoke;
+
+const BACKUP_FS_BIN: &str = "startos-backup-fs";
+
+/// A read-only point-in-time view of a backup-fs store.
+#[derive(Debug, Clone)]
+pub struct BackupFsSnapshot {
+    pub name: String,
+    pub created: DateTime<Utc>,
+}
 
 pub struct BackupFS<DataDir: AsRef<Path>, Password: fmt::Display> {
     data_dir: DataDir,
@@ -17,6 +29,68 @@ impl<DataDir: AsRef<Path>, Password: fmt::Display> BackupFS<DataDir, Password> {
     pub fn new(data_dir: DataDir, password: Password) -> Self {
         BackupFS { data_dir, password }
     }
+
+    fn snapshot_command(&self, action: &str) -> Command {
+        let mut cmd = Command::new(BACKUP_FS_BIN);
+        cmd.arg("snapshot")
+            .arg(action)
+            .arg("--password")
+            .arg(self.password.to_string())
+            .arg(self.data_dir.as_ref());
+        cmd
+    }
+
+    /// Snapshot the store, which must not be mounted.
+    pub async fn create_snapshot(&self, name: &str) -> Result<(), Error> {
+        self.snapshot_command("create")
+            .arg(name)
+            .invoke(ErrorKind::Backup)
+            .await?;
+        Ok(())
+    }
+
+    /// Every snapshot of the (unmounted) store, oldest first.
+    pub async fn list_snapshots(&self) -> Result<Vec<BackupFsSnapshot>, Error> {
+        String::from_utf8(
+            self.snapshot_command("list")
+                .invoke(ErrorKind::Backup)
+                .await?,
+        )?
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            // `<created unix>\t<inodes>\t<name>`
+            let mut fields = line.splitn(3, '\t');
+            let created = fields
+                .next()
+                .and_then(|c| c.parse().ok())
+                .and_then(|c| DateTime::from_timestamp(c, 0));
+            match (created, fields.nth(1)) {
+                (Some(created), Some(name)) => Ok(BackupFsSnapshot {
+                    name: name.to_owned(),
+                    created,
+                }),
+                _ => Err(Error::new(
+                    eyre!(
+                        "{}",
+                        t!("disk.mount.backupfs.bad-snapshot-list", line = line)
+                    ),
+                    ErrorKind::Backup,
+                )),
+            }
+        })
+        .collect()
+    }
+
+    /// Delete a snapshot of the (unmounted) store, reclaiming whatever only it
+    /// referenced.
+    pub async fn delete_snapshot(&self, name: &str) -> Result<(), Error> {
+        self.snapshot_command("delete")
+            .arg(name)
+            .invoke(ErrorKind::Backup)
+            .await?;
+        Ok(())
+    }
 }
 impl<DataDir: AsRef<Path> + Send + Sync, Password: fmt::Display + Send + Sync> FileSystem
     for BackupFS<DataDir, Password>
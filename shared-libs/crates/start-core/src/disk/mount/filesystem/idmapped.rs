@@ -1,5 +1,5 @@
 use std::os::fd::AsFd;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use clap::Parser;
@@ -131,4 +131,12 @@ impl<Fs: FileSystem> FileSystem for IdMapped<Fs> {
         }
         Ok(sha.finalize())
     }
+    fn runtime_dir(&self, mountpoint: &Path) -> Option<PathBuf> {
+        // With an idmap the inner mount is a staging copy, unmounted in `mount`
+        if self.idmap.is_empty() {
+            self.filesystem.runtime_dir(mountpoint)
+        } else {
+            None
+        }
+    }
 }
@@ -1,9 +1,12 @@
 use std::ffi::OsStr;
 use std::fmt::{Display, Write};
-use std::path::Path;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use futures::Future;
 use sha2::Sha256;
+use tokio::net::TcpStream;
 use tokio::process::Command;
 
 use crate::prelude::*;
@@ -19,7 +22,9 @@ pub mod httpdirfs;
 pub mod idmapped;
 pub mod label;
 pub mod loop_dev;
+pub mod nfs;
 pub mod overlayfs;
+pub mod sftp;
 #[cfg(target_os = "linux")]
 pub mod syscall;
 #[cfg(not(target_os = "linux"))]
@@ -35,6 +40,44 @@ pub enum MountType {
 
 pub use MountType::*;
 
+const REACHABLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Resolve the server of a network filesystem, using mDNS for `.local` names.
+pub async fn resolve_host(hostname: &str) -> Result<IpAddr, Error> {
+    if let Ok(addr) = hostname.parse() {
+        return Ok(addr);
+    }
+    if hostname.ends_with(".local") {
+        return Ok(IpAddr::V4(crate::net::mdns::resolve_mdns(hostname).await?));
+    }
+    tokio::net::lookup_host((hostname, 0))
+        .await
+        .with_kind(ErrorKind::Network)?
+        .next()
+        .map(|addr| addr.ip())
+        .ok_or_else(|| {
+            Error::new(
+                eyre!("{}", t!("disk.mount.host-not-found", host = hostname)),
+                ErrorKind::Network,
+            )
+        })
+}
+
+/// Fail fast if nothing accepts connections on `port`, instead of leaving a
+/// hard network mount to hang.
+pub async fn check_reachable(ip: IpAddr, port: u16) -> Result<(), Error> {
+    match tokio::time::timeout(REACHABLE_TIMEOUT, TcpStream::connect((ip, port))).await {
+        Ok(Ok(_)) => Ok(()),
+        _ => Err(Error::new(
+            eyre!(
+                "{}",
+                t!("disk.mount.host-unreachable", host = ip, port = port)
+            ),
+            ErrorKind::Network,
+        )),
+    }
+}
+
 pub(self) async fn default_mount_command(
     fs: &(impl FileSystem + ?Sized),
     mountpoint: impl AsRef<Path> + Send,
@@ -113,4 +156,11 @@ pub trait FileSystem: Send + Sync {
         default_mount_impl(self, mountpoint, mount_type)
     }
     fn source_hash(&self) -> impl Future<Output = Result<digest::Output<Sha256>, Error>> + Send;
+    /// A directory of files a mount at `mountpoint` needs for as long as it
+    /// lives, e.g. credentials a FUSE daemon re-reads on reconnect.
+    /// [`MountGuard`](super::guard::MountGuard) removes it once the
+    /// filesystem is unmounted.
+    fn runtime_dir(&self, #[allow(unused_variables)] mountpoint: &Path) -> Option<PathBuf> {
+        None
+    }
 }
@@ -0,0 +1,53 @@
+use std::fmt::Display;
+use std::net::IpAddr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use digest::Digest;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use ts_rs::TS;
+
+use super::{FileSystem, check_reachable, resolve_host};
+use crate::prelude::*;
+
+pub const NFS_PORT: u16 = 2049;
+
+#[derive(Debug, Deserialize, Serialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct Nfs {
+    pub hostname: String,
+    pub path: PathBuf,
+}
+impl Nfs {
+    pub async fn reachable(&self) -> Result<(), Error> {
+        check_reachable(resolve_host(&self.hostname).await?, NFS_PORT).await
+    }
+}
+impl FileSystem for Nfs {
+    fn mount_type(&self) -> Option<impl AsRef<str>> {
+        Some("nfs")
+    }
+    fn mount_options(&self) -> impl IntoIterator<Item = impl Display> {
+        // soft, so a server that goes away fails the backup with EIO after
+        // `retrans` timeouts rather than hanging it (and its unmount) forever
+        ["soft", "timeo=600", "retrans=3"]
+    }
+    async fn source(&self) -> Result<Option<impl AsRef<Path>>, Error> {
+        let host = match resolve_host(&self.hostname).await? {
+            IpAddr::V4(ip) => ip.to_string(),
+            IpAddr::V6(ip) => format!("[{ip}]"),
+        };
+        Ok(Some(PathBuf::from(format!(
+            "{host}:{}",
+            Path::new("/").join(&self.path).display()
+        ))))
+    }
+    async fn source_hash(&self) -> Result<digest::Output<Sha256>, Error> {
+        let mut sha = Sha256::new();
+        sha.update("Nfs");
+        sha.update(self.hostname.as_bytes());
+        sha.update(self.path.as_os_str().as_bytes());
+        Ok(sha.finalize())
+    }
+}
@@ -0,0 +1,314 @@
+use std::net::IpAddr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use digest::Digest;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tracing::instrument;
+use ts_rs::TS;
+
+use super::{
+    BackupWrite, FileSystem, MountType, ReadOnly, ReadWrite, check_reachable, resolve_host,
+};
+use crate::prelude::*;
+use crate::util::Invoke;
+use crate::util::io::{create_file_mod, delete_dir, write_file_atomic};
+
+pub const SFTP_PORT: u16 = 22;
+const SFTP_RUN_DIR: &str = "/run/startos/sftp";
+const HOST_KEY_ALIAS: &str = "startos-backup-target";
+
+fn default_port() -> u16 {
+    SFTP_PORT
+}
+
+/// Keyed by mountpoint rather than source so two mounts of the same target
+/// never share (or remove) each other's credentials.
+fn run_dir(mountpoint: &Path) -> PathBuf {
+    Path::new(SFTP_RUN_DIR).join(hex::encode(Sha256::digest(
+        mountpoint.as_os_str().as_bytes(),
+    )))
+}
+
+fn host_arg(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => ip.to_string(),
+        IpAddr::V6(ip) => format!("[{ip}]"),
+    }
+}
+
+/// Reject usernames `sshfs` would misread: the name is passed as the
+/// `user@host:path` argument, so a leading `-` would be parsed as an option and
+/// `@`/`:` would shift the host or path.
+pub fn validate_username(username: &str) -> Result<(), Error> {
+    if username.is_empty()
+        || username.starts_with('-')
+        || username
+            .chars()
+            .any(|c| c.is_whitespace() || c.is_control() || matches!(c, '@' | ':' | ','))
+    {
+        return Err(Error::new(
+            eyre!(
+                "{}",
+                t!("disk.mount.sftp.invalid-username", username = username)
+            ),
+            ErrorKind::InvalidRequest,
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub struct SftpHostKey {
+    /// `<type> <base64>`, as pinned in [`Sftp::host_keys`]
+    pub key: String,
+    /// `SHA256:<base64>`, as printed by `ssh-keygen -l`
+    pub fingerprint: String,
+}
+impl SftpHostKey {
+    pub fn new(key: String) -> Result<Self, Error> {
+        let fingerprint = host_key_fingerprint(&key)?;
+        Ok(Self { key, fingerprint })
+    }
+}
+
+/// Validate host keys the user confirmed from [`scan_host_keys`] before they
+/// are pinned.
+pub fn confirm_host_keys(hostname: &str, keys: Vec<String>) -> Result<Vec<String>, Error> {
+    if keys.is_empty() {
+        return Err(Error::new(
+            eyre!("{}", t!("disk.mount.sftp.no-host-keys", host = hostname)),
+            ErrorKind::InvalidRequest,
+        ));
+    }
+    keys.into_iter()
+        .map(|key| SftpHostKey::new(key.trim().to_owned()).map(|key| key.key))
+        .collect()
+}
+
+fn host_key_fingerprint(key: &str) -> Result<String, Error> {
+    use base64::Engine;
+
+    let blob = key.split_whitespace().nth(1).ok_or_else(|| {
+        Error::new(
+            eyre!("{}", t!("disk.mount.sftp.invalid-host-key", key = key)),
+            ErrorKind::ParseSshKey,
+        )
+    })?;
+    let blob = base64::engine::general_purpose::STANDARD
+        .decode(blob)
+        .with_kind(ErrorKind::ParseSshKey)?;
+    Ok(format!(
+        "SHA256:{}",
+        base64::engine::general_purpose::STANDARD_NO_PAD.encode(Sha256::digest(&blob))
+    ))
+}
+
+fn parse_keyscan(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once(' ').map(|(_, key)| key.trim().to_owned()))
+        .filter(|key| !key.is_empty())
+        .collect()
+}
+
+/// Fetch the server's public host keys so they can be pinned. The caller shows
+/// the fingerprints for confirmation before trusting them; every later mount
+/// refuses a server that presents a different key.
+#[instrument(skip_all)]
+pub async fn scan_host_keys(hostname: &str, port: u16) -> Result<Vec<SftpHostKey>, Error> {
+    let ip = resolve_host(hostname).await?;
+    check_reachable(ip, port).await?;
+    let keys = parse_keyscan(&String::from_utf8(
+        Command::new("ssh-keyscan")
+            .arg("-p")
+            .arg(port.to_string())
+            .arg("-T")
+            .arg("10")
+            .arg(ip.to_string())
+            .invoke(ErrorKind::Network)
+            .await?,
+    )?);
+    if keys.is_empty() {
+        return Err(Error::new(
+            eyre!("{}", t!("disk.mount.sftp.no-host-keys", host = hostname)),
+            ErrorKind::Network,
+        ));
+    }
+    keys.into_iter().map(SftpHostKey::new).collect()
+}
+
+#[derive(Debug, Deserialize, Serialize, TS)]
+#[serde(rename_all = "camelCase")]
+pub struct Sftp {
+    pub hostname: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    pub path: PathBuf,
+    pub username: String,
+    pub password: Option<String>,
+    pub private_key: Option<String>,
+    /// `<type> <base64>` host keys pinned by [`scan_host_keys`]
+    pub host_keys: Vec<String>,
+}
+impl Sftp {
+    pub async fn reachable(&self) -> Result<(), Error> {
+        check_reachable(resolve_host(&self.hostname).await?, self.port).await
+    }
+    async fn mount_with(
+        &self,
+        ip: IpAddr,
+        run_dir: &Path,
+        mountpoint: &Path,
+        mount_type: MountType,
+    ) -> Result<(), Error> {
+        let known_hosts = run_dir.join("known_hosts");
+        write_file_atomic(
+            &known_hosts,
+            self.host_keys
+                .iter()
+                .map(|key| format!("{HOST_KEY_ALIAS} {key}\n"))
+                .collect::<String>(),
+        )
+        .await?;
+        let mut opts = format!(
+            "StrictHostKeyChecking=yes,UserKnownHostsFile={},HostKeyAlias={HOST_KEY_ALIAS},reconnect,ServerAliveInterval=15,ServerAliveCountMax=3",
+            known_hosts.display()
+        );
+        match mount_type {
+            ReadOnly => opts.push_str(",ro"),
+            ReadWrite => (),
+            BackupWrite => opts.push_str(",dir_cache=no"),
+        }
+        if let Some(private_key) = &self.private_key {
+            let identity = run_dir.join("identity");
+            let mut file = create_file_mod(&identity, 0o600).await?;
+            file.write_all(private_key.trim_end().as_bytes()).await?;
+            file.write_all(b"\n").await?;
+            file.sync_all().await?;
+            opts.push_str(&format!(
+                ",IdentityFile={},IdentitiesOnly=yes",
+                identity.display()
+            ));
+        }
+        if self.password.is_some() {
+            opts.push_str(",password_stdin");
+        } else {
+            opts.push_str(",BatchMode=yes");
+        }
+        let mut cmd = Command::new("sshfs");
+        cmd.arg("-p")
+            .arg(self.port.to_string())
+            .arg(format!(
+                "{}@{}:{}",
+                self.username,
+                host_arg(ip),
+                Path::new("/").join(&self.path).display()
+            ))
+            .arg(mountpoint)
+            .arg("-o")
+            .arg(opts);
+        if let Some(password) = &self.password {
+            cmd.input(Some(&mut std::io::Cursor::new(format!("{password}\n"))))
+                .invoke(ErrorKind::Filesystem)
+                .await?;
+        } else {
+            cmd.invoke(ErrorKind::Filesystem).await?;
+        }
+        Ok(())
+    }
+}
+impl FileSystem for Sftp {
+    #[instrument(skip_all)]
+    async fn mount<P: AsRef<Path> + Send>(
+        &self,
+        mountpoint: P,
+        mount_type: MountType,
+    ) -> Result<(), Error> {
+        validate_username(&self.username)?;
+        tokio::fs::create_dir_all(mountpoint.as_ref()).await?;
+        let ip = resolve_host(&self.hostname).await?;
+        // sshfs re-reads these on reconnect, so they live as long as the mount
+        let run_dir = run_dir(mountpoint.as_ref());
+        let res = self
+            .mount_with(ip, &run_dir, mountpoint.as_ref(), mount_type)
+            .await;
+        if res.is_err() {
+            delete_dir(&run_dir).await.log_err();
+        }
+        res
+    }
+    fn runtime_dir(&self, mountpoint: &Path) -> Option<PathBuf> {
+        Some(run_dir(mountpoint))
+    }
+    async fn source_hash(&self) -> Result<digest::Output<Sha256>, Error> {
+        let mut sha = Sha256::new();
+        sha.update("Sftp");
+        sha.update(self.hostname.as_bytes());
+        sha.update(self.port.to_be_bytes());
+        sha.update(self.path.as_os_str().as_bytes());
+        Ok(sha.finalize())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keyscan_output() {
+        let out = "# 10.0.0.2:22 SSH-2.0-OpenSSH_9.2p1\n\
+            10.0.0.2 ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIOMqqnkVzrm0SdG6UOoqKLsabgH5C9okWi0dh2l9GKJl\n\
+            10.0.0.2 \n";
+        assert_eq!(
+            parse_keyscan(out),
+            vec![
+                "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIOMqqnkVzrm0SdG6UOoqKLsabgH5C9okWi0dh2l9GKJl"
+                    .to_owned()
+            ]
+        );
+    }
+
+    #[test]
+    fn fingerprint() {
+        let key = SftpHostKey::new(
+            "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIOMqqnkVzrm0SdG6UOoqKLsabgH5C9okWi0dh2l9GKJl"
+                .to_owned(),
+        )
+        .unwrap();
+        assert_eq!(
+            key.fingerprint,
+            "SHA256:+DiY3wvvV6TuJJhbpZisF/zLDA0zPMSvHdkr4UvCOqU"
+        );
+        assert!(SftpHostKey::new("ssh-ed25519".to_owned()).is_err());
+        assert!(SftpHostKey::new("ssh-ed25519 !!!".to_owned()).is_err());
+    }
+
+    #[test]
+    fn confirmed_keys() {
+        let key =
+            "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIOMqqnkVzrm0SdG6UOoqKLsabgH5C9okWi0dh2l9GKJl";
+        assert_eq!(
+            confirm_host_keys("nas", vec![format!(" {key}\n")]).unwrap(),
+            vec![key.to_owned()]
+        );
+        assert!(confirm_host_keys("nas", Vec::new()).is_err());
+        assert!(confirm_host_keys("nas", vec![key.to_owned(), "garbage".to_owned()]).is_err());
+    }
+
+    #[test]
+    fn usernames() {
+        for ok in ["backup", "user.name", "svc-backup", "a_b"] {
+            assert!(validate_username(ok).is_ok(), "{ok}");
+        }
+        for bad in ["", "-oProxyCommand=sh", "a@b", "a:b", "a b", "a,b", "a\nb"] {
+            assert!(validate_username(bad).is_err(), "{bad:?}");
+        }
+    }
+}
@@ -53,6 +53,8 @@ pub struct MountGuard {
     // `TmpMountGuard`-managed mounts carry their shared slot so teardown can
     // skip the umount once the slot's been re-occupied (see `unmount_slot`).
     slot: Option<MountSlot>,
+    /// `FileSystem::runtime_dir`, removed along with the mount
+    runtime_dir: Option<PathBuf>,
 }
 impl MountGuard {
     pub async fn mount(
@@ -63,6 +65,7 @@ impl MountGuard {
         let mountpoint = mountpoint.as_ref().to_owned();
         filesystem.mount(&mountpoint, mount_type).await?;
         Ok(MountGuard {
+            runtime_dir: filesystem.runtime_dir(&mountpoint),
             mountpoint,
             mounted: true,
             slot: None,
@@ -73,6 +76,7 @@ impl MountGuard {
             mountpoint: self.mountpoint.clone(),
             mounted: false,
             slot: self.slot.clone(),
+            runtime_dir: None,
         }
     }
     pub fn take(&mut self) -> Self {
@@ -84,6 +88,7 @@ impl MountGuard {
             unmount_slot(
                 &self.slot,
                 &self.mountpoint,
+                self.runtime_dir.as_deref(),
                 !cfg!(feature = "unstable"),
                 delete_mountpoint,
             )
@@ -101,6 +106,7 @@ impl MountGuard {
 async fn unmount_slot(
     slot: &Option<MountSlot>,
     mountpoint: &Path,
+    runtime_dir: Option<&Path>,
     lazy: bool,
     delete_mountpoint: bool,
 ) -> Result<(), Error> {
@@ -115,6 +121,9 @@ async fn unmount_slot(
         None => None,
     };
     unmount(mountpoint, lazy).await?;
+    if let Some(runtime_dir) = runtime_dir {
+        crate::util::io::delete_dir(runtime_dir).await?;
+    }
     if delete_mountpoint {
         match tokio::fs::remove_dir(mountpoint).await {
             Err(e) if e.raw_os_error() == Some(39) => Ok(()), // directory not empty
@@ -135,8 +144,9 @@ impl Drop for MountGuard {
         if self.mounted {
             let mountpoint = std::mem::take(&mut self.mountpoint);
             let slot = self.slot.take();
+            let runtime_dir = self.runtime_dir.take();
             tokio::spawn(async move {
-                unmount_slot(&slot, &mountpoint, true, false)
+                unmount_slot(&slot, &mountpoint, runtime_dir.as_deref(), true, false)
                     .await
                     .log_err()
             });
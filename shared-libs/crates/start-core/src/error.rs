@@ -103,6 +103,7 @@ pub enum ErrorKind {
     SetSysInfo = 79,
     Bios = 80,
     BackupPasswordMismatch = 81,
+    DatabaseConflict = 82,
 }
 impl ErrorKind {
     pub fn as_str(&self) -> String {
@@ -189,6 +190,7 @@ impl ErrorKind {
             SetSysInfo => t!("error.set-sys-info"),
             Bios => t!("error.bios"),
             BackupPasswordMismatch => t!("error.backup-password-mismatch"),
+            DatabaseConflict => t!("error.database-conflict"),
         }
         .to_string()
     }
@@ -341,7 +343,14 @@ impl From<std::num::ParseFloatError> for Error {
 }
 impl From<patch_db::Error> for Error {
     fn from(e: patch_db::Error) -> Self {
-        Error::new(e, ErrorKind::Database)
+        let kind = match e {
+            patch_db::Error::Conflict { .. }
+            | patch_db::Error::Patch(patch_db::json_patch::PatchError::TestFailed) => {
+                ErrorKind::DatabaseConflict
+            }
+            _ => ErrorKind::Database,
+        };
+        Error::new(e, kind)
     }
 }
 impl From<ed25519_dalek::SignatureError> for Error {
@@ -6,7 +6,7 @@ use axum::extract::ws;
 use clap::builder::ValueParserFactory;
 use clap::{CommandFactory, FromArgMatches, Parser, value_parser};
 use color_eyre::eyre::eyre;
-use exver::VersionRange;
+use exver::{Preference, VersionRange};
 use futures::{FutureExt, StreamExt};
 use imbl_value::{InternedString, json};
 use itertools::Itertools;
@@ -498,8 +498,16 @@ pub async fn cli_install(
                 )
                 .await?,
             )?;
+            // an update keeps the flavor that is installed
+            let same_flavor = source_version.as_ref().and_then(|source| {
+                VersionRange::Flavor(source.flavor().map(InternedString::intern))
+                    .best(packages.best.keys(), &Preference::max())
+                    .cloned()
+            });
             let version = if packages.best.len() == 1 {
                 packages.best.pop_first().map(|(k, _)| k).unwrap()
+            } else if let Some(version) = same_flavor {
+                version
             } else {
                 let versions = packages.best.keys().collect::<Vec<_>>();
                 let version = choose(
@@ -438,6 +438,7 @@ pub fn package<C: Context>() -> ParentHandler<C> {
             "install",
             from_fn_async(install::install)
                 .with_metadata("sync_db", Value::Bool(true))
+                .with_metadata("max_concurrent", Value::from(4))
                 .no_cli(),
         )
         .subcommand(
@@ -6,18 +6,19 @@ use std::time::{SystemTime, UNIX_EPOCH};
 use axum::body::Body;
 use axum::extract::Request;
 use chrono::Utc;
-use http::header::USER_AGENT;
-use http::{HeaderMap, HeaderValue};
+use http::header::{UPGRADE, USER_AGENT};
+use http::{HeaderMap, HeaderName, HeaderValue};
 use itertools::Itertools;
 use reqwest::Client;
 use rpc_toolkit::yajrc::RpcError;
-use rpc_toolkit::{Middleware, RpcRequest, RpcResponse};
+use rpc_toolkit::{Middleware, Notifier, RpcRequest, RpcResponse};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use url::Url;
 
 use crate::auth::AuthKeys;
 use crate::context::{CliContext, RpcContext};
+use crate::db::model::Database;
 use crate::middleware::auth::DbContext;
 use crate::prelude::*;
 use crate::rpc_continuations::OpenAuthedContinuations;
@@ -29,6 +30,9 @@ use crate::util::serde::Base64;
 use crate::util::sync::SyncMutex;
 
 pub const AUTH_SIG_HEADER: &str = "X-Start-Auth-Sig";
+/// Carries the [`AUTH_SIG_HEADER`] value, signed over an empty body, on a
+/// websocket upgrade to `/ws/rpc`. Browsers can't set headers on one.
+pub const AUTH_SIG_QUERY: &str = "authSig";
 
 /// Upper bound on how much we pre-reserve for a request body from the
 /// attacker-controlled `commitment.size`. A forged self-signature can carry any
@@ -249,32 +253,45 @@ impl SignatureAuthContext for RpcContext {
     }
     async fn post_auth_hook(&self, key: Self::CheckPubkeyRes, _: &RpcRequest) -> Result<(), Error> {
         if let Some(key) = key {
-            let ephemeral = self.ephemeral_auth_keys.mutate(|keys| {
-                if let Some(entry) = keys.0.get_mut(&*key) {
-                    entry.last_active = Utc::now();
-                    true
-                } else {
-                    false
-                }
-            });
-            if !ephemeral {
-                self.db
-                    .mutate(|db| {
-                        db.as_private_mut().as_session_pubkeys_mut().mutate(|keys| {
-                            if let Some(entry) = keys.0.get_mut(&*key) {
-                                entry.last_active = Utc::now();
-                            }
-                            Ok(())
-                        })
-                    })
-                    .await
-                    .result?;
-            }
+            record_activity(&self.ephemeral_auth_keys, &self.db, &key).await?;
         }
         Ok(())
     }
 }
 
+/// Refresh the `last_active` of the enrolled `key`. Ephemeral keys live in
+/// memory and are always refreshed; persisted ones only per
+/// [`Session::touch`](crate::auth::Session::touch), so that most requests
+/// leave the db revision alone.
+async fn record_activity(
+    ephemeral: &SyncMutex<AuthKeys>,
+    db: &TypedPatchDb<Database>,
+    key: &str,
+) -> Result<(), Error> {
+    let now = Utc::now();
+    let ephemeral = ephemeral.mutate(|keys| {
+        if let Some(entry) = keys.0.get_mut(key) {
+            entry.last_active = now;
+            true
+        } else {
+            false
+        }
+    });
+    if !ephemeral {
+        db.mutate(|db| {
+            db.as_private_mut().as_session_pubkeys_mut().mutate(|keys| {
+                if let Some(entry) = keys.0.get_mut(key) {
+                    entry.touch(now);
+                }
+                Ok(())
+            })
+        })
+        .await
+        .result?;
+    }
+    Ok(())
+}
+
 /// Format an IP the way `url::Url::host_str` (and `location.hostname`) renders
 /// it, so signature contexts match regardless of how the server was addressed.
 pub(crate) fn url_host_str(ip: IpAddr) -> InternedString {
@@ -534,6 +551,55 @@ pub fn verify_request(
     }
 }
 
+/// Move an [`AUTH_SIG_QUERY`] parameter into the [`AUTH_SIG_HEADER`] of a
+/// websocket upgrade that doesn't already carry one, so it is verified like
+/// any other signature. The nonce check keeps the URL from being replayed.
+///
+/// The parameter is stripped from the URI either way, so nothing that logs or
+/// forwards the request further along sees the signature.
+fn sig_from_query(request: &mut Request) {
+    let Some(query) = request.uri().query() else {
+        return;
+    };
+    let (sig, rest): (Vec<_>, Vec<_>) =
+        form_urlencoded::parse(query.as_bytes()).partition(|(k, _)| k == AUTH_SIG_QUERY);
+    let Some((_, sig)) = sig.into_iter().next() else {
+        return;
+    };
+    let sig = sig.into_owned();
+    let mut path_and_query = request.uri().path().to_owned();
+    if !rest.is_empty() {
+        path_and_query.push('?');
+        path_and_query.push_str(
+            &form_urlencoded::Serializer::new(String::new())
+                .extend_pairs(rest)
+                .finish(),
+        );
+    }
+    let mut parts = request.uri().clone().into_parts();
+    if let Ok(path_and_query) = path_and_query.parse() {
+        parts.path_and_query = Some(path_and_query);
+        if let Ok(uri) = http::Uri::from_parts(parts) {
+            *request.uri_mut() = uri;
+        }
+    }
+
+    let upgrade = request
+        .headers()
+        .get(UPGRADE)
+        .and_then(|h| h.to_str().ok())
+        .is_some_and(|h| h.eq_ignore_ascii_case("websocket"));
+    if !upgrade || request.headers().contains_key(AUTH_SIG_HEADER) {
+        return;
+    }
+    if let (Ok(name), Ok(value)) = (
+        HeaderName::try_from(AUTH_SIG_HEADER),
+        HeaderValue::try_from(sig),
+    ) {
+        request.headers_mut().insert(name, value);
+    }
+}
+
 impl<C: SignatureAuthContext> Middleware<C> for SignatureAuth {
     type Metadata = Metadata<C::AdditionalMetadata>;
     async fn process_http_request(
@@ -542,6 +608,7 @@ impl<C: SignatureAuthContext> Middleware<C> for SignatureAuth {
         request: &mut Request,
     ) -> Result<(), axum::response::Response> {
         self.user_agent = request.headers().get(USER_AGENT).cloned();
+        sig_from_query(request);
         if request.headers().contains_key(AUTH_SIG_HEADER) {
             self.signer = Some(
                 verify_request_signature(context, request)
@@ -569,9 +636,30 @@ impl<C: SignatureAuthContext> Middleware<C> for SignatureAuth {
                     request.params["__Auth_userAgent"] = to_value(&user_agent)?;
                 }
             }
+            // A websocket outlives this check, so it is closed when its signer
+            // is unenrolled. Subscribed before the check, so that an
+            // unenrollment landing in between still reaches it.
+            let continuations = context.open_authed_continuations();
+            let guard = signer
+                .as_ref()
+                .zip(Notifier::current())
+                .map(|(signer, notifier)| {
+                    let kill = continuations.subscribe_to_kill(Some(signer.interned_pem()));
+                    (kill, notifier)
+                });
             let db = context.db().peek().await;
             let res = context.check_pubkey(&db, signer.as_ref(), metadata.additional)?;
+            if let Some((mut kill, notifier)) = guard {
+                notifier.close_when(async move {
+                    kill.recv().await.ok();
+                });
+            }
             context.post_auth_hook(res, request).await?;
+            if let Some(signer) = &signer {
+                let signer = to_value(signer)?;
+                // attributes the request's db changes to its signer in the db history
+                patch_db::update_metadata(|metadata| metadata["signer"] = signer);
+            }
             Ok(())
         }
         .await
@@ -699,4 +787,77 @@ mod tests {
         )
         .expect("compact round trip verifies");
     }
+
+    #[test]
+    fn websocket_sig_from_query() {
+        let key = AnySigningKey::Ed25519(ed25519_dalek::SigningKey::from_bytes(&[7; 32]));
+        let header = SignatureHeader::sign(&key, b"", "start-9.local")
+            .expect("signs")
+            .to_header();
+        let mut url: Url = "http://start-9.local/ws/rpc?lang=en".parse().unwrap();
+        url.query_pairs_mut()
+            .append_pair(AUTH_SIG_QUERY, header.to_str().unwrap());
+        let request = |upgrade: bool| {
+            let mut req = Request::builder().uri(url.as_str());
+            if upgrade {
+                req = req.header(UPGRADE, "websocket");
+            }
+            req.body(Body::empty()).unwrap()
+        };
+
+        let mut upgrade = request(true);
+        sig_from_query(&mut upgrade);
+        assert_eq!(upgrade.headers().get(AUTH_SIG_HEADER), Some(&header));
+        assert_eq!(upgrade.uri().query(), Some("lang=en"));
+
+        let mut plain = request(false);
+        sig_from_query(&mut plain);
+        assert!(plain.headers().get(AUTH_SIG_HEADER).is_none());
+        // kept out of the URI even when it isn't used
+        assert_eq!(plain.uri().query(), Some("lang=en"));
+    }
+
+    /// A signed request must not move the db revision on every call, or a
+    /// client's `--revision` would always be stale by the time it applies.
+    #[tokio::test]
+    async fn activity_leaves_revision_for_compare_and_swap() {
+        use patch_db::json_ptr::ROOT;
+
+        use crate::auth::{SESSION_ACTIVITY_INTERVAL, Session};
+
+        let db = TypedPatchDb::<Database>::load_unchecked(PatchDb::in_memory());
+        let ephemeral = SyncMutex::new(AuthKeys::new());
+        let sessions = |last_active| {
+            let session = Session {
+                last_active,
+                ..Default::default()
+            };
+            imbl_value::json!({ "private": { "sessionPubkeys": { "key": session } } })
+        };
+
+        db.put(&ROOT, &sessions(Utc::now())).await.unwrap();
+        let revision = db.sequence().await;
+        record_activity(&ephemeral, &db, "key").await.unwrap();
+        assert_eq!(db.sequence().await, revision);
+        db.apply_function_at(revision, |v| Ok::<_, Error>((v, ())))
+            .await
+            .result
+            .expect("revision is still current");
+
+        let stale = Utc::now() - SESSION_ACTIVITY_INTERVAL * 2;
+        db.put(&ROOT, &sessions(stale)).await.unwrap();
+        let revision = db.sequence().await;
+        record_activity(&ephemeral, &db, "key").await.unwrap();
+        assert_eq!(db.sequence().await, revision + 1);
+        let session = db
+            .peek()
+            .await
+            .as_private()
+            .as_session_pubkeys()
+            .as_idx(&"key".into())
+            .unwrap()
+            .de()
+            .unwrap();
+        assert!(session.last_active > stale);
+    }
 }
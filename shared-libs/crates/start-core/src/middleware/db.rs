@@ -1,6 +1,7 @@
 use axum::response::Response;
 use http::HeaderValue;
 use http::header::InvalidHeaderValue;
+use imbl_value::{Value, json};
 use rpc_toolkit::{Middleware, RpcRequest, RpcResponse};
 use rust_i18n::t;
 use serde::Deserialize;
@@ -58,3 +59,24 @@ impl Middleware<RpcContext> for SyncDb {
         }
     }
 }
+
+/// Attributes the db changes a request makes to its RPC method in the db
+/// history. Middleware sees requests in reverse order, so this goes after
+/// [`Auth`](super::auth::Auth) to run before it: Auth then adds the signer.
+/// Each call, over HTTP or a websocket, gets its own scope via
+/// `HttpServer::with_call_scope`.
+#[derive(Clone)]
+pub struct RecordHistory;
+impl Middleware<RpcContext> for RecordHistory {
+    type Metadata = Value;
+    async fn process_rpc_request(
+        &mut self,
+        _: &RpcContext,
+        _: Self::Metadata,
+        request: &mut RpcRequest,
+    ) -> Result<(), RpcResponse> {
+        let method = request.method.as_str();
+        patch_db::update_metadata(|metadata| *metadata = json!({ "method": method }));
+        Ok(())
+    }
+}
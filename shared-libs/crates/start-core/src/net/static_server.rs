@@ -14,6 +14,7 @@ use axum::routing::{any, get};
 use base64::Engine;
 use base64::display::Base64Display;
 use digest::Digest;
+use futures::FutureExt;
 use futures::future::ready;
 use http::header::{
     ACCEPT_ENCODING, ACCEPT_RANGES, CACHE_CONTROL, CONNECTION, CONTENT_ENCODING, CONTENT_LENGTH,
@@ -26,7 +27,7 @@ use include_dir::Dir;
 use new_mime_guess::MimeGuess;
 use openssl::hash::MessageDigest;
 use openssl::x509::X509;
-use rpc_toolkit::{Context, HttpServer, ParentHandler, Server};
+use rpc_toolkit::{Context, HttpServer, OpenRpcDocument, OpenRpcInfo, ParentHandler, Server};
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, BufReader};
 use tokio_util::io::ReaderStream;
 use url::Url;
@@ -36,7 +37,7 @@ use crate::hostname::ServerHostname;
 use crate::middleware::auth::Auth;
 use crate::middleware::auth::signature::verify_request_signature;
 use crate::middleware::cors::Cors;
-use crate::middleware::db::SyncDb;
+use crate::middleware::db::{RecordHistory, SyncDb};
 use crate::prelude::*;
 use crate::rpc_continuations::{Guid, RpcContinuations};
 use crate::s9pk::S9pk;
@@ -46,6 +47,7 @@ use crate::s9pk::merkle_archive::source::multi_cursor_file::MultiCursorFile;
 use crate::sign::commitment::merkle_archive::MerkleArchiveCommitment;
 use crate::util::io::open_file;
 use crate::util::serde::BASE64;
+use crate::version::{Current, VersionT};
 use crate::{PackageId, main_api};
 
 const NOT_FOUND: &[u8] = b"Not Found";
@@ -56,6 +58,8 @@ const INTERNAL_SERVER_ERROR: &[u8] = b"Internal Server Error";
 pub const EMPTY_DIR: Dir<'_> = Dir::new("", &[]);
 
 pub trait UiContext: Context + AsRef<RpcContinuations> + Clone + Sized {
+    /// The title of the API's `rpc.discover` document.
+    const API_TITLE: &'static str = "StartOS";
     fn ui_dir() -> &'static Dir<'static>;
     fn api() -> ParentHandler<Self>;
     fn middleware(server: Server<Self>) -> HttpServer<Self>;
@@ -78,6 +82,9 @@ impl UiContext for RpcContext {
             .middleware(Cors::new())
             .middleware(Auth::new().with_local_auth().with_signature_auth())
             .middleware(SyncDb::new())
+            .middleware(RecordHistory)
+            // db changes made while handling a call are recorded against it
+            .with_call_scope(|call| patch_db::with_metadata(Value::Null, call).boxed())
     }
     fn extend_router(self, router: Router) -> Router {
         router
@@ -150,7 +157,11 @@ pub fn rpc_router<C: Context + Clone + AsRef<RpcContinuations>>(
     server: HttpServer<C>,
 ) -> Router {
     Router::new()
-        .route("/rpc/{*path}", any(server))
+        .route("/ws/rpc", {
+            let server = server.clone();
+            any(move |request: Request| server.handle_ws(request))
+        })
+        .route("/rpc/{*path}", any(move |request: Request| server.handle(request)))
         .route(
             "/ws/rpc/{guid}",
             any({
@@ -229,7 +240,10 @@ pub fn ui_router<C: UiContext>(ctx: C) -> Router {
     ctx.clone()
         .extend_router(rpc_router(
             ctx.clone(),
-            C::middleware(Server::new(move || ready(Ok(ctx.clone())), C::api())),
+            C::middleware(
+                Server::new(move || ready(Ok(ctx.clone())), C::api())
+                    .with_openrpc(describe_api(C::API_TITLE)),
+            ),
         ))
         .fallback(any(|request: Request| async move {
             serve_ui::<C>(request).unwrap_or_else(server_error)
@@ -237,6 +251,19 @@ pub fn ui_router<C: UiContext>(ctx: C) -> Router {
         .layer(axum::middleware::map_response(add_security_headers))
 }
 
+/// Titles the `rpc.discover` document and translates its method summaries,
+/// which are i18n keys, as in the CLI.
+pub fn describe_api(title: &'static str) -> impl Fn(&mut OpenRpcDocument) + Send + Sync + 'static {
+    move |doc| {
+        doc.info = OpenRpcInfo::new(title, Current::default().semver().to_string());
+        for method in &mut doc.methods {
+            if let Some(summary) = &mut method.summary {
+                *summary = t!(summary.as_str()).into_owned();
+            }
+        }
+    }
+}
+
 pub fn refresher() -> Router {
     Router::new().fallback(get(|request: Request| async move {
         let res = include_bytes!("./refresher.html");
@@ -0,0 +1,718 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use color_eyre::eyre::eyre;
+use hmac::{Hmac, KeyInit, Mac};
+use imbl_value::InternedString;
+use patch_db::json_ptr::JsonPointer;
+use rpc_toolkit::{Context, HandlerExt, ParentHandler, from_fn_async};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use ts_rs::TS;
+use url::Url;
+
+use super::{Notification, NotificationLevel, NotificationWithId};
+use crate::context::{CliContext, RpcContext};
+use crate::db::model::DatabaseModel;
+use crate::prelude::*;
+use crate::util::serde::HandlerExtSerde;
+
+/// Header carrying `sha256=<hex HMAC of the body>` on webhooks with a secret.
+pub const SIGNATURE_HEADER: &str = "X-StartOS-Signature";
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(30);
+/// Failed deliveries are retried with doubling delays from `RETRY_BASE`, up to
+/// `RETRY_MAX` apart, and given up after `MAX_ATTEMPTS`: about a day.
+const RETRY_BASE: Duration = Duration::from_secs(30);
+const RETRY_MAX: Duration = Duration::from_secs(60 * 60);
+const MAX_ATTEMPTS: u32 = 30;
+
+lazy_static::lazy_static! {
+    static ref OUTBOX_PTR: JsonPointer = "/private/notificationDelivery/outbox".parse().unwrap();
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, HasModel)]
+#[serde(rename_all = "camelCase")]
+#[model = "Model<Self>"]
+pub struct NotificationDelivery {
+    pub rules: DeliveryRules,
+    /// Deliveries not yet made, kept in the db so they survive a restart
+    pub outbox: Outbox,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct DeliveryRules(pub BTreeMap<u32, DeliveryRule>);
+impl Map for DeliveryRules {
+    type Key = u32;
+    type Value = DeliveryRule;
+    fn key_str(key: &Self::Key) -> Result<impl AsRef<str>, Error> {
+        Self::key_string(key)
+    }
+    fn key_string(key: &Self::Key) -> Result<InternedString, Error> {
+        Ok(InternedString::from_display(key))
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Outbox(pub BTreeMap<u32, PendingDelivery>);
+impl Map for Outbox {
+    type Key = u32;
+    type Value = PendingDelivery;
+    fn key_str(key: &Self::Key) -> Result<impl AsRef<str>, Error> {
+        Self::key_string(key)
+    }
+    fn key_string(key: &Self::Key) -> Result<InternedString, Error> {
+        Ok(InternedString::from_display(key))
+    }
+}
+
+/// Forward notifications of the given levels to a destination.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeliveryRule {
+    pub levels: BTreeSet<NotificationLevel>,
+    pub destination: Destination,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(tag = "type")]
+pub enum Destination {
+    /// Sent over the system SMTP server
+    Email { to: String },
+    /// POSTed as JSON, signed with `secret` if set
+    Webhook { url: Url, secret: Option<String> },
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PendingDelivery {
+    pub rule: u32,
+    pub notification: NotificationWithId,
+    pub attempts: u32,
+    pub not_before: DateTime<Utc>,
+}
+
+/// Queue `notification` for every rule that wants its level. Called from
+/// [`notify`](super::notify), inside the mutation that creates it.
+pub(super) fn enqueue(
+    db: &mut DatabaseModel,
+    id: u32,
+    notification: &Notification,
+) -> Result<(), Error> {
+    let rules = db
+        .as_private()
+        .as_notification_delivery()
+        .as_rules()
+        .de()?
+        .0;
+    let outbox = db
+        .as_private_mut()
+        .as_notification_delivery_mut()
+        .as_outbox_mut();
+    let mut next = outbox.keys()?.into_iter().max().map_or(0, |id| id + 1);
+    for (rule, _) in rules
+        .into_iter()
+        .filter(|(_, rule)| rule.levels.contains(&notification.level))
+    {
+        outbox.insert(
+            &next,
+            &PendingDelivery {
+                rule,
+                notification: NotificationWithId {
+                    id,
+                    notification: notification.clone(),
+                },
+                attempts: 0,
+                not_before: notification.created_at,
+            },
+        )?;
+        next += 1;
+    }
+    Ok(())
+}
+
+/// The [`SIGNATURE_HEADER`] value for a webhook body.
+fn signature(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WebhookPayload<'a> {
+    server_id: &'a str,
+    server_name: &'a str,
+    #[serde(flatten)]
+    notification: &'a NotificationWithId,
+}
+
+async fn send(
+    ctx: &RpcContext,
+    destination: &Destination,
+    notification: &NotificationWithId,
+) -> Result<(), Error> {
+    let peek = ctx.db.peek().await;
+    let server_id = peek.as_public().as_server_info().as_id().de()?;
+    let server_name = ctx.account.peek(|a| a.hostname.name.clone());
+    match destination {
+        Destination::Email { to } => {
+            let smtp = peek
+                .as_public()
+                .as_server_info()
+                .as_smtp()
+                .de()?
+                .ok_or_else(|| {
+                    Error::new(
+                        eyre!("{}", t!("notifications.delivery.smtp-not-configured")),
+                        ErrorKind::InvalidRequest,
+                    )
+                })?;
+            let notification = &notification.notification;
+            let mut body = notification.message.clone();
+            body.push_str("\n\n");
+            body.push_str(&t!(
+                "notifications.delivery.email-footer",
+                server = server_name,
+                level = notification.level,
+                time = notification.created_at.to_rfc3339(),
+            ));
+            smtp.send(
+                to,
+                &t!(
+                    "notifications.delivery.email-subject",
+                    server = server_name,
+                    title = notification.title,
+                ),
+                body,
+            )
+            .await
+        }
+        Destination::Webhook { url, secret } => {
+            let body = serde_json::to_vec(&WebhookPayload {
+                server_id: &server_id,
+                server_name: &server_name,
+                notification,
+            })
+            .with_kind(ErrorKind::Serialization)?;
+            let mut req = ctx
+                .client
+                .post(url.clone())
+                .timeout(WEBHOOK_TIMEOUT)
+                .header("Content-Type", "application/json");
+            if let Some(secret) = secret {
+                req = req.header(SIGNATURE_HEADER, signature(secret, &body));
+            }
+            req.body(body)
+                .send()
+                .await
+                .with_kind(ErrorKind::Network)?
+                .error_for_status()
+                .with_kind(ErrorKind::Network)?;
+            Ok(())
+        }
+    }
+}
+
+/// Deliver queued notifications until the context goes away. Wakes when
+/// something is queued, or when a failed delivery is due for a retry.
+pub async fn run_delivery(ctx: impl Fn() -> Option<RpcContext>) {
+    let Some(first) = ctx() else { return };
+    let mut watch = first.db.watch(OUTBOX_PTR.clone()).await;
+    drop(first);
+    loop {
+        let Some(ctx) = ctx() else { break };
+        let next = match deliver_due(&ctx).await {
+            Ok(next) => next,
+            Err(e) => {
+                tracing::error!(
+                    "{}",
+                    t!("notifications.delivery.error-in-delivery", error = e)
+                );
+                tracing::debug!("{e:?}");
+                Some(Utc::now() + RETRY_BASE)
+            }
+        };
+        drop(ctx);
+        let changed = if let Some(next) = next {
+            let delay = (next - Utc::now()).to_std().unwrap_or_default();
+            tokio::select! {
+                res = watch.changed() => res,
+                _ = tokio::time::sleep(delay) => Ok(()),
+            }
+        } else {
+            watch.changed().await
+        };
+        if changed.is_err() {
+            break;
+        }
+    }
+}
+
+/// Make every delivery that is due, returning when the next retry is. Each
+/// rule's deliveries go out in order, but rules are served concurrently, so
+/// an unreachable destination only holds up its own.
+async fn deliver_due(ctx: &RpcContext) -> Result<Option<DateTime<Utc>>, Error> {
+    let delivery = ctx
+        .db
+        .peek()
+        .await
+        .into_private()
+        .into_notification_delivery()
+        .de()?;
+    let now = Utc::now();
+    let mut next_retry: Option<DateTime<Utc>> = None;
+    let mut due: BTreeMap<u32, Vec<(u32, PendingDelivery)>> = BTreeMap::new();
+    for (key, pending) in delivery.outbox.0 {
+        if pending.not_before > now {
+            next_retry = Some(next_retry.map_or(pending.not_before, |n| n.min(pending.not_before)));
+        } else {
+            due.entry(pending.rule).or_default().push((key, pending));
+        }
+    }
+    let results = futures::future::join_all(
+        due.into_iter()
+            .map(|(rule, pending)| deliver_rule(ctx, delivery.rules.0.get(&rule), pending)),
+    )
+    .await;
+    for res in results {
+        if let Some(retry) = res? {
+            next_retry = Some(next_retry.map_or(retry, |n| n.min(retry)));
+        }
+    }
+    Ok(next_retry)
+}
+
+/// Make one rule's due deliveries in order, returning when the next retry is.
+/// Once one fails, the rest wait for its retry instead of each timing out.
+async fn deliver_rule(
+    ctx: &RpcContext,
+    rule: Option<&DeliveryRule>,
+    due: Vec<(u32, PendingDelivery)>,
+) -> Result<Option<DateTime<Utc>>, Error> {
+    let mut backoff: Option<DateTime<Utc>> = None;
+    for (key, mut pending) in due {
+        let retry = if let Some(not_before) = backoff {
+            pending.not_before = not_before;
+            Some(pending)
+        } else {
+            // a rule removed since this was queued takes its deliveries with it
+            let res = if let Some(rule) = rule {
+                send(ctx, &rule.destination, &pending.notification).await
+            } else {
+                Ok(())
+            };
+            match res {
+                Ok(()) => None,
+                Err(e) => {
+                    let now = Utc::now();
+                    let retry = record_failure(&mut pending, now);
+                    tracing::warn!(
+                        "{}",
+                        t!(
+                            "notifications.delivery.failed",
+                            id = pending.notification.id,
+                            rule = pending.rule,
+                            attempt = pending.attempts,
+                            error = e
+                        )
+                    );
+                    tracing::debug!("{e:?}");
+                    backoff = Some(now + retry_delay(pending.attempts));
+                    retry.then_some(pending)
+                }
+            }
+        };
+        ctx.db
+            .mutate(|db| {
+                let outbox = db
+                    .as_private_mut()
+                    .as_notification_delivery_mut()
+                    .as_outbox_mut();
+                if let Some(pending) = &retry {
+                    outbox.insert(&key, pending)
+                } else {
+                    outbox.remove(&key).map(|_| ())
+                }
+            })
+            .await
+            .result?;
+    }
+    Ok(backoff)
+}
+
+/// How long to wait after the `attempts`th failure.
+fn retry_delay(attempts: u32) -> Duration {
+    RETRY_BASE
+        .saturating_mul(1 << attempts.saturating_sub(1).min(16))
+        .min(RETRY_MAX)
+}
+
+/// Count a failed attempt and schedule the retry. Returns `false` once the
+/// delivery should be given up on.
+fn record_failure(pending: &mut PendingDelivery, now: DateTime<Utc>) -> bool {
+    pending.attempts += 1;
+    pending.not_before = now + retry_delay(pending.attempts);
+    pending.attempts < MAX_ATTEMPTS
+}
+
+pub fn delivery<C: Context>() -> ParentHandler<C> {
+    ParentHandler::new()
+        .subcommand(
+            "list",
+            from_fn_async(list)
+                .with_display_serializable()
+                .with_about("about.list-notification-delivery-rules")
+                .with_call_remote::<CliContext>(),
+        )
+        .subcommand(
+            "add-email",
+            from_fn_async(add_email)
+                .with_display_serializable()
+                .with_about("about.add-notification-email-rule")
+                .with_call_remote::<CliContext>(),
+        )
+        .subcommand(
+            "add-webhook",
+            from_fn_async(add_webhook)
+                .with_display_serializable()
+                .with_about("about.add-notification-webhook-rule")
+                .with_call_remote::<CliContext>(),
+        )
+        .subcommand(
+            "remove",
+            from_fn_async(remove)
+                .no_display()
+                .with_about("about.remove-notification-delivery-rule")
+                .with_call_remote::<CliContext>(),
+        )
+        .subcommand(
+            "test",
+            from_fn_async(test)
+                .no_display()
+                .with_about("about.test-notification-delivery-rule")
+                .with_call_remote::<CliContext>(),
+        )
+}
+
+#[derive(Debug, Deserialize, Serialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+#[serde(tag = "type")]
+pub enum DestinationInfo {
+    Email {
+        to: String,
+    },
+    Webhook {
+        #[ts(type = "string")]
+        url: Url,
+        signed: bool,
+    },
+}
+
+#[derive(Debug, Deserialize, Serialize, TS)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+pub struct DeliveryRuleInfo {
+    pub levels: BTreeSet<NotificationLevel>,
+    pub destination: DestinationInfo,
+}
+impl From<DeliveryRule> for DeliveryRuleInfo {
+    fn from(rule: DeliveryRule) -> Self {
+        Self {
+            levels: rule.levels,
+            destination: match rule.destination {
+                Destination::Email { to } => DestinationInfo::Email { to },
+                Destination::Webhook { url, secret } => DestinationInfo::Webhook {
+                    url,
+                    signed: secret.is_some(),
+                },
+            },
+        }
+    }
+}
+
+pub async fn list(ctx: RpcContext) -> Result<BTreeMap<u32, DeliveryRuleInfo>, Error> {
+    Ok(ctx
+        .db
+        .peek()
+        .await
+        .into_private()
+        .into_notification_delivery()
+        .into_rules()
+        .de()?
+        .0
+        .into_iter()
+        .map(|(id, rule)| (id, rule.into()))
+        .collect())
+}
+
+/// No levels means every level.
+fn levels(levels: Vec<NotificationLevel>) -> BTreeSet<NotificationLevel> {
+    if levels.is_empty() {
+        [
+            NotificationLevel::Success,
+            NotificationLevel::Info,
+            NotificationLevel::Warning,
+            NotificationLevel::Error,
+        ]
+        .into()
+    } else {
+        levels.into_iter().collect()
+    }
+}
+
+async fn add_rule(ctx: &RpcContext, rule: DeliveryRule) -> Result<u32, Error> {
+    ctx.db
+        .mutate(|db| {
+            let rules = db
+                .as_private_mut()
+                .as_notification_delivery_mut()
+                .as_rules_mut();
+            let id = rules.keys()?.into_iter().max().map_or(0, |id| id + 1);
+            rules.insert(&id, &rule)?;
+            Ok(id)
+        })
+        .await
+        .result
+}
+
+#[derive(Deserialize, Serialize, Parser, TS)]
+#[group(skip)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+#[command(rename_all = "kebab-case")]
+pub struct AddEmailRuleParams {
+    #[arg(help = "help.arg.notification-email-to")]
+    pub to: String,
+    #[arg(long = "level", help = "help.arg.notification-delivery-levels")]
+    #[serde(default)]
+    pub levels: Vec<NotificationLevel>,
+}
+
+pub async fn add_email(
+    ctx: RpcContext,
+    AddEmailRuleParams { to, levels: lvls }: AddEmailRuleParams,
+) -> Result<u32, Error> {
+    to.parse::<lettre::message::Mailbox>()?;
+    add_rule(
+        &ctx,
+        DeliveryRule {
+            levels: levels(lvls),
+            destination: Destination::Email { to },
+        },
+    )
+    .await
+}
+
+#[derive(Deserialize, Serialize, Parser, TS)]
+#[group(skip)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+#[command(rename_all = "kebab-case")]
+pub struct AddWebhookRuleParams {
+    #[arg(help = "help.arg.notification-webhook-url")]
+    #[ts(type = "string")]
+    pub url: Url,
+    #[arg(long, help = "help.arg.notification-webhook-secret")]
+    pub secret: Option<String>,
+    #[arg(long = "level", help = "help.arg.notification-delivery-levels")]
+    #[serde(default)]
+    pub levels: Vec<NotificationLevel>,
+}
+
+pub async fn add_webhook(
+    ctx: RpcContext,
+    AddWebhookRuleParams {
+        url,
+        secret,
+        levels: lvls,
+    }: AddWebhookRuleParams,
+) -> Result<u32, Error> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(Error::new(
+            eyre!(
+                "{}",
+                t!("notifications.delivery.unsupported-url-scheme", url = url)
+            ),
+            ErrorKind::InvalidRequest,
+        ));
+    }
+    add_rule(
+        &ctx,
+        DeliveryRule {
+            levels: levels(lvls),
+            destination: Destination::Webhook { url, secret },
+        },
+    )
+    .await
+}
+
+#[derive(Deserialize, Serialize, Parser, TS)]
+#[group(skip)]
+#[ts(export)]
+#[serde(rename_all = "camelCase")]
+#[command(rename_all = "kebab-case")]
+pub struct DeliveryRuleParams {
+    #[arg(help = "help.arg.notification-delivery-rule-id")]
+    pub id: u32,
+}
+
+pub async fn remove(
+    ctx: RpcContext,
+    DeliveryRuleParams { id }: DeliveryRuleParams,
+) -> Result<(), Error> {
+    ctx.db
+        .mutate(|db| {
+            db.as_private_mut()
+                .as_notification_delivery_mut()
+                .as_rules_mut()
+                .remove(&id)
+        })
+        .await
+        .result?;
+    Ok(())
+}
+
+/// Send a sample notification to a rule's destination right away, bypassing
+/// the outbox so that a failure is reported to the caller.
+pub async fn test(
+    ctx: RpcContext,
+    DeliveryRuleParams { id }: DeliveryRuleParams,
+) -> Result<(), Error> {
+    let rule = ctx
+        .db
+        .peek()
+        .await
+        .into_private()
+        .into_notification_delivery()
+        .into_rules()
+        .into_idx(&id)
+        .ok_or_else(|| {
+            Error::new(
+                eyre!("{}", t!("notifications.delivery.rule-not-found", id = id)),
+                ErrorKind::NotFound,
+            )
+        })?
+        .de()?;
+    send(
+        &ctx,
+        &rule.destination,
+        &NotificationWithId {
+            id: 0,
+            notification: Notification {
+                package_id: None,
+                created_at: Utc::now(),
+                code: <() as super::NotificationType>::CODE,
+                level: NotificationLevel::Info,
+                title: t!("notifications.delivery.test-title").to_string(),
+                message: t!("notifications.delivery.test-message").to_string(),
+                data: Value::Null,
+                seen: false,
+            },
+        },
+    )
+    .await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn notification(level: NotificationLevel) -> Notification {
+        Notification {
+            package_id: None,
+            created_at: Utc::now(),
+            code: 0,
+            level,
+            title: "title".into(),
+            message: "message".into(),
+            data: Value::Null,
+            seen: false,
+        }
+    }
+
+    fn pending() -> PendingDelivery {
+        PendingDelivery {
+            rule: 0,
+            notification: NotificationWithId {
+                id: 0,
+                notification: notification(NotificationLevel::Info),
+            },
+            attempts: 0,
+            not_before: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn signature_is_prefixed_hex_hmac_sha256() {
+        assert_eq!(
+            signature("key", b"The quick brown fox jumps over the lazy dog"),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+
+    #[test]
+    fn enqueue_only_for_rules_wanting_the_level() {
+        let rule = |levels: &[NotificationLevel]| DeliveryRule {
+            levels: levels.iter().cloned().collect(),
+            destination: Destination::Email {
+                to: "me@example.com".into(),
+            },
+        };
+        let mut db = DatabaseModel::from(imbl_value::json!({
+            "private": {
+                "notificationDelivery": {
+                    "rules": {
+                        "0": rule(&[NotificationLevel::Error]),
+                        "1": rule(&[NotificationLevel::Warning, NotificationLevel::Error]),
+                        "2": rule(&[NotificationLevel::Success]),
+                    },
+                    "outbox": {},
+                },
+            },
+        }));
+        let outbox = |db: &DatabaseModel| {
+            db.as_private()
+                .as_notification_delivery()
+                .as_outbox()
+                .de()
+                .unwrap()
+                .0
+                .into_iter()
+                .map(|(key, pending)| (key, pending.rule, pending.notification.id))
+                .collect::<Vec<_>>()
+        };
+
+        enqueue(&mut db, 7, &notification(NotificationLevel::Info)).unwrap();
+        assert!(outbox(&db).is_empty());
+        enqueue(&mut db, 8, &notification(NotificationLevel::Error)).unwrap();
+        assert_eq!(outbox(&db), [(0, 0, 8), (1, 1, 8)]);
+        enqueue(&mut db, 9, &notification(NotificationLevel::Warning)).unwrap();
+        assert_eq!(outbox(&db), [(0, 0, 8), (1, 1, 8), (2, 1, 9)]);
+    }
+
+    #[test]
+    fn retries_back_off_then_give_up() {
+        let now = Utc::now();
+        let mut pending = pending();
+
+        assert!(record_failure(&mut pending, now));
+        assert_eq!(pending.attempts, 1);
+        assert_eq!(pending.not_before, now + RETRY_BASE);
+        assert!(record_failure(&mut pending, now));
+        assert_eq!(pending.not_before, now + RETRY_BASE * 2);
+        assert!(record_failure(&mut pending, now));
+        assert_eq!(pending.not_before, now + RETRY_BASE * 4);
+
+        while pending.attempts < MAX_ATTEMPTS - 1 {
+            assert!(record_failure(&mut pending, now));
+        }
+        assert_eq!(pending.not_before, now + RETRY_MAX);
+        assert!(!record_failure(&mut pending, now));
+        assert_eq!(pending.attempts, MAX_ATTEMPTS);
+    }
+}
@@ -20,6 +20,8 @@ use crate::prelude::*;
 use crate::util::FromStrParser;
 use crate::util::serde::{HandlerExtSerde, const_true};
 
+pub mod delivery;
+
 // #[command(subcommands(list, delete, delete_before, create))]
 pub fn notification<C: Context>() -> ParentHandler<C> {
     ParentHandler::new()
@@ -72,6 +74,10 @@ pub fn notification<C: Context>() -> ParentHandler<C> {
                 .with_about("about.persist-new-notification")
                 .with_call_remote::<CliContext>(),
         )
+        .subcommand(
+            "delivery",
+            delivery::delivery::<C>().with_about("about.commands-notification-delivery"),
+        )
 }
 
 #[derive(Deserialize, Serialize, Parser, TS)]
@@ -332,7 +338,9 @@ pub async fn create(
         .result
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize, TS)]
+#[derive(
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize, TS,
+)]
 #[ts(export)]
 #[serde(rename_all = "camelCase")]
 pub enum NotificationLevel {
@@ -404,7 +412,7 @@ impl Map for Notifications {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, HasModel, TS)]
+#[derive(Debug, Clone, Serialize, Deserialize, HasModel, TS)]
 #[ts(export)]
 #[serde(rename_all = "camelCase")]
 #[model = "Model<Self>"]
@@ -422,7 +430,7 @@ pub struct Notification {
     pub seen: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize, TS)]
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
 #[ts(export)]
 #[serde(rename_all = "camelCase")]
 pub struct NotificationWithId {
@@ -472,19 +480,20 @@ pub fn notify<T: NotificationType>(
         .into_iter()
         .max()
         .map_or(0, |id| id + 1);
-    db.as_private_mut().as_notifications_mut().insert(
-        &id,
-        &Notification {
-            package_id,
-            created_at: Utc::now(),
-            code: T::CODE,
-            level,
-            title,
-            message,
-            data,
-            seen: false,
-        },
-    )?;
+    let notification = Notification {
+        package_id,
+        created_at: Utc::now(),
+        code: T::CODE,
+        level,
+        title,
+        message,
+        data,
+        seen: false,
+    };
+    db.as_private_mut()
+        .as_notifications_mut()
+        .insert(&id, &notification)?;
+    delivery::enqueue(db, id, &notification)?;
     Ok(())
 }
 
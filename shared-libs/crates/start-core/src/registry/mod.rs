@@ -10,7 +10,7 @@ use ts_rs::TS;
 use crate::context::CliContext;
 use crate::middleware::auth::Auth;
 use crate::middleware::cors::Cors;
-use crate::net::static_server::{bad_request, not_found, server_error};
+use crate::net::static_server::{bad_request, describe_api, not_found, server_error};
 use crate::prelude::*;
 use crate::registry::context::RegistryContext;
 use crate::registry::device_info::DeviceInfoMiddleware;
@@ -115,6 +115,7 @@ pub fn registry_router(ctx: RegistryContext) -> Router {
             let ctx = ctx.clone();
             any(
                 Server::new(move || ready(Ok(ctx.clone())), registry_api())
+                    .with_openrpc(describe_api("StartOS Registry"))
                     .middleware(Cors::new())
                     .middleware(Auth::new().with_local_auth().with_signature_auth())
                     .middleware(DeviceInfoMiddleware::new()),
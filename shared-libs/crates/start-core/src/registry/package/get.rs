@@ -3,7 +3,7 @@ use std::path::{Path, PathBuf};
 
 use chrono::Utc;
 use clap::{Parser, ValueEnum};
-use exver::{ExtendedVersion, VersionRange};
+use exver::{ExtendedVersion, Preference, VersionRange};
 use imbl_value::{InternedString, json};
 use itertools::Itertools;
 use rusqlite::params;
@@ -212,29 +212,29 @@ fn get_matching_models(
 
 pub async fn get_package(ctx: RegistryContext, params: GetPackageParams) -> Result<Value, Error> {
     let peek = ctx.db.peek().await;
-    let mut best: BTreeMap<PackageId, BTreeMap<VersionString, Model<PackageVersionInfo>>> =
-        Default::default();
     let mut other: BTreeMap<PackageId, BTreeMap<VersionString, Model<PackageVersionInfo>>> =
         Default::default();
     for (id, version, info) in get_matching_models(&peek.as_index().as_package(), &params)? {
-        let package_best = best.entry(id.clone()).or_default();
-        let package_other = other.entry(id.clone()).or_default();
-        if package_best.keys().all(|k| !(**k > version)) {
-            for worse_version in package_best
-                .keys()
-                .filter(|k| ***k < version)
-                .cloned()
-                .collect_vec()
-            {
-                if let Some(info) = package_best.remove(&worse_version) {
-                    package_other.insert(worse_version, info);
-                }
-            }
-            package_best.insert(version.into(), info);
-        } else {
-            package_other.insert(version.into(), info);
-        }
+        other.entry(id).or_default().insert(version.into(), info);
     }
+    // the newest version of each flavor is the best, and the rest are other versions
+    let mut best: BTreeMap<PackageId, BTreeMap<VersionString, Model<PackageVersionInfo>>> = other
+        .iter_mut()
+        .map(|(id, versions)| {
+            let package_best = VersionRange::Any
+                .best_per_flavor(versions.keys(), &Preference::max())
+                .into_iter()
+                .cloned()
+                .collect_vec();
+            (
+                id.clone(),
+                package_best
+                    .into_iter()
+                    .filter_map(|v| versions.remove_entry(&v))
+                    .collect(),
+            )
+        })
+        .collect();
     if let Some(id) = &params.id {
         if params.target_version.is_some() {
             let created_at = Utc::now().to_rfc3339();
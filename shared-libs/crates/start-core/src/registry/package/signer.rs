@@ -90,7 +90,7 @@ pub async fn add_package_signer(
                 .upsert(&signer, || Ok(VersionRange::None))?
                 .mutate(|existing| {
                     *existing = if merge {
-                        VersionRange::or(existing.clone(), versions)
+                        VersionRange::or(existing.clone(), versions).normalize()
                     } else {
                         versions
                     };
@@ -246,11 +246,15 @@ where
             channel.send(()).ok();
         }
     }
-    fn subscribe_to_kill(&self, session: T) -> broadcast::Receiver<()> {
+    /// Subscribe to `session`'s kill. Entries nobody is subscribed to any
+    /// more are pruned on the way, so sessions that ended (or never
+    /// authenticated) don't pile up.
+    pub(crate) fn subscribe_to_kill(&self, session: T) -> broadcast::Receiver<()> {
         let mut map = self.0.lock().unwrap();
         if let Some(send) = map.get(&session) {
             send.subscribe()
         } else {
+            map.retain(|_, send| send.receiver_count() > 0);
             let (send, recv) = broadcast::channel(1);
             map.insert(session, send);
             recv
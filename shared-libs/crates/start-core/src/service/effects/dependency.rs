@@ -345,7 +345,7 @@ pub async fn set_dependencies(
                 .dependency_icon_data_url(&dep_id)
                 .await?,
             kind,
-            version_range,
+            version_range: version_range.normalize(),
         };
         deps.insert(dep_id, info);
     }
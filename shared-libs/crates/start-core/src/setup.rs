@@ -29,8 +29,11 @@ use crate::db::model::Database;
 use crate::disk::REPAIR_DISK_PATH;
 use crate::disk::fsck::RepairStrategy;
 use crate::disk::main::DEFAULT_PASSWORD;
-use crate::disk::mount::filesystem::ReadWrite;
 use crate::disk::mount::filesystem::cifs::Cifs;
+use crate::disk::mount::filesystem::sftp::{
+    SFTP_PORT, Sftp, SftpHostKey, confirm_host_keys, scan_host_keys, validate_username,
+};
+use crate::disk::mount::filesystem::{ReadOnly, ReadWrite};
 use crate::disk::mount::guard::{GenericMountGuard, TmpMountGuard};
 use crate::disk::util::{DiskInfo, StartOsRecoveryInfo, pvscan, recovery_info};
 use crate::hostname::ServerHostnameInfo;
@@ -83,6 +86,7 @@ pub fn setup<C: Context>() -> ParentHandler<C> {
                 .with_about("about.setup-execute"),
         )
         .subcommand("cifs", cifs::<C>())
+        .subcommand("sftp", sftp::<C>())
         .subcommand(
             "complete",
             from_fn_async(complete)
@@ -456,6 +460,82 @@ pub async fn verify_cifs(
     Ok(start_os)
 }
 
+pub fn sftp<C: Context>() -> ParentHandler<C> {
+    ParentHandler::new()
+        .subcommand("scan-host-keys", from_fn_async(scan_sftp).no_cli())
+        .subcommand("verify", from_fn_async(verify_sftp).no_cli())
+}
+
+#[derive(Deserialize, Serialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct ScanSftpParams {
+    hostname: String,
+    port: Option<u16>,
+}
+
+/// The setup UI shows these fingerprints for the user to confirm, then sends
+/// the accepted keys back to `verify` and in the recovery source.
+pub async fn scan_sftp(
+    _: SetupContext,
+    ScanSftpParams { hostname, port }: ScanSftpParams,
+) -> Result<Vec<SftpHostKey>, Error> {
+    scan_host_keys(&hostname, port.unwrap_or(SFTP_PORT)).await
+}
+
+#[derive(Deserialize, Serialize, TS)]
+#[serde(rename_all = "camelCase")]
+#[ts(export)]
+pub struct VerifySftpParams {
+    hostname: String,
+    port: Option<u16>,
+    path: PathBuf,
+    username: String,
+    password: Option<EncryptedWire>,
+    private_key: Option<EncryptedWire>,
+    host_keys: Vec<String>,
+}
+
+pub async fn verify_sftp(
+    ctx: SetupContext,
+    VerifySftpParams {
+        hostname,
+        port,
+        path,
+        username,
+        password,
+        private_key,
+        host_keys,
+    }: VerifySftpParams,
+) -> Result<BTreeMap<String, StartOsRecoveryInfo>, Error> {
+    validate_username(&username)?;
+    let host_keys = confirm_host_keys(&hostname, host_keys)?;
+    let password: Option<String> = password.map(|x| x.decrypt(&ctx)).flatten();
+    let private_key: Option<String> = private_key.map(|x| x.decrypt(&ctx)).flatten();
+    let guard = TmpMountGuard::mount(
+        &Sftp {
+            hostname,
+            port: port.unwrap_or(SFTP_PORT),
+            path: Path::new("/").join(path),
+            username,
+            password,
+            private_key,
+            host_keys,
+        },
+        ReadOnly,
+    )
+    .await?;
+    let start_os = recovery_info(guard.path()).await?;
+    guard.unmount().await?;
+    if start_os.is_empty() {
+        return Err(Error::new(
+            eyre!("{}", t!("setup.no-backup-found")),
+            crate::ErrorKind::NotFound,
+        ));
+    }
+    Ok(start_os)
+}
+
 #[derive(Debug, Deserialize, Serialize, TS)]
 #[serde(tag = "type")]
 #[serde(rename_all = "camelCase")]
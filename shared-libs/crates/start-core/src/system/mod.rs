@@ -1275,33 +1275,56 @@ pub async fn test_smtp(
         security,
     }: TestSmtpParams,
 ) -> Result<(), Error> {
-    use lettre::message::header::ContentType;
-    use lettre::transport::smtp::authentication::Credentials;
-    use lettre::transport::smtp::client::{Tls, TlsParameters};
-    use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
-
-    let creds = Credentials::new(username, password);
-    let message = Message::builder()
-        .from(from.parse()?)
-        .to(to.parse()?)
-        .subject("StartOS Test Email")
-        .header(ContentType::TEXT_PLAIN)
-        .body("This is a test email sent from your StartOS Server".to_owned())?;
-
-    let tls_parameters = TlsParameters::new(host.clone())?;
-    let transport = match security {
-        SmtpSecurity::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&host)?
-            .port(port)
-            .tls(Tls::Required(tls_parameters)),
-        SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&host)?
-            .port(port)
-            .tls(Tls::Wrapper(tls_parameters)),
+    SmtpValue {
+        host,
+        port,
+        from,
+        username,
+        password: Some(password),
+        security,
     }
-    .credentials(creds)
-    .build();
+    .send(
+        &to,
+        "StartOS Test Email",
+        "This is a test email sent from your StartOS Server".to_owned(),
+    )
+    .await
+}
 
-    transport.send(message).await?;
-    Ok(())
+impl SmtpValue {
+    /// Send a plain-text email to `to` through this server.
+    pub async fn send(&self, to: &str, subject: &str, body: String) -> Result<(), Error> {
+        use lettre::message::header::ContentType;
+        use lettre::transport::smtp::authentication::Credentials;
+        use lettre::transport::smtp::client::{Tls, TlsParameters};
+        use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+        let message = Message::builder()
+            .from(self.from.parse()?)
+            .to(to.parse()?)
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(body)?;
+
+        let tls_parameters = TlsParameters::new(self.host.clone())?;
+        let mut transport = match self.security {
+            SmtpSecurity::Starttls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&self.host)?
+                    .port(self.port)
+                    .tls(Tls::Required(tls_parameters))
+            }
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&self.host)?
+                .port(self.port)
+                .tls(Tls::Wrapper(tls_parameters)),
+        };
+        if let Some(password) = &self.password {
+            transport =
+                transport.credentials(Credentials::new(self.username.clone(), password.clone()));
+        }
+
+        transport.build().send(message).await?;
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, TS, Parser)]
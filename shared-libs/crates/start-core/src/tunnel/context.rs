@@ -900,6 +900,7 @@ impl CallRemote<TunnelContext, TunnelUrlParams> for RpcContext {
 pub static TUNNEL_UI_CELL: OnceLock<Dir<'static>> = OnceLock::new();
 
 impl UiContext for TunnelContext {
+    const API_TITLE: &'static str = "StartTunnel";
     fn ui_dir() -> &'static Dir<'static> {
         TUNNEL_UI_CELL.get().unwrap_or(&EMPTY_DIR)
     }
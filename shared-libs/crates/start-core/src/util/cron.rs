@@ -0,0 +1,239 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, NaiveDate, Timelike, Utc};
+use clap::builder::ValueParserFactory;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use ts_rs::TS;
+
+use crate::prelude::*;
+use crate::util::FromStrParser;
+
+/// How far ahead [`CronSchedule::next_after`] looks: long enough to reach any
+/// Feb 29.
+const SEARCH_DAYS: usize = 366 * 8;
+
+/// A five-field cron expression (`minute hour day-of-month month
+/// day-of-week`), evaluated in UTC. Each field is `*` or a comma-separated
+/// list of values and `a-b` ranges, any of which may take a `/step`.
+/// Day-of-week runs 0-7 with both 0 and 7 meaning Sunday. As in cron, when
+/// both day fields are restricted a day matching either one matches.
+#[derive(Debug, Clone, PartialEq, Eq, TS)]
+#[ts(type = "string")]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    dom_any: bool,
+    dow_any: bool,
+    string: String,
+}
+impl CronSchedule {
+    /// The first minute strictly after `after` that the schedule matches, or
+    /// `None` if it never matches (e.g. `0 0 30 2 *`).
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = after.with_second(0)?.with_nanosecond(0)? + chrono::TimeDelta::minutes(1);
+        let mut date = start.date_naive();
+        for _ in 0..SEARCH_DAYS {
+            if self.day_matches(date) {
+                let (first_hour, first_minute) = if date == start.date_naive() {
+                    (start.hour(), start.minute())
+                } else {
+                    (0, 0)
+                };
+                for hour in first_hour..24 {
+                    if self.hours & (1 << hour) == 0 {
+                        continue;
+                    }
+                    let from = if hour == first_hour { first_minute } else { 0 };
+                    if let Some(minute) = (from..60).find(|m| self.minutes & (1 << m) != 0) {
+                        return Some(date.and_hms_opt(hour, minute, 0)?.and_utc());
+                    }
+                }
+            }
+            date = date.succ_opt()?;
+        }
+        None
+    }
+
+    fn day_matches(&self, date: NaiveDate) -> bool {
+        if self.months & (1 << date.month()) == 0 {
+            return false;
+        }
+        let dom = self.days_of_month & (1 << date.day()) != 0;
+        let dow = self.days_of_week & (1 << date.weekday().num_days_from_sunday()) != 0;
+        match (self.dom_any, self.dow_any) {
+            (false, false) => dom || dow,
+            _ => dom && dow,
+        }
+    }
+}
+
+fn invalid(expr: &str) -> Error {
+    Error::new(
+        eyre!("{}", t!("util.cron.invalid-expression", expr = expr)),
+        ErrorKind::InvalidRequest,
+    )
+}
+
+/// Parse one field into a bitmask of the values in `min..=max` it selects.
+fn parse_field(field: &str, min: u32, max: u32, expr: &str) -> Result<u64, Error> {
+    let mut mask = 0u64;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().map_err(|_| invalid(expr))?),
+            None => (item, 1),
+        };
+        if step == 0 {
+            return Err(invalid(expr));
+        }
+        let (lo, hi) = if range == "*" {
+            (min, max)
+        } else if let Some((lo, hi)) = range.split_once('-') {
+            (
+                lo.parse().map_err(|_| invalid(expr))?,
+                hi.parse().map_err(|_| invalid(expr))?,
+            )
+        } else {
+            let value = range.parse().map_err(|_| invalid(expr))?;
+            // `5/15` means "from 5, every 15"
+            (value, if item.contains('/') { max } else { value })
+        };
+        if lo < min || hi > max || lo > hi {
+            return Err(invalid(expr));
+        }
+        for value in (lo..=hi).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+    Ok(mask)
+}
+
+impl FromStr for CronSchedule {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [minute, hour, dom, month, dow] = fields[..] else {
+            return Err(invalid(s));
+        };
+        let mut days_of_week = parse_field(dow, 0, 7, s)?;
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week |= 1;
+        }
+        let res = CronSchedule {
+            minutes: parse_field(minute, 0, 59, s)?,
+            hours: parse_field(hour, 0, 23, s)?,
+            days_of_month: parse_field(dom, 1, 31, s)?,
+            months: parse_field(month, 1, 12, s)?,
+            days_of_week,
+            dom_any: dom == "*",
+            dow_any: dow == "*",
+            string: fields.join(" "),
+        };
+        if res.next_after(Utc::now()).is_none() {
+            return Err(invalid(s));
+        }
+        Ok(res)
+    }
+}
+impl std::fmt::Display for CronSchedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.string)
+    }
+}
+impl ValueParserFactory for CronSchedule {
+    type Parser = FromStrParser<Self>;
+    fn value_parser() -> Self::Parser {
+        FromStrParser::new()
+    }
+}
+impl<'de> Deserialize<'de> for CronSchedule {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        crate::util::serde::deserialize_from_str(deserializer)
+    }
+}
+impl Serialize for CronSchedule {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::util::serde::serialize_display(self, serializer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn at(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap()
+    }
+
+    #[test]
+    fn next_after_walks_fields() {
+        let nightly: CronSchedule = "30 3 * * *".parse().unwrap();
+        assert_eq!(
+            nightly.next_after(at(2026, 1, 1, 3, 29)),
+            Some(at(2026, 1, 1, 3, 30))
+        );
+        assert_eq!(
+            nightly.next_after(at(2026, 1, 1, 3, 30)),
+            Some(at(2026, 1, 2, 3, 30))
+        );
+        assert_eq!(
+            nightly.next_after(at(2026, 12, 31, 23, 59)),
+            Some(at(2027, 1, 1, 3, 30))
+        );
+
+        let quarter_hours: CronSchedule = "*/15 9-10 * * 1-5".parse().unwrap();
+        // 2026-01-03 is a Saturday
+        assert_eq!(
+            quarter_hours.next_after(at(2026, 1, 3, 12, 0)),
+            Some(at(2026, 1, 5, 9, 0))
+        );
+        assert_eq!(
+            quarter_hours.next_after(at(2026, 1, 5, 10, 45)),
+            Some(at(2026, 1, 6, 9, 0))
+        );
+
+        let leap: CronSchedule = "0 0 29 2 *".parse().unwrap();
+        assert_eq!(
+            leap.next_after(at(2026, 3, 1, 0, 0)),
+            Some(at(2028, 2, 29, 0, 0))
+        );
+    }
+
+    #[test]
+    fn restricted_day_fields_match_either() {
+        // the 1st of the month, and every Sunday (7 == 0)
+        let either: CronSchedule = "0 12 1 * 7".parse().unwrap();
+        // 2026-01-02 is a Friday, 2026-01-04 a Sunday
+        assert_eq!(
+            either.next_after(at(2026, 1, 2, 0, 0)),
+            Some(at(2026, 1, 4, 12, 0))
+        );
+        assert_eq!(
+            either.next_after(at(2026, 1, 25, 12, 0)),
+            Some(at(2026, 2, 1, 12, 0))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        for expr in [
+            "",
+            "* * * *",
+            "60 * * * *",
+            "* * 0 * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "0 0 30 2 *",
+        ] {
+            assert!(expr.parse::<CronSchedule>().is_err(), "{expr:?} accepted");
+        }
+        assert_eq!(
+            " 0  4 *  * 0 ".parse::<CronSchedule>().unwrap().to_string(),
+            "0 4 * * 0"
+        );
+    }
+}
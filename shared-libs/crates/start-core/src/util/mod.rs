@@ -38,6 +38,7 @@ pub mod clap;
 pub mod clone;
 pub mod collections;
 pub mod cpupower;
+pub mod cron;
 pub mod crypto;
 pub mod data_url;
 pub mod future;
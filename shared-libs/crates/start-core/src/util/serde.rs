@@ -524,6 +524,9 @@ impl<T: HandlerFor<C>, C: Context> HandlerFor<C> for DisplaySerializable<T> {
     fn method_from_dots(&self, method: &str) -> Option<VecDeque<&'static str>> {
         self.0.method_from_dots(method)
     }
+    fn methods(&self) -> Vec<VecDeque<&'static str>> {
+        self.0.methods()
+    }
 }
 impl<T: HandlerTypes, C: Context> PrintCliResult<C> for DisplaySerializable<T>
 where
@@ -0,0 +1,14 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { BackupTargetId } from './BackupTargetId'
+import type { CronSchedule } from './CronSchedule'
+import type { PackageId } from './PackageId'
+import type { Retention } from './Retention'
+
+export type BackupScheduleInfo = {
+  cron: CronSchedule
+  targetId: BackupTargetId
+  packageIds: Array<PackageId> | null
+  retention: Retention | null
+  lastRun: string | null
+  nextRun: string | null
+}
@@ -1,5 +1,7 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { CifsBackupTarget } from './CifsBackupTarget'
+import type { NfsBackupTarget } from './NfsBackupTarget'
+import type { SftpBackupTarget } from './SftpBackupTarget'
 import type { StartOsRecoveryInfo } from './StartOsRecoveryInfo'
 
 export type BackupTarget =
@@ -18,3 +20,5 @@ export type BackupTarget =
       filesystem: string | null
     }
   | ({ type: 'cifs' } & CifsBackupTarget)
+  | ({ type: 'sftp' } & SftpBackupTarget)
+  | ({ type: 'nfs' } & NfsBackupTarget)
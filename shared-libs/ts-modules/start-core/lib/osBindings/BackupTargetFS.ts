@@ -1,7 +1,11 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { BlockDev } from './BlockDev'
 import type { Cifs } from './Cifs'
+import type { Nfs } from './Nfs'
+import type { Sftp } from './Sftp'
 
 export type BackupTargetFS =
   | ({ type: 'disk' } & BlockDev)
   | ({ type: 'cifs' } & Cifs)
+  | ({ type: 'sftp' } & Sftp)
+  | ({ type: 'nfs' } & Nfs)
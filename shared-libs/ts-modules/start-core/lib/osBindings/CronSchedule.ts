@@ -0,0 +1,10 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * A five-field cron expression (`minute hour day-of-month month
+ * day-of-week`), evaluated in UTC. Each field is `*` or a comma-separated
+ * list of values and `a-b` ranges, any of which may take a `/step`.
+ * Day-of-week runs 0-7 with both 0 and 7 meaning Sunday. As in cron, when
+ * both day fields are restricted a day matching either one matches.
+ */
+export type CronSchedule = string
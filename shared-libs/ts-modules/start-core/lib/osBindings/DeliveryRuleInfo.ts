@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { DestinationInfo } from './DestinationInfo'
+import type { NotificationLevel } from './NotificationLevel'
+
+export type DeliveryRuleInfo = {
+  levels: Array<NotificationLevel>
+  destination: DestinationInfo
+}
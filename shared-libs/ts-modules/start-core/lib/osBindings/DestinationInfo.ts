@@ -0,0 +1,5 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type DestinationInfo =
+  | { type: 'email'; to: string }
+  | { type: 'webhook'; url: string; signed: boolean }
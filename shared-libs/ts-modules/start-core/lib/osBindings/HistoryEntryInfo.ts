@@ -0,0 +1,19 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { AnyVerifyingKey } from './AnyVerifyingKey'
+
+export type HistoryEntryInfo = {
+  id: number
+  timestamp: string
+  /**
+   * The RPC method that made the change
+   */
+  method: string | null
+  /**
+   * The key that signed the request, if it was not made locally
+   */
+  signer: AnyVerifyingKey | null
+  /**
+   * The change, relative to the pointer listed
+   */
+  patch: unknown[]
+}
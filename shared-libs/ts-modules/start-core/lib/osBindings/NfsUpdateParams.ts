@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { BackupTargetId } from './BackupTargetId'
+
+export type NfsUpdateParams = {
+  id: BackupTargetId
+  hostname: string
+  path: string
+}
@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+/**
+ * Which of the snapshots taken by scheduled backups to keep, in the style of
+ * grandfather-father-son rotation. Periods are UTC days, ISO weeks and
+ * months.
+ */
+export type Retention = { daily: number; weekly: number; monthly: number }
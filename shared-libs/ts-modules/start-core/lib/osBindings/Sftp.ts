@@ -0,0 +1,14 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type Sftp = {
+  hostname: string
+  port: number
+  path: string
+  username: string
+  password: string | null
+  privateKey: string | null
+  /**
+   * `<type> <base64>` host keys pinned by [`scan_host_keys`]
+   */
+  hostKeys: Array<string>
+}
@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type SftpAddParams = {
+  hostname: string
+  port: number | null
+  path: string
+  username: string
+  password: string | null
+  privateKey: string | null
+  hostKeys: Array<string> | null
+}
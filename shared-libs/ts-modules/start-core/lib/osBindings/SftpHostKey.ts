@@ -0,0 +1,12 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type SftpHostKey = {
+  /**
+   * `<type> <base64>`, as pinned in [`Sftp::host_keys`]
+   */
+  key: string
+  /**
+   * `SHA256:<base64>`, as printed by `ssh-keygen -l`
+   */
+  fingerprint: string
+}
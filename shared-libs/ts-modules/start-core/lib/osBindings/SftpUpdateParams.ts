@@ -0,0 +1,17 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { BackupTargetId } from './BackupTargetId'
+
+export type SftpUpdateParams = {
+  id: BackupTargetId
+  hostname: string
+  port: number | null
+  path: string
+  username: string
+  password: string | null
+  privateKey: string | null
+  /**
+   * Keys confirmed from `scan-host-keys`. Without them the pinned keys are
+   * kept, unless the hostname or port changed.
+   */
+  hostKeys: Array<string> | null
+}
@@ -0,0 +1,16 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { BackupTargetId } from './BackupTargetId'
+import type { CronSchedule } from './CronSchedule'
+import type { PackageId } from './PackageId'
+import type { PasswordType } from './PasswordType'
+
+export type UpdateScheduleParams = {
+  id: number
+  cron: CronSchedule
+  targetId: BackupTargetId
+  packageIds: Array<PackageId> | null
+  keepDaily: number | null
+  keepWeekly: number | null
+  keepMonthly: number | null
+  password: PasswordType
+}
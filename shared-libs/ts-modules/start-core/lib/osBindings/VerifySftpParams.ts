@@ -0,0 +1,12 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { EncryptedWire } from './EncryptedWire'
+
+export type VerifySftpParams = {
+  hostname: string
+  port: number | null
+  path: string
+  username: string
+  password: EncryptedWire | null
+  privateKey: EncryptedWire | null
+  hostKeys: Array<string>
+}
@@ -14,6 +14,7 @@ export { ActionVisibility } from './ActionVisibility'
 export { AddAdminParams } from './AddAdminParams'
 export { AddAssetParams } from './AddAssetParams'
 export { AddCategoryParams } from './AddCategoryParams'
+export { AddEmailRuleParams } from './AddEmailRuleParams'
 export { AddMirrorParams } from './AddMirrorParams'
 export { AddPackageParams } from './AddPackageParams'
 export { AddPackageSignerParams } from './AddPackageSignerParams'
@@ -21,9 +22,11 @@ export { AddPackageToCategoryParams } from './AddPackageToCategoryParams'
 export { AddPrivateDomainParams } from './AddPrivateDomainParams'
 export { AddPublicDomainParams } from './AddPublicDomainParams'
 export { AddPublicDomainRes } from './AddPublicDomainRes'
+export { AddScheduleParams } from './AddScheduleParams'
 export { AddSslOptions } from './AddSslOptions'
 export { AddTunnelParams } from './AddTunnelParams'
 export { AddVersionParams } from './AddVersionParams'
+export { AddWebhookRuleParams } from './AddWebhookRuleParams'
 export { AddressInfo } from './AddressInfo'
 export { Algorithm } from './Algorithm'
 export { AllPackageData } from './AllPackageData'
@@ -38,6 +41,7 @@ export { AuthKeys } from './AuthKeys'
 export { BackupInfo } from './BackupInfo'
 export { BackupParams } from './BackupParams'
 export { BackupReport } from './BackupReport'
+export { BackupScheduleInfo } from './BackupScheduleInfo'
 export { BackupTarget } from './BackupTarget'
 export { BackupTargetFS } from './BackupTargetFS'
 export { BackupTargetId } from './BackupTargetId'
@@ -84,10 +88,13 @@ export { CountEntry } from './CountEntry'
 export { CreateNotificationParams } from './CreateNotificationParams'
 export { CreateSubcontainerFsParams } from './CreateSubcontainerFsParams'
 export { CreateTaskParams } from './CreateTaskParams'
+export { CronSchedule } from './CronSchedule'
 export { CurrentDependencies } from './CurrentDependencies'
 export { CurrentDependencyInfo } from './CurrentDependencyInfo'
 export { DataUrl } from './DataUrl'
 export { DeleteLegacyParams } from './DeleteLegacyParams'
+export { DeliveryRuleInfo } from './DeliveryRuleInfo'
+export { DeliveryRuleParams } from './DeliveryRuleParams'
 export { DepInfo } from './DepInfo'
 export { Dependencies } from './Dependencies'
 export { DependencyMetadata } from './DependencyMetadata'
@@ -95,8 +102,10 @@ export { DependencyRequirement } from './DependencyRequirement'
 export { DerivedAddressInfo } from './DerivedAddressInfo'
 export { Description } from './Description'
 export { DesiredStatus } from './DesiredStatus'
+export { DestinationInfo } from './DestinationInfo'
 export { DestroySubcontainerFsParams } from './DestroySubcontainerFsParams'
 export { DeviceFilter } from './DeviceFilter'
+export { DiffRevisionsParams } from './DiffRevisionsParams'
 export { DnsSettings } from './DnsSettings'
 export { DomainSettings } from './DomainSettings'
 export { DownloadsResponse } from './DownloadsResponse'
@@ -144,6 +153,7 @@ export { Governor } from './Governor'
 export { Guid } from './Guid'
 export { HardwareRequirements } from './HardwareRequirements'
 export { HealthCheckId } from './HealthCheckId'
+export { HistoryEntryInfo } from './HistoryEntryInfo'
 export { Host } from './Host'
 export { HostId } from './HostId'
 export { HostnameInfo } from './HostnameInfo'
@@ -165,6 +175,7 @@ export { InstallingState } from './InstallingState'
 export { IpInfo } from './IpInfo'
 export { KeyboardOptions } from './KeyboardOptions'
 export { KillParams } from './KillParams'
+export { ListHistoryParams } from './ListHistoryParams'
 export { ListNotificationParams } from './ListNotificationParams'
 export { ListPackageSignersParams } from './ListPackageSignersParams'
 export { ListServiceInterfacesParams } from './ListServiceInterfacesParams'
@@ -201,6 +212,11 @@ export { NetInfo } from './NetInfo'
 export { NetworkInfo } from './NetworkInfo'
 export { NetworkInterfaceInfo } from './NetworkInterfaceInfo'
 export { NetworkInterfaceType } from './NetworkInterfaceType'
+export { Nfs } from './Nfs'
+export { NfsAddParams } from './NfsAddParams'
+export { NfsBackupTarget } from './NfsBackupTarget'
+export { NfsRemoveParams } from './NfsRemoveParams'
+export { NfsUpdateParams } from './NfsUpdateParams'
 export { Notification } from './Notification'
 export { NotificationLevel } from './NotificationLevel'
 export { NotificationWithId } from './NotificationWithId'
@@ -250,6 +266,7 @@ export { RemoveMirrorParams } from './RemoveMirrorParams'
 export { RemovePackageFromCategoryParams } from './RemovePackageFromCategoryParams'
 export { RemovePackageParams } from './RemovePackageParams'
 export { RemovePackageSignerParams } from './RemovePackageSignerParams'
+export { RemoveScheduleParams } from './RemoveScheduleParams'
 export { RemoveSignerParams } from './RemoveSignerParams'
 export { RemoveTunnelParams } from './RemoveTunnelParams'
 export { RemoveVersionParams } from './RemoveVersionParams'
@@ -259,9 +276,11 @@ export { RequestCommitment } from './RequestCommitment'
 export { ResetPasswordParams } from './ResetPasswordParams'
 export { RestartReason } from './RestartReason'
 export { RestorePackageParams } from './RestorePackageParams'
+export { Retention } from './Retention'
 export { RetireBindingParams } from './RetireBindingParams'
 export { RetireHostParams } from './RetireHostParams'
 export { RunActionParams } from './RunActionParams'
+export { ScanSftpParams } from './ScanSftpParams'
 export { Security } from './Security'
 export { ServerBackupReport } from './ServerBackupReport'
 export { ServerHostname } from './ServerHostname'
@@ -295,6 +314,13 @@ export { SetupInfo } from './SetupInfo'
 export { SetupProgress } from './SetupProgress'
 export { SetupResult } from './SetupResult'
 export { SetupStatusRes } from './SetupStatusRes'
+export { Sftp } from './Sftp'
+export { SftpAddParams } from './SftpAddParams'
+export { SftpBackupTarget } from './SftpBackupTarget'
+export { SftpHostKey } from './SftpHostKey'
+export { SftpRemoveParams } from './SftpRemoveParams'
+export { SftpScanParams } from './SftpScanParams'
+export { SftpUpdateParams } from './SftpUpdateParams'
 export { ShutdownParams } from './ShutdownParams'
 export { SideloadParams } from './SideloadParams'
 export { SideloadResponse } from './SideloadResponse'
@@ -322,6 +348,7 @@ export { TimeInfo } from './TimeInfo'
 export { UmountParams } from './UmountParams'
 export { UninstallParams } from './UninstallParams'
 export { UnsetGatewaySecureParams } from './UnsetGatewaySecureParams'
+export { UpdateScheduleParams } from './UpdateScheduleParams'
 export { UpdateTunnelParams } from './UpdateTunnelParams'
 export { UpdatingState } from './UpdatingState'
 export { UpstreamCertValidation } from './UpstreamCertValidation'
@@ -331,6 +358,7 @@ export { UrlPluginRegisterParams } from './UrlPluginRegisterParams'
 export { UrlPluginRegistration } from './UrlPluginRegistration'
 export { UsersResponse } from './UsersResponse'
 export { VerifyCifsParams } from './VerifyCifsParams'
+export { VerifySftpParams } from './VerifySftpParams'
 export { Version } from './Version'
 export { VersionSignerParams } from './VersionSignerParams'
 export { VolumeId } from './VolumeId'